@@ -0,0 +1,17 @@
+//! Plugin sandbox process without the rest of the app.
+//!
+//! Serves a sandboxed plugin over stdin and stdout when started with
+//! `PLUGIN_SANDBOX_ARG`, like the main executable does. The sandbox tests start it.
+
+use nuwe_rust::{run_plugin_sandbox, PLUGIN_SANDBOX_ARG};
+
+fn main() {
+    if std::env::args().nth(1).as_deref() != Some(PLUGIN_SANDBOX_ARG) {
+        eprintln!("usage: plugin_sandbox {}", PLUGIN_SANDBOX_ARG);
+        std::process::exit(2);
+    }
+    if let Err(e) = run_plugin_sandbox() {
+        eprintln!("❌ Plugin sandbox failed: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -1,3 +1,910 @@
 // Audio analysis: FFT, beat detection, etc.
-pub struct AudioAnalyzer;
-pub struct BeatDetector;
\ No newline at end of file
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use anyhow::Result;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use crate::audio::{AudioEngine, AudioMetrics, LoudnessMeter, LoudnessReading};
+use crate::core::{AudioBufferData, AudioEventType, DataType, InputPort, Node, NodeId, OutputPort, VjEvent};
+
+/// Which signal the live analysis listens to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnalysisSource {
+    /// Captured input, falling back to the output when there is no input device
+    Input,
+    /// Rendered output
+    Output,
+}
+
+/// Analysis configuration
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisSettings {
+    /// FFT length in samples; rounded up to a power of two
+    pub fft_size: usize,
+    /// Fraction of each FFT frame shared with the next, 0.0..0.95
+    pub overlap: f32,
+    /// Seconds for a peak reading to fall by 20 dB
+    pub peak_release_secs: f32,
+    /// RMS integration time constant in seconds
+    pub rms_window_secs: f32,
+    /// Spectrum smoothing between frames, 0.0 (none) .. 1.0 (frozen)
+    pub spectrum_smoothing: f32,
+    pub source: AnalysisSource,
+}
+
+impl Default for AnalysisSettings {
+    fn default() -> Self {
+        Self {
+            fft_size: 2048,
+            overlap: 0.5,
+            peak_release_secs: 1.5,
+            rms_window_secs: 0.3,
+            spectrum_smoothing: 0.5,
+            source: AnalysisSource::Input,
+        }
+    }
+}
+
+/// Named frequency bands, log-spaced across the audible range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FrequencyBand {
+    Sub,
+    Bass,
+    LowMid,
+    Mid,
+    High,
+}
+
+impl FrequencyBand {
+    pub const ALL: [FrequencyBand; 5] = [Self::Sub, Self::Bass, Self::LowMid, Self::Mid, Self::High];
+
+    /// Frequency range in Hz
+    pub fn range(&self) -> (f32, f32) {
+        match self {
+            Self::Sub => (20.0, 60.0),
+            Self::Bass => (60.0, 250.0),
+            Self::LowMid => (250.0, 1000.0),
+            Self::Mid => (1000.0, 4000.0),
+            Self::High => (4000.0, 20000.0),
+        }
+    }
+
+    /// Port and field name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sub => "sub",
+            Self::Bass => "bass",
+            Self::LowMid => "low_mid",
+            Self::Mid => "mid",
+            Self::High => "high",
+        }
+    }
+}
+
+/// Level of each frequency band, as linear amplitude
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BandLevels {
+    pub sub: f32,
+    pub bass: f32,
+    pub low_mid: f32,
+    pub mid: f32,
+    pub high: f32,
+}
+
+impl BandLevels {
+    pub fn get(&self, band: FrequencyBand) -> f32 {
+        match band {
+            FrequencyBand::Sub => self.sub,
+            FrequencyBand::Bass => self.bass,
+            FrequencyBand::LowMid => self.low_mid,
+            FrequencyBand::Mid => self.mid,
+            FrequencyBand::High => self.high,
+        }
+    }
+
+    fn set(&mut self, band: FrequencyBand, level: f32) {
+        match band {
+            FrequencyBand::Sub => self.sub = level,
+            FrequencyBand::Bass => self.bass = level,
+            FrequencyBand::LowMid => self.low_mid = level,
+            FrequencyBand::Mid => self.mid = level,
+            FrequencyBand::High => self.high = level,
+        }
+    }
+}
+
+/// Hann window of the given length
+pub fn hann_window(size: usize) -> Vec<f32> {
+    (0..size)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / size as f32).cos())
+        .collect()
+}
+
+/// Windowed, overlapping magnitude spectrum of a mono stream
+pub struct SpectrumAnalyzer {
+    fft_size: usize,
+    hop: usize,
+    sample_rate: f32,
+    smoothing: f32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    window_sum: f32,
+    history: VecDeque<f32>,
+    since_frame: usize,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(fft_size: usize, overlap: f32, sample_rate: f32) -> Self {
+        let fft_size = fft_size.max(16).next_power_of_two();
+        let overlap = overlap.clamp(0.0, 0.95);
+        let hop = ((fft_size as f32 * (1.0 - overlap)) as usize).max(1);
+
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+        let window = hann_window(fft_size);
+        let window_sum = window.iter().sum();
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+
+        Self {
+            fft_size,
+            hop,
+            sample_rate,
+            smoothing: 0.0,
+            fft,
+            window,
+            window_sum,
+            history: VecDeque::with_capacity(fft_size),
+            since_frame: 0,
+            buffer: vec![Complex::default(); fft_size],
+            scratch,
+            magnitudes: vec![0.0; fft_size / 2 + 1],
+        }
+    }
+
+    pub fn with_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.clamp(0.0, 0.99);
+        self
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Width of one bin in Hz
+    pub fn bin_width(&self) -> f32 {
+        self.sample_rate / self.fft_size as f32
+    }
+
+    /// Feed samples; returns the number of new spectrum frames computed
+    pub fn push(&mut self, samples: &[f32]) -> usize {
+        self.push_with(samples, |_| {})
+    }
+
+    /// Feed samples, calling `on_frame` with the magnitudes of every new frame
+    pub fn push_with(&mut self, samples: &[f32], mut on_frame: impl FnMut(&[f32])) -> usize {
+        let mut frames = 0;
+        for &sample in samples {
+            if self.history.len() == self.fft_size {
+                self.history.pop_front();
+            }
+            self.history.push_back(sample);
+            self.since_frame += 1;
+
+            if self.history.len() == self.fft_size && self.since_frame >= self.hop {
+                self.compute_frame();
+                self.since_frame = 0;
+                frames += 1;
+                on_frame(&self.magnitudes);
+            }
+        }
+        frames
+    }
+
+    fn compute_frame(&mut self) {
+        for ((slot, &sample), &weight) in self.buffer.iter_mut().zip(self.history.iter()).zip(self.window.iter()) {
+            *slot = Complex::new(sample * weight, 0.0);
+        }
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        // A full-scale sine reads 1.0 in its bin
+        let scale = 2.0 / self.window_sum;
+        for (magnitude, bin) in self.magnitudes.iter_mut().zip(self.buffer.iter()) {
+            let value = bin.norm() * scale;
+            *magnitude = *magnitude * self.smoothing + value * (1.0 - self.smoothing);
+        }
+    }
+
+    /// Magnitude per bin from DC to Nyquist, as linear amplitude
+    pub fn magnitudes(&self) -> &[f32] {
+        &self.magnitudes
+    }
+
+    /// Level of a frequency range, combining the power of its bins
+    pub fn range_level(&self, low_hz: f32, high_hz: f32) -> f32 {
+        let bin_width = self.bin_width();
+        let first = ((low_hz / bin_width).ceil() as usize).max(1);
+        let last = ((high_hz / bin_width).floor() as usize).min(self.magnitudes.len() - 1);
+        if first > last {
+            // Range narrower than a bin: read the nearest one
+            let nearest = ((low_hz + high_hz) * 0.5 / bin_width).round() as usize;
+            return self.magnitudes.get(nearest).copied().unwrap_or(0.0);
+        }
+
+        // Divide by the Hann window's noise bandwidth so a sine reads its amplitude
+        let power: f32 = self.magnitudes[first..=last].iter().map(|m| m * m).sum();
+        (power / 1.5).sqrt()
+    }
+
+    pub fn band_levels(&self) -> BandLevels {
+        let mut levels = BandLevels::default();
+        for band in FrequencyBand::ALL {
+            let (low, high) = band.range();
+            levels.set(band, self.range_level(low, high));
+        }
+        levels
+    }
+}
+
+/// Peak and RMS meter for one channel.
+///
+/// Peaks attack instantly and fall exponentially; RMS is an exponential average of power.
+#[derive(Debug, Clone, Default)]
+pub struct LevelMeter {
+    peak: f32,
+    power: f32,
+}
+
+impl LevelMeter {
+    /// Meter every `stride`-th sample starting at `offset`
+    pub fn process(&mut self, samples: &[f32], offset: usize, stride: usize, sample_rate: f32, settings: &AnalysisSettings) {
+        let release = 0.1f32.powf(1.0 / (settings.peak_release_secs.max(1e-3) * sample_rate));
+        let rms_coef = (-1.0 / (settings.rms_window_secs.max(1e-3) * sample_rate)).exp();
+
+        for &sample in samples.iter().skip(offset).step_by(stride.max(1)) {
+            let level = sample.abs();
+            self.peak = if level > self.peak { level } else { self.peak * release };
+            self.power = self.power * rms_coef + sample * sample * (1.0 - rms_coef);
+        }
+    }
+
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    pub fn rms(&self) -> f32 {
+        self.power.sqrt()
+    }
+}
+
+/// Tempo estimate from the onset autocorrelation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TempoEstimate {
+    pub bpm: f32,
+    /// Normalized autocorrelation at the beat period, 0.0..1.0
+    pub confidence: f32,
+}
+
+/// What happened during one `BeatDetector::process` call
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BeatUpdate {
+    pub onsets: u32,
+    pub beats: u32,
+    /// Strongest onset detection value among the new onsets
+    pub onset_strength: f32,
+}
+
+/// Onset detection and tempo/phase tracking over a mono stream.
+///
+/// Onsets are peaks of the log-compressed spectral flux above an adaptive threshold.
+/// Tempo comes from the autocorrelation of the flux, scored over the first few multiples
+/// of each candidate period so that half-tempo peaks lose to the true period. Beats are
+/// predicted from the period and the phase that best lines up with recent onsets.
+pub struct BeatDetector {
+    spectrum: SpectrumAnalyzer,
+    previous: Vec<f32>,
+    flux: VecDeque<f32>,
+    frame_rate: f32,
+    frame: u64,
+    min_bpm: f32,
+    max_bpm: f32,
+    /// Threshold in standard deviations above the local mean flux
+    pub sensitivity: f32,
+    last_onset: Option<u64>,
+    frames_since_tempo: usize,
+    tempo: Option<TempoEstimate>,
+    next_beat: Option<f64>,
+    last_beat: f64,
+    beat_count: u64,
+}
+
+/// Seconds of flux history kept for tempo estimation
+const TEMPO_HISTORY_SECS: f32 = 8.0;
+
+/// Seconds of history needed before the first tempo estimate
+const TEMPO_MIN_HISTORY_SECS: f32 = 3.0;
+
+/// Seconds between tempo updates
+const TEMPO_UPDATE_SECS: f32 = 0.5;
+
+/// Minimum time between onsets
+const MIN_ONSET_INTERVAL_SECS: f32 = 0.06;
+
+/// Period multiples summed when scoring a tempo candidate
+const COMB_HARMONICS: usize = 4;
+
+impl BeatDetector {
+    pub fn new(sample_rate: f32) -> Self {
+        let spectrum = SpectrumAnalyzer::new(1024, 0.75, sample_rate);
+        let frame_rate = sample_rate / spectrum.hop() as f32;
+        let bins = spectrum.magnitudes().len();
+
+        Self {
+            spectrum,
+            previous: vec![0.0; bins],
+            flux: VecDeque::new(),
+            frame_rate,
+            frame: 0,
+            min_bpm: 60.0,
+            max_bpm: 200.0,
+            sensitivity: 1.5,
+            last_onset: None,
+            frames_since_tempo: 0,
+            tempo: None,
+            next_beat: None,
+            last_beat: 0.0,
+            beat_count: 0,
+        }
+    }
+
+    /// Limit tempo estimates to a range
+    pub fn with_tempo_range(mut self, min_bpm: f32, max_bpm: f32) -> Self {
+        self.min_bpm = min_bpm.max(1.0);
+        self.max_bpm = max_bpm.max(self.min_bpm + 1.0);
+        self
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.spectrum.sample_rate()
+    }
+
+    /// Onset detection frames per second
+    pub fn frame_rate(&self) -> f32 {
+        self.frame_rate
+    }
+
+    pub fn tempo(&self) -> Option<TempoEstimate> {
+        self.tempo
+    }
+
+    /// Position within the current beat, 0.0 at the beat .. 1.0 just before the next
+    pub fn phase(&self) -> f32 {
+        match (self.tempo, self.next_beat) {
+            (Some(tempo), Some(next)) => {
+                let period = self.frame_rate * 60.0 / tempo.bpm;
+                (1.0 - (next - self.frame as f64) as f32 / period).clamp(0.0, 1.0)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Predicted beats so far
+    pub fn beat_count(&self) -> u64 {
+        self.beat_count
+    }
+
+    /// Time of the last predicted beat, in seconds of processed audio
+    pub fn last_beat_secs(&self) -> f64 {
+        self.last_beat / self.frame_rate as f64
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> BeatUpdate {
+        let mut update = BeatUpdate::default();
+        let mut flux_frames = Vec::new();
+
+        let previous = &mut self.previous;
+        self.spectrum.push_with(samples, |magnitudes| {
+            let mut flux = 0.0;
+            for (previous, &magnitude) in previous.iter_mut().zip(magnitudes) {
+                let compressed = (1.0 + 100.0 * magnitude).ln();
+                flux += (compressed - *previous).max(0.0);
+                *previous = compressed;
+            }
+            flux_frames.push(flux);
+        });
+
+        for flux in flux_frames {
+            self.push_flux(flux, &mut update);
+        }
+        update
+    }
+
+    fn push_flux(&mut self, flux: f32, update: &mut BeatUpdate) {
+        let history_len = (TEMPO_HISTORY_SECS * self.frame_rate) as usize;
+        if self.flux.len() == history_len {
+            self.flux.pop_front();
+        }
+        self.flux.push_back(flux);
+        self.frame += 1;
+
+        if let Some(strength) = self.detect_onset() {
+            update.onsets += 1;
+            update.onset_strength = update.onset_strength.max(strength);
+        }
+
+        self.frames_since_tempo += 1;
+        let min_history = (TEMPO_MIN_HISTORY_SECS * self.frame_rate) as usize;
+        if self.flux.len() >= min_history && self.frames_since_tempo as f32 >= TEMPO_UPDATE_SECS * self.frame_rate {
+            self.frames_since_tempo = 0;
+            self.update_tempo();
+        }
+
+        if let (Some(tempo), Some(next)) = (self.tempo, self.next_beat.as_mut()) {
+            let period = (self.frame_rate * 60.0 / tempo.bpm) as f64;
+            if self.frame as f64 >= *next {
+                self.last_beat = *next;
+                *next += period;
+                self.beat_count += 1;
+                update.beats += 1;
+            }
+        }
+    }
+
+    /// Check whether the previous flux frame is an onset peak
+    fn detect_onset(&mut self) -> Option<f32> {
+        let len = self.flux.len();
+        if len < 3 {
+            return None;
+        }
+
+        let candidate = self.flux[len - 2];
+        if candidate <= self.flux[len - 3] || candidate < self.flux[len - 1] {
+            return None;
+        }
+
+        let window = ((0.5 * self.frame_rate) as usize).min(len);
+        let recent = self.flux.range(len - window..);
+        let mean = recent.clone().sum::<f32>() / window as f32;
+        let variance = recent.map(|f| (f - mean).powi(2)).sum::<f32>() / window as f32;
+        let threshold = mean + self.sensitivity * variance.sqrt() + 1e-3;
+        if candidate <= threshold {
+            return None;
+        }
+
+        let onset_frame = self.frame - 1;
+        let min_interval = (MIN_ONSET_INTERVAL_SECS * self.frame_rate) as u64;
+        if self.last_onset.is_some_and(|last| onset_frame < last + min_interval) {
+            return None;
+        }
+        self.last_onset = Some(onset_frame);
+        Some(candidate)
+    }
+
+    fn update_tempo(&mut self) {
+        let mean = self.flux.iter().sum::<f32>() / self.flux.len() as f32;
+        let signal: Vec<f32> = self.flux.iter().map(|f| f - mean).collect();
+        let n = signal.len();
+
+        let min_lag = (self.frame_rate * 60.0 / self.max_bpm).floor().max(1.0) as usize;
+        let max_lag = (self.frame_rate * 60.0 / self.min_bpm).ceil() as usize;
+        let acf_len = (max_lag * COMB_HARMONICS + 2).min(n);
+        let acf: Vec<f32> = (0..acf_len)
+            .map(|lag| signal[..n - lag].iter().zip(&signal[lag..]).map(|(a, b)| a * b).sum::<f32>() / n as f32)
+            .collect();
+        if acf[0] <= 0.0 {
+            return;
+        }
+
+        // The true period rarely lands on a whole frame, so each multiple is read over a
+        // window that widens with the harmonic
+        let spread = |k: usize| 1 + k / 2;
+        let score = |lag: usize| -> f32 {
+            (1..=COMB_HARMONICS)
+                .filter(|&k| k * lag + spread(k) < acf.len())
+                .map(|k| acf[k * lag - spread(k)..=k * lag + spread(k)].iter().sum::<f32>() / k as f32)
+                .sum()
+        };
+
+        let Some(best) = (min_lag..=max_lag.min(acf.len() - 1)).max_by(|&a, &b| score(a).total_cmp(&score(b))) else {
+            return;
+        };
+
+        // Refine on the highest multiple that fits: its peak pins the period most precisely
+        let Some((k, peak)) = (1..=COMB_HARMONICS).rev()
+            .filter(|&k| k * best + spread(k) + 1 < acf.len())
+            .find_map(|k| {
+                let centre = k * best;
+                (centre - spread(k)..=centre + spread(k))
+                    .max_by(|&a, &b| acf[a].total_cmp(&acf[b]))
+                    .map(|peak| (k, peak))
+            })
+        else {
+            return;
+        };
+
+        // Parabolic interpolation between neighbouring lags
+        let mut peak_lag = peak as f32;
+        let (left, centre, right) = (acf[peak - 1], acf[peak], acf[peak + 1]);
+        let denominator = left - 2.0 * centre + right;
+        if denominator.abs() > f32::EPSILON {
+            peak_lag += (0.5 * (left - right) / denominator).clamp(-0.5, 0.5);
+        }
+        let period = peak_lag / k as f32;
+
+        let confidence = (acf[peak] / acf[0]).clamp(0.0, 1.0);
+        self.tempo = Some(TempoEstimate {
+            bpm: self.frame_rate * 60.0 / period,
+            confidence,
+        });
+        self.align_phase(period);
+    }
+
+    /// Place the next beat on the phase whose comb sums the most recent onset energy
+    fn align_phase(&mut self, period: f32) {
+        let len = self.flux.len();
+        let beats_back = (len as f32 / period) as usize;
+        let Some(best_offset) = (0..period.ceil() as usize).max_by(|&a, &b| {
+            let energy = |offset: usize| -> f32 {
+                (0..beats_back)
+                    .filter_map(|k| {
+                        let back = offset + (k as f32 * period).round() as usize;
+                        (back < len).then(|| self.flux[len - 1 - back])
+                    })
+                    .sum()
+            };
+            energy(a).total_cmp(&energy(b))
+        }) else {
+            return;
+        };
+
+        let last_beat = (self.frame - 1 - best_offset as u64) as f64;
+        let mut next = last_beat + period as f64;
+        while next <= self.frame as f64 {
+            next += period as f64;
+        }
+        // Keep beats already counted from firing again after a small re-alignment
+        if let Some(current) = self.next_beat {
+            if (next - current).abs() > period as f64 * 0.5 && next < current {
+                next += period as f64;
+            }
+        }
+        self.next_beat = Some(next);
+    }
+}
+
+/// One analysis result
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisFrame {
+    pub spectrum: Vec<f32>,
+    pub bands: BandLevels,
+    /// Peak level per channel
+    pub peak: Vec<f32>,
+    /// RMS level per channel
+    pub rms: Vec<f32>,
+    /// Estimated tempo, 0 until enough audio has been heard
+    pub bpm: f32,
+    pub tempo_confidence: f32,
+    /// Position within the current beat, 0.0..1.0
+    pub beat_phase: f32,
+    /// A beat fell within the last processed block
+    pub beat: bool,
+    /// An onset fell within the last processed block
+    pub onset: bool,
+    pub beat_count: u64,
+    pub loudness: LoudnessReading,
+}
+
+impl AnalysisFrame {
+    /// Loudest channel peak
+    pub fn peak_level(&self) -> f32 {
+        self.peak.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Loudest channel RMS
+    pub fn rms_level(&self) -> f32 {
+        self.rms.iter().copied().fold(0.0, f32::max)
+    }
+}
+
+/// Spectrum plus per-channel meters over interleaved audio
+#[derive(Resource)]
+pub struct AudioAnalyzer {
+    settings: AnalysisSettings,
+    spectrum: SpectrumAnalyzer,
+    beats: BeatDetector,
+    meters: Vec<LevelMeter>,
+    loudness: Option<LoudnessMeter>,
+    mono: Vec<f32>,
+    frame: AnalysisFrame,
+}
+
+impl Default for AudioAnalyzer {
+    fn default() -> Self {
+        Self::new(AnalysisSettings::default(), 44100.0)
+    }
+}
+
+impl AudioAnalyzer {
+    pub fn new(settings: AnalysisSettings, sample_rate: f32) -> Self {
+        let spectrum = SpectrumAnalyzer::new(settings.fft_size, settings.overlap, sample_rate)
+            .with_smoothing(settings.spectrum_smoothing);
+        Self {
+            settings,
+            spectrum,
+            beats: BeatDetector::new(sample_rate),
+            meters: Vec::new(),
+            loudness: None,
+            mono: Vec::new(),
+            frame: AnalysisFrame::default(),
+        }
+    }
+
+    pub fn settings(&self) -> &AnalysisSettings {
+        &self.settings
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.spectrum.sample_rate()
+    }
+
+    pub fn spectrum(&self) -> &SpectrumAnalyzer {
+        &self.spectrum
+    }
+
+    pub fn beats(&self) -> &BeatDetector {
+        &self.beats
+    }
+
+    /// Restart integrated loudness, loudness range and true peak
+    pub fn reset_loudness(&mut self) {
+        if let Some(meter) = &mut self.loudness {
+            meter.reset();
+        }
+        self.frame.loudness = LoudnessReading::default();
+    }
+
+    /// Analyze interleaved samples
+    pub fn process(&mut self, samples: &[f32], channels: usize) -> BeatUpdate {
+        let channels = channels.max(1);
+        let sample_rate = self.spectrum.sample_rate();
+        self.meters.resize_with(channels, LevelMeter::default);
+        for (channel, meter) in self.meters.iter_mut().enumerate() {
+            meter.process(samples, channel, channels, sample_rate, &self.settings);
+        }
+        if self.loudness.as_ref().is_none_or(|meter| meter.channels() != channels) {
+            self.loudness = Some(LoudnessMeter::new(sample_rate, channels));
+        }
+        let loudness = self.loudness.as_mut().expect("loudness meter created above");
+        loudness.process(samples);
+        self.frame.loudness = loudness.reading();
+
+        self.mono.clear();
+        self.mono.extend(samples.chunks(channels).map(|frame| frame.iter().sum::<f32>() / channels as f32));
+        let frames = self.spectrum.push(&self.mono);
+        let update = self.beats.process(&self.mono);
+
+        if frames > 0 {
+            self.frame.spectrum.clear();
+            self.frame.spectrum.extend_from_slice(self.spectrum.magnitudes());
+            self.frame.bands = self.spectrum.band_levels();
+        }
+        self.frame.peak = self.meters.iter().map(LevelMeter::peak).collect();
+        self.frame.rms = self.meters.iter().map(LevelMeter::rms).collect();
+
+        let tempo = self.beats.tempo();
+        self.frame.bpm = tempo.map(|t| t.bpm).unwrap_or(0.0);
+        self.frame.tempo_confidence = tempo.map(|t| t.confidence).unwrap_or(0.0);
+        self.frame.beat_phase = self.beats.phase();
+        self.frame.beat = update.beats > 0;
+        self.frame.onset = update.onsets > 0;
+        self.frame.beat_count = self.beats.beat_count();
+        update
+    }
+
+    /// Latest analysis result
+    pub fn frame(&self) -> &AnalysisFrame {
+        &self.frame
+    }
+}
+
+/// Latest live analysis, shared with graph nodes
+#[derive(Resource, Clone, Default)]
+pub struct SharedAnalysis(Arc<RwLock<AnalysisFrame>>);
+
+impl SharedAnalysis {
+    pub fn latest(&self) -> AnalysisFrame {
+        self.0.read().map(|frame| frame.clone()).unwrap_or_default()
+    }
+
+    fn publish(&self, frame: &AnalysisFrame) {
+        if let Ok(mut latest) = self.0.write() {
+            latest.clone_from(frame);
+        }
+    }
+}
+
+/// Analysis state read and written by `analyze_audio`
+#[derive(SystemParam)]
+pub(crate) struct AnalysisResources<'w> {
+    settings: Res<'w, AnalysisSettings>,
+    analyzer: ResMut<'w, AudioAnalyzer>,
+    shared: Res<'w, SharedAnalysis>,
+    metrics: Option<ResMut<'w, AudioMetrics>>,
+}
+
+/// Analyze the engine's audio and publish the results
+pub(crate) fn analyze_audio(
+    engine: Option<NonSendMut<AudioEngine>>,
+    analysis: AnalysisResources,
+    time: Res<Time>,
+    mut vj_events: MessageWriter<VjEvent>,
+    mut samples: Local<Vec<f32>>,
+) {
+    let Some(mut engine) = engine else {
+        return;
+    };
+    let AnalysisResources { settings, mut analyzer, shared, metrics } = analysis;
+
+    if *analyzer.settings() != *settings || analyzer.sample_rate() != engine.sample_rate() {
+        *analyzer = AudioAnalyzer::new(settings.clone(), engine.sample_rate());
+    }
+
+    samples.clear();
+    let channels = if settings.source == AnalysisSource::Input && engine.has_input() {
+        engine.read_input(&mut samples);
+        engine.input_channels()
+    } else {
+        engine.read_output(&mut samples);
+        engine.channels()
+    };
+    if samples.is_empty() {
+        return;
+    }
+
+    let update = analyzer.process(&samples, channels);
+    let frame = analyzer.frame();
+    shared.publish(frame);
+
+    for _ in 0..update.beats {
+        vj_events.write(VjEvent::AudioEvent {
+            event_type: AudioEventType::BeatDetected {
+                bpm: frame.bpm,
+                confidence: frame.tempo_confidence,
+            },
+        });
+    }
+
+    if let Some(mut metrics) = metrics {
+        metrics.spectrum.clone_from(&frame.spectrum);
+        metrics.bands = frame.bands;
+        metrics.peak_level_left = frame.peak.first().copied().unwrap_or(0.0);
+        metrics.peak_level_right = frame.peak.get(1).copied().unwrap_or(metrics.peak_level_left);
+        metrics.rms_level_left = frame.rms.first().copied().unwrap_or(0.0);
+        metrics.rms_level_right = frame.rms.get(1).copied().unwrap_or(metrics.rms_level_left);
+        metrics.current_bpm = frame.bpm;
+        metrics.loudness = frame.loudness;
+        if update.beats > 0 {
+            metrics.beats_detected += update.beats;
+            metrics.last_beat_time = time.elapsed_secs_f64();
+        }
+    }
+}
+
+/// Graph node publishing spectrum, band and level analysis.
+///
+/// Analyzes the `audio` input when connected, otherwise reports the live analysis.
+pub struct AudioAnalysisNode {
+    pub id: NodeId,
+    pub settings: AnalysisSettings,
+    analyzer: Option<AudioAnalyzer>,
+    live: Option<SharedAnalysis>,
+}
+
+impl AudioAnalysisNode {
+    pub fn new() -> Self {
+        Self {
+            id: NodeId::new(),
+            settings: AnalysisSettings::default(),
+            analyzer: None,
+            live: None,
+        }
+    }
+
+    /// Report the live analysis when no audio is connected
+    pub fn with_live(mut self, live: SharedAnalysis) -> Self {
+        self.live = Some(live);
+        self
+    }
+}
+
+impl Default for AudioAnalysisNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Node for AudioAnalysisNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "AudioAnalysis"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![InputPort::optional("audio", DataType::AudioBuffer)]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        let mut outputs = vec![OutputPort::new("spectrum", DataType::Array)];
+        outputs.extend(FrequencyBand::ALL.iter().map(|band| OutputPort::new(band.name(), DataType::Float)));
+        outputs.push(OutputPort::new("peak", DataType::Float));
+        outputs.push(OutputPort::new("rms", DataType::Float));
+        outputs.push(OutputPort::new("beat", DataType::Boolean));
+        outputs.push(OutputPort::new("onset", DataType::Boolean));
+        outputs.push(OutputPort::new("bpm", DataType::Float));
+        outputs.push(OutputPort::new("tempo_confidence", DataType::Float));
+        outputs.push(OutputPort::new("beat_phase", DataType::Float));
+        outputs.extend(LoudnessReading::NAMES.iter().map(|name| OutputPort::new(name, DataType::Float)));
+        outputs
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        let audio = inputs.get("audio").and_then(|value| AudioBufferData::from_value(value, 44100.0));
+
+        let frame = match audio {
+            Some(audio) => {
+                let stale = self.analyzer.as_ref().is_none_or(|analyzer| {
+                    *analyzer.settings() != self.settings || analyzer.sample_rate() != audio.sample_rate
+                });
+                if stale {
+                    self.analyzer = Some(AudioAnalyzer::new(self.settings.clone(), audio.sample_rate));
+                }
+                let analyzer = self.analyzer.as_mut().expect("analyzer created above");
+                analyzer.process(&audio.samples, audio.channels);
+                analyzer.frame().clone()
+            }
+            None => self.live.as_ref().map(SharedAnalysis::latest).unwrap_or_default(),
+        };
+
+        let mut outputs = HashMap::new();
+        outputs.insert("spectrum".to_string(), serde_json::to_value(&frame.spectrum)?);
+        for band in FrequencyBand::ALL {
+            outputs.insert(band.name().to_string(), serde_json::json!(frame.bands.get(band)));
+        }
+        outputs.insert("peak".to_string(), serde_json::json!(frame.peak_level()));
+        outputs.insert("rms".to_string(), serde_json::json!(frame.rms_level()));
+        outputs.insert("beat".to_string(), Value::Bool(frame.beat));
+        outputs.insert("onset".to_string(), Value::Bool(frame.onset));
+        outputs.insert("bpm".to_string(), serde_json::json!(frame.bpm));
+        outputs.insert("tempo_confidence".to_string(), serde_json::json!(frame.tempo_confidence));
+        outputs.insert("beat_phase".to_string(), serde_json::json!(frame.beat_phase));
+        for (name, value) in LoudnessReading::NAMES.iter().zip(frame.loudness.values()) {
+            outputs.insert(name.to_string(), serde_json::json!(value));
+        }
+        Ok(outputs)
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        let mut parameters = HashMap::new();
+        parameters.insert("settings".to_string(), serde_json::to_value(&self.settings).unwrap_or(Value::Null));
+        parameters
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
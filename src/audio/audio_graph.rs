@@ -0,0 +1,1123 @@
+//! Audio-rate node graph
+//!
+//! Runs inside the audio callback in fixed blocks of `AUDIO_BLOCK_SIZE` frames,
+//! separate from the frame-rate `NodeGraph`. Nodes live on the audio thread; the Bevy
+//! side edits an `AudioGraph` description, compiles it into a schedule and swaps the
+//! schedule in through a ring buffer, so the callback never allocates or locks. Bridge
+//! nodes carry control values in and signal measurements out.
+
+use anyhow::Result;
+use bevy::prelude::*;
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::f32::consts::TAU;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::audio::{AudioEngine, AudioProcessor, ProcessContext, ProcessorId};
+use crate::core::{AudioBufferData, DataType, InputPort, Node, NodeId, OutputPort, VjError};
+
+/// Frames per graph block. The graph adds one block of latency.
+pub const AUDIO_BLOCK_SIZE: usize = 64;
+
+/// Most input or output ports on one audio node
+pub const MAX_NODE_PORTS: usize = 16;
+
+/// Most nodes in one audio graph
+pub const MAX_AUDIO_NODES: usize = 256;
+
+type Block = [f32; AUDIO_BLOCK_SIZE];
+
+/// Block information passed to audio nodes
+#[derive(Debug, Clone, Copy)]
+pub struct BlockContext {
+    pub sample_rate: f32,
+    /// Engine frame of the block's first sample
+    pub frame_position: u64,
+}
+
+/// Node processing mono signals inside the audio callback.
+///
+/// `process` must be real-time safe: no allocation, locking or I/O. Allocate in the
+/// constructor or in `prepare`, which runs on the Bevy side.
+pub trait AudioNode: Send {
+    fn name(&self) -> &str;
+    fn input_count(&self) -> usize;
+    fn output_count(&self) -> usize;
+
+    /// Named parameters, addressed by index in `set_parameter`
+    fn parameter_names(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// Called once before the node is sent to the audio thread
+    fn prepare(&mut self, _sample_rate: f32) {}
+
+    fn set_parameter(&mut self, _index: usize, _value: f32) {}
+
+    /// Frames of delay between input and output. The graph delays parallel paths so
+    /// they line up where they meet.
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Fill every output block. Unconnected inputs are silent.
+    fn process(&mut self, context: &BlockContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]);
+}
+
+/// Handle to a node in an audio graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AudioNodeId(u64);
+
+/// Where a connection starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AudioSource {
+    /// Engine input channel
+    GraphInput(usize),
+    Node(AudioNodeId, usize),
+}
+
+/// Where a connection ends. Several sources into one sink are summed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AudioSink {
+    /// Engine output channel
+    GraphOutput(usize),
+    Node(AudioNodeId, usize),
+}
+
+/// Buffers feeding one input; `buffer` is the single source, a mix buffer, or silence.
+/// Sources with less latency than others reach `sources` through a delay line.
+struct ScheduledInput {
+    sources: Vec<usize>,
+    buffer: usize,
+    delays: Vec<DelayLine>,
+}
+
+/// Copies `source` into `buffer` a fixed number of frames late
+struct DelayLine {
+    source: usize,
+    buffer: usize,
+    line: Vec<f32>,
+    position: usize,
+}
+
+struct ScheduledNode {
+    slot: usize,
+    inputs: Vec<ScheduledInput>,
+    outputs: Vec<usize>,
+}
+
+/// Compiled processing order and buffer assignment. Buffer 0 is silence, followed by
+/// one buffer per graph input channel.
+struct Schedule {
+    nodes: Vec<ScheduledNode>,
+    outputs: Vec<ScheduledInput>,
+    buffers: Vec<Block>,
+    /// Latency of the graph outputs in frames
+    latency: usize,
+}
+
+enum GraphCommand {
+    AddNode(usize, Box<dyn AudioNode>),
+    RemoveNode(usize),
+    SetSchedule(Box<Schedule>),
+    SetParameter(usize, usize, f32),
+}
+
+/// Objects released by the audio thread, dropped on the Bevy side
+#[allow(dead_code)]
+enum GraphGarbage {
+    Node(Box<dyn AudioNode>),
+    Schedule(Box<Schedule>),
+}
+
+/// Runs the compiled graph in the engine's output callback
+pub struct AudioGraphProcessor {
+    nodes: Vec<Option<Box<dyn AudioNode>>>,
+    schedule: Box<Schedule>,
+    commands: Consumer<GraphCommand>,
+    garbage: Producer<GraphGarbage>,
+    sample_rate: f32,
+    input_channels: usize,
+    output_channels: usize,
+    input_block: Vec<f32>,
+    output_block: Vec<f32>,
+    /// Frames into the current block
+    position: usize,
+    node_outputs: Vec<Block>,
+}
+
+impl AudioGraphProcessor {
+    fn apply_commands(&mut self) {
+        while let Ok(command) = self.commands.pop() {
+            match command {
+                GraphCommand::AddNode(slot, node) => {
+                    if let Some(previous) = self.nodes[slot].replace(node) {
+                        let _ = self.garbage.push(GraphGarbage::Node(previous));
+                    }
+                }
+                GraphCommand::RemoveNode(slot) => {
+                    if let Some(node) = self.nodes[slot].take() {
+                        let _ = self.garbage.push(GraphGarbage::Node(node));
+                    }
+                }
+                GraphCommand::SetSchedule(schedule) => {
+                    let previous = std::mem::replace(&mut self.schedule, schedule);
+                    let _ = self.garbage.push(GraphGarbage::Schedule(previous));
+                }
+                GraphCommand::SetParameter(slot, index, value) => {
+                    if let Some(node) = self.nodes[slot].as_mut() {
+                        node.set_parameter(index, value);
+                    }
+                }
+            }
+        }
+    }
+
+    fn run_block(&mut self, frame_position: u64) {
+        let context = BlockContext { sample_rate: self.sample_rate, frame_position };
+        let Self { nodes, schedule, input_block, output_block, node_outputs, input_channels, output_channels, .. } = self;
+        let Schedule { nodes: scheduled, outputs, buffers, .. } = schedule.as_mut();
+
+        for channel in 0..*input_channels {
+            let buffer = &mut buffers[1 + channel];
+            for (frame, sample) in buffer.iter_mut().enumerate() {
+                *sample = input_block[frame * *input_channels + channel];
+            }
+        }
+
+        for entry in scheduled.iter_mut() {
+            for input in entry.inputs.iter_mut() {
+                gather_input(buffers, input);
+            }
+
+            let Some(node) = nodes[entry.slot].as_mut() else {
+                for &output in entry.outputs.iter() {
+                    buffers[output] = [0.0; AUDIO_BLOCK_SIZE];
+                }
+                continue;
+            };
+
+            let input_count = entry.inputs.len();
+            let output_count = entry.outputs.len();
+            {
+                let input_refs: [&[f32]; MAX_NODE_PORTS] = std::array::from_fn(|port| {
+                    entry.inputs.get(port).map(|input| &buffers[input.buffer][..]).unwrap_or(&[])
+                });
+                let mut scratch = node_outputs.iter_mut();
+                let mut output_refs: [&mut [f32]; MAX_NODE_PORTS] = std::array::from_fn(|_| {
+                    scratch.next().map(|block| &mut block[..]).unwrap_or_default()
+                });
+                for output in output_refs[..output_count].iter_mut() {
+                    output.fill(0.0);
+                }
+                node.process(&context, &input_refs[..input_count], &mut output_refs[..output_count]);
+            }
+
+            for (port, &output) in entry.outputs.iter().enumerate() {
+                buffers[output] = node_outputs[port];
+            }
+        }
+
+        output_block.fill(0.0);
+        for (channel, output) in outputs.iter_mut().enumerate().take(*output_channels) {
+            gather_input(buffers, output);
+            for (frame, &sample) in buffers[output.buffer].iter().enumerate() {
+                output_block[frame * *output_channels + channel] = sample;
+            }
+        }
+    }
+}
+
+/// Run an input's delay lines, then sum its sources into its mix buffer when it has
+/// more than one
+fn gather_input(buffers: &mut [Block], input: &mut ScheduledInput) {
+    for delay in input.delays.iter_mut() {
+        let source = buffers[delay.source];
+        for (delayed, sample) in buffers[delay.buffer].iter_mut().zip(source) {
+            *delayed = delay.line[delay.position];
+            delay.line[delay.position] = sample;
+            delay.position = (delay.position + 1) % delay.line.len();
+        }
+    }
+    if input.sources.len() < 2 {
+        return;
+    }
+    let mut mix = [0.0; AUDIO_BLOCK_SIZE];
+    for &source in &input.sources {
+        for (total, sample) in mix.iter_mut().zip(&buffers[source]) {
+            *total += sample;
+        }
+    }
+    buffers[input.buffer] = mix;
+}
+
+impl AudioProcessor for AudioGraphProcessor {
+    fn process(&mut self, context: &ProcessContext, input: &[f32], output: &mut [f32]) {
+        self.apply_commands();
+
+        for frame in 0..context.frames {
+            let block_offset = self.position;
+            for channel in 0..self.input_channels {
+                let sample = if context.input_channels > 0 {
+                    input.get(frame * context.input_channels + channel % context.input_channels).copied().unwrap_or(0.0)
+                } else {
+                    0.0
+                };
+                self.input_block[block_offset * self.input_channels + channel] = sample;
+            }
+            for channel in 0..context.channels.min(self.output_channels) {
+                output[frame * context.channels + channel] += self.output_block[block_offset * self.output_channels + channel];
+            }
+
+            self.position += 1;
+            if self.position == AUDIO_BLOCK_SIZE {
+                self.position = 0;
+                let block_end = context.frame_position + frame as u64 + 1;
+                self.run_block(block_end - AUDIO_BLOCK_SIZE as u64);
+            }
+        }
+    }
+}
+
+/// Bevy-side description of a node
+struct NodeEntry {
+    slot: usize,
+    name: String,
+    input_count: usize,
+    output_count: usize,
+    parameters: Vec<&'static str>,
+    latency: usize,
+}
+
+/// Editable audio-rate graph. Every edit recompiles the schedule and sends it to the
+/// audio thread.
+#[derive(Resource)]
+pub struct AudioGraph {
+    nodes: HashMap<AudioNodeId, NodeEntry>,
+    connections: Vec<(AudioSource, AudioSink)>,
+    free_slots: Vec<usize>,
+    next_node_id: u64,
+    sample_rate: f32,
+    input_channels: usize,
+    output_channels: usize,
+    commands: Mutex<Producer<GraphCommand>>,
+    garbage: Mutex<Consumer<GraphGarbage>>,
+    processor_id: Option<ProcessorId>,
+    latency: usize,
+}
+
+impl AudioGraph {
+    /// Create an empty graph and the processor that runs it
+    pub fn new(sample_rate: f32, input_channels: usize, output_channels: usize) -> (Self, AudioGraphProcessor) {
+        let (commands, command_consumer) = RingBuffer::new(1024);
+        let (garbage_producer, garbage) = RingBuffer::new(MAX_AUDIO_NODES * 2 + 64);
+
+        let graph = Self {
+            nodes: HashMap::new(),
+            connections: Vec::new(),
+            free_slots: (0..MAX_AUDIO_NODES).rev().collect(),
+            next_node_id: 0,
+            sample_rate,
+            input_channels,
+            output_channels,
+            commands: Mutex::new(commands),
+            garbage: Mutex::new(garbage),
+            processor_id: None,
+            latency: 0,
+        };
+        let schedule = graph.empty_schedule();
+
+        let processor = AudioGraphProcessor {
+            nodes: (0..MAX_AUDIO_NODES).map(|_| None).collect(),
+            schedule: Box::new(schedule),
+            commands: command_consumer,
+            garbage: garbage_producer,
+            sample_rate,
+            input_channels,
+            output_channels,
+            input_block: vec![0.0; AUDIO_BLOCK_SIZE * input_channels],
+            output_block: vec![0.0; AUDIO_BLOCK_SIZE * output_channels],
+            position: 0,
+            node_outputs: vec![[0.0; AUDIO_BLOCK_SIZE]; MAX_NODE_PORTS],
+        };
+        (graph, processor)
+    }
+
+    /// Create a graph matching the engine's channels and add it to the engine
+    pub fn start(engine: &mut AudioEngine) -> Result<Self, VjError> {
+        let input_channels = if engine.has_input() { engine.input_channels() } else { 0 };
+        let (mut graph, processor) = Self::new(engine.sample_rate(), input_channels, engine.channels());
+        graph.processor_id = Some(engine.add_processor(Box::new(processor))?);
+        Ok(graph)
+    }
+
+    pub fn processor_id(&self) -> Option<ProcessorId> {
+        self.processor_id
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn input_channels(&self) -> usize {
+        self.input_channels
+    }
+
+    pub fn output_channels(&self) -> usize {
+        self.output_channels
+    }
+
+    /// Frames the graph outputs lag its inputs through the slowest path, on top of
+    /// the one block every graph adds
+    pub fn latency(&self) -> usize {
+        self.latency
+    }
+
+    fn send(&self, command: GraphCommand) -> Result<(), VjError> {
+        let mut commands = self.commands.lock()
+            .map_err(|_| VjError::AudioError("Audio graph command queue poisoned".to_string()))?;
+        commands.push(command)
+            .map_err(|_| VjError::AudioError("Audio graph command queue full".to_string()))
+    }
+
+    /// Drop nodes and schedules the audio thread has released
+    pub fn collect_garbage(&self) {
+        if let Ok(mut garbage) = self.garbage.lock() {
+            while garbage.pop().is_ok() {}
+        }
+    }
+
+    pub fn add_node(&mut self, mut node: Box<dyn AudioNode>) -> Result<AudioNodeId, VjError> {
+        if node.input_count() > MAX_NODE_PORTS || node.output_count() > MAX_NODE_PORTS {
+            return Err(VjError::NodeError(format!(
+                "Audio node '{}' has more than {} ports", node.name(), MAX_NODE_PORTS
+            )));
+        }
+        self.collect_garbage();
+        let slot = self.free_slots.pop()
+            .ok_or_else(|| VjError::NodeError(format!("Audio graph is full ({} nodes)", MAX_AUDIO_NODES)))?;
+
+        node.prepare(self.sample_rate);
+        let entry = NodeEntry {
+            slot,
+            name: node.name().to_string(),
+            input_count: node.input_count(),
+            output_count: node.output_count(),
+            parameters: node.parameter_names(),
+            latency: node.latency(),
+        };
+        if let Err(e) = self.send(GraphCommand::AddNode(slot, node)) {
+            self.free_slots.push(slot);
+            return Err(e);
+        }
+
+        let id = AudioNodeId(self.next_node_id);
+        self.next_node_id += 1;
+        self.nodes.insert(id, entry);
+        Ok(id)
+    }
+
+    /// Remove a node and its connections
+    pub fn remove_node(&mut self, id: AudioNodeId) -> Result<(), VjError> {
+        let entry = self.nodes.remove(&id)
+            .ok_or_else(|| VjError::NodeError(format!("Unknown audio node {:?}", id)))?;
+        self.connections.retain(|(source, sink)| {
+            !matches!(source, AudioSource::Node(from, _) if *from == id)
+                && !matches!(sink, AudioSink::Node(to, _) if *to == id)
+        });
+        // The new schedule must be in place before the slot is emptied
+        self.commit()?;
+        self.send(GraphCommand::RemoveNode(entry.slot))?;
+        self.free_slots.push(entry.slot);
+        Ok(())
+    }
+
+    pub fn node_name(&self, id: AudioNodeId) -> Option<&str> {
+        self.nodes.get(&id).map(|entry| entry.name.as_str())
+    }
+
+    pub fn node_latency(&self, id: AudioNodeId) -> Option<usize> {
+        self.nodes.get(&id).map(|entry| entry.latency)
+    }
+
+    /// Update a node's latency after it changed, e.g. a plugin switching modes, and
+    /// realign the paths around it
+    pub fn set_node_latency(&mut self, id: AudioNodeId, frames: usize) -> Result<(), VjError> {
+        let entry = self.nodes.get_mut(&id)
+            .ok_or_else(|| VjError::NodeError(format!("Unknown audio node {:?}", id)))?;
+        if entry.latency == frames {
+            return Ok(());
+        }
+        entry.latency = frames;
+        self.commit()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn connections(&self) -> &[(AudioSource, AudioSink)] {
+        &self.connections
+    }
+
+    pub fn connect(&mut self, source: AudioSource, sink: AudioSink) -> Result<(), VjError> {
+        self.validate_source(source)?;
+        self.validate_sink(sink)?;
+        if self.connections.contains(&(source, sink)) {
+            return Ok(());
+        }
+
+        self.connections.push((source, sink));
+        if let Err(e) = self.commit() {
+            self.connections.pop();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self, source: AudioSource, sink: AudioSink) -> Result<(), VjError> {
+        let index = self.connections.iter().position(|connection| *connection == (source, sink))
+            .ok_or_else(|| VjError::ConnectionError("Audio connection not found".to_string()))?;
+        self.connections.remove(index);
+        self.commit()
+    }
+
+    pub fn set_parameter(&self, id: AudioNodeId, name: &str, value: f32) -> Result<(), VjError> {
+        let entry = self.nodes.get(&id)
+            .ok_or_else(|| VjError::NodeError(format!("Unknown audio node {:?}", id)))?;
+        let index = entry.parameters.iter().position(|parameter| *parameter == name)
+            .ok_or_else(|| VjError::NodeError(format!("Audio node '{}' has no parameter '{}'", entry.name, name)))?;
+        self.send(GraphCommand::SetParameter(entry.slot, index, value))
+    }
+
+    fn validate_source(&self, source: AudioSource) -> Result<(), VjError> {
+        match source {
+            AudioSource::GraphInput(channel) if channel < self.input_channels => Ok(()),
+            AudioSource::Node(id, port) if self.nodes.get(&id).is_some_and(|entry| port < entry.output_count) => Ok(()),
+            _ => Err(VjError::ConnectionError(format!("Invalid audio source {:?}", source))),
+        }
+    }
+
+    fn validate_sink(&self, sink: AudioSink) -> Result<(), VjError> {
+        match sink {
+            AudioSink::GraphOutput(channel) if channel < self.output_channels => Ok(()),
+            AudioSink::Node(id, port) if self.nodes.get(&id).is_some_and(|entry| port < entry.input_count) => Ok(()),
+            _ => Err(VjError::ConnectionError(format!("Invalid audio sink {:?}", sink))),
+        }
+    }
+
+    fn empty_schedule(&self) -> Schedule {
+        Schedule {
+            nodes: Vec::new(),
+            outputs: Vec::new(),
+            buffers: vec![[0.0; AUDIO_BLOCK_SIZE]; 1 + self.input_channels],
+            latency: 0,
+        }
+    }
+
+    fn commit(&mut self) -> Result<(), VjError> {
+        self.collect_garbage();
+        let schedule = self.compile()?;
+        let latency = schedule.latency;
+        self.send(GraphCommand::SetSchedule(Box::new(schedule)))?;
+        self.latency = latency;
+        Ok(())
+    }
+
+    /// Order nodes topologically, assign buffers and delay early paths
+    fn compile(&self) -> Result<Schedule, VjError> {
+        let mut dependents: HashMap<AudioNodeId, Vec<AudioNodeId>> = HashMap::new();
+        let mut pending: HashMap<AudioNodeId, usize> = self.nodes.keys().map(|&id| (id, 0)).collect();
+        let mut edges = HashSet::new();
+        for (source, sink) in &self.connections {
+            if let (AudioSource::Node(from, _), AudioSink::Node(to, _)) = (source, sink) {
+                if edges.insert((*from, *to)) {
+                    dependents.entry(*from).or_default().push(*to);
+                    *pending.entry(*to).or_default() += 1;
+                }
+            }
+        }
+
+        // Kahn's algorithm, starting from nodes in id order so schedules are stable
+        let mut ready: Vec<AudioNodeId> = pending.iter().filter(|(_, &count)| count == 0).map(|(&id, _)| id).collect();
+        ready.sort_by_key(|id| id.0);
+        let mut queue: VecDeque<AudioNodeId> = ready.into();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(id) = queue.pop_front() {
+            order.push(id);
+            let mut next: Vec<AudioNodeId> = Vec::new();
+            for dependent in dependents.get(&id).into_iter().flatten() {
+                let count = pending.get_mut(dependent).expect("dependent node is pending");
+                *count -= 1;
+                if *count == 0 {
+                    next.push(*dependent);
+                }
+            }
+            next.sort_by_key(|id| id.0);
+            queue.extend(next);
+        }
+        if order.len() != self.nodes.len() {
+            return Err(VjError::ConnectionError("Audio connection would create a cycle".to_string()));
+        }
+
+        let mut buffer_count = 1 + self.input_channels;
+        let mut output_buffers: HashMap<(AudioNodeId, usize), usize> = HashMap::new();
+        for id in &order {
+            for port in 0..self.nodes[id].output_count {
+                output_buffers.insert((*id, port), buffer_count);
+                buffer_count += 1;
+            }
+        }
+
+        // Latency where each node's inputs meet, the latest of its sources
+        let mut input_latencies: HashMap<AudioNodeId, usize> = HashMap::new();
+        let source_latency = |input_latencies: &HashMap<AudioNodeId, usize>, source: &AudioSource| match source {
+            AudioSource::GraphInput(_) => 0,
+            AudioSource::Node(id, _) => input_latencies[id] + self.nodes[id].latency,
+        };
+        for id in &order {
+            let latency = self.connections.iter()
+                .filter(|(_, sink)| matches!(sink, AudioSink::Node(to, _) if to == id))
+                .map(|(source, _)| source_latency(&input_latencies, source))
+                .max()
+                .unwrap_or(0);
+            input_latencies.insert(*id, latency);
+        }
+        let output_latency = self.connections.iter()
+            .filter(|(_, sink)| matches!(sink, AudioSink::GraphOutput(_)))
+            .map(|(source, _)| source_latency(&input_latencies, source))
+            .max()
+            .unwrap_or(0);
+
+        let source_buffer = |source: &AudioSource| match source {
+            AudioSource::GraphInput(channel) => 1 + channel,
+            AudioSource::Node(id, port) => output_buffers[&(*id, *port)],
+        };
+        let mut schedule_input = |sink: AudioSink, latency: usize| {
+            let mut delays = Vec::new();
+            let sources: Vec<usize> = self.connections.iter()
+                .filter(|(_, to)| *to == sink)
+                .map(|(from, _)| {
+                    let source = source_buffer(from);
+                    let delay = latency - source_latency(&input_latencies, from);
+                    if delay == 0 {
+                        return source;
+                    }
+                    buffer_count += 1;
+                    delays.push(DelayLine { source, buffer: buffer_count - 1, line: vec![0.0; delay], position: 0 });
+                    buffer_count - 1
+                })
+                .collect();
+            let buffer = match sources.len() {
+                0 => 0,
+                1 => sources[0],
+                _ => {
+                    buffer_count += 1;
+                    buffer_count - 1
+                }
+            };
+            ScheduledInput { sources, buffer, delays }
+        };
+
+        let nodes = order.iter()
+            .map(|id| {
+                let entry = &self.nodes[id];
+                let latency = input_latencies[id];
+                ScheduledNode {
+                    slot: entry.slot,
+                    inputs: (0..entry.input_count).map(|port| schedule_input(AudioSink::Node(*id, port), latency)).collect(),
+                    outputs: (0..entry.output_count).map(|port| output_buffers[&(*id, port)]).collect(),
+                }
+            })
+            .collect();
+        let outputs = (0..self.output_channels)
+            .map(|channel| schedule_input(AudioSink::GraphOutput(channel), output_latency))
+            .collect();
+
+        Ok(Schedule {
+            nodes,
+            outputs,
+            buffers: vec![[0.0; AUDIO_BLOCK_SIZE]; buffer_count],
+            latency: output_latency,
+        })
+    }
+}
+
+pub(crate) fn setup_audio_graph(world: &mut World) {
+    let Some(mut audio) = world.get_non_send_resource_mut::<AudioEngine>() else {
+        warn!("⚠️ No audio engine, audio graph disabled");
+        return;
+    };
+
+    match AudioGraph::start(&mut audio) {
+        Ok(graph) => {
+            world.insert_resource(graph);
+            info!("🔀 Audio-rate graph ready ({} frame blocks)", AUDIO_BLOCK_SIZE);
+        }
+        Err(e) => error!("❌ Failed to start audio graph: {}", e),
+    }
+}
+
+pub(crate) fn collect_audio_graph_garbage(graph: Option<Res<AudioGraph>>) {
+    if let Some(graph) = graph {
+        graph.collect_garbage();
+    }
+}
+
+/// Band-limited-enough oscillator for modulation and test tones
+pub struct OscillatorNode {
+    pub waveform: crate::audio::Waveform,
+    frequency: f32,
+    amplitude: f32,
+    phase: f32,
+    sample_rate: f32,
+}
+
+impl OscillatorNode {
+    pub fn new(waveform: crate::audio::Waveform, frequency: f32, amplitude: f32) -> Self {
+        Self { waveform, frequency, amplitude, phase: 0.0, sample_rate: 44100.0 }
+    }
+}
+
+impl AudioNode for OscillatorNode {
+    fn name(&self) -> &str {
+        "Oscillator"
+    }
+
+    /// Input 0 adds to the frequency in Hz, for FM
+    fn input_count(&self) -> usize {
+        1
+    }
+
+    fn output_count(&self) -> usize {
+        1
+    }
+
+    fn parameter_names(&self) -> Vec<&'static str> {
+        vec!["frequency", "amplitude"]
+    }
+
+    fn prepare(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.frequency = value,
+            1 => self.amplitude = value,
+            _ => {}
+        }
+    }
+
+    fn process(&mut self, _context: &BlockContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        use crate::audio::Waveform;
+        for (sample, &modulation) in outputs[0].iter_mut().zip(inputs[0].iter()) {
+            let phase = self.phase;
+            let value = match self.waveform {
+                Waveform::Sine | Waveform::Wavetable | Waveform::Noise => (phase * TAU).sin(),
+                Waveform::Saw => 2.0 * phase - 1.0,
+                Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+                Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            };
+            *sample = value * self.amplitude;
+            self.phase = (phase + (self.frequency + modulation) / self.sample_rate).rem_euclid(1.0);
+        }
+    }
+}
+
+/// Scales a signal by a smoothed gain
+pub struct GainNode {
+    gain: f32,
+    current: f32,
+}
+
+impl GainNode {
+    pub fn new(gain: f32) -> Self {
+        Self { gain, current: gain }
+    }
+}
+
+impl AudioNode for GainNode {
+    fn name(&self) -> &str {
+        "Gain"
+    }
+
+    fn input_count(&self) -> usize {
+        1
+    }
+
+    fn output_count(&self) -> usize {
+        1
+    }
+
+    fn parameter_names(&self) -> Vec<&'static str> {
+        vec!["gain"]
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        if index == 0 {
+            self.gain = value;
+        }
+    }
+
+    fn process(&mut self, _context: &BlockContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        // Ramp across the block so gain changes don't click
+        let step = (self.gain - self.current) / AUDIO_BLOCK_SIZE as f32;
+        for (sample, &input) in outputs[0].iter_mut().zip(inputs[0].iter()) {
+            self.current += step;
+            *sample = input * self.current;
+        }
+        self.current = self.gain;
+    }
+}
+
+/// Product of two signals: ring modulation, or a VCA with a control signal
+pub struct MultiplyNode;
+
+impl AudioNode for MultiplyNode {
+    fn name(&self) -> &str {
+        "Multiply"
+    }
+
+    fn input_count(&self) -> usize {
+        2
+    }
+
+    fn output_count(&self) -> usize {
+        1
+    }
+
+    fn process(&mut self, _context: &BlockContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        for ((sample, &a), &b) in outputs[0].iter_mut().zip(inputs[0].iter()).zip(inputs[1].iter()) {
+            *sample = a * b;
+        }
+    }
+}
+
+/// Spreads an `AudioProcessor`'s interleaved output over mono output ports, so the
+/// synth, file players and Glicol can run inside the graph
+pub struct ProcessorNode {
+    processor: Box<dyn AudioProcessor>,
+    channels: usize,
+    sample_rate: f32,
+    interleaved: Vec<f32>,
+}
+
+impl ProcessorNode {
+    pub fn new(processor: Box<dyn AudioProcessor>, channels: usize) -> Self {
+        let channels = channels.clamp(1, MAX_NODE_PORTS);
+        Self {
+            processor,
+            channels,
+            sample_rate: 44100.0,
+            interleaved: vec![0.0; AUDIO_BLOCK_SIZE * channels],
+        }
+    }
+}
+
+impl AudioNode for ProcessorNode {
+    fn name(&self) -> &str {
+        "Processor"
+    }
+
+    fn input_count(&self) -> usize {
+        0
+    }
+
+    fn output_count(&self) -> usize {
+        self.channels
+    }
+
+    fn prepare(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn latency(&self) -> usize {
+        self.processor.latency()
+    }
+
+    fn process(&mut self, context: &BlockContext, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let process_context = ProcessContext {
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            input_channels: 0,
+            frames: AUDIO_BLOCK_SIZE,
+            frame_position: context.frame_position,
+        };
+        self.interleaved.fill(0.0);
+        self.processor.process(&process_context, &[], &mut self.interleaved);
+        for (channel, output) in outputs.iter_mut().enumerate() {
+            for (frame, sample) in output.iter_mut().enumerate() {
+                *sample = self.interleaved[frame * self.channels + channel];
+            }
+        }
+    }
+}
+
+/// Sets a control value from outside the audio thread
+#[derive(Debug, Clone)]
+pub struct ControlSender(Arc<AtomicU32>);
+
+impl ControlSender {
+    pub fn set(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// Bridge into the audio graph: outputs a control value as a smoothed signal
+pub struct ControlInputNode {
+    target: Arc<AtomicU32>,
+    current: f32,
+    smoothing_secs: f32,
+    coefficient: f32,
+}
+
+impl ControlInputNode {
+    pub fn new(initial: f32) -> (Self, ControlSender) {
+        let target = Arc::new(AtomicU32::new(initial.to_bits()));
+        let node = Self {
+            target: target.clone(),
+            current: initial,
+            smoothing_secs: 0.01,
+            coefficient: 0.0,
+        };
+        (node, ControlSender(target))
+    }
+}
+
+impl AudioNode for ControlInputNode {
+    fn name(&self) -> &str {
+        "ControlInput"
+    }
+
+    fn input_count(&self) -> usize {
+        0
+    }
+
+    fn output_count(&self) -> usize {
+        1
+    }
+
+    fn parameter_names(&self) -> Vec<&'static str> {
+        vec!["smoothing"]
+    }
+
+    fn prepare(&mut self, sample_rate: f32) {
+        self.coefficient = (-1.0 / (self.smoothing_secs.max(1e-4) * sample_rate)).exp();
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        if index == 0 {
+            self.smoothing_secs = value;
+        }
+    }
+
+    fn process(&mut self, context: &BlockContext, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        if self.smoothing_secs > 0.0 {
+            self.coefficient = (-1.0 / (self.smoothing_secs * context.sample_rate)).exp();
+        } else {
+            self.coefficient = 0.0;
+        }
+        let target = f32::from_bits(self.target.load(Ordering::Relaxed));
+        for sample in outputs[0].iter_mut() {
+            self.current = target + (self.current - target) * self.coefficient;
+            *sample = self.current;
+        }
+    }
+}
+
+/// Signal measurements published by a probe
+#[derive(Debug, Default)]
+struct ProbeLevels {
+    peak: AtomicU32,
+    rms: AtomicU32,
+    last: AtomicU32,
+}
+
+/// Reads what a probe node measured, from any thread
+#[derive(Clone)]
+pub struct ProbeReader {
+    levels: Arc<ProbeLevels>,
+    samples: Arc<Mutex<Consumer<f32>>>,
+    sample_rate: f32,
+}
+
+impl ProbeReader {
+    /// Peak of the last block
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.levels.peak.load(Ordering::Relaxed))
+    }
+
+    /// RMS of the last block
+    pub fn rms(&self) -> f32 {
+        f32::from_bits(self.levels.rms.load(Ordering::Relaxed))
+    }
+
+    /// Last sample of the last block, for control signals such as envelopes
+    pub fn value(&self) -> f32 {
+        f32::from_bits(self.levels.last.load(Ordering::Relaxed))
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Drain the probed signal since the last call
+    pub fn read_samples(&self, buffer: &mut Vec<f32>) {
+        if let Ok(mut samples) = self.samples.lock() {
+            while let Ok(sample) = samples.pop() {
+                buffer.push(sample);
+            }
+        }
+    }
+}
+
+/// Bridge out of the audio graph: measures a signal and copies it for the frame graph
+pub struct ProbeNode {
+    levels: Arc<ProbeLevels>,
+    samples: Producer<f32>,
+}
+
+impl ProbeNode {
+    /// `capacity` is how many samples are kept until read
+    pub fn new(sample_rate: f32, capacity: usize) -> (Self, ProbeReader) {
+        let levels = Arc::new(ProbeLevels::default());
+        let (producer, consumer) = RingBuffer::new(capacity.max(AUDIO_BLOCK_SIZE));
+        let reader = ProbeReader {
+            levels: levels.clone(),
+            samples: Arc::new(Mutex::new(consumer)),
+            sample_rate,
+        };
+        (Self { levels, samples: producer }, reader)
+    }
+}
+
+impl AudioNode for ProbeNode {
+    fn name(&self) -> &str {
+        "Probe"
+    }
+
+    fn input_count(&self) -> usize {
+        1
+    }
+
+    fn output_count(&self) -> usize {
+        0
+    }
+
+    fn process(&mut self, _context: &BlockContext, inputs: &[&[f32]], _outputs: &mut [&mut [f32]]) {
+        let input = inputs[0];
+        let peak = input.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        let rms = (input.iter().map(|sample| sample * sample).sum::<f32>() / input.len().max(1) as f32).sqrt();
+        self.levels.peak.store(peak.to_bits(), Ordering::Relaxed);
+        self.levels.rms.store(rms.to_bits(), Ordering::Relaxed);
+        self.levels.last.store(input.last().copied().unwrap_or(0.0).to_bits(), Ordering::Relaxed);
+
+        let count = self.samples.slots().min(input.len());
+        if let Ok(mut chunk) = self.samples.write_chunk(count) {
+            let (first, second) = chunk.as_mut_slices();
+            let split = first.len();
+            first.copy_from_slice(&input[..split]);
+            second.copy_from_slice(&input[split..count]);
+            chunk.commit_all();
+        }
+    }
+}
+
+/// Frame-graph node feeding a value into the audio graph through a `ControlInputNode`
+pub struct AudioControlNode {
+    pub id: NodeId,
+    sender: ControlSender,
+}
+
+impl AudioControlNode {
+    pub fn new(sender: ControlSender) -> Self {
+        Self { id: NodeId::new(), sender }
+    }
+}
+
+impl Node for AudioControlNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "AudioControl"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![InputPort::new("value", DataType::Float)]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![OutputPort::new("value", DataType::Float)]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        if let Some(value) = inputs.get("value").and_then(Value::as_f64) {
+            self.sender.set(value as f32);
+        }
+
+        let mut outputs = HashMap::new();
+        outputs.insert("value".to_string(), serde_json::json!(self.sender.get()));
+        Ok(outputs)
+    }
+}
+
+/// Frame-graph node reading a `ProbeNode`: levels plus the signal as an audio buffer
+/// for the analysis nodes
+pub struct AudioProbeNode {
+    pub id: NodeId,
+    reader: ProbeReader,
+}
+
+impl AudioProbeNode {
+    pub fn new(reader: ProbeReader) -> Self {
+        Self { id: NodeId::new(), reader }
+    }
+}
+
+impl Node for AudioProbeNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "AudioProbe"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        Vec::new()
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![
+            OutputPort::new("audio", DataType::AudioBuffer),
+            OutputPort::new("peak", DataType::Float),
+            OutputPort::new("rms", DataType::Float),
+            OutputPort::new("value", DataType::Float),
+        ]
+    }
+
+    fn process(&mut self, _inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        let mut samples = Vec::new();
+        self.reader.read_samples(&mut samples);
+
+        let mut outputs = HashMap::new();
+        outputs.insert("audio".to_string(), AudioBufferData::new(samples, 1, self.reader.sample_rate()).to_value());
+        outputs.insert("peak".to_string(), serde_json::json!(self.reader.peak()));
+        outputs.insert("rms".to_string(), serde_json::json!(self.reader.rms()));
+        outputs.insert("value".to_string(), serde_json::json!(self.reader.value()));
+        Ok(outputs)
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,575 @@
+//! Percussion classification
+//!
+//! `DrumClassifier` finds drum hits in a mono stream and sorts them into kick, snare,
+//! hi-hat or other. Onsets are sudden rises in short-hop energy, either broadband or of
+//! the differentiated signal so that hats still register over a ringing kick. The first
+//! ~20 ms after each onset is described by its spectral shape (low/mid/high energy
+//! split, centroid and flatness) and matched against one profile per drum. Profiles
+//! start from generic defaults and can be calibrated on a few seconds of the real kit.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use crate::audio::Stft;
+use crate::core::{AudioBufferData, DataType, InputPort, Node, NodeId, OutputPort, VjError};
+
+/// Upper edge of the low band and lower edge of the high band, in Hz
+const LOW_BAND_HZ: f32 = 150.0;
+const HIGH_BAND_HZ: f32 = 5000.0;
+
+/// Number of values in a feature vector
+pub const DRUM_FEATURES: usize = 5;
+
+/// Smallest spread a calibrated profile keeps per feature, so a handful of identical
+/// hits still leaves room for natural variation
+const MIN_SPREAD: [f32; DRUM_FEATURES] = [0.05, 0.05, 0.05, 0.25, 0.05];
+
+/// Time constant of the background energy onsets are measured against
+const BACKGROUND_SECS: f32 = 0.05;
+
+/// Hit peak level mapped to zero velocity
+const VELOCITY_FLOOR_DB: f32 = -48.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DrumClass {
+    Kick,
+    Snare,
+    HiHat,
+    /// A hit too far from every drum profile
+    Other,
+}
+
+impl DrumClass {
+    pub const ALL: [DrumClass; 4] = [DrumClass::Kick, DrumClass::Snare, DrumClass::HiHat, DrumClass::Other];
+
+    /// Classes with a profile
+    pub const DRUMS: [DrumClass; 3] = [DrumClass::Kick, DrumClass::Snare, DrumClass::HiHat];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DrumClass::Kick => "kick",
+            DrumClass::Snare => "snare",
+            DrumClass::HiHat => "hihat",
+            DrumClass::Other => "other",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|class| class.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// Spectral shape of the start of a hit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DrumFeatures {
+    /// Share of the energy below 150 Hz
+    pub low: f32,
+    /// Share of the energy from 150 Hz to 5 kHz
+    pub mid: f32,
+    /// Share of the energy above 5 kHz
+    pub high: f32,
+    /// Spectral centroid in octaves relative to 1 kHz
+    pub centroid: f32,
+    /// Geometric over arithmetic mean of the power spectrum; near 1 for noise
+    pub flatness: f32,
+}
+
+impl DrumFeatures {
+    /// Describe a power spectrum of `n_fft` points
+    pub fn from_power(power: &[f32], sample_rate: f32, n_fft: usize) -> Self {
+        let bin_hz = sample_rate / n_fft as f32;
+        let (mut low, mut mid, mut high, mut weighted, mut log_sum) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (bin, &value) in power.iter().enumerate().skip(1) {
+            let hz = bin as f32 * bin_hz;
+            match hz {
+                hz if hz < LOW_BAND_HZ => low += value,
+                hz if hz < HIGH_BAND_HZ => mid += value,
+                _ => high += value,
+            }
+            weighted += hz * value;
+            log_sum += (value + 1e-12).ln();
+        }
+
+        let total: f32 = low + mid + high;
+        let bins = power.len().saturating_sub(1).max(1) as f32;
+        if total <= 0.0 {
+            return Self::default();
+        }
+        Self {
+            low: low / total,
+            mid: mid / total,
+            high: high / total,
+            centroid: (weighted / total / 1000.0).max(1e-3).log2(),
+            flatness: ((log_sum / bins).exp() / (total / bins)).min(1.0),
+        }
+    }
+
+    pub fn to_array(&self) -> [f32; DRUM_FEATURES] {
+        [self.low, self.mid, self.high, self.centroid, self.flatness]
+    }
+
+    pub fn from_array([low, mid, high, centroid, flatness]: [f32; DRUM_FEATURES]) -> Self {
+        Self { low, mid, high, centroid, flatness }
+    }
+}
+
+/// Expected features of one drum: a mean and a spread per feature
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DrumProfile {
+    pub mean: [f32; DRUM_FEATURES],
+    pub spread: [f32; DRUM_FEATURES],
+    /// Hits the profile was calibrated on; 0 for the built-in defaults
+    pub hits: u32,
+}
+
+impl DrumProfile {
+    /// Generic profile for a drum class; `Other` has none
+    pub fn default_for(class: DrumClass) -> Option<Self> {
+        let (mean, spread) = match class {
+            DrumClass::Kick => ([0.8, 0.2, 0.0, -3.0, 0.05], [0.2, 0.2, 0.1, 1.0, 0.1]),
+            DrumClass::Snare => ([0.05, 0.7, 0.25, 0.5, 0.2], [0.1, 0.2, 0.2, 1.0, 0.2]),
+            DrumClass::HiHat => ([0.0, 0.1, 0.9, 3.0, 0.4], [0.05, 0.15, 0.15, 1.0, 0.25]),
+            DrumClass::Other => return None,
+        };
+        Some(Self { mean, spread, hits: 0 })
+    }
+
+    /// Fit a profile to example hits
+    pub fn from_examples(examples: &[DrumFeatures]) -> Option<Self> {
+        if examples.is_empty() {
+            return None;
+        }
+        let count = examples.len() as f32;
+        let mut mean = [0.0; DRUM_FEATURES];
+        for example in examples {
+            for (mean, value) in mean.iter_mut().zip(example.to_array()) {
+                *mean += value / count;
+            }
+        }
+        let mut spread = [0.0; DRUM_FEATURES];
+        for example in examples {
+            for (index, value) in example.to_array().into_iter().enumerate() {
+                spread[index] += (value - mean[index]).powi(2) / count;
+            }
+        }
+        for (spread, floor) in spread.iter_mut().zip(MIN_SPREAD) {
+            *spread = spread.sqrt().max(floor);
+        }
+        Some(Self { mean, spread, hits: examples.len() as u32 })
+    }
+
+    /// RMS distance in spreads
+    pub fn distance(&self, features: &DrumFeatures) -> f32 {
+        let sum: f32 = features.to_array().iter().enumerate()
+            .map(|(index, value)| ((value - self.mean[index]) / self.spread[index]).powi(2))
+            .sum();
+        (sum / DRUM_FEATURES as f32).sqrt()
+    }
+}
+
+/// Profiles for kick, snare and hi-hat
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DrumProfiles {
+    pub kick: DrumProfile,
+    pub snare: DrumProfile,
+    pub hihat: DrumProfile,
+}
+
+impl Default for DrumProfiles {
+    fn default() -> Self {
+        let profile = |class| DrumProfile::default_for(class).expect("drums have default profiles");
+        Self {
+            kick: profile(DrumClass::Kick),
+            snare: profile(DrumClass::Snare),
+            hihat: profile(DrumClass::HiHat),
+        }
+    }
+}
+
+impl DrumProfiles {
+    pub fn get(&self, class: DrumClass) -> Option<&DrumProfile> {
+        match class {
+            DrumClass::Kick => Some(&self.kick),
+            DrumClass::Snare => Some(&self.snare),
+            DrumClass::HiHat => Some(&self.hihat),
+            DrumClass::Other => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, class: DrumClass) -> Option<&mut DrumProfile> {
+        match class {
+            DrumClass::Kick => Some(&mut self.kick),
+            DrumClass::Snare => Some(&mut self.snare),
+            DrumClass::HiHat => Some(&mut self.hihat),
+            DrumClass::Other => None,
+        }
+    }
+
+    /// Nearest profile and how clearly it matched, 0.0..1.0. Features further than
+    /// `max_distance` spreads from every profile are `Other`.
+    pub fn classify(&self, features: &DrumFeatures, max_distance: f32) -> (DrumClass, f32) {
+        let distances = DrumClass::DRUMS.map(|class| {
+            let profile = self.get(class).expect("drums have profiles");
+            (class, profile.distance(features))
+        });
+        let (class, best) = distances.iter().copied()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("three drum classes");
+
+        if best > max_distance {
+            return (DrumClass::Other, (1.0 - max_distance / best).clamp(0.0, 1.0));
+        }
+        let likelihood = |distance: f32| (-0.5 * distance * distance).exp();
+        let total: f32 = distances.iter().map(|&(_, distance)| likelihood(distance)).sum();
+        (class, if total > 0.0 { likelihood(best) / total } else { 0.0 })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DrumClassifierSettings {
+    /// Energy rise over the background that counts as an onset
+    pub sensitivity: f32,
+    /// Hits quieter than this are ignored
+    pub floor_db: f32,
+    /// Minimum time between hits
+    pub min_interval_secs: f32,
+    /// Hits further than this from every profile, in spreads, are `Other`
+    pub max_distance: f32,
+}
+
+impl Default for DrumClassifierSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 4.0,
+            floor_db: -50.0,
+            min_interval_secs: 0.05,
+            max_distance: 2.5,
+        }
+    }
+}
+
+/// A classified drum hit
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DrumHit {
+    pub class: DrumClass,
+    /// Peak level of the hit mapped from -48..0 dBFS to 0.0..1.0
+    pub velocity: f32,
+    /// How clearly the hit matched its class, 0.0..1.0
+    pub confidence: f32,
+    /// Onset time in seconds of processed audio
+    pub time_secs: f64,
+    pub features: DrumFeatures,
+}
+
+/// Audio collected since an onset, until there is enough to classify
+struct Capture {
+    samples: Vec<f32>,
+    start: u64,
+}
+
+/// Real-time drum hit detector and classifier over a mono stream
+pub struct DrumClassifier {
+    pub settings: DrumClassifierSettings,
+    profiles: DrumProfiles,
+    sample_rate: f32,
+    stft: Stft,
+    hop: Vec<f32>,
+    previous_hop: Vec<f32>,
+    previous_sample: f32,
+    energy_background: f32,
+    slope_background: f32,
+    /// Samples processed up to the current hop
+    position: u64,
+    last_onset: Option<u64>,
+    capture: Option<Capture>,
+    learning: Option<(DrumClass, Vec<DrumFeatures>)>,
+}
+
+impl DrumClassifier {
+    pub fn new(sample_rate: f32, settings: DrumClassifierSettings) -> Self {
+        // About 20 ms of audio per hit, in hops of an eighth of that
+        let n_fft = ((sample_rate * 0.02) as usize).next_power_of_two().max(256);
+        let hop = n_fft / 8;
+        Self {
+            settings,
+            profiles: DrumProfiles::default(),
+            sample_rate,
+            stft: Stft::new(n_fft, n_fft),
+            hop: Vec::with_capacity(hop),
+            previous_hop: vec![0.0; hop],
+            previous_sample: 0.0,
+            energy_background: 0.0,
+            slope_background: 0.0,
+            position: 0,
+            last_onset: None,
+            capture: None,
+            learning: None,
+        }
+    }
+
+    pub fn with_profiles(mut self, profiles: DrumProfiles) -> Self {
+        self.profiles = profiles;
+        self
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn profiles(&self) -> &DrumProfiles {
+        &self.profiles
+    }
+
+    /// Samples per onset detection hop
+    fn hop_size(&self) -> usize {
+        self.previous_hop.len()
+    }
+
+    /// Detect and classify hits in mono samples
+    pub fn process(&mut self, samples: &[f32]) -> Vec<DrumHit> {
+        let mut hits = Vec::new();
+        for &sample in samples {
+            self.hop.push(sample);
+            if self.hop.len() == self.hop_size() {
+                self.process_hop(&mut hits);
+            }
+        }
+        hits
+    }
+
+    fn process_hop(&mut self, hits: &mut Vec<DrumHit>) {
+        let hop_size = self.hop_size();
+        let mut energy = 0.0;
+        let mut slope = 0.0;
+        let mut previous = self.previous_sample;
+        for &sample in &self.hop {
+            energy += sample * sample;
+            slope += (sample - previous).powi(2);
+            previous = sample;
+        }
+        energy /= hop_size as f32;
+        slope /= hop_size as f32;
+        self.previous_sample = previous;
+
+        let floor = 10f32.powf(self.settings.floor_db / 10.0);
+        let rises = |level: f32, background: f32| level > floor && level > self.settings.sensitivity * background;
+        let min_interval = (self.settings.min_interval_secs * self.sample_rate) as u64;
+        let ready = self.last_onset.is_none_or(|last| self.position - last >= min_interval);
+        if ready && self.capture.is_none() && (rises(energy, self.energy_background) || rises(slope, self.slope_background)) {
+            self.last_onset = Some(self.position);
+            // Start a hop early so the attack is not cut
+            let mut samples = Vec::with_capacity(self.stft.n_fft() + hop_size);
+            samples.extend_from_slice(&self.previous_hop);
+            self.capture = Some(Capture { samples, start: self.position });
+        }
+
+        let smoothing = 1.0 - (-(hop_size as f32) / (BACKGROUND_SECS * self.sample_rate)).exp();
+        self.energy_background += smoothing * (energy - self.energy_background);
+        self.slope_background += smoothing * (slope - self.slope_background);
+
+        if let Some(capture) = &mut self.capture {
+            capture.samples.extend_from_slice(&self.hop);
+            if capture.samples.len() >= self.stft.n_fft() {
+                let capture = self.capture.take().expect("capture checked above");
+                hits.push(self.classify_capture(&capture));
+            }
+        }
+
+        std::mem::swap(&mut self.previous_hop, &mut self.hop);
+        self.hop.clear();
+        self.position += hop_size as u64;
+    }
+
+    fn classify_capture(&mut self, capture: &Capture) -> DrumHit {
+        let n_fft = self.stft.n_fft();
+        let samples = &capture.samples[..n_fft];
+        let power = self.stft.power_spectrogram(samples).into_iter().next().unwrap_or_default();
+        let features = DrumFeatures::from_power(&power, self.sample_rate, n_fft);
+
+        let peak = samples.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        let velocity = ((20.0 * peak.max(1e-6).log10() - VELOCITY_FLOOR_DB) / -VELOCITY_FLOOR_DB).clamp(0.0, 1.0);
+
+        let (class, confidence) = match &mut self.learning {
+            Some((class, examples)) => {
+                examples.push(features);
+                (*class, 1.0)
+            }
+            None => self.classify(&features),
+        };
+        DrumHit {
+            class,
+            velocity,
+            confidence,
+            time_secs: capture.start as f64 / self.sample_rate as f64,
+            features,
+        }
+    }
+
+    /// Match features to the nearest profile
+    pub fn classify(&self, features: &DrumFeatures) -> (DrumClass, f32) {
+        self.profiles.classify(features, self.settings.max_distance)
+    }
+
+    /// Label the following hits as `class` and collect them for its profile
+    pub fn start_learning(&mut self, class: DrumClass) {
+        self.finish_learning();
+        if class != DrumClass::Other {
+            self.learning = Some((class, Vec::new()));
+        }
+    }
+
+    pub fn learning(&self) -> Option<DrumClass> {
+        self.learning.as_ref().map(|(class, _)| *class)
+    }
+
+    /// Replace the learned class's profile with one fitted to the collected hits.
+    /// Returns the number of hits used; a class that heard no hits keeps its profile.
+    pub fn finish_learning(&mut self) -> usize {
+        let Some((class, examples)) = self.learning.take() else {
+            return 0;
+        };
+        match (DrumProfile::from_examples(&examples), self.profiles.get_mut(class)) {
+            (Some(fitted), Some(profile)) => {
+                *profile = fitted;
+                examples.len()
+            }
+            _ => 0,
+        }
+    }
+
+    /// Calibrate a class on a recording of that drum alone, e.g. a few seconds of kicks
+    pub fn calibrate(&mut self, class: DrumClass, samples: &[f32]) -> Result<usize, VjError> {
+        if class == DrumClass::Other {
+            return Err(VjError::AudioError("Only kick, snare and hi-hat can be calibrated".to_string()));
+        }
+        let mut detector = DrumClassifier::new(self.sample_rate, self.settings);
+        detector.start_learning(class);
+        detector.process(samples);
+        // Flush a hit still being captured at the end of the recording
+        detector.process(&vec![0.0; detector.stft.n_fft()]);
+        let (_, examples) = detector.learning.take().expect("learning started above");
+
+        let fitted = DrumProfile::from_examples(&examples).ok_or_else(|| {
+            VjError::AudioError(format!("No {} hits found in the calibration audio", class.name()))
+        })?;
+        *self.profiles.get_mut(class).expect("drum class checked above") = fitted;
+        Ok(examples.len())
+    }
+
+    /// Forget the stream position and onset history, keeping profiles
+    pub fn reset(&mut self) {
+        *self = Self::new(self.sample_rate, self.settings).with_profiles(self.profiles);
+    }
+}
+
+/// Graph node turning drum audio into per-drum triggers and velocities.
+///
+/// While `learn` names a drum, hits are reported as that drum and used to calibrate it;
+/// clearing `learn` applies the calibration.
+#[derive(Serialize, Deserialize)]
+pub struct DrumClassifierNode {
+    pub id: NodeId,
+    pub settings: DrumClassifierSettings,
+    pub profiles: DrumProfiles,
+    #[serde(skip)]
+    classifier: Option<DrumClassifier>,
+}
+
+impl DrumClassifierNode {
+    pub fn new() -> Self {
+        Self {
+            id: NodeId::new(),
+            settings: DrumClassifierSettings::default(),
+            profiles: DrumProfiles::default(),
+            classifier: None,
+        }
+    }
+}
+
+impl Default for DrumClassifierNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Node for DrumClassifierNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "DrumClassifier"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![
+            InputPort::new("audio", DataType::AudioBuffer),
+            InputPort::optional("learn", DataType::String),
+            InputPort::optional("sensitivity", DataType::Float),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        let mut outputs = Vec::new();
+        for class in DrumClass::ALL {
+            outputs.push(OutputPort::new(class.name(), DataType::Boolean));
+            outputs.push(OutputPort::new(&format!("{}_velocity", class.name()), DataType::Float));
+        }
+        outputs.push(OutputPort::new("class", DataType::String));
+        outputs.push(OutputPort::new("confidence", DataType::Float));
+        outputs.push(OutputPort::new("hits", DataType::Array));
+        outputs
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        if let Some(sensitivity) = inputs.get("sensitivity").and_then(Value::as_f64) {
+            self.settings.sensitivity = (sensitivity as f32).max(1.0);
+        }
+        let audio = inputs.get("audio")
+            .and_then(|value| AudioBufferData::from_value(value, 44100.0))
+            .unwrap_or_default();
+        let sample_rate = if audio.sample_rate > 0.0 { audio.sample_rate } else { 44100.0 };
+
+        if self.classifier.as_ref().is_none_or(|classifier| classifier.sample_rate() != sample_rate) {
+            self.classifier = Some(DrumClassifier::new(sample_rate, self.settings).with_profiles(self.profiles));
+        }
+        let classifier = self.classifier.as_mut().expect("classifier created above");
+        classifier.settings = self.settings;
+
+        let learn = inputs.get("learn").and_then(Value::as_str).and_then(DrumClass::from_name);
+        if learn != classifier.learning() {
+            classifier.finish_learning();
+            if let Some(class) = learn {
+                classifier.start_learning(class);
+            }
+            self.profiles = *classifier.profiles();
+        }
+
+        let hits = classifier.process(&audio.to_mono());
+
+        let mut outputs = HashMap::new();
+        for class in DrumClass::ALL {
+            // Loudest hit of the class in this block
+            let velocity = hits.iter().filter(|hit| hit.class == class).map(|hit| hit.velocity).reduce(f32::max);
+            outputs.insert(class.name().to_string(), Value::Bool(velocity.is_some()));
+            outputs.insert(format!("{}_velocity", class.name()), serde_json::json!(velocity.unwrap_or(0.0)));
+        }
+        let last = hits.last();
+        outputs.insert("class".to_string(), last.map(|hit| Value::String(hit.class.name().to_string())).unwrap_or(Value::Null));
+        outputs.insert("confidence".to_string(), serde_json::json!(last.map(|hit| hit.confidence).unwrap_or(0.0)));
+        outputs.insert("hits".to_string(), serde_json::to_value(&hits)?);
+        Ok(outputs)
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        let mut parameters = HashMap::new();
+        parameters.insert("settings".to_string(), serde_json::to_value(self.settings).unwrap_or(Value::Null));
+        parameters.insert("profiles".to_string(), serde_json::to_value(self.profiles).unwrap_or(Value::Null));
+        parameters
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,739 @@
+//! Audio I/O engine
+//!
+//! Opens input and output streams through cpal, or runs a null/loopback backend on
+//! machines without a sound card. Audio moves between the device callbacks and Bevy
+//! through lock-free ring buffers; processors added to the engine render inside the
+//! output callback.
+
+use bevy::prelude::*;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crate::core::{AudioEventType, VjError};
+use crate::audio::AudioSettings;
+
+/// Largest block rendered in one go; bigger device buffers are split
+pub const MAX_BLOCK_FRAMES: usize = 4096;
+
+/// Maximum number of processors the render thread holds without reallocating
+const MAX_PROCESSORS: usize = 64;
+
+/// Maximum number of output taps
+const MAX_TAPS: usize = 16;
+
+/// Which audio backend the engine runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AudioBackend {
+    /// Real devices through cpal, falling back to `Null` if none can be opened
+    Cpal,
+    /// Clock-driven render thread with silent input
+    Null,
+    /// Like `Null`, but the rendered output is fed back as input
+    Loopback,
+}
+
+/// Block information passed to processors
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessContext {
+    pub sample_rate: f32,
+    /// Output channel count; `output` is interleaved with this many channels
+    pub channels: usize,
+    /// Input channel count; `input` is interleaved with this many channels
+    pub input_channels: usize,
+    pub frames: usize,
+    /// Frames rendered since the engine started, at the start of this block
+    pub frame_position: u64,
+}
+
+/// Something that renders audio inside the output callback.
+///
+/// Implementations must be real-time safe: no allocation, locking or I/O in `process`.
+pub trait AudioProcessor: Send {
+    /// Add this processor's output for one block into `output`
+    fn process(&mut self, context: &ProcessContext, input: &[f32], output: &mut [f32]);
+
+    /// Frames of delay the processor adds, e.g. a plugin's lookahead
+    fn latency(&self) -> usize {
+        0
+    }
+}
+
+/// Handle to a processor running in the engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProcessorId(u64);
+
+/// Handle to an output tap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TapId(u64);
+
+/// Frame window in which a tap receives output; `stop` is exclusive
+#[derive(Debug)]
+pub struct TapGate {
+    start: AtomicU64,
+    stop: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl TapGate {
+    fn new(start: u64) -> Self {
+        Self {
+            start: AtomicU64::new(start),
+            stop: AtomicU64::new(u64::MAX),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn start_frame(&self) -> u64 {
+        self.start.load(Ordering::Relaxed)
+    }
+
+    /// Frame at which the tap stops, if one is set
+    pub fn stop_frame(&self) -> Option<u64> {
+        Some(self.stop.load(Ordering::Relaxed)).filter(|&frame| frame != u64::MAX)
+    }
+
+    /// Stop passing output from `frame` on
+    pub fn close_at(&self, frame: u64) {
+        self.stop.store(frame, Ordering::Relaxed);
+    }
+
+    /// Blocks that did not fit in the tap's ring buffer
+    pub fn dropped_blocks(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Copy of the master output (after volume) for a frame window, read from any thread
+pub struct OutputTap {
+    pub id: TapId,
+    pub gate: Arc<TapGate>,
+    /// Interleaved samples, `channels` wide
+    pub consumer: Consumer<f32>,
+}
+
+enum EngineCommand {
+    AddProcessor(ProcessorId, Box<dyn AudioProcessor>),
+    RemoveProcessor(ProcessorId),
+    AddTap(TapId, Producer<f32>, Arc<TapGate>),
+    RemoveTap(TapId),
+}
+
+/// Objects released by the audio thread, dropped on the Bevy side
+#[allow(dead_code)]
+enum Garbage {
+    Processor(Box<dyn AudioProcessor>),
+    Tap(Producer<f32>),
+}
+
+/// Audio device description
+#[derive(Debug, Clone)]
+pub struct AudioDeviceInfo {
+    pub host: String,
+    pub name: String,
+    pub is_input: bool,
+    pub is_output: bool,
+    pub default_sample_rate: Option<u32>,
+    pub max_channels: u16,
+}
+
+/// State shared between the engine handle and the audio thread
+struct EngineShared {
+    master_volume: AtomicU32,
+    underruns: AtomicU64,
+    overruns: AtomicU64,
+    frame_position: AtomicU64,
+    device_lost: AtomicBool,
+    running: AtomicBool,
+}
+
+/// Everything the output callback owns
+struct RenderState {
+    processors: Vec<(ProcessorId, Box<dyn AudioProcessor>)>,
+    commands: Consumer<EngineCommand>,
+    garbage: Producer<Garbage>,
+    playback: Consumer<f32>,
+    monitor: Producer<f32>,
+    taps: Vec<(TapId, Producer<f32>, Arc<TapGate>)>,
+    input: Option<Consumer<f32>>,
+    input_scratch: Vec<f32>,
+    shared: Arc<EngineShared>,
+    sample_rate: f32,
+    channels: usize,
+    input_channels: usize,
+}
+
+impl RenderState {
+    fn apply_commands(&mut self) {
+        while let Ok(command) = self.commands.pop() {
+            match command {
+                EngineCommand::AddProcessor(id, processor) => {
+                    if self.processors.len() < MAX_PROCESSORS {
+                        self.processors.push((id, processor));
+                    } else {
+                        let _ = self.garbage.push(Garbage::Processor(processor));
+                    }
+                }
+                EngineCommand::RemoveProcessor(id) => {
+                    if let Some(index) = self.processors.iter().position(|(pid, _)| *pid == id) {
+                        let (_, processor) = self.processors.swap_remove(index);
+                        // Dropped on the Bevy side; if the ring is full it drops here
+                        let _ = self.garbage.push(Garbage::Processor(processor));
+                    }
+                }
+                EngineCommand::AddTap(id, producer, gate) => {
+                    if self.taps.len() < MAX_TAPS {
+                        self.taps.push((id, producer, gate));
+                    } else {
+                        let _ = self.garbage.push(Garbage::Tap(producer));
+                    }
+                }
+                EngineCommand::RemoveTap(id) => {
+                    if let Some(index) = self.taps.iter().position(|(tid, _, _)| *tid == id) {
+                        let (_, producer, _) = self.taps.swap_remove(index);
+                        let _ = self.garbage.push(Garbage::Tap(producer));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Render interleaved output, splitting large device buffers into blocks
+    fn render(&mut self, output: &mut [f32]) {
+        self.apply_commands();
+
+        let block_samples = MAX_BLOCK_FRAMES * self.channels;
+        for block in output.chunks_mut(block_samples) {
+            self.render_block(block);
+        }
+    }
+
+    fn render_block(&mut self, output: &mut [f32]) {
+        let frames = output.len() / self.channels;
+        let input = &mut self.input_scratch[..frames * self.input_channels];
+
+        input.fill(0.0);
+        if let Some(source) = self.input.as_mut() {
+            let available = source.slots().min(input.len());
+            if let Ok(chunk) = source.read_chunk(available) {
+                let (first, second) = chunk.as_slices();
+                input[..first.len()].copy_from_slice(first);
+                input[first.len()..first.len() + second.len()].copy_from_slice(second);
+                chunk.commit_all();
+            }
+        }
+
+        output.fill(0.0);
+        let queued = self.playback.slots();
+        if queued > 0 {
+            let count = queued.min(output.len());
+            if let Ok(chunk) = self.playback.read_chunk(count) {
+                let (first, second) = chunk.as_slices();
+                output[..first.len()].copy_from_slice(first);
+                output[first.len()..first.len() + second.len()].copy_from_slice(second);
+                chunk.commit_all();
+            }
+            if count < output.len() {
+                self.shared.underruns.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let context = ProcessContext {
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            input_channels: self.input_channels,
+            frames,
+            frame_position: self.shared.frame_position.load(Ordering::Relaxed),
+        };
+        for (_, processor) in self.processors.iter_mut() {
+            processor.process(&context, input, output);
+        }
+
+        let volume = f32::from_bits(self.shared.master_volume.load(Ordering::Relaxed));
+        for sample in output.iter_mut() {
+            *sample *= volume;
+        }
+
+        push_samples(&mut self.monitor, output);
+
+        let block_end = context.frame_position + frames as u64;
+        for (_, producer, gate) in self.taps.iter_mut() {
+            let start = gate.start.load(Ordering::Relaxed).max(context.frame_position);
+            let stop = gate.stop.load(Ordering::Relaxed).min(block_end);
+            if start < stop {
+                let first = (start - context.frame_position) as usize * self.channels;
+                let last = (stop - context.frame_position) as usize * self.channels;
+                if !push_samples(producer, &output[first..last]) {
+                    gate.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        self.shared.frame_position.fetch_add(frames as u64, Ordering::Relaxed);
+    }
+}
+
+/// Push as many samples as fit; returns false if some were dropped
+fn push_samples(producer: &mut Producer<f32>, samples: &[f32]) -> bool {
+    let count = producer.slots().min(samples.len());
+    if let Ok(mut chunk) = producer.write_chunk(count) {
+        let (first, second) = chunk.as_mut_slices();
+        let split = first.len();
+        first.copy_from_slice(&samples[..split]);
+        second.copy_from_slice(&samples[split..count]);
+        chunk.commit_all();
+    }
+    count == samples.len()
+}
+
+/// Audio engine resource. Holds the device streams, so it lives on the main thread
+/// as a non-send resource.
+pub struct AudioEngine {
+    backend: AudioBackend,
+    sample_rate: f32,
+    channels: usize,
+    input_channels: usize,
+    buffer_size: usize,
+    has_input: bool,
+    shared: Arc<EngineShared>,
+    commands: Producer<EngineCommand>,
+    garbage: Consumer<Garbage>,
+    capture: Consumer<f32>,
+    monitor: Consumer<f32>,
+    playback: Producer<f32>,
+    streams: Vec<cpal::Stream>,
+    render_thread: Option<JoinHandle<()>>,
+    next_processor_id: u64,
+    next_tap_id: u64,
+    pending_events: Vec<AudioEventType>,
+    reported_underruns: u64,
+    reported_overruns: u64,
+    reported_device_lost: bool,
+    output_device_name: String,
+}
+
+/// Ring buffer ends created for one engine instance
+struct EngineChannels {
+    render: RenderState,
+    capture_producer: Producer<f32>,
+    input_producer: Option<Producer<f32>>,
+}
+
+impl AudioEngine {
+    /// Start the engine with the configured backend. A cpal backend that cannot open
+    /// its devices falls back to the null backend.
+    pub fn start(settings: &AudioSettings) -> Result<Self, VjError> {
+        match settings.backend {
+            AudioBackend::Cpal => match Self::start_cpal(settings) {
+                Ok(engine) => Ok(engine),
+                Err(e) => {
+                    warn!("⚠️ Could not open audio devices ({}), using null audio backend", e);
+                    Self::start_null(settings, false)
+                }
+            },
+            AudioBackend::Null => Self::start_null(settings, false),
+            AudioBackend::Loopback => Self::start_null(settings, true),
+        }
+    }
+
+    /// Names of the audio hosts available on this platform
+    pub fn list_hosts() -> Vec<String> {
+        cpal::available_hosts().iter().map(|id| id.name().to_string()).collect()
+    }
+
+    /// Enumerate devices of a host, or of the default host
+    pub fn list_devices(host_name: Option<&str>) -> Result<Vec<AudioDeviceInfo>, VjError> {
+        let host = find_host(host_name)?;
+        let host_label = host.id().name().to_string();
+        let devices = host.devices().map_err(|e| VjError::AudioError(e.to_string()))?;
+
+        Ok(devices
+            .filter_map(|device| {
+                let name = device.name().ok()?;
+                let input_config = device.default_input_config().ok();
+                let output_config = device.default_output_config().ok();
+                let default_sample_rate = output_config.as_ref()
+                    .or(input_config.as_ref())
+                    .map(|config| config.sample_rate().0);
+                let max_channels = output_config.iter()
+                    .chain(input_config.iter())
+                    .map(|config| config.channels())
+                    .max()
+                    .unwrap_or(0);
+
+                Some(AudioDeviceInfo {
+                    host: host_label.clone(),
+                    name,
+                    is_input: input_config.is_some(),
+                    is_output: output_config.is_some(),
+                    default_sample_rate,
+                    max_channels,
+                })
+            })
+            .collect())
+    }
+
+    fn create(settings: &AudioSettings, backend: AudioBackend, with_input: bool) -> (Self, EngineChannels) {
+        let sample_rate = settings.sample_rate;
+        let channels = settings.channels.max(1);
+        let input_channels = settings.input_channels.max(1);
+        let ring_seconds = sample_rate.max(1.0) as usize;
+
+        let shared = Arc::new(EngineShared {
+            master_volume: AtomicU32::new(settings.master_volume.to_bits()),
+            underruns: AtomicU64::new(0),
+            overruns: AtomicU64::new(0),
+            frame_position: AtomicU64::new(0),
+            device_lost: AtomicBool::new(false),
+            running: AtomicBool::new(true),
+        });
+
+        let (commands, command_consumer) = RingBuffer::new(256);
+        let (garbage_producer, garbage) = RingBuffer::new((MAX_PROCESSORS + MAX_TAPS) * 2);
+        let (capture_producer, capture) = RingBuffer::new(ring_seconds * input_channels);
+        let (monitor_producer, monitor) = RingBuffer::new(ring_seconds * channels);
+        let (playback, playback_consumer) = RingBuffer::new(ring_seconds * channels);
+        let (input_producer, input_consumer) = if with_input {
+            let (producer, consumer) = RingBuffer::new(ring_seconds * input_channels);
+            (Some(producer), Some(consumer))
+        } else {
+            (None, None)
+        };
+
+        let render = RenderState {
+            processors: Vec::with_capacity(MAX_PROCESSORS),
+            commands: command_consumer,
+            garbage: garbage_producer,
+            playback: playback_consumer,
+            monitor: monitor_producer,
+            taps: Vec::with_capacity(MAX_TAPS),
+            input: input_consumer,
+            input_scratch: vec![0.0; MAX_BLOCK_FRAMES * input_channels],
+            shared: shared.clone(),
+            sample_rate,
+            channels,
+            input_channels,
+        };
+
+        let engine = Self {
+            backend,
+            sample_rate,
+            channels,
+            input_channels,
+            buffer_size: settings.buffer_size,
+            has_input: with_input,
+            shared,
+            commands,
+            garbage,
+            capture,
+            monitor,
+            playback,
+            streams: Vec::new(),
+            render_thread: None,
+            next_processor_id: 0,
+            next_tap_id: 0,
+            pending_events: Vec::new(),
+            reported_underruns: 0,
+            reported_overruns: 0,
+            reported_device_lost: false,
+            output_device_name: String::new(),
+        };
+
+        (engine, EngineChannels { render, capture_producer, input_producer })
+    }
+
+    fn start_cpal(settings: &AudioSettings) -> Result<Self, VjError> {
+        let host = find_host(settings.host.as_deref())?;
+        let output_device = find_device(&host, settings.output_device.as_deref(), false)?;
+        let input_device = if settings.enable_input {
+            find_device(&host, settings.input_device.as_deref(), true).ok()
+        } else {
+            None
+        };
+
+        let (mut engine, channels) = Self::create(settings, AudioBackend::Cpal, input_device.is_some());
+        let EngineChannels { mut render, mut capture_producer, input_producer } = channels;
+
+        let buffer_size = if settings.buffer_size > 0 {
+            cpal::BufferSize::Fixed(settings.buffer_size as u32)
+        } else {
+            cpal::BufferSize::Default
+        };
+        let output_config = cpal::StreamConfig {
+            channels: engine.channels as u16,
+            sample_rate: cpal::SampleRate(settings.sample_rate as u32),
+            buffer_size,
+        };
+
+        if let (Some(device), Some(mut input_producer)) = (input_device, input_producer) {
+            let input_config = cpal::StreamConfig {
+                channels: engine.input_channels as u16,
+                ..output_config.clone()
+            };
+            let shared = engine.shared.clone();
+            let error_shared = engine.shared.clone();
+            let input_name = device.name().unwrap_or_default();
+
+            let stream = device.build_input_stream(
+                &input_config,
+                move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                    push_samples(&mut input_producer, data);
+                    if !push_samples(&mut capture_producer, data) {
+                        shared.overruns.fetch_add(1, Ordering::Relaxed);
+                    }
+                },
+                move |err| report_stream_error(&error_shared, err),
+                None,
+            ).map_err(|e| VjError::AudioError(format!("Failed to open input '{}': {}", input_name, e)))?;
+
+            stream.play().map_err(|e| VjError::AudioError(e.to_string()))?;
+            engine.pending_events.push(AudioEventType::DeviceConnected { device_name: input_name });
+            engine.streams.push(stream);
+        }
+
+        let output_name = output_device.name().unwrap_or_default();
+        let error_shared = engine.shared.clone();
+        let stream = output_device.build_output_stream(
+            &output_config,
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| render.render(data),
+            move |err| report_stream_error(&error_shared, err),
+            None,
+        ).map_err(|e| VjError::AudioError(format!("Failed to open output '{}': {}", output_name, e)))?;
+
+        stream.play().map_err(|e| VjError::AudioError(e.to_string()))?;
+        info!("🔊 Audio output '{}' at {} Hz, {} channels", output_name, settings.sample_rate, engine.channels);
+        engine.pending_events.push(AudioEventType::DeviceConnected { device_name: output_name.clone() });
+        engine.output_device_name = output_name;
+        engine.streams.push(stream);
+
+        Ok(engine)
+    }
+
+    fn start_null(settings: &AudioSettings, loopback: bool) -> Result<Self, VjError> {
+        let backend = if loopback { AudioBackend::Loopback } else { AudioBackend::Null };
+        let (mut engine, channels) = Self::create(settings, backend, loopback);
+        let EngineChannels { mut render, mut capture_producer, mut input_producer } = channels;
+
+        let frames = settings.buffer_size.clamp(16, MAX_BLOCK_FRAMES);
+        let period = Duration::from_secs_f64(frames as f64 / settings.sample_rate.max(1.0) as f64);
+        let shared = engine.shared.clone();
+        let output_channels = engine.channels;
+        let input_channels = engine.input_channels;
+
+        let thread = std::thread::Builder::new()
+            .name("nuwe-null-audio".to_string())
+            .spawn(move || {
+                let mut output = vec![0.0f32; frames * output_channels];
+                let mut looped = vec![0.0f32; frames * input_channels];
+                let mut deadline = Instant::now();
+
+                while shared.running.load(Ordering::Relaxed) {
+                    render.render(&mut output);
+
+                    if let Some(input_producer) = input_producer.as_mut() {
+                        for (frame, out_frame) in looped.chunks_mut(input_channels).zip(output.chunks(output_channels)) {
+                            for (channel, sample) in frame.iter_mut().enumerate() {
+                                *sample = out_frame[channel % output_channels];
+                            }
+                        }
+                        push_samples(input_producer, &looped);
+                        if !push_samples(&mut capture_producer, &looped) {
+                            shared.overruns.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+
+                    deadline += period;
+                    let now = Instant::now();
+                    if deadline > now {
+                        std::thread::sleep(deadline - now);
+                    } else {
+                        // Fell behind; don't try to catch up with a burst
+                        deadline = now;
+                    }
+                }
+            })
+            .map_err(|e| VjError::AudioError(format!("Failed to start null audio thread: {}", e)))?;
+
+        let device_name = if loopback { "Loopback" } else { "Null" }.to_string();
+        info!("🔇 {} audio backend at {} Hz, {} channels", device_name, settings.sample_rate, engine.channels);
+        engine.pending_events.push(AudioEventType::DeviceConnected { device_name: device_name.clone() });
+        engine.output_device_name = device_name;
+        engine.render_thread = Some(thread);
+
+        Ok(engine)
+    }
+
+    /// Add a processor that renders inside the audio callback
+    pub fn add_processor(&mut self, processor: Box<dyn AudioProcessor>) -> Result<ProcessorId, VjError> {
+        let id = ProcessorId(self.next_processor_id);
+        self.next_processor_id += 1;
+        self.commands.push(EngineCommand::AddProcessor(id, processor))
+            .map_err(|_| VjError::AudioError("Audio command queue full".to_string()))?;
+        Ok(id)
+    }
+
+    /// Remove a processor; it is dropped on the main thread once the audio thread releases it
+    pub fn remove_processor(&mut self, id: ProcessorId) -> Result<(), VjError> {
+        self.commands.push(EngineCommand::RemoveProcessor(id))
+            .map_err(|_| VjError::AudioError("Audio command queue full".to_string()))
+    }
+
+    /// Tap the master output from `start_frame` (or right away with `None`), buffering
+    /// up to `capacity_frames` until read
+    pub fn add_output_tap(&mut self, capacity_frames: usize, start_frame: Option<u64>) -> Result<OutputTap, VjError> {
+        let id = TapId(self.next_tap_id);
+        self.next_tap_id += 1;
+        let gate = Arc::new(TapGate::new(start_frame.unwrap_or(0)));
+        let (producer, consumer) = RingBuffer::new(capacity_frames.max(MAX_BLOCK_FRAMES) * self.channels);
+        self.commands.push(EngineCommand::AddTap(id, producer, gate.clone()))
+            .map_err(|_| VjError::AudioError("Audio command queue full".to_string()))?;
+        Ok(OutputTap { id, gate, consumer })
+    }
+
+    pub fn remove_output_tap(&mut self, id: TapId) -> Result<(), VjError> {
+        self.commands.push(EngineCommand::RemoveTap(id))
+            .map_err(|_| VjError::AudioError("Audio command queue full".to_string()))
+    }
+
+    /// Queue interleaved samples for playback; returns how many were accepted
+    pub fn write_output(&mut self, samples: &[f32]) -> usize {
+        let count = self.playback.slots().min(samples.len());
+        push_samples(&mut self.playback, &samples[..count]);
+        count
+    }
+
+    /// Drain captured input samples (interleaved, `input_channels` wide)
+    pub fn read_input(&mut self, buffer: &mut Vec<f32>) {
+        drain_into(&mut self.capture, buffer);
+    }
+
+    /// Drain rendered output samples (interleaved, `channels` wide)
+    pub fn read_output(&mut self, buffer: &mut Vec<f32>) {
+        drain_into(&mut self.monitor, buffer);
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.shared.master_volume.store(volume.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        f32::from_bits(self.shared.master_volume.load(Ordering::Relaxed))
+    }
+
+    pub fn backend(&self) -> AudioBackend {
+        self.backend
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn input_channels(&self) -> usize {
+        self.input_channels
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Whether captured input is available (an input device or loopback)
+    pub fn has_input(&self) -> bool {
+        self.has_input
+    }
+
+    pub fn output_device_name(&self) -> &str {
+        &self.output_device_name
+    }
+
+    /// Frames rendered since the engine started
+    pub fn frame_position(&self) -> u64 {
+        self.shared.frame_position.load(Ordering::Relaxed)
+    }
+
+    /// Drop processors and taps released by the audio thread and collect status events
+    pub fn poll(&mut self) -> Vec<AudioEventType> {
+        while self.garbage.pop().is_ok() {}
+
+        let underruns = self.shared.underruns.load(Ordering::Relaxed);
+        if underruns > self.reported_underruns {
+            self.reported_underruns = underruns;
+            self.pending_events.push(AudioEventType::BufferUnderrun);
+        }
+
+        let overruns = self.shared.overruns.load(Ordering::Relaxed);
+        if overruns > self.reported_overruns {
+            self.reported_overruns = overruns;
+            self.pending_events.push(AudioEventType::BufferOverrun);
+        }
+
+        if self.shared.device_lost.load(Ordering::Relaxed) && !self.reported_device_lost {
+            self.reported_device_lost = true;
+            self.pending_events.push(AudioEventType::DeviceDisconnected {
+                device_name: self.output_device_name.clone(),
+            });
+        }
+
+        std::mem::take(&mut self.pending_events)
+    }
+}
+
+impl Drop for AudioEngine {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+        self.streams.clear();
+        if let Some(thread) = self.render_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn drain_into(consumer: &mut Consumer<f32>, buffer: &mut Vec<f32>) {
+    let available = consumer.slots();
+    if let Ok(chunk) = consumer.read_chunk(available) {
+        let (first, second) = chunk.as_slices();
+        buffer.extend_from_slice(first);
+        buffer.extend_from_slice(second);
+        chunk.commit_all();
+    }
+}
+
+fn report_stream_error(shared: &EngineShared, error: cpal::StreamError) {
+    if matches!(error, cpal::StreamError::DeviceNotAvailable) {
+        shared.device_lost.store(true, Ordering::Relaxed);
+    }
+}
+
+fn find_host(name: Option<&str>) -> Result<cpal::Host, VjError> {
+    let Some(name) = name else {
+        return Ok(cpal::default_host());
+    };
+
+    let id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| VjError::AudioError(format!("Audio host '{}' not available", name)))?;
+    cpal::host_from_id(id).map_err(|e| VjError::AudioError(e.to_string()))
+}
+
+/// Find a device whose name contains `name`, or the host default
+fn find_device(host: &cpal::Host, name: Option<&str>, input: bool) -> Result<cpal::Device, VjError> {
+    let direction = if input { "input" } else { "output" };
+    match name {
+        Some(name) => {
+            let mut devices = if input { host.input_devices() } else { host.output_devices() }
+                .map_err(|e| VjError::AudioError(e.to_string()))?;
+            devices
+                .find(|device| device.name().map(|n| n.contains(name)).unwrap_or(false))
+                .ok_or_else(|| VjError::AudioError(format!("No {} device matching '{}'", direction, name)))
+        }
+        None => if input { host.default_input_device() } else { host.default_output_device() }
+            .ok_or_else(|| VjError::AudioError(format!("No default {} device", direction))),
+    }
+}
@@ -0,0 +1,632 @@
+//! Control signals from audio
+//!
+//! Envelope followers, band-limited followers, gates with hysteresis and a sidechain
+//! ducker. Each turns audio, or a level from the live analysis, into smooth `Float` and
+//! `Boolean` values for the modulation system.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Instant;
+use crate::audio::{BandLevels, FrequencyBand, SharedAnalysis};
+use crate::core::{AudioBufferData, DataType, InputPort, Node, NodeId, OutputPort};
+use crate::nodes::{AudioEffect, Biquad, BiquadMode};
+
+/// What an envelope follower tracks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnvelopeMode {
+    Peak,
+    /// Smoothed power, reported as amplitude
+    Rms,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnvelopeSettings {
+    /// Time constant while the level rises
+    pub attack_secs: f32,
+    /// Time constant while the level falls
+    pub release_secs: f32,
+    pub mode: EnvelopeMode,
+}
+
+impl Default for EnvelopeSettings {
+    fn default() -> Self {
+        Self {
+            attack_secs: 0.005,
+            release_secs: 0.15,
+            mode: EnvelopeMode::Peak,
+        }
+    }
+}
+
+fn smoothing(secs: f32, dt: f32) -> f32 {
+    if secs <= 0.0 {
+        0.0
+    } else {
+        (-dt / secs).exp()
+    }
+}
+
+/// One-pole follower with separate attack and release
+#[derive(Debug, Clone, Default)]
+pub struct EnvelopeFollower {
+    pub settings: EnvelopeSettings,
+    state: f32,
+}
+
+impl EnvelopeFollower {
+    pub fn new(settings: EnvelopeSettings) -> Self {
+        Self { settings, state: 0.0 }
+    }
+
+    fn step(&mut self, level: f32, attack: f32, release: f32) {
+        let level = match self.settings.mode {
+            EnvelopeMode::Peak => level.abs(),
+            EnvelopeMode::Rms => level * level,
+        };
+        let coefficient = if level > self.state { attack } else { release };
+        self.state = level + (self.state - level) * coefficient;
+    }
+
+    /// Follow a level measured `dt` seconds after the previous one
+    pub fn update(&mut self, level: f32, dt: f32) -> f32 {
+        let attack = smoothing(self.settings.attack_secs, dt);
+        let release = smoothing(self.settings.release_secs, dt);
+        self.step(level, attack, release);
+        self.value()
+    }
+
+    /// Follow mono samples
+    pub fn process(&mut self, samples: &[f32], sample_rate: f32) -> f32 {
+        let dt = 1.0 / sample_rate.max(1.0);
+        let attack = smoothing(self.settings.attack_secs, dt);
+        let release = smoothing(self.settings.release_secs, dt);
+        for &sample in samples {
+            self.step(sample, attack, release);
+        }
+        self.value()
+    }
+
+    pub fn value(&self) -> f32 {
+        match self.settings.mode {
+            EnvelopeMode::Peak => self.state,
+            EnvelopeMode::Rms => self.state.sqrt(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.state = 0.0;
+    }
+}
+
+/// Envelope of one frequency range, e.g. only the kick
+#[derive(Debug, Clone)]
+pub struct BandFollower {
+    pub low_hz: f32,
+    pub high_hz: f32,
+    pub follower: EnvelopeFollower,
+    highpass: Biquad,
+    lowpass: Biquad,
+    sample_rate: f32,
+    scratch: Vec<f32>,
+}
+
+impl BandFollower {
+    pub fn new(low_hz: f32, high_hz: f32, settings: EnvelopeSettings) -> Self {
+        let q = std::f32::consts::FRAC_1_SQRT_2;
+        Self {
+            low_hz,
+            high_hz,
+            follower: EnvelopeFollower::new(settings),
+            highpass: Biquad::new(BiquadMode::HighPass, low_hz, q),
+            lowpass: Biquad::new(BiquadMode::LowPass, high_hz, q),
+            sample_rate: 0.0,
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn for_band(band: FrequencyBand, settings: EnvelopeSettings) -> Self {
+        let (low_hz, high_hz) = band.range();
+        Self::new(low_hz, high_hz, settings)
+    }
+
+    /// Band-pass mono samples and follow the result
+    pub fn process(&mut self, samples: &[f32], sample_rate: f32) -> f32 {
+        if self.sample_rate != sample_rate {
+            self.highpass.prepare(sample_rate, 1);
+            self.lowpass.prepare(sample_rate, 1);
+            self.sample_rate = sample_rate;
+        }
+        self.scratch.clear();
+        self.scratch.extend_from_slice(samples);
+        self.highpass.process(&mut self.scratch, 1);
+        self.lowpass.process(&mut self.scratch, 1);
+        self.follower.process(&self.scratch, sample_rate)
+    }
+
+    /// Follow the loudest analysis band overlapping this range
+    pub fn update_from_bands(&mut self, bands: &BandLevels, dt: f32) -> f32 {
+        let level = FrequencyBand::ALL.iter()
+            .filter(|band| {
+                let (low, high) = band.range();
+                low < self.high_hz && high > self.low_hz
+            })
+            .map(|&band| bands.get(band))
+            .fold(0.0, f32::max);
+        self.follower.update(level, dt)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GateSettings {
+    /// Level at which the gate opens
+    pub open_threshold: f32,
+    /// Level below which the gate closes; below `open_threshold` for hysteresis
+    pub close_threshold: f32,
+    /// How long the level must stay below `close_threshold` before closing
+    pub hold_secs: f32,
+}
+
+impl Default for GateSettings {
+    fn default() -> Self {
+        Self {
+            open_threshold: 0.3,
+            close_threshold: 0.2,
+            hold_secs: 0.05,
+        }
+    }
+}
+
+/// Turns a level into an open/closed state and a trigger on opening
+#[derive(Debug, Clone, Default)]
+pub struct Gate {
+    pub settings: GateSettings,
+    open: bool,
+    below_secs: f32,
+}
+
+impl Gate {
+    pub fn new(settings: GateSettings) -> Self {
+        Self { settings, open: false, below_secs: 0.0 }
+    }
+
+    /// Returns true when the gate opens on this update
+    pub fn update(&mut self, level: f32, dt: f32) -> bool {
+        let close_threshold = self.settings.close_threshold.min(self.settings.open_threshold);
+        if level >= self.settings.open_threshold {
+            self.below_secs = 0.0;
+            let opened = !self.open;
+            self.open = true;
+            return opened;
+        }
+
+        if self.open && level < close_threshold {
+            self.below_secs += dt;
+            if self.below_secs >= self.settings.hold_secs {
+                self.open = false;
+            }
+        } else {
+            self.below_secs = 0.0;
+        }
+        false
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DuckerSettings {
+    /// Sidechain level where ducking starts
+    pub threshold: f32,
+    /// How far the output dips, 0.0..1.0
+    pub depth: f32,
+    pub attack_secs: f32,
+    /// Recovery time after the sidechain drops or a trigger
+    pub release_secs: f32,
+}
+
+impl Default for DuckerSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 0.1,
+            depth: 0.8,
+            attack_secs: 0.005,
+            release_secs: 0.25,
+        }
+    }
+}
+
+/// Sidechain pumping: 1.0 at rest, dipping toward `1 - depth` when the sidechain is
+/// loud or a trigger fires
+#[derive(Debug, Clone, Default)]
+pub struct Ducker {
+    pub settings: DuckerSettings,
+    follower: EnvelopeFollower,
+    pump: f32,
+}
+
+impl Ducker {
+    pub fn new(settings: DuckerSettings) -> Self {
+        Self { settings, follower: EnvelopeFollower::default(), pump: 0.0 }
+    }
+
+    /// Fully duck now and recover over the release time
+    pub fn trigger(&mut self) {
+        self.pump = 1.0;
+    }
+
+    fn sync_follower(&mut self) {
+        self.follower.settings = EnvelopeSettings {
+            attack_secs: self.settings.attack_secs,
+            release_secs: self.settings.release_secs,
+            mode: EnvelopeMode::Peak,
+        };
+    }
+
+    /// Ducking from the sidechain envelope, 0.0..1.0, like a compressor's gain reduction
+    fn amount(&self) -> f32 {
+        let envelope = self.follower.value();
+        let from_level = if envelope > self.settings.threshold {
+            1.0 - self.settings.threshold / envelope
+        } else {
+            0.0
+        };
+        from_level.max(self.pump)
+    }
+
+    fn output(&self) -> f32 {
+        1.0 - self.settings.depth.clamp(0.0, 1.0) * self.amount()
+    }
+
+    /// Advance by `dt` with a sidechain level
+    pub fn update(&mut self, level: f32, dt: f32) -> f32 {
+        self.sync_follower();
+        self.follower.update(level, dt);
+        self.pump *= smoothing(self.settings.release_secs, dt);
+        self.output()
+    }
+
+    /// Advance through mono sidechain samples
+    pub fn process(&mut self, samples: &[f32], sample_rate: f32) -> f32 {
+        self.sync_follower();
+        self.follower.process(samples, sample_rate);
+        self.pump *= smoothing(self.settings.release_secs, samples.len() as f32 / sample_rate.max(1.0));
+        self.output()
+    }
+
+    /// Current ducking, 0.0 (none) to 1.0 (full depth)
+    pub fn ducking(&self) -> f32 {
+        self.amount()
+    }
+
+    /// Current output without advancing
+    pub fn value(&self) -> f32 {
+        self.output()
+    }
+}
+
+/// Seconds since the last call, capped so a stalled graph doesn't jump
+fn elapsed(last_update: &mut Option<Instant>) -> f32 {
+    let now = Instant::now();
+    let dt = last_update.map(|last| now.duration_since(last).as_secs_f32()).unwrap_or(0.0);
+    *last_update = Some(now);
+    dt.min(0.1)
+}
+
+fn read_audio(inputs: &HashMap<String, Value>, port: &str) -> Option<AudioBufferData> {
+    inputs.get(port).and_then(|value| AudioBufferData::from_value(value, 44100.0))
+}
+
+fn read_float(inputs: &HashMap<String, Value>, port: &str) -> Option<f32> {
+    inputs.get(port).and_then(Value::as_f64).map(|value| value as f32)
+}
+
+/// Graph node following the level of its audio input, or of the live analysis when
+/// nothing is connected
+pub struct EnvelopeFollowerNode {
+    pub id: NodeId,
+    pub follower: EnvelopeFollower,
+    live: Option<SharedAnalysis>,
+    last_update: Option<Instant>,
+}
+
+impl EnvelopeFollowerNode {
+    pub fn new(settings: EnvelopeSettings) -> Self {
+        Self {
+            id: NodeId::new(),
+            follower: EnvelopeFollower::new(settings),
+            live: None,
+            last_update: None,
+        }
+    }
+
+    pub fn with_live(mut self, live: SharedAnalysis) -> Self {
+        self.live = Some(live);
+        self
+    }
+}
+
+impl Node for EnvelopeFollowerNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "EnvelopeFollower"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![
+            InputPort::optional("audio", DataType::AudioBuffer),
+            InputPort::optional("attack", DataType::Float),
+            InputPort::optional("release", DataType::Float),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![OutputPort::new("envelope", DataType::Float)]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        if let Some(attack) = read_float(&inputs, "attack") {
+            self.follower.settings.attack_secs = attack.max(0.0);
+        }
+        if let Some(release) = read_float(&inputs, "release") {
+            self.follower.settings.release_secs = release.max(0.0);
+        }
+
+        let dt = elapsed(&mut self.last_update);
+        if let Some(audio) = read_audio(&inputs, "audio") {
+            self.follower.process(&audio.to_mono(), audio.sample_rate);
+        } else if let Some(live) = &self.live {
+            self.follower.update(live.latest().rms_level(), dt);
+        }
+
+        let mut outputs = HashMap::new();
+        outputs.insert("envelope".to_string(), serde_json::json!(self.follower.value()));
+        Ok(outputs)
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        let mut parameters = HashMap::new();
+        parameters.insert("settings".to_string(), serde_json::to_value(self.follower.settings).unwrap_or(Value::Null));
+        parameters
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
+
+/// Graph node following one frequency range. Without audio it follows the live
+/// analysis bands overlapping the range.
+pub struct BandFollowerNode {
+    pub id: NodeId,
+    pub follower: BandFollower,
+    live: Option<SharedAnalysis>,
+    last_update: Option<Instant>,
+}
+
+impl BandFollowerNode {
+    pub fn new(low_hz: f32, high_hz: f32) -> Self {
+        Self {
+            id: NodeId::new(),
+            follower: BandFollower::new(low_hz, high_hz, EnvelopeSettings::default()),
+            live: None,
+            last_update: None,
+        }
+    }
+
+    pub fn for_band(band: FrequencyBand) -> Self {
+        let (low_hz, high_hz) = band.range();
+        Self::new(low_hz, high_hz)
+    }
+
+    pub fn with_live(mut self, live: SharedAnalysis) -> Self {
+        self.live = Some(live);
+        self
+    }
+}
+
+impl Node for BandFollowerNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "BandFollower"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![
+            InputPort::optional("audio", DataType::AudioBuffer),
+            InputPort::optional("attack", DataType::Float),
+            InputPort::optional("release", DataType::Float),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![OutputPort::new("envelope", DataType::Float)]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        let settings = &mut self.follower.follower.settings;
+        if let Some(attack) = read_float(&inputs, "attack") {
+            settings.attack_secs = attack.max(0.0);
+        }
+        if let Some(release) = read_float(&inputs, "release") {
+            settings.release_secs = release.max(0.0);
+        }
+
+        let dt = elapsed(&mut self.last_update);
+        if let Some(audio) = read_audio(&inputs, "audio") {
+            self.follower.process(&audio.to_mono(), audio.sample_rate);
+        } else if let Some(live) = &self.live {
+            self.follower.update_from_bands(&live.latest().bands, dt);
+        }
+
+        let mut outputs = HashMap::new();
+        outputs.insert("envelope".to_string(), serde_json::json!(self.follower.follower.value()));
+        Ok(outputs)
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        let mut parameters = HashMap::new();
+        parameters.insert("low_hz".to_string(), serde_json::json!(self.follower.low_hz));
+        parameters.insert("high_hz".to_string(), serde_json::json!(self.follower.high_hz));
+        parameters.insert("settings".to_string(), serde_json::to_value(self.follower.follower.settings).unwrap_or(Value::Null));
+        parameters
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
+
+/// Graph node gating a level: `open` while above the thresholds, `trigger` on opening
+pub struct GateNode {
+    pub id: NodeId,
+    pub gate: Gate,
+    last_update: Option<Instant>,
+}
+
+impl GateNode {
+    pub fn new(settings: GateSettings) -> Self {
+        Self { id: NodeId::new(), gate: Gate::new(settings), last_update: None }
+    }
+}
+
+impl Node for GateNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "Gate"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![
+            InputPort::new("level", DataType::Float),
+            InputPort::optional("open_threshold", DataType::Float),
+            InputPort::optional("close_threshold", DataType::Float),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![
+            OutputPort::new("open", DataType::Boolean),
+            OutputPort::new("trigger", DataType::Boolean),
+        ]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        if let Some(threshold) = read_float(&inputs, "open_threshold") {
+            self.gate.settings.open_threshold = threshold;
+        }
+        if let Some(threshold) = read_float(&inputs, "close_threshold") {
+            self.gate.settings.close_threshold = threshold;
+        }
+
+        let dt = elapsed(&mut self.last_update);
+        let level = read_float(&inputs, "level").unwrap_or(0.0);
+        let triggered = self.gate.update(level, dt);
+
+        let mut outputs = HashMap::new();
+        outputs.insert("open".to_string(), Value::Bool(self.gate.is_open()));
+        outputs.insert("trigger".to_string(), Value::Bool(triggered));
+        Ok(outputs)
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        let mut parameters = HashMap::new();
+        parameters.insert("settings".to_string(), serde_json::to_value(self.gate.settings).unwrap_or(Value::Null));
+        parameters
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
+
+/// Graph node outputting a 0–1 pumping signal from a sidechain: audio, a level such
+/// as a kick follower, or triggers
+pub struct DuckerNode {
+    pub id: NodeId,
+    pub ducker: Ducker,
+    last_trigger: bool,
+    last_update: Option<Instant>,
+}
+
+impl DuckerNode {
+    pub fn new(settings: DuckerSettings) -> Self {
+        Self {
+            id: NodeId::new(),
+            ducker: Ducker::new(settings),
+            last_trigger: false,
+            last_update: None,
+        }
+    }
+}
+
+impl Node for DuckerNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "Ducker"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![
+            InputPort::optional("sidechain", DataType::AudioBuffer),
+            InputPort::optional("level", DataType::Float),
+            InputPort::optional("trigger", DataType::Boolean),
+            InputPort::optional("depth", DataType::Float),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![
+            OutputPort::new("duck", DataType::Float),
+            OutputPort::new("amount", DataType::Float),
+        ]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        if let Some(depth) = read_float(&inputs, "depth") {
+            self.ducker.settings.depth = depth.clamp(0.0, 1.0);
+        }
+        let trigger = inputs.get("trigger").and_then(Value::as_bool).unwrap_or(false);
+        if trigger && !self.last_trigger {
+            self.ducker.trigger();
+        }
+        self.last_trigger = trigger;
+
+        let dt = elapsed(&mut self.last_update);
+        if let Some(audio) = read_audio(&inputs, "sidechain") {
+            self.ducker.process(&audio.to_mono(), audio.sample_rate);
+        } else {
+            self.ducker.update(read_float(&inputs, "level").unwrap_or(0.0), dt);
+        }
+
+        let mut outputs = HashMap::new();
+        outputs.insert("duck".to_string(), serde_json::json!(self.ducker.value()));
+        outputs.insert("amount".to_string(), serde_json::json!(self.ducker.ducking()));
+        Ok(outputs)
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        let mut parameters = HashMap::new();
+        parameters.insert("settings".to_string(), serde_json::to_value(self.ducker.settings).unwrap_or(Value::Null));
+        parameters
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
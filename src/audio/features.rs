@@ -0,0 +1,259 @@
+// Spectral features: STFT, mel filterbank, MFCC and deltas
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Mel feature configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MelConfig {
+    pub sample_rate: f32,
+    /// FFT and window length in samples
+    pub n_fft: usize,
+    pub hop_length: usize,
+    pub n_mels: usize,
+    pub n_mfcc: usize,
+    pub f_min: f32,
+    /// Upper filterbank edge; `None` means Nyquist
+    pub f_max: Option<f32>,
+    /// Sinusoidal lifter length; 0 disables liftering
+    pub lifter: usize,
+}
+
+impl Default for MelConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100.0,
+            n_fft: 1024,
+            hop_length: 512,
+            n_mels: 40,
+            n_mfcc: 13,
+            f_min: 0.0,
+            f_max: None,
+            lifter: 22,
+        }
+    }
+}
+
+/// Floor applied before taking the log of mel energies
+const LOG_FLOOR: f32 = 1e-10;
+
+/// HTK mel scale
+pub fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+pub fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+/// Triangular filters with peaks of 1.0, evenly spaced on the mel scale.
+///
+/// Returns `n_mels` rows of `n_fft / 2 + 1` weights. Neighbouring filters overlap so
+/// that their weights sum to 1.0 between the first and last centre.
+pub fn mel_filterbank(sample_rate: f32, n_fft: usize, n_mels: usize, f_min: f32, f_max: f32) -> Vec<Vec<f32>> {
+    let bins = n_fft / 2 + 1;
+    let mel_min = hz_to_mel(f_min);
+    let mel_max = hz_to_mel(f_max);
+    let edges: Vec<f32> = (0..n_mels + 2)
+        .map(|i| mel_to_hz(mel_min + (mel_max - mel_min) * i as f32 / (n_mels + 1) as f32))
+        .collect();
+
+    (0..n_mels)
+        .map(|m| {
+            let (lower, centre, upper) = (edges[m], edges[m + 1], edges[m + 2]);
+            (0..bins)
+                .map(|bin| {
+                    let hz = bin as f32 * sample_rate / n_fft as f32;
+                    let rising = (hz - lower) / (centre - lower);
+                    let falling = (upper - hz) / (upper - centre);
+                    rising.min(falling).max(0.0)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Orthonormal DCT-II, keeping the first `n_out` coefficients
+pub fn dct_ii(input: &[f32], n_out: usize) -> Vec<f32> {
+    let n = input.len() as f32;
+    (0..n_out.min(input.len()))
+        .map(|k| {
+            let scale = if k == 0 { (1.0 / n).sqrt() } else { (2.0 / n).sqrt() };
+            let sum: f32 = input.iter()
+                .enumerate()
+                .map(|(i, &x)| x * (std::f32::consts::PI * k as f32 * (2 * i + 1) as f32 / (2.0 * n)).cos())
+                .sum();
+            scale * sum
+        })
+        .collect()
+}
+
+/// Sinusoidal liftering, `c[n] *= 1 + (L / 2) sin(pi n / L)`
+pub fn lifter(coefficients: &mut [f32], length: usize) {
+    if length == 0 {
+        return;
+    }
+    let l = length as f32;
+    for (n, coefficient) in coefficients.iter_mut().enumerate() {
+        *coefficient *= 1.0 + 0.5 * l * (std::f32::consts::PI * n as f32 / l).sin();
+    }
+}
+
+/// Regression deltas over `width` frames either side, repeating the edge frames
+pub fn deltas(frames: &[Vec<f32>], width: usize) -> Vec<Vec<f32>> {
+    let Some(dimensions) = frames.first().map(Vec::len) else {
+        return Vec::new();
+    };
+    let width = width.max(1) as isize;
+    let last = frames.len() as isize - 1;
+    let denominator = 2.0 * (1..=width).map(|n| (n * n) as f32).sum::<f32>();
+
+    (0..frames.len() as isize)
+        .map(|t| {
+            (0..dimensions)
+                .map(|d| {
+                    let sum: f32 = (1..=width)
+                        .map(|n| {
+                            let ahead = &frames[(t + n).min(last) as usize];
+                            let behind = &frames[(t - n).max(0) as usize];
+                            n as f32 * (ahead[d] - behind[d])
+                        })
+                        .sum();
+                    sum / denominator
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Short-time Fourier transform with a Hann window
+pub struct Stft {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    hop_length: usize,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Stft {
+    pub fn new(n_fft: usize, hop_length: usize) -> Self {
+        let n_fft = n_fft.max(2);
+        let fft = FftPlanner::new().plan_fft_forward(n_fft);
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+
+        Self {
+            window: crate::audio::hann_window(n_fft),
+            hop_length: hop_length.max(1),
+            buffer: vec![Complex::default(); n_fft],
+            fft,
+            scratch,
+        }
+    }
+
+    pub fn n_fft(&self) -> usize {
+        self.window.len()
+    }
+
+    /// Power spectrum (|X|²) of every frame. Frames start at multiples of the hop;
+    /// a signal shorter than one window is zero-padded to a single frame.
+    pub fn power_spectrogram(&mut self, samples: &[f32]) -> Vec<Vec<f32>> {
+        let n_fft = self.window.len();
+        if samples.is_empty() {
+            return Vec::new();
+        }
+
+        let frame_count = if samples.len() <= n_fft { 1 } else { 1 + (samples.len() - n_fft) / self.hop_length };
+        (0..frame_count)
+            .map(|frame| {
+                let start = frame * self.hop_length;
+                for (i, slot) in self.buffer.iter_mut().enumerate() {
+                    let sample = samples.get(start + i).copied().unwrap_or(0.0);
+                    *slot = Complex::new(sample * self.window[i], 0.0);
+                }
+                self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+                self.buffer[..n_fft / 2 + 1].iter().map(|bin| bin.norm_sqr()).collect()
+            })
+            .collect()
+    }
+}
+
+/// STFT-based mel spectrogram and MFCC extraction
+pub struct MelExtractor {
+    config: MelConfig,
+    stft: Stft,
+    filterbank: Vec<Vec<f32>>,
+}
+
+impl MelExtractor {
+    pub fn new(config: MelConfig) -> Self {
+        let stft = Stft::new(config.n_fft, config.hop_length);
+        let f_max = config.f_max.unwrap_or(config.sample_rate / 2.0);
+
+        Self {
+            filterbank: mel_filterbank(config.sample_rate, stft.n_fft(), config.n_mels, config.f_min, f_max),
+            stft,
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &MelConfig {
+        &self.config
+    }
+
+    pub fn filterbank(&self) -> &[Vec<f32>] {
+        &self.filterbank
+    }
+
+    pub fn power_spectrogram(&mut self, samples: &[f32]) -> Vec<Vec<f32>> {
+        self.stft.power_spectrogram(samples)
+    }
+
+    /// Mel-band power per frame
+    pub fn mel_spectrogram(&mut self, samples: &[f32]) -> Vec<Vec<f32>> {
+        let spectrogram = self.power_spectrogram(samples);
+        spectrogram.iter()
+            .map(|power| {
+                self.filterbank.iter()
+                    .map(|filter| filter.iter().zip(power).map(|(w, p)| w * p).sum())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Natural log of the mel power, floored at 1e-10
+    pub fn log_mel_spectrogram(&mut self, samples: &[f32]) -> Vec<Vec<f32>> {
+        self.mel_spectrogram(samples)
+            .into_iter()
+            .map(|frame| frame.into_iter().map(|energy| energy.max(LOG_FLOOR).ln()).collect())
+            .collect()
+    }
+
+    /// Liftered MFCCs per frame
+    pub fn mfcc(&mut self, samples: &[f32]) -> Vec<Vec<f32>> {
+        self.log_mel_spectrogram(samples)
+            .iter()
+            .map(|log_mel| {
+                let mut coefficients = dct_ii(log_mel, self.config.n_mfcc);
+                lifter(&mut coefficients, self.config.lifter);
+                coefficients
+            })
+            .collect()
+    }
+
+    /// Spectral centroid in Hz per frame
+    pub fn spectral_centroid(&mut self, samples: &[f32]) -> Vec<f32> {
+        let bin_hz = self.config.sample_rate / self.stft.n_fft() as f32;
+        self.power_spectrogram(samples)
+            .iter()
+            .map(|power| {
+                let (weighted, total) = power.iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(weighted, total), (bin, p)| {
+                        let magnitude = p.sqrt();
+                        (weighted + bin as f32 * bin_hz * magnitude, total + magnitude)
+                    });
+                if total > 0.0 { weighted / total } else { 0.0 }
+            })
+            .collect()
+    }
+}
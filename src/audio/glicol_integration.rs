@@ -1,3 +1,530 @@
 // Glicol live coding integration
-pub struct GlicolEngine;
-pub struct LiveCodingContext;
\ No newline at end of file
+use bevy::prelude::*;
+use anyhow::Result;
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::audio::{AudioEngine, AudioProcessor, Mixer, ProcessContext, ProcessorId};
+use crate::core::{AudioEventType, DataType, InputPort, Node, NodeId, OutputPort, VjError, VjEvent};
+
+/// Frames rendered per Glicol block
+pub const GLICOL_BLOCK_SIZE: usize = 128;
+
+/// Size of the status report Glicol returns from each block
+const STATUS_LEN: usize = 256;
+
+/// Commands in flight; each returns at most one string through the garbage ring
+const COMMAND_CAPACITY: usize = 64;
+
+enum GlicolCommand {
+    UpdateCode(String),
+    Message(String),
+    SetBpm(f32),
+}
+
+/// Glicol engine running as a processor in the audio callback.
+///
+/// Code updates are applied with Glicol's diff-based hot swap, so unchanged chains keep
+/// playing without a click. The engine owns its graph, so parsing and diffing have to run
+/// here and allocate. That work is bounded: it scales with the length of the code, and an
+/// update is only sent when the code is edited, at most once per frame, never per block.
+/// The command strings themselves are handed back through a garbage ring so they are
+/// freed on the Bevy side.
+pub struct GlicolProcessor {
+    engine: glicol::Engine<GLICOL_BLOCK_SIZE>,
+    commands: Consumer<GlicolCommand>,
+    garbage: Producer<String>,
+    status: Producer<[u8; STATUS_LEN]>,
+    rendered: Vec<f32>,
+    rendered_channels: usize,
+    position: usize,
+}
+
+impl GlicolProcessor {
+    fn render_block(&mut self) {
+        let (buffers, status) = self.engine.next_block(vec![]);
+
+        self.rendered_channels = buffers.len().max(1);
+        self.rendered.resize(GLICOL_BLOCK_SIZE * self.rendered_channels, 0.0);
+        for (channel, buffer) in buffers.iter().enumerate() {
+            for frame in 0..GLICOL_BLOCK_SIZE {
+                self.rendered[frame * self.rendered_channels + channel] = buffer[frame];
+            }
+        }
+
+        if status[0] != 0 {
+            let _ = self.status.push(status);
+        }
+        self.position = 0;
+    }
+}
+
+impl AudioProcessor for GlicolProcessor {
+    fn process(&mut self, context: &ProcessContext, _input: &[f32], output: &mut [f32]) {
+        while let Ok(command) = self.commands.pop() {
+            match command {
+                GlicolCommand::UpdateCode(code) => {
+                    self.engine.update_with_code(&code);
+                    let _ = self.garbage.push(code);
+                }
+                GlicolCommand::Message(message) => {
+                    self.engine.send_msg(&message);
+                    let _ = self.garbage.push(message);
+                }
+                GlicolCommand::SetBpm(bpm) => self.engine.set_bpm(bpm),
+            }
+        }
+
+        for frame in output.chunks_mut(context.channels) {
+            if self.position >= GLICOL_BLOCK_SIZE {
+                self.render_block();
+            }
+            let rendered = &self.rendered[self.position * self.rendered_channels..][..self.rendered_channels];
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample += rendered[channel % self.rendered_channels];
+            }
+            self.position += 1;
+        }
+    }
+}
+
+/// A Glicol error with its position in the source, when known
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlicolError {
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl GlicolError {
+    /// Decode the status bytes returned by `next_block`: an error kind followed by a
+    /// NUL-terminated message. Parse errors carry a ` --> line:column` marker.
+    pub(crate) fn from_status(status: &[u8; STATUS_LEN]) -> Self {
+        let text = &status[2..];
+        let end = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+        let message = String::from_utf8_lossy(&text[..end]).trim().to_string();
+
+        let position = message.split("-->").nth(1).and_then(|rest| {
+            let location = rest.split_whitespace().next()?;
+            let (line, column) = location.split_once(':')?;
+            Some((line.parse().ok()?, column.parse().ok()?))
+        });
+
+        let message = match status[0] {
+            1 => format!("Parse error: {}", message),
+            2 => format!("Unknown reference: {}", message),
+            3 => format!("Unknown node: {}", message),
+            _ => message,
+        };
+
+        Self {
+            message,
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+        }
+    }
+}
+
+/// A `$name` placeholder in Glicol code, located for `send_msg`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlicolParameter {
+    pub name: String,
+    pub chain: String,
+    pub node_index: usize,
+    pub param_index: usize,
+}
+
+/// Find `$name` placeholders and the chain, node and argument they sit in.
+///
+/// Chains may continue on following lines that start with `>>`.
+pub fn find_glicol_parameters(code: &str) -> Vec<GlicolParameter> {
+    let mut parameters: Vec<GlicolParameter> = Vec::new();
+    let mut chain = String::new();
+    let mut node_offset = 0;
+
+    for line in code.lines() {
+        let line = line.split("//").next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let body = match line.split_once(':') {
+            Some((name, body)) if !line.starts_with(">>") => {
+                chain = name.trim().to_string();
+                node_offset = 0;
+                body
+            }
+            _ => line,
+        };
+
+        let mut nodes = body.split(">>").map(str::trim).peekable();
+        if body.trim_start().starts_with(">>") {
+            nodes.next();
+        }
+
+        for node in nodes {
+            for (index, token) in node.split_whitespace().skip(1).enumerate() {
+                let Some(name) = token.strip_prefix('$').map(parameter_name) else {
+                    continue;
+                };
+                if name.is_empty() {
+                    continue;
+                }
+                if parameters.iter().any(|p| p.name == name) {
+                    warn!("Glicol parameter ${} is used more than once; only the first is controllable", name);
+                    continue;
+                }
+                parameters.push(GlicolParameter {
+                    name: name.to_string(),
+                    chain: chain.clone(),
+                    node_index: node_offset,
+                    param_index: index,
+                });
+            }
+            node_offset += 1;
+        }
+    }
+
+    parameters
+}
+
+/// The identifier at the start of the text following a `$`
+fn parameter_name(text: &str) -> &str {
+    let len = text.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(text.len());
+    &text[..len]
+}
+
+/// Replace `$name` placeholders with values, falling back to 0. A `$` not followed by a
+/// name is left as it is.
+pub fn substitute_glicol_parameters(code: &str, values: &HashMap<String, f32>) -> String {
+    let mut result = String::with_capacity(code.len());
+    let mut rest = code;
+
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let name = parameter_name(&rest[start + 1..]);
+        if name.is_empty() {
+            result.push('$');
+        } else {
+            result.push_str(&values.get(name).copied().unwrap_or(0.0).to_string());
+        }
+        rest = &rest[start + 1 + name.len()..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Parameters of the running code and their last values
+#[derive(Default)]
+struct ParameterState {
+    parameters: Vec<GlicolParameter>,
+    values: HashMap<String, f32>,
+}
+
+/// Shareable sender for Glicol commands, usable from graph nodes
+#[derive(Clone)]
+pub struct GlicolControl {
+    commands: Arc<Mutex<Producer<GlicolCommand>>>,
+    garbage: Arc<Mutex<Consumer<String>>>,
+    state: Arc<Mutex<ParameterState>>,
+}
+
+impl GlicolControl {
+    fn send(&self, command: GlicolCommand) -> Result<(), VjError> {
+        // Emptying the garbage ring first keeps it from filling up with the commands in flight
+        self.collect_garbage();
+        let mut commands = self.commands.lock()
+            .map_err(|_| VjError::AudioError("Glicol command queue poisoned".to_string()))?;
+        commands.push(command)
+            .map_err(|_| VjError::AudioError("Glicol command queue full".to_string()))
+    }
+
+    /// Hot-swap new code. `$name` placeholders become controllable parameters and are
+    /// filled with their last value.
+    pub fn update_code(&self, code: &str) -> Result<Vec<GlicolParameter>, VjError> {
+        let parameters = find_glicol_parameters(code);
+        let mut state = self.state.lock()
+            .map_err(|_| VjError::AudioError("Glicol parameters poisoned".to_string()))?;
+        let source = substitute_glicol_parameters(code, &state.values);
+
+        self.send(GlicolCommand::UpdateCode(source))?;
+        state.parameters = parameters.clone();
+        Ok(parameters)
+    }
+
+    /// Send a raw Glicol message, e.g. `"o, 0, 0, 440"`
+    pub fn send_message(&self, message: &str) -> Result<(), VjError> {
+        self.send(GlicolCommand::Message(message.to_string()))
+    }
+
+    pub fn set_bpm(&self, bpm: f32) -> Result<(), VjError> {
+        self.send(GlicolCommand::SetBpm(bpm))
+    }
+
+    /// Set a `$name` parameter of the running code; the value is kept for later code updates
+    pub fn set_parameter(&self, name: &str, value: f32) -> Result<(), VjError> {
+        let mut state = self.state.lock()
+            .map_err(|_| VjError::AudioError("Glicol parameters poisoned".to_string()))?;
+        let parameter = state.parameters.iter().find(|p| p.name == name)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown Glicol parameter ${}", name)))?;
+        let message = format!("{}, {}, {}, {}", parameter.chain, parameter.node_index, parameter.param_index, value);
+
+        self.send(GlicolCommand::Message(message))?;
+        state.values.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Parameters found in the running code
+    pub fn parameters(&self) -> Vec<GlicolParameter> {
+        self.state.lock().map(|state| state.parameters.clone()).unwrap_or_default()
+    }
+
+    /// Drop command strings the audio thread has released
+    pub fn collect_garbage(&self) {
+        if let Ok(mut garbage) = self.garbage.lock() {
+            while garbage.pop().is_ok() {}
+        }
+    }
+}
+
+/// Handle to the Glicol engine running in the audio engine
+#[derive(Resource)]
+pub struct GlicolEngine {
+    control: GlicolControl,
+    status: Mutex<Consumer<[u8; STATUS_LEN]>>,
+    processor_id: Option<ProcessorId>,
+}
+
+impl GlicolEngine {
+    /// Create a Glicol handle and its processor
+    pub fn new(sample_rate: f32) -> (Self, GlicolProcessor) {
+        let (commands, command_consumer) = RingBuffer::new(COMMAND_CAPACITY);
+        let (garbage_producer, garbage) = RingBuffer::new(COMMAND_CAPACITY);
+        let (status_producer, status) = RingBuffer::new(16);
+
+        let mut engine = glicol::Engine::<GLICOL_BLOCK_SIZE>::new();
+        engine.set_sr(sample_rate as usize);
+
+        let processor = GlicolProcessor {
+            engine,
+            commands: command_consumer,
+            garbage: garbage_producer,
+            status: status_producer,
+            rendered: vec![0.0; GLICOL_BLOCK_SIZE * 2],
+            rendered_channels: 2,
+            position: GLICOL_BLOCK_SIZE,
+        };
+        let glicol = Self {
+            control: GlicolControl {
+                commands: Arc::new(Mutex::new(commands)),
+                garbage: Arc::new(Mutex::new(garbage)),
+                state: Arc::new(Mutex::new(ParameterState::default())),
+            },
+            status: Mutex::new(status),
+            processor_id: None,
+        };
+        (glicol, processor)
+    }
+
+    /// Create a Glicol processor and add it to the audio engine
+    pub fn start(audio: &mut AudioEngine) -> Result<Self, VjError> {
+        let (mut glicol, processor) = Self::new(audio.sample_rate());
+        glicol.processor_id = Some(audio.add_processor(Box::new(processor))?);
+        Ok(glicol)
+    }
+
+    pub fn update_code(&mut self, code: &str) -> Result<Vec<GlicolParameter>, VjError> {
+        self.control.update_code(code)
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), VjError> {
+        self.control.set_parameter(name, value)
+    }
+
+    pub fn control(&self) -> GlicolControl {
+        self.control.clone()
+    }
+
+    /// `None` when the processor runs in a mixer strip rather than the engine
+    pub fn processor_id(&self) -> Option<ProcessorId> {
+        self.processor_id
+    }
+
+    /// Errors reported by the audio thread since the last call
+    pub fn drain_errors(&self) -> Vec<GlicolError> {
+        let Ok(mut status) = self.status.lock() else {
+            return Vec::new();
+        };
+        std::iter::from_fn(|| status.pop().ok())
+            .map(|status| GlicolError::from_status(&status))
+            .collect()
+    }
+}
+
+/// Code being live-coded. Editing `code` hot-swaps it into the running engine.
+#[derive(Resource, Debug, Default)]
+pub struct LiveCodingContext {
+    pub code: String,
+    pub parameters: Vec<GlicolParameter>,
+    pub last_error: Option<GlicolError>,
+}
+
+/// Start Glicol once the audio engine is running
+pub(crate) fn setup_glicol(world: &mut World) {
+    let Some(sample_rate) = world.get_non_send_resource::<AudioEngine>().map(|audio| audio.sample_rate()) else {
+        warn!("⚠️ No audio engine, Glicol live coding disabled");
+        return;
+    };
+
+    // Play through a mixer strip when there is a mixer, straight into the engine otherwise
+    let started = match world.get_resource_mut::<Mixer>() {
+        Some(mut mixer) => {
+            let (engine, processor) = GlicolEngine::new(sample_rate);
+            mixer.add_strip("Glicol", Box::new(processor)).map(|_| engine)
+        }
+        None => {
+            let mut audio = world.get_non_send_resource_mut::<AudioEngine>().expect("audio engine exists");
+            GlicolEngine::start(&mut audio)
+        }
+    };
+
+    match started {
+        Ok(engine) => {
+            world.insert_resource(engine);
+            info!("🎼 Glicol live coding audio system ready");
+        }
+        Err(e) => error!("❌ Failed to start Glicol: {}", e),
+    }
+}
+
+/// Push edited code to the engine
+pub(crate) fn apply_live_code(
+    mut context: ResMut<LiveCodingContext>,
+    engine: Option<ResMut<GlicolEngine>>,
+) {
+    let Some(mut engine) = engine else {
+        return;
+    };
+    if !context.is_changed() {
+        return;
+    }
+
+    let code = context.code.clone();
+    match engine.update_code(&code) {
+        Ok(parameters) => {
+            // Avoid re-triggering change detection for our own write
+            let context = context.bypass_change_detection();
+            context.parameters = parameters;
+            context.last_error = None;
+        }
+        Err(e) => error!("❌ Failed to send Glicol code: {}", e),
+    }
+}
+
+/// Report Glicol errors as audio events
+pub(crate) fn report_glicol_errors(
+    engine: Option<Res<GlicolEngine>>,
+    mut context: ResMut<LiveCodingContext>,
+    mut vj_events: MessageWriter<VjEvent>,
+) {
+    let Some(engine) = engine else {
+        return;
+    };
+
+    for error in engine.drain_errors() {
+        warn!("🎼 Glicol: {}", error.message);
+        vj_events.write(VjEvent::AudioEvent {
+            event_type: AudioEventType::GlicolError {
+                message: error.message.clone(),
+                line: error.line,
+                column: error.column,
+            },
+        });
+        context.bypass_change_detection().last_error = Some(error);
+    }
+}
+
+/// Graph node playing Glicol code, with its `$name` parameters as float inputs
+pub struct GlicolNode {
+    pub id: NodeId,
+    pub code: String,
+    parameters: Vec<GlicolParameter>,
+    control: Option<GlicolControl>,
+    sent_code: Option<String>,
+    values: HashMap<String, f32>,
+}
+
+impl GlicolNode {
+    pub fn new(code: &str) -> Self {
+        Self {
+            id: NodeId::new(),
+            code: code.to_string(),
+            parameters: find_glicol_parameters(code),
+            control: None,
+            sent_code: None,
+            values: HashMap::new(),
+        }
+    }
+
+    /// Send parameter changes to a running engine
+    pub fn with_control(mut self, control: GlicolControl) -> Self {
+        self.control = Some(control);
+        self
+    }
+}
+
+impl Node for GlicolNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "Glicol"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        self.parameters.iter()
+            .map(|parameter| InputPort::new(&parameter.name, DataType::Float))
+            .collect()
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        Vec::new()
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        if self.sent_code.as_ref() != Some(&self.code) {
+            self.parameters = find_glicol_parameters(&self.code);
+            if let Some(control) = &self.control {
+                control.update_code(&self.code)?;
+            }
+            self.sent_code = Some(self.code.clone());
+            self.values.clear();
+        }
+
+        for parameter in &self.parameters {
+            let Some(value) = inputs.get(&parameter.name).and_then(Value::as_f64) else {
+                continue;
+            };
+            let value = value as f32;
+            if self.values.get(&parameter.name) == Some(&value) {
+                continue;
+            }
+            if let Some(control) = &self.control {
+                control.set_parameter(&parameter.name, value)?;
+            }
+            self.values.insert(parameter.name.clone(), value);
+        }
+
+        Ok(HashMap::new())
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        let mut parameters = HashMap::new();
+        parameters.insert("code".to_string(), Value::String(self.code.clone()));
+        parameters
+    }
+}
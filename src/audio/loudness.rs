@@ -0,0 +1,444 @@
+//! EBU R128 loudness metering
+//!
+//! `LoudnessMeter` measures momentary (400 ms), short-term (3 s) and gated integrated
+//! loudness in LUFS, the loudness range of EBU Tech 3342 and the true peak of
+//! interleaved audio, following ITU-R BS.1770-4: channels are K-weighted, summed with
+//! their surround weights in 100 ms steps, and peaks are found on a 4x oversampled
+//! signal. Gating blocks are counted in fixed histograms of 0.1 LU bins, so a meter
+//! never allocates after it is created and can run on the audio thread.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use crate::core::{AudioBufferData, DataType, InputPort, Node, NodeId, OutputPort};
+
+/// Lowest loudness (LUFS) and true peak (dBTP) reported; silence reads as this
+pub const LOUDNESS_FLOOR: f32 = -70.0;
+
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const INTEGRATED_RELATIVE_GATE_LU: f64 = -10.0;
+const RANGE_RELATIVE_GATE_LU: f64 = -20.0;
+
+/// Gating blocks start every 100 ms
+const STEPS_PER_SECOND: f32 = 10.0;
+const MOMENTARY_STEPS: usize = 4;
+const SHORT_TERM_STEPS: usize = 30;
+
+const HISTOGRAM_BINS: usize = 1000;
+const HISTOGRAM_STEP_LU: f64 = 0.1;
+
+/// Interpolation filter taps per oversampled phase
+const TRUE_PEAK_TAPS: usize = 12;
+
+/// Loudness of a weighted mean square
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn lufs_to_energy(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.0)
+}
+
+fn floored(lufs: f64) -> f32 {
+    (lufs as f32).max(LOUDNESS_FLOOR)
+}
+
+/// BS.1770 weight of a channel; 5.0 and 5.1 layouts boost the surrounds and skip the LFE
+pub fn loudness_channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (5, 3 | 4) | (6, 4 | 5) => 1.41,
+        (6, 3) => 0.0,
+        _ => 1.0,
+    }
+}
+
+/// Coefficients `[b0, b1, b2, a1, a2]` of the two K-weighting stages at a sample rate
+fn k_weighting(sample_rate: f64) -> [[f64; 5]; 2] {
+    // High shelf modelling the head
+    let k = (PI * 1681.974450955533 / sample_rate).tan();
+    let (q, vh) = (0.7071752369554196, 10f64.powf(3.999843853973347 / 20.0));
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = [
+        (vh + vb * k / q + k * k) / a0,
+        2.0 * (k * k - vh) / a0,
+        (vh - vb * k / q + k * k) / a0,
+        2.0 * (k * k - 1.0) / a0,
+        (1.0 - k / q + k * k) / a0,
+    ];
+
+    // RLB high-pass
+    let k = (PI * 38.13547087602444 / sample_rate).tan();
+    let q = 0.5003270373238773;
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = [1.0, -2.0, 1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0];
+    [shelf, highpass]
+}
+
+/// Windowed-sinc interpolator for `factor`x oversampling, as `factor` phases
+fn true_peak_phases(factor: usize) -> Vec<[f32; TRUE_PEAK_TAPS]> {
+    let length = factor * TRUE_PEAK_TAPS;
+    let center = (length - 1) as f64 / 2.0;
+    let taps: Vec<f64> = (0..length)
+        .map(|n| {
+            let x = (n as f64 - center) / factor as f64;
+            let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            let window = 0.5 - 0.5 * (2.0 * PI * (n as f64 + 0.5) / length as f64).cos();
+            sinc * window
+        })
+        .collect();
+    (0..factor)
+        .map(|phase| {
+            let mut coefficients = [0.0; TRUE_PEAK_TAPS];
+            for (k, coefficient) in coefficients.iter_mut().enumerate() {
+                *coefficient = taps[phase + k * factor];
+            }
+            // Unity gain at DC for every phase
+            let sum: f64 = coefficients.iter().sum();
+            coefficients.map(|coefficient| (coefficient / sum) as f32)
+        })
+        .collect()
+}
+
+/// Fixed-size histogram of block loudness in 0.1 LU bins from the absolute gate up
+#[derive(Debug, Clone)]
+struct LoudnessHistogram {
+    counts: Vec<u32>,
+}
+
+impl LoudnessHistogram {
+    fn new() -> Self {
+        Self { counts: vec![0; HISTOGRAM_BINS] }
+    }
+
+    fn bin_lufs(bin: usize) -> f64 {
+        ABSOLUTE_GATE_LUFS + (bin as f64 + 0.5) * HISTOGRAM_STEP_LU
+    }
+
+    fn bin(lufs: f64) -> usize {
+        (((lufs - ABSOLUTE_GATE_LUFS) / HISTOGRAM_STEP_LU).floor().max(0.0) as usize).min(HISTOGRAM_BINS - 1)
+    }
+
+    /// Count a block; blocks below the absolute gate are dropped
+    fn add(&mut self, lufs: f64) {
+        if lufs >= ABSOLUTE_GATE_LUFS {
+            self.counts[Self::bin(lufs)] += 1;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.counts.fill(0);
+    }
+
+    /// Mean loudness of the blocks from `first` bin up, and their count
+    fn mean_from(&self, first: usize) -> Option<(f64, u64)> {
+        let (mut energy, mut count) = (0.0, 0u64);
+        for (bin, &blocks) in self.counts.iter().enumerate().skip(first) {
+            energy += blocks as f64 * lufs_to_energy(Self::bin_lufs(bin));
+            count += blocks as u64;
+        }
+        (count > 0).then(|| (energy_to_lufs(energy / count as f64), count))
+    }
+
+    /// First bin at or above the relative gate, `offset` LU below the ungated mean
+    fn relative_gate(&self, offset: f64) -> Option<usize> {
+        self.mean_from(0).map(|(mean, _)| Self::bin(mean + offset))
+    }
+
+    fn integrated(&self) -> Option<f64> {
+        self.mean_from(self.relative_gate(INTEGRATED_RELATIVE_GATE_LU)?).map(|(lufs, _)| lufs)
+    }
+
+    /// Spread between the 10th and 95th percentile of the relative-gated blocks
+    fn range(&self) -> Option<f64> {
+        let first = self.relative_gate(RANGE_RELATIVE_GATE_LU)?;
+        let total: u64 = self.counts[first..].iter().map(|&count| count as u64).sum();
+        let percentile = |fraction: f64| {
+            let target = (fraction * (total - 1) as f64).round() as u64;
+            let mut seen = 0u64;
+            for (bin, &count) in self.counts.iter().enumerate().skip(first) {
+                seen += count as u64;
+                if seen > target {
+                    return Self::bin_lufs(bin);
+                }
+            }
+            Self::bin_lufs(HISTOGRAM_BINS - 1)
+        };
+        (total > 0).then(|| percentile(0.95) - percentile(0.10))
+    }
+}
+
+/// A loudness measurement
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessReading {
+    /// LUFS over the last 400 ms
+    pub momentary: f32,
+    /// LUFS over the last 3 s
+    pub short_term: f32,
+    /// Gated LUFS since the last reset
+    pub integrated: f32,
+    /// Loudness range in LU since the last reset
+    pub range: f32,
+    /// Highest dBTP since the last reset
+    pub true_peak: f32,
+}
+
+impl Default for LoudnessReading {
+    fn default() -> Self {
+        Self {
+            momentary: LOUDNESS_FLOOR,
+            short_term: LOUDNESS_FLOOR,
+            integrated: LOUDNESS_FLOOR,
+            range: 0.0,
+            true_peak: LOUDNESS_FLOOR,
+        }
+    }
+}
+
+impl LoudnessReading {
+    /// Fields in the order graph nodes and meters publish them
+    pub const NAMES: [&'static str; 5] = ["momentary_lufs", "short_term_lufs", "integrated_lufs", "loudness_range", "true_peak_dbtp"];
+
+    pub fn values(&self) -> [f32; 5] {
+        [self.momentary, self.short_term, self.integrated, self.range, self.true_peak]
+    }
+
+    pub fn from_values(values: [f32; 5]) -> Self {
+        let [momentary, short_term, integrated, range, true_peak] = values;
+        Self { momentary, short_term, integrated, range, true_peak }
+    }
+}
+
+/// EBU R128 meter over interleaved audio with a fixed channel count
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    sample_rate: f32,
+    channels: usize,
+    weights: Vec<f64>,
+    filters: [[f64; 5]; 2],
+    /// Two filter states per stage and channel
+    filter_states: Vec<[f64; 4]>,
+    step_frames: usize,
+    step_position: usize,
+    step_sums: Vec<f64>,
+    /// Weighted mean squares of the last 100 ms steps, as a ring
+    steps: [f64; SHORT_TERM_STEPS],
+    next_step: usize,
+    steps_filled: usize,
+    integrated_blocks: LoudnessHistogram,
+    range_blocks: LoudnessHistogram,
+    true_peak_phases: Vec<[f32; TRUE_PEAK_TAPS]>,
+    true_peak_history: Vec<[f32; TRUE_PEAK_TAPS]>,
+    true_peak: f32,
+    reading: LoudnessReading,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: f32, channels: usize) -> Self {
+        let channels = channels.max(1);
+        // Oversample to at least 192 kHz for true peaks
+        let factor = if sample_rate < 96000.0 { 4 } else if sample_rate < 192000.0 { 2 } else { 1 };
+        Self {
+            sample_rate,
+            channels,
+            weights: (0..channels).map(|channel| loudness_channel_weight(channel, channels)).collect(),
+            filters: k_weighting(sample_rate as f64),
+            filter_states: vec![[0.0; 4]; channels],
+            step_frames: ((sample_rate / STEPS_PER_SECOND).round() as usize).max(1),
+            step_position: 0,
+            step_sums: vec![0.0; channels],
+            steps: [0.0; SHORT_TERM_STEPS],
+            next_step: 0,
+            steps_filled: 0,
+            integrated_blocks: LoudnessHistogram::new(),
+            range_blocks: LoudnessHistogram::new(),
+            true_peak_phases: true_peak_phases(factor),
+            true_peak_history: vec![[0.0; TRUE_PEAK_TAPS]; channels],
+            true_peak: 0.0,
+            reading: LoudnessReading::default(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Meter interleaved samples
+    pub fn process(&mut self, samples: &[f32]) {
+        let [shelf, highpass] = self.filters;
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                self.true_peak = self.true_peak.max(self.oversampled_peak(channel, sample));
+
+                let state = &mut self.filter_states[channel];
+                let x = sample as f64;
+                let y = shelf[0] * x + state[0];
+                state[0] = shelf[1] * x - shelf[3] * y + state[1];
+                state[1] = shelf[2] * x - shelf[4] * y;
+                let z = highpass[0] * y + state[2];
+                state[2] = highpass[1] * y - highpass[3] * z + state[3];
+                state[3] = highpass[2] * y - highpass[4] * z;
+                self.step_sums[channel] += z * z;
+            }
+
+            self.step_position += 1;
+            if self.step_position == self.step_frames {
+                self.finish_step();
+            }
+        }
+        self.reading.true_peak = floored(20.0 * (self.true_peak as f64).log10());
+    }
+
+    /// Highest magnitude among a sample and the oversampled points leading up to it
+    fn oversampled_peak(&mut self, channel: usize, sample: f32) -> f32 {
+        let history = &mut self.true_peak_history[channel];
+        history.copy_within(0..TRUE_PEAK_TAPS - 1, 1);
+        history[0] = sample;
+        if self.true_peak_phases.len() == 1 {
+            return sample.abs();
+        }
+        self.true_peak_phases.iter()
+            .map(|phase| phase.iter().zip(history.iter()).map(|(c, x)| c * x).sum::<f32>().abs())
+            .fold(sample.abs(), f32::max)
+    }
+
+    fn finish_step(&mut self) {
+        let energy = self.step_sums.iter().zip(&self.weights)
+            .map(|(sum, weight)| weight * sum)
+            .sum::<f64>() / self.step_frames as f64;
+        self.step_sums.fill(0.0);
+        self.step_position = 0;
+        self.steps[self.next_step] = energy;
+        self.next_step = (self.next_step + 1) % SHORT_TERM_STEPS;
+        self.steps_filled = (self.steps_filled + 1).min(SHORT_TERM_STEPS);
+
+        if self.steps_filled >= MOMENTARY_STEPS {
+            let momentary = energy_to_lufs(self.mean_of_last(MOMENTARY_STEPS));
+            self.integrated_blocks.add(momentary);
+            self.reading.momentary = floored(momentary);
+            self.reading.integrated = floored(self.integrated_blocks.integrated().unwrap_or(f64::NEG_INFINITY));
+        }
+        if self.steps_filled == SHORT_TERM_STEPS {
+            let short_term = energy_to_lufs(self.mean_of_last(SHORT_TERM_STEPS));
+            self.range_blocks.add(short_term);
+            self.reading.short_term = floored(short_term);
+            self.reading.range = self.range_blocks.range().unwrap_or(0.0) as f32;
+        }
+    }
+
+    fn mean_of_last(&self, steps: usize) -> f64 {
+        (1..=steps)
+            .map(|back| self.steps[(self.next_step + SHORT_TERM_STEPS - back) % SHORT_TERM_STEPS])
+            .sum::<f64>() / steps as f64
+    }
+
+    pub fn reading(&self) -> LoudnessReading {
+        self.reading
+    }
+
+    pub fn momentary(&self) -> f32 {
+        self.reading.momentary
+    }
+
+    pub fn short_term(&self) -> f32 {
+        self.reading.short_term
+    }
+
+    pub fn integrated(&self) -> f32 {
+        self.reading.integrated
+    }
+
+    pub fn loudness_range(&self) -> f32 {
+        self.reading.range
+    }
+
+    pub fn true_peak(&self) -> f32 {
+        self.reading.true_peak
+    }
+
+    /// Start a new measurement, e.g. at the start of a set
+    pub fn reset(&mut self) {
+        self.filter_states.fill([0.0; 4]);
+        self.step_position = 0;
+        self.step_sums.fill(0.0);
+        self.steps = [0.0; SHORT_TERM_STEPS];
+        self.next_step = 0;
+        self.steps_filled = 0;
+        self.integrated_blocks.clear();
+        self.range_blocks.clear();
+        self.true_peak_history.fill([0.0; TRUE_PEAK_TAPS]);
+        self.true_peak = 0.0;
+        self.reading = LoudnessReading::default();
+    }
+}
+
+/// Graph node metering the loudness of its `audio` input
+pub struct LoudnessMeterNode {
+    pub id: NodeId,
+    meter: Option<LoudnessMeter>,
+}
+
+impl LoudnessMeterNode {
+    pub fn new() -> Self {
+        Self { id: NodeId::new(), meter: None }
+    }
+}
+
+impl Default for LoudnessMeterNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Node for LoudnessMeterNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "LoudnessMeter"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![
+            InputPort::new("audio", DataType::AudioBuffer),
+            InputPort::optional("reset", DataType::Boolean),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        LoudnessReading::NAMES.iter().map(|name| OutputPort::new(name, DataType::Float)).collect()
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        if let Some(audio) = inputs.get("audio").and_then(|value| AudioBufferData::from_value(value, 44100.0)) {
+            let stale = self.meter.as_ref().is_none_or(|meter| {
+                meter.sample_rate() != audio.sample_rate || meter.channels() != audio.channels.max(1)
+            });
+            if stale {
+                self.meter = Some(LoudnessMeter::new(audio.sample_rate, audio.channels));
+            }
+            let meter = self.meter.as_mut().expect("meter created above");
+            if inputs.get("reset").and_then(Value::as_bool).unwrap_or(false) {
+                meter.reset();
+            }
+            meter.process(&audio.samples);
+        }
+
+        let reading = self.meter.as_ref().map(LoudnessMeter::reading).unwrap_or_default();
+        Ok(LoudnessReading::NAMES.iter()
+            .zip(reading.values())
+            .map(|(name, value)| (name.to_string(), serde_json::json!(value)))
+            .collect())
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,853 @@
+//! Mixer
+//!
+//! Channel strips wrap audio processors (Glicol, the synth, file players, plugins) and
+//! run them inside one engine processor. Each strip has gain, pan, mute and solo, sends
+//! to aux buses, and an output routed to a group bus or the master bus. Strips and buses
+//! are metered, and buses and master also measure EBU R128 loudness. Fader settings live in atomics, so the UI and the node graph change them
+//! directly; routing changes go to the audio thread through a ring buffer.
+
+use anyhow::Result;
+use bevy::prelude::*;
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::audio::{AnalysisSettings, AudioEngine, AudioProcessor, LevelMeter, LoudnessMeter, LoudnessReading, ProcessContext, ProcessorId, MAX_BLOCK_FRAMES};
+use crate::core::{DataType, InputPort, Node, NodeId, OutputPort, VjError};
+
+/// Most strips one mixer holds
+pub const MAX_STRIPS: usize = 64;
+
+/// Most aux and group buses one mixer holds
+pub const MAX_BUSES: usize = 16;
+
+/// Most sends per strip
+pub const MAX_SENDS: usize = 4;
+
+/// Handle to a channel strip
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StripId(u64);
+
+/// Handle to an aux or group bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BusId(u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BusKind {
+    /// Fed by strip sends, e.g. a shared reverb return
+    Aux,
+    /// Fed by strip and bus outputs routed to it
+    Group,
+}
+
+/// Any fader in the mixer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MixerChannel {
+    Strip(StripId),
+    Bus(BusId),
+    Master,
+}
+
+/// Post-fader levels of a stereo channel
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MeterReading {
+    pub peak: [f32; 2],
+    pub rms: [f32; 2],
+}
+
+impl MeterReading {
+    pub fn peak_max(&self) -> f32 {
+        self.peak[0].max(self.peak[1])
+    }
+
+    pub fn rms_max(&self) -> f32 {
+        self.rms[0].max(self.rms[1])
+    }
+}
+
+/// Fader settings and meter readings shared with the audio thread
+struct ChannelShared {
+    gain: AtomicU32,
+    pan: AtomicU32,
+    mute: AtomicBool,
+    solo: AtomicBool,
+    /// Peak left/right, then RMS left/right
+    meter: [AtomicU32; 4],
+    /// The fader measures loudness
+    loudness_metered: bool,
+    /// Loudness reading in `LoudnessReading::values` order
+    loudness: [AtomicU32; 5],
+    reset_loudness: AtomicBool,
+}
+
+impl ChannelShared {
+    fn new(loudness_metered: bool) -> Self {
+        let floor = LoudnessReading::default().values();
+        Self {
+            gain: AtomicU32::new(1.0f32.to_bits()),
+            pan: AtomicU32::new(0.0f32.to_bits()),
+            mute: AtomicBool::new(false),
+            solo: AtomicBool::new(false),
+            meter: std::array::from_fn(|_| AtomicU32::new(0)),
+            loudness_metered,
+            loudness: std::array::from_fn(|index| AtomicU32::new(floor[index].to_bits())),
+            reset_loudness: AtomicBool::new(false),
+        }
+    }
+}
+
+/// Controls one fader from any thread
+#[derive(Clone)]
+pub struct ChannelControl(Arc<ChannelShared>);
+
+impl ChannelControl {
+    /// Linear gain, 1.0 is unity
+    pub fn set_gain(&self, gain: f32) {
+        self.0.gain.store(gain.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn gain(&self) -> f32 {
+        f32::from_bits(self.0.gain.load(Ordering::Relaxed))
+    }
+
+    /// Balance from -1.0 (left) to 1.0 (right); the centre leaves both sides at unity
+    pub fn set_pan(&self, pan: f32) {
+        self.0.pan.store(pan.clamp(-1.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn pan(&self) -> f32 {
+        f32::from_bits(self.0.pan.load(Ordering::Relaxed))
+    }
+
+    pub fn set_mute(&self, mute: bool) {
+        self.0.mute.store(mute, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.0.mute.load(Ordering::Relaxed)
+    }
+
+    /// While any strip is soloed, only soloed strips are heard. Has no effect on buses.
+    pub fn set_solo(&self, solo: bool) {
+        self.0.solo.store(solo, Ordering::Relaxed);
+    }
+
+    pub fn is_soloed(&self) -> bool {
+        self.0.solo.load(Ordering::Relaxed)
+    }
+
+    pub fn meter(&self) -> MeterReading {
+        let read = |index: usize| f32::from_bits(self.0.meter[index].load(Ordering::Relaxed));
+        MeterReading {
+            peak: [read(0), read(1)],
+            rms: [read(2), read(3)],
+        }
+    }
+
+    /// EBU R128 loudness after the fader; `None` for strips, which are not loudness metered
+    pub fn loudness(&self) -> Option<LoudnessReading> {
+        self.0.loudness_metered.then(|| {
+            LoudnessReading::from_values(std::array::from_fn(|index| {
+                f32::from_bits(self.0.loudness[index].load(Ordering::Relaxed))
+            }))
+        })
+    }
+
+    /// Restart integrated loudness, loudness range and true peak on the next block
+    pub fn reset_loudness(&self) {
+        self.0.reset_loudness.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Fader and meters of one channel on the audio thread
+struct Fader {
+    shared: Arc<ChannelShared>,
+    current: [f32; 2],
+    meters: [LevelMeter; 2],
+    loudness: Option<LoudnessMeter>,
+}
+
+impl Fader {
+    fn new(shared: Arc<ChannelShared>) -> Self {
+        Self { shared, current: [0.0; 2], meters: Default::default(), loudness: None }
+    }
+
+    /// A fader that also measures loudness; the meter is built here, off the audio thread
+    fn with_loudness(shared: Arc<ChannelShared>, sample_rate: f32) -> Self {
+        Self { loudness: Some(LoudnessMeter::new(sample_rate, 2)), ..Self::new(shared) }
+    }
+
+    fn targets(&self, audible: bool) -> [f32; 2] {
+        if !audible || self.shared.mute.load(Ordering::Relaxed) {
+            return [0.0; 2];
+        }
+        let gain = f32::from_bits(self.shared.gain.load(Ordering::Relaxed));
+        let pan = f32::from_bits(self.shared.pan.load(Ordering::Relaxed));
+        [gain * (1.0 - pan).min(1.0), gain * (1.0 + pan).min(1.0)]
+    }
+
+    /// Apply gain and pan to a stereo block in place, ramping from the previous block's
+    /// gains, then meter the result
+    fn apply(&mut self, buffer: &mut [f32], audible: bool, sample_rate: f32, settings: &AnalysisSettings) {
+        let frames = buffer.len() / 2;
+        if frames == 0 {
+            return;
+        }
+
+        let targets = self.targets(audible);
+        let step = [
+            (targets[0] - self.current[0]) / frames as f32,
+            (targets[1] - self.current[1]) / frames as f32,
+        ];
+        for frame in buffer.chunks_exact_mut(2) {
+            self.current[0] += step[0];
+            self.current[1] += step[1];
+            frame[0] *= self.current[0];
+            frame[1] *= self.current[1];
+        }
+        self.current = targets;
+
+        for (channel, meter) in self.meters.iter_mut().enumerate() {
+            meter.process(buffer, channel, 2, sample_rate, settings);
+            self.shared.meter[channel].store(meter.peak().to_bits(), Ordering::Relaxed);
+            self.shared.meter[2 + channel].store(meter.rms().to_bits(), Ordering::Relaxed);
+        }
+
+        if let Some(loudness) = &mut self.loudness {
+            if self.shared.reset_loudness.swap(false, Ordering::Relaxed) {
+                loudness.reset();
+            }
+            loudness.process(buffer);
+            for (shared, value) in self.shared.loudness.iter().zip(loudness.reading().values()) {
+                shared.store(value.to_bits(), Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SendSlot {
+    bus: Option<usize>,
+    level: f32,
+    pre_fader: bool,
+}
+
+struct StripState {
+    source: Box<dyn AudioProcessor>,
+    fader: Fader,
+    /// Group bus slot, or `None` for master
+    output: Option<usize>,
+    sends: [SendSlot; MAX_SENDS],
+    buffer: Vec<f32>,
+}
+
+struct BusState {
+    fader: Fader,
+    output: Option<usize>,
+    buffer: Vec<f32>,
+}
+
+enum MixerCommand {
+    AddStrip(usize, Box<StripState>),
+    RemoveStrip(usize),
+    AddBus(usize, Box<BusState>),
+    RemoveBus(usize),
+    RouteStrip(usize, Option<usize>),
+    RouteBus(usize, Option<usize>),
+    SetSends(usize, [SendSlot; MAX_SENDS]),
+    /// Bus slots in processing order, feeding buses before the buses they feed
+    SetBusOrder(Vec<usize>),
+}
+
+/// Objects released by the audio thread, dropped on the Bevy side
+#[allow(dead_code)]
+enum MixerGarbage {
+    Strip(Box<StripState>),
+    Bus(Box<BusState>),
+    BusOrder(Vec<usize>),
+}
+
+/// Renders every strip and mixes them through the buses into the engine output
+pub struct MixerProcessor {
+    strips: Vec<Option<Box<StripState>>>,
+    buses: Vec<Option<Box<BusState>>>,
+    bus_order: Vec<usize>,
+    master: Fader,
+    master_buffer: Vec<f32>,
+    commands: Consumer<MixerCommand>,
+    garbage: Producer<MixerGarbage>,
+    meter_settings: AnalysisSettings,
+}
+
+impl MixerProcessor {
+    fn apply_commands(&mut self) {
+        while let Ok(command) = self.commands.pop() {
+            match command {
+                MixerCommand::AddStrip(slot, strip) => {
+                    if let Some(previous) = self.strips[slot].replace(strip) {
+                        let _ = self.garbage.push(MixerGarbage::Strip(previous));
+                    }
+                }
+                MixerCommand::RemoveStrip(slot) => {
+                    if let Some(strip) = self.strips[slot].take() {
+                        let _ = self.garbage.push(MixerGarbage::Strip(strip));
+                    }
+                }
+                MixerCommand::AddBus(slot, bus) => {
+                    if let Some(previous) = self.buses[slot].replace(bus) {
+                        let _ = self.garbage.push(MixerGarbage::Bus(previous));
+                    }
+                }
+                MixerCommand::RemoveBus(slot) => {
+                    if let Some(bus) = self.buses[slot].take() {
+                        let _ = self.garbage.push(MixerGarbage::Bus(bus));
+                    }
+                }
+                MixerCommand::RouteStrip(slot, output) => {
+                    if let Some(strip) = self.strips[slot].as_mut() {
+                        strip.output = output;
+                    }
+                }
+                MixerCommand::RouteBus(slot, output) => {
+                    if let Some(bus) = self.buses[slot].as_mut() {
+                        bus.output = output;
+                    }
+                }
+                MixerCommand::SetSends(slot, sends) => {
+                    if let Some(strip) = self.strips[slot].as_mut() {
+                        strip.sends = sends;
+                    }
+                }
+                MixerCommand::SetBusOrder(order) => {
+                    let previous = std::mem::replace(&mut self.bus_order, order);
+                    let _ = self.garbage.push(MixerGarbage::BusOrder(previous));
+                }
+            }
+        }
+    }
+}
+
+/// Add `level` times `source` into `target`
+fn mix_into(target: &mut [f32], source: &[f32], level: f32) {
+    for (target, &sample) in target.iter_mut().zip(source) {
+        *target += sample * level;
+    }
+}
+
+/// Buffer a channel sends its output to: a bus, or master if the bus is gone
+fn output_buffer<'a>(buses: &'a mut [Option<Box<BusState>>], master: &'a mut [f32], output: Option<usize>, samples: usize) -> &'a mut [f32] {
+    match output {
+        Some(slot) if buses[slot].is_some() => &mut buses[slot].as_mut().expect("bus exists").buffer[..samples],
+        _ => &mut master[..samples],
+    }
+}
+
+impl AudioProcessor for MixerProcessor {
+    fn process(&mut self, context: &ProcessContext, input: &[f32], output: &mut [f32]) {
+        self.apply_commands();
+
+        let Self { strips, buses, bus_order, master, master_buffer, meter_settings, .. } = self;
+        let samples = context.frames.min(MAX_BLOCK_FRAMES) * 2;
+        let stereo = ProcessContext { channels: 2, frames: samples / 2, ..*context };
+
+        master_buffer[..samples].fill(0.0);
+        for bus in buses.iter_mut().flatten() {
+            bus.buffer[..samples].fill(0.0);
+        }
+
+        let any_solo = strips.iter().flatten().any(|strip| strip.fader.shared.solo.load(Ordering::Relaxed));
+        for strip in strips.iter_mut().flatten() {
+            let audible = !any_solo || strip.fader.shared.solo.load(Ordering::Relaxed);
+            let muted = !audible || strip.fader.shared.mute.load(Ordering::Relaxed);
+
+            let buffer = &mut strip.buffer[..samples];
+            buffer.fill(0.0);
+            strip.source.process(&stereo, input, buffer);
+
+            for send in strip.sends.iter().filter(|send| send.pre_fader && !muted) {
+                if let Some(bus) = send.bus.and_then(|slot| buses[slot].as_mut()) {
+                    mix_into(&mut bus.buffer[..samples], buffer, send.level);
+                }
+            }
+
+            strip.fader.apply(buffer, audible, context.sample_rate, meter_settings);
+
+            for send in strip.sends.iter().filter(|send| !send.pre_fader) {
+                if let Some(bus) = send.bus.and_then(|slot| buses[slot].as_mut()) {
+                    mix_into(&mut bus.buffer[..samples], buffer, send.level);
+                }
+            }
+
+            mix_into(output_buffer(buses, master_buffer, strip.output, samples), buffer, 1.0);
+        }
+
+        for &slot in bus_order.iter() {
+            let Some(bus) = buses[slot].as_mut() else {
+                continue;
+            };
+            // Moved out so the bus can mix into another bus
+            let mut buffer = std::mem::take(&mut bus.buffer);
+            bus.fader.apply(&mut buffer[..samples], true, context.sample_rate, meter_settings);
+            let target = bus.output.filter(|&target| target != slot);
+            mix_into(output_buffer(buses, master_buffer, target, samples), &buffer[..samples], 1.0);
+            if let Some(bus) = buses[slot].as_mut() {
+                bus.buffer = buffer;
+            }
+        }
+
+        master.apply(&mut master_buffer[..samples], true, context.sample_rate, meter_settings);
+        for (frame, stereo) in master_buffer[..samples].chunks_exact(2).enumerate() {
+            match context.channels {
+                0 => {}
+                1 => output[frame] += 0.5 * (stereo[0] + stereo[1]),
+                channels => {
+                    output[frame * channels] += stereo[0];
+                    output[frame * channels + 1] += stereo[1];
+                }
+            }
+        }
+    }
+}
+
+/// A send from a strip to an aux bus
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MixerSend {
+    pub bus: BusId,
+    pub level: f32,
+    /// Taken before the strip's fader and pan
+    pub pre_fader: bool,
+}
+
+struct StripEntry {
+    slot: usize,
+    name: String,
+    control: ChannelControl,
+    output: Option<BusId>,
+    sends: Vec<MixerSend>,
+}
+
+struct BusEntry {
+    slot: usize,
+    name: String,
+    kind: BusKind,
+    control: ChannelControl,
+    output: Option<BusId>,
+}
+
+/// Mixer running in the audio engine
+#[derive(Resource)]
+pub struct Mixer {
+    strips: HashMap<StripId, StripEntry>,
+    buses: HashMap<BusId, BusEntry>,
+    master: ChannelControl,
+    free_strips: Vec<usize>,
+    free_buses: Vec<usize>,
+    next_id: u64,
+    sample_rate: f32,
+    commands: Mutex<Producer<MixerCommand>>,
+    garbage: Mutex<Consumer<MixerGarbage>>,
+    processor_id: Option<ProcessorId>,
+}
+
+impl Mixer {
+    /// Create an empty mixer and the processor that runs it
+    pub fn new(sample_rate: f32) -> (Self, MixerProcessor) {
+        let (commands, command_consumer) = RingBuffer::new(1024);
+        let (garbage_producer, garbage) = RingBuffer::new((MAX_STRIPS + MAX_BUSES) * 2 + 64);
+        let master = Arc::new(ChannelShared::new(true));
+
+        let processor = MixerProcessor {
+            strips: (0..MAX_STRIPS).map(|_| None).collect(),
+            buses: (0..MAX_BUSES).map(|_| None).collect(),
+            bus_order: Vec::new(),
+            master: Fader::with_loudness(master.clone(), sample_rate),
+            master_buffer: vec![0.0; MAX_BLOCK_FRAMES * 2],
+            commands: command_consumer,
+            garbage: garbage_producer,
+            meter_settings: AnalysisSettings::default(),
+        };
+        let mixer = Self {
+            strips: HashMap::new(),
+            buses: HashMap::new(),
+            master: ChannelControl(master),
+            free_strips: (0..MAX_STRIPS).rev().collect(),
+            free_buses: (0..MAX_BUSES).rev().collect(),
+            next_id: 0,
+            sample_rate,
+            commands: Mutex::new(commands),
+            garbage: Mutex::new(garbage),
+            processor_id: None,
+        };
+        (mixer, processor)
+    }
+
+    /// Create a mixer and add it to the engine
+    pub fn start(engine: &mut AudioEngine) -> Result<Self, VjError> {
+        let (mut mixer, processor) = Self::new(engine.sample_rate());
+        mixer.processor_id = Some(engine.add_processor(Box::new(processor))?);
+        Ok(mixer)
+    }
+
+    pub fn processor_id(&self) -> Option<ProcessorId> {
+        self.processor_id
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn send(&self, command: MixerCommand) -> Result<(), VjError> {
+        let mut commands = self.commands.lock()
+            .map_err(|_| VjError::AudioError("Mixer command queue poisoned".to_string()))?;
+        commands.push(command)
+            .map_err(|_| VjError::AudioError("Mixer command queue full".to_string()))
+    }
+
+    /// Drop strips and buses the audio thread has released
+    pub fn collect_garbage(&self) {
+        if let Ok(mut garbage) = self.garbage.lock() {
+            while garbage.pop().is_ok() {}
+        }
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Add a strip playing `source`, routed to master at unity gain
+    pub fn add_strip(&mut self, name: &str, source: Box<dyn AudioProcessor>) -> Result<StripId, VjError> {
+        self.collect_garbage();
+        let slot = self.free_strips.pop()
+            .ok_or_else(|| VjError::AudioError(format!("Mixer is full ({} strips)", MAX_STRIPS)))?;
+
+        let control = ChannelControl(Arc::new(ChannelShared::new(false)));
+        let strip = StripState {
+            source,
+            fader: Fader::new(control.0.clone()),
+            output: None,
+            sends: [SendSlot::default(); MAX_SENDS],
+            buffer: vec![0.0; MAX_BLOCK_FRAMES * 2],
+        };
+        if let Err(e) = self.send(MixerCommand::AddStrip(slot, Box::new(strip))) {
+            self.free_strips.push(slot);
+            return Err(e);
+        }
+
+        let id = StripId(self.next_id());
+        self.strips.insert(id, StripEntry { slot, name: name.to_string(), control, output: None, sends: Vec::new() });
+        Ok(id)
+    }
+
+    pub fn remove_strip(&mut self, id: StripId) -> Result<(), VjError> {
+        let entry = self.strips.remove(&id)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown mixer strip {:?}", id)))?;
+        self.send(MixerCommand::RemoveStrip(entry.slot))?;
+        self.free_strips.push(entry.slot);
+        Ok(())
+    }
+
+    pub fn add_bus(&mut self, name: &str, kind: BusKind) -> Result<BusId, VjError> {
+        self.collect_garbage();
+        let slot = self.free_buses.pop()
+            .ok_or_else(|| VjError::AudioError(format!("Mixer is full ({} buses)", MAX_BUSES)))?;
+
+        let control = ChannelControl(Arc::new(ChannelShared::new(true)));
+        let bus = BusState {
+            fader: Fader::with_loudness(control.0.clone(), self.sample_rate),
+            output: None,
+            buffer: vec![0.0; MAX_BLOCK_FRAMES * 2],
+        };
+        if let Err(e) = self.send(MixerCommand::AddBus(slot, Box::new(bus))) {
+            self.free_buses.push(slot);
+            return Err(e);
+        }
+
+        let id = BusId(self.next_id());
+        self.buses.insert(id, BusEntry { slot, name: name.to_string(), kind, control, output: None });
+        self.send_bus_order()?;
+        Ok(id)
+    }
+
+    /// Remove a bus. Strips and buses routed to it go to master; sends to it are dropped.
+    pub fn remove_bus(&mut self, id: BusId) -> Result<(), VjError> {
+        if !self.buses.contains_key(&id) {
+            return Err(VjError::AudioError(format!("Unknown mixer bus {:?}", id)));
+        }
+
+        let strips: Vec<StripId> = self.strips.iter()
+            .filter(|(_, strip)| strip.output == Some(id) || strip.sends.iter().any(|send| send.bus == id))
+            .map(|(&strip, _)| strip)
+            .collect();
+        for strip in strips {
+            if self.strips[&strip].output == Some(id) {
+                self.route_strip(strip, None)?;
+            }
+            self.remove_send(strip, id)?;
+        }
+        let buses: Vec<BusId> = self.buses.iter()
+            .filter(|(_, bus)| bus.output == Some(id))
+            .map(|(&bus, _)| bus)
+            .collect();
+        for bus in buses {
+            self.route_bus(bus, None)?;
+        }
+
+        let entry = self.buses.remove(&id).expect("bus exists");
+        self.send_bus_order()?;
+        self.send(MixerCommand::RemoveBus(entry.slot))?;
+        self.free_buses.push(entry.slot);
+        Ok(())
+    }
+
+    fn group_slot(&self, bus: Option<BusId>) -> Result<Option<usize>, VjError> {
+        let Some(bus) = bus else {
+            return Ok(None);
+        };
+        match self.buses.get(&bus) {
+            Some(entry) if entry.kind == BusKind::Group => Ok(Some(entry.slot)),
+            Some(entry) => Err(VjError::ConnectionError(format!("'{}' is an aux bus; use a send", entry.name))),
+            None => Err(VjError::ConnectionError(format!("Unknown mixer bus {:?}", bus))),
+        }
+    }
+
+    /// Route a strip's output to a group bus, or to master with `None`
+    pub fn route_strip(&mut self, id: StripId, output: Option<BusId>) -> Result<(), VjError> {
+        let target = self.group_slot(output)?;
+        let entry = self.strips.get_mut(&id)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown mixer strip {:?}", id)))?;
+        entry.output = output;
+        let slot = entry.slot;
+        self.send(MixerCommand::RouteStrip(slot, target))
+    }
+
+    /// Route a bus's output to a group bus, or to master with `None`
+    pub fn route_bus(&mut self, id: BusId, output: Option<BusId>) -> Result<(), VjError> {
+        let target = self.group_slot(output)?;
+        if !self.buses.contains_key(&id) {
+            return Err(VjError::AudioError(format!("Unknown mixer bus {:?}", id)));
+        }
+
+        let mut next = output;
+        while let Some(bus) = next {
+            if bus == id {
+                return Err(VjError::ConnectionError("Bus routing would create a feedback loop".to_string()));
+            }
+            next = self.buses[&bus].output;
+        }
+
+        let entry = self.buses.get_mut(&id).expect("bus exists");
+        entry.output = output;
+        let slot = entry.slot;
+        self.send(MixerCommand::RouteBus(slot, target))?;
+        self.send_bus_order()
+    }
+
+    /// Add or update a send from a strip to an aux bus
+    pub fn set_send(&mut self, strip: StripId, bus: BusId, level: f32, pre_fader: bool) -> Result<(), VjError> {
+        match self.buses.get(&bus) {
+            Some(entry) if entry.kind == BusKind::Aux => {}
+            Some(entry) => return Err(VjError::ConnectionError(format!("'{}' is a group bus; route to it instead", entry.name))),
+            None => return Err(VjError::ConnectionError(format!("Unknown mixer bus {:?}", bus))),
+        }
+        let entry = self.strips.get_mut(&strip)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown mixer strip {:?}", strip)))?;
+
+        let send = MixerSend { bus, level: level.max(0.0), pre_fader };
+        match entry.sends.iter().position(|existing| existing.bus == bus) {
+            Some(index) => entry.sends[index] = send,
+            None if entry.sends.len() < MAX_SENDS => entry.sends.push(send),
+            None => return Err(VjError::AudioError(format!("Strip '{}' already has {} sends", entry.name, MAX_SENDS))),
+        }
+        self.send_sends(strip)
+    }
+
+    pub fn remove_send(&mut self, strip: StripId, bus: BusId) -> Result<(), VjError> {
+        let entry = self.strips.get_mut(&strip)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown mixer strip {:?}", strip)))?;
+        entry.sends.retain(|send| send.bus != bus);
+        self.send_sends(strip)
+    }
+
+    fn send_sends(&self, strip: StripId) -> Result<(), VjError> {
+        let entry = &self.strips[&strip];
+        let mut slots = [SendSlot::default(); MAX_SENDS];
+        for (slot, send) in slots.iter_mut().zip(&entry.sends) {
+            *slot = SendSlot {
+                bus: self.buses.get(&send.bus).map(|bus| bus.slot),
+                level: send.level,
+                pre_fader: send.pre_fader,
+            };
+        }
+        self.send(MixerCommand::SetSends(entry.slot, slots))
+    }
+
+    /// Process buses furthest from master first, so every bus has its inputs summed
+    fn send_bus_order(&self) -> Result<(), VjError> {
+        let depth = |mut bus: BusId| {
+            let mut hops = 0;
+            while let Some(next) = self.buses[&bus].output {
+                bus = next;
+                hops += 1;
+            }
+            hops
+        };
+        let mut order: Vec<(usize, u64, usize)> = self.buses.iter()
+            .map(|(&id, entry)| (depth(id), id.0, entry.slot))
+            .collect();
+        order.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        self.send(MixerCommand::SetBusOrder(order.into_iter().map(|(_, _, slot)| slot).collect()))
+    }
+
+    pub fn control(&self, channel: MixerChannel) -> Option<ChannelControl> {
+        match channel {
+            MixerChannel::Strip(id) => self.strips.get(&id).map(|entry| entry.control.clone()),
+            MixerChannel::Bus(id) => self.buses.get(&id).map(|entry| entry.control.clone()),
+            MixerChannel::Master => Some(self.master.clone()),
+        }
+    }
+
+    pub fn meter(&self, channel: MixerChannel) -> Option<MeterReading> {
+        self.control(channel).map(|control| control.meter())
+    }
+
+    pub fn name(&self, channel: MixerChannel) -> Option<&str> {
+        match channel {
+            MixerChannel::Strip(id) => self.strips.get(&id).map(|entry| entry.name.as_str()),
+            MixerChannel::Bus(id) => self.buses.get(&id).map(|entry| entry.name.as_str()),
+            MixerChannel::Master => Some("Master"),
+        }
+    }
+
+    /// Strips in the order they were added
+    pub fn strips(&self) -> Vec<StripId> {
+        let mut strips: Vec<StripId> = self.strips.keys().copied().collect();
+        strips.sort_by_key(|id| id.0);
+        strips
+    }
+
+    /// Buses in the order they were added
+    pub fn buses(&self) -> Vec<(BusId, BusKind)> {
+        let mut buses: Vec<(BusId, BusKind)> = self.buses.iter().map(|(&id, entry)| (id, entry.kind)).collect();
+        buses.sort_by_key(|(id, _)| id.0);
+        buses
+    }
+
+    /// Where a strip or bus sends its output; `None` is master
+    pub fn output(&self, channel: MixerChannel) -> Option<BusId> {
+        match channel {
+            MixerChannel::Strip(id) => self.strips.get(&id).and_then(|entry| entry.output),
+            MixerChannel::Bus(id) => self.buses.get(&id).and_then(|entry| entry.output),
+            MixerChannel::Master => None,
+        }
+    }
+
+    pub fn sends(&self, strip: StripId) -> &[MixerSend] {
+        self.strips.get(&strip).map(|entry| entry.sends.as_slice()).unwrap_or(&[])
+    }
+}
+
+pub(crate) fn setup_mixer(world: &mut World) {
+    let Some(mut audio) = world.get_non_send_resource_mut::<AudioEngine>() else {
+        warn!("⚠️ No audio engine, mixer disabled");
+        return;
+    };
+
+    match Mixer::start(&mut audio) {
+        Ok(mixer) => {
+            world.insert_resource(mixer);
+            info!("🎚️ Mixer ready");
+        }
+        Err(e) => error!("❌ Failed to start mixer: {}", e),
+    }
+}
+
+pub(crate) fn collect_mixer_garbage(mixer: Option<Res<Mixer>>) {
+    if let Some(mixer) = mixer {
+        mixer.collect_garbage();
+    }
+}
+
+/// Graph node controlling one mixer fader and reading its meter.
+///
+/// Bus and master channels also output their loudness and take a `reset_loudness` trigger.
+pub struct MixerChannelNode {
+    pub id: NodeId,
+    control: ChannelControl,
+}
+
+impl MixerChannelNode {
+    pub fn new(control: ChannelControl) -> Self {
+        Self { id: NodeId::new(), control }
+    }
+}
+
+impl Node for MixerChannelNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "MixerChannel"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![
+            InputPort::optional("gain", DataType::Float),
+            InputPort::optional("pan", DataType::Float),
+            InputPort::optional("mute", DataType::Boolean),
+            InputPort::optional("solo", DataType::Boolean),
+            InputPort::optional("reset_loudness", DataType::Boolean),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        let mut outputs = vec![
+            OutputPort::new("peak", DataType::Float),
+            OutputPort::new("rms", DataType::Float),
+            OutputPort::new("peak_left", DataType::Float),
+            OutputPort::new("peak_right", DataType::Float),
+        ];
+        if self.control.loudness().is_some() {
+            outputs.extend(LoudnessReading::NAMES.iter().map(|name| OutputPort::new(name, DataType::Float)));
+        }
+        outputs
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        if let Some(gain) = inputs.get("gain").and_then(Value::as_f64) {
+            self.control.set_gain(gain as f32);
+        }
+        if let Some(pan) = inputs.get("pan").and_then(Value::as_f64) {
+            self.control.set_pan(pan as f32);
+        }
+        if let Some(mute) = inputs.get("mute").and_then(Value::as_bool) {
+            self.control.set_mute(mute);
+        }
+        if let Some(solo) = inputs.get("solo").and_then(Value::as_bool) {
+            self.control.set_solo(solo);
+        }
+        if inputs.get("reset_loudness").and_then(Value::as_bool).unwrap_or(false) {
+            self.control.reset_loudness();
+        }
+
+        let meter = self.control.meter();
+        let mut outputs = HashMap::new();
+        outputs.insert("peak".to_string(), serde_json::json!(meter.peak_max()));
+        outputs.insert("rms".to_string(), serde_json::json!(meter.rms_max()));
+        outputs.insert("peak_left".to_string(), serde_json::json!(meter.peak[0]));
+        outputs.insert("peak_right".to_string(), serde_json::json!(meter.peak[1]));
+        if let Some(loudness) = self.control.loudness() {
+            for (name, value) in LoudnessReading::NAMES.iter().zip(loudness.values()) {
+                outputs.insert(name.to_string(), serde_json::json!(value));
+            }
+        }
+        Ok(outputs)
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
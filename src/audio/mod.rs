@@ -1,15 +1,41 @@
 use bevy::prelude::*;
+use crate::core::{AudioEventType, VjEvent};
 
+pub mod audio_graph;
+pub mod drums;
+pub mod engine;
+pub mod envelope;
+pub mod features;
 pub mod glicol_integration;
+pub mod loudness;
 pub mod midi_handler;
+pub mod mixer;
+pub mod pitch;
+pub mod player;
+pub mod recorder;
+pub mod spatial;
 pub mod audio_analysis;
 pub mod synthesis;
+pub mod transport;
 pub mod ui;
+mod tests;
 
+pub use audio_graph::*;
+pub use drums::*;
+pub use engine::*;
+pub use envelope::*;
+pub use features::*;
 pub use glicol_integration::*;
+pub use loudness::*;
 pub use midi_handler::*;
+pub use mixer::*;
+pub use pitch::*;
+pub use player::*;
+pub use recorder::*;
+pub use spatial::*;
 pub use audio_analysis::*;
 pub use synthesis::*;
+pub use transport::*;
 pub use ui::*;
 
 /// Audio system plugin integrating Glicol, MIDI, and analysis
@@ -26,10 +52,25 @@ impl Plugin for AudioPlugin {
                 AudioUiPlugin,
             ))
             .init_resource::<AudioSettings>()
-            .add_systems(Startup, setup_audio_system)
+            .init_resource::<Transport>()
+            .init_resource::<RecorderSettings>()
+            .init_resource::<AudioRecorder>()
+            .add_message::<RecordCommand>()
+            .add_systems(Startup, (
+                setup_audio_system,
+                setup_mixer.after(setup_audio_system),
+                setup_audio_graph.after(setup_audio_system),
+                setup_spatializer.after(setup_audio_system),
+            ))
             .add_systems(Update, (
-                update_audio_metrics,
+                sync_audio_settings,
+                collect_audio_graph_garbage,
+                collect_mixer_garbage,
+                collect_spatial_garbage,
+                update_spatial_sources,
                 process_audio_events,
+                follow_detected_tempo,
+                (handle_record_commands, mark_scene_changes, update_recorder).chain(),
             ));
 
     }
@@ -43,6 +84,17 @@ pub struct AudioSettings {
     pub channels: usize,
     pub master_volume: f32,
     pub latency_ms: f32,
+    pub backend: AudioBackend,
+    /// Audio host name (e.g. "ALSA", "JACK", "WASAPI"); `None` uses the platform default
+    pub host: Option<String>,
+    /// Substring of the output device name; `None` uses the default device
+    pub output_device: Option<String>,
+    /// Substring of the input device name; `None` uses the default device
+    pub input_device: Option<String>,
+    pub input_channels: usize,
+    pub enable_input: bool,
+    /// Speaker layout file (RON or JSON); opens enough output channels for it
+    pub speaker_layout: Option<std::path::PathBuf>,
 }
 
 impl Default for AudioSettings {
@@ -53,6 +105,13 @@ impl Default for AudioSettings {
             channels: 2,
             master_volume: 1.0,
             latency_ms: 12.0,
+            backend: AudioBackend::Cpal,
+            host: None,
+            output_device: None,
+            input_device: None,
+            input_channels: 2,
+            enable_input: true,
+            speaker_layout: None,
         }
     }
 }
@@ -66,53 +125,89 @@ pub struct AudioMetrics {
     pub rms_level_left: f32,
     pub rms_level_right: f32,
     pub spectrum: Vec<f32>, // FFT frequency bins
+    pub bands: BandLevels,
+    /// EBU R128 loudness of the analyzed input
+    pub loudness: LoudnessReading,
     pub beats_detected: u32,
     pub last_beat_time: f64,
 }
 
 /// Setup audio system
-fn setup_audio_system(
-    mut commands: Commands,
-    audio_settings: Res<AudioSettings>,
-) {
+pub(crate) fn setup_audio_system(world: &mut World) {
     info!("🎵 Initializing audio system...");
+    world.init_resource::<AudioMetrics>();
+
+    let layout_path = world.resource::<AudioSettings>().speaker_layout.clone();
+    if let Some(path) = layout_path {
+        match SpeakerLayout::load(&path) {
+            Ok(layout) => {
+                let mut audio_settings = world.resource_mut::<AudioSettings>();
+                audio_settings.channels = audio_settings.channels.max(layout.channel_count());
+                info!("🔊 Speaker layout '{}' ({} speakers)", layout.name, layout.speakers.len());
+                world.insert_resource(layout);
+            }
+            Err(e) => error!("❌ Failed to load speaker layout: {}", e),
+        }
+    }
+
+    let mut audio_settings = world.resource_mut::<AudioSettings>();
     info!("Sample rate: {} Hz", audio_settings.sample_rate);
     info!("Buffer size: {} samples", audio_settings.buffer_size);
     info!("Channels: {}", audio_settings.channels);
-    
-    // Initialize audio metrics resource
-    commands.init_resource::<AudioMetrics>();
-    
-    info!("✅ Audio system initialized");
+
+    match AudioEngine::start(&audio_settings) {
+        Ok(engine) => {
+            audio_settings.latency_ms = engine.buffer_size() as f32 / engine.sample_rate() * 1000.0;
+            world.insert_non_send_resource(engine);
+            info!("✅ Audio system initialized");
+        }
+        Err(e) => error!("❌ Failed to start audio engine: {}", e),
+    }
 }
 
-/// Update audio performance metrics
-fn update_audio_metrics(
-    mut metrics: ResMut<AudioMetrics>,
-    time: Res<Time>,
+/// Push settings changes to the running engine
+fn sync_audio_settings(
+    audio_settings: Res<AudioSettings>,
+    engine: Option<NonSendMut<AudioEngine>>,
 ) {
-    // This would be populated by actual audio processing
-    // For now, just demonstrate the structure
-    
-    // Simulate beat detection
-    let current_time = time.elapsed_secs_f64();
-    if current_time - metrics.last_beat_time > 60.0 / 120.0 { // 120 BPM
-        metrics.beats_detected += 1;
-        metrics.last_beat_time = current_time;
-        metrics.current_bpm = 120.0;
+    if let Some(mut engine) = engine {
+        if audio_settings.is_changed() {
+            engine.set_master_volume(audio_settings.master_volume);
+        }
     }
 }
 
-/// Process audio-related events
-fn process_audio_events() {
-    // Handle audio events from MIDI, beat detection, etc.
+/// Forward engine status (device changes, xruns) as audio events
+fn process_audio_events(
+    engine: Option<NonSendMut<AudioEngine>>,
+    mut vj_events: MessageWriter<VjEvent>,
+) {
+    let Some(mut engine) = engine else {
+        return;
+    };
+
+    for event_type in engine.poll() {
+        match &event_type {
+            AudioEventType::BufferUnderrun | AudioEventType::BufferOverrun => {
+                warn!("⚠️ Audio {:?}", event_type);
+            }
+            AudioEventType::DeviceDisconnected { device_name } => {
+                error!("🔌 Audio device '{}' disconnected", device_name);
+            }
+            _ => {}
+        }
+        vj_events.write(VjEvent::AudioEvent { event_type });
+    }
 }
 
-/// Placeholder plugins for audio subsystems
+/// Glicol live coding hosted in the audio engine
 pub struct GlicolPlugin;
 impl Plugin for GlicolPlugin {
     fn build(&self, app: &mut App) {
-        info!("🎼 Glicol live coding audio system ready");
+        app
+            .init_resource::<LiveCodingContext>()
+            .add_systems(Startup, setup_glicol.after(setup_mixer))
+            .add_systems(Update, (apply_live_code, report_glicol_errors).chain());
     }
 }
 
@@ -123,16 +218,25 @@ impl Plugin for MidiPlugin {
     }
 }
 
+/// Spectrum and level analysis of the engine's audio
 pub struct AudioAnalysisPlugin;
 impl Plugin for AudioAnalysisPlugin {
     fn build(&self, app: &mut App) {
-        info!("📊 Audio analysis system ready");
+        app
+            .init_resource::<AnalysisSettings>()
+            .init_resource::<AudioAnalyzer>()
+            .init_resource::<SharedAnalysis>()
+            .add_systems(Update, analyze_audio);
     }
 }
 
+/// Polyphonic synth played from MIDI input
 pub struct SynthesisPlugin;
 impl Plugin for SynthesisPlugin {
     fn build(&self, app: &mut App) {
-        info!("🎛️ Audio synthesis system ready");
+        app
+            .init_resource::<SynthPatch>()
+            .add_systems(Startup, setup_synthesizer.after(setup_mixer))
+            .add_systems(Update, (sync_synth_patch, play_midi_input));
     }
 }
\ No newline at end of file
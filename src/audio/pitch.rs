@@ -0,0 +1,375 @@
+// Pitch, chroma and key detection
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use crate::audio::Stft;
+use crate::core::{AudioBufferData, DataType, InputPort, Node, NodeId, OutputPort};
+
+pub const PITCH_CLASS_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// Fundamental frequency estimate
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PitchEstimate {
+    pub frequency: f32,
+    /// 1 minus the normalized difference at the chosen period, 0.0..1.0
+    pub confidence: f32,
+}
+
+impl PitchEstimate {
+    /// Nearest MIDI note number
+    pub fn midi_note(&self) -> u8 {
+        frequency_to_midi(self.frequency).round().clamp(0.0, 127.0) as u8
+    }
+
+    /// Offset from the nearest note in cents
+    pub fn cents(&self) -> f32 {
+        let midi = frequency_to_midi(self.frequency);
+        (midi - midi.round()) * 100.0
+    }
+}
+
+/// Fractional MIDI note number of a frequency (A4 = 440 Hz = 69)
+pub fn frequency_to_midi(frequency: f32) -> f32 {
+    69.0 + 12.0 * (frequency / 440.0).log2()
+}
+
+pub fn midi_to_frequency(note: f32) -> f32 {
+    440.0 * 2f32.powf((note - 69.0) / 12.0)
+}
+
+/// YIN pitch detector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct YinConfig {
+    pub min_frequency: f32,
+    pub max_frequency: f32,
+    /// Normalized difference below which a period is accepted
+    pub threshold: f32,
+}
+
+impl Default for YinConfig {
+    fn default() -> Self {
+        Self {
+            min_frequency: 50.0,
+            max_frequency: 2000.0,
+            threshold: 0.15,
+        }
+    }
+}
+
+/// Estimate the fundamental of a mono buffer with YIN.
+///
+/// The buffer must hold at least two periods of the lowest frequency; returns `None`
+/// for shorter buffers and for silence.
+pub fn yin(samples: &[f32], sample_rate: f32, config: &YinConfig) -> Option<PitchEstimate> {
+    let min_tau = ((sample_rate / config.max_frequency).floor() as usize).max(2);
+    let max_tau = (sample_rate / config.min_frequency).ceil() as usize;
+    if samples.len() < max_tau * 2 || samples.iter().all(|&s| s == 0.0) {
+        return None;
+    }
+    let window = samples.len() - max_tau;
+
+    // Difference function and its cumulative mean normalization
+    let difference: Vec<f32> = (0..=max_tau)
+        .map(|tau| {
+            samples[..window].iter()
+                .zip(&samples[tau..tau + window])
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum()
+        })
+        .collect();
+
+    let mut normalized = vec![1.0; max_tau + 1];
+    let mut running_sum = 0.0;
+    for tau in 1..=max_tau {
+        running_sum += difference[tau];
+        normalized[tau] = if running_sum > 0.0 { difference[tau] * tau as f32 / running_sum } else { 1.0 };
+    }
+
+    // First dip under the threshold, followed down to its minimum
+    let tau = match (min_tau..max_tau).find(|&tau| normalized[tau] < config.threshold) {
+        Some(mut tau) => {
+            while tau + 1 < max_tau && normalized[tau + 1] < normalized[tau] {
+                tau += 1;
+            }
+            tau
+        }
+        None => (min_tau..max_tau).min_by(|&a, &b| normalized[a].total_cmp(&normalized[b]))?,
+    };
+
+    // Parabolic interpolation around the minimum
+    let (left, centre, right) = (normalized[tau - 1], normalized[tau], normalized[tau + 1]);
+    let denominator = left - 2.0 * centre + right;
+    let offset = if denominator.abs() > f32::EPSILON {
+        (0.5 * (left - right) / denominator).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+
+    Some(PitchEstimate {
+        frequency: sample_rate / (tau as f32 + offset),
+        confidence: (1.0 - centre).clamp(0.0, 1.0),
+    })
+}
+
+/// 12-bin pitch-class energy from a power spectrum, normalized to a maximum of 1.0.
+/// Bins outside 55 Hz..5 kHz are ignored.
+pub fn chroma_from_power(power: &[f32], sample_rate: f32, n_fft: usize) -> [f32; 12] {
+    let mut chroma = [0.0f32; 12];
+    let bin_hz = sample_rate / n_fft as f32;
+
+    for (bin, &energy) in power.iter().enumerate().skip(1) {
+        let frequency = bin as f32 * bin_hz;
+        if !(55.0..=5000.0).contains(&frequency) {
+            continue;
+        }
+        let pitch_class = (frequency_to_midi(frequency).round() as i32).rem_euclid(12) as usize;
+        chroma[pitch_class] += energy;
+    }
+
+    let max = chroma.iter().copied().fold(0.0, f32::max);
+    if max > 0.0 {
+        for value in chroma.iter_mut() {
+            *value /= max;
+        }
+    }
+    chroma
+}
+
+/// Krumhansl-Kessler key profiles, starting on the tonic
+const MAJOR_PROFILE: [f32; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const MINOR_PROFILE: [f32; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+/// A major or minor key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MusicalKey {
+    /// Pitch class of the tonic, 0 = C
+    pub tonic: u8,
+    pub minor: bool,
+}
+
+impl MusicalKey {
+    /// 0..11 for major keys, 12..23 for minor keys
+    pub fn index(&self) -> usize {
+        self.tonic as usize + if self.minor { 12 } else { 0 }
+    }
+
+    pub fn name(&self) -> String {
+        let mode = if self.minor { "minor" } else { "major" };
+        format!("{} {}", PITCH_CLASS_NAMES[self.tonic as usize % 12], mode)
+    }
+}
+
+fn pearson(a: &[f32; 12], b: impl Fn(usize) -> f32) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / 12.0;
+    let mean_b = (0..12).map(&b).sum::<f32>() / 12.0;
+    let (mut covariance, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (i, &value) in a.iter().enumerate() {
+        let (da, db) = (value - mean_a, b(i) - mean_b);
+        covariance += da * db;
+        var_a += da * da;
+        var_b += db * db;
+    }
+    if var_a <= 0.0 || var_b <= 0.0 {
+        0.0
+    } else {
+        covariance / (var_a * var_b).sqrt()
+    }
+}
+
+/// Best matching key for a chroma vector and its correlation
+pub fn estimate_key(chroma: &[f32; 12]) -> Option<(MusicalKey, f32)> {
+    (0..24)
+        .map(|index| {
+            let key = MusicalKey { tonic: (index % 12) as u8, minor: index >= 12 };
+            let profile = if key.minor { &MINOR_PROFILE } else { &MAJOR_PROFILE };
+            let correlation = pearson(chroma, |pitch_class| profile[(pitch_class + 12 - key.tonic as usize) % 12]);
+            (key, correlation)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .filter(|(_, correlation)| *correlation > 0.0)
+}
+
+/// Key tracking over time with hysteresis.
+///
+/// Chroma is averaged with an exponential decay; the reported key only changes once
+/// another key has been the best match for `hold_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyTracker {
+    /// Time constant of the chroma average in seconds
+    pub memory_secs: f32,
+    pub hold_secs: f32,
+    chroma: [f32; 12],
+    key: Option<(MusicalKey, f32)>,
+    candidate: Option<(MusicalKey, f32)>,
+}
+
+impl Default for KeyTracker {
+    fn default() -> Self {
+        Self {
+            memory_secs: 8.0,
+            hold_secs: 2.0,
+            chroma: [0.0; 12],
+            key: None,
+            candidate: None,
+        }
+    }
+}
+
+impl KeyTracker {
+    /// Add chroma covering `duration_secs`; returns true when the reported key changes
+    pub fn update(&mut self, chroma: &[f32; 12], duration_secs: f32) -> bool {
+        let keep = (-duration_secs / self.memory_secs.max(1e-3)).exp();
+        for (average, &value) in self.chroma.iter_mut().zip(chroma) {
+            *average = *average * keep + value * (1.0 - keep);
+        }
+
+        let Some((best, correlation)) = estimate_key(&self.chroma) else {
+            return false;
+        };
+
+        match self.key {
+            None => {
+                self.key = Some((best, correlation));
+                true
+            }
+            Some((current, _)) if current == best => {
+                self.key = Some((best, correlation));
+                self.candidate = None;
+                false
+            }
+            Some(_) => {
+                let held = match self.candidate {
+                    Some((candidate, held)) if candidate == best => held + duration_secs,
+                    _ => duration_secs,
+                };
+                if held >= self.hold_secs {
+                    self.key = Some((best, correlation));
+                    self.candidate = None;
+                    true
+                } else {
+                    self.candidate = Some((best, held));
+                    false
+                }
+            }
+        }
+    }
+
+    pub fn key(&self) -> Option<MusicalKey> {
+        self.key.map(|(key, _)| key)
+    }
+
+    /// Correlation of the averaged chroma with the reported key's profile
+    pub fn confidence(&self) -> f32 {
+        self.key.map(|(_, correlation)| correlation.max(0.0)).unwrap_or(0.0)
+    }
+
+    pub fn chroma(&self) -> &[f32; 12] {
+        &self.chroma
+    }
+}
+
+/// Graph node reporting pitch, chroma and key of an audio buffer
+#[derive(Serialize, Deserialize)]
+pub struct PitchKeyNode {
+    pub id: NodeId,
+    pub yin: YinConfig,
+    pub n_fft: usize,
+    pub tracker: KeyTracker,
+    #[serde(skip)]
+    stft: Option<Stft>,
+}
+
+impl PitchKeyNode {
+    pub fn new() -> Self {
+        Self {
+            id: NodeId::new(),
+            yin: YinConfig::default(),
+            n_fft: 4096,
+            tracker: KeyTracker::default(),
+            stft: None,
+        }
+    }
+}
+
+impl Default for PitchKeyNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Node for PitchKeyNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "PitchKey"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![InputPort::new("audio", DataType::AudioBuffer)]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![
+            OutputPort::new("frequency", DataType::Float),
+            OutputPort::new("pitch_confidence", DataType::Float),
+            OutputPort::new("midi_note", DataType::Integer),
+            OutputPort::new("chroma", DataType::Array),
+            OutputPort::new("key", DataType::String),
+            OutputPort::new("key_index", DataType::Integer),
+            OutputPort::new("key_confidence", DataType::Float),
+            OutputPort::new("key_changed", DataType::Boolean),
+        ]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        let audio = inputs.get("audio")
+            .and_then(|value| AudioBufferData::from_value(value, 44100.0))
+            .unwrap_or_default();
+        let mono = audio.to_mono();
+        let sample_rate = if audio.sample_rate > 0.0 { audio.sample_rate } else { 44100.0 };
+
+        let pitch = yin(&mono, sample_rate, &self.yin);
+
+        if self.stft.as_ref().is_none_or(|stft| stft.n_fft() != self.n_fft) {
+            self.stft = Some(Stft::new(self.n_fft, self.n_fft / 2));
+        }
+        let stft = self.stft.as_mut().expect("stft created above");
+        let n_fft = stft.n_fft();
+        let frames = stft.power_spectrogram(&mono);
+        let mut power = vec![0.0; n_fft / 2 + 1];
+        for frame in &frames {
+            for (total, value) in power.iter_mut().zip(frame) {
+                *total += value;
+            }
+        }
+        let chroma = chroma_from_power(&power, sample_rate, n_fft);
+        let key_changed = !mono.is_empty() && self.tracker.update(&chroma, mono.len() as f32 / sample_rate);
+        let key = self.tracker.key();
+
+        let mut outputs = HashMap::new();
+        outputs.insert("frequency".to_string(), serde_json::json!(pitch.map(|p| p.frequency).unwrap_or(0.0)));
+        outputs.insert("pitch_confidence".to_string(), serde_json::json!(pitch.map(|p| p.confidence).unwrap_or(0.0)));
+        outputs.insert("midi_note".to_string(), pitch.map(|p| Value::from(p.midi_note())).unwrap_or(Value::Null));
+        outputs.insert("chroma".to_string(), serde_json::to_value(chroma)?);
+        outputs.insert("key".to_string(), key.map(|k| Value::String(k.name())).unwrap_or(Value::Null));
+        outputs.insert("key_index".to_string(), key.map(|k| Value::from(k.index())).unwrap_or(Value::Null));
+        outputs.insert("key_confidence".to_string(), serde_json::json!(self.tracker.confidence()));
+        outputs.insert("key_changed".to_string(), Value::Bool(key_changed));
+        Ok(outputs)
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        let mut parameters = HashMap::new();
+        parameters.insert("yin".to_string(), serde_json::to_value(&self.yin).unwrap_or(Value::Null));
+        parameters.insert("n_fft".to_string(), Value::from(self.n_fft));
+        parameters
+    }
+
+    fn is_cacheable(&self) -> bool {
+        // Key tracking accumulates chroma across runs
+        false
+    }
+}
@@ -0,0 +1,466 @@
+// Audio file playback
+use anyhow::Result;
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use crate::audio::{AudioEngine, AudioProcessor, ProcessContext, ProcessorId};
+use crate::core::{AudioBufferData, DataType, InputPort, Node, NodeId, OutputPort, VjError};
+
+/// Decoded audio held in memory
+#[derive(Debug, Clone)]
+pub struct AudioClip {
+    /// Interleaved samples
+    pub samples: Vec<f32>,
+    pub channels: usize,
+    pub sample_rate: f32,
+}
+
+impl AudioClip {
+    /// Decode a WAV, FLAC or Ogg Vorbis file. Decoding a long file takes a while; load
+    /// clips from a background task.
+    pub fn load(path: &Path) -> Result<Self, VjError> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| VjError::FileError(format!("Failed to open {}: {}", path.display(), e)))?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(extension);
+        }
+
+        let decode_error = |e: DecodeError| VjError::FileError(format!("Failed to decode {}: {}", path.display(), e));
+        let probed = symphonia::default::get_probe()
+            .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+            .map_err(decode_error)?;
+        let mut format = probed.format;
+
+        let track = format.tracks().iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or_else(|| VjError::FileError(format!("No audio track in {}", path.display())))?;
+        let track_id = track.id;
+        let mut sample_rate = track.codec_params.sample_rate.unwrap_or(44100) as f32;
+        let mut channels = track.codec_params.channels.map(|c| c.count()).unwrap_or(0);
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(decode_error)?;
+
+        let mut samples = Vec::new();
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(DecodeError::ResetRequired) => break,
+                Err(e) => return Err(decode_error(e)),
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+
+            match decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    channels = spec.channels.count();
+                    sample_rate = spec.rate as f32;
+                    let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                    buffer.copy_interleaved_ref(decoded);
+                    samples.extend_from_slice(buffer.samples());
+                }
+                // A corrupt packet only loses that packet
+                Err(DecodeError::DecodeError(_)) => continue,
+                Err(e) => return Err(decode_error(e)),
+            }
+        }
+
+        if channels == 0 {
+            return Err(VjError::FileError(format!("No audio decoded from {}", path.display())));
+        }
+        Ok(Self { samples, channels, sample_rate })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1)
+    }
+
+    pub fn duration_secs(&self) -> f32 {
+        self.frames() as f32 / self.sample_rate
+    }
+
+    /// Sample of a channel at a whole frame, silent outside the clip
+    fn sample(&self, frame: isize, channel: usize) -> f32 {
+        if frame < 0 || frame as usize >= self.frames() {
+            return 0.0;
+        }
+        self.samples[frame as usize * self.channels + channel % self.channels]
+    }
+
+    /// Cubic Hermite interpolation at a fractional frame
+    fn interpolate(&self, position: f64, channel: usize) -> f32 {
+        let index = position.floor() as isize;
+        let t = (position - index as f64) as f32;
+        let (y0, y1, y2, y3) = (
+            self.sample(index - 1, channel),
+            self.sample(index, channel),
+            self.sample(index + 1, channel),
+            self.sample(index + 2, channel),
+        );
+        let c1 = 0.5 * (y2 - y0);
+        let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+        let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+        ((c3 * t + c2) * t + c1) * t + y1
+    }
+}
+
+enum PlayerCommand {
+    /// Start at an engine frame, or immediately
+    Play(Option<u64>),
+    Pause,
+    Stop,
+    Seek(f64),
+    SetLoop(Option<(f64, f64)>),
+    SetSpeed(f32),
+    SetGain(f32),
+}
+
+/// Playback state visible from the Bevy side
+struct PlayerStatus {
+    /// Position in clip frames, as f64 bits
+    position: AtomicU64,
+    playing: AtomicBool,
+}
+
+/// Renders a clip inside the audio callback with sample-rate conversion and varispeed
+pub struct FilePlayerProcessor {
+    clip: Arc<AudioClip>,
+    commands: Consumer<PlayerCommand>,
+    tap: Producer<f32>,
+    status: Arc<PlayerStatus>,
+    position: f64,
+    speed: f32,
+    gain: f32,
+    loop_region: Option<(f64, f64)>,
+    playing: bool,
+    start_at: Option<u64>,
+}
+
+impl AudioProcessor for FilePlayerProcessor {
+    fn process(&mut self, context: &ProcessContext, _input: &[f32], output: &mut [f32]) {
+        while let Ok(command) = self.commands.pop() {
+            match command {
+                PlayerCommand::Play(at) => {
+                    // Playing again after the end restarts the clip
+                    if self.position >= self.clip.frames() as f64 {
+                        self.position = 0.0;
+                    }
+                    self.playing = at.is_none();
+                    self.start_at = at;
+                }
+                PlayerCommand::Pause => {
+                    self.playing = false;
+                    self.start_at = None;
+                }
+                PlayerCommand::Stop => {
+                    self.playing = false;
+                    self.start_at = None;
+                    self.position = 0.0;
+                }
+                PlayerCommand::Seek(position) => self.position = position.max(0.0),
+                PlayerCommand::SetLoop(region) => self.loop_region = region.filter(|(start, end)| end > start),
+                PlayerCommand::SetSpeed(speed) => self.speed = speed,
+                PlayerCommand::SetGain(gain) => self.gain = gain,
+            }
+        }
+
+        let step = self.clip.sample_rate as f64 / context.sample_rate as f64 * self.speed as f64;
+        let end = self.clip.frames() as f64;
+
+        for (index, frame) in output.chunks_mut(context.channels).enumerate() {
+            if let Some(start) = self.start_at {
+                if context.frame_position + index as u64 >= start {
+                    self.playing = true;
+                    self.start_at = None;
+                }
+            }
+
+            if !self.playing {
+                for _ in 0..frame.len() {
+                    let _ = self.tap.push(0.0);
+                }
+                continue;
+            }
+
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let value = self.clip.interpolate(self.position, channel) * self.gain;
+                *sample += value;
+                let _ = self.tap.push(value);
+            }
+
+            self.position += step;
+            match self.loop_region {
+                Some((loop_start, loop_end)) if self.position >= loop_end => {
+                    self.position = loop_start + (self.position - loop_end) % (loop_end - loop_start);
+                }
+                _ if self.position >= end || self.position < 0.0 => {
+                    self.playing = false;
+                    self.position = self.position.clamp(0.0, end);
+                }
+                _ => {}
+            }
+        }
+
+        self.status.position.store(self.position.to_bits(), Ordering::Relaxed);
+        self.status.playing.store(self.playing, Ordering::Relaxed);
+    }
+}
+
+/// Handle to a clip playing in the audio engine
+#[derive(Clone)]
+pub struct FilePlayer {
+    clip: Arc<AudioClip>,
+    commands: Arc<Mutex<Producer<PlayerCommand>>>,
+    tap: Arc<Mutex<Consumer<f32>>>,
+    status: Arc<PlayerStatus>,
+    cues: Arc<Mutex<HashMap<String, f64>>>,
+    processor_id: Option<ProcessorId>,
+}
+
+impl FilePlayer {
+    /// Create a player and its processor for an engine running at `sample_rate`
+    pub fn new(clip: AudioClip, sample_rate: f32, channels: usize) -> (Self, FilePlayerProcessor) {
+        let clip = Arc::new(clip);
+        let (commands, command_consumer) = RingBuffer::new(64);
+        let (tap_producer, tap) = RingBuffer::new(sample_rate.max(1.0) as usize * channels.max(1));
+        let status = Arc::new(PlayerStatus {
+            position: AtomicU64::new(0f64.to_bits()),
+            playing: AtomicBool::new(false),
+        });
+
+        let processor = FilePlayerProcessor {
+            clip: clip.clone(),
+            commands: command_consumer,
+            tap: tap_producer,
+            status: status.clone(),
+            position: 0.0,
+            speed: 1.0,
+            gain: 1.0,
+            loop_region: None,
+            playing: false,
+            start_at: None,
+        };
+        let player = Self {
+            clip,
+            commands: Arc::new(Mutex::new(commands)),
+            tap: Arc::new(Mutex::new(tap)),
+            status,
+            cues: Arc::new(Mutex::new(HashMap::new())),
+            processor_id: None,
+        };
+        (player, processor)
+    }
+
+    /// Create a player and add it to the engine
+    pub fn start(engine: &mut AudioEngine, clip: AudioClip) -> Result<Self, VjError> {
+        let (mut player, processor) = Self::new(clip, engine.sample_rate(), engine.channels());
+        player.processor_id = Some(engine.add_processor(Box::new(processor))?);
+        Ok(player)
+    }
+
+    pub fn processor_id(&self) -> Option<ProcessorId> {
+        self.processor_id
+    }
+
+    pub fn clip(&self) -> &AudioClip {
+        &self.clip
+    }
+
+    fn send(&self, command: PlayerCommand) -> Result<(), VjError> {
+        let mut commands = self.commands.lock()
+            .map_err(|_| VjError::AudioError("Player command queue poisoned".to_string()))?;
+        commands.push(command)
+            .map_err(|_| VjError::AudioError("Player command queue full".to_string()))
+    }
+
+    fn seconds_to_frames(&self, seconds: f32) -> f64 {
+        seconds as f64 * self.clip.sample_rate as f64
+    }
+
+    pub fn play(&self) -> Result<(), VjError> {
+        self.send(PlayerCommand::Play(None))
+    }
+
+    /// Start playing at an engine frame, e.g. from `Transport::next_quantized_frame`
+    pub fn play_at(&self, engine_frame: u64) -> Result<(), VjError> {
+        self.send(PlayerCommand::Play(Some(engine_frame)))
+    }
+
+    pub fn pause(&self) -> Result<(), VjError> {
+        self.send(PlayerCommand::Pause)
+    }
+
+    /// Stop and rewind to the start
+    pub fn stop(&self) -> Result<(), VjError> {
+        self.send(PlayerCommand::Stop)
+    }
+
+    pub fn seek(&self, seconds: f32) -> Result<(), VjError> {
+        self.send(PlayerCommand::Seek(self.seconds_to_frames(seconds)))
+    }
+
+    /// Loop between two positions in seconds, or play through with `None`
+    pub fn set_loop(&self, region: Option<(f32, f32)>) -> Result<(), VjError> {
+        let region = region.map(|(start, end)| (self.seconds_to_frames(start), self.seconds_to_frames(end)));
+        self.send(PlayerCommand::SetLoop(region))
+    }
+
+    /// Playback rate; 1.0 is normal speed, pitch follows speed
+    pub fn set_speed(&self, speed: f32) -> Result<(), VjError> {
+        self.send(PlayerCommand::SetSpeed(speed))
+    }
+
+    pub fn set_gain(&self, gain: f32) -> Result<(), VjError> {
+        self.send(PlayerCommand::SetGain(gain))
+    }
+
+    pub fn add_cue(&self, name: &str, seconds: f32) {
+        if let Ok(mut cues) = self.cues.lock() {
+            cues.insert(name.to_string(), seconds as f64);
+        }
+    }
+
+    pub fn remove_cue(&self, name: &str) {
+        if let Ok(mut cues) = self.cues.lock() {
+            cues.remove(name);
+        }
+    }
+
+    /// Cue points sorted by position
+    pub fn cues(&self) -> Vec<(String, f32)> {
+        let mut cues: Vec<(String, f32)> = self.cues.lock()
+            .map(|cues| cues.iter().map(|(name, &seconds)| (name.clone(), seconds as f32)).collect())
+            .unwrap_or_default();
+        cues.sort_by(|a, b| a.1.total_cmp(&b.1));
+        cues
+    }
+
+    pub fn jump_to_cue(&self, name: &str) -> Result<(), VjError> {
+        let seconds = self.cues.lock().ok()
+            .and_then(|cues| cues.get(name).copied())
+            .ok_or_else(|| VjError::AudioError(format!("Unknown cue '{}'", name)))?;
+        self.seek(seconds as f32)
+    }
+
+    /// Playback position in seconds
+    pub fn position_secs(&self) -> f32 {
+        (f64::from_bits(self.status.position.load(Ordering::Relaxed)) / self.clip.sample_rate as f64) as f32
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.status.playing.load(Ordering::Relaxed)
+    }
+
+    /// Drain the player's rendered output since the last call
+    pub fn read_output(&self, buffer: &mut Vec<f32>) {
+        if let Ok(mut tap) = self.tap.lock() {
+            while let Ok(sample) = tap.pop() {
+                buffer.push(sample);
+            }
+        }
+    }
+}
+
+/// Graph node controlling a file player and exposing its output as an audio buffer
+pub struct FilePlayerNode {
+    pub id: NodeId,
+    player: Option<FilePlayer>,
+    sample_rate: f32,
+    channels: usize,
+    was_playing: bool,
+    last_cue: Option<String>,
+}
+
+impl FilePlayerNode {
+    pub fn new(player: FilePlayer, sample_rate: f32, channels: usize) -> Self {
+        Self {
+            id: NodeId::new(),
+            player: Some(player),
+            sample_rate,
+            channels,
+            was_playing: false,
+            last_cue: None,
+        }
+    }
+}
+
+impl Node for FilePlayerNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "FilePlayer"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![
+            InputPort::optional("play", DataType::Boolean),
+            InputPort::optional("speed", DataType::Float),
+            InputPort::optional("gain", DataType::Float),
+            InputPort::optional("cue", DataType::String),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![
+            OutputPort::new("audio", DataType::AudioBuffer),
+            OutputPort::new("position", DataType::Float),
+            OutputPort::new("playing", DataType::Boolean),
+        ]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        let Some(player) = &self.player else {
+            return Ok(HashMap::new());
+        };
+
+        if let Some(play) = inputs.get("play").and_then(Value::as_bool) {
+            if play != self.was_playing {
+                if play { player.play()? } else { player.pause()? }
+                self.was_playing = play;
+            }
+        }
+        if let Some(speed) = inputs.get("speed").and_then(Value::as_f64) {
+            player.set_speed(speed as f32)?;
+        }
+        if let Some(gain) = inputs.get("gain").and_then(Value::as_f64) {
+            player.set_gain(gain as f32)?;
+        }
+        let cue = inputs.get("cue").and_then(Value::as_str).map(str::to_string);
+        if cue.is_some() && cue != self.last_cue {
+            player.jump_to_cue(cue.as_deref().unwrap_or_default())?;
+        }
+        self.last_cue = cue;
+
+        let mut samples = Vec::new();
+        player.read_output(&mut samples);
+        let audio = AudioBufferData::new(samples, self.channels, self.sample_rate);
+
+        let mut outputs = HashMap::new();
+        outputs.insert("audio".to_string(), audio.to_value());
+        outputs.insert("position".to_string(), serde_json::json!(player.position_secs()));
+        outputs.insert("playing".to_string(), Value::Bool(player.is_playing()));
+        Ok(outputs)
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
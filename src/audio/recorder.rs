@@ -0,0 +1,655 @@
+// Recording audio to WAV files from a disk-writer thread
+use anyhow::Result;
+use bevy::prelude::*;
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::audio::{AudioEngine, Quantize, TapGate, TapId, Transport};
+use crate::core::{AudioBufferData, DataType, InputPort, Node, NodeId, OutputPort, SceneEvent, VjError};
+
+/// How often the disk writer drains its ring buffer
+const WRITER_POLL: Duration = Duration::from_millis(10);
+
+/// Seconds of audio buffered between the audio thread and the disk writer
+const RING_SECONDS: usize = 2;
+
+/// Sample format of recorded files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordFormat {
+    Float32,
+    Int16,
+    Int24,
+}
+
+/// Where and how recordings are written
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct RecorderSettings {
+    pub directory: PathBuf,
+    /// File names are `<prefix>_<date>_<time>.wav`, with `_002`, `_003`... after rotation
+    pub prefix: String,
+    pub format: RecordFormat,
+    /// Start a new file after this many seconds; `None` writes a single file
+    pub max_file_secs: Option<f32>,
+    /// Add a marker whenever a scene is loaded or switched
+    pub mark_scene_changes: bool,
+}
+
+impl Default for RecorderSettings {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("recordings"),
+            prefix: "nuwe".to_string(),
+            format: RecordFormat::Float32,
+            max_file_secs: None,
+            mark_scene_changes: true,
+        }
+    }
+}
+
+enum WriterCommand {
+    /// Label at a frame counted from the start of the recording
+    Marker(u64, String),
+    Finish,
+}
+
+#[derive(Default)]
+struct WriterStatus {
+    frames: AtomicU64,
+    files: Mutex<Vec<PathBuf>>,
+    error: Mutex<Option<String>>,
+}
+
+/// One recording: a disk-writer thread draining interleaved samples into WAV files
+pub struct RecordingSession {
+    commands: Sender<WriterCommand>,
+    status: Arc<WriterStatus>,
+    thread: Option<JoinHandle<()>>,
+    channels: usize,
+    sample_rate: f32,
+}
+
+impl RecordingSession {
+    /// Start writing everything that arrives on `source`
+    pub fn start(settings: &RecorderSettings, source: Consumer<f32>, channels: usize, sample_rate: f32) -> Result<Self, VjError> {
+        std::fs::create_dir_all(&settings.directory)
+            .map_err(|e| VjError::FileError(format!("Failed to create {}: {}", settings.directory.display(), e)))?;
+
+        let stem = format!("{}_{}", settings.prefix, chrono::Local::now().format("%Y%m%d_%H%M%S"));
+        let channels = channels.max(1);
+        let max_file_frames = settings.max_file_secs
+            .map(|secs| (secs * sample_rate).max(1.0) as u64)
+            .unwrap_or(u64::MAX);
+
+        let mut writer = DiskWriter {
+            directory: settings.directory.clone(),
+            stem,
+            format: settings.format,
+            channels,
+            sample_rate: sample_rate as u32,
+            max_file_frames,
+            file: None,
+            file_index: 0,
+            file_start: 0,
+            markers: Vec::new(),
+            scratch: Vec::new(),
+            status: Arc::new(WriterStatus::default()),
+        };
+        // Open the first file here so a bad directory fails the call
+        writer.open_file()?;
+
+        let status = writer.status.clone();
+        let (commands, command_receiver) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("nuwe-recorder".to_string())
+            .spawn(move || writer.run(source, command_receiver))
+            .map_err(|e| VjError::AudioError(format!("Failed to start recorder thread: {}", e)))?;
+
+        Ok(Self {
+            commands,
+            status,
+            thread: Some(thread),
+            channels,
+            sample_rate,
+        })
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Place a labelled cue at a frame counted from the start of the recording
+    pub fn mark(&self, frame: u64, label: &str) {
+        let _ = self.commands.send(WriterCommand::Marker(frame, label.to_string()));
+    }
+
+    pub fn frames_written(&self) -> u64 {
+        self.status.frames.load(Ordering::Relaxed)
+    }
+
+    pub fn seconds_written(&self) -> f32 {
+        self.frames_written() as f32 / self.sample_rate
+    }
+
+    /// Files started so far, oldest first
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.status.files.lock().map(|files| files.clone()).unwrap_or_default()
+    }
+
+    /// Write what is left in the ring buffer, close the files and return their paths
+    pub fn finish(mut self) -> Result<Vec<PathBuf>, VjError> {
+        self.join();
+        if let Some(error) = self.status.error.lock().ok().and_then(|mut error| error.take()) {
+            return Err(VjError::FileError(error));
+        }
+        Ok(self.files())
+    }
+
+    fn join(&mut self) {
+        let _ = self.commands.send(WriterCommand::Finish);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for RecordingSession {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// State owned by the disk-writer thread
+struct DiskWriter {
+    directory: PathBuf,
+    stem: String,
+    format: RecordFormat,
+    channels: usize,
+    sample_rate: u32,
+    max_file_frames: u64,
+    file: Option<hound::WavWriter<BufWriter<File>>>,
+    file_index: usize,
+    /// Recording frame at which the current file starts
+    file_start: u64,
+    /// Markers not yet written, in recording frames
+    markers: Vec<(u64, String)>,
+    scratch: Vec<f32>,
+    status: Arc<WriterStatus>,
+}
+
+impl DiskWriter {
+    fn run(mut self, mut source: Consumer<f32>, commands: Receiver<WriterCommand>) {
+        loop {
+            let mut finish = false;
+            while let Ok(command) = commands.try_recv() {
+                match command {
+                    WriterCommand::Marker(frame, label) => self.markers.push((frame, label)),
+                    WriterCommand::Finish => finish = true,
+                }
+            }
+
+            let available = source.slots();
+            if let Ok(chunk) = source.read_chunk(available) {
+                let (first, second) = chunk.as_slices();
+                self.scratch.extend_from_slice(first);
+                self.scratch.extend_from_slice(second);
+                chunk.commit_all();
+            }
+            if let Err(e) = self.write_pending() {
+                self.fail(e);
+                return;
+            }
+
+            if finish {
+                if let Err(e) = self.close_file(true) {
+                    self.fail(e);
+                }
+                return;
+            }
+            std::thread::sleep(WRITER_POLL);
+        }
+    }
+
+    fn fail(&mut self, error: VjError) {
+        error!("❌ Recording stopped: {}", error);
+        self.file = None;
+        if let Ok(mut slot) = self.status.error.lock() {
+            *slot = Some(error.to_string());
+        }
+    }
+
+    /// Write the whole frames collected so far, rotating files at the size limit
+    fn write_pending(&mut self) -> Result<(), VjError> {
+        let frames = self.scratch.len() / self.channels;
+        let mut written = 0;
+
+        while written < frames {
+            let recorded = self.status.frames.load(Ordering::Relaxed);
+            let room = self.max_file_frames - (recorded - self.file_start);
+            if room == 0 {
+                self.close_file(false)?;
+                self.file_index += 1;
+                self.file_start = recorded;
+                self.open_file()?;
+                continue;
+            }
+
+            let count = (frames - written).min(room as usize);
+            let samples = &self.scratch[written * self.channels..(written + count) * self.channels];
+            if let Some(file) = self.file.as_mut() {
+                write_samples(file, self.format, samples)
+                    .map_err(|e| VjError::FileError(format!("Failed to write recording: {}", e)))?;
+            }
+            self.status.frames.fetch_add(count as u64, Ordering::Relaxed);
+            written += count;
+        }
+
+        self.scratch.drain(..written * self.channels);
+        Ok(())
+    }
+
+    fn file_path(&self) -> PathBuf {
+        let name = if self.file_index == 0 {
+            format!("{}.wav", self.stem)
+        } else {
+            format!("{}_{:03}.wav", self.stem, self.file_index + 1)
+        };
+        self.directory.join(name)
+    }
+
+    fn open_file(&mut self) -> Result<(), VjError> {
+        let path = self.file_path();
+        let (bits_per_sample, sample_format) = match self.format {
+            RecordFormat::Float32 => (32, hound::SampleFormat::Float),
+            RecordFormat::Int16 => (16, hound::SampleFormat::Int),
+            RecordFormat::Int24 => (24, hound::SampleFormat::Int),
+        };
+        let spec = hound::WavSpec {
+            channels: self.channels as u16,
+            sample_rate: self.sample_rate,
+            bits_per_sample,
+            sample_format,
+        };
+
+        let file = hound::WavWriter::create(&path, spec)
+            .map_err(|e| VjError::FileError(format!("Failed to create {}: {}", path.display(), e)))?;
+        info!("⏺️ Recording to {}", path.display());
+        self.file = Some(file);
+        if let Ok(mut files) = self.status.files.lock() {
+            files.push(path);
+        }
+        Ok(())
+    }
+
+    /// Finalize the current file and append its markers. The last file also takes
+    /// markers placed beyond the end of the recording.
+    fn close_file(&mut self, last: bool) -> Result<(), VjError> {
+        let Some(file) = self.file.take() else {
+            return Ok(());
+        };
+        file.finalize()
+            .map_err(|e| VjError::FileError(format!("Failed to finalize recording: {}", e)))?;
+
+        let file_end = self.status.frames.load(Ordering::Relaxed);
+        let (inside, later): (Vec<_>, Vec<_>) = std::mem::take(&mut self.markers)
+            .into_iter()
+            .partition(|(frame, _)| last || *frame < file_end);
+        self.markers = later;
+
+        let cues: Vec<(u32, String)> = inside.into_iter()
+            .map(|(frame, label)| {
+                let offset = frame.saturating_sub(self.file_start).min(file_end - self.file_start);
+                (offset as u32, label)
+            })
+            .collect();
+        let path = self.file_path();
+        append_cue_chunks(&path, &cues)
+            .map_err(|e| VjError::FileError(format!("Failed to write markers to {}: {}", path.display(), e)))
+    }
+}
+
+fn write_samples(file: &mut hound::WavWriter<BufWriter<File>>, format: RecordFormat, samples: &[f32]) -> hound::Result<()> {
+    for &sample in samples {
+        match format {
+            RecordFormat::Float32 => file.write_sample(sample)?,
+            RecordFormat::Int16 => file.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?,
+            RecordFormat::Int24 => file.write_sample((sample.clamp(-1.0, 1.0) * 8_388_607.0) as i32)?,
+        }
+    }
+    Ok(())
+}
+
+/// Append `cue ` and `LIST/adtl` label chunks to a finished WAV file and fix up the
+/// RIFF size. Cue positions are frame offsets into the data chunk.
+pub fn append_cue_chunks(path: &Path, cues: &[(u32, String)]) -> std::io::Result<()> {
+    if cues.is_empty() {
+        return Ok(());
+    }
+
+    let mut chunks = Vec::new();
+    chunks.extend_from_slice(b"cue ");
+    chunks.extend_from_slice(&(4 + 24 * cues.len() as u32).to_le_bytes());
+    chunks.extend_from_slice(&(cues.len() as u32).to_le_bytes());
+    for (id, (offset, _)) in cues.iter().enumerate() {
+        chunks.extend_from_slice(&(id as u32 + 1).to_le_bytes());
+        chunks.extend_from_slice(&offset.to_le_bytes());
+        chunks.extend_from_slice(b"data");
+        chunks.extend_from_slice(&0u32.to_le_bytes());
+        chunks.extend_from_slice(&0u32.to_le_bytes());
+        chunks.extend_from_slice(&offset.to_le_bytes());
+    }
+
+    let mut labels = b"adtl".to_vec();
+    for (id, (_, label)) in cues.iter().enumerate() {
+        let text_len = label.len() as u32 + 1;
+        labels.extend_from_slice(b"labl");
+        labels.extend_from_slice(&(4 + text_len).to_le_bytes());
+        labels.extend_from_slice(&(id as u32 + 1).to_le_bytes());
+        labels.extend_from_slice(label.as_bytes());
+        labels.push(0);
+        if text_len % 2 == 1 {
+            labels.push(0);
+        }
+    }
+    chunks.extend_from_slice(b"LIST");
+    chunks.extend_from_slice(&(labels.len() as u32).to_le_bytes());
+    chunks.extend_from_slice(&labels);
+
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut length = file.seek(SeekFrom::End(0))?;
+    // Chunks start on even offsets; an odd-sized data chunk is followed by a pad byte
+    if length % 2 == 1 {
+        file.write_all(&[0])?;
+        length += 1;
+    }
+    file.write_all(&chunks)?;
+    let riff_size = (length + chunks.len() as u64 - 8) as u32;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&riff_size.to_le_bytes())?;
+    Ok(())
+}
+
+/// Recorder for the master output
+#[derive(Resource, Default)]
+pub struct AudioRecorder {
+    session: Option<RecordingSession>,
+    tap: Option<(TapId, Arc<TapGate>)>,
+    last_files: Vec<PathBuf>,
+}
+
+impl AudioRecorder {
+    /// Start recording the master output at an engine frame, or right away
+    pub fn start(&mut self, engine: &mut AudioEngine, settings: &RecorderSettings, start_frame: Option<u64>) -> Result<(), VjError> {
+        if self.session.is_some() {
+            return Err(VjError::AudioError("Already recording".to_string()));
+        }
+
+        let start_frame = start_frame.unwrap_or_else(|| engine.frame_position());
+        let capacity = engine.sample_rate() as usize * RING_SECONDS;
+        let tap = engine.add_output_tap(capacity, Some(start_frame))?;
+        let session = match RecordingSession::start(settings, tap.consumer, engine.channels(), engine.sample_rate()) {
+            Ok(session) => session,
+            Err(e) => {
+                engine.remove_output_tap(tap.id)?;
+                return Err(e);
+            }
+        };
+
+        self.tap = Some((tap.id, tap.gate));
+        self.session = Some(session);
+        Ok(())
+    }
+
+    /// Stop recording at an engine frame, or right away. The files are closed once the
+    /// engine has played past that frame.
+    pub fn stop(&mut self, engine: &AudioEngine, stop_frame: Option<u64>) {
+        if let Some((_, gate)) = &self.tap {
+            let stop_frame = stop_frame.unwrap_or_else(|| engine.frame_position());
+            gate.close_at(stop_frame.max(gate.start_frame()));
+        }
+    }
+
+    /// Mark the current engine position with a label
+    pub fn mark(&self, engine: &AudioEngine, label: &str) {
+        if let (Some(session), Some((_, gate))) = (&self.session, &self.tap) {
+            session.mark(engine.frame_position().saturating_sub(gate.start_frame()), label);
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn seconds_recorded(&self) -> f32 {
+        self.session.as_ref().map(RecordingSession::seconds_written).unwrap_or(0.0)
+    }
+
+    /// Files of the current recording, or of the last finished one
+    pub fn files(&self) -> Vec<PathBuf> {
+        match &self.session {
+            Some(session) => session.files(),
+            None => self.last_files.clone(),
+        }
+    }
+
+    /// Close the recording once the engine has passed its stop frame; returns the
+    /// outcome when that happens
+    pub fn poll(&mut self, engine: &mut AudioEngine) -> Option<Result<Vec<PathBuf>, VjError>> {
+        let (tap_id, gate) = self.tap.as_ref()?;
+        let stop_frame = gate.stop_frame()?;
+        if engine.frame_position() < stop_frame {
+            return None;
+        }
+
+        let tap_id = *tap_id;
+        self.tap = None;
+        let session = self.session.take()?;
+        if let Err(e) = engine.remove_output_tap(tap_id) {
+            return Some(Err(e));
+        }
+        let result = session.finish();
+        if let Ok(files) = &result {
+            self.last_files = files.clone();
+        }
+        Some(result)
+    }
+}
+
+/// Recording triggers, quantized to the transport
+#[derive(Message, Debug, Clone)]
+pub enum RecordCommand {
+    Start { quantize: Quantize },
+    Stop { quantize: Quantize },
+    Marker { label: String },
+}
+
+pub(crate) fn handle_record_commands(
+    mut commands: MessageReader<RecordCommand>,
+    mut recorder: ResMut<AudioRecorder>,
+    settings: Res<RecorderSettings>,
+    transport: Res<Transport>,
+    engine: Option<NonSendMut<AudioEngine>>,
+) {
+    let Some(mut engine) = engine else {
+        commands.clear();
+        return;
+    };
+
+    for command in commands.read() {
+        let now = engine.frame_position();
+        let sample_rate = engine.sample_rate();
+        match command {
+            RecordCommand::Start { quantize } => {
+                let frame = transport.next_quantized_frame(now, sample_rate, *quantize);
+                if let Err(e) = recorder.start(&mut engine, &settings, Some(frame)) {
+                    error!("❌ Failed to start recording: {}", e);
+                }
+            }
+            RecordCommand::Stop { quantize } => {
+                let frame = transport.next_quantized_frame(now, sample_rate, *quantize);
+                recorder.stop(&engine, Some(frame));
+            }
+            RecordCommand::Marker { label } => recorder.mark(&engine, label),
+        }
+    }
+}
+
+pub(crate) fn mark_scene_changes(
+    mut scene_events: MessageReader<SceneEvent>,
+    recorder: Res<AudioRecorder>,
+    settings: Res<RecorderSettings>,
+    engine: Option<NonSend<AudioEngine>>,
+) {
+    let Some(engine) = engine.filter(|_| settings.mark_scene_changes && recorder.is_recording()) else {
+        scene_events.clear();
+        return;
+    };
+
+    for event in scene_events.read() {
+        match event {
+            SceneEvent::SceneLoaded { scene_name } => recorder.mark(&engine, scene_name),
+            SceneEvent::SceneSwitched { to, .. } => recorder.mark(&engine, to),
+            _ => {}
+        }
+    }
+}
+
+pub(crate) fn update_recorder(mut recorder: ResMut<AudioRecorder>, engine: Option<NonSendMut<AudioEngine>>) {
+    let Some(mut engine) = engine else {
+        return;
+    };
+
+    match recorder.poll(&mut engine) {
+        Some(Ok(files)) => info!("⏹️ Recording finished: {} file(s)", files.len()),
+        Some(Err(e)) => error!("❌ Recording failed: {}", e),
+        None => {}
+    }
+}
+
+/// Graph node recording any audio connection
+pub struct RecorderNode {
+    pub id: NodeId,
+    pub settings: RecorderSettings,
+    session: Option<(RecordingSession, Mutex<Producer<f32>>)>,
+    frames_pushed: u64,
+    last_marker: Option<String>,
+    last_files: Vec<PathBuf>,
+}
+
+impl RecorderNode {
+    pub fn new(settings: RecorderSettings) -> Self {
+        Self {
+            id: NodeId::new(),
+            settings,
+            session: None,
+            frames_pushed: 0,
+            last_marker: None,
+            last_files: Vec::new(),
+        }
+    }
+
+    fn stop(&mut self) -> Result<(), VjError> {
+        if let Some((session, _)) = self.session.take() {
+            self.last_files = session.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl Node for RecorderNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "Recorder"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![
+            InputPort::new("audio", DataType::AudioBuffer),
+            InputPort::optional("record", DataType::Boolean),
+            InputPort::optional("marker", DataType::String),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![
+            OutputPort::new("recording", DataType::Boolean),
+            OutputPort::new("seconds", DataType::Float),
+            OutputPort::new("file", DataType::String),
+        ]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        let audio = inputs.get("audio").and_then(|value| AudioBufferData::from_value(value, 44100.0));
+        let record = inputs.get("record").and_then(Value::as_bool).unwrap_or(true);
+
+        // A format change mid-recording starts a new recording
+        let format_changed = match (&self.session, &audio) {
+            (Some((session, _)), Some(audio)) => session.channels() != audio.channels || session.sample_rate() != audio.sample_rate,
+            _ => false,
+        };
+        if !record || format_changed {
+            self.stop()?;
+        }
+
+        if let Some(audio) = audio.filter(|audio| record && audio.channels > 0) {
+            if self.session.is_none() {
+                let capacity = audio.sample_rate.max(1.0) as usize * RING_SECONDS * audio.channels;
+                let (producer, consumer) = RingBuffer::new(capacity);
+                let session = RecordingSession::start(&self.settings, consumer, audio.channels, audio.sample_rate)?;
+                self.session = Some((session, Mutex::new(producer)));
+                self.frames_pushed = 0;
+            }
+
+            if let Some((session, producer)) = self.session.as_mut() {
+                let producer = producer.get_mut()
+                    .map_err(|_| VjError::AudioError("Recorder queue poisoned".to_string()))?;
+                let marker = inputs.get("marker").and_then(Value::as_str).map(str::to_string);
+                if let Some(label) = marker.as_deref().filter(|_| marker != self.last_marker) {
+                    session.mark(self.frames_pushed, label);
+                }
+                self.last_marker = marker;
+
+                let count = producer.slots().min(audio.samples.len());
+                for &sample in &audio.samples[..count] {
+                    let _ = producer.push(sample);
+                }
+                if count < audio.samples.len() {
+                    warn!("⚠️ Recorder dropped {} samples", audio.samples.len() - count);
+                }
+                self.frames_pushed += (count / audio.channels) as u64;
+            }
+        }
+
+        let (seconds, file) = match &self.session {
+            Some((session, _)) => (session.seconds_written(), session.files().last().cloned()),
+            None => (0.0, self.last_files.last().cloned()),
+        };
+        let mut outputs = HashMap::new();
+        outputs.insert("recording".to_string(), Value::Bool(self.session.is_some()));
+        outputs.insert("seconds".to_string(), serde_json::json!(seconds));
+        outputs.insert(
+            "file".to_string(),
+            Value::String(file.map(|path| path.display().to_string()).unwrap_or_default()),
+        );
+        Ok(outputs)
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
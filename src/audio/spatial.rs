@@ -0,0 +1,860 @@
+//! Spatial audio
+//!
+//! Places mono sources in 3D around a listener and renders them to any number of
+//! device channels, either with VBAP over a speaker layout or through first-order
+//! Ambisonics (AmbiX: ACN order, SN3D normalisation). Layouts are loaded from RON or
+//! JSON files, so rings, domes and irregular rigs all use the same renderer.
+//!
+//! Directions use the audio convention: +X front, +Y left, +Z up, azimuth counter-
+//! clockwise from the front. `bevy_to_audio` converts from Bevy's axes.
+
+use anyhow::Result;
+use bevy::prelude::*;
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::audio::{AudioEngine, AudioProcessor, ProcessContext, ProcessorId, MAX_BLOCK_FRAMES};
+use crate::core::{DataType, InputPort, Node, NodeId, OutputPort, VjError};
+
+/// Most speakers in one layout
+pub const MAX_SPEAKERS: usize = 64;
+
+/// Most sources one spatializer renders
+pub const MAX_SPATIAL_SOURCES: usize = 64;
+
+/// One loudspeaker and the device channel feeding it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Speaker {
+    #[serde(default)]
+    pub name: String,
+    /// Degrees counter-clockwise from the front
+    pub azimuth: f32,
+    /// Degrees above the horizon
+    #[serde(default)]
+    pub elevation: f32,
+    /// Zero-based device output channel
+    pub channel: usize,
+}
+
+impl Speaker {
+    pub fn new(azimuth: f32, elevation: f32, channel: usize) -> Self {
+        Self { name: String::new(), azimuth, elevation, channel }
+    }
+
+    pub fn direction(&self) -> Vec3 {
+        direction_from_angles(self.azimuth, self.elevation)
+    }
+}
+
+/// Unit vector for an azimuth and elevation in degrees
+pub fn direction_from_angles(azimuth: f32, elevation: f32) -> Vec3 {
+    let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+    Vec3::new(
+        azimuth.cos() * elevation.cos(),
+        azimuth.sin() * elevation.cos(),
+        elevation.sin(),
+    )
+}
+
+/// Convert a Bevy vector (+X right, +Y up, -Z forward) to audio axes
+pub fn bevy_to_audio(vector: Vec3) -> Vec3 {
+    Vec3::new(-vector.z, -vector.x, vector.y)
+}
+
+/// Speaker positions of an installation
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeakerLayout {
+    pub name: String,
+    pub speakers: Vec<Speaker>,
+}
+
+impl SpeakerLayout {
+    /// Left and right at ±30°
+    pub fn stereo() -> Self {
+        Self {
+            name: "Stereo".to_string(),
+            speakers: vec![Speaker::new(30.0, 0.0, 0), Speaker::new(-30.0, 0.0, 1)],
+        }
+    }
+
+    /// `count` speakers evenly around the horizon, channel 0 at the front, going clockwise
+    pub fn ring(count: usize) -> Self {
+        Self {
+            name: format!("{}-channel ring", count),
+            speakers: ring_speakers(count, 0.0, 0),
+        }
+    }
+
+    /// Eight at the horizon, four at 45° and one overhead
+    pub fn dome() -> Self {
+        let mut speakers = ring_speakers(8, 0.0, 0);
+        speakers.extend(ring_speakers(4, 45.0, 8).into_iter().map(|mut speaker| {
+            speaker.azimuth -= 45.0;
+            speaker
+        }));
+        speakers.push(Speaker::new(0.0, 90.0, 12));
+        Self { name: "Dome".to_string(), speakers }
+    }
+
+    /// Stereo for two channels, a ring for anything else
+    pub fn for_channels(channels: usize) -> Self {
+        match channels {
+            0..=2 => Self::stereo(),
+            count => Self::ring(count),
+        }
+    }
+
+    /// Device channels needed to feed every speaker
+    pub fn channel_count(&self) -> usize {
+        self.speakers.iter().map(|speaker| speaker.channel + 1).max().unwrap_or(0)
+    }
+
+    /// True if every speaker sits at the horizon
+    pub fn is_planar(&self) -> bool {
+        self.speakers.iter().all(|speaker| speaker.elevation.abs() < 1.0)
+    }
+
+    pub fn validate(&self) -> Result<(), VjError> {
+        if self.speakers.is_empty() || self.speakers.len() > MAX_SPEAKERS {
+            return Err(VjError::AudioError(format!(
+                "Speaker layout '{}' needs 1 to {} speakers, has {}", self.name, MAX_SPEAKERS, self.speakers.len()
+            )));
+        }
+        for (index, speaker) in self.speakers.iter().enumerate() {
+            if self.speakers[..index].iter().any(|other| other.channel == speaker.channel) {
+                return Err(VjError::AudioError(format!(
+                    "Speaker layout '{}' uses channel {} twice", self.name, speaker.channel
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a layout file; `.ron` files are read as RON, anything else as JSON
+    pub fn load(path: &Path) -> Result<Self, VjError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| VjError::FileError(format!("Failed to read {}: {}", path.display(), e)))?;
+
+        let layout: Self = if path.extension().is_some_and(|extension| extension == "ron") {
+            ron::from_str(&source)
+                .map_err(|e| VjError::FileError(format!("Invalid speaker layout {}: {}", path.display(), e)))?
+        } else {
+            serde_json::from_str(&source)
+                .map_err(|e| VjError::FileError(format!("Invalid speaker layout {}: {}", path.display(), e)))?
+        };
+        layout.validate()?;
+        Ok(layout)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), VjError> {
+        let source = if path.extension().is_some_and(|extension| extension == "ron") {
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|e| VjError::FileError(e.to_string()))?
+        } else {
+            serde_json::to_string_pretty(self)
+                .map_err(|e| VjError::FileError(e.to_string()))?
+        };
+
+        std::fs::write(path, source)
+            .map_err(|e| VjError::FileError(format!("Failed to write {}: {}", path.display(), e)))
+    }
+}
+
+fn ring_speakers(count: usize, elevation: f32, first_channel: usize) -> Vec<Speaker> {
+    (0..count)
+        .map(|index| Speaker::new(-360.0 * index as f32 / count as f32, elevation, first_channel + index))
+        .collect()
+}
+
+struct VbapPair {
+    speakers: [usize; 2],
+    inverse: Mat2,
+}
+
+struct VbapTriangle {
+    /// Indices at or past the speaker count are virtual speakers
+    speakers: [usize; 3],
+    inverse: Mat3,
+}
+
+/// Vector base amplitude panning over a speaker layout.
+///
+/// Planar layouts pan between adjacent pairs; 3D layouts between triangles of the
+/// speakers' convex hull. Virtual speakers fill in a missing top or bottom, and their
+/// share goes to the real speakers next to them.
+pub struct Vbap {
+    speaker_count: usize,
+    pairs: Vec<VbapPair>,
+    triangles: Vec<VbapTriangle>,
+}
+
+impl Vbap {
+    pub fn new(layout: &SpeakerLayout) -> Result<Self, VjError> {
+        layout.validate()?;
+        let directions: Vec<Vec3> = layout.speakers.iter().map(Speaker::direction).collect();
+        let mut vbap = Self { speaker_count: directions.len(), pairs: Vec::new(), triangles: Vec::new() };
+
+        if directions.len() == 1 {
+            return Ok(vbap);
+        }
+        if layout.is_planar() {
+            vbap.pairs = planar_pairs(&directions);
+        } else {
+            vbap.triangles = hull_triangles(&directions);
+            if vbap.triangles.is_empty() {
+                return Err(VjError::AudioError(format!("Speaker layout '{}' can't be triangulated", layout.name)));
+            }
+        }
+        Ok(vbap)
+    }
+
+    pub fn speaker_count(&self) -> usize {
+        self.speaker_count
+    }
+
+    /// Write power-normalised gains for a unit direction, one per speaker
+    pub fn gains(&self, direction: Vec3, gains: &mut [f32]) {
+        let gains = &mut gains[..self.speaker_count];
+        gains.fill(0.0);
+
+        if self.speaker_count == 1 {
+            gains[0] = 1.0;
+            return;
+        }
+
+        if !self.pairs.is_empty() {
+            let horizontal = direction.truncate();
+            if horizontal.length() < 1e-6 {
+                gains.fill((1.0 / self.speaker_count as f32).sqrt());
+                return;
+            }
+            let horizontal = horizontal.normalize();
+            let best = self.pairs.iter()
+                .map(|pair| (pair, pair.inverse * horizontal))
+                .max_by(|(_, a), (_, b)| a.min_element().total_cmp(&b.min_element()));
+            if let Some((pair, weights)) = best {
+                gains[pair.speakers[0]] = weights.x.max(0.0);
+                gains[pair.speakers[1]] = weights.y.max(0.0);
+            }
+        } else {
+            let best = self.triangles.iter()
+                .map(|triangle| (triangle, triangle.inverse * direction))
+                .max_by(|(_, a), (_, b)| a.min_element().total_cmp(&b.min_element()));
+            if let Some((triangle, weights)) = best {
+                let weights = weights.max(Vec3::ZERO).to_array();
+                let real = triangle.speakers.iter().filter(|&&speaker| speaker < self.speaker_count).count();
+                let virtual_share: f32 = triangle.speakers.iter().zip(weights)
+                    .filter(|(&speaker, _)| speaker >= self.speaker_count)
+                    .map(|(_, weight)| weight)
+                    .sum();
+                for (&speaker, weight) in triangle.speakers.iter().zip(weights) {
+                    if speaker < self.speaker_count {
+                        gains[speaker] += weight + virtual_share / real.max(1) as f32;
+                    }
+                }
+            }
+        }
+
+        let power = gains.iter().map(|gain| gain * gain).sum::<f32>().sqrt();
+        if power > 1e-9 {
+            for gain in gains.iter_mut() {
+                *gain /= power;
+            }
+        }
+    }
+}
+
+fn planar_pairs(directions: &[Vec3]) -> Vec<VbapPair> {
+    let azimuth = |index: usize| directions[index].y.atan2(directions[index].x);
+    let mut order: Vec<usize> = (0..directions.len()).collect();
+    order.sort_by(|&a, &b| azimuth(a).total_cmp(&azimuth(b)));
+
+    let mut pairs = Vec::new();
+    for (position, &first) in order.iter().enumerate() {
+        let second = order[(position + 1) % order.len()];
+        let gap = (azimuth(second) - azimuth(first)).rem_euclid(std::f32::consts::TAU);
+        // A gap of half a turn or more has no speakers to pan between
+        if !(1e-4..std::f32::consts::PI - 1e-3).contains(&gap) {
+            continue;
+        }
+        let basis = Mat2::from_cols(directions[first].truncate().normalize(), directions[second].truncate().normalize());
+        if basis.determinant().abs() > 1e-4 {
+            pairs.push(VbapPair { speakers: [first, second], inverse: basis.inverse() });
+        }
+    }
+    pairs
+}
+
+fn hull_triangles(directions: &[Vec3]) -> Vec<VbapTriangle> {
+    let mut points = directions.to_vec();
+    if !directions.iter().any(|direction| direction.z < -0.17) {
+        points.push(Vec3::NEG_Z);
+    }
+    if !directions.iter().any(|direction| direction.z > 0.17) {
+        points.push(Vec3::Z);
+    }
+
+    let mut triangles = Vec::new();
+    for a in 0..points.len() {
+        for b in a + 1..points.len() {
+            for c in b + 1..points.len() {
+                let normal = (points[b] - points[a]).cross(points[c] - points[a]);
+                if normal.length() < 1e-6 {
+                    continue;
+                }
+                let offset = normal.dot(points[a]);
+                let sides = points.iter().map(|point| normal.dot(*point) - offset);
+                let on_hull = sides.clone().all(|side| side <= 1e-4) || sides.clone().all(|side| side >= -1e-4);
+                let basis = Mat3::from_cols(points[a], points[b], points[c]);
+                if on_hull && basis.determinant().abs() > 1e-3 {
+                    triangles.push(VbapTriangle { speakers: [a, b, c], inverse: basis.inverse() });
+                }
+            }
+        }
+    }
+    triangles
+}
+
+/// Encode a unit direction as first-order AmbiX gains: W, Y, Z, X
+pub fn encode_first_order(direction: Vec3) -> [f32; 4] {
+    [1.0, direction.y, direction.z, direction.x]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AmbisonicDecoding {
+    /// Plain sampling decoder; sharpest image at the centre
+    Basic,
+    /// Weights the first order down for better energy localisation over a wider area
+    #[default]
+    MaxRe,
+}
+
+/// Decodes first-order AmbiX to a speaker layout by sampling at each speaker.
+/// Works best on regular layouts; planar layouts use a 2D decode.
+pub struct AmbisonicDecoder {
+    matrix: Vec<[f32; 4]>,
+}
+
+impl AmbisonicDecoder {
+    pub fn new(layout: &SpeakerLayout, decoding: AmbisonicDecoding) -> Self {
+        let planar = layout.is_planar();
+        let order_gain = match (planar, decoding) {
+            (true, AmbisonicDecoding::Basic) => 2.0,
+            (true, AmbisonicDecoding::MaxRe) => 2.0 * std::f32::consts::FRAC_1_SQRT_2,
+            (false, AmbisonicDecoding::Basic) => 3.0,
+            (false, AmbisonicDecoding::MaxRe) => 3.0 * (1.0 / 3.0f32).sqrt(),
+        };
+        let scale = 1.0 / layout.speakers.len().max(1) as f32;
+        let matrix = layout.speakers.iter()
+            .map(|speaker| {
+                let direction = speaker.direction();
+                let height = if planar { 0.0 } else { direction.z };
+                [
+                    scale,
+                    scale * order_gain * direction.y,
+                    scale * order_gain * height,
+                    scale * order_gain * direction.x,
+                ]
+            })
+            .collect();
+        Self { matrix }
+    }
+
+    /// Gain of one speaker for a B-format frame
+    pub fn decode(&self, speaker: usize, frame: &[f32]) -> f32 {
+        self.matrix[speaker].iter().zip(frame).map(|(weight, sample)| weight * sample).sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SpatialMethod {
+    #[default]
+    Vbap,
+    Ambisonics(AmbisonicDecoding),
+}
+
+/// Handle to a spatialized source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SpatialSourceId(u64);
+
+struct SourceShared {
+    /// Listener-relative position in metres, audio axes
+    position: [AtomicU32; 3],
+    gain: AtomicU32,
+    reference_distance: AtomicU32,
+}
+
+/// Moves one source from any thread
+#[derive(Clone)]
+pub struct SpatialSourceControl(Arc<SourceShared>);
+
+impl SpatialSourceControl {
+    fn new() -> Self {
+        Self(Arc::new(SourceShared {
+            position: [AtomicU32::new(1.0f32.to_bits()), AtomicU32::new(0), AtomicU32::new(0)],
+            gain: AtomicU32::new(1.0f32.to_bits()),
+            reference_distance: AtomicU32::new(1.0f32.to_bits()),
+        }))
+    }
+
+    /// Listener-relative position in metres, audio axes
+    pub fn set_position(&self, position: Vec3) {
+        for (axis, value) in self.0.position.iter().zip(position.to_array()) {
+            axis.store(value.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn position(&self) -> Vec3 {
+        let read = |axis: &AtomicU32| f32::from_bits(axis.load(Ordering::Relaxed));
+        Vec3::new(read(&self.0.position[0]), read(&self.0.position[1]), read(&self.0.position[2]))
+    }
+
+    /// Place the source on the unit sphere
+    pub fn set_direction(&self, azimuth: f32, elevation: f32) {
+        self.set_position(direction_from_angles(azimuth, elevation));
+    }
+
+    pub fn set_gain(&self, gain: f32) {
+        self.0.gain.store(gain.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn gain(&self) -> f32 {
+        f32::from_bits(self.0.gain.load(Ordering::Relaxed))
+    }
+
+    /// Distance inside which the source plays at full gain; beyond it, level falls as 1/distance
+    pub fn set_reference_distance(&self, distance: f32) {
+        self.0.reference_distance.store(distance.max(1e-3).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn reference_distance(&self) -> f32 {
+        f32::from_bits(self.0.reference_distance.load(Ordering::Relaxed))
+    }
+}
+
+struct SourceState {
+    source: Box<dyn AudioProcessor>,
+    shared: Arc<SourceShared>,
+    buffer: Vec<f32>,
+    /// Gains reached at the end of the last block, ramped from each block
+    gains: [f32; MAX_SPEAKERS],
+    encoding: [f32; 4],
+}
+
+struct SpatialRenderer {
+    method: SpatialMethod,
+    vbap: Vbap,
+    decoder: AmbisonicDecoder,
+    channels: Vec<usize>,
+}
+
+impl SpatialRenderer {
+    fn new(layout: &SpeakerLayout, method: SpatialMethod) -> Result<Self, VjError> {
+        let decoding = match method {
+            SpatialMethod::Ambisonics(decoding) => decoding,
+            SpatialMethod::Vbap => AmbisonicDecoding::default(),
+        };
+        Ok(Self {
+            method,
+            vbap: Vbap::new(layout)?,
+            decoder: AmbisonicDecoder::new(layout, decoding),
+            channels: layout.speakers.iter().map(|speaker| speaker.channel).collect(),
+        })
+    }
+}
+
+enum SpatialCommand {
+    AddSource(usize, Box<SourceState>),
+    RemoveSource(usize),
+    SetRenderer(Box<SpatialRenderer>),
+}
+
+/// Objects released by the audio thread, dropped on the Bevy side
+#[allow(dead_code)]
+enum SpatialGarbage {
+    Source(Box<SourceState>),
+    Renderer(Box<SpatialRenderer>),
+}
+
+/// Renders every source to the speaker channels of the engine output
+pub struct SpatialProcessor {
+    sources: Vec<Option<Box<SourceState>>>,
+    renderer: Box<SpatialRenderer>,
+    targets: [f32; MAX_SPEAKERS],
+    bformat: Vec<f32>,
+    commands: Consumer<SpatialCommand>,
+    garbage: Producer<SpatialGarbage>,
+}
+
+impl SpatialProcessor {
+    fn apply_commands(&mut self) {
+        while let Ok(command) = self.commands.pop() {
+            match command {
+                SpatialCommand::AddSource(slot, source) => {
+                    if let Some(previous) = self.sources[slot].replace(source) {
+                        let _ = self.garbage.push(SpatialGarbage::Source(previous));
+                    }
+                }
+                SpatialCommand::RemoveSource(slot) => {
+                    if let Some(source) = self.sources[slot].take() {
+                        let _ = self.garbage.push(SpatialGarbage::Source(source));
+                    }
+                }
+                SpatialCommand::SetRenderer(renderer) => {
+                    let previous = std::mem::replace(&mut self.renderer, renderer);
+                    let _ = self.garbage.push(SpatialGarbage::Renderer(previous));
+                }
+            }
+        }
+    }
+}
+
+impl AudioProcessor for SpatialProcessor {
+    fn process(&mut self, context: &ProcessContext, input: &[f32], output: &mut [f32]) {
+        self.apply_commands();
+
+        let Self { sources, renderer, targets, bformat, .. } = self;
+        let frames = context.frames.min(MAX_BLOCK_FRAMES);
+        let mono = ProcessContext { channels: 1, frames, ..*context };
+        let speakers = renderer.channels.len();
+        let ramp = 1.0 / frames.max(1) as f32;
+        bformat[..frames * 4].fill(0.0);
+
+        for source in sources.iter_mut().flatten() {
+            let buffer = &mut source.buffer[..frames];
+            buffer.fill(0.0);
+            source.source.process(&mono, input, buffer);
+
+            let position = Vec3::from_array(source.shared.position.each_ref().map(|axis| f32::from_bits(axis.load(Ordering::Relaxed))));
+            let reference = f32::from_bits(source.shared.reference_distance.load(Ordering::Relaxed));
+            let distance = position.length();
+            let level = f32::from_bits(source.shared.gain.load(Ordering::Relaxed)) * reference / distance.max(reference);
+            let direction = if distance > 1e-6 { position / distance } else { Vec3::X };
+
+            match renderer.method {
+                SpatialMethod::Vbap => {
+                    renderer.vbap.gains(direction, &mut targets[..speakers]);
+                    let speaker_gains = targets[..speakers].iter().zip(source.gains.iter_mut()).zip(&renderer.channels);
+                    for ((&gain, current), &channel) in speaker_gains {
+                        let target = gain * level;
+                        let start = std::mem::replace(current, target);
+                        if channel >= context.channels || (start == 0.0 && target == 0.0) {
+                            continue;
+                        }
+                        let step = (target - start) * ramp;
+                        for (frame, &sample) in buffer.iter().enumerate() {
+                            output[frame * context.channels + channel] += sample * (start + step * (frame + 1) as f32);
+                        }
+                    }
+                }
+                SpatialMethod::Ambisonics(_) => {
+                    let target = encode_first_order(direction).map(|gain| gain * level);
+                    for component in 0..4 {
+                        let start = source.encoding[component];
+                        let step = (target[component] - start) * ramp;
+                        for (frame, &sample) in buffer.iter().enumerate() {
+                            bformat[frame * 4 + component] += sample * (start + step * (frame + 1) as f32);
+                        }
+                    }
+                    source.encoding = target;
+                }
+            }
+        }
+
+        if let SpatialMethod::Ambisonics(_) = renderer.method {
+            for (frame, samples) in bformat[..frames * 4].chunks_exact(4).enumerate() {
+                for (speaker, &channel) in renderer.channels.iter().enumerate() {
+                    if channel < context.channels {
+                        output[frame * context.channels + channel] += renderer.decoder.decode(speaker, samples);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct SourceEntry {
+    slot: usize,
+    name: String,
+    control: SpatialSourceControl,
+}
+
+/// Spatializer running in the audio engine
+#[derive(Resource)]
+pub struct Spatializer {
+    sources: HashMap<SpatialSourceId, SourceEntry>,
+    free_slots: Vec<usize>,
+    next_id: u64,
+    layout: SpeakerLayout,
+    method: SpatialMethod,
+    commands: Mutex<Producer<SpatialCommand>>,
+    garbage: Mutex<Consumer<SpatialGarbage>>,
+    processor_id: Option<ProcessorId>,
+}
+
+impl Spatializer {
+    /// Create a spatializer for `layout` and the processor that runs it
+    pub fn new(layout: SpeakerLayout, method: SpatialMethod) -> Result<(Self, SpatialProcessor), VjError> {
+        let renderer = SpatialRenderer::new(&layout, method)?;
+        let (commands, command_consumer) = RingBuffer::new(256);
+        let (garbage_producer, garbage) = RingBuffer::new(MAX_SPATIAL_SOURCES * 2 + 64);
+
+        let processor = SpatialProcessor {
+            sources: (0..MAX_SPATIAL_SOURCES).map(|_| None).collect(),
+            renderer: Box::new(renderer),
+            targets: [0.0; MAX_SPEAKERS],
+            bformat: vec![0.0; MAX_BLOCK_FRAMES * 4],
+            commands: command_consumer,
+            garbage: garbage_producer,
+        };
+        let spatializer = Self {
+            sources: HashMap::new(),
+            free_slots: (0..MAX_SPATIAL_SOURCES).rev().collect(),
+            next_id: 0,
+            layout,
+            method,
+            commands: Mutex::new(commands),
+            garbage: Mutex::new(garbage),
+            processor_id: None,
+        };
+        Ok((spatializer, processor))
+    }
+
+    /// Create a spatializer and add it to the engine
+    pub fn start(engine: &mut AudioEngine, layout: SpeakerLayout, method: SpatialMethod) -> Result<Self, VjError> {
+        if layout.channel_count() > engine.channels() {
+            warn!("⚠️ Speaker layout '{}' needs {} channels, device has {}", layout.name, layout.channel_count(), engine.channels());
+        }
+        let (mut spatializer, processor) = Self::new(layout, method)?;
+        spatializer.processor_id = Some(engine.add_processor(Box::new(processor))?);
+        Ok(spatializer)
+    }
+
+    pub fn processor_id(&self) -> Option<ProcessorId> {
+        self.processor_id
+    }
+
+    fn send(&self, command: SpatialCommand) -> Result<(), VjError> {
+        let mut commands = self.commands.lock()
+            .map_err(|_| VjError::AudioError("Spatializer command queue poisoned".to_string()))?;
+        commands.push(command)
+            .map_err(|_| VjError::AudioError("Spatializer command queue full".to_string()))
+    }
+
+    /// Drop sources and renderers the audio thread has released
+    pub fn collect_garbage(&self) {
+        if let Ok(mut garbage) = self.garbage.lock() {
+            while garbage.pop().is_ok() {}
+        }
+    }
+
+    /// Add a source rendering mono audio, placed one metre in front
+    pub fn add_source(&mut self, name: &str, source: Box<dyn AudioProcessor>) -> Result<SpatialSourceId, VjError> {
+        self.collect_garbage();
+        let slot = self.free_slots.pop()
+            .ok_or_else(|| VjError::AudioError(format!("Spatializer is full ({} sources)", MAX_SPATIAL_SOURCES)))?;
+
+        let control = SpatialSourceControl::new();
+        let state = SourceState {
+            source,
+            shared: control.0.clone(),
+            buffer: vec![0.0; MAX_BLOCK_FRAMES],
+            gains: [0.0; MAX_SPEAKERS],
+            encoding: [0.0; 4],
+        };
+        if let Err(e) = self.send(SpatialCommand::AddSource(slot, Box::new(state))) {
+            self.free_slots.push(slot);
+            return Err(e);
+        }
+
+        self.next_id += 1;
+        let id = SpatialSourceId(self.next_id);
+        self.sources.insert(id, SourceEntry { slot, name: name.to_string(), control });
+        Ok(id)
+    }
+
+    pub fn remove_source(&mut self, id: SpatialSourceId) -> Result<(), VjError> {
+        let entry = self.sources.remove(&id)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown spatial source {:?}", id)))?;
+        self.send(SpatialCommand::RemoveSource(entry.slot))?;
+        self.free_slots.push(entry.slot);
+        Ok(())
+    }
+
+    pub fn control(&self, id: SpatialSourceId) -> Option<SpatialSourceControl> {
+        self.sources.get(&id).map(|entry| entry.control.clone())
+    }
+
+    pub fn name(&self, id: SpatialSourceId) -> Option<&str> {
+        self.sources.get(&id).map(|entry| entry.name.as_str())
+    }
+
+    pub fn sources(&self) -> Vec<SpatialSourceId> {
+        let mut sources: Vec<_> = self.sources.keys().copied().collect();
+        sources.sort_by_key(|id| id.0);
+        sources
+    }
+
+    pub fn layout(&self) -> &SpeakerLayout {
+        &self.layout
+    }
+
+    pub fn method(&self) -> SpatialMethod {
+        self.method
+    }
+
+    /// Switch to another speaker layout
+    pub fn set_layout(&mut self, layout: SpeakerLayout) -> Result<(), VjError> {
+        self.send(SpatialCommand::SetRenderer(Box::new(SpatialRenderer::new(&layout, self.method)?)))?;
+        self.layout = layout;
+        Ok(())
+    }
+
+    pub fn set_method(&mut self, method: SpatialMethod) -> Result<(), VjError> {
+        self.send(SpatialCommand::SetRenderer(Box::new(SpatialRenderer::new(&self.layout, method)?)))?;
+        self.method = method;
+        Ok(())
+    }
+}
+
+/// Places a spatial source at this entity's position
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SpatialEmitter {
+    pub source: SpatialSourceId,
+}
+
+/// Entity whose transform is the listening position, usually the camera.
+/// Without one, the listener is at the origin facing -Z.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct SpatialListener;
+
+pub(crate) fn setup_spatializer(world: &mut World) {
+    let layout = world.get_resource::<SpeakerLayout>().cloned();
+    let Some(mut audio) = world.get_non_send_resource_mut::<AudioEngine>() else {
+        warn!("⚠️ No audio engine, spatial audio disabled");
+        return;
+    };
+
+    let layout = layout.unwrap_or_else(|| SpeakerLayout::for_channels(audio.channels()));
+    let name = layout.name.clone();
+    match Spatializer::start(&mut audio, layout, SpatialMethod::Vbap) {
+        Ok(spatializer) => {
+            world.insert_resource(spatializer);
+            info!("🔊 Spatial audio ready ({})", name);
+        }
+        Err(e) => error!("❌ Failed to start spatial audio: {}", e),
+    }
+}
+
+/// Move sources to their emitters' positions relative to the listener
+pub(crate) fn update_spatial_sources(
+    spatializer: Option<Res<Spatializer>>,
+    listeners: Query<&GlobalTransform, With<SpatialListener>>,
+    emitters: Query<(&SpatialEmitter, &GlobalTransform)>,
+) {
+    let Some(spatializer) = spatializer else {
+        return;
+    };
+
+    let listener = listeners.iter().next().map(GlobalTransform::compute_transform).unwrap_or_default();
+    let to_listener = listener.rotation.inverse();
+    for (emitter, transform) in emitters.iter() {
+        if let Some(control) = spatializer.control(emitter.source) {
+            let local = to_listener * (transform.translation() - listener.translation);
+            control.set_position(bevy_to_audio(local));
+        }
+    }
+}
+
+pub(crate) fn collect_spatial_garbage(spatializer: Option<Res<Spatializer>>) {
+    if let Some(spatializer) = spatializer {
+        spatializer.collect_garbage();
+    }
+}
+
+/// Graph node positioning a spatial source, in metres on audio axes
+pub struct SpatialSourceNode {
+    pub id: NodeId,
+    control: SpatialSourceControl,
+}
+
+impl SpatialSourceNode {
+    pub fn new(control: SpatialSourceControl) -> Self {
+        Self { id: NodeId::new(), control }
+    }
+}
+
+impl Node for SpatialSourceNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "SpatialSource"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![
+            InputPort::optional("x", DataType::Float),
+            InputPort::optional("y", DataType::Float),
+            InputPort::optional("z", DataType::Float),
+            InputPort::optional("azimuth", DataType::Float),
+            InputPort::optional("elevation", DataType::Float),
+            InputPort::optional("gain", DataType::Float),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![
+            OutputPort::new("azimuth", DataType::Float),
+            OutputPort::new("elevation", DataType::Float),
+            OutputPort::new("distance", DataType::Float),
+        ]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        let read = |port: &str| inputs.get(port).and_then(Value::as_f64).map(|value| value as f32);
+
+        if let Some(gain) = read("gain") {
+            self.control.set_gain(gain);
+        }
+        let mut position = self.control.position();
+        if read("azimuth").is_some() || read("elevation").is_some() {
+            // Keep the distance, change the direction
+            let distance = position.length().max(1e-3);
+            let (azimuth, elevation) = position_angles(position);
+            position = direction_from_angles(
+                read("azimuth").unwrap_or(azimuth),
+                read("elevation").unwrap_or(elevation),
+            ) * distance;
+        }
+        for (axis, port) in ["x", "y", "z"].into_iter().enumerate() {
+            if let Some(value) = read(port) {
+                position[axis] = value;
+            }
+        }
+        self.control.set_position(position);
+
+        let (azimuth, elevation) = position_angles(position);
+        let mut outputs = HashMap::new();
+        outputs.insert("azimuth".to_string(), serde_json::json!(azimuth));
+        outputs.insert("elevation".to_string(), serde_json::json!(elevation));
+        outputs.insert("distance".to_string(), serde_json::json!(position.length()));
+        Ok(outputs)
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
+
+/// Azimuth and elevation in degrees of a position
+fn position_angles(position: Vec3) -> (f32, f32) {
+    let azimuth = position.y.atan2(position.x).to_degrees();
+    let elevation = position.z.atan2(position.truncate().length()).to_degrees();
+    (azimuth, elevation)
+}
@@ -1,3 +1,986 @@
 // Audio synthesis engines
-pub struct SynthEngine;
-pub struct Oscillator;
\ No newline at end of file
+use anyhow::Result;
+use bevy::prelude::*;
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::audio::{midi_to_frequency, AudioEngine, AudioProcessor, Mixer, ProcessContext, ProcessorId, MAX_BLOCK_FRAMES};
+use crate::core::{AudioBufferData, DataType, InputPort, Node, NodeId, OutputPort, VjError};
+use crate::input::InputEvent;
+
+/// Most voices a synth can be configured for
+pub const MAX_VOICES: usize = 32;
+
+/// Samples between updates of pitch, filter coefficients and LFOs
+const CONTROL_INTERVAL: usize = 16;
+
+/// Length of one wavetable cycle
+pub const WAVETABLE_SIZE: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Waveform {
+    Sine,
+    Saw,
+    Square,
+    Triangle,
+    Noise,
+    /// The synth's wavetable, scanned by `SynthPatch::wavetable_position`
+    Wavetable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OscillatorSettings {
+    pub waveform: Waveform,
+    pub level: f32,
+    pub semitones: f32,
+    pub detune_cents: f32,
+}
+
+/// Envelope times in seconds; `sustain` is a level
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Adsr {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    /// Time to fall from full level to silence
+    pub release: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterMode {
+    LowPass,
+    HighPass,
+    BandPass,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FilterSettings {
+    pub mode: FilterMode,
+    pub cutoff: f32,
+    /// 0.0 to 1.0; self-oscillation is avoided near 1.0
+    pub resonance: f32,
+    /// Octaves of cutoff movement at full filter envelope
+    pub envelope_amount: f32,
+    /// 1.0 moves the cutoff an octave per octave played, relative to middle C
+    pub key_tracking: f32,
+    /// Octaves of cutoff movement at full velocity
+    pub velocity_amount: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LfoTarget {
+    /// Depth in semitones
+    Pitch,
+    /// Depth in octaves
+    Cutoff,
+    /// Depth as a fraction of the level
+    Amplitude,
+    /// Depth as a fraction of the table
+    WavetablePosition,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LfoSettings {
+    pub waveform: Waveform,
+    pub rate_hz: f32,
+    pub depth: f32,
+    pub target: LfoTarget,
+}
+
+/// Complete synth sound. Plain data, so patches can be swapped in the audio callback.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SynthPatch {
+    pub oscillators: [OscillatorSettings; 2],
+    pub wavetable_position: f32,
+    pub amp_envelope: Adsr,
+    pub filter_envelope: Adsr,
+    pub filter: FilterSettings,
+    pub lfos: [LfoSettings; 2],
+    pub max_voices: usize,
+    pub pitch_bend_range: f32,
+    /// 0.0 plays every note at full level, 1.0 scales level by velocity
+    pub velocity_sensitivity: f32,
+    /// Octaves of cutoff movement at full aftertouch
+    pub aftertouch_cutoff: f32,
+    /// Level boost at full aftertouch
+    pub aftertouch_level: f32,
+    /// Depth added to the first LFO at full mod wheel
+    pub mod_wheel_depth: f32,
+    pub volume: f32,
+}
+
+impl Default for SynthPatch {
+    fn default() -> Self {
+        Self {
+            oscillators: [
+                OscillatorSettings { waveform: Waveform::Saw, level: 0.5, semitones: 0.0, detune_cents: -7.0 },
+                OscillatorSettings { waveform: Waveform::Saw, level: 0.5, semitones: 0.0, detune_cents: 7.0 },
+            ],
+            wavetable_position: 0.0,
+            amp_envelope: Adsr { attack: 0.005, decay: 0.2, sustain: 0.7, release: 0.3 },
+            filter_envelope: Adsr { attack: 0.005, decay: 0.3, sustain: 0.2, release: 0.3 },
+            filter: FilterSettings {
+                mode: FilterMode::LowPass,
+                cutoff: 1200.0,
+                resonance: 0.3,
+                envelope_amount: 2.0,
+                key_tracking: 0.5,
+                velocity_amount: 1.0,
+            },
+            lfos: [
+                LfoSettings { waveform: Waveform::Sine, rate_hz: 5.0, depth: 0.0, target: LfoTarget::Pitch },
+                LfoSettings { waveform: Waveform::Triangle, rate_hz: 0.2, depth: 0.0, target: LfoTarget::Cutoff },
+            ],
+            max_voices: 8,
+            pitch_bend_range: 2.0,
+            velocity_sensitivity: 0.7,
+            aftertouch_cutoff: 1.0,
+            aftertouch_level: 0.2,
+            mod_wheel_depth: 0.5,
+            volume: 0.3,
+        }
+    }
+}
+
+/// Single-cycle waveforms scanned from first to last
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Wavetable {
+    frames: Vec<Vec<f32>>,
+}
+
+impl Wavetable {
+    /// Build from single cycles of any length; each is resampled to `WAVETABLE_SIZE`
+    pub fn new(cycles: Vec<Vec<f32>>) -> Result<Self, VjError> {
+        if cycles.is_empty() || cycles.iter().any(Vec::is_empty) {
+            return Err(VjError::AudioError("Wavetable needs at least one non-empty cycle".to_string()));
+        }
+
+        let frames = cycles.iter()
+            .map(|cycle| {
+                (0..WAVETABLE_SIZE)
+                    .map(|i| {
+                        let position = i as f32 * cycle.len() as f32 / WAVETABLE_SIZE as f32;
+                        let index = position as usize;
+                        let t = position - index as f32;
+                        cycle[index] * (1.0 - t) + cycle[(index + 1) % cycle.len()] * t
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(Self { frames })
+    }
+
+    /// Sine, triangle, saw and square, each band-limited to 64 harmonics
+    pub fn basic() -> Self {
+        let additive = |harmonic_level: fn(usize) -> f32| -> Vec<f32> {
+            (0..WAVETABLE_SIZE)
+                .map(|i| {
+                    let phase = TAU * i as f32 / WAVETABLE_SIZE as f32;
+                    (1..=64).map(|h| harmonic_level(h) * (phase * h as f32).sin()).sum()
+                })
+                .collect()
+        };
+
+        let frames = vec![
+            additive(|h| if h == 1 { 1.0 } else { 0.0 }),
+            additive(|h| if h % 2 == 1 { 8.0 / (std::f32::consts::PI * h as f32).powi(2) * if h % 4 == 1 { 1.0 } else { -1.0 } } else { 0.0 }),
+            additive(|h| 2.0 / (std::f32::consts::PI * h as f32) * if h % 2 == 1 { 1.0 } else { -1.0 }),
+            additive(|h| if h % 2 == 1 { 4.0 / (std::f32::consts::PI * h as f32) } else { 0.0 }),
+        ];
+        Self { frames }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Sample at a phase (0 to 1) and table position (0 to 1), interpolating both
+    pub fn sample(&self, phase: f32, position: f32) -> f32 {
+        let frame_position = position.clamp(0.0, 1.0) * (self.frames.len() - 1) as f32;
+        let frame = (frame_position as usize).min(self.frames.len() - 1);
+        let next_frame = (frame + 1).min(self.frames.len() - 1);
+        let frame_t = frame_position - frame as f32;
+
+        let index_position = phase * WAVETABLE_SIZE as f32;
+        let index = (index_position as usize) % WAVETABLE_SIZE;
+        let next_index = (index + 1) % WAVETABLE_SIZE;
+        let t = index_position - index_position.floor();
+
+        let read = |table: &[f32]| table[index] * (1.0 - t) + table[next_index] * t;
+        read(&self.frames[frame]) * (1.0 - frame_t) + read(&self.frames[next_frame]) * frame_t
+    }
+}
+
+/// Polynomial correction for the discontinuity of a naive saw or square
+fn poly_blep(phase: f32, increment: f32) -> f32 {
+    if phase < increment {
+        let t = phase / increment;
+        2.0 * t - t * t - 1.0
+    } else if phase > 1.0 - increment {
+        let t = (phase - 1.0) / increment;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// xorshift32 noise in -1..1
+fn next_noise(state: &mut u32) -> f32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+fn oscillator_sample(waveform: Waveform, phase: f32, increment: f32, wavetable: &Wavetable, position: f32, noise: &mut u32) -> f32 {
+    match waveform {
+        Waveform::Sine => (phase * TAU).sin(),
+        Waveform::Saw => 2.0 * phase - 1.0 - poly_blep(phase, increment),
+        Waveform::Square => {
+            let naive = if phase < 0.5 { 1.0 } else { -1.0 };
+            naive + poly_blep(phase, increment) - poly_blep((phase + 0.5).fract(), increment)
+        }
+        Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        Waveform::Noise => next_noise(noise),
+        Waveform::Wavetable => wavetable.sample(phase, position),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Linear ADSR; a retrigger attacks from the current level instead of jumping to zero
+#[derive(Debug, Clone, Copy)]
+struct Envelope {
+    stage: Stage,
+    level: f32,
+}
+
+impl Envelope {
+    const IDLE: Self = Self { stage: Stage::Idle, level: 0.0 };
+
+    fn gate_on(&mut self) {
+        self.stage = Stage::Attack;
+    }
+
+    fn gate_off(&mut self) {
+        if self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+        }
+    }
+
+    fn next(&mut self, adsr: &Adsr, dt: f32) -> f32 {
+        match self.stage {
+            Stage::Idle => {}
+            Stage::Attack => {
+                self.level += dt / adsr.attack.max(1e-4);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                let sustain = adsr.sustain.clamp(0.0, 1.0);
+                self.level -= dt * (1.0 - sustain) / adsr.decay.max(1e-4);
+                if self.level <= sustain {
+                    self.level = sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = adsr.sustain.clamp(0.0, 1.0),
+            Stage::Release => {
+                self.level -= dt / adsr.release.max(1e-4);
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+        self.level
+    }
+}
+
+/// Topology-preserving state-variable filter
+#[derive(Debug, Clone, Copy, Default)]
+struct StateVariableFilter {
+    ic1: f32,
+    ic2: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+    k: f32,
+}
+
+impl StateVariableFilter {
+    fn set(&mut self, cutoff: f32, resonance: f32, sample_rate: f32) {
+        let g = (std::f32::consts::PI * cutoff / sample_rate).tan();
+        self.k = 2.0 - 2.0 * resonance.clamp(0.0, 0.98);
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+
+    fn process(&mut self, input: f32, mode: FilterMode) -> f32 {
+        let v3 = input - self.ic2;
+        let v1 = self.a1 * self.ic1 + self.a2 * v3;
+        let v2 = self.ic2 + self.a2 * self.ic1 + self.a3 * v3;
+        self.ic1 = 2.0 * v1 - self.ic1;
+        self.ic2 = 2.0 * v2 - self.ic2;
+        match mode {
+            FilterMode::LowPass => v2,
+            FilterMode::BandPass => v1,
+            FilterMode::HighPass => input - self.k * v1 - v2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    note: u8,
+    channel: u8,
+    velocity: f32,
+    pressure: f32,
+    /// Key is down
+    held: bool,
+    /// Key is up but the sustain pedal holds the note
+    sustained: bool,
+    /// Note-on order, for stealing the oldest voice
+    started: u64,
+    phases: [f32; 2],
+    amp: Envelope,
+    filter_envelope: Envelope,
+    filter: StateVariableFilter,
+    noise: u32,
+}
+
+impl Voice {
+    fn new(seed: u32) -> Self {
+        Self {
+            note: 0,
+            channel: 0,
+            velocity: 0.0,
+            pressure: 0.0,
+            held: false,
+            sustained: false,
+            started: 0,
+            phases: [0.0; 2],
+            amp: Envelope::IDLE,
+            filter_envelope: Envelope::IDLE,
+            filter: StateVariableFilter::default(),
+            noise: seed.max(1),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.amp.stage != Stage::Idle
+    }
+
+    fn release(&mut self) {
+        self.held = false;
+        self.sustained = false;
+        self.amp.gate_off();
+        self.filter_envelope.gate_off();
+    }
+}
+
+/// Per-MIDI-channel controller state
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelState {
+    /// -1.0 to 1.0
+    pitch_bend: f32,
+    pressure: f32,
+    mod_wheel: f32,
+    sustain: bool,
+}
+
+/// Live-adjustable patch values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SynthParam {
+    Cutoff,
+    Resonance,
+    WavetablePosition,
+    Volume,
+}
+
+/// Polyphonic subtractive and wavetable synth voice engine
+pub struct Synth {
+    patch: SynthPatch,
+    wavetable: Wavetable,
+    sample_rate: f32,
+    voices: Vec<Voice>,
+    channels: [ChannelState; 16],
+    lfo_phases: [f32; 2],
+    /// Current value of sample-and-hold LFOs
+    lfo_held: [f32; 2],
+    lfo_noise: u32,
+    next_start: u64,
+}
+
+impl Synth {
+    pub fn new(sample_rate: f32, patch: SynthPatch, wavetable: Option<Wavetable>) -> Self {
+        Self {
+            patch,
+            wavetable: wavetable.unwrap_or_else(Wavetable::basic),
+            sample_rate,
+            voices: (0..MAX_VOICES as u32).map(|i| Voice::new(0x9E37_79B9u32.wrapping_mul(i + 1))).collect(),
+            channels: [ChannelState::default(); 16],
+            lfo_phases: [0.0; 2],
+            lfo_held: [0.0; 2],
+            lfo_noise: 0x1234_5678,
+            next_start: 0,
+        }
+    }
+
+    pub fn patch(&self) -> &SynthPatch {
+        &self.patch
+    }
+
+    pub fn set_patch(&mut self, patch: SynthPatch) {
+        self.patch = patch;
+    }
+
+    pub fn set_param(&mut self, param: SynthParam, value: f32) {
+        match param {
+            SynthParam::Cutoff => self.patch.filter.cutoff = value,
+            SynthParam::Resonance => self.patch.filter.resonance = value,
+            SynthParam::WavetablePosition => self.patch.wavetable_position = value,
+            SynthParam::Volume => self.patch.volume = value,
+        }
+    }
+
+    fn voice_limit(&self) -> usize {
+        self.patch.max_voices.clamp(1, MAX_VOICES)
+    }
+
+    /// Start a note, stealing a voice when all are busy. Velocity 0 releases the note.
+    pub fn note_on(&mut self, channel: u8, note: u8, velocity: u8) {
+        if velocity == 0 {
+            self.note_off(channel, note);
+            return;
+        }
+
+        let limit = self.voice_limit();
+        let voices = &self.voices[..limit];
+        // Retrigger the same key, else take a free voice, else steal: released voices
+        // first, quietest first, then the oldest held voice
+        let index = voices.iter().position(|v| v.is_active() && v.channel == channel && v.note == note)
+            .or_else(|| voices.iter().position(|v| !v.is_active()))
+            .or_else(|| {
+                voices.iter().enumerate()
+                    .filter(|(_, v)| !v.held && !v.sustained)
+                    .min_by(|(_, a), (_, b)| a.amp.level.total_cmp(&b.amp.level))
+                    .map(|(index, _)| index)
+            })
+            .or_else(|| voices.iter().enumerate().min_by_key(|(_, v)| v.started).map(|(index, _)| index))
+            .unwrap_or(0);
+
+        let voice = &mut self.voices[index];
+        if !voice.is_active() {
+            voice.phases = [0.0; 2];
+            voice.filter = StateVariableFilter::default();
+        }
+        voice.note = note;
+        voice.channel = channel & 0x0F;
+        voice.velocity = velocity as f32 / 127.0;
+        voice.pressure = 0.0;
+        voice.held = true;
+        voice.sustained = false;
+        voice.started = self.next_start;
+        voice.amp.gate_on();
+        voice.filter_envelope.gate_on();
+        self.next_start += 1;
+    }
+
+    pub fn note_off(&mut self, channel: u8, note: u8) {
+        let sustain = self.channels[(channel & 0x0F) as usize].sustain;
+        for voice in self.voices.iter_mut().filter(|v| v.held && v.channel == channel & 0x0F && v.note == note) {
+            if sustain {
+                voice.held = false;
+                voice.sustained = true;
+            } else {
+                voice.release();
+            }
+        }
+    }
+
+    /// 14-bit pitch bend, 8192 is centre
+    pub fn pitch_bend(&mut self, channel: u8, value: u16) {
+        self.channels[(channel & 0x0F) as usize].pitch_bend = ((value.min(16383) as f32 - 8192.0) / 8192.0).max(-1.0);
+    }
+
+    pub fn channel_pressure(&mut self, channel: u8, pressure: u8) {
+        self.channels[(channel & 0x0F) as usize].pressure = pressure as f32 / 127.0;
+    }
+
+    pub fn poly_pressure(&mut self, channel: u8, note: u8, pressure: u8) {
+        for voice in self.voices.iter_mut().filter(|v| v.is_active() && v.channel == channel & 0x0F && v.note == note) {
+            voice.pressure = pressure as f32 / 127.0;
+        }
+    }
+
+    /// Mod wheel (1), sustain pedal (64), all sound off (120) and all notes off (123)
+    pub fn control_change(&mut self, channel: u8, controller: u8, value: u8) {
+        let channel = channel & 0x0F;
+        let state = &mut self.channels[channel as usize];
+        match controller {
+            1 => state.mod_wheel = value as f32 / 127.0,
+            64 => {
+                state.sustain = value >= 64;
+                if !state.sustain {
+                    for voice in self.voices.iter_mut().filter(|v| v.sustained && v.channel == channel) {
+                        voice.release();
+                    }
+                }
+            }
+            120 => {
+                for voice in self.voices.iter_mut().filter(|v| v.channel == channel) {
+                    *voice = Voice::new(voice.noise);
+                }
+            }
+            123 => self.all_notes_off(),
+            _ => {}
+        }
+    }
+
+    /// Release every note, ignoring the sustain pedal
+    pub fn all_notes_off(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.release();
+        }
+    }
+
+    pub fn active_voices(&self) -> usize {
+        self.voices.iter().filter(|v| v.is_active()).count()
+    }
+
+    /// Notes currently sounding, including released notes still fading
+    pub fn active_notes(&self) -> Vec<u8> {
+        self.voices.iter().filter(|v| v.is_active()).map(|v| v.note).collect()
+    }
+
+    /// Render mono output, overwriting `output`
+    pub fn render(&mut self, output: &mut [f32]) {
+        output.fill(0.0);
+        let dt = 1.0 / self.sample_rate;
+        let patch = self.patch;
+        let nyquist_limit = self.sample_rate * 0.45;
+
+        for block in output.chunks_mut(CONTROL_INTERVAL) {
+            let block_secs = block.len() as f32 * dt;
+            let mut lfo_values = [0.0; 2];
+            for (i, lfo) in patch.lfos.iter().enumerate() {
+                let phase = self.lfo_phases[i];
+                lfo_values[i] = match lfo.waveform {
+                    // Sample and hold: a new random value each cycle
+                    Waveform::Noise => {
+                        if phase + lfo.rate_hz * block_secs >= 1.0 {
+                            self.lfo_held[i] = next_noise(&mut self.lfo_noise);
+                        }
+                        self.lfo_held[i]
+                    }
+                    waveform => oscillator_sample(waveform, phase, 0.0, &self.wavetable, 0.0, &mut self.lfo_noise),
+                };
+                self.lfo_phases[i] = (phase + lfo.rate_hz * block_secs).fract();
+            }
+
+            for voice in self.voices.iter_mut().filter(|v| v.is_active()) {
+                let channel = self.channels[voice.channel as usize];
+                let pressure = voice.pressure.max(channel.pressure);
+                let mut modulation = [0.0f32; 4];
+                for (i, lfo) in patch.lfos.iter().enumerate() {
+                    let depth = if i == 0 { lfo.depth + channel.mod_wheel * patch.mod_wheel_depth } else { lfo.depth };
+                    modulation[lfo.target as usize] += lfo_values[i] * depth;
+                }
+
+                let pitch = voice.note as f32
+                    + channel.pitch_bend * patch.pitch_bend_range
+                    + modulation[LfoTarget::Pitch as usize];
+                let base_frequency = midi_to_frequency(pitch);
+                let mut increments = [0.0; 2];
+                for (increment, oscillator) in increments.iter_mut().zip(patch.oscillators.iter()) {
+                    let offset = oscillator.semitones + oscillator.detune_cents / 100.0;
+                    *increment = (base_frequency * 2f32.powf(offset / 12.0) / self.sample_rate).min(0.5);
+                }
+                let table_position = patch.wavetable_position + modulation[LfoTarget::WavetablePosition as usize];
+
+                let filter_octaves = patch.filter.envelope_amount * voice.filter_envelope.level
+                    + patch.filter.key_tracking * (voice.note as f32 - 60.0) / 12.0
+                    + patch.filter.velocity_amount * voice.velocity
+                    + patch.aftertouch_cutoff * pressure
+                    + modulation[LfoTarget::Cutoff as usize];
+                let cutoff = (patch.filter.cutoff * 2f32.powf(filter_octaves)).clamp(20.0, nyquist_limit);
+                voice.filter.set(cutoff, patch.filter.resonance, self.sample_rate);
+
+                let level = patch.volume
+                    * (1.0 - patch.velocity_sensitivity + patch.velocity_sensitivity * voice.velocity)
+                    * (1.0 + modulation[LfoTarget::Amplitude as usize]).max(0.0)
+                    * (1.0 + patch.aftertouch_level * pressure);
+
+                for sample in block.iter_mut() {
+                    let mut mixed = 0.0;
+                    for (i, oscillator) in patch.oscillators.iter().enumerate() {
+                        if oscillator.level != 0.0 {
+                            let phase = voice.phases[i];
+                            mixed += oscillator.level * oscillator_sample(
+                                oscillator.waveform, phase, increments[i], &self.wavetable, table_position, &mut voice.noise,
+                            );
+                        }
+                        voice.phases[i] = (voice.phases[i] + increments[i]).fract();
+                    }
+
+                    voice.filter_envelope.next(&patch.filter_envelope, dt);
+                    let amp = voice.amp.next(&patch.amp_envelope, dt);
+                    *sample += voice.filter.process(mixed, patch.filter.mode) * amp * level;
+                }
+            }
+        }
+    }
+}
+
+enum SynthCommand {
+    NoteOn(u8, u8, u8),
+    NoteOff(u8, u8),
+    PitchBend(u8, u16),
+    ChannelPressure(u8, u8),
+    PolyPressure(u8, u8, u8),
+    ControlChange(u8, u8, u8),
+    AllNotesOff,
+    SetPatch(Box<SynthPatch>),
+    SetParam(SynthParam, f32),
+}
+
+/// Runs a `Synth` inside the audio callback
+pub struct SynthProcessor {
+    synth: Synth,
+    commands: Consumer<SynthCommand>,
+    /// Boxes from `SetPatch` go back to be freed off the audio thread
+    released: Producer<Box<SynthPatch>>,
+    tap: Producer<f32>,
+    active_voices: Arc<AtomicUsize>,
+    scratch: Vec<f32>,
+}
+
+impl AudioProcessor for SynthProcessor {
+    fn process(&mut self, context: &ProcessContext, _input: &[f32], output: &mut [f32]) {
+        while let Ok(command) = self.commands.pop() {
+            match command {
+                SynthCommand::NoteOn(channel, note, velocity) => self.synth.note_on(channel, note, velocity),
+                SynthCommand::NoteOff(channel, note) => self.synth.note_off(channel, note),
+                SynthCommand::PitchBend(channel, value) => self.synth.pitch_bend(channel, value),
+                SynthCommand::ChannelPressure(channel, pressure) => self.synth.channel_pressure(channel, pressure),
+                SynthCommand::PolyPressure(channel, note, pressure) => self.synth.poly_pressure(channel, note, pressure),
+                SynthCommand::ControlChange(channel, controller, value) => self.synth.control_change(channel, controller, value),
+                SynthCommand::AllNotesOff => self.synth.all_notes_off(),
+                SynthCommand::SetPatch(patch) => {
+                    self.synth.set_patch(*patch);
+                    let _ = self.released.push(patch);
+                }
+                SynthCommand::SetParam(param, value) => self.synth.set_param(param, value),
+            }
+        }
+
+        let frames = context.frames.min(self.scratch.len());
+        let mono = &mut self.scratch[..frames];
+        self.synth.render(mono);
+        for (frame, &sample) in output.chunks_mut(context.channels).zip(mono.iter()) {
+            for channel_sample in frame.iter_mut() {
+                *channel_sample += sample;
+            }
+            let _ = self.tap.push(sample);
+        }
+        self.active_voices.store(self.synth.active_voices(), Ordering::Relaxed);
+    }
+}
+
+/// Handle to the synth running in the audio engine
+#[derive(Resource, Clone)]
+pub struct Synthesizer {
+    commands: Arc<Mutex<Producer<SynthCommand>>>,
+    released: Arc<Mutex<Consumer<Box<SynthPatch>>>>,
+    tap: Arc<Mutex<Consumer<f32>>>,
+    active_voices: Arc<AtomicUsize>,
+    sample_rate: f32,
+    processor_id: Option<ProcessorId>,
+    /// MIDI channel the synth plays from; `None` listens to all channels
+    pub midi_channel: Option<u8>,
+}
+
+impl Synthesizer {
+    /// Create a synth handle and its processor
+    pub fn new(sample_rate: f32, patch: SynthPatch, wavetable: Option<Wavetable>) -> (Self, SynthProcessor) {
+        let (commands, command_consumer) = RingBuffer::new(1024);
+        let (released_producer, released) = RingBuffer::new(64);
+        let (tap_producer, tap) = RingBuffer::new(sample_rate.max(1.0) as usize);
+        let active_voices = Arc::new(AtomicUsize::new(0));
+
+        let processor = SynthProcessor {
+            synth: Synth::new(sample_rate, patch, wavetable),
+            commands: command_consumer,
+            released: released_producer,
+            tap: tap_producer,
+            active_voices: active_voices.clone(),
+            scratch: vec![0.0; MAX_BLOCK_FRAMES],
+        };
+        let synthesizer = Self {
+            commands: Arc::new(Mutex::new(commands)),
+            released: Arc::new(Mutex::new(released)),
+            tap: Arc::new(Mutex::new(tap)),
+            active_voices,
+            sample_rate,
+            processor_id: None,
+            midi_channel: None,
+        };
+        (synthesizer, processor)
+    }
+
+    /// Create a synth and add it to the engine
+    pub fn start(engine: &mut AudioEngine, patch: SynthPatch, wavetable: Option<Wavetable>) -> Result<Self, VjError> {
+        let (mut synthesizer, processor) = Self::new(engine.sample_rate(), patch, wavetable);
+        synthesizer.processor_id = Some(engine.add_processor(Box::new(processor))?);
+        Ok(synthesizer)
+    }
+
+    pub fn processor_id(&self) -> Option<ProcessorId> {
+        self.processor_id
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn send(&self, command: SynthCommand) -> Result<(), VjError> {
+        let mut commands = self.commands.lock()
+            .map_err(|_| VjError::AudioError("Synth command queue poisoned".to_string()))?;
+        commands.push(command)
+            .map_err(|_| VjError::AudioError("Synth command queue full".to_string()))
+    }
+
+    pub fn note_on(&self, channel: u8, note: u8, velocity: u8) -> Result<(), VjError> {
+        self.send(SynthCommand::NoteOn(channel, note, velocity))
+    }
+
+    pub fn note_off(&self, channel: u8, note: u8) -> Result<(), VjError> {
+        self.send(SynthCommand::NoteOff(channel, note))
+    }
+
+    pub fn pitch_bend(&self, channel: u8, value: u16) -> Result<(), VjError> {
+        self.send(SynthCommand::PitchBend(channel, value))
+    }
+
+    pub fn channel_pressure(&self, channel: u8, pressure: u8) -> Result<(), VjError> {
+        self.send(SynthCommand::ChannelPressure(channel, pressure))
+    }
+
+    pub fn poly_pressure(&self, channel: u8, note: u8, pressure: u8) -> Result<(), VjError> {
+        self.send(SynthCommand::PolyPressure(channel, note, pressure))
+    }
+
+    pub fn control_change(&self, channel: u8, controller: u8, value: u8) -> Result<(), VjError> {
+        self.send(SynthCommand::ControlChange(channel, controller, value))
+    }
+
+    pub fn all_notes_off(&self) -> Result<(), VjError> {
+        self.send(SynthCommand::AllNotesOff)
+    }
+
+    pub fn set_patch(&self, patch: SynthPatch) -> Result<(), VjError> {
+        if let Ok(mut released) = self.released.lock() {
+            while released.pop().is_ok() {}
+        }
+        self.send(SynthCommand::SetPatch(Box::new(patch)))
+    }
+
+    pub fn set_param(&self, param: SynthParam, value: f32) -> Result<(), VjError> {
+        self.send(SynthCommand::SetParam(param, value))
+    }
+
+    pub fn active_voices(&self) -> usize {
+        self.active_voices.load(Ordering::Relaxed)
+    }
+
+    /// Drain the synth's mono output since the last call
+    pub fn read_output(&self, buffer: &mut Vec<f32>) {
+        if let Ok(mut tap) = self.tap.lock() {
+            while let Ok(sample) = tap.pop() {
+                buffer.push(sample);
+            }
+        }
+    }
+
+    /// Play a MIDI input event; other events are ignored
+    pub fn handle_input(&self, event: &InputEvent) -> Result<(), VjError> {
+        let listens = |channel: &u8| self.midi_channel.is_none_or(|wanted| wanted == *channel);
+        match *event {
+            InputEvent::MidiNoteOn { channel, note, velocity } if listens(&channel) => self.note_on(channel, note, velocity),
+            InputEvent::MidiNoteOff { channel, note } if listens(&channel) => self.note_off(channel, note),
+            InputEvent::MidiControlChange { channel, controller, value } if listens(&channel) => {
+                self.control_change(channel, controller, value)
+            }
+            InputEvent::MidiPitchBend { channel, value } if listens(&channel) => self.pitch_bend(channel, value),
+            InputEvent::MidiAftertouch { channel, pressure } if listens(&channel) => self.channel_pressure(channel, pressure),
+            InputEvent::MidiPolyAftertouch { channel, note, pressure } if listens(&channel) => {
+                self.poly_pressure(channel, note, pressure)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+pub(crate) fn setup_synthesizer(world: &mut World) {
+    let patch = world.get_resource::<SynthPatch>().copied().unwrap_or_default();
+    let Some(sample_rate) = world.get_non_send_resource::<AudioEngine>().map(|audio| audio.sample_rate()) else {
+        warn!("⚠️ No audio engine, synthesizer disabled");
+        return;
+    };
+
+    // Play through a mixer strip when there is a mixer, straight into the engine otherwise
+    let started = match world.get_resource_mut::<Mixer>() {
+        Some(mut mixer) => {
+            let (synthesizer, processor) = Synthesizer::new(sample_rate, patch, None);
+            mixer.add_strip("Synth", Box::new(processor)).map(|_| synthesizer)
+        }
+        None => {
+            let mut audio = world.get_non_send_resource_mut::<AudioEngine>().expect("audio engine exists");
+            Synthesizer::start(&mut audio, patch, None)
+        }
+    };
+
+    match started {
+        Ok(synthesizer) => {
+            world.insert_resource(synthesizer);
+            info!("🎛️ Audio synthesis system ready");
+        }
+        Err(e) => error!("❌ Failed to start synthesizer: {}", e),
+    }
+}
+
+/// Play MIDI input on the synth
+pub(crate) fn play_midi_input(
+    mut input_events: MessageReader<InputEvent>,
+    synthesizer: Option<Res<Synthesizer>>,
+) {
+    let Some(synthesizer) = synthesizer else {
+        input_events.clear();
+        return;
+    };
+
+    for event in input_events.read() {
+        if let Err(e) = synthesizer.handle_input(event) {
+            warn!("⚠️ {}", e);
+        }
+    }
+}
+
+/// Send edits of the patch resource to the synth
+pub(crate) fn sync_synth_patch(patch: Res<SynthPatch>, synthesizer: Option<Res<Synthesizer>>) {
+    let Some(synthesizer) = synthesizer else {
+        return;
+    };
+    if patch.is_changed() && !patch.is_added() {
+        if let Err(e) = synthesizer.set_patch(*patch) {
+            warn!("⚠️ {}", e);
+        }
+    }
+}
+
+/// Graph node playing the synth from a note and gate
+pub struct SynthNode {
+    pub id: NodeId,
+    pub channel: u8,
+    synthesizer: Synthesizer,
+    playing: Option<u8>,
+}
+
+impl SynthNode {
+    pub fn new(synthesizer: Synthesizer) -> Self {
+        Self {
+            id: NodeId::new(),
+            channel: 0,
+            synthesizer,
+            playing: None,
+        }
+    }
+}
+
+impl Node for SynthNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "Synth"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        vec![
+            InputPort::optional("note", DataType::Float),
+            InputPort::optional("gate", DataType::Boolean),
+            InputPort::optional("velocity", DataType::Float),
+            InputPort::optional("pitch_bend", DataType::Float),
+            InputPort::optional("cutoff", DataType::Float),
+            InputPort::optional("resonance", DataType::Float),
+        ]
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![
+            OutputPort::new("audio", DataType::AudioBuffer),
+            OutputPort::new("voices", DataType::Float),
+        ]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        let synth = &self.synthesizer;
+        let note = inputs.get("note").and_then(Value::as_f64).map(|note| note.round().clamp(0.0, 127.0) as u8);
+        let gate = inputs.get("gate").and_then(Value::as_bool).unwrap_or(false);
+        let velocity = inputs.get("velocity").and_then(Value::as_f64).unwrap_or(0.8);
+
+        let wanted = note.filter(|_| gate);
+        if wanted != self.playing {
+            if let Some(previous) = self.playing {
+                synth.note_off(self.channel, previous)?;
+            }
+            if let Some(note) = wanted {
+                synth.note_on(self.channel, note, (velocity.clamp(0.0, 1.0) * 127.0).round().max(1.0) as u8)?;
+            }
+            self.playing = wanted;
+        }
+
+        if let Some(bend) = inputs.get("pitch_bend").and_then(Value::as_f64) {
+            synth.pitch_bend(self.channel, (8192.0 + bend.clamp(-1.0, 1.0) * 8191.0).round() as u16)?;
+        }
+        if let Some(cutoff) = inputs.get("cutoff").and_then(Value::as_f64) {
+            synth.set_param(SynthParam::Cutoff, cutoff as f32)?;
+        }
+        if let Some(resonance) = inputs.get("resonance").and_then(Value::as_f64) {
+            synth.set_param(SynthParam::Resonance, resonance as f32)?;
+        }
+
+        let mut samples = Vec::new();
+        synth.read_output(&mut samples);
+        let audio = AudioBufferData::new(samples, 1, synth.sample_rate());
+
+        let mut outputs = HashMap::new();
+        outputs.insert("audio".to_string(), audio.to_value());
+        outputs.insert("voices".to_string(), serde_json::json!(synth.active_voices()));
+        Ok(outputs)
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
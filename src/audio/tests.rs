@@ -0,0 +1,1737 @@
+#[cfg(test)]
+mod tests {
+    use crate::audio::*;
+    use crate::core::{AudioEventType, Node};
+    use std::time::{Duration, Instant};
+
+    /// Adds a constant to every output sample
+    struct ConstantProcessor(f32);
+
+    impl AudioProcessor for ConstantProcessor {
+        fn process(&mut self, _context: &ProcessContext, _input: &[f32], output: &mut [f32]) {
+            for sample in output.iter_mut() {
+                *sample += self.0;
+            }
+        }
+    }
+
+    fn loopback_settings() -> AudioSettings {
+        AudioSettings {
+            backend: AudioBackend::Loopback,
+            buffer_size: 64,
+            sample_rate: 48000.0,
+            ..Default::default()
+        }
+    }
+
+    /// Poll `read` until `predicate` holds or a second passes
+    fn wait_for(engine: &mut AudioEngine, mut predicate: impl FnMut(&[f32]) -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let mut samples = Vec::new();
+        while Instant::now() < deadline {
+            samples.clear();
+            engine.read_input(&mut samples);
+            if !samples.is_empty() && predicate(&samples) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    #[test]
+    fn test_loopback_engine_renders_processors() {
+        let mut engine = AudioEngine::start(&loopback_settings()).unwrap();
+        assert_eq!(engine.backend(), AudioBackend::Loopback);
+
+        let events = engine.poll();
+        assert!(matches!(events.as_slice(), [AudioEventType::DeviceConnected { .. }]));
+
+        let id = engine.add_processor(Box::new(ConstantProcessor(0.25))).unwrap();
+        assert!(wait_for(&mut engine, |samples| samples.iter().any(|&s| (s - 0.25).abs() < 1e-6)));
+
+        engine.set_master_volume(2.0);
+        assert!(wait_for(&mut engine, |samples| samples.iter().any(|&s| (s - 0.5).abs() < 1e-6)));
+
+        engine.remove_processor(id).unwrap();
+        assert!(wait_for(&mut engine, |samples| samples.iter().all(|&s| s == 0.0)));
+        assert!(engine.frame_position() > 0);
+    }
+
+    #[test]
+    fn test_playback_underrun_is_reported() {
+        let mut engine = AudioEngine::start(&loopback_settings()).unwrap();
+        engine.poll();
+
+        // Fewer samples than one block: the render thread runs dry mid-block
+        assert_eq!(engine.write_output(&[0.1; 10]), 10);
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let mut events = Vec::new();
+        while events.is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+            events = engine.poll();
+        }
+        assert!(events.iter().any(|event| matches!(event, AudioEventType::BufferUnderrun)));
+    }
+
+    #[test]
+    fn test_glicol_parameters_are_located() {
+        let code = "o: sin $freq >> mul $amp\n~lfo: sin 0.5\n    >> mul 0.3 $depth // wobble";
+        let parameters = find_glicol_parameters(code);
+
+        assert_eq!(parameters.len(), 3);
+        assert_eq!((parameters[0].chain.as_str(), parameters[0].node_index, parameters[0].param_index), ("o", 0, 0));
+        assert_eq!((parameters[1].chain.as_str(), parameters[1].node_index, parameters[1].param_index), ("o", 1, 0));
+        assert_eq!((parameters[2].chain.as_str(), parameters[2].node_index, parameters[2].param_index), ("~lfo", 1, 1));
+
+        let mut values = std::collections::HashMap::new();
+        values.insert("freq".to_string(), 220.0);
+        assert_eq!(substitute_glicol_parameters("o: sin $freq >> mul $amp", &values), "o: sin 220 >> mul 0");
+    }
+
+    #[test]
+    fn test_glicol_lone_dollar_is_not_a_parameter() {
+        let code = "o: sin $ >> mul $, $amp >> add $$";
+        let parameters = find_glicol_parameters(code);
+
+        assert_eq!(parameters.len(), 1);
+        assert_eq!(parameters[0].name, "amp");
+
+        let mut values = std::collections::HashMap::new();
+        values.insert("amp".to_string(), 0.5);
+        assert_eq!(substitute_glicol_parameters(code, &values), "o: sin $ >> mul $, 0.5 >> add $$");
+        assert_eq!(substitute_glicol_parameters("$", &values), "$");
+    }
+
+    #[test]
+    fn test_glicol_error_position_is_parsed() {
+        let mut status = [0u8; 256];
+        status[0] = 1;
+        let message = b" --> 2:7\n  |\n2 | o: sin >> \n  |       ^---\n  |\n  = expected number";
+        status[2..2 + message.len()].copy_from_slice(message);
+
+        let error = GlicolError::from_status(&status);
+        assert_eq!(error.line, Some(2));
+        assert_eq!(error.column, Some(7));
+        assert!(error.message.starts_with("Parse error"));
+    }
+
+    fn sine(frequency: f32, amplitude: f32, sample_rate: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_spectrum_reads_sine_amplitude_in_its_band() {
+        let sample_rate = 48000.0;
+        let mut analyzer = SpectrumAnalyzer::new(2048, 0.5, sample_rate);
+        assert_eq!(analyzer.hop(), 1024);
+
+        // 1500 Hz sits exactly on bin 64
+        let frames = analyzer.push(&sine(1500.0, 0.5, sample_rate, 4096));
+        assert_eq!(frames, 3);
+
+        let magnitudes = analyzer.magnitudes();
+        let peak_bin = (0..magnitudes.len()).max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b])).unwrap();
+        assert_eq!(peak_bin, 64);
+        assert!((magnitudes[64] - 0.5).abs() < 0.01, "peak magnitude {}", magnitudes[64]);
+
+        let bands = analyzer.band_levels();
+        assert!((bands.mid - 0.5).abs() < 0.02, "mid band {}", bands.mid);
+        assert!(bands.sub < 0.01 && bands.bass < 0.01 && bands.high < 0.01);
+    }
+
+    #[test]
+    fn test_level_meter_ballistics() {
+        let settings = AnalysisSettings::default();
+        let sample_rate = 48000.0;
+        let mut analyzer = AudioAnalyzer::new(settings.clone(), sample_rate);
+
+        // Stereo: sine on the left, silence on the right
+        let left = sine(1000.0, 0.8, sample_rate, 48000);
+        let interleaved: Vec<f32> = left.iter().flat_map(|&s| [s, 0.0]).collect();
+        analyzer.process(&interleaved, 2);
+
+        let frame = analyzer.frame().clone();
+        assert!((frame.peak[0] - 0.8).abs() < 0.01);
+        assert!((frame.rms[0] - 0.8 / 2f32.sqrt()).abs() < 0.02, "rms {}", frame.rms[0]);
+        assert_eq!(frame.peak[1], 0.0);
+
+        // Peak falls 20 dB over the release time
+        let silence = vec![0.0; (settings.peak_release_secs * sample_rate) as usize * 2];
+        analyzer.process(&silence, 2);
+        let released = analyzer.frame().peak[0];
+        assert!((released / frame.peak[0] - 0.1).abs() < 0.01, "released to {}", released);
+    }
+
+    #[test]
+    fn test_analysis_node_outputs_bands() {
+        let mut node = AudioAnalysisNode::new();
+        node.settings.fft_size = 1024;
+        let audio = crate::core::AudioBufferData::new(sine(100.0, 1.0, 44100.0, 4096), 1, 44100.0);
+
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("audio".to_string(), audio.to_value());
+        let outputs = node.process(inputs).unwrap();
+
+        let bass = outputs["bass"].as_f64().unwrap();
+        let high = outputs["high"].as_f64().unwrap();
+        assert!(bass > 0.5, "bass {}", bass);
+        assert!(high < 0.05, "high {}", high);
+        assert_eq!(outputs["spectrum"].as_array().unwrap().len(), 513);
+        assert!((outputs["peak"].as_f64().unwrap() - 1.0).abs() < 0.01);
+    }
+
+    /// Click track: a decaying noise burst on every beat
+    fn click_track(bpm: f32, sample_rate: f32, seconds: f32) -> Vec<f32> {
+        let mut samples = vec![0.0; (seconds * sample_rate) as usize];
+        let period = 60.0 / bpm * sample_rate;
+        let burst = (0.01 * sample_rate) as usize;
+        let mut seed = 0x1234_5678u32;
+
+        let mut beat = 0.0;
+        while (beat as usize) < samples.len() {
+            for i in 0..burst.min(samples.len() - beat as usize) {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let noise = (seed >> 8) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0;
+                samples[beat as usize + i] = noise * (-(i as f32) / (burst as f32 * 0.3)).exp();
+            }
+            beat += period;
+        }
+        samples
+    }
+
+    #[test]
+    fn test_tempo_of_click_tracks() {
+        let sample_rate = 44100.0;
+        for bpm in [90.0, 120.0, 128.0, 174.0] {
+            let mut detector = BeatDetector::new(sample_rate);
+            detector.process(&click_track(bpm, sample_rate, 10.0));
+
+            let tempo = detector.tempo().expect("tempo after 10 seconds");
+            assert!((tempo.bpm - bpm).abs() < 1.5, "expected {} BPM, got {}", bpm, tempo.bpm);
+            assert!(tempo.confidence > 0.5, "confidence {} at {} BPM", tempo.confidence, bpm);
+        }
+    }
+
+    #[test]
+    fn test_onsets_and_predicted_beats_follow_clicks() {
+        let sample_rate = 44100.0;
+        let bpm = 120.0;
+        let track = click_track(bpm, sample_rate, 12.0);
+        let mut detector = BeatDetector::new(sample_rate);
+
+        let mut onsets = 0;
+        let mut beat_times = Vec::new();
+        for (block, chunk) in track.chunks(512).enumerate() {
+            let update = detector.process(chunk);
+            onsets += update.onsets;
+            if update.beats > 0 {
+                beat_times.push((block + 1) as f32 * 512.0 / sample_rate);
+            }
+        }
+
+        // One onset per click
+        assert!((22..=24).contains(&onsets), "{} onsets", onsets);
+
+        // Beats are reported once per period, close to the clicks
+        let late_beats: Vec<f32> = beat_times.into_iter().filter(|&t| t > 6.0).collect();
+        assert!(late_beats.len() >= 10, "{} beats after 6 s", late_beats.len());
+        for time in late_beats {
+            let offset = (time * bpm / 60.0).fract();
+            let distance = offset.min(1.0 - offset) * 60.0 / bpm;
+            assert!(distance < 0.05, "beat at {:.3}s is {:.3}s from a click", time, distance);
+        }
+    }
+
+    #[test]
+    fn test_silence_has_no_tempo() {
+        let mut detector = BeatDetector::new(44100.0);
+        let update = detector.process(&vec![0.0; 44100 * 5]);
+        assert_eq!(update.onsets, 0);
+        assert!(detector.tempo().is_none());
+    }
+
+    #[test]
+    fn test_mel_scale_and_filterbank() {
+        assert!((hz_to_mel(700.0) - 781.173).abs() < 1e-2);
+        assert!((mel_to_hz(hz_to_mel(1234.5)) - 1234.5).abs() < 1e-2);
+
+        let filterbank = mel_filterbank(16000.0, 512, 26, 0.0, 8000.0);
+        assert_eq!(filterbank.len(), 26);
+        assert_eq!(filterbank[0].len(), 257);
+
+        // Overlapping triangles sum to one between the first and last centres
+        let first_centre = mel_to_hz(hz_to_mel(8000.0) / 27.0);
+        let last_centre = mel_to_hz(hz_to_mel(8000.0) * 26.0 / 27.0);
+        for bin in 0..257 {
+            let hz = bin as f32 * 16000.0 / 512.0;
+            if hz > first_centre && hz < last_centre {
+                let sum: f32 = filterbank.iter().map(|filter| filter[bin]).sum();
+                assert!((sum - 1.0).abs() < 1e-4, "weights at {} Hz sum to {}", hz, sum);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dct_lifter_and_deltas() {
+        // A constant input has only a DC coefficient
+        let dct = dct_ii(&[2.0; 8], 4);
+        assert!((dct[0] - 2.0 * 8f32.sqrt()).abs() < 1e-5);
+        assert!(dct[1..].iter().all(|c| c.abs() < 1e-5));
+
+        // scipy.fft.dct([1, 2, 3, 4], type=2, norm="ortho")
+        let dct = dct_ii(&[1.0, 2.0, 3.0, 4.0], 4);
+        for (value, expected) in dct.iter().zip([5.0, -2.2304425, 0.0, -0.15851267]) {
+            assert!((value - expected).abs() < 1e-5, "dct {:?}", dct);
+        }
+
+        let mut coefficients = vec![1.0; 4];
+        lifter(&mut coefficients, 22);
+        assert_eq!(coefficients[0], 1.0);
+        assert!((coefficients[1] - (1.0 + 11.0 * (std::f32::consts::PI / 22.0).sin())).abs() < 1e-5);
+
+        // A linear ramp has unit slope away from the edges
+        let ramp: Vec<Vec<f32>> = (0..10).map(|t| vec![t as f32]).collect();
+        let slope = deltas(&ramp, 2);
+        assert!(slope[2..8].iter().all(|frame| (frame[0] - 1.0).abs() < 1e-6));
+        assert!(slope[0][0] < 1.0);
+    }
+
+    /// Reference values from tests/fixtures/mfcc_reference.py, an mpmath script written
+    /// from the HTK, librosa (`htk=True, norm=None`), scipy DCT and Kaldi lifter definitions
+    #[test]
+    fn test_mfcc_matches_reference() {
+        let mut seed = 12345u32;
+        let samples: Vec<f32> = (0..512)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let noise = (seed >> 8) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0;
+                0.5 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 8000.0).sin() + 0.1 * noise
+            })
+            .collect();
+
+        let mut extractor = MelExtractor::new(MelConfig {
+            sample_rate: 8000.0,
+            n_fft: 256,
+            hop_length: 128,
+            n_mels: 20,
+            n_mfcc: 13,
+            ..Default::default()
+        });
+
+        let mel = extractor.mel_spectrogram(&samples);
+        assert_eq!(mel.len(), 3);
+        let expected_mel = [
+            1.4962499, 0.64181727, 0.18955722, 0.79131564, 975.46838, 571.28006, 0.57672813, 1.4166717, 0.78713896, 0.55718129,
+            2.0505279, 1.8410705, 1.9715842, 2.0712866, 4.2429511, 4.7398683, 3.3425946, 3.3716597, 2.7953849, 3.5136357,
+        ];
+        assert_eq!(mel[0].len(), expected_mel.len());
+        for (value, expected) in mel[0].iter().zip(expected_mel) {
+            assert!((value - expected).abs() < expected * 1e-3 + 1e-3, "mel {} vs {}", value, expected);
+        }
+
+        let mfcc = extractor.mfcc(&samples);
+        let expected_mfcc = [
+            [4.630984, -0.02448287, 1.447758, -13.14768, -30.42002, -20.22147, 3.99935, 32.35147, 46.47078, 28.99986, 11.24001, -12.28713, -31.20897],
+            [3.653087, -1.396109, 2.138045, -22.93279, -33.32452, -22.54675, -0.359448, 28.29875, 34.99564, 29.9879, 2.24574, -30.31996, -21.8623],
+        ];
+        for (frame, expected) in [0, 2].into_iter().zip(expected_mfcc) {
+            for (value, expected) in mfcc[frame].iter().zip(expected) {
+                assert!((value - expected).abs() < 0.01, "frame {} mfcc {} vs {}", frame, value, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spectral_centroid_of_sine() {
+        let mut extractor = MelExtractor::new(MelConfig { sample_rate: 8000.0, n_fft: 512, ..Default::default() });
+        let centroid = extractor.spectral_centroid(&sine(1000.0, 1.0, 8000.0, 2048));
+        assert!(centroid.iter().all(|&c| (c - 1000.0).abs() < 20.0), "{:?}", centroid);
+    }
+
+    fn chord(frequencies: &[f32], sample_rate: f32, frames: usize) -> Vec<f32> {
+        let mut samples = vec![0.0; frames];
+        for &frequency in frequencies {
+            for (sample, tone) in samples.iter_mut().zip(sine(frequency, 0.2, sample_rate, frames)) {
+                *sample += tone;
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn test_yin_pitch() {
+        let sample_rate = 44100.0;
+        let config = YinConfig::default();
+
+        let pitch = yin(&sine(220.0, 0.5, sample_rate, 4096), sample_rate, &config).unwrap();
+        assert!((pitch.frequency - 220.0).abs() < 0.5, "{}", pitch.frequency);
+        assert!(pitch.confidence > 0.9);
+        assert_eq!(pitch.midi_note(), 57);
+
+        // Harmonics don't pull the estimate up an octave
+        let rich: Vec<f32> = sine(110.0, 0.5, sample_rate, 4096).iter()
+            .zip(sine(220.0, 0.4, sample_rate, 4096))
+            .zip(sine(330.0, 0.3, sample_rate, 4096))
+            .map(|((a, b), c)| a + b + c)
+            .collect();
+        let pitch = yin(&rich, sample_rate, &config).unwrap();
+        assert!((pitch.frequency - 110.0).abs() < 0.5, "{}", pitch.frequency);
+
+        assert!(yin(&[0.0; 4096], sample_rate, &config).is_none());
+        assert!((midi_to_frequency(69.0) - 440.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_chroma_and_key_of_chords() {
+        let sample_rate = 22050.0;
+        let mut stft = Stft::new(8192, 8192);
+        let c_major = chord(&[261.63, 329.63, 392.0], sample_rate, 8192);
+        let power = stft.power_spectrogram(&c_major).remove(0);
+        let chroma = chroma_from_power(&power, sample_rate, 8192);
+
+        for pitch_class in [0, 4, 7] {
+            assert!(chroma[pitch_class] > 0.5, "pitch class {} = {}", pitch_class, chroma[pitch_class]);
+        }
+        for pitch_class in [1, 3, 6, 8, 10] {
+            assert!(chroma[pitch_class] < 0.1, "pitch class {} = {}", pitch_class, chroma[pitch_class]);
+        }
+
+        let (key, _) = estimate_key(&chroma).unwrap();
+        assert_eq!(key.name(), "C major");
+
+        // A minor: A, C, E with the leading tone G#
+        let mut a_minor = [0.0; 12];
+        for (pitch_class, weight) in [(9, 1.0), (0, 0.8), (4, 0.8), (8, 0.3), (2, 0.3)] {
+            a_minor[pitch_class] = weight;
+        }
+        let (key, _) = estimate_key(&a_minor).unwrap();
+        assert_eq!(key, MusicalKey { tonic: 9, minor: true });
+    }
+
+    #[test]
+    fn test_key_tracker_holds_before_switching() {
+        let mut c_major = [0.0; 12];
+        let mut g_major = [0.0; 12];
+        for pitch_class in [0, 2, 4, 5, 7, 9, 11] {
+            c_major[pitch_class] = if [0, 4, 7].contains(&pitch_class) { 1.0 } else { 0.5 };
+        }
+        for pitch_class in [7, 9, 11, 0, 2, 4, 6] {
+            g_major[pitch_class] = if [7, 11, 2].contains(&pitch_class) { 1.0 } else { 0.5 };
+        }
+
+        let mut tracker = KeyTracker::default();
+        tracker.memory_secs = 1.0;
+        tracker.hold_secs = 2.0;
+        assert!(tracker.update(&c_major, 0.5));
+        for _ in 0..10 {
+            assert!(!tracker.update(&c_major, 0.5));
+        }
+        assert_eq!(tracker.key().unwrap().name(), "C major");
+
+        // The switch happens once, after G major has led for the hold time
+        let mut changes = Vec::new();
+        for step in 0..20 {
+            if tracker.update(&g_major, 0.5) {
+                changes.push(step);
+            }
+        }
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0] >= 3, "switched after {} steps", changes[0]);
+        assert_eq!(tracker.key().unwrap().name(), "G major");
+    }
+
+    #[test]
+    fn test_pitch_key_node() {
+        let mut node = PitchKeyNode::new();
+        let audio = crate::core::AudioBufferData::new(sine(440.0, 0.5, 44100.0, 8192), 1, 44100.0);
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("audio".to_string(), audio.to_value());
+
+        let outputs = node.process(inputs).unwrap();
+        assert_eq!(outputs["midi_note"], 69);
+        assert_eq!(outputs["chroma"][9], 1.0);
+        assert_eq!(outputs["key_changed"], true);
+        assert!(outputs["key"].is_string());
+    }
+
+    /// Write a mono 16-bit PCM WAV file
+    fn write_wav(path: &std::path::Path, samples: &[f32], sample_rate: u32) {
+        let data_len = samples.len() as u32 * 2;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples {
+            bytes.extend_from_slice(&((sample * 32767.0) as i16).to_le_bytes());
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    fn ramp_clip(frames: usize, sample_rate: f32) -> AudioClip {
+        AudioClip {
+            samples: (0..frames).map(|i| i as f32 / frames as f32).collect(),
+            channels: 1,
+            sample_rate,
+        }
+    }
+
+    /// Run a processor for `frames` stereo frames starting at `frame_position`
+    fn render(processor: &mut FilePlayerProcessor, frame_position: u64, frames: usize) -> Vec<f32> {
+        let context = ProcessContext {
+            sample_rate: 44100.0,
+            channels: 2,
+            input_channels: 0,
+            frames,
+            frame_position,
+        };
+        let mut output = vec![0.0; frames * 2];
+        processor.process(&context, &[], &mut output);
+        output
+    }
+
+    #[test]
+    fn test_audio_clip_load_wav() {
+        let path = std::env::temp_dir().join(format!("nuwe_clip_{}.wav", std::process::id()));
+        let samples: Vec<f32> = (0..2205).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+        write_wav(&path, &samples, 22050);
+
+        let clip = AudioClip::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(clip.channels, 1);
+        assert_eq!(clip.sample_rate, 22050.0);
+        assert_eq!(clip.frames(), 2205);
+        assert!((clip.duration_secs() - 0.1).abs() < 1e-6);
+        assert!((clip.samples[100] - samples[100]).abs() < 1e-3);
+
+        assert!(AudioClip::load(std::path::Path::new("/nonexistent/clip.wav")).is_err());
+    }
+
+    #[test]
+    fn test_file_player_resamples_and_stops() {
+        // A 22.05 kHz clip takes twice as many 44.1 kHz frames
+        let (player, mut processor) = FilePlayer::new(ramp_clip(1000, 22050.0), 44100.0, 2);
+        player.play().unwrap();
+        let output = render(&mut processor, 0, 2500);
+
+        let last = output.chunks(2).rposition(|frame| frame[0] != 0.0).unwrap();
+        assert!((1995..=2001).contains(&last), "played {} frames", last);
+        // The mono clip is mapped to both channels and halfway through is half the ramp
+        assert_eq!(output[1000 * 2], output[1000 * 2 + 1]);
+        assert!((output[1000 * 2] - 0.5).abs() < 0.01);
+        assert!(!player.is_playing());
+
+        let mut tap = Vec::new();
+        player.read_output(&mut tap);
+        assert_eq!(tap.len(), output.len());
+    }
+
+    #[test]
+    fn test_file_player_loop_speed_and_cues() {
+        let (player, mut processor) = FilePlayer::new(ramp_clip(44100, 44100.0), 44100.0, 2);
+        player.set_loop(Some((0.25, 0.5))).unwrap();
+        player.add_cue("drop", 0.25);
+        player.add_cue("intro", 0.0);
+        assert_eq!(player.cues()[0].0, "intro");
+        player.jump_to_cue("drop").unwrap();
+        assert!(player.jump_to_cue("missing").is_err());
+        player.set_speed(2.0).unwrap();
+        player.play().unwrap();
+
+        // A second at double speed covers the quarter-second loop eight times
+        let output = render(&mut processor, 0, 44100);
+        assert!(player.is_playing());
+        let position = player.position_secs();
+        assert!((0.25..0.5).contains(&position), "position {}", position);
+        assert!(output.iter().step_by(2).all(|&sample| (0.24..=0.51).contains(&sample)));
+    }
+
+    #[test]
+    fn test_file_player_scheduled_start() {
+        let (player, mut processor) = FilePlayer::new(ramp_clip(1000, 44100.0), 44100.0, 2);
+        let transport = Transport::default();
+        let start = transport.next_quantized_frame(100, 44100.0, Quantize::Beat);
+        assert_eq!(start, 22050);
+        player.play_at(start).unwrap();
+
+        let output = render(&mut processor, 22000, 100);
+        let first = output.chunks(2).position(|frame| frame[0] != 0.0).unwrap();
+        // The clip's first frame is silent, so sound begins one frame after the start
+        assert_eq!(22000 + first as u64, start + 1);
+    }
+
+    #[test]
+    fn test_transport_grid() {
+        let mut transport = Transport::default();
+        assert_eq!(transport.frames_per_beat(48000.0), 24000.0);
+        assert_eq!(transport.next_quantized_frame(24000, 48000.0, Quantize::Beat), 24000);
+        assert_eq!(transport.next_quantized_frame(24001, 48000.0, Quantize::Bar), 96000);
+        assert_eq!(transport.next_quantized_frame(5, 48000.0, Quantize::Immediate), 5);
+
+        // Changing tempo keeps the beat position at the change
+        transport.set_bpm(60.0, 48000, 48000.0);
+        assert!((transport.beat_at(48000, 48000.0) - 2.0).abs() < 1e-9);
+        assert!((transport.beat_at(96000, 48000.0) - 3.0).abs() < 1e-9);
+    }
+
+    fn temp_recording_dir(name: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("nuwe_{}_{}", name, std::process::id()));
+        std::fs::remove_dir_all(&directory).ok();
+        directory
+    }
+
+    /// Labels and frame offsets from a WAV file's cue and label chunks
+    fn read_cues(path: &std::path::Path) -> Vec<(u32, String)> {
+        let bytes = std::fs::read(path).unwrap();
+        let riff_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        assert_eq!(riff_size + 8, bytes.len());
+
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let mut cues = Vec::new();
+        let mut labels = std::collections::HashMap::new();
+        let mut at = 12;
+        while at + 8 <= bytes.len() {
+            let size = u32_at(at + 4) as usize;
+            match &bytes[at..at + 4] {
+                b"cue " => {
+                    for point in 0..u32_at(at + 8) as usize {
+                        let entry = at + 12 + point * 24;
+                        cues.push((u32_at(entry), u32_at(entry + 20)));
+                    }
+                }
+                b"LIST" => {
+                    let mut sub = at + 12;
+                    while sub < at + 8 + size {
+                        let sub_size = u32_at(sub + 4) as usize;
+                        let text = &bytes[sub + 12..sub + 8 + sub_size - 1];
+                        labels.insert(u32_at(sub + 8), String::from_utf8(text.to_vec()).unwrap());
+                        sub += 8 + sub_size + sub_size % 2;
+                    }
+                }
+                _ => {}
+            }
+            at += 8 + size + size % 2;
+        }
+        cues.into_iter().map(|(id, offset)| (offset, labels[&id].clone())).collect()
+    }
+
+    #[test]
+    fn test_recording_session_rotates_and_marks() {
+        let directory = temp_recording_dir("rotate");
+        let settings = RecorderSettings {
+            directory: directory.clone(),
+            format: RecordFormat::Int16,
+            max_file_secs: Some(0.5),
+            ..Default::default()
+        };
+        let (mut producer, consumer) = rtrb::RingBuffer::new(8000 * 4);
+        let session = RecordingSession::start(&settings, consumer, 2, 8000.0).unwrap();
+
+        session.mark(1000, "intro");
+        session.mark(4500, "drop");
+        session.mark(20000, "late");
+        // 1.25 s of stereo audio spread over three files
+        for frame in 0..10000 {
+            producer.push(frame as f32 / 10000.0).unwrap();
+            producer.push(-0.5).unwrap();
+        }
+        let files = session.finish().unwrap();
+
+        assert_eq!(files.len(), 3);
+        let frames: Vec<u32> = files.iter().map(|path| hound::WavReader::open(path).unwrap().duration()).collect();
+        assert_eq!(frames, vec![4000, 4000, 2000]);
+
+        let reader = hound::WavReader::open(&files[1]).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().bits_per_sample, 16);
+        let first: Vec<i16> = reader.into_samples().take(2).map(Result::unwrap).collect();
+        assert_eq!(first, vec![(0.4 * 32767.0) as i16, -16383]);
+
+        assert_eq!(read_cues(&files[0]), vec![(1000, "intro".to_string())]);
+        assert_eq!(read_cues(&files[1]), vec![(500, "drop".to_string())]);
+        // Markers past the end land on the last frame of the last file
+        assert_eq!(read_cues(&files[2]), vec![(2000, "late".to_string())]);
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn test_master_recording_is_sample_accurate() {
+        let directory = temp_recording_dir("master");
+        let settings = RecorderSettings { directory: directory.clone(), ..Default::default() };
+        let mut engine = AudioEngine::start(&AudioSettings { backend: AudioBackend::Null, ..loopback_settings() }).unwrap();
+        engine.add_processor(Box::new(ConstantProcessor(0.25))).unwrap();
+
+        let transport = Transport { bpm: 600.0, ..Default::default() };
+        let start = transport.next_quantized_frame(engine.frame_position() + 1, 48000.0, Quantize::Beat);
+        let mut recorder = AudioRecorder::default();
+        recorder.start(&mut engine, &settings, Some(start)).unwrap();
+        assert!(recorder.start(&mut engine, &settings, None).is_err());
+        recorder.stop(&engine, Some(start + 2400));
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let files = loop {
+            if let Some(result) = recorder.poll(&mut engine) {
+                break result.unwrap();
+            }
+            assert!(Instant::now() < deadline, "recording did not finish");
+            std::thread::sleep(Duration::from_millis(5));
+        };
+
+        assert!(!recorder.is_recording());
+        assert_eq!(recorder.files(), files);
+        let mut reader = hound::WavReader::open(&files[0]).unwrap();
+        assert_eq!(reader.spec().sample_format, hound::SampleFormat::Float);
+        assert_eq!(reader.duration(), 2400);
+        assert!(reader.samples::<f32>().all(|sample| sample.unwrap() == 0.25));
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn test_recorder_node() {
+        let directory = temp_recording_dir("node");
+        let mut node = RecorderNode::new(RecorderSettings {
+            directory: directory.clone(),
+            ..Default::default()
+        });
+        let audio = crate::core::AudioBufferData::new(vec![0.1; 512], 1, 22050.0);
+
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("audio".to_string(), audio.to_value());
+        inputs.insert("marker".to_string(), serde_json::json!("start"));
+        assert_eq!(node.process(inputs.clone()).unwrap()["recording"], true);
+        node.process(inputs.clone()).unwrap();
+
+        inputs.insert("record".to_string(), serde_json::json!(false));
+        let outputs = node.process(inputs).unwrap();
+        assert_eq!(outputs["recording"], false);
+
+        let path = std::path::PathBuf::from(outputs["file"].as_str().unwrap());
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().sample_rate, 22050);
+        assert_eq!(reader.duration(), 1024);
+        assert_eq!(read_cues(&path), vec![(0, "start".to_string())]);
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    /// One sine oscillator through a wide-open filter, without velocity scaling
+    fn sine_patch() -> SynthPatch {
+        let mut patch = SynthPatch::default();
+        patch.oscillators[0] = OscillatorSettings { waveform: Waveform::Sine, level: 1.0, semitones: 0.0, detune_cents: 0.0 };
+        patch.oscillators[1].level = 0.0;
+        patch.filter.cutoff = 18000.0;
+        patch.filter.resonance = 0.0;
+        patch.filter.envelope_amount = 0.0;
+        patch.filter.key_tracking = 0.0;
+        patch.filter.velocity_amount = 0.0;
+        patch.velocity_sensitivity = 0.0;
+        patch.amp_envelope = Adsr { attack: 0.001, decay: 0.01, sustain: 1.0, release: 0.05 };
+        patch
+    }
+
+    fn render_synth(synth: &mut Synth, frames: usize) -> Vec<f32> {
+        let mut output = vec![0.0; frames];
+        synth.render(&mut output);
+        output
+    }
+
+    #[test]
+    fn test_synth_voice_allocation_and_stealing() {
+        let patch = SynthPatch { max_voices: 3, ..SynthPatch::default() };
+        let mut synth = Synth::new(48000.0, patch, None);
+
+        for note in [60, 64, 67] {
+            synth.note_on(0, note, 100);
+        }
+        render_synth(&mut synth, 256);
+        assert_eq!(synth.active_voices(), 3);
+
+        // All voices held: the oldest is stolen
+        synth.note_on(0, 72, 100);
+        let mut notes = synth.active_notes();
+        notes.sort();
+        assert_eq!(notes, vec![64, 67, 72]);
+
+        // A released voice is stolen before any held one
+        synth.note_off(0, 72);
+        render_synth(&mut synth, 64);
+        synth.note_on(0, 48, 100);
+        let mut notes = synth.active_notes();
+        notes.sort();
+        assert_eq!(notes, vec![48, 64, 67]);
+
+        // Retriggering a sounding key reuses its voice
+        synth.note_on(0, 64, 50);
+        assert_eq!(synth.active_voices(), 3);
+    }
+
+    #[test]
+    fn test_synth_pitch_and_bend() {
+        let sample_rate = 48000.0;
+        let mut synth = Synth::new(sample_rate, sine_patch(), None);
+        synth.note_on(0, 69, 127);
+        let output = render_synth(&mut synth, 8192);
+        let estimate = yin(&output[2048..], sample_rate, &YinConfig::default()).unwrap();
+        assert!((estimate.frequency - 440.0).abs() < 1.0, "{} Hz", estimate.frequency);
+
+        // Full bend up is the bend range, two semitones
+        synth.pitch_bend(0, 16383);
+        let output = render_synth(&mut synth, 8192);
+        let estimate = yin(&output[2048..], sample_rate, &YinConfig::default()).unwrap();
+        assert!((estimate.frequency - 493.88).abs() < 1.5, "{} Hz", estimate.frequency);
+    }
+
+    #[test]
+    fn test_synth_release_and_sustain_pedal() {
+        let mut synth = Synth::new(48000.0, sine_patch(), None);
+        synth.note_on(0, 60, 127);
+        let output = render_synth(&mut synth, 4800);
+        let peak = output.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - 0.3).abs() < 0.01, "peak {}", peak);
+
+        // The pedal keeps the note after key up until it is lifted
+        synth.control_change(0, 64, 127);
+        synth.note_off(0, 60);
+        render_synth(&mut synth, 4800);
+        assert_eq!(synth.active_voices(), 1);
+        synth.control_change(0, 64, 0);
+
+        // 50 ms release
+        let output = render_synth(&mut synth, 4800);
+        assert_eq!(synth.active_voices(), 0);
+        assert!(output[2400..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_synth_filter_and_aftertouch() {
+        // Energy of the sample-to-sample change weights high frequencies
+        let roughness = |samples: &[f32]| samples.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum::<f32>();
+        let mut patch = sine_patch();
+        patch.oscillators[0].waveform = Waveform::Saw;
+        patch.filter.cutoff = 300.0;
+        patch.aftertouch_cutoff = 4.0;
+        patch.aftertouch_level = 0.0;
+
+        let mut synth = Synth::new(48000.0, patch, None);
+        synth.note_on(0, 45, 127);
+        let dark = roughness(&render_synth(&mut synth, 4800)[2400..]);
+        synth.channel_pressure(0, 127);
+        let bright = roughness(&render_synth(&mut synth, 4800)[2400..]);
+        assert!(bright > dark * 2.0, "dark {} bright {}", dark, bright);
+
+        patch.filter.mode = FilterMode::HighPass;
+        patch.filter.cutoff = 10000.0;
+        patch.aftertouch_cutoff = 0.0;
+        synth.set_patch(patch);
+        let thin = render_synth(&mut synth, 4800);
+        let rms = (thin[2400..].iter().map(|s| s * s).sum::<f32>() / 2400.0).sqrt();
+        assert!(rms < 0.03, "high-passed saw rms {}", rms);
+    }
+
+    #[test]
+    fn test_wavetable() {
+        let table = Wavetable::basic();
+        assert_eq!(table.len(), 4);
+        assert!((table.sample(0.25, 0.0) - 1.0).abs() < 1e-3);
+        // The last frame is a square
+        assert!((table.sample(0.25, 1.0) - 1.0).abs() < 0.1);
+        assert!((table.sample(0.75, 1.0) + 1.0).abs() < 0.1);
+
+        let custom = Wavetable::new(vec![vec![0.0, 1.0, 0.0, -1.0]]).unwrap();
+        assert!((custom.sample(0.25, 0.5) - 1.0).abs() < 1e-6);
+        assert!(Wavetable::new(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_synth_node_and_midi_input() {
+        let (synthesizer, mut processor) = Synthesizer::new(44100.0, sine_patch(), None);
+        let mut node = SynthNode::new(synthesizer.clone());
+        let context = ProcessContext { sample_rate: 44100.0, channels: 2, input_channels: 0, frames: 512, frame_position: 0 };
+        let mut output = vec![0.0; 1024];
+
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("note".to_string(), serde_json::json!(57));
+        inputs.insert("gate".to_string(), serde_json::json!(true));
+        node.process(inputs.clone()).unwrap();
+        processor.process(&context, &[], &mut output);
+        assert!(output.iter().any(|&s| s != 0.0));
+        assert_eq!(output[100], output[101]);
+
+        let outputs = node.process(inputs.clone()).unwrap();
+        assert_eq!(outputs["voices"], 1);
+        let audio = crate::core::AudioBufferData::from_value(&outputs["audio"], 0.0).unwrap();
+        assert_eq!(audio.frames(), 512);
+
+        // MIDI on another channel is ignored when the synth listens to one channel
+        let mut synthesizer = synthesizer;
+        synthesizer.midi_channel = Some(1);
+        synthesizer.handle_input(&crate::input::InputEvent::MidiNoteOn { channel: 0, note: 60, velocity: 100 }).unwrap();
+        synthesizer.handle_input(&crate::input::InputEvent::MidiNoteOn { channel: 1, note: 64, velocity: 100 }).unwrap();
+        processor.process(&context, &[], &mut output);
+        assert_eq!(synthesizer.active_voices(), 2);
+    }
+
+    /// Run a graph processor over `frames` frames with a mono input signal
+    fn run_graph(processor: &mut AudioGraphProcessor, input: &[f32], frames: usize, channels: usize) -> Vec<f32> {
+        let context = ProcessContext { sample_rate: 48000.0, channels, input_channels: 1, frames, frame_position: 0 };
+        let input = if input.is_empty() { vec![0.0; frames] } else { input.to_vec() };
+        let mut output = vec![0.0; frames * channels];
+        processor.process(&context, &input, &mut output);
+        output
+    }
+
+    #[test]
+    fn test_audio_graph_renders_chain_with_one_block_latency() {
+        let (mut graph, mut processor) = AudioGraph::new(48000.0, 1, 2);
+        let oscillator = graph.add_node(Box::new(OscillatorNode::new(Waveform::Square, 750.0, 1.0))).unwrap();
+        let gain = graph.add_node(Box::new(GainNode::new(0.5))).unwrap();
+        graph.connect(AudioSource::Node(oscillator, 0), AudioSink::Node(gain, 0)).unwrap();
+        graph.connect(AudioSource::Node(gain, 0), AudioSink::GraphOutput(0)).unwrap();
+        assert_eq!(graph.node_name(gain), Some("Gain"));
+
+        let output = run_graph(&mut processor, &[], 256, 2);
+        // The first block is silent, then the square wave at half level on the left only
+        assert!(output[..AUDIO_BLOCK_SIZE * 2].iter().all(|&s| s == 0.0));
+        assert_eq!(output[AUDIO_BLOCK_SIZE * 2], 0.5);
+        assert!(output[AUDIO_BLOCK_SIZE * 2..].chunks(2).all(|frame| frame[0].abs() == 0.5 && frame[1] == 0.0));
+
+        // Graph input passes through with exactly one block of delay
+        let (mut graph, mut processor) = AudioGraph::new(48000.0, 1, 1);
+        graph.connect(AudioSource::GraphInput(0), AudioSink::GraphOutput(0)).unwrap();
+        let input: Vec<f32> = (0..300).map(|i| i as f32 + 1.0).collect();
+        let output = run_graph(&mut processor, &input, 300, 1);
+        assert_eq!(output[AUDIO_BLOCK_SIZE - 1], 0.0);
+        assert_eq!(output[AUDIO_BLOCK_SIZE], 1.0);
+        assert_eq!(output[299], input[299 - AUDIO_BLOCK_SIZE]);
+    }
+
+    #[test]
+    fn test_audio_graph_sums_fan_in_and_rejects_cycles() {
+        let (mut graph, mut processor) = AudioGraph::new(48000.0, 1, 1);
+        let (first, _) = ControlInputNode::new(0.25);
+        let (second, _) = ControlInputNode::new(0.5);
+        let first = graph.add_node(Box::new(first)).unwrap();
+        let second = graph.add_node(Box::new(second)).unwrap();
+        let gain = graph.add_node(Box::new(GainNode::new(2.0))).unwrap();
+        graph.connect(AudioSource::Node(first, 0), AudioSink::Node(gain, 0)).unwrap();
+        graph.connect(AudioSource::Node(second, 0), AudioSink::Node(gain, 0)).unwrap();
+        graph.connect(AudioSource::Node(gain, 0), AudioSink::GraphOutput(0)).unwrap();
+
+        let output = run_graph(&mut processor, &[], 128, 1);
+        assert!((output[127] - 1.5).abs() < 1e-6);
+
+        let other = graph.add_node(Box::new(GainNode::new(1.0))).unwrap();
+        graph.connect(AudioSource::Node(gain, 0), AudioSink::Node(other, 0)).unwrap();
+        assert!(graph.connect(AudioSource::Node(other, 0), AudioSink::Node(gain, 0)).is_err());
+        assert!(graph.connect(AudioSource::Node(gain, 0), AudioSink::Node(gain, 0)).is_err());
+        assert!(graph.connect(AudioSource::Node(first, 1), AudioSink::Node(gain, 0)).is_err());
+        assert!(graph.connect(AudioSource::GraphInput(3), AudioSink::GraphOutput(0)).is_err());
+        assert_eq!(graph.connections().len(), 4);
+
+        // Removing a node drops its connections; the output falls silent
+        graph.remove_node(gain).unwrap();
+        assert_eq!(graph.connections().len(), 0);
+        assert!(graph.set_parameter(gain, "gain", 1.0).is_err());
+        let output = run_graph(&mut processor, &[], 128, 1);
+        assert!(output[AUDIO_BLOCK_SIZE..].iter().all(|&s| s == 0.0));
+        graph.collect_garbage();
+        assert_eq!(graph.node_count(), 3);
+    }
+
+    #[test]
+    fn test_audio_graph_control_and_probe_bridges() {
+        let (mut graph, mut processor) = AudioGraph::new(48000.0, 1, 1);
+        let (control, sender) = ControlInputNode::new(0.0);
+        let (probe, reader) = ProbeNode::new(48000.0, 4096);
+        let control = graph.add_node(Box::new(control)).unwrap();
+        let oscillator = graph.add_node(Box::new(OscillatorNode::new(Waveform::Sine, 750.0, 1.0))).unwrap();
+        let vca = graph.add_node(Box::new(MultiplyNode)).unwrap();
+        let probe = graph.add_node(Box::new(probe)).unwrap();
+        graph.connect(AudioSource::Node(oscillator, 0), AudioSink::Node(vca, 0)).unwrap();
+        graph.connect(AudioSource::Node(control, 0), AudioSink::Node(vca, 1)).unwrap();
+        graph.connect(AudioSource::Node(vca, 0), AudioSink::Node(probe, 0)).unwrap();
+
+        // The frame graph drives the VCA level through the control bridge
+        let mut control_node = AudioControlNode::new(sender.clone());
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("value".to_string(), serde_json::json!(0.8));
+        control_node.process(inputs).unwrap();
+        assert_eq!(sender.get(), 0.8);
+
+        // The value is smoothed: the first block has not reached it yet
+        run_graph(&mut processor, &[], AUDIO_BLOCK_SIZE, 1);
+        assert!(reader.peak() < 0.5);
+        run_graph(&mut processor, &[], 4800, 1);
+        assert!((reader.peak() - 0.8).abs() < 0.02);
+        assert!((reader.rms() - 0.8 / 2f32.sqrt()).abs() < 0.03);
+
+        let mut probe_node = AudioProbeNode::new(reader.clone());
+        let outputs = probe_node.process(std::collections::HashMap::new()).unwrap();
+        let audio = crate::core::AudioBufferData::from_value(&outputs["audio"], 0.0).unwrap();
+        assert_eq!(audio.frames(), 4096);
+        assert!((outputs["peak"].as_f64().unwrap() - 0.8).abs() < 0.02);
+
+        // Parameters are set by name
+        graph.set_parameter(oscillator, "amplitude", 0.0).unwrap();
+        assert!(graph.set_parameter(oscillator, "detune", 0.0).is_err());
+        run_graph(&mut processor, &[], 128, 1);
+        assert_eq!(reader.peak(), 0.0);
+    }
+
+    /// Render two blocks so fader ramps settle, returning the last stereo frame
+    fn render_mixer(processor: &mut MixerProcessor) -> [f32; 2] {
+        let context = ProcessContext { sample_rate: 48000.0, channels: 2, input_channels: 0, frames: 256, frame_position: 0 };
+        let mut output = vec![0.0; 512];
+        processor.process(&context, &[], &mut output);
+        output.fill(0.0);
+        processor.process(&context, &[], &mut output);
+        [output[510], output[511]]
+    }
+
+    #[test]
+    fn test_mixer_strips_pan_mute_and_solo() {
+        let (mut mixer, mut processor) = Mixer::new(48000.0);
+        let a = mixer.add_strip("A", Box::new(ConstantProcessor(0.25))).unwrap();
+        let b = mixer.add_strip("B", Box::new(ConstantProcessor(0.5))).unwrap();
+        assert_eq!(mixer.strips(), vec![a, b]);
+        assert_eq!(mixer.name(MixerChannel::Strip(b)), Some("B"));
+
+        let output = render_mixer(&mut processor);
+        assert!((output[0] - 0.75).abs() < 1e-6 && (output[1] - 0.75).abs() < 1e-6);
+
+        let strip_a = mixer.control(MixerChannel::Strip(a)).unwrap();
+        strip_a.set_pan(-1.0);
+        strip_a.set_gain(2.0);
+        let output = render_mixer(&mut processor);
+        assert!((output[0] - 1.0).abs() < 1e-6 && (output[1] - 0.5).abs() < 1e-6);
+
+        // Meters read post-fader levels
+        let meter = mixer.meter(MixerChannel::Strip(a)).unwrap();
+        assert!((meter.peak[0] - 0.5).abs() < 1e-3 && meter.peak[1] < 0.25);
+        assert!((mixer.meter(MixerChannel::Master).unwrap().peak[0] - 1.0).abs() < 1e-3);
+
+        let strip_b = mixer.control(MixerChannel::Strip(b)).unwrap();
+        strip_b.set_solo(true);
+        let output = render_mixer(&mut processor);
+        assert!((output[0] - 0.5).abs() < 1e-6 && (output[1] - 0.5).abs() < 1e-6);
+
+        strip_b.set_mute(true);
+        assert_eq!(render_mixer(&mut processor), [0.0, 0.0]);
+
+        strip_b.set_solo(false);
+        strip_b.set_mute(false);
+        mixer.control(MixerChannel::Master).unwrap().set_gain(0.5);
+        mixer.remove_strip(a).unwrap();
+        let output = render_mixer(&mut processor);
+        assert!((output[0] - 0.25).abs() < 1e-6);
+        assert!(mixer.control(MixerChannel::Strip(a)).is_none());
+    }
+
+    #[test]
+    fn test_mixer_sends_groups_and_routing() {
+        let (mut mixer, mut processor) = Mixer::new(48000.0);
+        let strip = mixer.add_strip("Synth", Box::new(ConstantProcessor(0.5))).unwrap();
+        let group = mixer.add_bus("Drums", BusKind::Group).unwrap();
+        let aux = mixer.add_bus("Reverb", BusKind::Aux).unwrap();
+
+        mixer.route_strip(strip, Some(group)).unwrap();
+        mixer.control(MixerChannel::Bus(group)).unwrap().set_gain(0.5);
+        let output = render_mixer(&mut processor);
+        assert!((output[0] - 0.25).abs() < 1e-6);
+
+        // Post-fader sends follow the strip fader, pre-fader sends don't
+        mixer.set_send(strip, aux, 1.0, false).unwrap();
+        mixer.control(MixerChannel::Strip(strip)).unwrap().set_gain(0.0);
+        assert_eq!(render_mixer(&mut processor), [0.0, 0.0]);
+        mixer.set_send(strip, aux, 1.0, true).unwrap();
+        assert_eq!(mixer.sends(strip).len(), 1);
+        let output = render_mixer(&mut processor);
+        assert!((output[0] - 0.5).abs() < 1e-6);
+        assert!((mixer.meter(MixerChannel::Bus(aux)).unwrap().peak_max() - 0.5).abs() < 1e-3);
+
+        // Groups can feed groups, but not in a loop; aux buses only take sends
+        let sub = mixer.add_bus("Sub", BusKind::Group).unwrap();
+        mixer.route_bus(group, Some(sub)).unwrap();
+        assert!(mixer.route_bus(sub, Some(group)).is_err());
+        assert!(mixer.route_bus(sub, Some(sub)).is_err());
+        assert!(mixer.route_strip(strip, Some(aux)).is_err());
+        assert!(mixer.set_send(strip, group, 1.0, true).is_err());
+
+        mixer.control(MixerChannel::Strip(strip)).unwrap().set_gain(1.0);
+        mixer.remove_send(strip, aux).unwrap();
+        let output = render_mixer(&mut processor);
+        assert!((output[0] - 0.25).abs() < 1e-6);
+
+        // Removing a group sends what was routed to it to master
+        mixer.remove_bus(group).unwrap();
+        assert_eq!(mixer.output(MixerChannel::Strip(strip)), None);
+        let output = render_mixer(&mut processor);
+        assert!((output[0] - 0.5).abs() < 1e-6);
+        mixer.collect_garbage();
+    }
+
+    #[test]
+    fn test_mixer_channel_node() {
+        let (mut mixer, mut processor) = Mixer::new(48000.0);
+        let strip = mixer.add_strip("Input", Box::new(ConstantProcessor(0.5))).unwrap();
+        let mut node = MixerChannelNode::new(mixer.control(MixerChannel::Strip(strip)).unwrap());
+
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("gain".to_string(), serde_json::json!(0.5));
+        inputs.insert("pan".to_string(), serde_json::json!(1.0));
+        node.process(inputs).unwrap();
+        render_mixer(&mut processor);
+
+        let outputs = node.process(std::collections::HashMap::new()).unwrap();
+        assert!((outputs["peak"].as_f64().unwrap() - 0.25).abs() < 1e-6);
+        assert_eq!(outputs["peak_left"].as_f64().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_envelope_follower_attack_and_release() {
+        let sample_rate = 48000.0;
+        let mut follower = EnvelopeFollower::new(EnvelopeSettings {
+            attack_secs: 0.001,
+            release_secs: 0.1,
+            mode: EnvelopeMode::Peak,
+        });
+
+        // Reaches the level within a few attack times, then falls by 1/e per release time
+        let level = follower.process(&vec![0.8; 480], sample_rate);
+        assert!((level - 0.8).abs() < 0.01);
+        let level = follower.process(&vec![0.0; 4800], sample_rate);
+        assert!((level - 0.8 / std::f32::consts::E).abs() < 0.01);
+
+        // RMS mode reports a sine's RMS, not its peak
+        let mut rms = EnvelopeFollower::new(EnvelopeSettings {
+            attack_secs: 0.05,
+            release_secs: 0.05,
+            mode: EnvelopeMode::Rms,
+        });
+        let level = rms.process(&sine(440.0, 1.0, sample_rate, 48000), sample_rate);
+        assert!((level - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.05);
+
+        // A band follower hears the kick range but not hats
+        let settings = EnvelopeSettings { release_secs: 0.05, ..Default::default() };
+        let mut kick = BandFollower::new(40.0, 120.0, settings);
+        let low = kick.process(&sine(60.0, 0.5, sample_rate, 9600), sample_rate);
+        kick.follower.reset();
+        let high = kick.process(&sine(8000.0, 0.5, sample_rate, 9600), sample_rate);
+        assert!(low > 0.3, "kick level {low}");
+        assert!(high < 0.02, "hat level {high}");
+
+        let mut bands = BandLevels::default();
+        bands.bass = 0.6;
+        bands.high = 0.9;
+        kick.follower.reset();
+        let level = kick.update_from_bands(&bands, 1.0);
+        assert!((level - 0.6).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_gate_hysteresis_and_ducker() {
+        let mut gate = Gate::new(GateSettings {
+            open_threshold: 0.5,
+            close_threshold: 0.3,
+            hold_secs: 0.02,
+        });
+        assert!(!gate.update(0.4, 0.01));
+        assert!(gate.update(0.6, 0.01));
+        assert!(!gate.update(0.7, 0.01));
+
+        // Between the thresholds it stays open; below, it waits out the hold time
+        assert!(!gate.update(0.4, 0.01));
+        assert!(gate.is_open());
+        gate.update(0.1, 0.01);
+        assert!(gate.is_open());
+        gate.update(0.1, 0.01);
+        assert!(!gate.is_open());
+        assert!(gate.update(0.6, 0.01));
+
+        let mut ducker = Ducker::new(DuckerSettings {
+            threshold: 0.1,
+            depth: 0.8,
+            attack_secs: 0.0,
+            release_secs: 0.1,
+        });
+        assert_eq!(ducker.update(0.05, 0.01), 1.0);
+        let ducked = ducker.update(1.0, 0.01);
+        assert!((ducked - (1.0 - 0.8 * 0.9)).abs() < 1e-4);
+        for _ in 0..100 {
+            ducker.update(0.0, 0.01);
+        }
+        assert!(ducker.value() > 0.99);
+
+        // Triggers pump fully and recover over the release
+        ducker.trigger();
+        assert!((ducker.update(0.0, 0.0) - 0.2).abs() < 1e-4);
+        let recovering = ducker.update(0.0, 0.1);
+        assert!(recovering > 0.2 && recovering < 1.0);
+        assert!((0.0..=1.0).contains(&ducker.ducking()));
+    }
+
+    #[test]
+    fn test_envelope_nodes() {
+        let audio = crate::core::AudioBufferData::new(vec![0.5; 4800], 1, 48000.0).to_value();
+        let mut follower = EnvelopeFollowerNode::new(EnvelopeSettings::default());
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("audio".to_string(), audio.clone());
+        let outputs = follower.process(inputs).unwrap();
+        assert!((outputs["envelope"].as_f64().unwrap() - 0.5).abs() < 0.01);
+        assert!(!follower.is_cacheable());
+
+        let mut gate = GateNode::new(GateSettings::default());
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("level".to_string(), serde_json::json!(0.5));
+        let outputs = gate.process(inputs.clone()).unwrap();
+        assert_eq!(outputs["open"], serde_json::json!(true));
+        assert_eq!(outputs["trigger"], serde_json::json!(true));
+        let outputs = gate.process(inputs).unwrap();
+        assert_eq!(outputs["trigger"], serde_json::json!(false));
+
+        let mut ducker = DuckerNode::new(DuckerSettings::default());
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("sidechain".to_string(), audio);
+        let outputs = ducker.process(inputs).unwrap();
+        let duck = outputs["duck"].as_f64().unwrap();
+        assert!(duck < 0.5 && duck >= 0.0);
+        assert!((duck + 0.8 * outputs["amount"].as_f64().unwrap() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_speaker_layouts_load_and_validate() {
+        let assets = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/speaker_layouts");
+        let dome = SpeakerLayout::load(&assets.join("dome.ron")).unwrap();
+        assert_eq!(dome.speakers.len(), 13);
+        assert_eq!(dome.channel_count(), 13);
+        assert!(!dome.is_planar());
+        for (loaded, built) in dome.speakers.iter().zip(&SpeakerLayout::dome().speakers) {
+            assert!(loaded.direction().distance(built.direction()) < 1e-5);
+            assert_eq!(loaded.channel, built.channel);
+        }
+        let ring = SpeakerLayout::load(&assets.join("ring8.ron")).unwrap();
+        assert!(ring.is_planar());
+        assert_eq!(ring.channel_count(), 8);
+
+        let path = std::env::temp_dir().join(format!("nuwe_layout_{}.json", std::process::id()));
+        SpeakerLayout::ring(6).save(&path).unwrap();
+        assert_eq!(SpeakerLayout::load(&path).unwrap(), SpeakerLayout::ring(6));
+        std::fs::remove_file(&path).unwrap();
+
+        let mut duplicate = SpeakerLayout::stereo();
+        duplicate.speakers[1].channel = 0;
+        assert!(duplicate.validate().is_err());
+        assert!(Vbap::new(&duplicate).is_err());
+        assert_eq!(SpeakerLayout::for_channels(8), SpeakerLayout::ring(8));
+    }
+
+    #[test]
+    fn test_vbap_gains() {
+        let mut gains = [0.0; MAX_SPEAKERS];
+
+        // On a speaker only that speaker plays; halfway between two, both at -3 dB
+        let ring = Vbap::new(&SpeakerLayout::ring(8)).unwrap();
+        ring.gains(direction_from_angles(-90.0, 0.0), &mut gains);
+        assert!((gains[2] - 1.0).abs() < 1e-5);
+        assert!(gains[..8].iter().enumerate().all(|(speaker, &gain)| speaker == 2 || gain.abs() < 1e-5));
+        ring.gains(direction_from_angles(-22.5, 0.0), &mut gains);
+        assert!((gains[0] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-4);
+        assert!((gains[1] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-4);
+
+        // Stereo: sources outside the pair go to the nearer speaker
+        let stereo = Vbap::new(&SpeakerLayout::stereo()).unwrap();
+        stereo.gains(direction_from_angles(0.0, 0.0), &mut gains);
+        assert!((gains[0] - gains[1]).abs() < 1e-5);
+        stereo.gains(direction_from_angles(100.0, 0.0), &mut gains);
+        assert!((gains[0] - 1.0).abs() < 1e-5 && gains[1] == 0.0);
+
+        // Dome: the top speaker overhead, ring speakers for sources below the horizon
+        let dome = Vbap::new(&SpeakerLayout::dome()).unwrap();
+        assert_eq!(dome.speaker_count(), 13);
+        dome.gains(Vec3::Z, &mut gains);
+        assert!((gains[12] - 1.0).abs() < 1e-4);
+        dome.gains(direction_from_angles(-45.0, 45.0), &mut gains);
+        assert!((gains[8] - 1.0).abs() < 1e-4);
+        dome.gains(direction_from_angles(-20.0, -60.0), &mut gains);
+        assert!(gains[..8].iter().any(|&gain| gain > 0.5));
+        assert!(gains[8..13].iter().all(|&gain| gain.abs() < 1e-5));
+        for azimuth in (0..360).step_by(15) {
+            for elevation in [-30.0, 0.0, 20.0, 60.0, 85.0] {
+                dome.gains(direction_from_angles(azimuth as f32, elevation), &mut gains);
+                let power: f32 = gains[..13].iter().map(|gain| gain * gain).sum();
+                assert!((power - 1.0).abs() < 1e-4, "power {power} at {azimuth}/{elevation}");
+                assert!(gains[..13].iter().all(|&gain| gain >= 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_spatializer_renders_to_speaker_channels() {
+        let context = ProcessContext { sample_rate: 48000.0, channels: 8, input_channels: 0, frames: 64, frame_position: 0 };
+        let render = |processor: &mut SpatialProcessor| {
+            let mut output = vec![0.0; 64 * 8];
+            processor.process(&context, &[], &mut output);
+            output.fill(0.0);
+            processor.process(&context, &[], &mut output);
+            output[63 * 8..].to_vec()
+        };
+
+        let (mut spatializer, mut processor) = Spatializer::new(SpeakerLayout::ring(8), SpatialMethod::Vbap).unwrap();
+        let source = spatializer.add_source("Tone", Box::new(ConstantProcessor(0.5))).unwrap();
+        let control = spatializer.control(source).unwrap();
+        control.set_direction(-90.0, 0.0);
+        let frame = render(&mut processor);
+        assert!((frame[2] - 0.5).abs() < 1e-5);
+        assert!(frame.iter().enumerate().all(|(channel, &sample)| channel == 2 || sample.abs() < 1e-5));
+
+        // Level falls as 1/distance past the reference distance
+        control.set_position(direction_from_angles(-90.0, 0.0) * 4.0);
+        assert!((render(&mut processor)[2] - 0.125).abs() < 1e-5);
+
+        // A source in Bevy space to the listener's right lands on the right speaker
+        control.set_position(bevy_to_audio(Vec3::X));
+        assert!((render(&mut processor)[2] - 0.5).abs() < 1e-5);
+
+        // Ambisonics: front-heavy but spread, summing to the source level
+        spatializer.set_method(SpatialMethod::Ambisonics(AmbisonicDecoding::Basic)).unwrap();
+        control.set_direction(0.0, 0.0);
+        let frame = render(&mut processor);
+        assert!((frame.iter().sum::<f32>() - 0.5).abs() < 1e-4);
+        assert!(frame[0] > frame[1] && frame[1] > frame[2] && frame[0] > frame[4]);
+        assert_eq!(spatializer.method(), SpatialMethod::Ambisonics(AmbisonicDecoding::Basic));
+
+        spatializer.remove_source(source).unwrap();
+        assert!(render(&mut processor).iter().all(|sample| sample.abs() < 1e-6));
+        assert!(spatializer.sources().is_empty());
+        spatializer.collect_garbage();
+    }
+
+    /// Delays its input by a fixed number of frames and reports it as latency
+    struct DelayNode {
+        line: Vec<f32>,
+        position: usize,
+    }
+
+    impl AudioNode for DelayNode {
+        fn name(&self) -> &str {
+            "Delay"
+        }
+
+        fn input_count(&self) -> usize {
+            1
+        }
+
+        fn output_count(&self) -> usize {
+            1
+        }
+
+        fn latency(&self) -> usize {
+            self.line.len()
+        }
+
+        fn process(&mut self, _context: &BlockContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+            for (sample, &input) in outputs[0].iter_mut().zip(inputs[0]) {
+                *sample = std::mem::replace(&mut self.line[self.position], input);
+                self.position = (self.position + 1) % self.line.len();
+            }
+        }
+    }
+
+    #[test]
+    fn test_audio_graph_compensates_parallel_path_latency() {
+        let (mut graph, mut processor) = AudioGraph::new(48000.0, 1, 1);
+        let delay = graph.add_node(Box::new(DelayNode { line: vec![0.0; 10], position: 0 })).unwrap();
+        let gain = graph.add_node(Box::new(GainNode::new(1.0))).unwrap();
+        graph.connect(AudioSource::GraphInput(0), AudioSink::Node(delay, 0)).unwrap();
+        graph.connect(AudioSource::Node(delay, 0), AudioSink::Node(gain, 0)).unwrap();
+        graph.connect(AudioSource::Node(gain, 0), AudioSink::GraphOutput(0)).unwrap();
+        graph.connect(AudioSource::GraphInput(0), AudioSink::GraphOutput(0)).unwrap();
+        assert_eq!(graph.node_latency(delay), Some(10));
+        assert_eq!(graph.latency(), 10);
+
+        // The dry path is delayed to meet the delayed one: a single impulse at twice the level
+        let mut impulse = vec![0.0; 256];
+        impulse[0] = 1.0;
+        let output = run_graph(&mut processor, &impulse, 256, 1);
+        let hits: Vec<(usize, f32)> = output.iter().copied().enumerate().filter(|(_, sample)| *sample != 0.0).collect();
+        assert_eq!(hits, vec![(AUDIO_BLOCK_SIZE + 10, 2.0)]);
+
+        // Paths meeting at a node input are aligned as well
+        let (mut graph, mut processor) = AudioGraph::new(48000.0, 1, 1);
+        let delay = graph.add_node(Box::new(DelayNode { line: vec![0.0; 5], position: 0 })).unwrap();
+        let mix = graph.add_node(Box::new(GainNode::new(0.5))).unwrap();
+        graph.connect(AudioSource::GraphInput(0), AudioSink::Node(delay, 0)).unwrap();
+        graph.connect(AudioSource::Node(delay, 0), AudioSink::Node(mix, 0)).unwrap();
+        graph.connect(AudioSource::GraphInput(0), AudioSink::Node(mix, 0)).unwrap();
+        graph.connect(AudioSource::Node(mix, 0), AudioSink::GraphOutput(0)).unwrap();
+        let output = run_graph(&mut processor, &impulse, 256, 1);
+        assert_eq!(output[AUDIO_BLOCK_SIZE + 5], 1.0);
+        assert_eq!(output.iter().filter(|&&sample| sample != 0.0).count(), 1);
+
+        // A node reporting no latency leaves the paths apart
+        graph.set_node_latency(delay, 0).unwrap();
+        assert_eq!(graph.latency(), 0);
+        let output = run_graph(&mut processor, &impulse, 256, 1);
+        assert_eq!(output.iter().filter(|&&sample| sample != 0.0).count(), 2);
+        graph.remove_node(mix).unwrap();
+        assert!(graph.set_node_latency(mix, 1).is_err());
+    }
+
+    fn note_ons(events: &[crate::audio_midi_integration::MidiEvent]) -> Vec<u8> {
+        use crate::audio_midi_integration::MidiEventType;
+        events.iter()
+            .filter(|event| matches!(event.event_type, MidiEventType::NoteOn))
+            .filter_map(|event| event.note)
+            .collect()
+    }
+
+    #[test]
+    fn test_audio_to_midi_onsets_pitch_and_band_energy() {
+        use crate::audio_midi_integration::{AudioMidiProcessor, AudioToMidiMode, MidiEventType, MidiMapping};
+
+        let sample_rate = 44100.0;
+        let mapping = |mode, controller, high_resolution, min_value, max_value| MidiMapping {
+            controller,
+            channel: 9,
+            min_value,
+            max_value,
+            mode,
+            high_resolution,
+        };
+        let mut processor = AudioMidiProcessor::with_sample_rate(sample_rate);
+        processor.add_audio_to_midi_mapping("pads", mapping(AudioToMidiMode::OnsetTrigger { note: 36, gate_secs: 0.05 }, 0, false, 0.0, 1.0));
+        processor.add_audio_to_midi_mapping("voice", mapping(AudioToMidiMode::PitchToNote { min_confidence: 0.8 }, 0, false, 0.0, 1.0));
+        processor.add_audio_to_midi_mapping("bass", mapping(AudioToMidiMode::BandEnergy { low_hz: 100.0, high_hz: 1000.0 }, 7, true, 0.25, 0.75));
+
+        // Each pad hit is a note on; its note off follows after the gate
+        let mut events = Vec::new();
+        for chunk in click_track(120.0, sample_rate, 6.0).chunks(512) {
+            events.extend(processor.audio_to_midi(chunk, "pads"));
+        }
+        let ons = note_ons(&events);
+        assert!((11..=12).contains(&ons.len()), "{} note ons", ons.len());
+        assert!(ons.iter().all(|&note| note == 36));
+        assert!(events.iter().all(|event| event.channel == 9));
+        for pair in events.chunks(2) {
+            assert!(matches!(pair[0].event_type, MidiEventType::NoteOn) && pair[0].velocity.unwrap() > 0);
+            if let Some(off) = pair.get(1) {
+                assert!(matches!(off.event_type, MidiEventType::NoteOff));
+                let gate = off.timestamp - pair[0].timestamp;
+                assert!((gate - 0.05).abs() < 1e-4, "gate {}", gate);
+            }
+        }
+        assert!(processor.audio_to_midi(&[0.0; 512], "unmapped").is_empty());
+
+        // A4 then E5, then silence ends the note
+        let mut events = Vec::new();
+        let melody = [sine(440.0, 0.5, sample_rate, 22050), sine(659.26, 0.5, sample_rate, 22050), vec![0.0; 8192]].concat();
+        for chunk in melody.chunks(512) {
+            events.extend(processor.audio_to_midi(chunk, "voice"));
+        }
+        let ons = note_ons(&events);
+        assert_eq!(ons.first(), Some(&69));
+        assert_eq!(ons.last(), Some(&76));
+        let last = events.last().unwrap();
+        assert!(matches!(last.event_type, MidiEventType::NoteOff) && last.note == Some(76));
+
+        // Band energy as 14-bit CC 7/39 pairs, only when the value changes, within 0.25..0.75
+        let mut events = Vec::new();
+        let bass = [sine(220.0, 1.0, sample_rate, 22050), vec![0.0; 44100]].concat();
+        for chunk in bass.chunks(512) {
+            events.extend(processor.audio_to_midi(chunk, "bass"));
+        }
+        assert!(!events.is_empty() && events.len() % 2 == 0);
+        let values: Vec<u16> = events.chunks(2)
+            .map(|pair| {
+                assert_eq!((pair[0].controller, pair[1].controller), (Some(7), Some(39)));
+                (pair[0].value.unwrap() << 7) | pair[1].value.unwrap()
+            })
+            .collect();
+        assert!(values.windows(2).all(|pair| pair[0] != pair[1]));
+        let loudest = *values.iter().max().unwrap();
+        assert!((10000..=12288).contains(&loudest), "loudest {}", loudest);
+        assert!(values.iter().all(|&value| value >= 4095));
+        assert!(*values.last().unwrap() < 4300);
+    }
+
+    #[test]
+    fn test_midi_to_audio_applies_ranges_and_curves() {
+        use crate::audio_midi_integration::{AudioMapping, AudioMidiProcessor, MappingCurve, MidiEvent, MidiEventType, MidiSource};
+
+        let mapping = |parameter: &str, source, curve, min_value, max_value, channel| AudioMapping {
+            parameter: parameter.to_string(),
+            min_value,
+            max_value,
+            source,
+            curve,
+            channel,
+        };
+        let mut processor = AudioMidiProcessor::new();
+        processor.add_midi_to_audio_mapping("main", mapping("cutoff", MidiSource::ControlChange { controller: 74, high_resolution: false }, MappingCurve::Exponential, 20.0, 20000.0, None));
+        processor.add_midi_to_audio_mapping("main", mapping("volume", MidiSource::ControlChange { controller: 7, high_resolution: true }, MappingCurve::Linear, 0.0, 1.0, None));
+        processor.add_midi_to_audio_mapping("main", mapping("drive", MidiSource::NoteVelocity { note: Some(36) }, MappingCurve::Power { exponent: 2.0 }, 0.0, 1.0, None));
+        processor.add_midi_to_audio_mapping("main", mapping("bend", MidiSource::PitchBend, MappingCurve::Linear, -2.0, 2.0, Some(9)));
+
+        let changed = processor.midi_to_audio(&[
+            MidiEvent::control_change(0, 74, 127, 0.0),
+            MidiEvent::control_change(0, 7, 64, 0.0),
+            MidiEvent::control_change(0, 39, 127, 0.0),
+            MidiEvent::note_on(0, 36, 64, 0.0),
+            MidiEvent::note_on(0, 38, 127, 0.0),
+        ], "main");
+        assert_eq!(changed.len(), 3);
+        assert!((changed["cutoff"] - 20000.0).abs() < 0.5);
+        assert!((changed["volume"] - 8319.0 / 16383.0).abs() < 1e-6);
+        assert!((changed["drive"] - (64.0f32 / 127.0).powi(2)).abs() < 1e-6);
+
+        let changed = processor.midi_to_audio(&[MidiEvent::control_change(0, 74, 0, 0.0), MidiEvent::note_off(0, 36, 0.0)], "main");
+        assert!((changed["cutoff"] - 20.0).abs() < 1e-3);
+        assert_eq!(changed["drive"], 0.0);
+        assert!((processor.parameter("volume").unwrap() - 8319.0 / 16383.0).abs() < 1e-6);
+
+        // Pitch bend only on channel 10 (index 9)
+        let bend = |channel| MidiEvent { event_type: MidiEventType::PitchBend, channel, note: None, velocity: None, controller: None, value: Some(16383), timestamp: 0.0 };
+        assert!(processor.midi_to_audio(&[bend(0)], "main").is_empty());
+        assert_eq!(processor.midi_to_audio(&[bend(9)], "main")["bend"], 2.0);
+        assert!(processor.midi_to_audio(&[bend(9)], "other").is_empty());
+
+        assert!((MappingCurve::SCurve.apply(0.25, 0.0, 1.0) - 0.15625).abs() < 1e-6);
+        assert_eq!(MappingCurve::Toggle.apply(0.49, 0.0, 5.0), 0.0);
+        assert_eq!(MappingCurve::Toggle.apply(0.5, 0.0, 5.0), 5.0);
+        assert!((MappingCurve::Exponential.apply(0.5, 20.0, 20000.0) - 632.456).abs() < 0.01);
+    }
+
+    /// The same signal on both channels of an interleaved stereo buffer
+    fn stereo(mono: &[f32]) -> Vec<f32> {
+        mono.iter().flat_map(|&sample| [sample, sample]).collect()
+    }
+
+    #[test]
+    fn test_loudness_meter_levels_gating_range_and_true_peak() {
+        let sample_rate = 48000.0;
+        let seconds = |secs: usize| secs * 48000;
+
+        // A -20 dBFS sine near 1 kHz on both channels reads -20 LUFS
+        let mut meter = LoudnessMeter::new(sample_rate, 2);
+        assert_eq!(meter.reading(), LoudnessReading::default());
+        let loud = stereo(&sine(997.0, 0.1, sample_rate, seconds(10)));
+        meter.process(&loud);
+        assert!((meter.momentary() + 20.0).abs() < 0.2, "momentary {}", meter.momentary());
+        assert!((meter.short_term() + 20.0).abs() < 0.2, "short-term {}", meter.short_term());
+        assert!((meter.integrated() + 20.0).abs() < 0.2, "integrated {}", meter.integrated());
+        assert!(meter.loudness_range() < 0.5);
+
+        // Silence falls below the absolute gate and quiet passages below the relative gate
+        meter.process(&vec![0.0; seconds(10) * 2]);
+        assert_eq!(meter.momentary(), LOUDNESS_FLOOR);
+        assert!((meter.integrated() + 20.0).abs() < 0.2, "integrated {}", meter.integrated());
+        meter.process(&stereo(&sine(997.0, 0.01, sample_rate, seconds(10))));
+        assert!((meter.momentary() + 40.0).abs() < 0.2);
+        assert!((meter.integrated() + 20.0).abs() < 0.5, "integrated {}", meter.integrated());
+
+        // Alternating -20 and -30 LUFS passages span about 10 LU
+        meter.reset();
+        assert_eq!(meter.integrated(), LOUDNESS_FLOOR);
+        let quiet = stereo(&sine(997.0, 0.1 / 10f32.sqrt(), sample_rate, seconds(10)));
+        for _ in 0..3 {
+            meter.process(&loud);
+            meter.process(&quiet);
+        }
+        assert!((meter.loudness_range() - 10.0).abs() < 1.0, "range {}", meter.loudness_range());
+
+        // A quarter-rate sine sampled between its peaks has a true peak 3 dB over its samples
+        let mut meter = LoudnessMeter::new(sample_rate, 1);
+        let between: Vec<f32> = (0..seconds(1))
+            .map(|i| (std::f32::consts::FRAC_PI_2 * (i % 4) as f32 + std::f32::consts::FRAC_PI_4).sin())
+            .collect();
+        let sample_peak = 20.0 * between.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs())).log10();
+        meter.process(&between);
+        assert!((sample_peak + 3.01).abs() < 0.01);
+        assert!(meter.true_peak() > -0.5 && meter.true_peak() < 0.3, "true peak {}", meter.true_peak());
+    }
+
+    #[test]
+    fn test_mixer_buses_master_and_node_report_loudness() {
+        /// Plays a sine on both channels
+        struct SineProcessor {
+            phase: f32,
+        }
+
+        impl AudioProcessor for SineProcessor {
+            fn process(&mut self, context: &ProcessContext, _input: &[f32], output: &mut [f32]) {
+                for frame in output.chunks_exact_mut(context.channels) {
+                    let sample = 0.1 * (2.0 * std::f32::consts::PI * self.phase).sin();
+                    self.phase = (self.phase + 997.0 / context.sample_rate).fract();
+                    frame.iter_mut().for_each(|output| *output += sample);
+                }
+            }
+        }
+
+        let (mut mixer, mut processor) = Mixer::new(48000.0);
+        let strip = mixer.add_strip("Tone", Box::new(SineProcessor { phase: 0.0 })).unwrap();
+        let bus = mixer.add_bus("Music", BusKind::Group).unwrap();
+        mixer.route_strip(strip, Some(bus)).unwrap();
+        for _ in 0..100 {
+            render_mixer(&mut processor);
+        }
+
+        assert!(mixer.control(MixerChannel::Strip(strip)).unwrap().loudness().is_none());
+        for channel in [MixerChannel::Bus(bus), MixerChannel::Master] {
+            let loudness = mixer.control(channel).unwrap().loudness().unwrap();
+            assert!((loudness.momentary + 20.0).abs() < 0.3, "{:?} momentary {}", channel, loudness.momentary);
+            assert!((loudness.integrated + 20.0).abs() < 0.3);
+        }
+
+        let master = mixer.control(MixerChannel::Master).unwrap();
+        let mut node = MixerChannelNode::new(master.clone());
+        assert!(node.outputs().iter().any(|port| port.name == "integrated_lufs"));
+        let outputs = node.process(std::collections::HashMap::new()).unwrap();
+        assert!((outputs["momentary_lufs"].as_f64().unwrap() + 20.0).abs() < 0.3);
+
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("reset_loudness".to_string(), serde_json::json!(true));
+        node.process(inputs).unwrap();
+        render_mixer(&mut processor);
+        assert_eq!(master.loudness().unwrap().integrated, LOUDNESS_FLOOR);
+
+        // The standalone node meters whatever audio it is given
+        let mut node = LoudnessMeterNode::new();
+        let audio = crate::core::AudioBufferData::new(stereo(&sine(997.0, 0.1, 48000.0, 48000)), 2, 48000.0);
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("audio".to_string(), audio.to_value());
+        let outputs = node.process(inputs).unwrap();
+        assert!((outputs["short_term_lufs"].as_f64().unwrap() - LOUDNESS_FLOOR as f64).abs() < 1e-6);
+        assert!((outputs["momentary_lufs"].as_f64().unwrap() + 20.0).abs() < 0.2);
+    }
+
+    /// Synthetic drum hits at 48 kHz: a pitch-swept sine kick, a tone-plus-noise snare and
+    /// a high-passed noise hat
+    fn drum_hit(class: DrumClass, amplitude: f32, seed: &mut u32) -> Vec<f32> {
+        let sample_rate = 48000.0;
+        let mut noise = move || {
+            *seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (*seed >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+        };
+        let (mut phase, mut low, mut previous) = (0.0f32, 0.0f32, 0.0f32);
+        (0..(0.15 * sample_rate) as usize)
+            .map(|i| {
+                let t = i as f32 / sample_rate;
+                let sample = match class {
+                    DrumClass::Kick => {
+                        phase += 2.0 * std::f32::consts::PI * (50.0 + 100.0 * (-t / 0.03).exp()) / sample_rate;
+                        phase.sin() * (-t / 0.08).exp()
+                    }
+                    DrumClass::Snare => {
+                        low += 0.3 * (noise() - low);
+                        (0.5 * (2.0 * std::f32::consts::PI * 190.0 * t).sin() + low) * (-t / 0.05).exp()
+                    }
+                    DrumClass::HiHat => {
+                        let white = noise();
+                        let high = white - previous;
+                        previous = white;
+                        0.5 * high * (-t / 0.015).exp()
+                    }
+                    DrumClass::Other => (2.0 * std::f32::consts::PI * 1000.0 * t).sin() * (-t / 0.1).exp(),
+                };
+                amplitude * sample
+            })
+            .collect()
+    }
+
+    /// Hits laid out every 250 ms
+    fn drum_pattern(pattern: &[(DrumClass, f32)], seed: &mut u32) -> Vec<f32> {
+        let step = 12000;
+        let mut samples = vec![0.0; step * (pattern.len() + 1)];
+        for (index, &(class, amplitude)) in pattern.iter().enumerate() {
+            for (offset, sample) in drum_hit(class, amplitude, seed).into_iter().enumerate() {
+                samples[step * index + 1000 + offset] += sample;
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn test_drum_classifier_sorts_hits_with_timing_and_velocity() {
+        use DrumClass::*;
+        let mut seed = 1;
+        let pattern = [(Kick, 0.9), (HiHat, 0.5), (Snare, 0.8), (HiHat, 0.5), (Kick, 0.2), (HiHat, 0.3), (Snare, 0.8), (HiHat, 0.5)];
+        let audio = drum_pattern(&pattern, &mut seed);
+
+        let mut classifier = DrumClassifier::new(48000.0, DrumClassifierSettings::default());
+        let hits: Vec<DrumHit> = audio.chunks(480).flat_map(|block| classifier.process(block)).collect();
+        let classes: Vec<DrumClass> = hits.iter().map(|hit| hit.class).collect();
+        assert_eq!(classes, pattern.iter().map(|&(class, _)| class).collect::<Vec<_>>());
+        for (index, hit) in hits.iter().enumerate() {
+            let expected = (12000 * index + 1000) as f64 / 48000.0;
+            assert!((hit.time_secs - expected).abs() < 0.01, "hit {} at {}", index, hit.time_secs);
+            assert!(hit.confidence > 0.5);
+        }
+        assert!(hits[0].velocity > hits[4].velocity + 0.2);
+    }
+
+    #[test]
+    fn test_drum_classifier_calibrates_on_the_kit() {
+        use DrumClass::*;
+        let mut seed = 7;
+        let mut classifier = DrumClassifier::new(48000.0, DrumClassifierSettings::default());
+        let tone = drum_pattern(&[(Other, 0.8)], &mut seed);
+        assert_eq!(classifier.process(&tone)[0].class, Snare);
+
+        for class in DrumClass::DRUMS {
+            let recording = drum_pattern(&[(class, 0.8), (class, 0.5), (class, 0.7), (class, 0.6)], &mut seed);
+            assert_eq!(classifier.calibrate(class, &recording).unwrap(), 4);
+            assert_eq!(classifier.profiles().get(class).unwrap().hits, 4);
+        }
+        assert!(classifier.calibrate(Kick, &vec![0.0; 48000]).is_err());
+        assert!(classifier.calibrate(Other, &tone).is_err());
+
+        // Calibrated profiles are tight enough to reject sounds outside the kit
+        classifier.reset();
+        assert_eq!(classifier.process(&tone)[0].class, Other);
+        let kit = drum_pattern(&[(Kick, 0.6), (Snare, 0.6), (HiHat, 0.6)], &mut seed);
+        let classes: Vec<DrumClass> = classifier.process(&kit).iter().map(|hit| hit.class).collect();
+        assert_eq!(classes, vec![Kick, Snare, HiHat]);
+    }
+
+    #[test]
+    fn test_drum_classifier_node_triggers_and_learns() {
+        use DrumClass::*;
+        let mut seed = 3;
+        let mut node = DrumClassifierNode::new();
+        let mut run = |samples: Vec<f32>, learn: Option<&str>| {
+            let mut inputs = std::collections::HashMap::new();
+            inputs.insert("audio".to_string(), crate::core::AudioBufferData::new(samples, 1, 48000.0).to_value());
+            if let Some(class) = learn {
+                inputs.insert("learn".to_string(), serde_json::json!(class));
+            }
+            node.process(inputs).unwrap()
+        };
+
+        let outputs = run(drum_pattern(&[(Kick, 0.8), (HiHat, 0.5)], &mut seed), None);
+        assert_eq!(outputs["kick"], serde_json::json!(true));
+        assert_eq!(outputs["hihat"], serde_json::json!(true));
+        assert_eq!(outputs["snare"], serde_json::json!(false));
+        assert!(outputs["kick_velocity"].as_f64().unwrap() > 0.9);
+        assert_eq!(outputs["snare_velocity"].as_f64().unwrap(), 0.0);
+        assert_eq!(outputs["class"], serde_json::json!("hihat"));
+        assert_eq!(outputs["hits"].as_array().unwrap().len(), 2);
+
+        // Hits heard while learning are reported as the learned drum and calibrate it
+        let outputs = run(drum_pattern(&[(Snare, 0.8), (Snare, 0.6)], &mut seed), Some("snare"));
+        assert_eq!(outputs["snare"], serde_json::json!(true));
+        run(vec![0.0; 4800], None);
+        assert_eq!(node.profiles.snare.hits, 2);
+        assert_eq!(node.profiles.kick.hits, 0);
+    }
+}
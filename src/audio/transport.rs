@@ -0,0 +1,78 @@
+// Musical transport: tempo grid for quantized starts
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Where a quantized action lands on the tempo grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Quantize {
+    Immediate,
+    Beat,
+    Bar,
+}
+
+/// Tempo grid shared by players and sequencers, measured in engine frames
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct Transport {
+    pub bpm: f32,
+    pub beats_per_bar: u32,
+    /// Engine frame of beat zero; negative after slowing down early in a session
+    pub origin_frame: i64,
+    /// Follow the tempo found by beat detection
+    pub follow_detected_tempo: bool,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self {
+            bpm: 120.0,
+            beats_per_bar: 4,
+            origin_frame: 0,
+            follow_detected_tempo: false,
+        }
+    }
+}
+
+impl Transport {
+    pub fn frames_per_beat(&self, sample_rate: f32) -> f64 {
+        sample_rate as f64 * 60.0 / self.bpm.max(1.0) as f64
+    }
+
+    /// Beat position of an engine frame
+    pub fn beat_at(&self, frame: u64, sample_rate: f32) -> f64 {
+        (frame as f64 - self.origin_frame as f64) / self.frames_per_beat(sample_rate)
+    }
+
+    /// Change tempo at `frame` without moving the current beat position
+    pub fn set_bpm(&mut self, bpm: f32, frame: u64, sample_rate: f32) {
+        let beat = self.beat_at(frame, sample_rate);
+        self.bpm = bpm.max(1.0);
+        let origin = frame as f64 - beat * self.frames_per_beat(sample_rate);
+        self.origin_frame = origin.round() as i64;
+    }
+
+    /// First frame at or after `frame` that lies on the grid
+    pub fn next_quantized_frame(&self, frame: u64, sample_rate: f32, quantize: Quantize) -> u64 {
+        let beats = match quantize {
+            Quantize::Immediate => return frame,
+            Quantize::Beat => 1.0,
+            Quantize::Bar => self.beats_per_bar.max(1) as f64,
+        };
+        let step = self.frames_per_beat(sample_rate) * beats;
+        let steps = ((frame as f64 - self.origin_frame as f64) / step).ceil();
+        (self.origin_frame as f64 + steps * step).round().max(0.0) as u64
+    }
+}
+
+/// Track detected tempo when the transport is set to follow it
+pub(crate) fn follow_detected_tempo(
+    mut transport: ResMut<Transport>,
+    metrics: Option<Res<crate::audio::AudioMetrics>>,
+    engine: Option<NonSend<crate::audio::AudioEngine>>,
+) {
+    let (Some(metrics), Some(engine)) = (metrics, engine) else {
+        return;
+    };
+    if transport.follow_detected_tempo && metrics.current_bpm > 0.0 && metrics.current_bpm != transport.bpm {
+        transport.set_bpm(metrics.current_bpm, engine.frame_position(), engine.sample_rate());
+    }
+}
@@ -50,6 +50,7 @@ fn audio_ui_system(
     plugdata: Option<()>,
     audio_settings: Res<crate::audio::AudioSettings>,
     audio_metrics: Res<crate::audio::AudioMetrics>,
+    mixer: Option<Res<crate::audio::Mixer>>,
 ) {
     let ctx = contexts.ctx_mut().unwrap();
 
@@ -64,6 +65,12 @@ fn audio_ui_system(
                     ui.label("📊 Real-time audio analysis");
                 });
 
+                if let Some(mixer) = mixer.as_deref() {
+                    ui.collapsing("🎚️ Mixer", |ui| {
+                        show_mixer_ui(ui, mixer);
+                    });
+                }
+
                 ui.collapsing("📊 Audio Metrics", |ui| {
                     show_audio_metrics_ui(ui, &audio_metrics);
                 });
@@ -186,12 +193,58 @@ fn show_plugdata_ui(
     }
 }
 
+/// Show mixer faders and meters
+#[cfg(feature = "ui")]
+fn show_mixer_ui(ui: &mut egui::Ui, mixer: &crate::audio::Mixer) {
+    use crate::audio::MixerChannel;
+
+    let channels = mixer.strips().into_iter().map(MixerChannel::Strip)
+        .chain(mixer.buses().into_iter().map(|(bus, _)| MixerChannel::Bus(bus)))
+        .chain(std::iter::once(MixerChannel::Master));
+
+    for channel in channels {
+        let (Some(control), Some(name)) = (mixer.control(channel), mixer.name(channel)) else {
+            continue;
+        };
+
+        ui.horizontal(|ui| {
+            ui.label(name);
+
+            let mut gain = control.gain();
+            if ui.add(egui::Slider::new(&mut gain, 0.0..=2.0).text("Gain")).changed() {
+                control.set_gain(gain);
+            }
+            let mut pan = control.pan();
+            if ui.add(egui::Slider::new(&mut pan, -1.0..=1.0).text("Pan")).changed() {
+                control.set_pan(pan);
+            }
+            let mut mute = control.is_muted();
+            if ui.checkbox(&mut mute, "M").changed() {
+                control.set_mute(mute);
+            }
+            if let MixerChannel::Strip(_) = channel {
+                let mut solo = control.is_soloed();
+                if ui.checkbox(&mut solo, "S").changed() {
+                    control.set_solo(solo);
+                }
+            }
+
+            let meter = control.meter();
+            ui.add(egui::ProgressBar::new(meter.peak[0].min(1.0)).desired_width(60.0));
+            ui.add(egui::ProgressBar::new(meter.peak[1].min(1.0)).desired_width(60.0));
+        });
+    }
+}
+
 /// Show audio metrics display
 #[cfg(feature = "ui")]
 fn show_audio_metrics_ui(ui: &mut egui::Ui, metrics: &crate::audio::AudioMetrics) {
     ui.label(format!("🎵 BPM: {:.1}", metrics.current_bpm));
     ui.label(format!("📊 Peak L/R: {:.3}/{:.3}", metrics.peak_level_left, metrics.peak_level_right));
     ui.label(format!("📈 RMS L/R: {:.3}/{:.3}", metrics.rms_level_left, metrics.rms_level_right));
+    let loudness = &metrics.loudness;
+    ui.label(format!("🔊 LUFS M/S/I: {:.1}/{:.1}/{:.1}", loudness.momentary, loudness.short_term, loudness.integrated));
+    ui.label(format!("📏 LRA: {:.1} LU  True peak: {:.1} dBTP", loudness.range, loudness.true_peak));
     ui.label(format!("🥁 Beats Detected: {}", metrics.beats_detected));
 
     if !metrics.spectrum.is_empty() {
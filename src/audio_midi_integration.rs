@@ -1,11 +1,16 @@
 //! Audio MIDI Integration for NUWE
 //!
 //! This module provides comprehensive MIDI input/output handling and audio-MIDI integration
-//! for the NUWE node-based system.
+//! for the NUWE node-based system. Audio turns into MIDI by onset triggers, pitch tracking
+//! or band energy controllers (optionally 14-bit), e.g. so drum pads can drive a lighting
+//! desk; MIDI turns into named parameter values through ranges and curves.
 
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::time::{Duration, Instant};
+use crate::audio::{yin, BandFollower, BeatDetector, EnvelopeFollower, EnvelopeMode, EnvelopeSettings, YinConfig};
+use crate::input::{InputEvent, MidiPortProvider, MidiSystem, MidirPorts};
 
 /// MIDI event types
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,6 +20,7 @@ pub enum MidiEventType {
     ControlChange,
     PitchBend,
     Aftertouch,
+    ProgramChange,
 }
 
 /// MIDI event structure
@@ -88,35 +94,65 @@ impl MidiHandler {
     }
 }
 
-/// MIDI input device abstraction
+/// MIDI input device: the input ports whose names match `name` (see
+/// `midi_port_matches`), reconnected when unplugged and plugged back in
 pub struct MidiInputDevice {
     name: String,
-    connected: bool,
+    system: Option<MidiSystem>,
+    connected_at: Instant,
+    last_scan: Instant,
 }
 
 impl MidiInputDevice {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
-            connected: false,
+            system: None,
+            connected_at: Instant::now(),
+            last_scan: Instant::now(),
         }
     }
 
-    /// Poll for new MIDI events
+    /// Poll for new MIDI events, timestamped in seconds since connecting
     pub fn poll_events(&mut self) -> Vec<MidiEvent> {
-        // Placeholder - would poll actual MIDI device
-        Vec::new()
+        let Some(system) = &mut self.system else {
+            return Vec::new();
+        };
+        if self.last_scan.elapsed() >= Duration::from_secs_f32(system.rescan_interval_secs) {
+            self.last_scan = Instant::now();
+            let _ = system.refresh();
+        }
+        let timestamp = self.connected_at.elapsed().as_secs_f64();
+        system.poll().iter().filter_map(|event| MidiEvent::from_input(event, timestamp)).collect()
     }
 
-    /// Connect to device
+    /// Connect to the system MIDI ports matching the device name
     pub fn connect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.connected = true;
+        self.connect_with(Box::new(MidirPorts::default()))
+    }
+
+    /// Connect to matching ports of another port source
+    pub fn connect_with(&mut self, ports: Box<dyn MidiPortProvider>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut system = MidiSystem::with_ports(ports);
+        system.patterns.clear();
+        system.enabled = true;
+        if system.connect(&self.name)?.is_empty() {
+            return Err(format!("No MIDI input matches '{}'", self.name).into());
+        }
+        self.system = Some(system);
+        self.connected_at = Instant::now();
+        self.last_scan = Instant::now();
         Ok(())
     }
 
+    /// Names of the connected ports
+    pub fn ports(&self) -> Vec<String> {
+        self.system.as_ref().map(MidiSystem::connected_ports).unwrap_or_default()
+    }
+
     /// Disconnect from device
     pub fn disconnect(&mut self) {
-        self.connected = false;
+        self.system = None;
     }
 }
 
@@ -153,68 +189,186 @@ impl MidiOutputDevice {
     }
 }
 
-/// Audio-MIDI integration processor
+/// Audio-MIDI integration processor. Audio channels drive MIDI through `MidiMapping`s;
+/// MIDI drives named parameters through `AudioMapping`s. A channel can have several of each.
 pub struct AudioMidiProcessor {
     midi_handler: MidiHandler,
-    audio_to_midi_map: HashMap<String, MidiMapping>,
-    midi_to_audio_map: HashMap<String, AudioMapping>,
+    sample_rate: f32,
+    audio_to_midi_map: HashMap<String, Vec<AudioToMidiConverter>>,
+    midi_to_audio_map: HashMap<String, Vec<MidiToAudioConverter>>,
+    parameters: HashMap<String, f32>,
 }
 
 impl AudioMidiProcessor {
     pub fn new() -> Self {
+        Self::with_sample_rate(44100.0)
+    }
+
+    pub fn with_sample_rate(sample_rate: f32) -> Self {
         Self {
             midi_handler: MidiHandler::new(),
+            sample_rate,
             audio_to_midi_map: HashMap::new(),
             midi_to_audio_map: HashMap::new(),
+            parameters: HashMap::new(),
         }
     }
 
-    /// Process audio signal and generate MIDI events
-    pub fn audio_to_midi(&mut self, audio_input: &[f32], channel: &str) -> Vec<MidiEvent> {
-        if let Some(mapping) = self.audio_to_midi_map.get(channel) {
-            // Placeholder - would analyze audio and generate MIDI
-            vec![MidiEvent {
-                event_type: MidiEventType::ControlChange,
-                channel: 0,
-                note: None,
-                velocity: None,
-                controller: Some(1),
-                value: Some((audio_input.get(0).unwrap_or(&0.0) * 127.0) as u16),
-                timestamp: 0.0,
-            }]
-        } else {
-            Vec::new()
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Rate of the audio passed to `audio_to_midi`; analysis restarts at the new rate
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            for converter in self.audio_to_midi_map.values_mut().flatten() {
+                *converter = AudioToMidiConverter::new(converter.mapping.clone());
+            }
         }
     }
 
-    /// Process MIDI events and generate audio control signals
-    pub fn midi_to_audio(&mut self, midi_events: &[MidiEvent], channel: &str) -> Vec<f32> {
-        if let Some(mapping) = self.midi_to_audio_map.get(channel) {
-            // Placeholder - would convert MIDI to audio control
-            midi_events.iter().map(|event| {
-                match event.event_type {
-                    MidiEventType::ControlChange => {
-                        event.value.unwrap_or(0) as f32 / 127.0
-                    }
-                    MidiEventType::NoteOn => {
-                        event.velocity.unwrap_or(0) as f32 / 127.0
-                    }
-                    _ => 0.0
+    /// Analyze the next block of mono audio on a channel and generate MIDI events.
+    /// Timestamps are seconds of audio processed on the channel.
+    pub fn audio_to_midi(&mut self, audio_input: &[f32], channel: &str) -> Vec<MidiEvent> {
+        let Some(converters) = self.audio_to_midi_map.get_mut(channel) else {
+            return Vec::new();
+        };
+        let mut events: Vec<MidiEvent> = converters.iter_mut()
+            .flat_map(|converter| converter.process(audio_input, self.sample_rate))
+            .collect();
+        events.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        events
+    }
+
+    /// Apply MIDI events to the parameters mapped on a channel. Returns the parameters
+    /// the events changed, with their new values.
+    pub fn midi_to_audio(&mut self, midi_events: &[MidiEvent], channel: &str) -> HashMap<String, f32> {
+        let mut changed = HashMap::new();
+        let Some(converters) = self.midi_to_audio_map.get_mut(channel) else {
+            return changed;
+        };
+        for event in midi_events {
+            for converter in converters.iter_mut() {
+                if let Some(value) = converter.process(event) {
+                    self.parameters.insert(converter.mapping.parameter.clone(), value);
+                    changed.insert(converter.mapping.parameter.clone(), value);
                 }
-            }).collect()
-        } else {
-            Vec::new()
+            }
         }
+        changed
+    }
+
+    /// Last value MIDI set for a parameter
+    pub fn parameter(&self, name: &str) -> Option<f32> {
+        self.parameters.get(name).copied()
+    }
+
+    pub fn parameters(&self) -> &HashMap<String, f32> {
+        &self.parameters
     }
 
     /// Add audio-to-MIDI mapping
     pub fn add_audio_to_midi_mapping(&mut self, channel: &str, mapping: MidiMapping) {
-        self.audio_to_midi_map.insert(channel.to_string(), mapping);
+        self.audio_to_midi_map.entry(channel.to_string()).or_default().push(AudioToMidiConverter::new(mapping));
     }
 
     /// Add MIDI-to-audio mapping
     pub fn add_midi_to_audio_mapping(&mut self, channel: &str, mapping: AudioMapping) {
-        self.midi_to_audio_map.insert(channel.to_string(), mapping);
+        self.midi_to_audio_map.entry(channel.to_string()).or_default().push(MidiToAudioConverter::new(mapping));
+    }
+
+    /// Remove a channel's mappings in both directions
+    pub fn clear_mappings(&mut self, channel: &str) {
+        self.audio_to_midi_map.remove(channel);
+        self.midi_to_audio_map.remove(channel);
+    }
+}
+
+impl Default for AudioMidiProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiEvent {
+    pub fn note_on(channel: u8, note: u8, velocity: u8, timestamp: f64) -> Self {
+        Self {
+            event_type: MidiEventType::NoteOn,
+            channel,
+            note: Some(note),
+            velocity: Some(velocity),
+            controller: None,
+            value: None,
+            timestamp,
+        }
+    }
+
+    pub fn note_off(channel: u8, note: u8, timestamp: f64) -> Self {
+        Self {
+            event_type: MidiEventType::NoteOff,
+            channel,
+            note: Some(note),
+            velocity: Some(0),
+            controller: None,
+            value: None,
+            timestamp,
+        }
+    }
+
+    /// Convert a MIDI input event; SysEx has no equivalent
+    pub fn from_input(event: &InputEvent, timestamp: f64) -> Option<Self> {
+        let (event_type, channel, note, velocity, controller, value) = match *event {
+            InputEvent::MidiNoteOn { channel, note, velocity } => (MidiEventType::NoteOn, channel, Some(note), Some(velocity), None, None),
+            InputEvent::MidiNoteOff { channel, note } => (MidiEventType::NoteOff, channel, Some(note), Some(0), None, None),
+            InputEvent::MidiControlChange { channel, controller, value } => {
+                (MidiEventType::ControlChange, channel, None, None, Some(controller), Some(value as u16))
+            }
+            InputEvent::MidiPitchBend { channel, value } => (MidiEventType::PitchBend, channel, None, None, None, Some(value)),
+            InputEvent::MidiAftertouch { channel, pressure } => (MidiEventType::Aftertouch, channel, None, None, None, Some(pressure as u16)),
+            InputEvent::MidiPolyAftertouch { channel, note, pressure } => {
+                (MidiEventType::Aftertouch, channel, Some(note), None, None, Some(pressure as u16))
+            }
+            InputEvent::MidiProgramChange { channel, program } => (MidiEventType::ProgramChange, channel, None, None, None, Some(program as u16)),
+            _ => return None,
+        };
+        Some(Self { event_type, channel, note, velocity, controller, value, timestamp })
+    }
+
+    pub fn control_change(channel: u8, controller: u8, value: u16, timestamp: f64) -> Self {
+        Self {
+            event_type: MidiEventType::ControlChange,
+            channel,
+            note: None,
+            velocity: None,
+            controller: Some(controller),
+            value: Some(value),
+            timestamp,
+        }
+    }
+}
+
+/// Largest 14-bit controller value
+const CC_14BIT_MAX: u16 = 16383;
+
+/// Samples analyzed per pitch estimate; two periods of YIN's lowest frequency at 48 kHz
+const PITCH_WINDOW: usize = 2048;
+
+/// What an audio-to-MIDI mapping listens for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AudioToMidiMode {
+    /// A note on for each onset, with velocity from the hit's level; the note off
+    /// follows `gate_secs` later
+    OnsetTrigger { note: u8, gate_secs: f32 },
+    /// The detected pitch as notes, retriggered when the nearest note changes
+    PitchToNote { min_confidence: f32 },
+    /// The envelope of a frequency band as a controller
+    BandEnergy { low_hz: f32, high_hz: f32 },
+}
+
+impl Default for AudioToMidiMode {
+    fn default() -> Self {
+        AudioToMidiMode::BandEnergy { low_hz: 20.0, high_hz: 20000.0 }
     }
 }
 
@@ -223,8 +377,178 @@ impl AudioMidiProcessor {
 pub struct MidiMapping {
     pub controller: u8,
     pub channel: u8,
+    /// Output range as a fraction (0..1) of the controller's or velocity's full scale
     pub min_value: f32,
     pub max_value: f32,
+    #[serde(default)]
+    pub mode: AudioToMidiMode,
+    /// Send controllers 0-31 as 14-bit pairs: MSB, then LSB on `controller + 32`
+    #[serde(default)]
+    pub high_resolution: bool,
+}
+
+impl MidiMapping {
+    /// A 0..1 level within the mapping's range
+    fn scale(&self, level: f32) -> f32 {
+        (self.min_value + (self.max_value - self.min_value) * level.clamp(0.0, 1.0)).clamp(0.0, 1.0)
+    }
+
+    fn velocity(&self, level: f32) -> u8 {
+        (self.scale(level) * 127.0).round().clamp(1.0, 127.0) as u8
+    }
+}
+
+/// Analysis state behind one audio-to-MIDI mapping
+struct AudioToMidiConverter {
+    mapping: MidiMapping,
+    /// Level of recent hits, for velocities
+    level: EnvelopeFollower,
+    onsets: Option<BeatDetector>,
+    band: Option<BandFollower>,
+    pitch_window: Vec<f32>,
+    /// Sounding note, and the sample position of its note off when timed
+    active_note: Option<(u8, Option<u64>)>,
+    last_value: Option<u16>,
+    position: u64,
+}
+
+impl AudioToMidiConverter {
+    fn new(mapping: MidiMapping) -> Self {
+        let band = match mapping.mode {
+            AudioToMidiMode::BandEnergy { low_hz, high_hz } => Some(BandFollower::new(low_hz, high_hz, EnvelopeSettings::default())),
+            _ => None,
+        };
+        Self {
+            mapping,
+            level: EnvelopeFollower::new(EnvelopeSettings { attack_secs: 0.0, release_secs: 0.1, mode: EnvelopeMode::Peak }),
+            onsets: None,
+            band,
+            pitch_window: Vec::new(),
+            active_note: None,
+            last_value: None,
+            position: 0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f32], sample_rate: f32) -> Vec<MidiEvent> {
+        let mut events = Vec::new();
+        let channel = self.mapping.channel;
+        self.position += samples.len() as u64;
+        let now = self.position as f64 / sample_rate as f64;
+        let level = self.level.process(samples, sample_rate);
+
+        match self.mapping.mode.clone() {
+            AudioToMidiMode::OnsetTrigger { note, gate_secs } => {
+                if let Some((active, Some(off_at))) = self.active_note {
+                    if self.position >= off_at {
+                        events.push(MidiEvent::note_off(channel, active, off_at as f64 / sample_rate as f64));
+                        self.active_note = None;
+                    }
+                }
+                let detector = self.onsets.get_or_insert_with(|| BeatDetector::new(sample_rate));
+                if detector.process(samples).onsets > 0 {
+                    if let Some((active, _)) = self.active_note.take() {
+                        events.push(MidiEvent::note_off(channel, active, now));
+                    }
+                    events.push(MidiEvent::note_on(channel, note, self.mapping.velocity(level), now));
+                    let gate = (gate_secs.max(0.0) * sample_rate) as u64;
+                    self.active_note = Some((note, Some(self.position + gate)));
+                }
+            }
+            AudioToMidiMode::PitchToNote { min_confidence } => {
+                self.pitch_window.extend_from_slice(samples);
+                let excess = self.pitch_window.len().saturating_sub(PITCH_WINDOW);
+                self.pitch_window.drain(..excess);
+                if self.pitch_window.len() < PITCH_WINDOW {
+                    return events;
+                }
+                let note = yin(&self.pitch_window, sample_rate, &YinConfig::default())
+                    .filter(|estimate| estimate.confidence >= min_confidence)
+                    .map(|estimate| estimate.midi_note());
+                if note != self.active_note.map(|(active, _)| active) {
+                    if let Some((active, _)) = self.active_note.take() {
+                        events.push(MidiEvent::note_off(channel, active, now));
+                    }
+                    if let Some(note) = note {
+                        events.push(MidiEvent::note_on(channel, note, self.mapping.velocity(level), now));
+                        self.active_note = Some((note, None));
+                    }
+                }
+            }
+            AudioToMidiMode::BandEnergy { .. } => {
+                let Some(band) = &mut self.band else {
+                    return events;
+                };
+                let level = self.mapping.scale(band.process(samples, sample_rate));
+                let controller = self.mapping.controller;
+                if self.mapping.high_resolution && controller < 32 {
+                    let value = (level * CC_14BIT_MAX as f32).round() as u16;
+                    if self.last_value != Some(value) {
+                        events.push(MidiEvent::control_change(channel, controller, value >> 7, now));
+                        events.push(MidiEvent::control_change(channel, controller + 32, value & 0x7F, now));
+                        self.last_value = Some(value);
+                    }
+                } else {
+                    let value = (level * 127.0).round() as u16;
+                    if self.last_value != Some(value) {
+                        events.push(MidiEvent::control_change(channel, controller, value, now));
+                        self.last_value = Some(value);
+                    }
+                }
+            }
+        }
+        events
+    }
+}
+
+/// Which MIDI messages drive a parameter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MidiSource {
+    /// A controller's value; with `high_resolution`, paired with its LSB on `controller + 32`
+    ControlChange { controller: u8, high_resolution: bool },
+    /// Velocity of note ons, of one note or any; note offs return to the minimum
+    NoteVelocity { note: Option<u8> },
+    /// Note number of note ons
+    NoteNumber,
+    PitchBend,
+    Aftertouch,
+}
+
+impl Default for MidiSource {
+    fn default() -> Self {
+        MidiSource::ControlChange { controller: 1, high_resolution: false }
+    }
+}
+
+/// Shape between a normalized MIDI value and a parameter's range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum MappingCurve {
+    #[default]
+    Linear,
+    /// `x^exponent`; above 1 gives finer control near the minimum
+    Power { exponent: f32 },
+    /// Equal ratios for equal steps, e.g. for frequencies; linear unless both ends are positive
+    Exponential,
+    /// Smoothstep, flat near both ends
+    SCurve,
+    /// The maximum from half way up, otherwise the minimum
+    Toggle,
+}
+
+impl MappingCurve {
+    /// Map `x` in 0..1 onto `min..max`
+    pub fn apply(&self, x: f32, min: f32, max: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        let shaped = match *self {
+            MappingCurve::Linear => x,
+            MappingCurve::Power { exponent } => x.powf(exponent.max(f32::EPSILON)),
+            MappingCurve::Exponential if min > 0.0 && max > 0.0 => return min * (max / min).powf(x),
+            MappingCurve::Exponential => x,
+            MappingCurve::SCurve => x * x * (3.0 - 2.0 * x),
+            MappingCurve::Toggle => if x >= 0.5 { 1.0 } else { 0.0 },
+        };
+        min + (max - min) * shaped
+    }
 }
 
 /// Audio mapping configuration
@@ -233,6 +557,71 @@ pub struct AudioMapping {
     pub parameter: String,
     pub min_value: f32,
     pub max_value: f32,
+    #[serde(default)]
+    pub source: MidiSource,
+    #[serde(default)]
+    pub curve: MappingCurve,
+    /// Only events on this MIDI channel; any channel when `None`
+    #[serde(default)]
+    pub channel: Option<u8>,
+}
+
+/// One MIDI-to-parameter mapping with its 14-bit controller halves
+struct MidiToAudioConverter {
+    mapping: AudioMapping,
+    msb: u16,
+    lsb: u16,
+}
+
+impl MidiToAudioConverter {
+    fn new(mapping: AudioMapping) -> Self {
+        Self { mapping, msb: 0, lsb: 0 }
+    }
+
+    /// The parameter value an event sets, if it concerns this mapping
+    fn process(&mut self, event: &MidiEvent) -> Option<f32> {
+        if self.mapping.channel.is_some_and(|channel| channel != event.channel) {
+            return None;
+        }
+        let value = event.value.unwrap_or(0);
+        let velocity = event.velocity.unwrap_or(0);
+        let normalized = match (&self.mapping.source, &event.event_type) {
+            (MidiSource::ControlChange { controller, high_resolution }, MidiEventType::ControlChange) => {
+                let received = event.controller?;
+                if received == *controller {
+                    if !high_resolution {
+                        return Some(self.map(value.min(127) as f32 / 127.0));
+                    }
+                    // A new MSB starts a new value; the LSB refines it
+                    self.msb = value.min(127);
+                    self.lsb = 0;
+                } else if *high_resolution && *controller < 32 && received == controller + 32 {
+                    self.lsb = value.min(127);
+                } else {
+                    return None;
+                }
+                ((self.msb << 7) | self.lsb) as f32 / CC_14BIT_MAX as f32
+            }
+            (MidiSource::NoteVelocity { note }, MidiEventType::NoteOn | MidiEventType::NoteOff) => {
+                if note.is_some_and(|note| event.note != Some(note)) {
+                    return None;
+                }
+                match event.event_type {
+                    MidiEventType::NoteOn => velocity as f32 / 127.0,
+                    _ => 0.0,
+                }
+            }
+            (MidiSource::NoteNumber, MidiEventType::NoteOn) if velocity > 0 => event.note? as f32 / 127.0,
+            (MidiSource::PitchBend, MidiEventType::PitchBend) => value.min(CC_14BIT_MAX) as f32 / CC_14BIT_MAX as f32,
+            (MidiSource::Aftertouch, MidiEventType::Aftertouch) => event.value.unwrap_or(velocity as u16).min(127) as f32 / 127.0,
+            _ => return None,
+        };
+        Some(self.map(normalized))
+    }
+
+    fn map(&self, normalized: f32) -> f32 {
+        self.mapping.curve.apply(normalized, self.mapping.min_value, self.mapping.max_value)
+    }
 }
 
 /// NUWE-compatible audio-MIDI integration node
@@ -254,13 +643,11 @@ impl AudioMidiNode {
     /// Process audio-MIDI integration
     pub fn process_integration(&mut self, audio_input: &[f32], midi_events: &[MidiEvent]) -> Result<HashMap<String, Value>, Box<dyn std::error::Error>> {
         let midi_from_audio = self.processor.audio_to_midi(audio_input, "main");
-        let audio_from_midi = self.processor.midi_to_audio(midi_events, "main");
+        self.processor.midi_to_audio(midi_events, "main");
 
         let mut output = HashMap::new();
         output.insert("midi_events".to_string(), serde_json::to_value(&midi_from_audio).unwrap_or(Value::Null));
-        output.insert("audio_controls".to_string(), Value::Array(
-            audio_from_midi.iter().map(|&v| Value::Number(serde_json::Number::from_f64(v as f64).unwrap())).collect()
-        ));
+        output.insert("audio_controls".to_string(), serde_json::to_value(self.processor.parameters()).unwrap_or(Value::Null));
 
         Ok(output)
     }
@@ -270,4 +657,12 @@ impl AudioMidiNode {
         self.processor.add_audio_to_midi_mapping(audio_channel, midi_mapping);
         self.processor.add_midi_to_audio_mapping(audio_channel, audio_mapping);
     }
-}
\ No newline at end of file
+
+    pub fn processor(&self) -> &AudioMidiProcessor {
+        &self.processor
+    }
+
+    pub fn processor_mut(&mut self) -> &mut AudioMidiProcessor {
+        &mut self.processor
+    }
+}
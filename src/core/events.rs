@@ -70,6 +70,13 @@ pub enum AudioEventType {
     MidiControlChange { channel: u8, controller: u8, value: u8 },
     BeatDetected { bpm: f32, confidence: f32 },
     BeatTriggered,
+    /// Glicol code failed to parse or run; position is 1-based when known
+    GlicolError { message: String, line: Option<usize>, column: Option<usize> },
+    /// A sandboxed plugin's process died or stopped answering; it is bypassed until restarted
+    PluginCrashed { plugin: String, reason: String },
+    PluginRestarted { plugin: String, restarts: u32 },
+    /// Restarting a crashed plugin failed; `gave_up` once its restart budget is spent
+    PluginRestartFailed { plugin: String, reason: String, gave_up: bool },
 }
 
 /// Visual-specific event types
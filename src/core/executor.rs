@@ -0,0 +1,258 @@
+use bevy::prelude::*;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use crate::core::{Node, NodeId, NodeGraph, GraphError, VjEvent};
+
+/// Executes node instances in graph order, memoizing outputs on an input hash
+#[derive(Resource, Default)]
+pub struct GraphExecutor {
+    nodes: HashMap<NodeId, Box<dyn Node>>,
+    cache: HashMap<NodeId, CachedOutputs>,
+    /// Input hash of each node's last failed run; the node is not retried on the same inputs
+    failed: HashMap<NodeId, u64>,
+    stats: ExecutionStats,
+}
+
+/// Outputs of the last run of a node, keyed by the hash of what produced them
+#[derive(Debug, Clone)]
+pub struct CachedOutputs {
+    /// `None` once invalidated, forcing the next run while keeping outputs for comparison
+    pub input_hash: Option<u64>,
+    pub outputs: HashMap<String, Value>,
+}
+
+/// Counters for the most recent evaluation pass
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionStats {
+    /// Nodes whose `process` was called
+    pub processed: usize,
+    /// Dirty nodes skipped because their input hash matched the cache or a failed run
+    pub skipped: usize,
+    /// Processed nodes whose outputs equalled the cached outputs
+    pub unchanged: usize,
+}
+
+impl GraphExecutor {
+    /// Register a node instance and add it to the graph
+    pub fn insert_node(&mut self, graph: &mut NodeGraph, node: Box<dyn Node>) -> Result<NodeId, GraphError> {
+        let node_id = node.id();
+        graph.add_node(node_id)?;
+        if let Some(group) = node.input_group() {
+            graph.set_input_group(node_id, group)?;
+        }
+        self.nodes.insert(node_id, node);
+        Ok(node_id)
+    }
+
+    /// Remove a node instance from the executor and the graph
+    pub fn remove_node(&mut self, graph: &mut NodeGraph, node_id: NodeId) -> Result<Option<Box<dyn Node>>, GraphError> {
+        let downstream = graph.downstream_nodes(node_id);
+        graph.remove_node(node_id)?;
+        for downstream_node in downstream {
+            graph.mark_dirty(downstream_node);
+        }
+
+        self.cache.remove(&node_id);
+        self.failed.remove(&node_id);
+        Ok(self.nodes.remove(&node_id))
+    }
+
+    /// Get a node instance
+    pub fn node(&self, node_id: NodeId) -> Option<&dyn Node> {
+        self.nodes.get(&node_id).map(|node| node.as_ref())
+    }
+
+    /// Get a mutable node instance. Mark the node dirty after changing its parameters.
+    pub fn node_mut(&mut self, node_id: NodeId) -> Option<&mut Box<dyn Node>> {
+        self.nodes.get_mut(&node_id)
+    }
+
+    /// Cached outputs of a node from its last run
+    pub fn outputs(&self, node_id: NodeId) -> Option<&HashMap<String, Value>> {
+        self.cache.get(&node_id).map(|cached| &cached.outputs)
+    }
+
+    /// Force the next run of a node to call `process`, retrying it if it failed
+    pub fn invalidate(&mut self, node_id: NodeId) {
+        if let Some(cached) = self.cache.get_mut(&node_id) {
+            cached.input_hash = None;
+        }
+        self.failed.remove(&node_id);
+    }
+
+    /// Statistics for the last evaluation pass
+    pub fn stats(&self) -> &ExecutionStats {
+        &self.stats
+    }
+
+    /// Events raised by nodes since the last call
+    pub fn drain_events(&mut self) -> Vec<VjEvent> {
+        self.nodes.values_mut().flat_map(|node| node.drain_events()).collect()
+    }
+
+    /// Evaluate dirty nodes in topological order.
+    ///
+    /// A dirty node is skipped when the hash of its inputs and parameters matches the
+    /// cached run. Downstream nodes are only marked dirty when a node's outputs differ
+    /// from its cached outputs. Errors are returned once per failing input hash: a failed
+    /// node drops its cached outputs and is only retried when its inputs or parameters
+    /// change or it is invalidated. Non-cacheable nodes still run every evaluation, but a
+    /// repeated failure on the same inputs is not reported again.
+    pub fn evaluate(&mut self, graph: &mut NodeGraph) -> Vec<(NodeId, anyhow::Error)> {
+        let mut errors = Vec::new();
+        self.stats = ExecutionStats::default();
+
+        for node_id in graph.evaluation_order().to_vec() {
+            let Some(node) = self.nodes.get(&node_id) else {
+                continue;
+            };
+
+            let cacheable = node.is_cacheable();
+            if !cacheable {
+                graph.mark_dirty(node_id);
+            }
+            if !graph.dirty_nodes().contains(&node_id) {
+                continue;
+            }
+
+            if let Some(len) = graph.input_group_len(node_id) {
+                if let Some(node) = self.nodes.get_mut(&node_id) {
+                    node.set_input_group_len(len);
+                }
+            }
+
+            let inputs = self.gather_inputs(graph, node_id);
+            let input_hash = hash_inputs(&inputs, &self.nodes[&node_id].parameters());
+
+            let failed_before = self.failed.get(&node_id) == Some(&input_hash);
+            if cacheable {
+                let cached = self.cache.get(&node_id)
+                    .is_some_and(|cached| cached.input_hash == Some(input_hash));
+                if cached || failed_before {
+                    graph.clear_dirty(node_id);
+                    self.stats.skipped += 1;
+                    continue;
+                }
+            }
+
+            let node = self.nodes.get_mut(&node_id).expect("node checked above");
+            let outputs = match node.process(inputs) {
+                Ok(outputs) => outputs,
+                Err(error) => {
+                    graph.clear_dirty(node_id);
+                    // Downstream stops seeing the outputs of the last successful run
+                    if self.cache.remove(&node_id).is_some() {
+                        graph.mark_downstream_dirty(node_id);
+                    }
+                    self.failed.insert(node_id, input_hash);
+                    if !failed_before {
+                        errors.push((node_id, error));
+                    }
+                    continue;
+                }
+            };
+            self.stats.processed += 1;
+            self.failed.remove(&node_id);
+            graph.clear_dirty(node_id);
+
+            let changed = self.cache.get(&node_id)
+                .is_none_or(|cached| cached.outputs != outputs);
+            self.cache.insert(node_id, CachedOutputs { input_hash: Some(input_hash), outputs });
+
+            if changed {
+                graph.mark_downstream_dirty(node_id);
+            } else {
+                self.stats.unchanged += 1;
+            }
+        }
+
+        errors
+    }
+
+    /// Collect the cached upstream outputs feeding a node, keyed by input port name
+    fn gather_inputs(&self, graph: &NodeGraph, node_id: NodeId) -> HashMap<String, Value> {
+        let mut inputs = HashMap::new();
+        let input_ports = self.nodes[&node_id].inputs();
+
+        for connection in graph.get_input_connections(node_id) {
+            let (Some(upstream), Some(cached)) = (
+                self.nodes.get(&connection.from_node),
+                self.cache.get(&connection.from_node),
+            ) else {
+                continue;
+            };
+            let (Some(output_port), Some(input_port)) = (
+                upstream.outputs().get(connection.from_port).cloned(),
+                input_ports.get(connection.to_port),
+            ) else {
+                continue;
+            };
+
+            if let Some(value) = cached.outputs.get(&output_port.name) {
+                inputs.insert(input_port.name.clone(), value.clone());
+            }
+        }
+
+        inputs
+    }
+}
+
+/// Hash a node's inputs and parameters independently of map iteration order
+pub fn hash_inputs(inputs: &HashMap<String, Value>, parameters: &HashMap<String, Value>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_map(&mut hasher, inputs);
+    hash_map(&mut hasher, parameters);
+    hasher.finish()
+}
+
+fn hash_map<H: Hasher>(hasher: &mut H, map: &HashMap<String, Value>) {
+    let mut keys: Vec<_> = map.keys().collect();
+    keys.sort();
+    keys.len().hash(hasher);
+    for key in keys {
+        key.hash(hasher);
+        hash_value(hasher, &map[key]);
+    }
+}
+
+fn hash_value<H: Hasher>(hasher: &mut H, value: &Value) {
+    match value {
+        Value::Null => 0u8.hash(hasher),
+        Value::Bool(b) => {
+            1u8.hash(hasher);
+            b.hash(hasher);
+        }
+        Value::Number(n) => {
+            2u8.hash(hasher);
+            if let Some(i) = n.as_i64() {
+                i.hash(hasher);
+            } else if let Some(u) = n.as_u64() {
+                u.hash(hasher);
+            } else {
+                n.as_f64().unwrap_or(0.0).to_bits().hash(hasher);
+            }
+        }
+        Value::String(s) => {
+            3u8.hash(hasher);
+            s.hash(hasher);
+        }
+        Value::Array(items) => {
+            4u8.hash(hasher);
+            items.len().hash(hasher);
+            for item in items {
+                hash_value(hasher, item);
+            }
+        }
+        Value::Object(map) => {
+            5u8.hash(hasher);
+            map.len().hash(hasher);
+            // serde_json maps are ordered by key
+            for (key, item) in map {
+                key.hash(hasher);
+                hash_value(hasher, item);
+            }
+        }
+    }
+}
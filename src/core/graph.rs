@@ -1,9 +1,10 @@
 use bevy::prelude::*;
 use petgraph::{Graph, Direction, Directed};
 use petgraph::graph::{NodeIndex, EdgeIndex};
+use petgraph::visit::EdgeRef;
 use std::collections::{HashMap, HashSet, VecDeque};
 use serde::{Deserialize, Serialize};
-use crate::core::{NodeId, ConnectionId, DataType, VjEvent};
+use crate::core::{Node, NodeId, ConnectionId, DataType, GraphExecutor, PortGroup, SavedConnectionData, VjEvent};
 
 /// Node graph plugin
 pub struct NodeGraphPlugin;
@@ -12,10 +13,13 @@ impl Plugin for NodeGraphPlugin {
     fn build(&self, app: &mut App) {
         app
             .init_resource::<NodeGraph>()
+            .init_resource::<GraphExecutor>()
             .init_resource::<NodeRegistry>()
             .register_type::<NodePort>()
             .register_type::<NodeConnection>()
+            .add_message::<GraphCommand>()
             .add_systems(Update, (
+                apply_graph_commands,
                 evaluate_node_graph,
                 propagate_dirty_nodes,
                 update_node_positions,
@@ -40,6 +44,15 @@ pub struct NodeGraph {
     connections: HashMap<ConnectionId, NodeConnection>,
     evaluation_order: Vec<NodeId>,
     dirty_nodes: HashSet<NodeId>,
+    input_groups: HashMap<NodeId, InputGroupState>,
+}
+
+/// Current size of a node's variadic input group
+#[derive(Debug, Clone)]
+struct InputGroupState {
+    group: PortGroup,
+    /// Number of connected ports; the port at `first_index + len` is the free slot
+    len: usize,
 }
 
 impl Default for NodeGraph {
@@ -51,6 +64,7 @@ impl Default for NodeGraph {
             connections: HashMap::new(),
             evaluation_order: Vec::new(),
             dirty_nodes: HashSet::new(),
+            input_groups: HashMap::new(),
         }
     }
 }
@@ -87,17 +101,31 @@ impl NodeGraph {
             .collect();
             
         for conn_id in connections_to_remove {
-            self.connections.remove(&conn_id);
+            if let Some(connection) = self.connections.remove(&conn_id) {
+                if connection.to_node != node_id {
+                    self.release_group_slot(connection.to_node, connection.to_port);
+                    self.mark_dirty(connection.to_node);
+                }
+            }
         }
+        self.input_groups.remove(&node_id);
         
         self.graph.remove_node(index);
+        // petgraph moves the last node into the freed index
+        if let Some(&moved_node) = self.graph.node_weight(index) {
+            self.index_to_node.remove(&NodeIndex::new(self.graph.node_count()));
+            self.node_to_index.insert(moved_node, index);
+            self.index_to_node.insert(index, moved_node);
+        }
         self.dirty_nodes.remove(&node_id);
         self.update_evaluation_order();
         
         Ok(())
     }
 
-    /// Add a connection between nodes
+    /// Add a connection between nodes.
+    ///
+    /// Connecting to the free slot of a variadic input group grows the group by one port.
     pub fn add_connection(
         &mut self,
         from_node: NodeId,
@@ -105,6 +133,18 @@ impl NodeGraph {
         to_node: NodeId,
         to_port: usize,
         data_type: DataType,
+    ) -> Result<ConnectionId, GraphError> {
+        self.insert_connection(ConnectionId::new(), from_node, from_port, to_node, to_port, data_type)
+    }
+
+    fn insert_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        from_node: NodeId,
+        from_port: usize,
+        to_node: NodeId,
+        to_port: usize,
+        data_type: DataType,
     ) -> Result<ConnectionId, GraphError> {
         let from_index = self.node_to_index.get(&from_node)
             .ok_or(GraphError::NodeNotFound(from_node))?;
@@ -116,7 +156,9 @@ impl NodeGraph {
             return Err(GraphError::CycleDetected);
         }
 
-        let connection_id = ConnectionId::new();
+        let (from_index, to_index) = (*from_index, *to_index);
+        self.claim_group_slot(to_node, to_port)?;
+
         let connection = NodeConnection {
             id: connection_id,
             from_node,
@@ -126,7 +168,7 @@ impl NodeGraph {
             data_type,
         };
 
-        self.graph.add_edge(*from_index, *to_index, connection_id);
+        self.graph.add_edge(from_index, to_index, connection_id);
         self.connections.insert(connection_id, connection);
         
         self.mark_dirty(to_node);
@@ -140,44 +182,114 @@ impl NodeGraph {
         let connection = self.connections.remove(&connection_id)
             .ok_or(GraphError::ConnectionNotFound(connection_id))?;
 
-        // Find and remove the edge
+        // Find and remove the edge carrying this connection
         let from_index = self.node_to_index[&connection.from_node];
         let to_index = self.node_to_index[&connection.to_node];
         
-        if let Some(edge_index) = self.graph.find_edge(from_index, to_index) {
+        let edge_index = self.graph.edges_connecting(from_index, to_index)
+            .find(|edge| *edge.weight() == connection_id)
+            .map(|edge| edge.id());
+        if let Some(edge_index) = edge_index {
             self.graph.remove_edge(edge_index);
         }
 
+        self.release_group_slot(connection.to_node, connection.to_port);
         self.mark_dirty(connection.to_node);
         self.update_evaluation_order();
         
         Ok(())
     }
 
-    /// Mark a node as dirty for re-evaluation
-    pub fn mark_dirty(&mut self, node_id: NodeId) {
-        self.dirty_nodes.insert(node_id);
-        self.propagate_dirty_downstream(node_id);
+    /// Declare a variadic input group on a node
+    pub fn set_input_group(&mut self, node_id: NodeId, group: PortGroup) -> Result<(), GraphError> {
+        if !self.node_to_index.contains_key(&node_id) {
+            return Err(GraphError::NodeNotFound(node_id));
+        }
+
+        let len = self.connections.values()
+            .filter(|conn| conn.to_node == node_id && conn.to_port >= group.first_index)
+            .count();
+        self.input_groups.insert(node_id, InputGroupState { group, len });
+        Ok(())
     }
 
-    /// Propagate dirty flag to downstream nodes
-    fn propagate_dirty_downstream(&mut self, node_id: NodeId) {
-        if let Some(&index) = self.node_to_index.get(&node_id) {
-            let downstream_nodes: Vec<_> = self.graph
-                .neighbors_directed(index, Direction::Outgoing)
-                .filter_map(|idx| self.index_to_node.get(&idx))
-                .copied()
-                .collect();
-
-            for downstream_node in downstream_nodes {
-                if self.dirty_nodes.insert(downstream_node) {
-                    // Recursively propagate if this node wasn't already dirty
-                    self.propagate_dirty_downstream(downstream_node);
-                }
+    /// Number of connected ports in a node's variadic input group
+    pub fn input_group_len(&self, node_id: NodeId) -> Option<usize> {
+        self.input_groups.get(&node_id).map(|state| state.len)
+    }
+
+    /// Grow a variadic group when a cable lands on its free slot
+    fn claim_group_slot(&mut self, node_id: NodeId, port: usize) -> Result<(), GraphError> {
+        let Some(state) = self.input_groups.get(&node_id) else {
+            return Ok(());
+        };
+        if port < state.group.first_index {
+            return Ok(());
+        }
+
+        let slot = port - state.group.first_index;
+        if slot > state.len {
+            return Err(GraphError::InvalidPortIndex);
+        }
+        if slot < state.len {
+            return Err(GraphError::PortOccupied(port));
+        }
+
+        if let Some(state) = self.input_groups.get_mut(&node_id) {
+            state.len += 1;
+        }
+        Ok(())
+    }
+
+    /// Shrink a variadic group when a cable is removed, shifting later ports down
+    fn release_group_slot(&mut self, node_id: NodeId, port: usize) {
+        let Some(state) = self.input_groups.get_mut(&node_id) else {
+            return;
+        };
+        if port < state.group.first_index {
+            return;
+        }
+        state.len = state.len.saturating_sub(1);
+
+        for connection in self.connections.values_mut() {
+            if connection.to_node == node_id && connection.to_port > port {
+                connection.to_port -= 1;
             }
         }
     }
 
+    /// Mark a node as dirty for re-evaluation.
+    ///
+    /// Downstream nodes are not marked here; the executor marks them once this
+    /// node's outputs actually change.
+    pub fn mark_dirty(&mut self, node_id: NodeId) {
+        if self.node_to_index.contains_key(&node_id) {
+            self.dirty_nodes.insert(node_id);
+        }
+    }
+
+    /// Mark the direct downstream nodes of a node as dirty
+    pub fn mark_downstream_dirty(&mut self, node_id: NodeId) {
+        for downstream_node in self.downstream_nodes(node_id) {
+            self.dirty_nodes.insert(downstream_node);
+        }
+    }
+
+    /// Get the nodes directly fed by a node
+    pub fn downstream_nodes(&self, node_id: NodeId) -> Vec<NodeId> {
+        let Some(&index) = self.node_to_index.get(&node_id) else {
+            return Vec::new();
+        };
+
+        let mut downstream: Vec<_> = self.graph
+            .neighbors_directed(index, Direction::Outgoing)
+            .filter_map(|idx| self.index_to_node.get(&idx))
+            .copied()
+            .collect();
+        downstream.dedup();
+        downstream
+    }
+
     /// Check if adding an edge would create a cycle
     fn would_create_cycle(&self, from_index: NodeIndex, to_index: NodeIndex) -> bool {
         // Use DFS to check if 'to' can reach 'from'
@@ -272,6 +384,42 @@ impl NodeGraph {
             .filter(|conn| conn.from_node == node_id)
             .collect()
     }
+
+    /// Connections in save order: by target node and ascending port, so variadic
+    /// groups rebuild slot by slot when restored
+    pub fn saved_connections(&self) -> Vec<SavedConnectionData> {
+        let mut connections: Vec<_> = self.connections.values().collect();
+        connections.sort_by_key(|conn| (conn.to_node.0, conn.to_port, conn.from_node.0, conn.from_port));
+        connections.into_iter().map(SavedConnectionData::from).collect()
+    }
+
+    /// Restore a saved connection, keeping its id and port indices
+    pub fn restore_connection(&mut self, saved: &SavedConnectionData) -> Result<ConnectionId, GraphError> {
+        let data_type: DataType = serde_json::from_value(serde_json::Value::String(saved.data_type.clone()))
+            .map_err(|_| GraphError::PortTypeMismatch)?;
+
+        self.insert_connection(
+            ConnectionId(saved.id),
+            NodeId(saved.from_node),
+            saved.from_port,
+            NodeId(saved.to_node),
+            saved.to_port,
+            data_type,
+        )
+    }
+}
+
+impl From<&NodeConnection> for SavedConnectionData {
+    fn from(connection: &NodeConnection) -> Self {
+        Self {
+            id: connection.id.0,
+            from_node: connection.from_node.0,
+            from_port: connection.from_port,
+            to_node: connection.to_node.0,
+            to_port: connection.to_port,
+            data_type: format!("{:?}", connection.data_type),
+        }
+    }
 }
 
 /// Node connection data
@@ -339,26 +487,67 @@ pub enum GraphError {
     PortTypeMismatch,
     #[error("Invalid port index")]
     InvalidPortIndex,
+    #[error("Input port {0} is already connected")]
+    PortOccupied(usize),
+}
+
+/// Requests to add or remove node instances, applied before the graph is evaluated
+#[derive(Message)]
+pub enum GraphCommand {
+    AddNode(Box<dyn Node>),
+    RemoveNode(NodeId),
+}
+
+/// System to add and remove node instances through the executor
+fn apply_graph_commands(
+    mut commands: ResMut<Messages<GraphCommand>>,
+    mut graph: ResMut<NodeGraph>,
+    mut executor: ResMut<GraphExecutor>,
+    mut vj_events: MessageWriter<VjEvent>,
+) {
+    for command in commands.drain() {
+        match command {
+            GraphCommand::AddNode(node) => {
+                let node_type = node.name().to_string();
+                match executor.insert_node(&mut graph, node) {
+                    Ok(node_id) => {
+                        vj_events.write(VjEvent::NodeCreated { node_id, node_type });
+                    }
+                    Err(e) => error!("❌ Failed to add {} node: {}", node_type, e),
+                }
+            }
+            GraphCommand::RemoveNode(node_id) => match executor.remove_node(&mut graph, node_id) {
+                Ok(_) => {
+                    vj_events.write(VjEvent::NodeDestroyed { node_id });
+                }
+                Err(e) => error!("❌ Failed to remove node {:?}: {}", node_id, e),
+            },
+        }
+    }
 }
 
 /// System to evaluate the node graph
 fn evaluate_node_graph(
-    graph: ResMut<NodeGraph>,
-    mut _vj_events: MessageWriter<VjEvent>,
+    mut graph: ResMut<NodeGraph>,
+    mut executor: ResMut<GraphExecutor>,
+    mut vj_events: MessageWriter<VjEvent>,
 ) {
-    // Evaluate nodes in topological order
-    for &node_id in graph.evaluation_order().clone().iter() {
-        if graph.dirty_nodes().contains(&node_id) {
-            // Node evaluation happens in individual node systems
-            // This system just manages the overall flow
-            debug!("Evaluating node: {:?}", node_id);
-        }
+    // Evaluate dirty nodes in topological order, skipping ones with unchanged inputs
+    for (node_id, error) in executor.evaluate(&mut graph) {
+        warn!("Node {:?} failed to process: {}", node_id, error);
+    }
+    vj_events.write_batch(executor.drain_events());
+
+    let stats = executor.stats();
+    if stats.processed > 0 || stats.skipped > 0 {
+        debug!("Graph evaluated: {} processed, {} skipped, {} unchanged",
+               stats.processed, stats.skipped, stats.unchanged);
     }
 }
 
 /// System to propagate dirty flags
 fn propagate_dirty_nodes(_graph: ResMut<NodeGraph>) {
-    // Dirty propagation is already handled in the mark_dirty method
+    // The executor marks downstream nodes dirty when a node's outputs change
     // This system could be used for additional propagation logic
 }
 
@@ -0,0 +1,251 @@
+use bevy::prelude::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::core::{SceneData, VjError};
+
+/// Schema version written by this build. Bump it together with a new migration.
+pub const SCENE_SCHEMA_VERSION: u32 = 1;
+
+/// Which side of a node a moved port is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortDirection {
+    Input,
+    Output,
+}
+
+/// A single edit applied to a scene document
+#[derive(Debug, Clone)]
+pub enum MigrationStep {
+    /// Node type was renamed
+    RenameNodeType { from: String, to: String },
+    /// Port index changed on a node type; connections are re-pointed
+    MovePort { node_type: String, direction: PortDirection, from: usize, to: usize },
+    /// Parameter key was renamed on a node type
+    RenameParameter { node_type: String, from: String, to: String },
+    /// Arbitrary rewrite of the whole document
+    Custom(fn(&mut Value) -> Result<(), VjError>),
+}
+
+/// Upgrade from `from_version` to `from_version + 1`
+#[derive(Debug, Clone)]
+pub struct SceneMigration {
+    pub from_version: u32,
+    pub description: String,
+    pub steps: Vec<MigrationStep>,
+}
+
+impl SceneMigration {
+    pub fn new(from_version: u32, description: &str) -> Self {
+        Self {
+            from_version,
+            description: description.to_string(),
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn rename_node_type(mut self, from: &str, to: &str) -> Self {
+        self.steps.push(MigrationStep::RenameNodeType { from: from.to_string(), to: to.to_string() });
+        self
+    }
+
+    pub fn move_port(mut self, node_type: &str, direction: PortDirection, from: usize, to: usize) -> Self {
+        self.steps.push(MigrationStep::MovePort { node_type: node_type.to_string(), direction, from, to });
+        self
+    }
+
+    pub fn rename_parameter(mut self, node_type: &str, from: &str, to: &str) -> Self {
+        self.steps.push(MigrationStep::RenameParameter {
+            node_type: node_type.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+        self
+    }
+
+    pub fn custom(mut self, step: fn(&mut Value) -> Result<(), VjError>) -> Self {
+        self.steps.push(MigrationStep::Custom(step));
+        self
+    }
+
+    /// Apply every step to a scene document
+    fn apply(&self, document: &mut Value) -> Result<(), VjError> {
+        for step in &self.steps {
+            match step {
+                MigrationStep::RenameNodeType { from, to } => {
+                    for node in nodes_mut(document) {
+                        if node.get("node_type").and_then(Value::as_str) == Some(from) {
+                            node["node_type"] = Value::String(to.clone());
+                        }
+                    }
+                }
+                MigrationStep::MovePort { node_type, direction, from, to } => {
+                    let node_ids = node_ids_of_type(document, node_type);
+                    let (node_key, port_key) = match direction {
+                        PortDirection::Input => ("to_node", "to_port"),
+                        PortDirection::Output => ("from_node", "from_port"),
+                    };
+                    for connection in connections_mut(document) {
+                        let on_node = connection.get(node_key)
+                            .and_then(Value::as_str)
+                            .is_some_and(|id| node_ids.iter().any(|node_id| node_id == id));
+                        if on_node && connection.get(port_key).and_then(Value::as_u64) == Some(*from as u64) {
+                            connection[port_key] = Value::from(*to);
+                        }
+                    }
+                }
+                MigrationStep::RenameParameter { node_type, from, to } => {
+                    for node in nodes_mut(document) {
+                        if node.get("node_type").and_then(Value::as_str) != Some(node_type) {
+                            continue;
+                        }
+                        if let Some(parameters) = node.get_mut("parameters").and_then(Value::as_object_mut) {
+                            if let Some(value) = parameters.remove(from) {
+                                parameters.insert(to.clone(), value);
+                            }
+                        }
+                    }
+                }
+                MigrationStep::Custom(step) => step(document)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn nodes_mut(document: &mut Value) -> impl Iterator<Item = &mut Value> {
+    document.get_mut("nodes")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+}
+
+fn connections_mut(document: &mut Value) -> impl Iterator<Item = &mut Value> {
+    document.get_mut("connections")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+}
+
+fn node_ids_of_type(document: &Value, node_type: &str) -> Vec<String> {
+    document.get("nodes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|node| node.get("node_type").and_then(Value::as_str) == Some(node_type))
+        .filter_map(|node| node.get("id").and_then(Value::as_str).map(str::to_string))
+        .collect()
+}
+
+/// Registry of scene migrations, applied step by step when loading older files
+#[derive(Resource, Debug, Clone)]
+pub struct SceneMigrations {
+    migrations: HashMap<u32, SceneMigration>,
+    current_version: u32,
+}
+
+impl Default for SceneMigrations {
+    fn default() -> Self {
+        let mut registry = Self {
+            migrations: HashMap::new(),
+            current_version: SCENE_SCHEMA_VERSION,
+        };
+
+        // Files saved before versioning have no schema_version field
+        registry.register(SceneMigration::new(0, "Add schema version"));
+        registry
+    }
+}
+
+impl SceneMigrations {
+    /// Registry targeting a specific version, without the built-in migrations
+    pub fn with_version(current_version: u32) -> Self {
+        Self {
+            migrations: HashMap::new(),
+            current_version,
+        }
+    }
+
+    pub fn register(&mut self, migration: SceneMigration) {
+        self.migrations.insert(migration.from_version, migration);
+    }
+
+    pub fn current_version(&self) -> u32 {
+        self.current_version
+    }
+
+    /// Upgrade a scene document to the current version
+    pub fn migrate(&self, mut document: Value) -> Result<Value, VjError> {
+        let mut version = document.get("schema_version")
+            .and_then(Value::as_u64)
+            .unwrap_or(0) as u32;
+
+        if version > self.current_version {
+            return Err(VjError::FileError(format!(
+                "Scene schema version {} is newer than supported version {}; update the application to open it",
+                version, self.current_version
+            )));
+        }
+
+        while version < self.current_version {
+            let migration = self.migrations.get(&version).ok_or_else(|| VjError::FileError(format!(
+                "No migration registered from scene schema version {}", version
+            )))?;
+            migration.apply(&mut document)?;
+            version += 1;
+            document["schema_version"] = Value::from(version);
+            debug!("Migrated scene to schema version {}: {}", version, migration.description);
+        }
+
+        Ok(document)
+    }
+
+    /// Parse and migrate a JSON scene
+    pub fn load_scene_str(&self, source: &str) -> Result<SceneData, VjError> {
+        let document: Value = serde_json::from_str(source)
+            .map_err(|e| VjError::FileError(format!("Invalid scene file: {}", e)))?;
+        self.load_scene_value(document)
+    }
+
+    /// Load and migrate a scene file; `.ron` files are read as RON, anything else as JSON
+    pub fn load_scene(&self, path: &Path) -> Result<SceneData, VjError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| VjError::FileError(format!("Failed to read {}: {}", path.display(), e)))?;
+
+        let document: Value = if is_ron(path) {
+            ron::from_str(&source)
+                .map_err(|e| VjError::FileError(format!("Invalid scene file {}: {}", path.display(), e)))?
+        } else {
+            serde_json::from_str(&source)
+                .map_err(|e| VjError::FileError(format!("Invalid scene file {}: {}", path.display(), e)))?
+        };
+        self.load_scene_value(document)
+    }
+
+    /// Save a scene at this registry's current version; `.ron` paths are written as RON
+    pub fn save_scene(&self, scene: &SceneData, path: &Path) -> Result<(), VjError> {
+        let mut scene = scene.clone();
+        scene.schema_version = self.current_version;
+
+        let source = if is_ron(path) {
+            ron::ser::to_string_pretty(&scene, ron::ser::PrettyConfig::default())
+                .map_err(|e| VjError::FileError(e.to_string()))?
+        } else {
+            serde_json::to_string_pretty(&scene)
+                .map_err(|e| VjError::FileError(e.to_string()))?
+        };
+
+        std::fs::write(path, source)
+            .map_err(|e| VjError::FileError(format!("Failed to write {}: {}", path.display(), e)))
+    }
+
+    fn load_scene_value(&self, document: Value) -> Result<SceneData, VjError> {
+        let document = self.migrate(document)?;
+        serde_json::from_value(document)
+            .map_err(|e| VjError::FileError(format!("Scene does not match schema version {}: {}", self.current_version, e)))
+    }
+}
+
+fn is_ron(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some("ron")
+}
@@ -7,11 +7,15 @@ use anyhow::Result;
 pub mod graph;
 pub mod resources;
 pub mod events;
+pub mod executor;
+pub mod migrations;
 mod tests;
 
 pub use graph::*;
 pub use resources::*;
 pub use events::*;
+pub use executor::*;
+pub use migrations::*;
 
 /// VJ system error types
 #[derive(Debug, Clone)]
@@ -55,7 +59,9 @@ impl Plugin for VjCorePlugin {
         app
             .init_resource::<VjSystemState>()
             .init_resource::<PerformanceMetrics>()
+            .init_resource::<SceneMigrations>()
             .add_message::<VjEvent>()
+            .add_message::<SceneEvent>()
             .register_type::<NodeId>()
             .register_type::<ConnectionId>()
             .add_systems(Update, (
@@ -139,6 +145,32 @@ pub trait Node: Send + Sync {
     fn inputs(&self) -> Vec<InputPort>;
     fn outputs(&self) -> Vec<OutputPort>;
     fn process(&mut self, inputs: HashMap<String, serde_json::Value>) -> Result<HashMap<String, serde_json::Value>>;
+
+    /// Parameters that affect the output besides the inputs; hashed for memoization
+    fn parameters(&self) -> HashMap<String, serde_json::Value> {
+        HashMap::new()
+    }
+
+    /// Variadic input group that grows as cables are connected to its last port
+    fn input_group(&self) -> Option<PortGroup> {
+        None
+    }
+
+    /// Called with the number of connected ports in the input group before processing.
+    /// `inputs()` should list that many group ports plus one free slot.
+    fn set_input_group_len(&mut self, _len: usize) {}
+
+    /// Whether outputs depend only on inputs and parameters. Nodes that keep state
+    /// between runs or read time should return false so they run every evaluation.
+    fn is_cacheable(&self) -> bool {
+        true
+    }
+
+    /// Events raised since the last call, such as a hosted plugin crashing. Published
+    /// after every graph evaluation.
+    fn drain_events(&mut self) -> Vec<VjEvent> {
+        Vec::new()
+    }
 }
 
 /// Input port definition for nodes
@@ -167,6 +199,39 @@ impl InputPort {
     }
 }
 
+/// Dynamic group of input ports named `{prefix}{n}`, e.g. `layer_0`, `layer_1`, ...
+///
+/// The group occupies every input from `first_index` onwards and always ends with
+/// one unconnected slot; connecting to that slot grows the group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortGroup {
+    pub prefix: String,
+    pub data_type: DataType,
+    pub first_index: usize,
+}
+
+impl PortGroup {
+    pub fn new(prefix: &str, data_type: DataType, first_index: usize) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            data_type,
+            first_index,
+        }
+    }
+
+    /// Name of the port at a slot within the group
+    pub fn port_name(&self, slot: usize) -> String {
+        format!("{}{}", self.prefix, slot)
+    }
+
+    /// Input ports for a group with `len` connected ports plus the free slot
+    pub fn ports(&self, len: usize) -> Vec<InputPort> {
+        (0..=len)
+            .map(|slot| InputPort::optional(&self.port_name(slot), self.data_type.clone()))
+            .collect()
+    }
+}
+
 /// Output port definition for nodes
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OutputPort {
@@ -183,6 +248,45 @@ impl OutputPort {
     }
 }
 
+/// Interleaved audio carried on `AudioBuffer` ports
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AudioBufferData {
+    pub samples: Vec<f32>,
+    pub channels: usize,
+    pub sample_rate: f32,
+}
+
+impl AudioBufferData {
+    pub fn new(samples: Vec<f32>, channels: usize, sample_rate: f32) -> Self {
+        Self { samples, channels, sample_rate }
+    }
+
+    /// Read a port value: either a buffer object or a bare array of mono samples
+    pub fn from_value(value: &serde_json::Value, default_sample_rate: f32) -> Option<Self> {
+        if let Some(samples) = value.as_array() {
+            let samples = samples.iter().map(|v| v.as_f64().unwrap_or(0.0) as f32).collect();
+            return Some(Self::new(samples, 1, default_sample_rate));
+        }
+        serde_json::from_value(value.clone()).ok()
+    }
+
+    pub fn to_value(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or(serde_json::Value::Null)
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1)
+    }
+
+    /// Average the channels into one
+    pub fn to_mono(&self) -> Vec<f32> {
+        let channels = self.channels.max(1);
+        self.samples.chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect()
+    }
+}
+
 /// System for updating performance metrics
 fn update_performance_metrics(
     time: Res<Time>,
@@ -92,6 +92,9 @@ impl SceneManager {
 /// Data for a complete VJ scene
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SceneData {
+    /// Schema version the file was written with; absent in files older than versioning
+    #[serde(default)]
+    pub schema_version: u32,
     pub name: String,
     pub description: String,
     pub nodes: Vec<SavedNodeData>,
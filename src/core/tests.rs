@@ -180,4 +180,366 @@ mod tests {
         // Test that NodeId wraps a UUID
         assert_eq!(node_id.0, 42);
     }
-}
\ No newline at end of file
+
+    /// Node that adds its `offset` parameter to its input and counts its runs
+    struct CountingNode {
+        id: NodeId,
+        offset: f64,
+        runs: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        /// Runs that fail before the node starts succeeding
+        failures: usize,
+    }
+
+    impl CountingNode {
+        fn new(offset: f64) -> (Self, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+            let runs = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            (Self { id: NodeId::new(), offset, runs: runs.clone(), failures: 0 }, runs)
+        }
+    }
+
+    impl crate::core::Node for CountingNode {
+        fn id(&self) -> NodeId {
+            self.id
+        }
+
+        fn name(&self) -> &str {
+            "Counting"
+        }
+
+        fn inputs(&self) -> Vec<crate::core::InputPort> {
+            vec![crate::core::InputPort::optional("value", crate::core::DataType::Float)]
+        }
+
+        fn outputs(&self) -> Vec<crate::core::OutputPort> {
+            vec![crate::core::OutputPort::new("value", crate::core::DataType::Float)]
+        }
+
+        fn process(&mut self, inputs: std::collections::HashMap<String, serde_json::Value>) -> anyhow::Result<std::collections::HashMap<String, serde_json::Value>> {
+            if self.runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < self.failures {
+                anyhow::bail!("counting node failed");
+            }
+            let value = inputs.get("value").and_then(|v| v.as_f64()).unwrap_or(0.0);
+            let mut outputs = std::collections::HashMap::new();
+            outputs.insert("value".to_string(), serde_json::json!(value + self.offset));
+            Ok(outputs)
+        }
+
+        fn parameters(&self) -> std::collections::HashMap<String, serde_json::Value> {
+            let mut parameters = std::collections::HashMap::new();
+            parameters.insert("offset".to_string(), serde_json::json!(self.offset));
+            parameters
+        }
+    }
+
+    #[test]
+    fn test_executor_skips_unchanged_inputs() {
+        let mut graph = NodeGraph::default();
+        let mut executor = crate::core::GraphExecutor::default();
+
+        let (source, source_runs) = CountingNode::new(1.0);
+        let (sink, sink_runs) = CountingNode::new(0.0);
+        let source = executor.insert_node(&mut graph, Box::new(source)).unwrap();
+        let sink = executor.insert_node(&mut graph, Box::new(sink)).unwrap();
+        graph.add_connection(source, 0, sink, 0, crate::core::DataType::Float).unwrap();
+
+        assert!(executor.evaluate(&mut graph).is_empty());
+        assert_eq!(source_runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(sink_runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(executor.outputs(sink).unwrap()["value"], serde_json::json!(1.0));
+
+        // Dirty again but nothing changed: both nodes are skipped
+        graph.mark_dirty(source);
+        graph.mark_dirty(sink);
+        executor.evaluate(&mut graph);
+        assert_eq!(source_runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(sink_runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(executor.stats().skipped, 2);
+        assert!(graph.dirty_nodes().is_empty());
+    }
+
+    #[test]
+    fn test_executor_stops_propagation_on_equal_outputs() {
+        let mut graph = NodeGraph::default();
+        let mut executor = crate::core::GraphExecutor::default();
+
+        let (source, source_runs) = CountingNode::new(1.0);
+        let (sink, sink_runs) = CountingNode::new(0.0);
+        let source = executor.insert_node(&mut graph, Box::new(source)).unwrap();
+        let sink = executor.insert_node(&mut graph, Box::new(sink)).unwrap();
+        graph.add_connection(source, 0, sink, 0, crate::core::DataType::Float).unwrap();
+        executor.evaluate(&mut graph);
+
+        // Invalidate the source so it re-runs; its output is identical so the sink stays clean
+        executor.invalidate(source);
+        graph.mark_dirty(source);
+        executor.evaluate(&mut graph);
+        assert_eq!(source_runs.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(sink_runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(executor.stats().unchanged, 1);
+    }
+
+    #[test]
+    fn test_executor_reruns_on_parameter_change() {
+        let mut graph = NodeGraph::default();
+        let mut executor = crate::core::GraphExecutor::default();
+
+        let (source, source_runs) = CountingNode::new(1.0);
+        let (sink, sink_runs) = CountingNode::new(0.0);
+        let source_id = source.id;
+        let source = executor.insert_node(&mut graph, Box::new(source)).unwrap();
+        let sink = executor.insert_node(&mut graph, Box::new(sink)).unwrap();
+        graph.add_connection(source, 0, sink, 0, crate::core::DataType::Float).unwrap();
+        executor.evaluate(&mut graph);
+
+        // Replace the source with a different offset under the same id
+        executor.remove_node(&mut graph, source_id).unwrap();
+        let (mut changed, changed_runs) = CountingNode::new(2.0);
+        changed.id = source_id;
+        executor.insert_node(&mut graph, Box::new(changed)).unwrap();
+        graph.add_connection(source_id, 0, sink, 0, crate::core::DataType::Float).unwrap();
+        executor.evaluate(&mut graph);
+
+        assert_eq!(source_runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(changed_runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(sink_runs.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(executor.outputs(sink).unwrap()["value"], serde_json::json!(2.0));
+    }
+
+    #[test]
+    fn test_executor_retries_failed_node_on_new_inputs() {
+        let mut graph = NodeGraph::default();
+        let mut executor = crate::core::GraphExecutor::default();
+
+        let (first, _) = CountingNode::new(1.0);
+        let (second, _) = CountingNode::new(2.0);
+        let (mut sink, sink_runs) = CountingNode::new(0.0);
+        sink.failures = 2;
+        let first = executor.insert_node(&mut graph, Box::new(first)).unwrap();
+        let second = executor.insert_node(&mut graph, Box::new(second)).unwrap();
+        let sink = executor.insert_node(&mut graph, Box::new(sink)).unwrap();
+        let connection = graph.add_connection(first, 0, sink, 0, crate::core::DataType::Float).unwrap();
+
+        let errors = executor.evaluate(&mut graph);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, sink);
+        assert!(executor.outputs(sink).is_none());
+
+        // Same inputs: the failed node is neither retried nor reported again
+        graph.mark_dirty(sink);
+        assert!(executor.evaluate(&mut graph).is_empty());
+        assert_eq!(sink_runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(graph.dirty_nodes().is_empty());
+
+        // New inputs: retried, and the new failure is reported
+        graph.remove_connection(connection).unwrap();
+        graph.add_connection(second, 0, sink, 0, crate::core::DataType::Float).unwrap();
+        assert_eq!(executor.evaluate(&mut graph).len(), 1);
+        assert_eq!(sink_runs.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // Invalidating retries on the same inputs
+        executor.invalidate(sink);
+        graph.mark_dirty(sink);
+        assert!(executor.evaluate(&mut graph).is_empty());
+        assert_eq!(sink_runs.load(std::sync::atomic::Ordering::SeqCst), 3);
+        assert_eq!(executor.outputs(sink).unwrap()["value"], serde_json::json!(2.0));
+    }
+
+    #[test]
+    fn test_graph_commands_add_and_remove_nodes() {
+        let mut app = App::new();
+        app.add_message::<VjEvent>().add_plugins(crate::core::NodeGraphPlugin);
+
+        let (node, runs) = CountingNode::new(1.0);
+        let node_id = node.id;
+        app.world_mut().write_message(crate::core::GraphCommand::AddNode(Box::new(node)));
+        app.update();
+
+        let executor = app.world().resource::<crate::core::GraphExecutor>();
+        assert_eq!(executor.outputs(node_id).unwrap()["value"], serde_json::json!(1.0));
+        assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        let events = app.world().resource::<Messages<VjEvent>>();
+        assert!(events.iter_current_update_messages()
+            .any(|event| matches!(event, VjEvent::NodeCreated { node_id: id, node_type } if *id == node_id && node_type == "Counting")));
+
+        app.world_mut().write_message(crate::core::GraphCommand::RemoveNode(node_id));
+        app.update();
+
+        assert!(app.world().resource::<crate::core::GraphExecutor>().node(node_id).is_none());
+        assert!(!app.world().resource::<NodeGraph>().evaluation_order().contains(&node_id));
+        let events = app.world().resource::<Messages<VjEvent>>();
+        assert!(events.iter_current_update_messages()
+            .any(|event| matches!(event, VjEvent::NodeDestroyed { node_id: id } if *id == node_id)));
+    }
+
+    #[test]
+    fn test_input_hash_is_order_independent() {
+        let mut a = std::collections::HashMap::new();
+        a.insert("x".to_string(), serde_json::json!(1.0));
+        a.insert("y".to_string(), serde_json::json!([1, 2, 3]));
+        let mut b = std::collections::HashMap::new();
+        b.insert("y".to_string(), serde_json::json!([1, 2, 3]));
+        b.insert("x".to_string(), serde_json::json!(1.0));
+        let empty = std::collections::HashMap::new();
+
+        assert_eq!(crate::core::hash_inputs(&a, &empty), crate::core::hash_inputs(&b, &empty));
+        b.insert("x".to_string(), serde_json::json!(1.5));
+        assert_ne!(crate::core::hash_inputs(&a, &empty), crate::core::hash_inputs(&b, &empty));
+    }
+
+    #[test]
+    fn test_variadic_group_grows_and_shrinks() {
+        let mut graph = NodeGraph::default();
+        let mut executor = crate::core::GraphExecutor::default();
+
+        let mixer = executor.insert_node(&mut graph, Box::new(crate::nodes::MixerNode::new(crate::nodes::MixMode::Sum))).unwrap();
+        let sources: Vec<_> = (1..=3)
+            .map(|i| executor.insert_node(&mut graph, Box::new(CountingNode::new(i as f64).0)).unwrap())
+            .collect();
+
+        assert_eq!(graph.input_group_len(mixer), Some(0));
+        // Only the free slot accepts a new cable
+        assert!(matches!(
+            graph.add_connection(sources[0], 0, mixer, 1, crate::core::DataType::Float),
+            Err(crate::core::GraphError::InvalidPortIndex)
+        ));
+
+        let connections: Vec<_> = sources.iter().enumerate()
+            .map(|(slot, &source)| graph.add_connection(source, 0, mixer, slot, crate::core::DataType::Float).unwrap())
+            .collect();
+        assert_eq!(graph.input_group_len(mixer), Some(3));
+        assert!(matches!(
+            graph.add_connection(sources[0], 0, mixer, 1, crate::core::DataType::Float),
+            Err(crate::core::GraphError::PortOccupied(1))
+        ));
+
+        executor.evaluate(&mut graph);
+        assert_eq!(executor.node(mixer).unwrap().inputs().len(), 4);
+        assert_eq!(executor.outputs(mixer).unwrap()["output"], serde_json::json!(6.0));
+
+        // Removing the middle cable shifts the last one down to layer_1
+        graph.remove_connection(connections[1]).unwrap();
+        assert_eq!(graph.input_group_len(mixer), Some(2));
+        let mut ports: Vec<_> = graph.get_input_connections(mixer).iter().map(|conn| (conn.to_port, conn.from_node)).collect();
+        ports.sort_by_key(|(port, _)| *port);
+        assert_eq!(ports, vec![(0, sources[0]), (1, sources[2])]);
+
+        executor.evaluate(&mut graph);
+        assert_eq!(executor.node(mixer).unwrap().inputs().len(), 3);
+        assert_eq!(executor.outputs(mixer).unwrap()["output"], serde_json::json!(4.0));
+
+        // Removing a source node releases its slot too
+        executor.remove_node(&mut graph, sources[0]).unwrap();
+        assert_eq!(graph.input_group_len(mixer), Some(1));
+        assert_eq!(graph.get_input_connections(mixer)[0].to_port, 0);
+    }
+
+    #[test]
+    fn test_variadic_connections_restore_from_saved_data() {
+        let mut graph = NodeGraph::default();
+        let mixer = NodeId::new();
+        let sources = [NodeId::new(), NodeId::new()];
+        graph.add_node(mixer).unwrap();
+        for &source in &sources {
+            graph.add_node(source).unwrap();
+        }
+        graph.set_input_group(mixer, crate::core::PortGroup::new("layer_", crate::core::DataType::Float, 0)).unwrap();
+        for (slot, &source) in sources.iter().enumerate() {
+            graph.add_connection(source, 0, mixer, slot, crate::core::DataType::Float).unwrap();
+        }
+
+        let saved = graph.saved_connections();
+        assert_eq!(saved.iter().map(|conn| conn.to_port).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(saved[0].data_type, "Float");
+
+        let mut restored = NodeGraph::default();
+        restored.add_node(mixer).unwrap();
+        for &source in &sources {
+            restored.add_node(source).unwrap();
+        }
+        restored.set_input_group(mixer, crate::core::PortGroup::new("layer_", crate::core::DataType::Float, 0)).unwrap();
+        for connection in &saved {
+            let id = restored.restore_connection(connection).unwrap();
+            assert_eq!(id.0, connection.id);
+        }
+        assert_eq!(restored.input_group_len(mixer), Some(2));
+    }
+
+    fn legacy_scene_json() -> String {
+        let (oscillator, mixer) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        serde_json::json!({
+            "name": "Opening",
+            "description": "Saved before schema versioning",
+            "nodes": [
+                { "id": oscillator, "node_type": "Oscilator", "position": [0.0, 0.0], "parameters": { "freq": 440.0 } },
+                { "id": mixer, "node_type": "Mixer", "position": [200.0, 0.0], "parameters": {} }
+            ],
+            "connections": [
+                { "id": uuid::Uuid::new_v4(), "from_node": oscillator, "from_port": 1, "to_node": mixer, "to_port": 0, "data_type": "Float" }
+            ],
+            "parameters": {},
+            "created_at": "2025-01-01T00:00:00Z",
+            "modified_at": "2025-01-01T00:00:00Z"
+        }).to_string()
+    }
+
+    /// Registry with steps past `SCENE_SCHEMA_VERSION`
+    fn version_3_migrations() -> crate::core::SceneMigrations {
+        let mut migrations = crate::core::SceneMigrations::with_version(3);
+        migrations.register(crate::core::SceneMigration::new(0, "Add schema version"));
+        migrations.register(crate::core::SceneMigration::new(1, "Fix oscillator name")
+            .rename_node_type("Oscilator", "Oscillator"));
+        migrations.register(crate::core::SceneMigration::new(2, "Oscillator frequency moved to output 0")
+            .move_port("Oscillator", crate::core::PortDirection::Output, 1, 0)
+            .rename_parameter("Oscillator", "freq", "frequency"));
+        migrations
+    }
+
+    #[test]
+    fn test_scene_migrations_apply_in_order() {
+        let migrations = version_3_migrations();
+        let scene = migrations.load_scene_str(&legacy_scene_json()).unwrap();
+        assert_eq!(scene.schema_version, 3);
+        assert_eq!(scene.nodes[0].node_type, "Oscillator");
+        assert_eq!(scene.nodes[0].parameters["frequency"], serde_json::json!(440.0));
+        assert!(!scene.nodes[0].parameters.contains_key("freq"));
+        assert_eq!(scene.connections[0].from_port, 0);
+    }
+
+    #[test]
+    fn test_scene_from_newer_version_is_rejected() {
+        let migrations = crate::core::SceneMigrations::default();
+        let mut document: serde_json::Value = serde_json::from_str(&legacy_scene_json()).unwrap();
+        document["schema_version"] = serde_json::json!(crate::core::SCENE_SCHEMA_VERSION + 1);
+
+        let result = migrations.load_scene_str(&document.to_string());
+        assert!(matches!(result, Err(crate::VjError::FileError(_))));
+    }
+
+    #[test]
+    fn test_scene_missing_migration_is_reported() {
+        let migrations = crate::core::SceneMigrations::with_version(2);
+        assert!(matches!(
+            migrations.load_scene_str(&legacy_scene_json()),
+            Err(crate::VjError::FileError(_))
+        ));
+    }
+
+    #[test]
+    fn test_scene_save_round_trip() {
+        let dir = std::env::temp_dir();
+
+        for migrations in [crate::core::SceneMigrations::default(), version_3_migrations()] {
+            let scene = migrations.load_scene_str(&legacy_scene_json()).unwrap();
+            for extension in ["json", "ron"] {
+                let path = dir.join(format!("nuwe_scene_{}.{}", uuid::Uuid::new_v4(), extension));
+                migrations.save_scene(&scene, &path).unwrap();
+                let loaded = migrations.load_scene(&path).unwrap();
+                std::fs::remove_file(&path).unwrap();
+
+                // Saved at the registry's version, so reloading runs no migrations
+                assert_eq!(loaded.schema_version, migrations.current_version());
+                assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&scene).unwrap());
+            }
+        }
+    }
+}
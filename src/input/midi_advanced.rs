@@ -1,29 +1,428 @@
-use bevy::prelude::*;
-use midir::{MidiInput, MidiOutput};
-use std::collections::HashMap;
+//! MIDI input
+//!
+//! `MidiSystem` enumerates input ports, connects to them by name or wildcard pattern and
+//! turns their bytes into `InputEvent`s. Each port has its own `MidiParser`, which
+//! handles running status, SysEx split across packets and realtime bytes interleaved
+//! with other messages. Ports are rescanned periodically: an unplugged device is
+//! dropped, and reconnected when a port with the same device name comes back.
+
 use anyhow::Result;
+use bevy::prelude::*;
+use midir::{Ignore, MidiInput};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use crate::core::{AudioEventType, VjError, VjEvent};
+use crate::input::{InputEvent, InputSystemManager};
+
+/// Longest SysEx message kept; longer ones are dropped
+pub const MAX_SYSEX_BYTES: usize = 64 * 1024;
+
+/// Turns a MIDI byte stream into input events
+#[derive(Debug, Clone, Default)]
+pub struct MidiParser {
+    /// Status of the message being read; channel statuses stay for running status
+    status: Option<u8>,
+    data: [u8; 2],
+    data_len: usize,
+    sysex: Option<Vec<u8>>,
+}
+
+/// Data bytes following a status byte
+fn data_length(status: u8) -> usize {
+    match status {
+        0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+        0x80..=0xEF | 0xF2 => 2,
+        _ => 0,
+    }
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse bytes, returning the messages they complete
+    pub fn parse(&mut self, bytes: &[u8]) -> Vec<InputEvent> {
+        bytes.iter().filter_map(|&byte| self.push(byte)).collect()
+    }
+
+    /// Feed one byte
+    pub fn push(&mut self, byte: u8) -> Option<InputEvent> {
+        match byte {
+            // Realtime bytes (clock, start, stop, active sensing) may appear anywhere and
+            // leave the message around them intact
+            0xF8..=0xFF => None,
+            0xF0 => {
+                self.status = None;
+                self.sysex = Some(vec![0xF0]);
+                None
+            }
+            0xF7 => {
+                let mut data = self.sysex.take()?;
+                data.push(0xF7);
+                Some(InputEvent::MidiSysEx { data })
+            }
+            0x80..=0xF6 => {
+                // Any other status ends an unterminated SysEx, which is dropped
+                self.sysex = None;
+                self.status = (data_length(byte) > 0).then_some(byte);
+                self.data_len = 0;
+                None
+            }
+            data => {
+                if let Some(sysex) = &mut self.sysex {
+                    if sysex.len() < MAX_SYSEX_BYTES {
+                        sysex.push(data);
+                    } else {
+                        self.sysex = None;
+                    }
+                    return None;
+                }
+
+                let status = self.status?;
+                self.data[self.data_len] = data;
+                self.data_len += 1;
+                if self.data_len < data_length(status) {
+                    return None;
+                }
+                self.data_len = 0;
+                if status >= 0xF0 {
+                    // System common messages have no running status and no event
+                    self.status = None;
+                    return None;
+                }
+                Some(Self::channel_message(status, self.data))
+            }
+        }
+    }
+
+    fn channel_message(status: u8, data: [u8; 2]) -> InputEvent {
+        let channel = status & 0x0F;
+        match status & 0xF0 {
+            0x90 if data[1] > 0 => InputEvent::MidiNoteOn { channel, note: data[0], velocity: data[1] },
+            0x80 | 0x90 => InputEvent::MidiNoteOff { channel, note: data[0] },
+            0xA0 => InputEvent::MidiPolyAftertouch { channel, note: data[0], pressure: data[1] },
+            0xB0 => InputEvent::MidiControlChange { channel, controller: data[0], value: data[1] },
+            0xC0 => InputEvent::MidiProgramChange { channel, program: data[0] },
+            0xD0 => InputEvent::MidiAftertouch { channel, pressure: data[0] },
+            _ => InputEvent::MidiPitchBend { channel, value: data[0] as u16 | (data[1] as u16) << 7 },
+        }
+    }
+}
+
+/// Device part of a port name: ALSA appends client and port numbers (e.g. "Launchpad
+/// MIDI 1 24:0") that change when the device is plugged in again
+pub fn midi_device_name(port_name: &str) -> &str {
+    let trimmed = port_name.trim_end();
+    if let Some((device, suffix)) = trimmed.rsplit_once(' ') {
+        let mut numbers = suffix.split(':');
+        let is_number = |part: Option<&str>| part.is_some_and(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+        if is_number(numbers.next()) && is_number(numbers.next()) && numbers.next().is_none() {
+            return device.trim_end();
+        }
+    }
+    trimmed
+}
+
+/// Match a port name against a pattern: `*` matches any run of characters, and a
+/// pattern without `*` matches any name containing it. Case is ignored.
+pub fn midi_port_matches(pattern: &str, port_name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let name = port_name.to_lowercase();
+    if !pattern.contains('*') {
+        return name.contains(&pattern);
+    }
+
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || !name[first.len()..].ends_with(last) {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Keeps a port open until dropped
+pub type MidiPortConnection = Box<dyn Send>;
+
+/// Receives a port's bytes, from any thread
+pub type MidiBytesCallback = Box<dyn FnMut(&[u8]) + Send>;
+
+/// Where MIDI input ports come from: midir in the app, a virtual source in tests
+pub trait MidiPortProvider: Send {
+    fn port_names(&mut self) -> Result<Vec<String>, VjError>;
+
+    /// Open a port and pass its bytes to `on_bytes`, from any thread, until the returned
+    /// connection is dropped
+    fn open(&mut self, port_name: &str, on_bytes: MidiBytesCallback) -> Result<MidiPortConnection, VjError>;
+}
+
+/// System MIDI inputs through midir
+pub struct MidirPorts {
+    client_name: String,
+}
+
+impl MidirPorts {
+    pub fn new(client_name: &str) -> Self {
+        Self { client_name: client_name.to_string() }
+    }
+
+    fn client(&self) -> Result<MidiInput, VjError> {
+        let mut input = MidiInput::new(&self.client_name)
+            .map_err(|e| VjError::AudioError(format!("MIDI is unavailable: {}", e)))?;
+        input.ignore(Ignore::None);
+        Ok(input)
+    }
+}
+
+impl Default for MidirPorts {
+    fn default() -> Self {
+        Self::new("nuwe")
+    }
+}
+
+impl MidiPortProvider for MidirPorts {
+    fn port_names(&mut self) -> Result<Vec<String>, VjError> {
+        let input = self.client()?;
+        Ok(input.ports().iter().filter_map(|port| input.port_name(port).ok()).collect())
+    }
+
+    fn open(&mut self, port_name: &str, mut on_bytes: MidiBytesCallback) -> Result<MidiPortConnection, VjError> {
+        let input = self.client()?;
+        let port = input.ports().into_iter()
+            .find(|port| input.port_name(port).is_ok_and(|name| name == port_name))
+            .ok_or_else(|| VjError::AudioError(format!("MIDI port '{}' not found", port_name)))?;
+        let connection = input.connect(&port, &format!("{} in", self.client_name), move |_, bytes, _| on_bytes(bytes), ())
+            .map_err(|e| VjError::AudioError(format!("Failed to open MIDI port '{}': {}", port_name, e)))?;
+        Ok(Box::new(connection))
+    }
+}
+
+/// Connection changes found by a rescan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MidiDeviceChange {
+    Connected(String),
+    Disconnected(String),
+    /// A device came back under the same name after being unplugged
+    Reconnected(String),
+}
+
+/// An input port the system has opened
+struct MidiInputPort {
+    port_name: String,
+    connection: Option<MidiPortConnection>,
+}
 
 #[derive(Resource)]
 pub struct MidiSystem {
     pub enabled: bool,
-    pub input_connections: HashMap<String, MidiInput>,
-    pub output_connections: HashMap<String, MidiOutput>,
+    /// Ports to connect, by name or pattern, including devices plugged in later
+    pub patterns: Vec<String>,
+    /// Ports never connected by a pattern, e.g. loopback ports that would echo output
+    pub exclude: Vec<String>,
+    /// Seconds between scans for plugged and unplugged devices
+    pub rescan_interval_secs: f32,
+    ports: Mutex<Box<dyn MidiPortProvider>>,
+    inputs: Mutex<Vec<MidiInputPort>>,
+    sender: Sender<InputEvent>,
+    receiver: Mutex<Receiver<InputEvent>>,
 }
 
 impl Default for MidiSystem {
     fn default() -> Self {
+        Self::with_ports(Box::new(MidirPorts::default()))
+    }
+}
+
+impl MidiSystem {
+    pub fn with_ports(ports: Box<dyn MidiPortProvider>) -> Self {
+        let (sender, receiver) = channel();
         Self {
             enabled: false,
-            input_connections: HashMap::new(),
-            output_connections: HashMap::new(),
+            patterns: vec!["*".to_string()],
+            exclude: vec!["Midi Through".to_string()],
+            rescan_interval_secs: 1.0,
+            ports: Mutex::new(ports),
+            inputs: Mutex::new(Vec::new()),
+            sender,
+            receiver: Mutex::new(receiver),
         }
     }
-}
 
-impl MidiSystem {
     pub fn initialize(&mut self) -> Result<()> {
         self.enabled = true;
-        info!("🎹 Enhanced MIDI system initialized");
+        let changes = self.refresh()?;
+        info!("🎹 Enhanced MIDI system initialized ({} inputs)", changes.len());
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Names of the available input ports
+    pub fn available_ports(&mut self) -> Result<Vec<String>, VjError> {
+        self.provider().port_names()
+    }
+
+    /// Names of the ports currently connected
+    pub fn connected_ports(&self) -> Vec<String> {
+        self.inputs.lock().map(|inputs| {
+            inputs.iter().filter(|input| input.connection.is_some()).map(|input| input.port_name.clone()).collect()
+        }).unwrap_or_default()
+    }
+
+    /// Connect the ports matching a name or pattern now and whenever they appear later.
+    /// Returns the ports connected now.
+    pub fn connect(&mut self, pattern: &str) -> Result<Vec<String>, VjError> {
+        if !self.patterns.iter().any(|existing| existing == pattern) {
+            self.patterns.push(pattern.to_string());
+        }
+        let changes = self.refresh()?;
+        Ok(changes.into_iter()
+            .filter_map(|change| match change {
+                MidiDeviceChange::Connected(name) | MidiDeviceChange::Reconnected(name) => Some(name),
+                MidiDeviceChange::Disconnected(_) => None,
+            })
+            .filter(|name| midi_port_matches(pattern, name))
+            .collect())
+    }
+
+    /// Stop connecting a pattern and close the ports it no longer matches
+    pub fn disconnect(&mut self, pattern: &str) {
+        self.patterns.retain(|existing| existing != pattern);
+        let (patterns, exclude) = (&self.patterns, &self.exclude);
+        let wanted = |name: &str| Self::wants(patterns, exclude, name);
+        if let Ok(inputs) = self.inputs.get_mut() {
+            inputs.retain(|input| wanted(&input.port_name));
+        }
+    }
+
+    fn wants(patterns: &[String], exclude: &[String], port_name: &str) -> bool {
+        patterns.iter().any(|pattern| {
+            // Exclusions only apply to wildcards; naming a port connects it
+            midi_port_matches(pattern, port_name)
+                && (!pattern.contains('*') || !exclude.iter().any(|excluded| midi_port_matches(excluded, port_name)))
+        })
+    }
+
+    fn provider(&mut self) -> &mut Box<dyn MidiPortProvider> {
+        self.ports.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn open(&mut self, port_name: &str) -> Result<MidiPortConnection, VjError> {
+        let sender = self.sender.clone();
+        let mut parser = MidiParser::new();
+        self.provider().open(port_name, Box::new(move |bytes| {
+            for event in parser.parse(bytes) {
+                let _ = sender.send(event);
+            }
+        }))
+    }
+
+    /// Rescan the ports: close unplugged devices, reopen devices that came back and
+    /// connect new ports matching the patterns
+    pub fn refresh(&mut self) -> Result<Vec<MidiDeviceChange>, VjError> {
+        let available = self.available_ports()?;
+        let mut inputs = std::mem::take(self.inputs.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        let mut changes = Vec::new();
+
+        for input in &mut inputs {
+            if input.connection.is_some() && !available.contains(&input.port_name) {
+                input.connection = None;
+                info!("🔌 MIDI input '{}' unplugged", input.port_name);
+                changes.push(MidiDeviceChange::Disconnected(input.port_name.clone()));
+            }
+        }
+
+        for input in inputs.iter_mut().filter(|input| input.connection.is_none()) {
+            let device = midi_device_name(&input.port_name);
+            let Some(port_name) = available.iter().find(|name| midi_device_name(name) == device).cloned() else {
+                continue;
+            };
+            match self.open(&port_name) {
+                Ok(connection) => {
+                    info!("🎹 MIDI input '{}' reconnected", port_name);
+                    input.port_name = port_name.clone();
+                    input.connection = Some(connection);
+                    changes.push(MidiDeviceChange::Reconnected(port_name));
+                }
+                Err(e) => warn!("⚠️ {}", e),
+            }
+        }
+
+        for port_name in &available {
+            let known = inputs.iter().any(|input| {
+                input.port_name == *port_name || midi_device_name(&input.port_name) == midi_device_name(port_name)
+            });
+            if known || !Self::wants(&self.patterns, &self.exclude, port_name) {
+                continue;
+            }
+            match self.open(port_name) {
+                Ok(connection) => {
+                    info!("🎹 MIDI input '{}' connected", port_name);
+                    inputs.push(MidiInputPort { port_name: port_name.clone(), connection: Some(connection) });
+                    changes.push(MidiDeviceChange::Connected(port_name.clone()));
+                }
+                Err(e) => warn!("⚠️ {}", e),
+            }
+        }
+
+        *self.inputs.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner()) = inputs;
+        Ok(changes)
+    }
+
+    /// Events received since the last poll, from all ports
+    pub fn poll(&mut self) -> Vec<InputEvent> {
+        match self.receiver.get_mut() {
+            Ok(receiver) => receiver.try_iter().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Open the MIDI inputs at startup when MIDI input is enabled
+pub(crate) fn setup_midi_input(mut midi: ResMut<MidiSystem>, manager: Res<InputSystemManager>) {
+    if !manager.midi_enabled {
+        return;
+    }
+    if let Err(e) = midi.initialize() {
+        warn!("⚠️ {}", e);
+    }
+}
+
+/// Publish received MIDI as input events and rescan for devices now and then
+pub(crate) fn poll_midi_input(
+    mut midi: ResMut<MidiSystem>,
+    time: Res<Time>,
+    mut since_scan: Local<f32>,
+    mut input_events: MessageWriter<InputEvent>,
+    mut vj_events: MessageWriter<VjEvent>,
+) {
+    if !midi.enabled {
+        return;
+    }
+
+    *since_scan += time.delta_secs();
+    if *since_scan >= midi.rescan_interval_secs {
+        *since_scan = 0.0;
+        match midi.refresh() {
+            Ok(changes) => {
+                for change in changes {
+                    let event_type = match change {
+                        MidiDeviceChange::Connected(device_name) | MidiDeviceChange::Reconnected(device_name) => {
+                            AudioEventType::DeviceConnected { device_name }
+                        }
+                        MidiDeviceChange::Disconnected(device_name) => AudioEventType::DeviceDisconnected { device_name },
+                    };
+                    vj_events.write(VjEvent::AudioEvent { event_type });
+                }
+            }
+            Err(e) => debug!("MIDI rescan failed: {}", e),
+        }
+    }
+
+    input_events.write_batch(midi.poll());
+}
@@ -6,6 +6,7 @@ use glam::{Vec2, Vec3, Quat};
 
 pub mod midi_advanced;
 pub mod osc;
+mod tests;
 
 pub use midi_advanced::*;
 pub use osc::*;
@@ -18,7 +19,13 @@ pub enum InputEvent {
     MidiNoteOff { channel: u8, note: u8 },
     MidiControlChange { channel: u8, controller: u8, value: u8 },
     MidiPitchBend { channel: u8, value: u16 },
+    /// Channel pressure
+    MidiAftertouch { channel: u8, pressure: u8 },
+    /// Per-note pressure
+    MidiPolyAftertouch { channel: u8, note: u8, pressure: u8 },
     MidiProgramChange { channel: u8, program: u8 },
+    /// Complete SysEx message, from 0xF0 to 0xF7
+    MidiSysEx { data: Vec<u8> },
 
     // OSC
     OscMessage { address: String, args: Vec<OscArg> },
@@ -227,9 +234,12 @@ pub struct InputSystemPlugin;
 impl Plugin for InputSystemPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(InputSystemManager::default())
+            .init_resource::<MidiSystem>()
             .add_message::<InputEvent>()
             .add_message::<InputMappingEvent>()
+            .add_systems(Startup, setup_midi_input)
             .add_systems(Update, (
+                poll_midi_input.before(process_input_events),
                 process_input_events,
                 handle_input_mappings,
             ));
@@ -0,0 +1,164 @@
+#[cfg(test)]
+mod tests {
+    use crate::audio_midi_integration::{MidiEventType, MidiInputDevice};
+    use crate::core::VjError;
+    use crate::input::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// Ports that tests plug, unplug and play into
+    #[derive(Clone, Default)]
+    struct VirtualPorts {
+        ports: Arc<Mutex<Vec<String>>>,
+        open: Arc<Mutex<HashMap<String, MidiBytesCallback>>>,
+    }
+
+    impl VirtualPorts {
+        fn plug(&self, name: &str) {
+            self.ports.lock().unwrap().push(name.to_string());
+        }
+
+        fn unplug(&self, name: &str) {
+            self.ports.lock().unwrap().retain(|port| port != name);
+            self.open.lock().unwrap().remove(name);
+        }
+
+        fn play(&self, name: &str, bytes: &[u8]) {
+            if let Some(callback) = self.open.lock().unwrap().get_mut(name) {
+                callback(bytes);
+            }
+        }
+    }
+
+    impl MidiPortProvider for VirtualPorts {
+        fn port_names(&mut self) -> Result<Vec<String>, VjError> {
+            Ok(self.ports.lock().unwrap().clone())
+        }
+
+        fn open(&mut self, port_name: &str, on_bytes: MidiBytesCallback) -> Result<MidiPortConnection, VjError> {
+            if !self.ports.lock().unwrap().iter().any(|port| port == port_name) {
+                return Err(VjError::AudioError(format!("MIDI port '{}' not found", port_name)));
+            }
+            self.open.lock().unwrap().insert(port_name.to_string(), on_bytes);
+            Ok(Box::new(()))
+        }
+    }
+
+    fn note_ons(events: &[InputEvent]) -> Vec<(u8, u8, u8)> {
+        events.iter()
+            .filter_map(|event| match *event {
+                InputEvent::MidiNoteOn { channel, note, velocity } => Some((channel, note, velocity)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_midi_parser_running_status_sysex_and_channel_messages() {
+        let mut parser = MidiParser::new();
+
+        // Running status, with a clock byte in the middle and a zero-velocity note-off
+        let events = parser.parse(&[0x91, 60, 100, 62, 0xF8, 90, 60, 0]);
+        assert_eq!(note_ons(&events), vec![(1, 60, 100), (1, 62, 90)]);
+        assert!(matches!(events[2], InputEvent::MidiNoteOff { channel: 1, note: 60 }));
+
+        // Messages split across packets
+        assert!(parser.parse(&[0xB0, 7]).is_empty());
+        let events = parser.parse(&[127, 10, 64]);
+        assert!(matches!(events[0], InputEvent::MidiControlChange { channel: 0, controller: 7, value: 127 }));
+        assert!(matches!(events[1], InputEvent::MidiControlChange { channel: 0, controller: 10, value: 64 }));
+
+        let events = parser.parse(&[0xE3, 0x00, 0x40, 0xE3, 0x7F, 0x7F, 0xC5, 12, 0xD2, 33, 0xA4, 61, 44, 0x82, 61, 0]);
+        assert!(matches!(events[0], InputEvent::MidiPitchBend { channel: 3, value: 8192 }));
+        assert!(matches!(events[1], InputEvent::MidiPitchBend { channel: 3, value: 16383 }));
+        assert!(matches!(events[2], InputEvent::MidiProgramChange { channel: 5, program: 12 }));
+        assert!(matches!(events[3], InputEvent::MidiAftertouch { channel: 2, pressure: 33 }));
+        assert!(matches!(events[4], InputEvent::MidiPolyAftertouch { channel: 4, note: 61, pressure: 44 }));
+        assert!(matches!(events[5], InputEvent::MidiNoteOff { channel: 2, note: 61 }));
+
+        // SysEx split across packets survives realtime bytes and ends running status
+        assert!(parser.parse(&[0x90, 60, 100, 0xF0, 0x7E, 0x7F]).len() == 1);
+        let events = parser.parse(&[0x06, 0xFE, 0x01, 0xF7, 64, 100]);
+        match &events[..] {
+            [InputEvent::MidiSysEx { data }] => assert_eq!(data, &vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]),
+            other => panic!("expected one SysEx message, got {:?}", other),
+        }
+
+        // System common messages are skipped and end running status too; a status byte
+        // drops an unterminated SysEx
+        assert!(parser.parse(&[0x92, 60, 1, 0xF2, 0x10, 0x20, 61, 1]).len() == 1);
+        assert!(parser.parse(&[0xF0, 1, 2, 0x93, 62, 5]).len() == 1);
+        assert!(parser.parse(&[0xF7]).is_empty());
+    }
+
+    #[test]
+    fn test_midi_port_names_and_patterns() {
+        assert_eq!(midi_device_name("nanoKONTROL2:nanoKONTROL2 MIDI 1 20:0"), "nanoKONTROL2:nanoKONTROL2 MIDI 1");
+        assert_eq!(midi_device_name("IAC Driver Bus 1"), "IAC Driver Bus 1");
+
+        assert!(midi_port_matches("launchpad", "Launchpad X:Launchpad X MIDI 1 24:0"));
+        assert!(midi_port_matches("*", "Anything"));
+        assert!(midi_port_matches("launch*midi 2*", "Launchpad X:Launchpad X MIDI 2 24:1"));
+        assert!(!midi_port_matches("launch*midi 2*", "Launchpad X:Launchpad X MIDI 1 24:0"));
+        assert!(!midi_port_matches("*pad", "Launchpad X"));
+    }
+
+    #[test]
+    fn test_midi_system_connects_by_pattern_and_reconnects_after_unplug() {
+        let ports = VirtualPorts::default();
+        ports.plug("Midi Through:Midi Through Port-0 14:0");
+        ports.plug("Launchpad X:Launchpad X MIDI 1 24:0");
+        ports.plug("Keystation 49:Keystation 49 MIDI 1 28:0");
+
+        let mut midi = MidiSystem::with_ports(Box::new(ports.clone()));
+        midi.patterns.clear();
+        assert_eq!(midi.available_ports().unwrap().len(), 3);
+        assert_eq!(midi.connect("launchpad").unwrap(), vec!["Launchpad X:Launchpad X MIDI 1 24:0"]);
+        assert!(midi.connect("no such device").unwrap().is_empty());
+
+        ports.play("Launchpad X:Launchpad X MIDI 1 24:0", &[0x90, 36, 127]);
+        ports.play("Keystation 49:Keystation 49 MIDI 1 28:0", &[0x90, 48, 127]);
+        assert_eq!(note_ons(&midi.poll()), vec![(0, 36, 127)]);
+
+        // Unplugging closes the port; the device comes back under new ALSA numbers
+        ports.unplug("Launchpad X:Launchpad X MIDI 1 24:0");
+        assert_eq!(midi.refresh().unwrap(), vec![MidiDeviceChange::Disconnected("Launchpad X:Launchpad X MIDI 1 24:0".to_string())]);
+        assert!(midi.connected_ports().is_empty());
+        ports.plug("Launchpad X:Launchpad X MIDI 1 32:0");
+        assert_eq!(midi.refresh().unwrap(), vec![MidiDeviceChange::Reconnected("Launchpad X:Launchpad X MIDI 1 32:0".to_string())]);
+        ports.play("Launchpad X:Launchpad X MIDI 1 32:0", &[0xB0, 1, 64]);
+        assert!(matches!(midi.poll()[..], [InputEvent::MidiControlChange { controller: 1, value: 64, .. }]));
+
+        // A pattern waits for devices plugged in later; wildcards skip excluded ports
+        midi.connect("*").unwrap();
+        assert_eq!(midi.connected_ports().len(), 2);
+        ports.plug("Drum Pad 36:0");
+        assert_eq!(midi.refresh().unwrap(), vec![MidiDeviceChange::Connected("Drum Pad 36:0".to_string())]);
+
+        midi.disconnect("*");
+        assert_eq!(midi.connected_ports(), vec!["Launchpad X:Launchpad X MIDI 1 32:0"]);
+    }
+
+    #[test]
+    fn test_midi_input_device_polls_connected_ports() {
+        let ports = VirtualPorts::default();
+        let mut device = MidiInputDevice::new("keystation");
+        assert!(device.connect_with(Box::new(ports.clone())).is_err());
+        assert!(device.poll_events().is_empty());
+
+        ports.plug("Keystation 49:Keystation 49 MIDI 1 28:0");
+        device.connect_with(Box::new(ports.clone())).unwrap();
+        assert_eq!(device.ports().len(), 1);
+        ports.play("Keystation 49:Keystation 49 MIDI 1 28:0", &[0x90, 60, 80, 0xE0, 0, 0x40, 0xC0, 3, 0xF0, 1, 0xF7]);
+
+        let events = device.poll_events();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[0].event_type, MidiEventType::NoteOn));
+        assert_eq!((events[0].note, events[0].velocity), (Some(60), Some(80)));
+        assert!(matches!(events[1].event_type, MidiEventType::PitchBend) && events[1].value == Some(8192));
+        assert!(matches!(events[2].event_type, MidiEventType::ProgramChange) && events[2].value == Some(3));
+
+        device.disconnect();
+        assert!(device.ports().is_empty());
+    }
+}
@@ -1,7 +1,16 @@
 use bevy::prelude::*;
-use nuwe_rust::ImmersiveVjPlugin;
+use nuwe_rust::{run_plugin_sandbox, ImmersiveVjPlugin, PLUGIN_SANDBOX_ARG};
 
 fn main() {
+    // Hosted plugins can run in child processes started from this executable
+    if std::env::args().nth(1).as_deref() == Some(PLUGIN_SANDBOX_ARG) {
+        if let Err(e) = run_plugin_sandbox() {
+            eprintln!("❌ Plugin sandbox failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
         .add_plugins((
             DefaultPlugins.set(WindowPlugin {
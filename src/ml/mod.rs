@@ -2,6 +2,7 @@ use bevy::prelude::*;
 
 pub mod workflow;
 pub mod native;
+mod tests;
 
 pub use workflow::*;
 pub use native::*;
@@ -2,6 +2,7 @@ use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use anyhow::Result;
+use crate::audio::{deltas, BeatDetector, BeatUpdate, DrumClass, DrumClassifierNode, DrumClassifierSettings, DrumFeatures, DrumProfiles, MelConfig, MelExtractor, DRUM_FEATURES};
 use ndarray::{Array1, Array2};
 #[cfg(feature = "ml-native")]
 use burn::tensor::{Tensor, Device, Shape};
@@ -17,7 +18,7 @@ use dasp::signal;
 use rubato::{Resampler, SincFixedIn, SincInterpolationType, WindowFunction};
 use nalgebra::{DVector, DMatrix};
 
-use crate::core::{Node, NodeId, InputPort, OutputPort, DataType};
+use crate::core::{hash_inputs, Node, NodeId, InputPort, OutputPort, DataType};
 
 // Native Rust ML Node Types
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +27,18 @@ pub struct AudioFeatureExtractorNode {
     pub window_size: usize,
     pub hop_length: usize,
     pub n_mels: usize,
+    #[serde(default = "default_n_mfcc")]
+    pub n_mfcc: usize,
+    #[serde(default = "default_lifter")]
+    pub lifter: usize,
+}
+
+fn default_n_mfcc() -> usize {
+    13
+}
+
+fn default_lifter() -> usize {
+    22
 }
 
 impl Node for AudioFeatureExtractorNode {
@@ -47,6 +60,8 @@ impl Node for AudioFeatureExtractorNode {
     fn outputs(&self) -> Vec<OutputPort> {
         vec![
             OutputPort::new("mfcc_features", DataType::Array),
+            OutputPort::new("mfcc_delta", DataType::Array),
+            OutputPort::new("mfcc_delta2", DataType::Array),
             OutputPort::new("mel_spectrogram", DataType::Array),
             OutputPort::new("spectral_centroid", DataType::Array),
             OutputPort::new("zero_crossing_rate", DataType::Array),
@@ -64,17 +79,22 @@ impl Node for AudioFeatureExtractorNode {
             .unwrap_or(44100) as usize;
 
         let mut outputs = HashMap::new();
+        let mut extractor = MelExtractor::new(self.mel_config(sample_rate));
 
-        // Extract MFCC features using native Rust implementation
-        let mfcc_features = self.extract_mfcc(&audio_samples, sample_rate)?;
+        // MFCCs with first and second order deltas
+        let mfcc_features = extractor.mfcc(&audio_samples);
+        let mfcc_delta = deltas(&mfcc_features, 2);
+        let mfcc_delta2 = deltas(&mfcc_delta, 2);
         outputs.insert("mfcc_features".to_string(), serde_json::to_value(mfcc_features)?);
+        outputs.insert("mfcc_delta".to_string(), serde_json::to_value(mfcc_delta)?);
+        outputs.insert("mfcc_delta2".to_string(), serde_json::to_value(mfcc_delta2)?);
 
-        // Extract mel spectrogram
-        let mel_spec = self.extract_mel_spectrogram(&audio_samples, sample_rate)?;
+        // Log mel spectrogram
+        let mel_spec = extractor.log_mel_spectrogram(&audio_samples);
         outputs.insert("mel_spectrogram".to_string(), serde_json::to_value(mel_spec)?);
 
-        // Extract spectral centroid
-        let spectral_centroid = self.extract_spectral_centroid(&audio_samples)?;
+        // Spectral centroid in Hz
+        let spectral_centroid = extractor.spectral_centroid(&audio_samples);
         outputs.insert("spectral_centroid".to_string(), serde_json::to_value(spectral_centroid)?);
 
         // Extract zero crossing rate
@@ -92,95 +112,21 @@ impl AudioFeatureExtractorNode {
             window_size: 1024,
             hop_length: 512,
             n_mels: 80,
+            n_mfcc: default_n_mfcc(),
+            lifter: default_lifter(),
         }
     }
 
-    fn extract_mfcc(&self, samples: &[f32], sample_rate: usize) -> Result<Vec<Vec<f32>>> {
-        // Simplified MFCC extraction using FFT and mel filterbank
-        let n_fft = self.window_size;
-        let mut mfcc_features = Vec::new();
-        
-        for chunk in samples.chunks(self.hop_length) {
-            if chunk.len() < n_fft {
-                break;
-            }
-            
-            // Apply window function (Hamming)
-            let windowed: Vec<f32> = chunk.iter()
-                .enumerate()
-                .map(|(i, &sample)| {
-                    sample * (0.54 - 0.46 * (2.0 * std::f32::consts::PI * i as f32 / (n_fft - 1) as f32).cos())
-                })
-                .collect();
-            
-            // Simple mel-scale features (placeholder implementation)
-            let mut mel_features = vec![0.0f32; self.n_mels];
-            for (i, &sample) in windowed.iter().take(self.n_mels).enumerate() {
-                mel_features[i] = sample.abs().ln().max(-10.0);
-            }
-            
-            // DCT to get MFCC coefficients (simplified)
-            let mut mfcc_frame = vec![0.0f32; 13];
-            for i in 0..13 {
-                let mut sum = 0.0;
-                for (j, &mel) in mel_features.iter().enumerate() {
-                    sum += mel * (std::f32::consts::PI * i as f32 * (2.0 * j as f32 + 1.0) / (2.0 * self.n_mels as f32)).cos();
-                }
-                mfcc_frame[i] = sum;
-            }
-            
-            mfcc_features.push(mfcc_frame);
-        }
-        
-        Ok(mfcc_features)
-    }
-
-    fn extract_mel_spectrogram(&self, samples: &[f32], _sample_rate: usize) -> Result<Vec<Vec<f32>>> {
-        let mut mel_spec = Vec::new();
-        
-        for chunk in samples.chunks(self.hop_length) {
-            if chunk.len() < self.window_size {
-                break;
-            }
-            
-            let mut mel_frame = vec![0.0f32; self.n_mels];
-            for (i, &sample) in chunk.iter().take(self.n_mels).enumerate() {
-                mel_frame[i] = sample.abs().max(1e-10).ln();
-            }
-            
-            mel_spec.push(mel_frame);
-        }
-        
-        Ok(mel_spec)
-    }
-
-    fn extract_spectral_centroid(&self, samples: &[f32]) -> Result<Vec<f32>> {
-        let mut centroids = Vec::new();
-        
-        for chunk in samples.chunks(self.hop_length) {
-            if chunk.len() < self.window_size {
-                break;
-            }
-            
-            let mut weighted_sum = 0.0;
-            let mut magnitude_sum = 0.0;
-            
-            for (i, &sample) in chunk.iter().enumerate() {
-                let magnitude = sample.abs();
-                weighted_sum += i as f32 * magnitude;
-                magnitude_sum += magnitude;
-            }
-            
-            let centroid = if magnitude_sum > 0.0 {
-                weighted_sum / magnitude_sum
-            } else {
-                0.0
-            };
-            
-            centroids.push(centroid);
+    fn mel_config(&self, sample_rate: usize) -> MelConfig {
+        MelConfig {
+            sample_rate: sample_rate as f32,
+            n_fft: self.window_size,
+            hop_length: self.hop_length,
+            n_mels: self.n_mels,
+            n_mfcc: self.n_mfcc,
+            lifter: self.lifter,
+            ..Default::default()
         }
-        
-        Ok(centroids)
     }
 
     fn extract_zero_crossing_rate(&self, samples: &[f32]) -> Result<Vec<f32>> {
@@ -203,12 +149,18 @@ impl AudioFeatureExtractorNode {
     }
 }
 
-// Neural Network-based Audio Classifier using Candle
+// Neural Network-based Audio Classifier using Candle.
+// Without `ml-native` it classifies drum hit features (`DrumFeatures::to_array` order)
+// with the percussion classifier's profiles; `DrumClassifierNode` does the same on audio.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioClassifierNode {
     pub id: NodeId,
     pub model_path: Option<String>,
     pub classes: Vec<String>,
+    #[serde(default)]
+    pub drum_profiles: DrumProfiles,
+    #[serde(default)]
+    pub drum_settings: DrumClassifierSettings,
 }
 
 impl Node for AudioClassifierNode {
@@ -275,8 +227,7 @@ impl Node for AudioClassifierNode {
 
         #[cfg(not(feature = "ml-native"))]
         {
-            // Fallback implementation using basic math
-            let predictions_vec = self.simple_classification(&features)?;
+            let predictions_vec = self.drum_classification(&features)?;
 
             let max_idx = predictions_vec.iter()
                 .enumerate()
@@ -300,11 +251,19 @@ impl Node for AudioClassifierNode {
 }
 
 impl AudioClassifierNode {
+    /// Without classes, predicts the drum classes
     pub fn new(classes: Vec<String>) -> Self {
+        let classes = if classes.is_empty() {
+            DrumClass::ALL.iter().map(|class| class.name().to_string()).collect()
+        } else {
+            classes
+        };
         Self {
             id: NodeId::new(),
             model_path: None,
             classes,
+            drum_profiles: DrumProfiles::default(),
+            drum_settings: DrumClassifierSettings::default(),
         }
     }
 
@@ -329,32 +288,15 @@ impl AudioClassifierNode {
         Ok(output)
     }
 
+    /// Confidence of the matching drum class for each of `classes`, zero for the others
     #[cfg(not(feature = "ml-native"))]
-    fn simple_classification(&self, features: &[f32]) -> Result<Vec<f32>> {
-        // Simple classification using basic math operations (placeholder)
-        // This would be replaced with proper ML inference when Burn is working
-        let mut predictions = vec![0.0f32; self.classes.len()];
-
-        if !features.is_empty() {
-            // Simple weighted sum based on feature values
-            let sum: f32 = features.iter().sum();
-            let avg = sum / features.len() as f32;
-
-            for (i, pred) in predictions.iter_mut().enumerate() {
-                // Simple heuristic: higher features favor certain classes
-                *pred = (avg * (i + 1) as f32).min(1.0).max(0.0);
-            }
-
-            // Normalize to sum to 1 (simple softmax approximation)
-            let total: f32 = predictions.iter().sum();
-            if total > 0.0 {
-                for pred in predictions.iter_mut() {
-                    *pred /= total;
-                }
-            }
-        }
-
-        Ok(predictions)
+    fn drum_classification(&self, features: &[f32]) -> Result<Vec<f32>> {
+        let features: [f32; DRUM_FEATURES] = features.try_into()
+            .map_err(|_| anyhow::anyhow!("Expected {} drum features, got {}", DRUM_FEATURES, features.len()))?;
+        let (class, confidence) = self.drum_profiles.classify(&DrumFeatures::from_array(features), self.drum_settings.max_distance);
+        Ok(self.classes.iter()
+            .map(|name| if DrumClass::from_name(name) == Some(class) { confidence } else { 0.0 })
+            .collect())
     }
 }
 
@@ -414,13 +356,38 @@ impl StyleTransferNode {
 }
 
 // Beat Detection using native Rust DSP
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct BeatDetectorNode {
     pub id: NodeId,
+    /// Onset threshold in standard deviations above the local mean flux
     pub threshold: f32,
-    pub min_interval: usize,
-    pub energy_history: Vec<f32>,
-    pub last_beat_time: usize,
+    #[serde(skip)]
+    detector: Option<BeatDetector>,
+    /// Hash of the audio last fed to the detector, so a repeated buffer is not counted twice
+    #[serde(skip)]
+    last_audio: Option<u64>,
+}
+
+impl Clone for BeatDetectorNode {
+    /// Clones start with fresh onset and tempo history
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            threshold: self.threshold,
+            detector: None,
+            last_audio: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for BeatDetectorNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BeatDetectorNode")
+            .field("id", &self.id)
+            .field("threshold", &self.threshold)
+            .field("tempo", &self.detector.as_ref().and_then(BeatDetector::tempo))
+            .finish_non_exhaustive()
+    }
 }
 
 impl Node for BeatDetectorNode {
@@ -437,18 +404,27 @@ impl Node for BeatDetectorNode {
             InputPort::new("audio_samples", DataType::Array),
             InputPort::new("sample_rate", DataType::Integer),
             InputPort::new("threshold", DataType::Float),
+            // Position of the buffer in the stream, so identical consecutive buffers still count
+            InputPort::optional("frame", DataType::Integer),
         ]
     }
 
     fn outputs(&self) -> Vec<OutputPort> {
         vec![
             OutputPort::new("beat_detected", DataType::Boolean),
+            OutputPort::new("onset_detected", DataType::Boolean),
             OutputPort::new("energy", DataType::Float),
             OutputPort::new("bpm", DataType::Float),
+            OutputPort::new("confidence", DataType::Float),
+            OutputPort::new("phase", DataType::Float),
         ]
     }
 
-    fn process(&mut self, inputs: HashMap<String, serde_json::Value>) -> Result<HashMap<String, serde_json::Value>> {
+    fn process(&mut self, mut inputs: HashMap<String, serde_json::Value>) -> Result<HashMap<String, serde_json::Value>> {
+        if let Some(threshold) = inputs.remove("threshold").and_then(|v| v.as_f64()) {
+            self.threshold = threshold as f32;
+        }
+
         let samples: Vec<f32> = inputs.get("audio_samples")
             .and_then(|v| v.as_array())
             .map(|arr| arr.iter().map(|v| v.as_f64().unwrap_or(0.0) as f32).collect())
@@ -456,50 +432,44 @@ impl Node for BeatDetectorNode {
 
         let sample_rate = inputs.get("sample_rate")
             .and_then(|v| v.as_u64())
-            .unwrap_or(44100) as usize;
+            .unwrap_or(44100) as f32;
 
-        // Calculate instantaneous energy
-        let energy = samples.iter().map(|&x| x * x).sum::<f32>() / samples.len() as f32;
-        
-        // Update energy history (keep last 43 frames for ~1 second at 512 hop length)
-        self.energy_history.push(energy);
-        if self.energy_history.len() > 43 {
-            self.energy_history.remove(0);
+        if self.detector.as_ref().is_none_or(|detector| detector.sample_rate() != sample_rate) {
+            self.detector = Some(BeatDetector::new(sample_rate));
         }
-        
-        // Beat detection using energy flux
-        let beat_detected = if self.energy_history.len() > 1 {
-            let current_energy = self.energy_history[self.energy_history.len() - 1];
-            let prev_energy = self.energy_history[self.energy_history.len() - 2];
-            let avg_energy = self.energy_history.iter().sum::<f32>() / self.energy_history.len() as f32;
-            
-            let energy_flux = current_energy - prev_energy;
-            let adaptive_threshold = avg_energy * self.threshold;
-            
-            energy_flux > adaptive_threshold && 
-            self.last_beat_time + self.min_interval < samples.len()
+        let detector = self.detector.as_mut().expect("detector created above");
+        detector.sensitivity = self.threshold;
+
+        // The node runs every evaluation; the same samples at the same frame are not new audio
+        let audio = hash_inputs(&inputs, &HashMap::new());
+        let update = if self.last_audio == Some(audio) {
+            BeatUpdate::default()
         } else {
-            false
+            self.last_audio = Some(audio);
+            detector.process(&samples)
         };
-        
-        if beat_detected {
-            self.last_beat_time = samples.len();
-        }
-        
-        // Simple BPM estimation
-        let bpm = if !self.energy_history.is_empty() {
-            60.0 * sample_rate as f32 / (self.min_interval as f32)
+        let tempo = detector.tempo();
+        let energy = if samples.is_empty() {
+            0.0
         } else {
-            120.0
+            samples.iter().map(|&x| x * x).sum::<f32>() / samples.len() as f32
         };
 
         let mut outputs = HashMap::new();
-        outputs.insert("beat_detected".to_string(), serde_json::Value::Bool(beat_detected));
-        outputs.insert("energy".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(energy as f64).unwrap()));
-        outputs.insert("bpm".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(bpm as f64).unwrap()));
+        outputs.insert("beat_detected".to_string(), serde_json::Value::Bool(update.beats > 0));
+        outputs.insert("onset_detected".to_string(), serde_json::Value::Bool(update.onsets > 0));
+        outputs.insert("energy".to_string(), serde_json::json!(energy));
+        outputs.insert("bpm".to_string(), serde_json::json!(tempo.map(|t| t.bpm).unwrap_or(0.0)));
+        outputs.insert("confidence".to_string(), serde_json::json!(tempo.map(|t| t.confidence).unwrap_or(0.0)));
+        outputs.insert("phase".to_string(), serde_json::json!(detector.phase()));
 
         Ok(outputs)
     }
+
+    fn is_cacheable(&self) -> bool {
+        // Onset and tempo history make the output depend on previous frames
+        false
+    }
 }
 
 impl BeatDetectorNode {
@@ -507,9 +477,8 @@ impl BeatDetectorNode {
         Self {
             id: NodeId::new(),
             threshold: 1.5,
-            min_interval: 512, // Minimum samples between beats
-            energy_history: Vec::new(),
-            last_beat_time: 0,
+            detector: None,
+            last_audio: None,
         }
     }
 }
@@ -585,4 +554,8 @@ pub fn create_style_transfer() -> Box<dyn Node> {
 
 pub fn create_beat_detector() -> Box<dyn Node> {
     Box::new(BeatDetectorNode::new())
+}
+
+pub fn create_drum_classifier() -> Box<dyn Node> {
+    Box::new(DrumClassifierNode::new())
 }
\ No newline at end of file
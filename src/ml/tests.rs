@@ -0,0 +1,51 @@
+#[cfg(test)]
+mod tests {
+    use crate::core::Node;
+    use crate::ml::BeatDetectorNode;
+    use std::collections::HashMap;
+
+    /// Clicks at a steady tempo, one sample wide
+    fn click_track(bpm: f32, sample_rate: f32, seconds: f32) -> Vec<f32> {
+        let mut samples = vec![0.0; (sample_rate * seconds) as usize];
+        let period = (sample_rate * 60.0 / bpm) as usize;
+        for start in (0..samples.len()).step_by(period) {
+            for (i, sample) in samples[start..].iter_mut().take(64).enumerate() {
+                *sample = if i % 2 == 0 { 0.9 } else { -0.9 };
+            }
+        }
+        samples
+    }
+
+    fn run(node: &mut BeatDetectorNode, chunk: &[f32], frame: usize) -> HashMap<String, serde_json::Value> {
+        let mut inputs = HashMap::new();
+        inputs.insert("audio_samples".to_string(), serde_json::json!(chunk));
+        inputs.insert("sample_rate".to_string(), serde_json::json!(44100));
+        inputs.insert("frame".to_string(), serde_json::json!(frame));
+        node.process(inputs).unwrap()
+    }
+
+    #[test]
+    fn test_beat_detector_node_ignores_repeated_buffers() {
+        let track = click_track(120.0, 44100.0, 6.0);
+        let mut once = BeatDetectorNode::new();
+        let mut repeated = BeatDetectorNode::new();
+
+        let (mut onsets_once, mut onsets_repeated) = (0, 0);
+        for (index, chunk) in track.chunks(2048).enumerate() {
+            let frame = index * 2048;
+            onsets_once += run(&mut once, chunk, frame)["onset_detected"].as_bool().unwrap() as usize;
+            // The graph evaluates the node every frame, often before new audio arrives
+            for _ in 0..3 {
+                onsets_repeated += run(&mut repeated, chunk, frame)["onset_detected"].as_bool().unwrap() as usize;
+            }
+        }
+
+        assert!(onsets_once > 0);
+        assert_eq!(onsets_repeated, onsets_once);
+
+        // Clones start without the detector's history
+        let clone = repeated.clone();
+        assert_eq!(clone.threshold, repeated.threshold);
+        assert!(format!("{:?}", clone).starts_with("BeatDetectorNode"));
+    }
+}
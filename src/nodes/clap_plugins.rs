@@ -0,0 +1,920 @@
+//! CLAP plugin hosting
+//!
+//! Loads `.clap` libraries through their `clap_entry` export and runs plugins from the
+//! plugin factory. Parameters, notes and raw MIDI go in as events at the start of each
+//! block; parameter values the plugin outputs update the cached values. State is saved
+//! through the state extension and latency read from the latency extension.
+
+use base64::Engine;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use clap_sys::audio_buffer::clap_audio_buffer;
+use clap_sys::entry::clap_plugin_entry;
+use clap_sys::events::{
+    clap_event_header, clap_event_midi, clap_event_note, clap_event_param_value, clap_input_events,
+    clap_output_events, CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_MIDI, CLAP_EVENT_NOTE_OFF, CLAP_EVENT_NOTE_ON,
+    CLAP_EVENT_PARAM_VALUE,
+};
+use clap_sys::ext::audio_ports::{clap_audio_port_info, clap_plugin_audio_ports, CLAP_EXT_AUDIO_PORTS};
+use clap_sys::ext::latency::{clap_plugin_latency, CLAP_EXT_LATENCY};
+use clap_sys::ext::note_ports::{
+    clap_note_port_info, clap_plugin_note_ports, CLAP_EXT_NOTE_PORTS, CLAP_NOTE_DIALECT_CLAP, CLAP_NOTE_DIALECT_MIDI,
+};
+use clap_sys::ext::params::{clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS, CLAP_PARAM_IS_READONLY, CLAP_PARAM_IS_STEPPED};
+use clap_sys::ext::state::{clap_plugin_state, CLAP_EXT_STATE};
+use clap_sys::factory::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID};
+use clap_sys::host::clap_host;
+use clap_sys::plugin::{clap_plugin, clap_plugin_descriptor};
+use clap_sys::plugin_features::CLAP_PLUGIN_FEATURE_INSTRUMENT;
+use clap_sys::process::{clap_process, CLAP_PROCESS_ERROR};
+use clap_sys::stream::{clap_istream, clap_ostream};
+use clap_sys::version::{clap_version_is_compatible, CLAP_VERSION};
+use libloading::Library;
+use serde_json::Value;
+use crate::core::VjError;
+use super::plugin_host::{unknown_instance, PluginDescriptor, PluginFormat, PluginHost, PluginParameter, PluginSetup};
+
+/// Events queued for one block, and output events kept from one
+const EVENT_CAPACITY: usize = 1024;
+
+/// A loaded CLAP library, kept alive while its plugins exist
+pub struct ClapModule {
+    path: PathBuf,
+    entry: *const clap_plugin_entry,
+    factory: *const clap_plugin_factory,
+    _library: Library,
+}
+
+// SAFETY: the entry and factory are immutable tables CLAP allows using from any thread
+unsafe impl Send for ClapModule {}
+unsafe impl Sync for ClapModule {}
+
+/// Shared library of a `.clap` plugin: the file itself, or the binary of a macOS bundle
+pub fn clap_binary_path(path: &Path) -> Result<PathBuf, VjError> {
+    if path.is_dir() {
+        let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+        let binary = path.join("Contents/MacOS").join(stem);
+        return if binary.exists() {
+            Ok(binary)
+        } else {
+            Err(VjError::FileError(format!("CLAP bundle {} has no binary ({})", path.display(), binary.display())))
+        };
+    }
+    if path.exists() {
+        Ok(path.to_path_buf())
+    } else {
+        Err(VjError::FileError(format!("CLAP plugin not found: {}", path.display())))
+    }
+}
+
+impl ClapModule {
+    pub fn load(path: &Path) -> Result<Arc<Self>, VjError> {
+        let binary = clap_binary_path(path)?;
+        // SAFETY: loading a plugin runs its initializers; that's the point of hosting it
+        let library = unsafe { Library::new(&binary) }
+            .map_err(|e| VjError::AudioError(format!("Failed to load {}: {}", binary.display(), e)))?;
+
+        // SAFETY: `clap_entry` is a data symbol of this type in every CLAP library
+        let entry = unsafe {
+            let symbol = library.get::<*const clap_plugin_entry>(b"clap_entry\0")
+                .map_err(|_| VjError::AudioError(format!("{} is not a CLAP plugin", binary.display())))?;
+            *symbol
+        };
+        // SAFETY: the entry lives as long as the library
+        let entry_table = unsafe { &*entry };
+        if !clap_version_is_compatible(entry_table.clap_version) {
+            return Err(VjError::AudioError(format!(
+                "{} uses unsupported CLAP version {}.{}",
+                binary.display(), entry_table.clap_version.major, entry_table.clap_version.minor
+            )));
+        }
+
+        let plugin_path = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|e| VjError::FileError(e.to_string()))?;
+        let (Some(init), Some(get_factory)) = (entry_table.init, entry_table.get_factory) else {
+            return Err(VjError::AudioError(format!("{} has an incomplete CLAP entry", binary.display())));
+        };
+        // SAFETY: init gets the plugin's path; the factory id is a valid C string
+        let factory = unsafe {
+            if !init(plugin_path.as_ptr()) {
+                return Err(VjError::AudioError(format!("{} failed to initialize", binary.display())));
+            }
+            get_factory(CLAP_PLUGIN_FACTORY_ID.as_ptr()) as *const clap_plugin_factory
+        };
+        let module = Arc::new(Self { path: path.to_path_buf(), entry, factory, _library: library });
+        if factory.is_null() {
+            return Err(VjError::AudioError(format!("{} has no CLAP plugin factory", binary.display())));
+        }
+        Ok(module)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Plugins the factory offers
+    pub fn plugins(&self) -> Vec<PluginDescriptor> {
+        // SAFETY: the factory and its descriptors live as long as the module
+        unsafe {
+            let factory = &*self.factory;
+            let (Some(count), Some(get_descriptor)) = (factory.get_plugin_count, factory.get_plugin_descriptor) else {
+                return Vec::new();
+            };
+            (0..count(self.factory))
+                .filter_map(|index| get_descriptor(self.factory, index).as_ref())
+                .map(|descriptor| self.describe(descriptor))
+                .collect()
+        }
+    }
+
+    /// # Safety
+    /// The descriptor's strings must be valid C strings or null
+    unsafe fn describe(&self, descriptor: &clap_plugin_descriptor) -> PluginDescriptor {
+        let mut is_instrument = false;
+        let mut feature = descriptor.features;
+        while !feature.is_null() && !(*feature).is_null() {
+            is_instrument |= CStr::from_ptr(*feature) == CLAP_PLUGIN_FEATURE_INSTRUMENT;
+            feature = feature.add(1);
+        }
+        PluginDescriptor {
+            format: PluginFormat::Clap,
+            id: c_str(descriptor.id),
+            name: c_str(descriptor.name),
+            vendor: c_str(descriptor.vendor),
+            version: c_str(descriptor.version),
+            path: self.path.clone(),
+            is_instrument,
+        }
+    }
+}
+
+impl Drop for ClapModule {
+    fn drop(&mut self) {
+        // SAFETY: every plugin from this module has been destroyed
+        unsafe {
+            if let Some(deinit) = (*self.entry).deinit {
+                deinit();
+            }
+        }
+    }
+}
+
+/// Describe the plugins in a `.clap` library
+pub fn scan_clap(path: &Path) -> Result<Vec<PluginDescriptor>, VjError> {
+    Ok(ClapModule::load(path)?.plugins())
+}
+
+/// # Safety
+/// `text` must be null or a valid C string
+unsafe fn c_str(text: *const c_char) -> String {
+    if text.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(text).to_string_lossy().into_owned()
+    }
+}
+
+/// Text of a fixed-size, zero-terminated name field
+fn name_string(name: &[c_char]) -> String {
+    let bytes: Vec<u8> = name.iter().take_while(|&&c| c != 0).map(|&c| c as u8).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// What the plugin asked of the host through `clap_host`
+#[derive(Default)]
+struct HostRequests {
+    restart: AtomicBool,
+    callback: AtomicBool,
+}
+
+/// The `clap_host` handed to one plugin, with the requests it made
+struct ClapHost {
+    host: clap_host,
+    requests: HostRequests,
+}
+
+impl ClapHost {
+    fn new() -> Box<Self> {
+        let mut host = Box::new(Self {
+            host: clap_host {
+                clap_version: CLAP_VERSION,
+                host_data: std::ptr::null_mut(),
+                name: c"NUWE".as_ptr(),
+                vendor: c"NUWE".as_ptr(),
+                url: c"".as_ptr(),
+                version: c"0.1.0".as_ptr(),
+                get_extension: Some(host_get_extension),
+                request_restart: Some(host_request_restart),
+                request_process: Some(host_request_process),
+                request_callback: Some(host_request_callback),
+            },
+            requests: HostRequests::default(),
+        });
+        host.host.host_data = &host.requests as *const HostRequests as *mut c_void;
+        host
+    }
+}
+
+unsafe fn host_requests<'a>(host: *const clap_host) -> Option<&'a HostRequests> {
+    host.as_ref().and_then(|host| (host.host_data as *const HostRequests).as_ref())
+}
+
+unsafe extern "C" fn host_get_extension(_host: *const clap_host, _extension_id: *const c_char) -> *const c_void {
+    std::ptr::null()
+}
+
+unsafe extern "C" fn host_request_restart(host: *const clap_host) {
+    if let Some(requests) = host_requests(host) {
+        requests.restart.store(true, Ordering::Release);
+    }
+}
+
+unsafe extern "C" fn host_request_process(_host: *const clap_host) {}
+
+unsafe extern "C" fn host_request_callback(host: *const clap_host) {
+    if let Some(requests) = host_requests(host) {
+        requests.callback.store(true, Ordering::Release);
+    }
+}
+
+/// One input event in the layout of its CLAP struct
+#[repr(C)]
+#[derive(Clone, Copy)]
+union InputEvent {
+    header: clap_event_header,
+    note: clap_event_note,
+    param: clap_event_param_value,
+    midi: clap_event_midi,
+}
+
+/// Events for the next block, behind a `clap_input_events`
+struct InputEvents {
+    list: clap_input_events,
+    events: Vec<InputEvent>,
+}
+
+impl InputEvents {
+    fn new() -> Box<Self> {
+        let mut events = Box::new(Self {
+            list: clap_input_events { ctx: std::ptr::null_mut(), size: Some(input_events_size), get: Some(input_events_get) },
+            events: Vec::with_capacity(EVENT_CAPACITY),
+        });
+        events.list.ctx = &mut *events as *mut Self as *mut c_void;
+        events
+    }
+
+    fn push(&mut self, event: InputEvent) -> bool {
+        if self.events.len() == EVENT_CAPACITY {
+            return false;
+        }
+        self.events.push(event);
+        true
+    }
+}
+
+fn event_header<T>(event_type: u16) -> clap_event_header {
+    clap_event_header {
+        size: std::mem::size_of::<T>() as u32,
+        time: 0,
+        space_id: CLAP_CORE_EVENT_SPACE_ID,
+        type_: event_type,
+        flags: 0,
+    }
+}
+
+unsafe extern "C" fn input_events_size(list: *const clap_input_events) -> u32 {
+    ((*list).ctx as *const InputEvents).as_ref().map_or(0, |events| events.events.len() as u32)
+}
+
+unsafe extern "C" fn input_events_get(list: *const clap_input_events, index: u32) -> *const clap_event_header {
+    match ((*list).ctx as *const InputEvents).as_ref().and_then(|events| events.events.get(index as usize)) {
+        Some(event) => &event.header,
+        None => std::ptr::null(),
+    }
+}
+
+/// Parameter values the plugin output during a block, behind a `clap_output_events`
+struct OutputEvents {
+    list: clap_output_events,
+    values: Vec<(u32, f64)>,
+}
+
+impl OutputEvents {
+    fn new() -> Box<Self> {
+        let mut events = Box::new(Self {
+            list: clap_output_events { ctx: std::ptr::null_mut(), try_push: Some(output_events_try_push) },
+            values: Vec::with_capacity(EVENT_CAPACITY),
+        });
+        events.list.ctx = &mut *events as *mut Self as *mut c_void;
+        events
+    }
+}
+
+unsafe extern "C" fn output_events_try_push(list: *const clap_output_events, event: *const clap_event_header) -> bool {
+    let (Some(events), Some(header)) = (((*list).ctx as *mut OutputEvents).as_mut(), event.as_ref()) else {
+        return false;
+    };
+    if header.space_id == CLAP_CORE_EVENT_SPACE_ID && header.type_ == CLAP_EVENT_PARAM_VALUE {
+        if events.values.len() == EVENT_CAPACITY {
+            return false;
+        }
+        let param = &*(event as *const clap_event_param_value);
+        events.values.push((param.param_id, param.value));
+    }
+    // Other events, e.g. note ends and gestures, aren't used
+    true
+}
+
+unsafe extern "C" fn ostream_write(stream: *const clap_ostream, buffer: *const c_void, size: u64) -> i64 {
+    let Some(data) = ((*stream).ctx as *mut Vec<u8>).as_mut() else {
+        return -1;
+    };
+    if size > 0 {
+        data.extend_from_slice(std::slice::from_raw_parts(buffer as *const u8, size as usize));
+    }
+    size as i64
+}
+
+/// Saved state being read back, behind a `clap_istream`
+struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+unsafe extern "C" fn istream_read(stream: *const clap_istream, buffer: *mut c_void, size: u64) -> i64 {
+    let Some(reader) = ((*stream).ctx as *mut StateReader).as_mut() else {
+        return -1;
+    };
+    let count = (size as usize).min(reader.data.len() - reader.position);
+    if count > 0 {
+        std::ptr::copy_nonoverlapping(reader.data[reader.position..].as_ptr(), buffer as *mut u8, count);
+        reader.position += count;
+    }
+    count as i64
+}
+
+/// Channel buffers of one audio port and the pointer array handed to the plugin
+struct PortBuffers {
+    channels: Vec<Vec<f32>>,
+    pointers: Vec<*mut f32>,
+}
+
+impl PortBuffers {
+    fn new(channels: usize, block_size: usize) -> Self {
+        let mut channels: Vec<Vec<f32>> = (0..channels).map(|_| vec![0.0; block_size]).collect();
+        let pointers = channels.iter_mut().map(|channel| channel.as_mut_ptr()).collect();
+        Self { channels, pointers }
+    }
+
+    fn audio_buffer(&mut self) -> clap_audio_buffer {
+        clap_audio_buffer {
+            data32: self.pointers.as_mut_ptr(),
+            data64: std::ptr::null_mut(),
+            channel_count: self.channels.len() as u32,
+            latency: 0,
+            constant_mask: 0,
+        }
+    }
+}
+
+/// How the plugin takes notes, if at all
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NoteDialect {
+    None,
+    Clap,
+    Midi,
+}
+
+/// A CLAP plugin instance, activated and processing
+pub struct ClapPluginInstance {
+    descriptor: PluginDescriptor,
+    setup: PluginSetup,
+    plugin: *const clap_plugin,
+    params: Option<&'static clap_plugin_params>,
+    latency: Option<&'static clap_plugin_latency>,
+    state: Option<&'static clap_plugin_state>,
+    parameters: Vec<PluginParameter>,
+    parameter_index: HashMap<u32, usize>,
+    /// Plain values, as CLAP uses them
+    parameter_values: Vec<f64>,
+    note_dialect: NoteDialect,
+    input_ports: Vec<PortBuffers>,
+    output_ports: Vec<PortBuffers>,
+    input_buffers: Vec<clap_audio_buffer>,
+    output_buffers: Vec<clap_audio_buffer>,
+    input_events: Box<InputEvents>,
+    output_events: Box<OutputEvents>,
+    steady_time: i64,
+    host: Box<ClapHost>,
+    _module: Arc<ClapModule>,
+}
+
+// SAFETY: the instance is used from one thread at a time through `&mut self`, which CLAP
+// treats as both its main and its audio thread
+unsafe impl Send for ClapPluginInstance {}
+
+impl ClapPluginInstance {
+    /// Create, initialize and activate the plugin a descriptor names
+    pub fn load(descriptor: &PluginDescriptor, setup: PluginSetup) -> Result<Self, VjError> {
+        setup.validate()?;
+        let module = ClapModule::load(&descriptor.path)?;
+        Self::from_module(module, descriptor, setup)
+    }
+
+    pub fn from_module(module: Arc<ClapModule>, descriptor: &PluginDescriptor, setup: PluginSetup) -> Result<Self, VjError> {
+        let plugin_id = CString::new(descriptor.id.as_str()).map_err(|e| VjError::ConfigError(e.to_string()))?;
+        let host = ClapHost::new();
+        // SAFETY: the factory is live; the host outlives the plugin
+        let plugin = unsafe {
+            let factory = &*module.factory;
+            let create = factory.create_plugin
+                .ok_or_else(|| VjError::AudioError("CLAP factory can't create plugins".to_string()))?;
+            create(module.factory, &host.host, plugin_id.as_ptr())
+        };
+        // SAFETY: a non-null plugin is valid until destroyed
+        let Some(plugin_table) = (unsafe { plugin.as_ref() }) else {
+            return Err(VjError::AudioError(format!("CLAP plugin '{}' not found in {}", descriptor.id, module.path.display())));
+        };
+        // SAFETY: init is called once, before anything else
+        if !plugin_table.init.is_some_and(|init| unsafe { init(plugin) }) {
+            // SAFETY: a plugin that failed init is still destroyed
+            unsafe { plugin_table.destroy.map(|destroy| destroy(plugin)) };
+            return Err(VjError::AudioError(format!("CLAP plugin '{}' failed to initialize", descriptor.name)));
+        }
+
+        let mut instance = Self {
+            descriptor: descriptor.clone(),
+            setup,
+            plugin,
+            // SAFETY: extension tables live as long as the plugin
+            params: unsafe { extension(plugin, CLAP_EXT_PARAMS) },
+            latency: unsafe { extension(plugin, CLAP_EXT_LATENCY) },
+            state: unsafe { extension(plugin, CLAP_EXT_STATE) },
+            parameters: Vec::new(),
+            parameter_index: HashMap::new(),
+            parameter_values: Vec::new(),
+            note_dialect: NoteDialect::None,
+            input_ports: Vec::new(),
+            output_ports: Vec::new(),
+            input_buffers: Vec::new(),
+            output_buffers: Vec::new(),
+            input_events: InputEvents::new(),
+            output_events: OutputEvents::new(),
+            steady_time: 0,
+            host,
+            _module: module,
+        };
+        instance.read_ports();
+        instance.read_parameters();
+
+        // SAFETY: activation and processing start once ports and parameters are known
+        unsafe {
+            let block_size = setup.block_size as u32;
+            if !plugin_table.activate.is_some_and(|activate| activate(plugin, setup.sample_rate as f64, 1, block_size)) {
+                return Err(VjError::AudioError(format!("CLAP plugin '{}' failed to activate", descriptor.name)));
+            }
+            if !plugin_table.start_processing.is_some_and(|start| start(plugin)) {
+                if let Some(deactivate) = plugin_table.deactivate {
+                    deactivate(plugin);
+                }
+                return Err(VjError::AudioError(format!("CLAP plugin '{}' failed to start processing", descriptor.name)));
+            }
+        }
+        Ok(instance)
+    }
+
+    fn read_ports(&mut self) {
+        let block_size = self.setup.block_size;
+        // SAFETY: extension tables live as long as the plugin; info structs are plain data
+        unsafe {
+            if let Some(ports) = extension::<clap_plugin_audio_ports>(self.plugin, CLAP_EXT_AUDIO_PORTS) {
+                if let (Some(count), Some(get)) = (ports.count, ports.get) {
+                    for is_input in [true, false] {
+                        for index in 0..count(self.plugin, is_input) {
+                            let mut info: clap_audio_port_info = std::mem::zeroed();
+                            if get(self.plugin, index, is_input, &mut info) {
+                                let buffers = PortBuffers::new(info.channel_count as usize, block_size);
+                                if is_input { &mut self.input_ports } else { &mut self.output_ports }.push(buffers);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if let Some(ports) = extension::<clap_plugin_note_ports>(self.plugin, CLAP_EXT_NOTE_PORTS) {
+                if let (Some(count), Some(get)) = (ports.count, ports.get) {
+                    let mut info: clap_note_port_info = std::mem::zeroed();
+                    if count(self.plugin, true) > 0 && get(self.plugin, 0, true, &mut info) {
+                        self.note_dialect = if info.supported_dialects & CLAP_NOTE_DIALECT_CLAP != 0 {
+                            NoteDialect::Clap
+                        } else if info.supported_dialects & CLAP_NOTE_DIALECT_MIDI != 0 {
+                            NoteDialect::Midi
+                        } else {
+                            NoteDialect::None
+                        };
+                    }
+                }
+            }
+        }
+        self.input_buffers = self.input_ports.iter_mut().map(PortBuffers::audio_buffer).collect();
+        self.output_buffers = self.output_ports.iter_mut().map(PortBuffers::audio_buffer).collect();
+    }
+
+    fn read_parameters(&mut self) {
+        let Some(params) = self.params else {
+            return;
+        };
+        let (Some(count), Some(get_info)) = (params.count, params.get_info) else {
+            return;
+        };
+        // SAFETY: the plugin is live; info structs are plain data
+        unsafe {
+            for index in 0..count(self.plugin) {
+                let mut info: clap_param_info = std::mem::zeroed();
+                if !get_info(self.plugin, index, &mut info) {
+                    continue;
+                }
+                let mut value = info.default_value;
+                if let Some(get_value) = params.get_value {
+                    get_value(self.plugin, info.id, &mut value);
+                }
+                let stepped = info.flags & CLAP_PARAM_IS_STEPPED != 0;
+                let range = info.max_value - info.min_value;
+                self.parameter_index.insert(info.id, self.parameters.len());
+                self.parameters.push(PluginParameter {
+                    id: info.id,
+                    name: name_string(&info.name),
+                    units: String::new(),
+                    default_value: normalize(info.default_value, info.min_value, info.max_value),
+                    min: info.min_value,
+                    max: info.max_value,
+                    step_count: if stepped { range.round().max(0.0) as u32 } else { 0 },
+                    read_only: info.flags & CLAP_PARAM_IS_READONLY != 0,
+                });
+                self.parameter_values.push(value);
+            }
+        }
+    }
+
+    pub fn descriptor(&self) -> &PluginDescriptor {
+        &self.descriptor
+    }
+
+    pub fn parameters(&self) -> &[PluginParameter] {
+        &self.parameters
+    }
+
+    /// Plain value of a parameter, in the plugin's own range
+    pub fn parameter_plain(&self, param_id: u32) -> Option<f64> {
+        self.parameter_index.get(&param_id).map(|&index| self.parameter_values[index])
+    }
+
+    /// Normalized (0..1) value of a parameter
+    pub fn get_parameter(&self, param_id: u32) -> Result<f32, VjError> {
+        let &index = self.parameter_index.get(&param_id)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown CLAP parameter {}", param_id)))?;
+        let parameter = &self.parameters[index];
+        Ok(normalize(self.parameter_values[index], parameter.min, parameter.max) as f32)
+    }
+
+    /// Set a normalized (0..1) parameter from the next block on
+    pub fn set_parameter(&mut self, param_id: u32, value: f32) -> Result<(), VjError> {
+        let &index = self.parameter_index.get(&param_id)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown CLAP parameter {}", param_id)))?;
+        let parameter = &self.parameters[index];
+        if parameter.read_only {
+            return Err(VjError::AudioError(format!("CLAP parameter '{}' is read-only", parameter.name)));
+        }
+        let mut plain = parameter.min + (value as f64).clamp(0.0, 1.0) * (parameter.max - parameter.min);
+        if parameter.step_count > 0 {
+            plain = plain.round();
+        }
+        let param = clap_event_param_value {
+            header: event_header::<clap_event_param_value>(CLAP_EVENT_PARAM_VALUE),
+            param_id,
+            cookie: std::ptr::null_mut(),
+            note_id: -1,
+            port_index: -1,
+            channel: -1,
+            key: -1,
+            value: plain,
+        };
+        if !self.input_events.push(InputEvent { param }) {
+            return Err(VjError::AudioError("Too many CLAP events in one block".to_string()));
+        }
+        self.parameter_values[index] = plain;
+        Ok(())
+    }
+
+    /// Queue a raw MIDI message; plugins taking CLAP notes get note events, others the bytes
+    pub fn send_midi(&mut self, message: &[u8]) -> bool {
+        let [status, key, value, ..] = *message else {
+            return false;
+        };
+        match self.note_dialect {
+            NoteDialect::None => false,
+            NoteDialect::Midi => self.input_events.push(InputEvent {
+                midi: clap_event_midi {
+                    header: event_header::<clap_event_midi>(CLAP_EVENT_MIDI),
+                    port_index: 0,
+                    data: [status, key, value],
+                },
+            }),
+            NoteDialect::Clap => {
+                let event_type = match status & 0xF0 {
+                    0x90 if value > 0 => CLAP_EVENT_NOTE_ON,
+                    0x80 | 0x90 => CLAP_EVENT_NOTE_OFF,
+                    _ => return false,
+                };
+                self.input_events.push(InputEvent {
+                    note: clap_event_note {
+                        header: event_header::<clap_event_note>(event_type),
+                        note_id: -1,
+                        port_index: 0,
+                        channel: (status & 0x0F) as i16,
+                        key: key as i16,
+                        velocity: value as f64 / 127.0,
+                    },
+                })
+            }
+        }
+    }
+
+    pub fn accepts_midi(&self) -> bool {
+        self.note_dialect != NoteDialect::None
+    }
+
+    pub fn latency_samples(&self) -> u32 {
+        match self.latency.and_then(|latency| latency.get) {
+            // SAFETY: the plugin is live and activated
+            Some(get) => unsafe { get(self.plugin) },
+            None => 0,
+        }
+    }
+
+    /// The plugin's state chunk; plugins without the state extension save nothing
+    pub fn save_state(&mut self) -> Result<Vec<u8>, VjError> {
+        let Some(save) = self.state.and_then(|state| state.save) else {
+            return Ok(Vec::new());
+        };
+        let mut data = Vec::new();
+        let stream = clap_ostream { ctx: &mut data as *mut Vec<u8> as *mut c_void, write: Some(ostream_write) };
+        // SAFETY: the stream and its buffer outlive the call
+        if !unsafe { save(self.plugin, &stream) } {
+            return Err(VjError::AudioError(format!("CLAP plugin '{}' failed to save its state", self.descriptor.name)));
+        }
+        Ok(data)
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), VjError> {
+        let Some(load) = self.state.and_then(|state| state.load) else {
+            return Ok(());
+        };
+        let mut reader = StateReader { data, position: 0 };
+        let stream = clap_istream { ctx: &mut reader as *mut StateReader as *mut c_void, read: Some(istream_read) };
+        // SAFETY: the stream and its data outlive the call
+        if !unsafe { load(self.plugin, &stream) } {
+            return Err(VjError::AudioError(format!("CLAP plugin '{}' rejected its saved state", self.descriptor.name)));
+        }
+        self.reload_parameter_values();
+        Ok(())
+    }
+
+    fn reload_parameter_values(&mut self) {
+        let Some(get_value) = self.params.and_then(|params| params.get_value) else {
+            return;
+        };
+        for (parameter, value) in self.parameters.iter().zip(self.parameter_values.iter_mut()) {
+            // SAFETY: the plugin is live
+            unsafe { get_value(self.plugin, parameter.id, value) };
+        }
+    }
+
+    /// Run callbacks the plugin asked for since the last block
+    fn service_requests(&mut self) {
+        if self.host.requests.callback.swap(false, Ordering::AcqRel) {
+            // SAFETY: the plugin is live and this is its main thread
+            unsafe {
+                if let Some(on_main_thread) = (*self.plugin).on_main_thread {
+                    on_main_thread(self.plugin);
+                }
+            }
+        }
+        // Restarts only matter for setups we don't change while running
+        self.host.requests.restart.store(false, Ordering::Release);
+    }
+
+    fn process_block(&mut self, frames: usize) -> Result<(), VjError> {
+        let process = clap_process {
+            steady_time: self.steady_time,
+            frames_count: frames as u32,
+            transport: std::ptr::null(),
+            audio_inputs: self.input_buffers.as_ptr(),
+            audio_outputs: self.output_buffers.as_mut_ptr(),
+            audio_inputs_count: self.input_buffers.len() as u32,
+            audio_outputs_count: self.output_buffers.len() as u32,
+            in_events: &self.input_events.list,
+            out_events: &self.output_events.list,
+        };
+        // SAFETY: every buffer holds at least `block_size` frames and frames <= block_size
+        let status = unsafe { (*self.plugin).process.map_or(CLAP_PROCESS_ERROR, |process_fn| process_fn(self.plugin, &process)) };
+        self.steady_time += frames as i64;
+        self.input_events.events.clear();
+
+        for (param_id, value) in self.output_events.values.drain(..) {
+            if let Some(&index) = self.parameter_index.get(&param_id) {
+                self.parameter_values[index] = value;
+            }
+        }
+
+        if status == CLAP_PROCESS_ERROR {
+            return Err(VjError::AudioError(format!("CLAP plugin '{}' failed to process", self.descriptor.name)));
+        }
+        Ok(())
+    }
+
+    /// Process interleaved audio with the setup's channel count. Missing input is silence;
+    /// output replaces what was in `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> Result<(), VjError> {
+        self.service_requests();
+        let channels = self.setup.num_channels.max(1);
+        let frames = output.len() / channels;
+        let mut start = 0;
+        while start < frames {
+            let count = (frames - start).min(self.setup.block_size);
+
+            for (index, port) in self.input_ports.iter_mut().enumerate() {
+                for (port_channel, samples) in port.channels.iter_mut().enumerate() {
+                    for (frame, sample) in samples[..count].iter_mut().enumerate() {
+                        let position = (start + frame) * channels + port_channel % channels;
+                        *sample = if index == 0 { input.get(position).copied().unwrap_or(0.0) } else { 0.0 };
+                    }
+                }
+            }
+            for port in &mut self.output_ports {
+                for samples in &mut port.channels {
+                    samples[..count].fill(0.0);
+                }
+            }
+
+            self.process_block(count)?;
+
+            let main = self.output_ports.first().filter(|port| !port.channels.is_empty());
+            for frame in 0..count {
+                for channel in 0..channels {
+                    output[(start + frame) * channels + channel] =
+                        main.map(|port| port.channels[channel % port.channels.len()][frame]).unwrap_or(0.0);
+                }
+            }
+            start += count;
+        }
+        output[frames * channels..].fill(0.0);
+        Ok(())
+    }
+}
+
+impl Drop for ClapPluginInstance {
+    fn drop(&mut self) {
+        // SAFETY: shutting down in the order CLAP documents; the plugin is still live
+        unsafe {
+            let plugin = &*self.plugin;
+            if let Some(stop) = plugin.stop_processing {
+                stop(self.plugin);
+            }
+            if let Some(deactivate) = plugin.deactivate {
+                deactivate(self.plugin);
+            }
+            if let Some(destroy) = plugin.destroy {
+                destroy(self.plugin);
+            }
+        }
+    }
+}
+
+/// # Safety
+/// `T` must be the table type of extension `id`
+unsafe fn extension<T>(plugin: *const clap_plugin, id: &CStr) -> Option<&'static T> {
+    let get_extension = (*plugin).get_extension?;
+    (get_extension(plugin, id.as_ptr()) as *const T).as_ref()
+}
+
+fn normalize(plain: f64, min: f64, max: f64) -> f64 {
+    if max > min {
+        ((plain - min) / (max - min)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Hosts CLAP plugins, sharing each library between its instances
+pub struct ClapPluginHost {
+    setup: PluginSetup,
+    modules: HashMap<PathBuf, Arc<ClapModule>>,
+    instances: HashMap<String, ClapPluginInstance>,
+}
+
+impl ClapPluginHost {
+    pub fn new() -> Self {
+        Self { setup: PluginSetup::default(), modules: HashMap::new(), instances: HashMap::new() }
+    }
+
+    pub fn instance(&self, instance_id: &str) -> Option<&ClapPluginInstance> {
+        self.instances.get(instance_id)
+    }
+
+    pub fn instance_mut(&mut self, instance_id: &str) -> Option<&mut ClapPluginInstance> {
+        self.instances.get_mut(instance_id)
+    }
+
+    fn require(&self, instance_id: &str) -> Result<&ClapPluginInstance, VjError> {
+        self.instances.get(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Clap, instance_id))
+    }
+
+    fn require_mut(&mut self, instance_id: &str) -> Result<&mut ClapPluginInstance, VjError> {
+        self.instances.get_mut(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Clap, instance_id))
+    }
+}
+
+impl Default for ClapPluginHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PluginHost for ClapPluginHost {
+    fn format(&self) -> PluginFormat {
+        PluginFormat::Clap
+    }
+
+    fn set_setup(&mut self, setup: PluginSetup) {
+        self.setup = setup;
+    }
+
+    fn load(&mut self, instance_id: &str, descriptor: &PluginDescriptor) -> Result<(), VjError> {
+        self.setup.validate()?;
+        let module = match self.modules.get(&descriptor.path) {
+            Some(module) => module.clone(),
+            None => {
+                let module = ClapModule::load(&descriptor.path)?;
+                self.modules.insert(descriptor.path.clone(), module.clone());
+                module
+            }
+        };
+        // Replace after creating, so a failed load keeps the old instance
+        let instance = ClapPluginInstance::from_module(module, descriptor, self.setup)?;
+        self.instances.insert(instance_id.to_string(), instance);
+        Ok(())
+    }
+
+    fn unload(&mut self, instance_id: &str) -> bool {
+        let removed = self.instances.remove(instance_id).is_some();
+        let in_use: Vec<PathBuf> = self.instances.values().map(|instance| instance.descriptor.path.clone()).collect();
+        self.modules.retain(|path, _| in_use.contains(path));
+        removed
+    }
+
+    fn instance_ids(&self) -> Vec<String> {
+        self.instances.keys().cloned().collect()
+    }
+
+    fn parameters(&self, instance_id: &str) -> Result<Vec<PluginParameter>, VjError> {
+        Ok(self.require(instance_id)?.parameters.clone())
+    }
+
+    fn set_parameter(&mut self, instance_id: &str, param_id: u32, value: f32) -> Result<(), VjError> {
+        self.require_mut(instance_id)?.set_parameter(param_id, value)
+    }
+
+    fn get_parameter(&self, instance_id: &str, param_id: u32) -> Result<f32, VjError> {
+        self.require(instance_id)?.get_parameter(param_id)
+    }
+
+    fn send_midi(&mut self, instance_id: &str, message: &[u8]) -> Result<bool, VjError> {
+        Ok(self.require_mut(instance_id)?.send_midi(message))
+    }
+
+    fn process(&mut self, instance_id: &str, input: &[f32], output: &mut [f32]) -> Result<(), VjError> {
+        self.require_mut(instance_id)?.process(input, output)
+    }
+
+    fn latency(&self, instance_id: &str) -> Result<usize, VjError> {
+        Ok(self.require(instance_id)?.latency_samples() as usize)
+    }
+
+    fn instance_state(&mut self, instance_id: &str) -> Result<Value, VjError> {
+        let state = self.require_mut(instance_id)?.save_state()?;
+        Ok(serde_json::json!({ "state": base64::engine::general_purpose::STANDARD.encode(state) }))
+    }
+
+    fn restore_instance_state(&mut self, instance_id: &str, state: &Value) -> Result<(), VjError> {
+        let data = match state.get("state").and_then(Value::as_str) {
+            Some(text) => base64::engine::general_purpose::STANDARD.decode(text)
+                .map_err(|e| VjError::ConfigError(format!("Invalid CLAP state: {}", e)))?,
+            None => return Ok(()),
+        };
+        self.require_mut(instance_id)?.load_state(&data)
+    }
+}
@@ -1,3 +1,1148 @@
-// Effect nodes: Blur, Distortion, etc.
+// Effect nodes: filters, delay, reverb, modulation, distortion and dynamics
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::audio::{AudioNode, BlockContext, AUDIO_BLOCK_SIZE};
+use crate::core::{AudioBufferData, DataType, InputPort, Node, NodeId, OutputPort};
+
 pub struct BlurNode;
-pub struct DistortionNode;
\ No newline at end of file
+
+/// Most channels an effect processes; further channels pass through dry
+pub const MAX_EFFECT_CHANNELS: usize = 8;
+
+/// A modulatable effect parameter
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EffectParameter {
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+}
+
+impl EffectParameter {
+    const fn new(name: &'static str, min: f32, max: f32, default: f32) -> Self {
+        Self { name, min, max, default }
+    }
+
+    pub fn clamp(&self, value: f32) -> f32 {
+        if value.is_nan() {
+            self.default
+        } else {
+            value.clamp(self.min, self.max)
+        }
+    }
+}
+
+/// Audio effect processing interleaved buffers in place.
+///
+/// `process` is real-time safe; delay lines and other buffers are allocated in `prepare`.
+pub trait AudioEffect: Send + Sync {
+    fn name(&self) -> &str;
+    fn parameters(&self) -> &'static [EffectParameter];
+    fn parameter(&self, index: usize) -> f32;
+    /// Values are clamped to the parameter's range
+    fn set_parameter(&mut self, index: usize, value: f32);
+    fn prepare(&mut self, sample_rate: f32, channels: usize);
+    /// Clear internal state such as delay lines and filter memory
+    fn reset(&mut self);
+    fn process(&mut self, buffer: &mut [f32], channels: usize);
+}
+
+/// Set a parameter by name; returns false if the effect has no such parameter
+pub fn set_effect_parameter(effect: &mut dyn AudioEffect, name: &str, value: f32) -> bool {
+    match effect.parameters().iter().position(|parameter| parameter.name == name) {
+        Some(index) => {
+            effect.set_parameter(index, value);
+            true
+        }
+        None => false,
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-9).log10()
+}
+
+/// Biquad response shape
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BiquadMode {
+    LowPass,
+    HighPass,
+    /// Constant 0 dB peak gain
+    BandPass,
+    Notch,
+    LowShelf,
+    HighShelf,
+    Peak,
+}
+
+const BIQUAD_PARAMETERS: &[EffectParameter] = &[
+    EffectParameter::new("cutoff", 20.0, 20000.0, 1000.0),
+    EffectParameter::new("q", 0.1, 20.0, std::f32::consts::FRAC_1_SQRT_2),
+    EffectParameter::new("gain_db", -24.0, 24.0, 0.0),
+];
+
+/// RBJ cookbook biquad in transposed direct form II
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    mode: BiquadMode,
+    cutoff: f32,
+    q: f32,
+    gain_db: f32,
+    sample_rate: f32,
+    /// b0, b1, b2, a1, a2 normalized by a0
+    coefficients: [f64; 5],
+    state: [[f32; 2]; MAX_EFFECT_CHANNELS],
+}
+
+impl Biquad {
+    pub fn new(mode: BiquadMode, cutoff: f32, q: f32) -> Self {
+        let mut biquad = Self {
+            mode,
+            cutoff: BIQUAD_PARAMETERS[0].clamp(cutoff),
+            q: BIQUAD_PARAMETERS[1].clamp(q),
+            gain_db: 0.0,
+            sample_rate: 44100.0,
+            coefficients: [1.0, 0.0, 0.0, 0.0, 0.0],
+            state: [[0.0; 2]; MAX_EFFECT_CHANNELS],
+        };
+        biquad.update();
+        biquad
+    }
+
+    /// Shelf or peak filter with a gain
+    pub fn with_gain(mode: BiquadMode, cutoff: f32, q: f32, gain_db: f32) -> Self {
+        let mut biquad = Self::new(mode, cutoff, q);
+        biquad.set_parameter(2, gain_db);
+        biquad
+    }
+
+    pub fn mode(&self) -> BiquadMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: BiquadMode) {
+        self.mode = mode;
+        self.update();
+    }
+
+    fn update(&mut self) {
+        let frequency = (self.cutoff as f64).min(self.sample_rate as f64 * 0.49);
+        let w0 = std::f64::consts::TAU * frequency / self.sample_rate as f64;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * self.q as f64);
+        let a = 10f64.powf(self.gain_db as f64 / 40.0);
+        let shelf = 2.0 * a.sqrt() * alpha;
+
+        let [b0, b1, b2, a0, a1, a2] = match self.mode {
+            BiquadMode::LowPass => [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            BiquadMode::HighPass => [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            BiquadMode::BandPass => [alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            BiquadMode::Notch => [1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            BiquadMode::Peak => [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            BiquadMode::LowShelf => [
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ],
+            BiquadMode::HighShelf => [
+                a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                (a + 1.0) - (a - 1.0) * cos + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - shelf,
+            ],
+        };
+        self.coefficients = [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0];
+    }
+
+    /// Magnitude of the frequency response at `frequency` Hz
+    pub fn magnitude_at(&self, frequency: f32) -> f32 {
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+        let w = std::f64::consts::TAU * frequency as f64 / self.sample_rate as f64;
+        let (sin1, cos1) = w.sin_cos();
+        let (sin2, cos2) = (2.0 * w).sin_cos();
+        let numerator = ((b0 + b1 * cos1 + b2 * cos2).powi(2) + (b1 * sin1 + b2 * sin2).powi(2)).sqrt();
+        let denominator = ((1.0 + a1 * cos1 + a2 * cos2).powi(2) + (a1 * sin1 + a2 * sin2).powi(2)).sqrt();
+        (numerator / denominator) as f32
+    }
+}
+
+impl AudioEffect for Biquad {
+    fn name(&self) -> &str {
+        "Biquad"
+    }
+
+    fn parameters(&self) -> &'static [EffectParameter] {
+        BIQUAD_PARAMETERS
+    }
+
+    fn parameter(&self, index: usize) -> f32 {
+        match index {
+            0 => self.cutoff,
+            1 => self.q,
+            2 => self.gain_db,
+            _ => 0.0,
+        }
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        let Some(parameter) = BIQUAD_PARAMETERS.get(index) else {
+            return;
+        };
+        let value = parameter.clamp(value);
+        match index {
+            0 => self.cutoff = value,
+            1 => self.q = value,
+            _ => self.gain_db = value,
+        }
+        self.update();
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _channels: usize) {
+        self.sample_rate = sample_rate;
+        self.update();
+    }
+
+    fn reset(&mut self) {
+        self.state = [[0.0; 2]; MAX_EFFECT_CHANNELS];
+    }
+
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        let [b0, b1, b2, a1, a2] = self.coefficients.map(|c| c as f32);
+        for frame in buffer.chunks_exact_mut(channels.max(1)) {
+            for (sample, state) in frame.iter_mut().zip(self.state.iter_mut()) {
+                let x = *sample;
+                let y = b0 * x + state[0];
+                state[0] = b1 * x - a1 * y + state[1];
+                state[1] = b2 * x - a2 * y;
+                *sample = y;
+            }
+        }
+    }
+}
+
+/// State-variable filter output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SvfMode {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
+const SVF_PARAMETERS: &[EffectParameter] = &[
+    EffectParameter::new("cutoff", 20.0, 20000.0, 1000.0),
+    EffectParameter::new("resonance", 0.0, 1.0, 0.2),
+];
+
+/// Trapezoidal state-variable filter; stays stable under fast cutoff modulation
+#[derive(Debug, Clone)]
+pub struct StateVariableFilter {
+    pub mode: SvfMode,
+    cutoff: f32,
+    resonance: f32,
+    sample_rate: f32,
+    state: [[f32; 2]; MAX_EFFECT_CHANNELS],
+}
+
+impl StateVariableFilter {
+    pub fn new(mode: SvfMode, cutoff: f32, resonance: f32) -> Self {
+        Self {
+            mode,
+            cutoff: SVF_PARAMETERS[0].clamp(cutoff),
+            resonance: SVF_PARAMETERS[1].clamp(resonance),
+            sample_rate: 44100.0,
+            state: [[0.0; 2]; MAX_EFFECT_CHANNELS],
+        }
+    }
+}
+
+impl AudioEffect for StateVariableFilter {
+    fn name(&self) -> &str {
+        "StateVariableFilter"
+    }
+
+    fn parameters(&self) -> &'static [EffectParameter] {
+        SVF_PARAMETERS
+    }
+
+    fn parameter(&self, index: usize) -> f32 {
+        match index {
+            0 => self.cutoff,
+            1 => self.resonance,
+            _ => 0.0,
+        }
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.cutoff = SVF_PARAMETERS[0].clamp(value),
+            1 => self.resonance = SVF_PARAMETERS[1].clamp(value),
+            _ => {}
+        }
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _channels: usize) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn reset(&mut self) {
+        self.state = [[0.0; 2]; MAX_EFFECT_CHANNELS];
+    }
+
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        let g = (PI * self.cutoff.min(self.sample_rate * 0.49) / self.sample_rate).tan();
+        // k runs from 2 (no resonance) down to 0.05, short of self-oscillation
+        let k = 2.0 - 1.95 * self.resonance;
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+
+        for frame in buffer.chunks_exact_mut(channels.max(1)) {
+            for (sample, state) in frame.iter_mut().zip(self.state.iter_mut()) {
+                let x = *sample;
+                let v3 = x - state[1];
+                let v1 = a1 * state[0] + a2 * v3;
+                let v2 = state[1] + a2 * state[0] + a3 * v3;
+                state[0] = 2.0 * v1 - state[0];
+                state[1] = 2.0 * v2 - state[1];
+                *sample = match self.mode {
+                    SvfMode::LowPass => v2,
+                    SvfMode::HighPass => x - k * v1 - v2,
+                    SvfMode::BandPass => v1,
+                    SvfMode::Notch => x - k * v1,
+                };
+            }
+        }
+    }
+}
+
+/// Longest delay time
+pub const MAX_DELAY_SECS: f32 = 4.0;
+
+const DELAY_PARAMETERS: &[EffectParameter] = &[
+    EffectParameter::new("time_ms", 1.0, MAX_DELAY_SECS * 1000.0, 375.0),
+    EffectParameter::new("sync", 0.0, 1.0, 0.0),
+    EffectParameter::new("beats", 0.0625, 4.0, 0.75),
+    EffectParameter::new("bpm", 20.0, 300.0, 120.0),
+    EffectParameter::new("feedback", 0.0, 0.95, 0.4),
+    EffectParameter::new("damping", 0.0, 1.0, 0.2),
+    EffectParameter::new("mix", 0.0, 1.0, 0.35),
+];
+
+/// Feedback delay. With `sync` on, the time is `beats` at `bpm`.
+#[derive(Debug, Clone)]
+pub struct Delay {
+    values: [f32; 7],
+    sample_rate: f32,
+    lines: Vec<Vec<f32>>,
+    damping_state: [f32; MAX_EFFECT_CHANNELS],
+    write: usize,
+    /// Smoothed delay in samples, so time changes glide instead of clicking
+    current_delay: f32,
+}
+
+impl Default for Delay {
+    fn default() -> Self {
+        Self {
+            values: std::array::from_fn(|index| DELAY_PARAMETERS[index].default),
+            sample_rate: 44100.0,
+            lines: Vec::new(),
+            damping_state: [0.0; MAX_EFFECT_CHANNELS],
+            write: 0,
+            current_delay: 0.0,
+        }
+    }
+}
+
+impl Delay {
+    pub fn new(time_ms: f32, feedback: f32, mix: f32) -> Self {
+        let mut delay = Self::default();
+        delay.set_parameter(0, time_ms);
+        delay.set_parameter(4, feedback);
+        delay.set_parameter(6, mix);
+        delay
+    }
+
+    /// Delay of `beats` at `bpm`
+    pub fn synced(beats: f32, bpm: f32, feedback: f32, mix: f32) -> Self {
+        let mut delay = Self::new(DELAY_PARAMETERS[0].default, feedback, mix);
+        delay.set_parameter(1, 1.0);
+        delay.set_parameter(2, beats);
+        delay.set_parameter(3, bpm);
+        delay
+    }
+
+    /// Delay time in seconds from the current parameters
+    pub fn delay_secs(&self) -> f32 {
+        let secs = if self.values[1] >= 0.5 {
+            self.values[2] * 60.0 / self.values[3]
+        } else {
+            self.values[0] / 1000.0
+        };
+        secs.min(MAX_DELAY_SECS)
+    }
+
+    fn target_delay(&self) -> f32 {
+        let length = self.lines.first().map(Vec::len).unwrap_or(0) as f32;
+        (self.delay_secs() * self.sample_rate).clamp(1.0, (length - 2.0).max(1.0))
+    }
+}
+
+impl AudioEffect for Delay {
+    fn name(&self) -> &str {
+        "Delay"
+    }
+
+    fn parameters(&self) -> &'static [EffectParameter] {
+        DELAY_PARAMETERS
+    }
+
+    fn parameter(&self, index: usize) -> f32 {
+        self.values.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        if let Some(parameter) = DELAY_PARAMETERS.get(index) {
+            self.values[index] = parameter.clamp(value);
+        }
+    }
+
+    fn prepare(&mut self, sample_rate: f32, channels: usize) {
+        self.sample_rate = sample_rate;
+        let length = (MAX_DELAY_SECS * sample_rate) as usize + 4;
+        self.lines = vec![vec![0.0; length]; channels.min(MAX_EFFECT_CHANNELS)];
+        self.write = 0;
+        self.current_delay = self.target_delay();
+    }
+
+    fn reset(&mut self) {
+        for line in self.lines.iter_mut() {
+            line.fill(0.0);
+        }
+        self.damping_state = [0.0; MAX_EFFECT_CHANNELS];
+        self.current_delay = self.target_delay();
+    }
+
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        let Some(length) = self.lines.first().map(Vec::len) else {
+            return;
+        };
+        let target = self.target_delay();
+        let glide = 1.0 - (-1.0 / (0.05 * self.sample_rate)).exp();
+        let feedback = self.values[4];
+        let damping = self.values[5];
+        let mix = self.values[6];
+
+        for frame in buffer.chunks_exact_mut(channels.max(1)) {
+            self.current_delay += (target - self.current_delay) * glide;
+            let read = self.write as f32 - self.current_delay + length as f32;
+            let index = read as usize;
+            let fraction = read - index as f32;
+
+            for ((sample, line), damped) in frame.iter_mut().zip(self.lines.iter_mut()).zip(self.damping_state.iter_mut()) {
+                let a = line[index % length];
+                let b = line[(index + 1) % length];
+                let delayed = a + (b - a) * fraction;
+                *damped += (delayed - *damped) * (1.0 - damping);
+                line[self.write] = *sample + *damped * feedback;
+                *sample += (delayed - *sample) * mix;
+            }
+            self.write = (self.write + 1) % length;
+        }
+    }
+}
+
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+
+#[derive(Debug, Clone, Default)]
+struct Comb {
+    buffer: Vec<f32>,
+    index: usize,
+    filter: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.index];
+        self.filter = output * (1.0 - damping) + self.filter * damping;
+        self.buffer[self.index] = input + self.filter * feedback;
+        self.index = (self.index + 1) % self.buffer.len();
+        output
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Allpass {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.index];
+        self.buffer[self.index] = input + delayed * 0.5;
+        self.index = (self.index + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+const REVERB_PARAMETERS: &[EffectParameter] = &[
+    EffectParameter::new("room_size", 0.0, 1.0, 0.5),
+    EffectParameter::new("damping", 0.0, 1.0, 0.5),
+    EffectParameter::new("width", 0.0, 1.0, 1.0),
+    EffectParameter::new("mix", 0.0, 1.0, 0.3),
+];
+
+/// Freeverb-style algorithmic reverb: parallel damped combs into serial allpasses.
+/// Processes the first two channels; mono input gets the left tank only.
+#[derive(Debug, Clone)]
+pub struct Reverb {
+    values: [f32; 4],
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+}
+
+impl Default for Reverb {
+    fn default() -> Self {
+        Self {
+            values: std::array::from_fn(|index| REVERB_PARAMETERS[index].default),
+            combs: Default::default(),
+            allpasses: Default::default(),
+        }
+    }
+}
+
+impl Reverb {
+    pub fn new(room_size: f32, damping: f32, mix: f32) -> Self {
+        let mut reverb = Self::default();
+        reverb.set_parameter(0, room_size);
+        reverb.set_parameter(1, damping);
+        reverb.set_parameter(3, mix);
+        reverb
+    }
+}
+
+impl AudioEffect for Reverb {
+    fn name(&self) -> &str {
+        "Reverb"
+    }
+
+    fn parameters(&self) -> &'static [EffectParameter] {
+        REVERB_PARAMETERS
+    }
+
+    fn parameter(&self, index: usize) -> f32 {
+        self.values.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        if let Some(parameter) = REVERB_PARAMETERS.get(index) {
+            self.values[index] = parameter.clamp(value);
+        }
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _channels: usize) {
+        let scale = sample_rate / 44100.0;
+        let length = |samples: usize| ((samples as f32 * scale) as usize).max(1);
+        for (side, spread) in [0, STEREO_SPREAD].into_iter().enumerate() {
+            self.combs[side] = COMB_TUNING.iter()
+                .map(|&tuning| Comb { buffer: vec![0.0; length(tuning + spread)], ..Default::default() })
+                .collect();
+            self.allpasses[side] = ALLPASS_TUNING.iter()
+                .map(|&tuning| Allpass { buffer: vec![0.0; length(tuning + spread)], index: 0 })
+                .collect();
+        }
+    }
+
+    fn reset(&mut self) {
+        for comb in self.combs.iter_mut().flatten() {
+            comb.buffer.fill(0.0);
+            comb.filter = 0.0;
+        }
+        for allpass in self.allpasses.iter_mut().flatten() {
+            allpass.buffer.fill(0.0);
+        }
+    }
+
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        if self.combs[0].is_empty() {
+            return;
+        }
+        let channels = channels.max(1);
+        let feedback = self.values[0] * 0.28 + 0.7;
+        let damping = self.values[1] * 0.4;
+        let width = self.values[2];
+        let mix = self.values[3];
+        let wet_direct = 3.0 * (width / 2.0 + 0.5);
+        let wet_cross = 3.0 * ((1.0 - width) / 2.0);
+        let sides = channels.min(2);
+
+        for frame in buffer.chunks_exact_mut(channels) {
+            let input = frame[..sides].iter().sum::<f32>() * 0.015;
+            let mut tanks = [0.0f32; 2];
+            for (side, tank) in tanks.iter_mut().enumerate().take(sides) {
+                let mut output: f32 = self.combs[side].iter_mut()
+                    .map(|comb| comb.process(input, feedback, damping))
+                    .sum();
+                for allpass in self.allpasses[side].iter_mut() {
+                    output = allpass.process(output);
+                }
+                *tank = output;
+            }
+
+            if sides == 1 {
+                frame[0] += (tanks[0] * 3.0 - frame[0]) * mix;
+            } else {
+                let left = tanks[0] * wet_direct + tanks[1] * wet_cross;
+                let right = tanks[1] * wet_direct + tanks[0] * wet_cross;
+                frame[0] += (left - frame[0]) * mix;
+                frame[1] += (right - frame[1]) * mix;
+            }
+        }
+    }
+}
+
+const CHORUS_PARAMETERS: &[EffectParameter] = &[
+    EffectParameter::new("rate", 0.01, 10.0, 0.8),
+    EffectParameter::new("depth_ms", 0.0, 10.0, 3.0),
+    EffectParameter::new("delay_ms", 0.5, 30.0, 15.0),
+    EffectParameter::new("feedback", -0.95, 0.95, 0.0),
+    EffectParameter::new("mix", 0.0, 1.0, 0.5),
+];
+
+/// LFO-modulated short delay. Long delays without feedback chorus; short delays with
+/// feedback flange. Each channel's LFO is offset for stereo width.
+#[derive(Debug, Clone)]
+pub struct Chorus {
+    name: &'static str,
+    values: [f32; 5],
+    sample_rate: f32,
+    lines: Vec<Vec<f32>>,
+    write: usize,
+    phase: f32,
+}
+
+impl Chorus {
+    pub fn new() -> Self {
+        Self {
+            name: "Chorus",
+            values: std::array::from_fn(|index| CHORUS_PARAMETERS[index].default),
+            sample_rate: 44100.0,
+            lines: Vec::new(),
+            write: 0,
+            phase: 0.0,
+        }
+    }
+
+    pub fn flanger() -> Self {
+        let mut flanger = Self { name: "Flanger", ..Self::new() };
+        flanger.set_parameter(0, 0.25);
+        flanger.set_parameter(1, 1.5);
+        flanger.set_parameter(2, 2.0);
+        flanger.set_parameter(3, 0.7);
+        flanger
+    }
+}
+
+impl Default for Chorus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioEffect for Chorus {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn parameters(&self) -> &'static [EffectParameter] {
+        CHORUS_PARAMETERS
+    }
+
+    fn parameter(&self, index: usize) -> f32 {
+        self.values.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        if let Some(parameter) = CHORUS_PARAMETERS.get(index) {
+            self.values[index] = parameter.clamp(value);
+        }
+    }
+
+    fn prepare(&mut self, sample_rate: f32, channels: usize) {
+        self.sample_rate = sample_rate;
+        // Longest delay plus depth, with room for interpolation
+        let length = (0.045 * sample_rate) as usize + 4;
+        self.lines = vec![vec![0.0; length]; channels.min(MAX_EFFECT_CHANNELS)];
+        self.write = 0;
+    }
+
+    fn reset(&mut self) {
+        for line in self.lines.iter_mut() {
+            line.fill(0.0);
+        }
+        self.phase = 0.0;
+    }
+
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        let Some(length) = self.lines.first().map(Vec::len) else {
+            return;
+        };
+        let [rate, depth_ms, delay_ms, feedback, mix] = self.values;
+        let line_channels = self.lines.len().max(1);
+
+        for frame in buffer.chunks_exact_mut(channels.max(1)) {
+            for (channel, (sample, line)) in frame.iter_mut().zip(self.lines.iter_mut()).enumerate() {
+                let phase = self.phase + channel as f32 / line_channels as f32 * 0.25;
+                let modulation = 0.5 + 0.5 * (phase * TAU).sin();
+                let delay = ((delay_ms + depth_ms * modulation) * 0.001 * self.sample_rate).clamp(1.0, length as f32 - 2.0);
+
+                let read = self.write as f32 - delay + length as f32;
+                let index = read as usize;
+                let fraction = read - index as f32;
+                let a = line[index % length];
+                let b = line[(index + 1) % length];
+                let delayed = a + (b - a) * fraction;
+
+                line[self.write] = *sample + delayed * feedback;
+                *sample += (delayed - *sample) * mix;
+            }
+            self.write = (self.write + 1) % length;
+            self.phase = (self.phase + rate / self.sample_rate).fract();
+        }
+    }
+}
+
+const BITCRUSHER_PARAMETERS: &[EffectParameter] = &[
+    EffectParameter::new("bits", 1.0, 24.0, 8.0),
+    EffectParameter::new("downsample", 1.0, 64.0, 1.0),
+    EffectParameter::new("mix", 0.0, 1.0, 1.0),
+];
+
+/// Bit-depth and sample-rate reduction
+#[derive(Debug, Clone)]
+pub struct Bitcrusher {
+    values: [f32; 3],
+    held: [f32; MAX_EFFECT_CHANNELS],
+    counter: f32,
+}
+
+impl Bitcrusher {
+    pub fn new(bits: f32, downsample: f32) -> Self {
+        let mut crusher = Self {
+            values: std::array::from_fn(|index| BITCRUSHER_PARAMETERS[index].default),
+            held: [0.0; MAX_EFFECT_CHANNELS],
+            counter: 0.0,
+        };
+        crusher.set_parameter(0, bits);
+        crusher.set_parameter(1, downsample);
+        crusher
+    }
+}
+
+impl AudioEffect for Bitcrusher {
+    fn name(&self) -> &str {
+        "Bitcrusher"
+    }
+
+    fn parameters(&self) -> &'static [EffectParameter] {
+        BITCRUSHER_PARAMETERS
+    }
+
+    fn parameter(&self, index: usize) -> f32 {
+        self.values.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        if let Some(parameter) = BITCRUSHER_PARAMETERS.get(index) {
+            self.values[index] = parameter.clamp(value);
+        }
+    }
+
+    fn prepare(&mut self, _sample_rate: f32, _channels: usize) {}
+
+    fn reset(&mut self) {
+        self.held = [0.0; MAX_EFFECT_CHANNELS];
+        self.counter = 0.0;
+    }
+
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        let [bits, downsample, mix] = self.values;
+        let levels = 2f32.powf(bits - 1.0);
+
+        for frame in buffer.chunks_exact_mut(channels.max(1)) {
+            let sample_now = self.counter <= 0.0;
+            if sample_now {
+                self.counter += downsample;
+            }
+            self.counter -= 1.0;
+            for (sample, held) in frame.iter_mut().zip(self.held.iter_mut()) {
+                if sample_now {
+                    *held = (*sample * levels).round() / levels;
+                }
+                *sample += (*held - *sample) * mix;
+            }
+        }
+    }
+}
+
+/// Waveshaping curve
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DistortionMode {
+    /// tanh saturation
+    SoftClip,
+    HardClip,
+    /// Folds peaks back into range for a harsher, brighter sound
+    Foldback,
+}
+
+const DISTORTION_PARAMETERS: &[EffectParameter] = &[
+    EffectParameter::new("drive_db", 0.0, 48.0, 12.0),
+    EffectParameter::new("tone", 200.0, 20000.0, 8000.0),
+    EffectParameter::new("output_db", -24.0, 12.0, -6.0),
+    EffectParameter::new("mix", 0.0, 1.0, 1.0),
+];
+
+/// Waveshaping distortion with a one-pole tone control after the shaper
+#[derive(Debug, Clone)]
+pub struct Distortion {
+    pub mode: DistortionMode,
+    values: [f32; 4],
+    sample_rate: f32,
+    tone_state: [f32; MAX_EFFECT_CHANNELS],
+}
+
+impl Distortion {
+    pub fn new(mode: DistortionMode, drive_db: f32) -> Self {
+        let mut distortion = Self {
+            mode,
+            values: std::array::from_fn(|index| DISTORTION_PARAMETERS[index].default),
+            sample_rate: 44100.0,
+            tone_state: [0.0; MAX_EFFECT_CHANNELS],
+        };
+        distortion.set_parameter(0, drive_db);
+        distortion
+    }
+
+    fn shape(mode: DistortionMode, x: f32) -> f32 {
+        match mode {
+            DistortionMode::SoftClip => x.tanh(),
+            DistortionMode::HardClip => x.clamp(-1.0, 1.0),
+            DistortionMode::Foldback => {
+                // Triangle fold keeps the output in -1..1 for any input
+                let folded = (x + 1.0).rem_euclid(4.0);
+                if folded < 2.0 { folded - 1.0 } else { 3.0 - folded }
+            }
+        }
+    }
+}
+
+impl AudioEffect for Distortion {
+    fn name(&self) -> &str {
+        "Distortion"
+    }
+
+    fn parameters(&self) -> &'static [EffectParameter] {
+        DISTORTION_PARAMETERS
+    }
+
+    fn parameter(&self, index: usize) -> f32 {
+        self.values.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        if let Some(parameter) = DISTORTION_PARAMETERS.get(index) {
+            self.values[index] = parameter.clamp(value);
+        }
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _channels: usize) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn reset(&mut self) {
+        self.tone_state = [0.0; MAX_EFFECT_CHANNELS];
+    }
+
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        let [drive_db, tone, output_db, mix] = self.values;
+        let drive = db_to_gain(drive_db);
+        let output = db_to_gain(output_db);
+        let tone_coefficient = 1.0 - (-TAU * tone.min(self.sample_rate * 0.49) / self.sample_rate).exp();
+        let mode = self.mode;
+
+        for frame in buffer.chunks_exact_mut(channels.max(1)) {
+            for (sample, state) in frame.iter_mut().zip(self.tone_state.iter_mut()) {
+                let shaped = Self::shape(mode, *sample * drive);
+                *state += (shaped - *state) * tone_coefficient;
+                *sample += (*state * output - *sample) * mix;
+            }
+        }
+    }
+}
+
+const COMPRESSOR_PARAMETERS: &[EffectParameter] = &[
+    EffectParameter::new("threshold_db", -60.0, 0.0, -18.0),
+    EffectParameter::new("ratio", 1.0, 50.0, 4.0),
+    EffectParameter::new("attack_ms", 0.1, 200.0, 10.0),
+    EffectParameter::new("release_ms", 5.0, 2000.0, 100.0),
+    EffectParameter::new("knee_db", 0.0, 24.0, 6.0),
+    EffectParameter::new("makeup_db", 0.0, 24.0, 0.0),
+];
+
+/// Feed-forward compressor with a soft knee and stereo-linked peak detection.
+/// As a limiter the ratio is infinite.
+#[derive(Debug, Clone)]
+pub struct Compressor {
+    values: [f32; 6],
+    pub limiter: bool,
+    sample_rate: f32,
+    /// Smoothed gain reduction in dB, zero or negative
+    reduction_db: f32,
+}
+
+impl Compressor {
+    pub fn new(threshold_db: f32, ratio: f32) -> Self {
+        let mut compressor = Self {
+            values: std::array::from_fn(|index| COMPRESSOR_PARAMETERS[index].default),
+            limiter: false,
+            sample_rate: 44100.0,
+            reduction_db: 0.0,
+        };
+        compressor.set_parameter(0, threshold_db);
+        compressor.set_parameter(1, ratio);
+        compressor
+    }
+
+    /// Fast limiter holding peaks near `ceiling_db`
+    pub fn limiter(ceiling_db: f32) -> Self {
+        let mut limiter = Self::new(ceiling_db, COMPRESSOR_PARAMETERS[1].max);
+        limiter.limiter = true;
+        limiter.set_parameter(2, 0.1);
+        limiter.set_parameter(3, 50.0);
+        limiter.set_parameter(4, 0.0);
+        limiter
+    }
+
+    /// Current gain reduction in dB, for metering
+    pub fn gain_reduction_db(&self) -> f32 {
+        -self.reduction_db
+    }
+
+    /// Static gain computer: output level in dB for an input level in dB
+    pub fn output_level_db(&self, input_db: f32) -> f32 {
+        let [threshold, ratio, _, _, knee, _] = self.values;
+        let slope = if self.limiter { 0.0 } else { 1.0 / ratio };
+        let over = input_db - threshold;
+        if 2.0 * over < -knee {
+            input_db
+        } else if 2.0 * over.abs() <= knee && knee > 0.0 {
+            input_db + (slope - 1.0) * (over + knee / 2.0).powi(2) / (2.0 * knee)
+        } else {
+            threshold + over * slope
+        }
+    }
+}
+
+impl AudioEffect for Compressor {
+    fn name(&self) -> &str {
+        if self.limiter { "Limiter" } else { "Compressor" }
+    }
+
+    fn parameters(&self) -> &'static [EffectParameter] {
+        COMPRESSOR_PARAMETERS
+    }
+
+    fn parameter(&self, index: usize) -> f32 {
+        self.values.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        if let Some(parameter) = COMPRESSOR_PARAMETERS.get(index) {
+            self.values[index] = parameter.clamp(value);
+        }
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _channels: usize) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn reset(&mut self) {
+        self.reduction_db = 0.0;
+    }
+
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        let attack = (-1.0 / (self.values[2] * 0.001 * self.sample_rate)).exp();
+        let release = (-1.0 / (self.values[3] * 0.001 * self.sample_rate)).exp();
+        let makeup_db = self.values[5];
+
+        for frame in buffer.chunks_exact_mut(channels.max(1)) {
+            let level = frame.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            let input_db = gain_to_db(level);
+            let target = self.output_level_db(input_db) - input_db;
+
+            let coefficient = if target < self.reduction_db { attack } else { release };
+            self.reduction_db = target + (self.reduction_db - target) * coefficient;
+
+            let gain = db_to_gain(self.reduction_db + makeup_db);
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+    }
+}
+
+/// Graph node running an effect over audio buffers. Every effect parameter is an
+/// optional float input, so it can be modulated from the graph.
+pub struct EffectNode {
+    pub id: NodeId,
+    pub effect: Box<dyn AudioEffect>,
+    pub bypass: bool,
+    prepared: Option<(u32, usize)>,
+}
+
+impl EffectNode {
+    pub fn new(effect: Box<dyn AudioEffect>) -> Self {
+        Self {
+            id: NodeId::new(),
+            effect,
+            bypass: false,
+            prepared: None,
+        }
+    }
+}
+
+impl Node for EffectNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        self.effect.name()
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        let mut inputs = vec![
+            InputPort::new("audio", DataType::AudioBuffer),
+            InputPort::optional("bypass", DataType::Boolean),
+        ];
+        inputs.extend(self.effect.parameters().iter().map(|parameter| InputPort::optional(parameter.name, DataType::Float)));
+        inputs
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![OutputPort::new("audio", DataType::AudioBuffer)]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        if let Some(bypass) = inputs.get("bypass").and_then(Value::as_bool) {
+            self.bypass = bypass;
+        }
+        for (index, parameter) in self.effect.parameters().iter().enumerate() {
+            if let Some(value) = inputs.get(parameter.name).and_then(Value::as_f64) {
+                self.effect.set_parameter(index, value as f32);
+            }
+        }
+
+        let mut outputs = HashMap::new();
+        let Some(mut audio) = inputs.get("audio").and_then(|value| AudioBufferData::from_value(value, 44100.0)) else {
+            return Ok(outputs);
+        };
+
+        let format = (audio.sample_rate.to_bits(), audio.channels);
+        if self.prepared != Some(format) {
+            self.effect.prepare(audio.sample_rate, audio.channels);
+            self.prepared = Some(format);
+        }
+        if !self.bypass {
+            self.effect.process(&mut audio.samples, audio.channels);
+        }
+
+        outputs.insert("audio".to_string(), audio.to_value());
+        Ok(outputs)
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        self.effect.parameters().iter().enumerate()
+            .map(|(index, parameter)| (parameter.name.to_string(), serde_json::json!(self.effect.parameter(index))))
+            .chain(std::iter::once(("bypass".to_string(), Value::Bool(self.bypass))))
+            .collect()
+    }
+
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
+
+/// Runs an effect inside the audio-rate graph with one mono port per channel
+pub struct EffectAudioNode {
+    effect: Box<dyn AudioEffect>,
+    channels: usize,
+    interleaved: Vec<f32>,
+}
+
+impl EffectAudioNode {
+    pub fn new(effect: Box<dyn AudioEffect>, channels: usize) -> Self {
+        let channels = channels.clamp(1, MAX_EFFECT_CHANNELS);
+        Self {
+            effect,
+            channels,
+            interleaved: vec![0.0; AUDIO_BLOCK_SIZE * channels],
+        }
+    }
+}
+
+impl AudioNode for EffectAudioNode {
+    fn name(&self) -> &str {
+        self.effect.name()
+    }
+
+    fn input_count(&self) -> usize {
+        self.channels
+    }
+
+    fn output_count(&self) -> usize {
+        self.channels
+    }
+
+    fn parameter_names(&self) -> Vec<&'static str> {
+        self.effect.parameters().iter().map(|parameter| parameter.name).collect()
+    }
+
+    fn prepare(&mut self, sample_rate: f32) {
+        self.effect.prepare(sample_rate, self.channels);
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        self.effect.set_parameter(index, value);
+    }
+
+    fn process(&mut self, _context: &BlockContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let channels = self.channels;
+        for (channel, input) in inputs.iter().enumerate() {
+            for (frame, &sample) in input.iter().enumerate() {
+                self.interleaved[frame * channels + channel] = sample;
+            }
+        }
+        self.effect.process(&mut self.interleaved, channels);
+        for (channel, output) in outputs.iter_mut().enumerate() {
+            for (frame, sample) in output.iter_mut().enumerate() {
+                *sample = self.interleaved[frame * channels + channel];
+            }
+        }
+    }
+}
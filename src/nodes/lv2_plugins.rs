@@ -0,0 +1,900 @@
+//! LV2 plugin hosting
+//!
+//! Bundles are described by their Turtle metadata (`manifest.ttl` and the files it
+//! points to with `rdfs:seeAlso`), so scanning never loads a binary. Instances get the
+//! URID map, options and bounded block length features, take MIDI through an atom
+//! sequence port, and are driven through their control ports. Control input values are
+//! an instance's saved state; the LV2 state extension isn't used.
+
+mod turtle;
+
+pub use turtle::{resolve_iri, Term, Triple, TurtleGraph};
+
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use libloading::Library;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::core::VjError;
+use super::plugin_host::{unknown_instance, PluginDescriptor, PluginFormat, PluginHost, PluginParameter, PluginSetup};
+
+const LV2: &str = "http://lv2plug.in/ns/lv2core#";
+const RDFS_SEE_ALSO: &str = "http://www.w3.org/2000/01/rdf-schema#seeAlso";
+const DOAP: &str = "http://usefulinc.com/ns/doap#";
+const FOAF_NAME: &str = "http://xmlns.com/foaf/0.1/name";
+const ATOM: &str = "http://lv2plug.in/ns/ext/atom#";
+const MIDI_EVENT: &str = "http://lv2plug.in/ns/ext/midi#MidiEvent";
+const URID_MAP: &str = "http://lv2plug.in/ns/ext/urid#map";
+const URID_UNMAP: &str = "http://lv2plug.in/ns/ext/urid#unmap";
+const OPTIONS: &str = "http://lv2plug.in/ns/ext/options#options";
+const BOUNDED_BLOCK_LENGTH: &str = "http://lv2plug.in/ns/ext/buf-size#boundedBlockLength";
+const MIN_BLOCK_LENGTH: &str = "http://lv2plug.in/ns/ext/buf-size#minBlockLength";
+const MAX_BLOCK_LENGTH: &str = "http://lv2plug.in/ns/ext/buf-size#maxBlockLength";
+const NOMINAL_BLOCK_LENGTH: &str = "http://lv2plug.in/ns/ext/buf-size#nominalBlockLength";
+const SAMPLE_RATE: &str = "http://lv2plug.in/ns/ext/parameters#sampleRate";
+const PORT_PROPS_NOT_ON_GUI: &str = "http://lv2plug.in/ns/ext/port-props#notOnGUI";
+
+/// Features instances are given, plus ones that need nothing from the host
+const SUPPORTED_FEATURES: [&str; 7] = [
+    URID_MAP,
+    URID_UNMAP,
+    OPTIONS,
+    BOUNDED_BLOCK_LENGTH,
+    "http://lv2plug.in/ns/lv2core#isLive",
+    "http://lv2plug.in/ns/lv2core#inPlaceBroken",
+    "http://lv2plug.in/ns/lv2core#hardRTCapable",
+];
+
+/// Bytes of each atom port buffer
+const ATOM_BUFFER_SIZE: usize = 8192;
+
+/// Whether data flows into or out of the plugin through a port
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Lv2PortDirection {
+    Input,
+    Output,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Lv2PortKind {
+    Audio,
+    Control,
+    Cv,
+    Atom,
+}
+
+/// A port from a plugin's metadata
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lv2Port {
+    pub index: u32,
+    pub symbol: String,
+    pub name: String,
+    pub direction: Lv2PortDirection,
+    pub kind: Lv2PortKind,
+    pub default: Option<f32>,
+    pub minimum: Option<f32>,
+    pub maximum: Option<f32>,
+    /// `lv2:portProperty` IRIs, e.g. `lv2:toggled`
+    pub properties: Vec<String>,
+    pub designation: Option<String>,
+    /// Atom port carrying MIDI events
+    pub supports_midi: bool,
+}
+
+impl Lv2Port {
+    pub fn has_property(&self, property: &str) -> bool {
+        self.properties.iter().any(|existing| existing == property)
+    }
+
+    pub fn is_toggled(&self) -> bool {
+        self.has_property(&format!("{}toggled", LV2))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.has_property(&format!("{}integer", LV2)) || self.has_property(&format!("{}enumeration", LV2))
+    }
+
+    pub fn reports_latency(&self) -> bool {
+        self.has_property(&format!("{}reportsLatency", LV2)) || self.designation.as_deref() == Some(&format!("{}latency", LV2))
+    }
+
+    /// Plain range of a control port, `lv2:minimum` to `lv2:maximum`
+    pub fn range(&self) -> (f32, f32) {
+        if self.is_toggled() {
+            return (0.0, 1.0);
+        }
+        let minimum = self.minimum.unwrap_or(0.0);
+        (minimum, self.maximum.unwrap_or(minimum.max(1.0)))
+    }
+
+    /// Initial value: the default, else the minimum, clamped to the range
+    pub fn initial_value(&self) -> f32 {
+        let (minimum, maximum) = self.range();
+        let value = self.default.or(self.minimum).unwrap_or(0.0);
+        if maximum > minimum { value.clamp(minimum, maximum) } else { value }
+    }
+}
+
+/// A plugin described by an LV2 bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lv2PluginInfo {
+    pub uri: String,
+    pub name: String,
+    pub vendor: String,
+    pub version: String,
+    pub bundle: PathBuf,
+    pub binary: PathBuf,
+    pub is_instrument: bool,
+    pub ports: Vec<Lv2Port>,
+    pub required_features: Vec<String>,
+}
+
+impl Lv2PluginInfo {
+    pub fn descriptor(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            format: PluginFormat::Lv2,
+            id: self.uri.clone(),
+            name: self.name.clone(),
+            vendor: self.vendor.clone(),
+            version: self.version.clone(),
+            path: self.bundle.clone(),
+            is_instrument: self.is_instrument,
+        }
+    }
+
+    /// Required features this host doesn't provide
+    pub fn unsupported_features(&self) -> Vec<&str> {
+        self.required_features.iter()
+            .map(String::as_str)
+            .filter(|feature| !SUPPORTED_FEATURES.contains(feature))
+            .collect()
+    }
+}
+
+/// `file://` IRI of a path
+pub fn file_iri(path: &Path) -> String {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut iri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => iri.push(byte as char),
+            _ => iri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    iri
+}
+
+/// Local path of a `file://` IRI
+pub fn iri_path(iri: &str) -> Option<PathBuf> {
+    let encoded = iri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match (byte, tail.get(..2).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+fn parse_file(graph: &mut TurtleGraph, path: &Path) -> Result<(), VjError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| VjError::FileError(format!("Failed to read {}: {}", path.display(), e)))?;
+    graph.parse(&text, &file_iri(path))
+        .map_err(|e| VjError::FileError(format!("{}: {}", path.display(), e)))
+}
+
+/// Read the plugins a bundle describes, following `rdfs:seeAlso` from its manifest
+pub fn read_lv2_bundle(bundle: &Path) -> Result<Vec<Lv2PluginInfo>, VjError> {
+    let mut graph = TurtleGraph::new();
+    parse_file(&mut graph, &bundle.join("manifest.ttl"))?;
+
+    let plugin_class = Term::Iri(format!("{}Plugin", LV2));
+    let rdf_type = format!("{}type", turtle::RDF);
+    let plugins: Vec<Term> = graph.subjects(&rdf_type, &plugin_class).cloned().collect();
+    let see_also: Vec<PathBuf> = plugins.iter()
+        .flat_map(|plugin| graph.objects(plugin, RDFS_SEE_ALSO))
+        .filter_map(|file| file.as_iri().and_then(iri_path))
+        .collect();
+    for file in see_also {
+        parse_file(&mut graph, &file)?;
+    }
+
+    plugins.iter().map(|plugin| describe_plugin(&graph, plugin, bundle)).collect()
+}
+
+fn describe_plugin(graph: &TurtleGraph, plugin: &Term, bundle: &Path) -> Result<Lv2PluginInfo, VjError> {
+    let lv2 = |name: &str| format!("{}{}", LV2, name);
+    let text = |subject: &Term, predicate: &str| graph.object(subject, predicate).and_then(Term::as_str).map(str::to_string);
+    let uri = plugin.as_iri().unwrap_or_default().to_string();
+
+    let binary = graph.object(plugin, &lv2("binary"))
+        .and_then(Term::as_iri)
+        .and_then(iri_path)
+        .ok_or_else(|| VjError::FileError(format!("LV2 plugin {} has no binary", uri)))?;
+
+    let vendor = [format!("{}maintainer", DOAP), format!("{}developer", DOAP)].iter()
+        .filter_map(|predicate| graph.object(plugin, predicate))
+        .find_map(|person| text(person, FOAF_NAME))
+        .unwrap_or_default();
+    let version = match (graph.object(plugin, &lv2("minorVersion")), graph.object(plugin, &lv2("microVersion"))) {
+        (Some(minor), Some(micro)) => format!("{}.{}", minor.as_str().unwrap_or("0"), micro.as_str().unwrap_or("0")),
+        _ => String::new(),
+    };
+
+    let rdf_type = format!("{}type", turtle::RDF);
+    let mut ports = Vec::new();
+    for port in graph.objects(plugin, &lv2("port")) {
+        let types: Vec<&str> = graph.objects(port, &rdf_type).filter_map(Term::as_iri).collect();
+        let has_type = |name: &str| types.iter().any(|iri| iri.strip_prefix(LV2) == Some(name) || iri.strip_prefix(ATOM) == Some(name));
+        let direction = if has_type("InputPort") {
+            Lv2PortDirection::Input
+        } else if has_type("OutputPort") {
+            Lv2PortDirection::Output
+        } else {
+            return Err(VjError::FileError(format!("LV2 plugin {} has a port without a direction", uri)));
+        };
+        let kind = if has_type("AudioPort") {
+            Lv2PortKind::Audio
+        } else if has_type("ControlPort") {
+            Lv2PortKind::Control
+        } else if has_type("CVPort") {
+            Lv2PortKind::Cv
+        } else if has_type("AtomPort") {
+            Lv2PortKind::Atom
+        } else {
+            return Err(VjError::FileError(format!("LV2 plugin {} has a port of unsupported type", uri)));
+        };
+        let index = graph.object(port, &lv2("index")).and_then(Term::as_f64)
+            .ok_or_else(|| VjError::FileError(format!("LV2 plugin {} has a port without an index", uri)))?;
+        let number = |name: &str| graph.object(port, &lv2(name)).and_then(Term::as_f64).map(|value| value as f32);
+        let midi = Term::Iri(MIDI_EVENT.to_string());
+        ports.push(Lv2Port {
+            index: index as u32,
+            symbol: text(port, &lv2("symbol")).unwrap_or_default(),
+            name: text(port, &lv2("name")).unwrap_or_default(),
+            direction,
+            kind,
+            default: number("default"),
+            minimum: number("minimum"),
+            maximum: number("maximum"),
+            properties: graph.objects(port, &lv2("portProperty")).filter_map(Term::as_iri).map(str::to_string).collect(),
+            designation: graph.object(port, &lv2("designation")).and_then(Term::as_iri).map(str::to_string),
+            supports_midi: graph.has(port, &format!("{}supports", ATOM), &midi),
+        });
+    }
+    ports.sort_by_key(|port| port.index);
+    if ports.iter().enumerate().any(|(position, port)| port.index as usize != position) {
+        return Err(VjError::FileError(format!("LV2 plugin {} has gaps in its port indices", uri)));
+    }
+
+    Ok(Lv2PluginInfo {
+        name: text(plugin, &format!("{}name", DOAP)).unwrap_or_else(|| uri.clone()),
+        is_instrument: graph.has(plugin, &rdf_type, &Term::Iri(lv2("InstrumentPlugin"))),
+        required_features: graph.objects(plugin, &lv2("requiredFeature")).filter_map(Term::as_iri).map(str::to_string).collect(),
+        uri,
+        vendor,
+        version,
+        bundle: bundle.to_path_buf(),
+        binary,
+        ports,
+    })
+}
+
+/// Describe the plugins in an `.lv2` bundle from its metadata alone
+pub fn scan_lv2(path: &Path) -> Result<Vec<PluginDescriptor>, VjError> {
+    Ok(read_lv2_bundle(path)?.iter().map(Lv2PluginInfo::descriptor).collect())
+}
+
+#[repr(C)]
+struct Lv2Descriptor {
+    uri: *const c_char,
+    instantiate: Option<unsafe extern "C" fn(*const Lv2Descriptor, f64, *const c_char, *const *const Lv2Feature) -> *mut c_void>,
+    connect_port: Option<unsafe extern "C" fn(*mut c_void, u32, *mut c_void)>,
+    activate: Option<unsafe extern "C" fn(*mut c_void)>,
+    run: Option<unsafe extern "C" fn(*mut c_void, u32)>,
+    deactivate: Option<unsafe extern "C" fn(*mut c_void)>,
+    cleanup: Option<unsafe extern "C" fn(*mut c_void)>,
+    extension_data: Option<unsafe extern "C" fn(*const c_char) -> *const c_void>,
+}
+
+#[repr(C)]
+struct Lv2Feature {
+    uri: *const c_char,
+    data: *mut c_void,
+}
+
+#[repr(C)]
+struct UridMapFeature {
+    handle: *mut c_void,
+    map: unsafe extern "C" fn(*mut c_void, *const c_char) -> u32,
+}
+
+#[repr(C)]
+struct UridUnmapFeature {
+    handle: *mut c_void,
+    unmap: unsafe extern "C" fn(*mut c_void, u32) -> *const c_char,
+}
+
+#[repr(C)]
+struct OptionsOption {
+    context: u32,
+    subject: u32,
+    key: u32,
+    size: u32,
+    value_type: u32,
+    value: *const c_void,
+}
+
+#[repr(C)]
+struct AtomHeader {
+    size: u32,
+    atom_type: u32,
+}
+
+/// URIDs handed out to plugins; ids start at 1
+#[derive(Default)]
+struct UridMap {
+    uris: Mutex<Vec<CString>>,
+}
+
+impl UridMap {
+    fn map(&self, uri: &CStr) -> u32 {
+        let mut uris = self.uris.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match uris.iter().position(|existing| existing.as_c_str() == uri) {
+            Some(index) => index as u32 + 1,
+            None => {
+                uris.push(uri.to_owned());
+                uris.len() as u32
+            }
+        }
+    }
+
+    fn map_str(&self, uri: &str) -> u32 {
+        CString::new(uri).map(|uri| self.map(&uri)).unwrap_or(0)
+    }
+}
+
+unsafe extern "C" fn map_uri(handle: *mut c_void, uri: *const c_char) -> u32 {
+    match ((handle as *const UridMap).as_ref(), uri.is_null()) {
+        (Some(map), false) => map.map(CStr::from_ptr(uri)),
+        _ => 0,
+    }
+}
+
+unsafe extern "C" fn unmap_urid(handle: *mut c_void, urid: u32) -> *const c_char {
+    let Some(map) = (handle as *const UridMap).as_ref() else {
+        return std::ptr::null();
+    };
+    let uris = map.uris.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    // The strings' buffers don't move when the list grows
+    uris.get((urid as usize).wrapping_sub(1)).map_or(std::ptr::null(), |uri| uri.as_ptr())
+}
+
+/// Feature data an instance was created with; lives as long as the instance
+struct Features {
+    urid_map: Box<UridMap>,
+    map: Box<UridMapFeature>,
+    unmap: Box<UridUnmapFeature>,
+    block_lengths: Box<[i32; 3]>,
+    sample_rate: Box<f32>,
+    options: Vec<OptionsOption>,
+    uris: Vec<CString>,
+    features: Vec<Lv2Feature>,
+    pointers: Vec<*const Lv2Feature>,
+}
+
+impl Features {
+    fn new(setup: &PluginSetup) -> Box<Self> {
+        let urid_map = Box::<UridMap>::default();
+        let handle = &*urid_map as *const UridMap as *mut c_void;
+        let mut features = Box::new(Self {
+            map: Box::new(UridMapFeature { handle, map: map_uri }),
+            unmap: Box::new(UridUnmapFeature { handle, unmap: unmap_urid }),
+            block_lengths: Box::new([1, setup.block_size as i32, setup.block_size as i32]),
+            sample_rate: Box::new(setup.sample_rate),
+            urid_map,
+            options: Vec::new(),
+            uris: Vec::new(),
+            features: Vec::new(),
+            pointers: Vec::new(),
+        });
+
+        let map = &features.urid_map;
+        let int = map.map_str(&format!("{}Int", ATOM));
+        let float = map.map_str(&format!("{}Float", ATOM));
+        let option = |key: &str, value_type: u32, value: *const c_void| OptionsOption {
+            context: 0,
+            subject: 0,
+            key: map.map_str(key),
+            size: 4,
+            value_type,
+            value,
+        };
+        let lengths = features.block_lengths.as_ptr();
+        let mut options = vec![
+            // SAFETY: offsets stay within the three block lengths
+            option(MIN_BLOCK_LENGTH, int, lengths as *const c_void),
+            option(MAX_BLOCK_LENGTH, int, unsafe { lengths.add(1) } as *const c_void),
+            option(NOMINAL_BLOCK_LENGTH, int, unsafe { lengths.add(2) } as *const c_void),
+            option(SAMPLE_RATE, float, &*features.sample_rate as *const f32 as *const c_void),
+        ];
+        // The option list ends with a zeroed entry
+        options.push(OptionsOption { context: 0, subject: 0, key: 0, size: 0, value_type: 0, value: std::ptr::null() });
+        features.options = options;
+
+        let data: [(&str, *mut c_void); 4] = [
+            (URID_MAP, &mut *features.map as *mut UridMapFeature as *mut c_void),
+            (URID_UNMAP, &mut *features.unmap as *mut UridUnmapFeature as *mut c_void),
+            (OPTIONS, features.options.as_mut_ptr() as *mut c_void),
+            (BOUNDED_BLOCK_LENGTH, std::ptr::null_mut()),
+        ];
+        features.uris = data.iter().map(|(uri, _)| CString::new(*uri).unwrap_or_default()).collect();
+        features.features = data.iter().zip(&features.uris)
+            .map(|((_, data), uri)| Lv2Feature { uri: uri.as_ptr(), data: *data })
+            .collect();
+        features.pointers = features.features.iter().map(|feature| feature as *const Lv2Feature).collect();
+        features.pointers.push(std::ptr::null());
+        features
+    }
+}
+
+/// Storage a port is connected to
+enum PortBuffer {
+    /// Audio and CV ports
+    Samples(Vec<f32>),
+    /// Index into the control values
+    Control(usize),
+    /// 8-byte aligned atom sequence
+    Atom(Vec<u64>),
+}
+
+/// An LV2 plugin instance, activated and running
+pub struct Lv2PluginInstance {
+    info: Lv2PluginInfo,
+    setup: PluginSetup,
+    descriptor: *const Lv2Descriptor,
+    handle: *mut c_void,
+    buffers: Vec<PortBuffer>,
+    controls: Vec<f32>,
+    audio_inputs: Vec<usize>,
+    audio_outputs: Vec<usize>,
+    midi_input: Option<usize>,
+    sequence_type: u32,
+    chunk_type: u32,
+    midi_event_type: u32,
+    _features: Box<Features>,
+    _library: Library,
+}
+
+// SAFETY: the instance is used from one thread at a time through `&mut self`
+unsafe impl Send for Lv2PluginInstance {}
+
+impl Lv2PluginInstance {
+    /// Instantiate, connect and activate a plugin
+    pub fn load(info: &Lv2PluginInfo, setup: PluginSetup) -> Result<Self, VjError> {
+        setup.validate()?;
+        let unsupported = info.unsupported_features();
+        if !unsupported.is_empty() {
+            return Err(VjError::AudioError(format!("LV2 plugin '{}' requires unsupported features: {}", info.name, unsupported.join(", "))));
+        }
+
+        // SAFETY: loading a plugin runs its initializers; that's the point of hosting it
+        let library = unsafe { Library::new(&info.binary) }
+            .map_err(|e| VjError::AudioError(format!("Failed to load {}: {}", info.binary.display(), e)))?;
+        // SAFETY: `lv2_descriptor` has this signature in every LV2 library, and the
+        // descriptors it returns live as long as the library
+        let descriptor = unsafe {
+            let entry = library.get::<unsafe extern "C" fn(u32) -> *const Lv2Descriptor>(b"lv2_descriptor\0")
+                .map_err(|_| VjError::AudioError(format!("{} is not an LV2 library", info.binary.display())))?;
+            (0..)
+                .map(|index| entry(index))
+                .take_while(|descriptor| !descriptor.is_null())
+                .find(|&descriptor| !(*descriptor).uri.is_null() && CStr::from_ptr((*descriptor).uri).to_bytes() == info.uri.as_bytes())
+                .ok_or_else(|| VjError::AudioError(format!("{} doesn't provide {}", info.binary.display(), info.uri)))?
+        };
+
+        let features = Features::new(&setup);
+        let bundle = CString::new(format!("{}/", info.bundle.to_string_lossy()))
+            .map_err(|e| VjError::FileError(e.to_string()))?;
+        // SAFETY: the features outlive the instance
+        let handle = unsafe {
+            match (*descriptor).instantiate {
+                Some(instantiate) => instantiate(descriptor, setup.sample_rate as f64, bundle.as_ptr(), features.pointers.as_ptr()),
+                None => std::ptr::null_mut(),
+            }
+        };
+        if handle.is_null() {
+            return Err(VjError::AudioError(format!("LV2 plugin '{}' failed to instantiate", info.name)));
+        }
+
+        let map = &features.urid_map;
+        let (sequence_type, chunk_type) = (map.map_str(&format!("{}Sequence", ATOM)), map.map_str(&format!("{}Chunk", ATOM)));
+        let midi_event_type = map.map_str(MIDI_EVENT);
+        let mut instance = Self {
+            info: info.clone(),
+            setup,
+            descriptor,
+            handle,
+            buffers: Vec::new(),
+            controls: Vec::new(),
+            audio_inputs: Vec::new(),
+            audio_outputs: Vec::new(),
+            midi_input: None,
+            sequence_type,
+            chunk_type,
+            midi_event_type,
+            _features: features,
+            _library: library,
+        };
+        instance.connect_ports();
+        // SAFETY: every port is connected
+        unsafe {
+            if let Some(activate) = (*descriptor).activate {
+                activate(handle);
+            }
+        }
+        Ok(instance)
+    }
+
+    fn connect_ports(&mut self) {
+        for port in &self.info.ports {
+            let input = port.direction == Lv2PortDirection::Input;
+            let buffer = match port.kind {
+                Lv2PortKind::Audio | Lv2PortKind::Cv => {
+                    if port.kind == Lv2PortKind::Audio {
+                        if input { &mut self.audio_inputs } else { &mut self.audio_outputs }.push(self.buffers.len());
+                    }
+                    PortBuffer::Samples(vec![0.0; self.setup.block_size])
+                }
+                Lv2PortKind::Control => {
+                    self.controls.push(port.initial_value());
+                    PortBuffer::Control(self.controls.len() - 1)
+                }
+                Lv2PortKind::Atom => {
+                    if input && port.supports_midi && self.midi_input.is_none() {
+                        self.midi_input = Some(self.buffers.len());
+                    }
+                    PortBuffer::Atom(vec![0; ATOM_BUFFER_SIZE / 8])
+                }
+            };
+            self.buffers.push(buffer);
+        }
+        // Buffers and control values don't move from here on
+        for index in 0..self.buffers.len() {
+            self.reset_atom_port(index);
+            let data = match &mut self.buffers[index] {
+                PortBuffer::Samples(samples) => samples.as_mut_ptr() as *mut c_void,
+                PortBuffer::Control(control) => &mut self.controls[*control] as *mut f32 as *mut c_void,
+                PortBuffer::Atom(atom) => atom.as_mut_ptr() as *mut c_void,
+            };
+            // SAFETY: buffers are allocated once and outlive the instance's runs
+            unsafe {
+                if let Some(connect_port) = (*self.descriptor).connect_port {
+                    connect_port(self.handle, index as u32, data);
+                }
+            }
+        }
+    }
+
+    /// Empty an input sequence, or give an output port its whole buffer to write into
+    fn reset_atom_port(&mut self, index: usize) {
+        let input = self.info.ports[index].direction == Lv2PortDirection::Input;
+        let (sequence_type, chunk_type) = (self.sequence_type, self.chunk_type);
+        if let PortBuffer::Atom(atom) = &mut self.buffers[index] {
+            let header = atom.as_mut_ptr() as *mut AtomHeader;
+            // SAFETY: the buffer holds at least the atom and sequence headers
+            unsafe {
+                if input {
+                    *header = AtomHeader { size: 8, atom_type: sequence_type };
+                    // Sequence body: time unit (0 for frames) and padding
+                    *atom.as_mut_ptr().add(1) = 0;
+                } else {
+                    *header = AtomHeader { size: (ATOM_BUFFER_SIZE - 8) as u32, atom_type: chunk_type };
+                }
+            }
+        }
+    }
+
+    pub fn info(&self) -> &Lv2PluginInfo {
+        &self.info
+    }
+
+    /// Current plain value of a control port
+    pub fn control(&self, port_index: u32) -> Option<f32> {
+        match self.buffers.get(port_index as usize) {
+            Some(PortBuffer::Control(control)) => Some(self.controls[*control]),
+            _ => None,
+        }
+    }
+
+    pub fn set_control(&mut self, port_index: u32, value: f32) -> Result<(), VjError> {
+        let port = self.info.ports.get(port_index as usize)
+            .filter(|port| port.kind == Lv2PortKind::Control && port.direction == Lv2PortDirection::Input)
+            .ok_or_else(|| VjError::AudioError(format!("LV2 plugin '{}' has no control input {}", self.info.name, port_index)))?;
+        let (minimum, maximum) = port.range();
+        let value = if maximum > minimum { value.clamp(minimum, maximum) } else { value };
+        let value = if port.is_integer() || port.is_toggled() { value.round() } else { value };
+        if let PortBuffer::Control(control) = self.buffers[port_index as usize] {
+            self.controls[control] = value;
+        }
+        Ok(())
+    }
+
+    /// Control ports as parameters: inputs can be set, outputs are read-only. Parameter
+    /// ids are port indices.
+    pub fn parameters(&self) -> Vec<PluginParameter> {
+        self.info.ports.iter()
+            .filter(|port| port.kind == Lv2PortKind::Control && !port.reports_latency() && !port.has_property(PORT_PROPS_NOT_ON_GUI))
+            .map(|port| {
+                let (minimum, maximum) = port.range();
+                PluginParameter {
+                    id: port.index,
+                    name: if port.name.is_empty() { port.symbol.clone() } else { port.name.clone() },
+                    units: String::new(),
+                    default_value: normalize(port.initial_value(), minimum, maximum),
+                    min: minimum as f64,
+                    max: maximum as f64,
+                    step_count: if port.is_toggled() { 1 } else if port.is_integer() { (maximum - minimum).max(0.0) as u32 } else { 0 },
+                    read_only: port.direction == Lv2PortDirection::Output,
+                }
+            })
+            .collect()
+    }
+
+    /// Normalized (0..1) value of a control port
+    pub fn get_parameter(&self, port_index: u32) -> Result<f32, VjError> {
+        let port = self.info.ports.get(port_index as usize)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown LV2 port {}", port_index)))?;
+        let value = self.control(port_index)
+            .ok_or_else(|| VjError::AudioError(format!("LV2 port '{}' isn't a control port", port.symbol)))?;
+        let (minimum, maximum) = port.range();
+        Ok(normalize(value, minimum, maximum) as f32)
+    }
+
+    /// Set a control input from a normalized (0..1) value
+    pub fn set_parameter(&mut self, port_index: u32, value: f32) -> Result<(), VjError> {
+        let port = self.info.ports.get(port_index as usize)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown LV2 port {}", port_index)))?;
+        let (minimum, maximum) = port.range();
+        self.set_control(port_index, minimum + value.clamp(0.0, 1.0) * (maximum - minimum))
+    }
+
+    pub fn accepts_midi(&self) -> bool {
+        self.midi_input.is_some()
+    }
+
+    /// Append a MIDI event to the next block's input sequence; false without room or a port
+    pub fn send_midi(&mut self, message: &[u8]) -> bool {
+        let (Some(index), false) = (self.midi_input, message.is_empty()) else {
+            return false;
+        };
+        let midi_event_type = self.midi_event_type;
+        let PortBuffer::Atom(atom) = &mut self.buffers[index] else {
+            return false;
+        };
+        // SAFETY: the header was written by `reset_atom_port` and the event is bounds-checked
+        unsafe {
+            let bytes = atom.as_mut_ptr() as *mut u8;
+            let header = &mut *(bytes as *mut AtomHeader);
+            // Event: 8-byte frame time, atom header, then the message padded to 8 bytes
+            let offset = 8 + header.size as usize;
+            let event_size = 16 + message.len().div_ceil(8) * 8;
+            if offset + event_size > ATOM_BUFFER_SIZE {
+                return false;
+            }
+            let event = bytes.add(offset);
+            (event as *mut i64).write(0);
+            (event.add(8) as *mut AtomHeader).write(AtomHeader { size: message.len() as u32, atom_type: midi_event_type });
+            std::ptr::copy_nonoverlapping(message.as_ptr(), event.add(16), message.len());
+            header.size += event_size as u32;
+        }
+        true
+    }
+
+    /// Frames of delay from the port designated to report latency
+    pub fn latency_samples(&self) -> usize {
+        self.info.ports.iter()
+            .find(|port| port.kind == Lv2PortKind::Control && port.direction == Lv2PortDirection::Output && port.reports_latency())
+            .and_then(|port| self.control(port.index))
+            .map_or(0, |latency| latency.max(0.0) as usize)
+    }
+
+    /// Control input values by port symbol
+    pub fn save_state(&self) -> HashMap<String, f32> {
+        self.info.ports.iter()
+            .filter(|port| port.kind == Lv2PortKind::Control && port.direction == Lv2PortDirection::Input)
+            .filter_map(|port| Some((port.symbol.clone(), self.control(port.index)?)))
+            .collect()
+    }
+
+    /// Apply saved control values; symbols the plugin no longer has are skipped
+    pub fn load_state(&mut self, controls: &HashMap<String, f32>) -> Result<(), VjError> {
+        let ports: Vec<(u32, f32)> = self.info.ports.iter()
+            .filter_map(|port| Some((port.index, *controls.get(&port.symbol)?)))
+            .collect();
+        for (index, value) in ports {
+            self.set_control(index, value)?;
+        }
+        Ok(())
+    }
+
+    /// Process interleaved audio with the setup's channel count. Missing input is silence;
+    /// output replaces what was in `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> Result<(), VjError> {
+        let channels = self.setup.num_channels.max(1);
+        let frames = output.len() / channels;
+        let mut start = 0;
+        while start < frames {
+            let count = (frames - start).min(self.setup.block_size);
+
+            for (channel, &index) in self.audio_inputs.iter().enumerate() {
+                if let PortBuffer::Samples(samples) = &mut self.buffers[index] {
+                    for (frame, sample) in samples[..count].iter_mut().enumerate() {
+                        *sample = input.get((start + frame) * channels + channel % channels).copied().unwrap_or(0.0);
+                    }
+                }
+            }
+            for &index in &self.audio_outputs {
+                if let PortBuffer::Samples(samples) = &mut self.buffers[index] {
+                    samples[..count].fill(0.0);
+                }
+            }
+
+            // SAFETY: every port is connected to a buffer of at least `block_size` frames
+            unsafe {
+                if let Some(run) = (*self.descriptor).run {
+                    run(self.handle, count as u32);
+                }
+            }
+            for index in 0..self.buffers.len() {
+                self.reset_atom_port(index);
+            }
+
+            for frame in 0..count {
+                for channel in 0..channels {
+                    let sample = match self.audio_outputs.len() {
+                        0 => 0.0,
+                        outputs => match &self.buffers[self.audio_outputs[channel % outputs]] {
+                            PortBuffer::Samples(samples) => samples[frame],
+                            _ => 0.0,
+                        },
+                    };
+                    output[(start + frame) * channels + channel] = sample;
+                }
+            }
+            start += count;
+        }
+        output[frames * channels..].fill(0.0);
+        Ok(())
+    }
+}
+
+impl Drop for Lv2PluginInstance {
+    fn drop(&mut self) {
+        // SAFETY: the handle is live; cleanup is the last call it gets
+        unsafe {
+            if let Some(deactivate) = (*self.descriptor).deactivate {
+                deactivate(self.handle);
+            }
+            if let Some(cleanup) = (*self.descriptor).cleanup {
+                cleanup(self.handle);
+            }
+        }
+    }
+}
+
+fn normalize(value: f32, minimum: f32, maximum: f32) -> f64 {
+    if maximum > minimum {
+        ((value - minimum) / (maximum - minimum)).clamp(0.0, 1.0) as f64
+    } else {
+        0.0
+    }
+}
+
+/// Hosts LV2 plugins
+pub struct Lv2PluginHost {
+    setup: PluginSetup,
+    instances: HashMap<String, Lv2PluginInstance>,
+}
+
+impl Lv2PluginHost {
+    pub fn new() -> Self {
+        Self { setup: PluginSetup::default(), instances: HashMap::new() }
+    }
+
+    pub fn instance(&self, instance_id: &str) -> Option<&Lv2PluginInstance> {
+        self.instances.get(instance_id)
+    }
+
+    pub fn instance_mut(&mut self, instance_id: &str) -> Option<&mut Lv2PluginInstance> {
+        self.instances.get_mut(instance_id)
+    }
+
+    fn require(&self, instance_id: &str) -> Result<&Lv2PluginInstance, VjError> {
+        self.instances.get(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Lv2, instance_id))
+    }
+
+    fn require_mut(&mut self, instance_id: &str) -> Result<&mut Lv2PluginInstance, VjError> {
+        self.instances.get_mut(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Lv2, instance_id))
+    }
+}
+
+impl Default for Lv2PluginHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PluginHost for Lv2PluginHost {
+    fn format(&self) -> PluginFormat {
+        PluginFormat::Lv2
+    }
+
+    fn set_setup(&mut self, setup: PluginSetup) {
+        self.setup = setup;
+    }
+
+    fn load(&mut self, instance_id: &str, descriptor: &PluginDescriptor) -> Result<(), VjError> {
+        let info = read_lv2_bundle(&descriptor.path)?.into_iter()
+            .find(|info| info.uri == descriptor.id)
+            .ok_or_else(|| VjError::AudioError(format!("LV2 bundle {} doesn't describe {}", descriptor.path.display(), descriptor.id)))?;
+        let instance = Lv2PluginInstance::load(&info, self.setup)?;
+        self.instances.insert(instance_id.to_string(), instance);
+        Ok(())
+    }
+
+    fn unload(&mut self, instance_id: &str) -> bool {
+        self.instances.remove(instance_id).is_some()
+    }
+
+    fn instance_ids(&self) -> Vec<String> {
+        self.instances.keys().cloned().collect()
+    }
+
+    fn parameters(&self, instance_id: &str) -> Result<Vec<PluginParameter>, VjError> {
+        Ok(self.require(instance_id)?.parameters())
+    }
+
+    fn set_parameter(&mut self, instance_id: &str, param_id: u32, value: f32) -> Result<(), VjError> {
+        self.require_mut(instance_id)?.set_parameter(param_id, value)
+    }
+
+    fn get_parameter(&self, instance_id: &str, param_id: u32) -> Result<f32, VjError> {
+        self.require(instance_id)?.get_parameter(param_id)
+    }
+
+    fn send_midi(&mut self, instance_id: &str, message: &[u8]) -> Result<bool, VjError> {
+        Ok(self.require_mut(instance_id)?.send_midi(message))
+    }
+
+    fn process(&mut self, instance_id: &str, input: &[f32], output: &mut [f32]) -> Result<(), VjError> {
+        self.require_mut(instance_id)?.process(input, output)
+    }
+
+    fn latency(&self, instance_id: &str) -> Result<usize, VjError> {
+        Ok(self.require(instance_id)?.latency_samples())
+    }
+
+    fn instance_state(&mut self, instance_id: &str) -> Result<Value, VjError> {
+        Ok(serde_json::json!({ "controls": self.require(instance_id)?.save_state() }))
+    }
+
+    fn restore_instance_state(&mut self, instance_id: &str, state: &Value) -> Result<(), VjError> {
+        let controls: HashMap<String, f32> = match state.get("controls") {
+            Some(controls) => serde_json::from_value(controls.clone())
+                .map_err(|e| VjError::ConfigError(format!("Invalid LV2 state: {}", e)))?,
+            None => return Ok(()),
+        };
+        self.require_mut(instance_id)?.load_state(&controls)
+    }
+}
@@ -0,0 +1,509 @@
+//! Just enough Turtle to read LV2 bundle metadata
+//!
+//! Parses prefixes, base IRIs, predicate and object lists, blank node property lists,
+//! collections and the literal forms into a flat list of triples.
+
+use crate::core::VjError;
+
+pub const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// Subject or object of a triple
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Iri(String),
+    Blank(usize),
+    Literal { value: String, datatype: Option<String> },
+}
+
+impl Term {
+    pub fn as_iri(&self) -> Option<&str> {
+        match self {
+            Term::Iri(iri) => Some(iri),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Term::Literal { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_str()?.trim().parse().ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Triple {
+    pub subject: Term,
+    pub predicate: String,
+    pub object: Term,
+}
+
+/// Triples from one or more Turtle documents
+#[derive(Debug, Clone, Default)]
+pub struct TurtleGraph {
+    triples: Vec<Triple>,
+    next_blank: usize,
+}
+
+impl TurtleGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a document's triples; relative IRIs resolve against `base`
+    pub fn parse(&mut self, text: &str, base: &str) -> Result<(), VjError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { graph: self, tokens, position: 0, base: base.to_string(), prefixes: Vec::new(), blanks: Vec::new() };
+        parser.document()
+    }
+
+    pub fn triples(&self) -> &[Triple] {
+        &self.triples
+    }
+
+    pub fn objects<'a>(&'a self, subject: &'a Term, predicate: &'a str) -> impl Iterator<Item = &'a Term> + 'a {
+        self.triples.iter()
+            .filter(move |triple| &triple.subject == subject && triple.predicate == predicate)
+            .map(|triple| &triple.object)
+    }
+
+    pub fn object<'a>(&'a self, subject: &'a Term, predicate: &'a str) -> Option<&'a Term> {
+        self.objects(subject, predicate).next()
+    }
+
+    pub fn subjects<'a>(&'a self, predicate: &'a str, object: &'a Term) -> impl Iterator<Item = &'a Term> + 'a {
+        self.triples.iter()
+            .filter(move |triple| triple.predicate == predicate && &triple.object == object)
+            .map(|triple| &triple.subject)
+    }
+
+    pub fn has(&self, subject: &Term, predicate: &str, object: &Term) -> bool {
+        self.objects(subject, predicate).any(|candidate| candidate == object)
+    }
+
+    fn blank(&mut self) -> Term {
+        self.next_blank += 1;
+        Term::Blank(self.next_blank)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Iri(String),
+    PrefixedName(String, String),
+    Blank(String),
+    String(String),
+    LanguageTag,
+    DatatypeMark,
+    Number(String, &'static str),
+    Boolean(bool),
+    A,
+    PrefixDirective { sparql: bool },
+    BaseDirective { sparql: bool },
+    Punctuation(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, VjError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let error = |message: &str, at: usize| {
+        let line = chars[..at.min(chars.len())].iter().filter(|&&c| c == '\n').count() + 1;
+        VjError::FileError(format!("Turtle line {}: {}", line, message))
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '<' => {
+                let end = chars[i..].iter().position(|&c| c == '>').ok_or_else(|| error("unterminated IRI", i))?;
+                tokens.push(Token::Iri(chars[i + 1..i + end].iter().collect()));
+                i += end + 1;
+            }
+            '"' | '\'' => {
+                let long = chars.get(i + 1) == Some(&c) && chars.get(i + 2) == Some(&c);
+                i += if long { 3 } else { 1 };
+                let mut value = String::new();
+                loop {
+                    let Some(&next) = chars.get(i) else {
+                        return Err(error("unterminated string", i));
+                    };
+                    if next == '\\' {
+                        let escaped = chars.get(i + 1).copied().ok_or_else(|| error("unterminated escape", i))?;
+                        i += 2;
+                        match escaped {
+                            't' => value.push('\t'),
+                            'n' => value.push('\n'),
+                            'r' => value.push('\r'),
+                            'b' => value.push('\u{8}'),
+                            'f' => value.push('\u{c}'),
+                            'u' | 'U' => {
+                                let digits = if escaped == 'u' { 4 } else { 8 };
+                                let hex: String = chars.get(i..i + digits).ok_or_else(|| error("short unicode escape", i))?.iter().collect();
+                                let code = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                                    .ok_or_else(|| error("invalid unicode escape", i))?;
+                                value.push(code);
+                                i += digits;
+                            }
+                            other => value.push(other),
+                        }
+                    } else if next == c && (!long || (chars.get(i + 1) == Some(&c) && chars.get(i + 2) == Some(&c))) {
+                        i += if long { 3 } else { 1 };
+                        break;
+                    } else if next == '\n' && !long {
+                        return Err(error("newline in string", i));
+                    } else {
+                        value.push(next);
+                        i += 1;
+                    }
+                }
+                tokens.push(Token::String(value));
+            }
+            '^' if chars.get(i + 1) == Some(&'^') => {
+                tokens.push(Token::DatatypeMark);
+                i += 2;
+            }
+            '@' => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '-') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.as_str() {
+                    "prefix" => Token::PrefixDirective { sparql: false },
+                    "base" => Token::BaseDirective { sparql: false },
+                    _ => Token::LanguageTag,
+                });
+            }
+            '.' | ';' | ',' | '[' | ']' | '(' | ')' if !(c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) => {
+                tokens.push(Token::Punctuation(c));
+                i += 1;
+            }
+            c if c.is_ascii_digit() || ((c == '+' || c == '-' || c == '.') && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit() || *n == '.')) => {
+                let start = i;
+                let mut kind = "integer";
+                i += 1;
+                while i < chars.len() {
+                    match chars[i] {
+                        d if d.is_ascii_digit() => i += 1,
+                        '.' if kind == "integer" && chars.get(i + 1).is_some_and(char::is_ascii_digit) => {
+                            kind = "decimal";
+                            i += 1;
+                        }
+                        'e' | 'E' => {
+                            kind = "double";
+                            i += 1;
+                            if matches!(chars.get(i), Some('+') | Some('-')) {
+                                i += 1;
+                            }
+                        }
+                        _ => break,
+                    }
+                }
+                if chars[start] == '.' {
+                    kind = "decimal";
+                }
+                tokens.push(Token::Number(chars[start..i].iter().collect(), kind));
+            }
+            '_' if chars.get(i + 1) == Some(&':') => {
+                let start = i + 2;
+                i = start;
+                while i < chars.len() && is_name_char(chars[i]) {
+                    i += 1;
+                }
+                while i > start && chars[i - 1] == '.' {
+                    i -= 1;
+                }
+                tokens.push(Token::Blank(chars[start..i].iter().collect()));
+            }
+            c if c.is_alphabetic() || c == ':' || c == '_' => {
+                let start = i;
+                while i < chars.len() && (is_name_char(chars[i]) || chars[i] == ':' || chars[i] == '%' || chars[i] == '\\') {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                // A trailing dot ends the statement
+                while i > start && chars[i - 1] == '.' {
+                    i -= 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.split_once(':') {
+                    Some((prefix, local)) => Token::PrefixedName(prefix.to_string(), local.replace('\\', "")),
+                    None => match word.as_str() {
+                        "a" => Token::A,
+                        "true" => Token::Boolean(true),
+                        "false" => Token::Boolean(false),
+                        _ if word.eq_ignore_ascii_case("prefix") => Token::PrefixDirective { sparql: true },
+                        _ if word.eq_ignore_ascii_case("base") => Token::BaseDirective { sparql: true },
+                        _ => return Err(error(&format!("unexpected '{}'", word), start)),
+                    },
+                });
+            }
+            other => return Err(error(&format!("unexpected '{}'", other), i)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// Resolve a possibly relative IRI reference against a base IRI
+pub fn resolve_iri(base: &str, reference: &str) -> String {
+    if reference.contains(':') && reference.split(':').next().is_some_and(|scheme| scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')) {
+        return reference.to_string();
+    }
+    let base = base.split('#').next().unwrap_or(base);
+    if reference.is_empty() {
+        return base.to_string();
+    }
+    if reference.starts_with('#') {
+        return format!("{}{}", base, reference);
+    }
+    let authority_end = base.find("://").map(|scheme| scheme + 3 + base[scheme + 3..].find('/').unwrap_or(base.len() - scheme - 3));
+    if let Some(path) = reference.strip_prefix('/') {
+        let root = authority_end.map(|end| &base[..end]).unwrap_or("");
+        return format!("{}/{}", root, path);
+    }
+    let root_end = authority_end.unwrap_or(0);
+    let directory = match base.rfind('/') {
+        Some(slash) if slash >= root_end => &base[root_end..slash],
+        _ => "",
+    };
+    let mut segments: Vec<&str> = directory.split('/').skip(1).collect();
+    let mut trailing = false;
+    for segment in reference.split('/') {
+        trailing = matches!(segment, "." | "..");
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    let path = segments.join("/");
+    format!("{}/{}{}", &base[..root_end], path, if trailing && !path.is_empty() { "/" } else { "" })
+}
+
+struct Parser<'g> {
+    graph: &'g mut TurtleGraph,
+    tokens: Vec<Token>,
+    position: usize,
+    base: String,
+    prefixes: Vec<(String, String)>,
+    /// Labeled blank nodes of this document
+    blanks: Vec<(String, Term)>,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> VjError {
+        VjError::FileError(format!("Turtle: {} (token {})", message, self.position))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, punctuation: char) -> Result<(), VjError> {
+        match self.next() {
+            Some(Token::Punctuation(c)) if c == punctuation => Ok(()),
+            _ => Err(self.error(&format!("expected '{}'", punctuation))),
+        }
+    }
+
+    fn document(&mut self) -> Result<(), VjError> {
+        while let Some(token) = self.peek().cloned() {
+            match token {
+                Token::PrefixDirective { sparql } => {
+                    self.position += 1;
+                    let Some(Token::PrefixedName(prefix, local)) = self.next() else {
+                        return Err(self.error("expected prefix name"));
+                    };
+                    if !local.is_empty() {
+                        return Err(self.error("prefix name has a local part"));
+                    }
+                    let Some(Token::Iri(iri)) = self.next() else {
+                        return Err(self.error("expected prefix IRI"));
+                    };
+                    let iri = resolve_iri(&self.base, &iri);
+                    self.prefixes.retain(|(existing, _)| existing != &prefix);
+                    self.prefixes.push((prefix, iri));
+                    if !sparql {
+                        self.expect('.')?;
+                    }
+                }
+                Token::BaseDirective { sparql } => {
+                    self.position += 1;
+                    let Some(Token::Iri(iri)) = self.next() else {
+                        return Err(self.error("expected base IRI"));
+                    };
+                    self.base = resolve_iri(&self.base, &iri);
+                    if !sparql {
+                        self.expect('.')?;
+                    }
+                }
+                _ => {
+                    self.triples()?;
+                    self.expect('.')?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn triples(&mut self) -> Result<(), VjError> {
+        if self.peek() == Some(&Token::Punctuation('[')) {
+            let subject = self.blank_property_list()?;
+            if self.peek() != Some(&Token::Punctuation('.')) {
+                self.predicate_object_list(&subject)?;
+            }
+            return Ok(());
+        }
+        let subject = self.subject()?;
+        self.predicate_object_list(&subject)
+    }
+
+    fn subject(&mut self) -> Result<Term, VjError> {
+        match self.peek() {
+            Some(Token::Punctuation('(')) => self.collection(),
+            _ => match self.next() {
+                Some(Token::Iri(iri)) => Ok(Term::Iri(resolve_iri(&self.base, &iri))),
+                Some(Token::PrefixedName(prefix, local)) => Ok(Term::Iri(self.expand(&prefix, &local)?)),
+                Some(Token::Blank(label)) => Ok(self.labeled_blank(label)),
+                _ => Err(self.error("expected subject")),
+            },
+        }
+    }
+
+    fn predicate_object_list(&mut self, subject: &Term) -> Result<(), VjError> {
+        loop {
+            let predicate = match self.next() {
+                Some(Token::A) => format!("{}type", RDF),
+                Some(Token::Iri(iri)) => resolve_iri(&self.base, &iri),
+                Some(Token::PrefixedName(prefix, local)) => self.expand(&prefix, &local)?,
+                _ => return Err(self.error("expected predicate")),
+            };
+            loop {
+                let object = self.object()?;
+                self.graph.triples.push(Triple { subject: subject.clone(), predicate: predicate.clone(), object });
+                if self.peek() != Some(&Token::Punctuation(',')) {
+                    break;
+                }
+                self.position += 1;
+            }
+            if self.peek() != Some(&Token::Punctuation(';')) {
+                return Ok(());
+            }
+            while self.peek() == Some(&Token::Punctuation(';')) {
+                self.position += 1;
+            }
+            if matches!(self.peek(), Some(Token::Punctuation('.')) | Some(Token::Punctuation(']')) | None) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Term, VjError> {
+        match self.peek().cloned() {
+            Some(Token::Punctuation('[')) => self.blank_property_list(),
+            Some(Token::Punctuation('(')) => self.collection(),
+            Some(Token::String(value)) => {
+                self.position += 1;
+                let datatype = match self.peek() {
+                    Some(Token::LanguageTag) => {
+                        self.position += 1;
+                        None
+                    }
+                    Some(Token::DatatypeMark) => {
+                        self.position += 1;
+                        match self.next() {
+                            Some(Token::Iri(iri)) => Some(resolve_iri(&self.base, &iri)),
+                            Some(Token::PrefixedName(prefix, local)) => Some(self.expand(&prefix, &local)?),
+                            _ => return Err(self.error("expected datatype")),
+                        }
+                    }
+                    _ => None,
+                };
+                Ok(Term::Literal { value, datatype })
+            }
+            Some(Token::Number(value, kind)) => {
+                self.position += 1;
+                Ok(Term::Literal { value, datatype: Some(format!("{}{}", XSD, kind)) })
+            }
+            Some(Token::Boolean(value)) => {
+                self.position += 1;
+                Ok(Term::Literal { value: value.to_string(), datatype: Some(format!("{}boolean", XSD)) })
+            }
+            _ => self.subject(),
+        }
+    }
+
+    fn blank_property_list(&mut self) -> Result<Term, VjError> {
+        self.expect('[')?;
+        let node = self.graph.blank();
+        if self.peek() != Some(&Token::Punctuation(']')) {
+            self.predicate_object_list(&node)?;
+        }
+        self.expect(']')?;
+        Ok(node)
+    }
+
+    fn collection(&mut self) -> Result<Term, VjError> {
+        self.expect('(')?;
+        let mut items = Vec::new();
+        while self.peek() != Some(&Token::Punctuation(')')) {
+            if self.peek().is_none() {
+                return Err(self.error("unterminated collection"));
+            }
+            items.push(self.object()?);
+        }
+        self.position += 1;
+
+        let mut list = Term::Iri(format!("{}nil", RDF));
+        for item in items.into_iter().rev() {
+            let node = self.graph.blank();
+            self.graph.triples.push(Triple { subject: node.clone(), predicate: format!("{}first", RDF), object: item });
+            self.graph.triples.push(Triple { subject: node.clone(), predicate: format!("{}rest", RDF), object: list });
+            list = node;
+        }
+        Ok(list)
+    }
+
+    fn expand(&self, prefix: &str, local: &str) -> Result<String, VjError> {
+        self.prefixes.iter().rev()
+            .find(|(name, _)| name == prefix)
+            .map(|(_, iri)| format!("{}{}", iri, local))
+            .ok_or_else(|| self.error(&format!("undefined prefix '{}:'", prefix)))
+    }
+
+    fn labeled_blank(&mut self, label: String) -> Term {
+        if let Some((_, term)) = self.blanks.iter().find(|(existing, _)| existing == &label) {
+            return term.clone();
+        }
+        let term = self.graph.blank();
+        self.blanks.push((label, term.clone()));
+        term
+    }
+}
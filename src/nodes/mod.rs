@@ -8,7 +8,12 @@ pub mod utilities;
 pub mod motion_capture;
 pub mod fractal_shaders;
 pub mod vst3_plugins;
+pub mod plugin_host;
+pub mod clap_plugins;
+pub mod lv2_plugins;
+pub mod plugin_sandbox;
 pub mod stream_diffusion;
+mod tests;
 // pub mod ui; // Temporarily disabled due to egui compatibility issues
 
 pub use generators::*;
@@ -18,6 +23,10 @@ pub use utilities::*;
 pub use motion_capture::*;
 pub use fractal_shaders::*;
 pub use vst3_plugins::*;
+pub use plugin_host::*;
+pub use clap_plugins::*;
+pub use lv2_plugins::*;
+pub use plugin_sandbox::*;
 pub use stream_diffusion::*;
 // pub use ui::*; // Temporarily disabled due to egui compatibility issues
 
@@ -51,7 +60,9 @@ impl Plugin for NodesPlugin {
                 OutputNodesPlugin,
                 UtilityNodesPlugin,
                 // NodeGraphUIPlugin, // Temporarily disabled due to egui compatibility issues
-            ));
+            ))
+            .add_systems(Startup, setup_plugin_catalog)
+            .add_systems(Update, update_vst3_nodes);
     }
 }
 
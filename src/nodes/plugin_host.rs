@@ -0,0 +1,578 @@
+//! Format-independent plugin hosting
+//!
+//! `PluginHost` is implemented by the VST3, CLAP and LV2 backends so nodes can run any
+//! installed plugin the same way. `PluginScanner` walks the standard plugin folders and
+//! keeps what it finds in a cache file, rescanning only bundles that changed since the
+//! last run. Cached plugins are registered in `NodeRegistry` as `<format>:<id>` node types.
+//! `PluginNode::sandboxed` runs a plugin out of process through `SandboxedPluginHost`.
+
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::core::{
+    AudioBufferData, AudioEventType, DataType, InputPort, Node, NodeId, NodePort, NodeRegistry,
+    NodeTypeDefinition, OutputPort, PortType, VjError, VjEvent,
+};
+use super::clap_plugins::{scan_clap, ClapPluginHost};
+use super::lv2_plugins::{scan_lv2, Lv2PluginHost};
+use super::plugin_sandbox::{SandboxConfig, SandboxedPluginHost};
+use super::vst3_plugins::{scan_vst3, Vst3PluginProcessor};
+
+/// Version of the cache layout; caches with another version are rescanned
+const CACHE_VERSION: u32 = 1;
+
+/// Directory levels searched below each search path
+const MAX_SCAN_DEPTH: usize = 6;
+
+/// Category of plugin node types in the registry
+pub const PLUGIN_NODE_CATEGORY: &str = "Audio Plugin";
+
+/// Plugin binary formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginFormat {
+    Vst3,
+    Clap,
+    Lv2,
+}
+
+impl PluginFormat {
+    pub const ALL: [PluginFormat; 3] = [PluginFormat::Vst3, PluginFormat::Clap, PluginFormat::Lv2];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PluginFormat::Vst3 => "vst3",
+            PluginFormat::Clap => "clap",
+            PluginFormat::Lv2 => "lv2",
+        }
+    }
+
+    /// Format of a bundle or library by its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::ALL.into_iter().find(|format| format.as_str() == extension)
+    }
+
+    /// Variable listing extra search paths, separated like `PATH`
+    fn path_variable(self) -> &'static str {
+        match self {
+            PluginFormat::Vst3 => "VST3_PATH",
+            PluginFormat::Clap => "CLAP_PATH",
+            PluginFormat::Lv2 => "LV2_PATH",
+        }
+    }
+
+    /// Folders this format installs to, then the ones in its path variable
+    pub fn default_search_paths(self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = std::env::var_os(self.path_variable())
+            .map(|value| std::env::split_paths(&value).collect())
+            .unwrap_or_default();
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let (user, system): (&str, &[&str]) = match self {
+            PluginFormat::Vst3 => (".vst3", &["/usr/lib/vst3", "/usr/local/lib/vst3"]),
+            PluginFormat::Clap => (".clap", &["/usr/lib/clap", "/usr/local/lib/clap"]),
+            PluginFormat::Lv2 => (".lv2", &["/usr/lib/lv2", "/usr/local/lib/lv2"]),
+        };
+        paths.extend(home.map(|home| home.join(user)));
+        paths.extend(system.iter().map(PathBuf::from));
+        paths
+    }
+
+    /// Describe the plugins in a bundle or library; this loads the plugin binary
+    pub fn scan(self, path: &Path) -> Result<Vec<PluginDescriptor>, VjError> {
+        match self {
+            PluginFormat::Vst3 => scan_vst3(path),
+            PluginFormat::Clap => scan_clap(path),
+            PluginFormat::Lv2 => scan_lv2(path),
+        }
+    }
+
+    /// A host for plugins of this format
+    pub fn host(self) -> Box<dyn PluginHost> {
+        match self {
+            PluginFormat::Vst3 => Box::new(Vst3PluginProcessor::new()),
+            PluginFormat::Clap => Box::new(ClapPluginHost::new()),
+            PluginFormat::Lv2 => Box::new(Lv2PluginHost::new()),
+        }
+    }
+}
+
+impl std::fmt::Display for PluginFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PluginFormat::Vst3 => "VST3",
+            PluginFormat::Clap => "CLAP",
+            PluginFormat::Lv2 => "LV2",
+        })
+    }
+}
+
+/// An installed plugin, as found by the scanner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginDescriptor {
+    pub format: PluginFormat,
+    /// VST3 class id in hex, CLAP plugin id or LV2 plugin URI
+    pub id: String,
+    pub name: String,
+    pub vendor: String,
+    pub version: String,
+    /// Bundle or library the plugin is loaded from
+    pub path: PathBuf,
+    pub is_instrument: bool,
+}
+
+impl PluginDescriptor {
+    /// Registry name, e.g. `clap:org.example.gain`
+    pub fn node_type(&self) -> String {
+        format!("{}:{}", self.format.as_str(), self.id)
+    }
+
+    pub fn node_type_definition(&self) -> NodeTypeDefinition {
+        let port = |name: &str, port_type: PortType, data_type: DataType| NodePort {
+            name: name.to_string(),
+            port_type,
+            data_type,
+            required: false,
+        };
+        let mut input_ports = vec![port("midi", PortType::Input, DataType::Array)];
+        if !self.is_instrument {
+            input_ports.insert(0, port("audio", PortType::Input, DataType::AudioBuffer));
+        }
+        let vendor = if self.vendor.is_empty() { String::new() } else { format!(" by {}", self.vendor) };
+        NodeTypeDefinition {
+            name: self.node_type(),
+            category: PLUGIN_NODE_CATEGORY.to_string(),
+            description: format!("{}{} ({})", self.name, vendor, self.format),
+            input_ports,
+            output_ports: vec![port("audio", PortType::Output, DataType::AudioBuffer)],
+            default_size: Vec2::new(200.0, 100.0),
+        }
+    }
+}
+
+/// A plugin parameter in a format-independent form. Values passed to and from
+/// `PluginHost` are normalized 0..1; `min` and `max` are the plain range.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginParameter {
+    pub id: u32,
+    pub name: String,
+    pub units: String,
+    pub default_value: f64,
+    pub min: f64,
+    pub max: f64,
+    /// 0 for continuous parameters, otherwise the number of steps
+    pub step_count: u32,
+    pub read_only: bool,
+}
+
+/// Sample rate, block size and channel count plugins run with
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PluginSetup {
+    pub sample_rate: f32,
+    pub block_size: usize,
+    pub num_channels: usize,
+}
+
+impl Default for PluginSetup {
+    fn default() -> Self {
+        Self { sample_rate: 44100.0, block_size: 512, num_channels: 2 }
+    }
+}
+
+impl PluginSetup {
+    pub fn validate(&self) -> Result<(), VjError> {
+        if self.block_size == 0 || self.sample_rate <= 0.0 || self.num_channels == 0 {
+            return Err(VjError::ConfigError(format!(
+                "Invalid plugin processing setup: {} Hz, {} frames, {} channels",
+                self.sample_rate, self.block_size, self.num_channels
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Hosts plugin instances of one format under caller-chosen ids
+pub trait PluginHost: Send {
+    fn format(&self) -> PluginFormat;
+
+    /// Processing setup for instances loaded afterwards
+    fn set_setup(&mut self, setup: PluginSetup);
+
+    /// Instantiate and activate a plugin, replacing any instance with the same id
+    fn load(&mut self, instance_id: &str, descriptor: &PluginDescriptor) -> Result<(), VjError>;
+
+    fn unload(&mut self, instance_id: &str) -> bool;
+
+    fn instance_ids(&self) -> Vec<String>;
+
+    fn parameters(&self, instance_id: &str) -> Result<Vec<PluginParameter>, VjError>;
+
+    /// Set a normalized (0..1) parameter for the next block
+    fn set_parameter(&mut self, instance_id: &str, param_id: u32, value: f32) -> Result<(), VjError>;
+
+    fn get_parameter(&self, instance_id: &str, param_id: u32) -> Result<f32, VjError>;
+
+    /// Queue a raw MIDI message for the next block; false if the plugin takes no notes
+    fn send_midi(&mut self, instance_id: &str, message: &[u8]) -> Result<bool, VjError>;
+
+    /// Process interleaved audio with the setup's channel count. Missing input is
+    /// silence; output replaces what was in `output`.
+    fn process(&mut self, instance_id: &str, input: &[f32], output: &mut [f32]) -> Result<(), VjError>;
+
+    /// Processing delay the plugin reports, in frames
+    fn latency(&self, instance_id: &str) -> Result<usize, VjError>;
+
+    /// The instance's state as JSON, for saved node parameters
+    fn instance_state(&mut self, instance_id: &str) -> Result<Value, VjError>;
+
+    fn restore_instance_state(&mut self, instance_id: &str, state: &Value) -> Result<(), VjError>;
+
+    /// Events since the last call, such as sandboxed plugins crashing and restarting
+    fn drain_events(&mut self) -> Vec<AudioEventType> {
+        Vec::new()
+    }
+}
+
+/// Error for an instance id a host doesn't know
+pub(crate) fn unknown_instance(format: PluginFormat, instance_id: &str) -> VjError {
+    VjError::AudioError(format!("{} plugin '{}' not found", format, instance_id))
+}
+
+/// One scanned bundle or library in the cache
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginCacheEntry {
+    pub path: PathBuf,
+    /// Newest modification time in the bundle, in seconds since the epoch
+    pub modified: u64,
+    pub plugins: Vec<PluginDescriptor>,
+    /// Why scanning failed; failed bundles are retried once they change
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Scan results kept between runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PluginCache {
+    pub version: u32,
+    pub entries: Vec<PluginCacheEntry>,
+}
+
+impl PluginCache {
+    /// Read a cache file; a missing, unreadable or outdated cache is empty
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path).ok()
+            .and_then(|text| serde_json::from_str::<Self>(&text).ok())
+            .filter(|cache| cache.version == CACHE_VERSION)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), VjError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| VjError::FileError(format!("Failed to create {}: {}", parent.display(), e)))?;
+        }
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|e| VjError::FileError(e.to_string()))?;
+        std::fs::write(path, text)
+            .map_err(|e| VjError::FileError(format!("Failed to write plugin cache {}: {}", path.display(), e)))
+    }
+}
+
+/// Finds installed plugins and caches what it found
+#[derive(Debug, Clone)]
+pub struct PluginScanner {
+    pub search_paths: Vec<PathBuf>,
+    pub cache_path: PathBuf,
+}
+
+impl Default for PluginScanner {
+    fn default() -> Self {
+        let search_paths = PluginFormat::ALL.into_iter().flat_map(PluginFormat::default_search_paths).collect();
+        Self { search_paths, cache_path: Self::default_cache_path() }
+    }
+}
+
+impl PluginScanner {
+    pub fn new(search_paths: Vec<PathBuf>, cache_path: PathBuf) -> Self {
+        Self { search_paths, cache_path }
+    }
+
+    /// `$XDG_CACHE_HOME/nuwe/plugins.json`, falling back to `~/.cache`
+    pub fn default_cache_path() -> PathBuf {
+        let cache = std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir);
+        cache.join("nuwe").join("plugins.json")
+    }
+
+    /// Plugin bundles and libraries below the search paths
+    pub fn find_candidates(&self) -> Vec<PathBuf> {
+        let mut candidates = Vec::new();
+        for path in &self.search_paths {
+            collect_candidates(path, 0, &mut candidates);
+        }
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+
+    /// The catalog as of the last scan, without touching any plugin
+    pub fn cached(&self) -> PluginCatalog {
+        PluginCatalog::from_cache(&PluginCache::load(&self.cache_path))
+    }
+
+    /// Scan bundles that are new or changed since the cached scan, drop removed ones,
+    /// and write the cache back
+    pub fn scan(&self) -> PluginCatalog {
+        let previous: HashMap<PathBuf, PluginCacheEntry> = PluginCache::load(&self.cache_path).entries.into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+
+        let mut cache = PluginCache { version: CACHE_VERSION, entries: Vec::new() };
+        let mut rescanned = 0;
+        for path in self.find_candidates() {
+            let Some(format) = PluginFormat::from_path(&path) else {
+                continue;
+            };
+            let modified = modified_time(&path);
+            if let Some(entry) = previous.get(&path).filter(|entry| entry.modified == modified) {
+                cache.entries.push(entry.clone());
+                continue;
+            }
+
+            rescanned += 1;
+            let entry = match format.scan(&path) {
+                Ok(plugins) => PluginCacheEntry { path, modified, plugins, error: None },
+                Err(e) => {
+                    warn!("⚠️ Skipping plugin {}: {}", path.display(), e);
+                    PluginCacheEntry { path, modified, plugins: Vec::new(), error: Some(e.to_string()) }
+                }
+            };
+            cache.entries.push(entry);
+        }
+
+        if let Err(e) = cache.save(&self.cache_path) {
+            warn!("⚠️ {}", e);
+        }
+        let catalog = PluginCatalog::from_cache(&cache);
+        info!("🔌 Found {} plugins in {} bundles ({} rescanned)", catalog.plugins.len(), cache.entries.len(), rescanned);
+        catalog
+    }
+}
+
+fn collect_candidates(path: &Path, depth: usize, candidates: &mut Vec<PathBuf>) {
+    if PluginFormat::from_path(path).is_some() && path.exists() {
+        candidates.push(path.to_path_buf());
+        return;
+    }
+    if depth >= MAX_SCAN_DEPTH || !path.is_dir() {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        collect_candidates(&entry.path(), depth + 1, candidates);
+    }
+}
+
+/// Newest modification time of a file or anything inside a bundle
+fn modified_time(path: &Path) -> u64 {
+    let own = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let children = std::fs::read_dir(path).into_iter().flatten().flatten()
+        .map(|entry| modified_time(&entry.path()));
+    children.fold(own, u64::max)
+}
+
+/// Installed plugins, available as node types
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PluginCatalog {
+    pub plugins: Vec<PluginDescriptor>,
+    /// Bundles that failed to scan, with the reason
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+impl PluginCatalog {
+    pub fn from_cache(cache: &PluginCache) -> Self {
+        let mut catalog = Self::default();
+        for entry in &cache.entries {
+            catalog.plugins.extend(entry.plugins.iter().cloned());
+            if let Some(error) = &entry.error {
+                catalog.failures.push((entry.path.clone(), error.clone()));
+            }
+        }
+        catalog
+    }
+
+    pub fn find(&self, node_type: &str) -> Option<&PluginDescriptor> {
+        self.plugins.iter().find(|plugin| plugin.node_type() == node_type)
+    }
+
+    pub fn by_format(&self, format: PluginFormat) -> impl Iterator<Item = &PluginDescriptor> {
+        self.plugins.iter().filter(move |plugin| plugin.format == format)
+    }
+
+    pub fn register_node_types(&self, registry: &mut NodeRegistry) {
+        for plugin in &self.plugins {
+            registry.register_node_type(plugin.node_type_definition());
+        }
+    }
+
+    /// A node running the plugin registered as `node_type`
+    pub fn create_node(&self, node_type: &str, setup: PluginSetup) -> Result<PluginNode, VjError> {
+        let descriptor = self.find(node_type)
+            .ok_or_else(|| VjError::NodeError(format!("Unknown plugin node type '{}'", node_type)))?;
+        PluginNode::new(descriptor.clone(), setup)
+    }
+}
+
+/// Scan for plugins at startup and register them as node types
+pub(crate) fn setup_plugin_catalog(mut commands: Commands, registry: Option<ResMut<NodeRegistry>>) {
+    let catalog = PluginScanner::default().scan();
+    if let Some(mut registry) = registry {
+        catalog.register_node_types(&mut registry);
+    }
+    commands.insert_resource(catalog);
+}
+
+/// Instance id of a node's plugin within its host
+const NODE_INSTANCE: &str = "node";
+
+/// Graph node running one plugin of any format
+pub struct PluginNode {
+    id: NodeId,
+    descriptor: PluginDescriptor,
+    setup: PluginSetup,
+    host: Mutex<Box<dyn PluginHost>>,
+}
+
+impl PluginNode {
+    pub fn new(descriptor: PluginDescriptor, setup: PluginSetup) -> Result<Self, VjError> {
+        let host = descriptor.format.host();
+        Self::with_plugin_host(descriptor, setup, host)
+    }
+
+    /// Run the plugin in a sandbox process, so a crash only bypasses this node
+    pub fn sandboxed(descriptor: PluginDescriptor, setup: PluginSetup, config: SandboxConfig) -> Result<Self, VjError> {
+        let host = Box::new(SandboxedPluginHost::new(descriptor.format, config));
+        Self::with_plugin_host(descriptor, setup, host)
+    }
+
+    fn with_plugin_host(descriptor: PluginDescriptor, setup: PluginSetup, mut host: Box<dyn PluginHost>) -> Result<Self, VjError> {
+        setup.validate()?;
+        host.set_setup(setup);
+        host.load(NODE_INSTANCE, &descriptor)?;
+        info!("🔌 Loaded {} plugin '{}'", descriptor.format, descriptor.name);
+        Ok(Self { id: NodeId::new(), descriptor, setup, host: Mutex::new(host) })
+    }
+
+    pub fn descriptor(&self) -> &PluginDescriptor {
+        &self.descriptor
+    }
+
+    /// Run `f` with the node's host and the instance id its plugin has there
+    pub fn with_host<T>(&self, f: impl FnOnce(&mut dyn PluginHost, &str) -> T) -> T {
+        let mut host = self.host.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(host.as_mut(), NODE_INSTANCE)
+    }
+
+    /// Plugin state for saved node parameters
+    pub fn saved_parameters(&self) -> Result<HashMap<String, Value>, VjError> {
+        let state = self.with_host(|host, instance| host.instance_state(instance))?;
+        let mut parameters = HashMap::new();
+        parameters.insert("node_type".to_string(), Value::String(self.descriptor.node_type()));
+        parameters.insert("state".to_string(), state);
+        Ok(parameters)
+    }
+
+    /// Host events since the last call, as audio events
+    pub fn events(&self) -> Vec<VjEvent> {
+        self.with_host(|host, _| host.drain_events()).into_iter()
+            .map(|event_type| VjEvent::AudioEvent { event_type })
+            .collect()
+    }
+
+    pub fn restore(&self, parameters: &HashMap<String, Value>) -> Result<(), VjError> {
+        match parameters.get("state") {
+            Some(state) => self.with_host(|host, instance| host.restore_instance_state(instance, state)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Node for PluginNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.descriptor.name
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        let mut inputs = vec![InputPort::new("midi", DataType::Array)];
+        if !self.descriptor.is_instrument {
+            inputs.insert(0, InputPort::new("audio", DataType::AudioBuffer));
+        }
+        inputs
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![OutputPort::new("audio", DataType::AudioBuffer)]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        let channels = self.setup.num_channels;
+        let audio = inputs.get("audio").and_then(|value| AudioBufferData::from_value(value, self.setup.sample_rate));
+        let frames = audio.as_ref().map(AudioBufferData::frames).unwrap_or(self.setup.block_size);
+
+        let mut input = vec![0.0; frames * channels];
+        if let Some(audio) = &audio {
+            let source_channels = audio.channels.max(1);
+            for (index, sample) in input.iter_mut().enumerate() {
+                let (frame, channel) = (index / channels, index % channels);
+                *sample = audio.samples.get(frame * source_channels + channel % source_channels).copied().unwrap_or(0.0);
+            }
+        }
+
+        let messages: Vec<Vec<u8>> = inputs.get("midi")
+            .map(|value| serde_json::from_value(value.clone()))
+            .transpose()?
+            .unwrap_or_default();
+
+        let mut output = vec![0.0; frames * channels];
+        self.with_host(|host, instance| -> Result<(), VjError> {
+            for message in &messages {
+                host.send_midi(instance, message)?;
+            }
+            host.process(instance, &input, &mut output)
+        })?;
+
+        let mut outputs = HashMap::new();
+        outputs.insert("audio".to_string(), AudioBufferData::new(output, channels, self.setup.sample_rate).to_value());
+        Ok(outputs)
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        let mut parameters = HashMap::new();
+        parameters.insert("node_type".to_string(), Value::String(self.descriptor.node_type()));
+        parameters
+    }
+
+    fn is_cacheable(&self) -> bool {
+        // Plugins keep state between blocks
+        false
+    }
+
+    fn drain_events(&mut self) -> Vec<VjEvent> {
+        self.events()
+    }
+}
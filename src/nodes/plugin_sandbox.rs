@@ -0,0 +1,701 @@
+//! Out-of-process plugin hosting
+//!
+//! `SandboxedPluginHost` runs each plugin instance in a child process, so a plugin that
+//! crashes or hangs takes down only its sandbox. Audio blocks are exchanged through a
+//! memory-mapped file shared with the child; requests and replies are JSON lines over the
+//! child's stdin and stdout. When the child dies or misses a deadline the instance is
+//! bypassed, and a background thread starts a new sandbox with the last known state and
+//! parameters. Crashes and restarts are reported as `AudioEventType` events.
+//!
+//! Sandboxes are the NUWE executable started with `PLUGIN_SANDBOX_ARG`, which hands the
+//! process to `run_plugin_sandbox`.
+
+use bevy::log::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use memmap2::MmapMut;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::core::{AudioEventType, VjError};
+use super::plugin_host::{unknown_instance, PluginDescriptor, PluginFormat, PluginHost, PluginParameter, PluginSetup};
+
+/// First argument that starts the NUWE executable as a plugin sandbox
+pub const PLUGIN_SANDBOX_ARG: &str = "--plugin-sandbox";
+
+/// Marks protocol lines on the child's stdout; anything else there is plugin output
+const REPLY_PREFIX: &str = "nuwe-sandbox ";
+
+/// Id of the one instance each sandbox hosts
+const SANDBOX_INSTANCE: &str = "sandbox";
+
+const SAMPLE_BYTES: usize = std::mem::size_of::<f32>();
+
+/// Numbers the shared buffer files of this process
+static NEXT_SHARED_BUFFER: AtomicU64 = AtomicU64::new(0);
+
+/// How sandboxes are started and supervised
+#[derive(Debug, Clone)]
+pub struct SandboxConfig {
+    /// Executable started for each instance; it must call `run_plugin_sandbox`
+    pub executable: PathBuf,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    /// Deadline for loading a plugin and for saving or restoring its state
+    pub load_timeout: Duration,
+    /// Deadline for every other request, including processing a block
+    pub response_timeout: Duration,
+    /// Wait before restarting a crashed instance
+    pub restart_delay: Duration,
+    /// Restarts allowed per instance before it stays bypassed
+    pub max_restarts: u32,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            executable: std::env::current_exe().unwrap_or_else(|_| PathBuf::from("nuwe_rust")),
+            args: vec![PLUGIN_SANDBOX_ARG.to_string()],
+            env: Vec::new(),
+            load_timeout: Duration::from_secs(10),
+            response_timeout: Duration::from_millis(250),
+            restart_delay: Duration::from_millis(500),
+            max_restarts: 5,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+enum SandboxRequest {
+    Load { descriptor: PluginDescriptor, setup: PluginSetup, shared_buffer: PathBuf },
+    Parameters,
+    SetParameter { id: u32, value: f32 },
+    GetParameter { id: u32 },
+    SendMidi { message: Vec<u8> },
+    /// Process `frames` frames from the shared input block into the shared output block
+    Process { frames: usize },
+    Latency,
+    State,
+    RestoreState { state: Value },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "reply", content = "value", rename_all = "snake_case")]
+enum SandboxReply {
+    Done,
+    Error(String),
+    Parameters(Vec<PluginParameter>),
+    Value(f32),
+    Accepted(bool),
+    Latency(usize),
+    State(Value),
+}
+
+/// Why a request to a sandbox failed
+enum SandboxFailure {
+    /// The plugin refused the request; the sandbox is fine
+    Rejected(String),
+    /// The sandbox died or missed its deadline and has been stopped
+    Crashed(String),
+}
+
+impl From<SandboxFailure> for VjError {
+    fn from(failure: SandboxFailure) -> Self {
+        match failure {
+            SandboxFailure::Rejected(message) => VjError::AudioError(message),
+            SandboxFailure::Crashed(reason) => VjError::AudioError(format!("Plugin sandbox failed: {}", reason)),
+        }
+    }
+}
+
+fn unexpected_reply(reply: SandboxReply) -> SandboxFailure {
+    SandboxFailure::Rejected(format!("Unexpected plugin sandbox reply: {:?}", reply))
+}
+
+/// An input block followed by an output block of interleaved samples, in a file both
+/// processes map. Each side only touches the blocks while the other waits for a reply.
+struct SharedBuffer {
+    path: PathBuf,
+    map: MmapMut,
+    block_samples: usize,
+    /// The creating side removes the file
+    owned: bool,
+}
+
+impl SharedBuffer {
+    fn create(block_samples: usize) -> Result<Self, VjError> {
+        let path = std::env::temp_dir().join(format!(
+            "nuwe_sandbox_{}_{}.buf",
+            std::process::id(),
+            NEXT_SHARED_BUFFER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path)
+            .and_then(|file| file.set_len((2 * block_samples * SAMPLE_BYTES) as u64).map(|_| file))
+            .map_err(|e| VjError::FileError(format!("Failed to create plugin buffer '{}': {}", path.display(), e)))?;
+        // SAFETY: the file is private to this host and its sandbox, which take turns using it
+        let map = unsafe { MmapMut::map_mut(&file) }
+            .map_err(|e| VjError::FileError(format!("Failed to map plugin buffer '{}': {}", path.display(), e)))?;
+        Ok(Self { path, map, block_samples, owned: true })
+    }
+
+    fn open(path: PathBuf, block_samples: usize) -> Result<Self, VjError> {
+        let file = OpenOptions::new().read(true).write(true).open(&path)
+            .map_err(|e| VjError::FileError(format!("Failed to open plugin buffer '{}': {}", path.display(), e)))?;
+        // SAFETY: as in `create`; the host sized the file for this block size
+        let map = unsafe { MmapMut::map_mut(&file) }
+            .map_err(|e| VjError::FileError(format!("Failed to map plugin buffer '{}': {}", path.display(), e)))?;
+        if map.len() < 2 * block_samples * SAMPLE_BYTES {
+            return Err(VjError::AudioError(format!("Plugin buffer '{}' is too small", path.display())));
+        }
+        Ok(Self { path, map, block_samples, owned: false })
+    }
+
+    fn block(&mut self, output: bool) -> &mut [u8] {
+        let bytes = self.block_samples * SAMPLE_BYTES;
+        let start = if output { bytes } else { 0 };
+        &mut self.map[start..start + bytes]
+    }
+
+    /// Fill a block from `samples`, padding with silence
+    fn write(&mut self, output: bool, samples: &[f32]) {
+        for (index, bytes) in self.block(output).chunks_exact_mut(SAMPLE_BYTES).enumerate() {
+            bytes.copy_from_slice(&samples.get(index).copied().unwrap_or(0.0).to_ne_bytes());
+        }
+    }
+
+    fn read(&mut self, output: bool, samples: &mut [f32]) {
+        for (sample, bytes) in samples.iter_mut().zip(self.block(output).chunks_exact(SAMPLE_BYTES)) {
+            *sample = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    }
+}
+
+impl Drop for SharedBuffer {
+    fn drop(&mut self) {
+        if self.owned {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// A running sandbox with its plugin loaded
+struct SandboxProcess {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    replies: Receiver<SandboxReply>,
+    buffer: SharedBuffer,
+    channels: usize,
+    parameters: Vec<PluginParameter>,
+    latency: usize,
+}
+
+impl SandboxProcess {
+    /// Start a sandbox, load the plugin and bring back its state and parameter values
+    fn start(
+        config: &SandboxConfig,
+        descriptor: &PluginDescriptor,
+        setup: PluginSetup,
+        state: Option<&Value>,
+        parameter_values: &BTreeMap<u32, f32>,
+    ) -> Result<Self, VjError> {
+        setup.validate()?;
+        let buffer = SharedBuffer::create(setup.block_size * setup.num_channels)?;
+        let mut child = Command::new(&config.executable)
+            .args(&config.args)
+            .envs(config.env.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| VjError::AudioError(format!("Failed to start plugin sandbox '{}': {}", config.executable.display(), e)))?;
+
+        let stdout = child.stdout.take().expect("sandbox stdout is piped");
+        let (sender, replies) = mpsc::channel();
+        thread::Builder::new()
+            .name("plugin-sandbox".to_string())
+            .spawn(move || {
+                for line in BufReader::new(stdout).lines() {
+                    let Ok(line) = line else { break };
+                    let Some(start) = line.find(REPLY_PREFIX) else { continue };
+                    match serde_json::from_str(&line[start + REPLY_PREFIX.len()..]) {
+                        Ok(reply) => {
+                            if sender.send(reply).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => warn!("⚠️ Unreadable plugin sandbox reply: {}", e),
+                    }
+                }
+            })
+            .map_err(|e| VjError::AudioError(format!("Failed to read plugin sandbox: {}", e)))?;
+
+        let mut process = Self {
+            stdin: child.stdin.take(),
+            child,
+            replies,
+            channels: setup.num_channels,
+            parameters: Vec::new(),
+            latency: 0,
+            buffer,
+        };
+        let load = SandboxRequest::Load {
+            descriptor: descriptor.clone(),
+            setup,
+            shared_buffer: process.buffer.path.clone(),
+        };
+        process.expect_done(&load, config.load_timeout)?;
+        if let Some(state) = state {
+            process.expect_done(&SandboxRequest::RestoreState { state: state.clone() }, config.load_timeout)?;
+        }
+        for (&id, &value) in parameter_values {
+            process.expect_done(&SandboxRequest::SetParameter { id, value }, config.response_timeout)?;
+        }
+        process.parameters = match process.request(&SandboxRequest::Parameters, config.response_timeout)? {
+            SandboxReply::Parameters(parameters) => parameters,
+            reply => return Err(unexpected_reply(reply).into()),
+        };
+        process.latency = match process.request(&SandboxRequest::Latency, config.response_timeout)? {
+            SandboxReply::Latency(latency) => latency,
+            reply => return Err(unexpected_reply(reply).into()),
+        };
+        Ok(process)
+    }
+
+    fn request(&mut self, request: &SandboxRequest, timeout: Duration) -> Result<SandboxReply, SandboxFailure> {
+        let line = serde_json::to_string(request)
+            .map_err(|e| SandboxFailure::Rejected(format!("Failed to encode sandbox request: {}", e)))?;
+        let sent = match &mut self.stdin {
+            Some(stdin) => writeln!(stdin, "{}", line).and_then(|_| stdin.flush()).is_ok(),
+            None => false,
+        };
+        if !sent {
+            return Err(SandboxFailure::Crashed(self.exit_reason()));
+        }
+
+        match self.replies.recv_timeout(timeout) {
+            Ok(SandboxReply::Error(message)) => Err(SandboxFailure::Rejected(message)),
+            Ok(reply) => Ok(reply),
+            Err(RecvTimeoutError::Timeout) => {
+                self.stop();
+                Err(SandboxFailure::Crashed(format!("no reply within {} ms", timeout.as_millis())))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(SandboxFailure::Crashed(self.exit_reason())),
+        }
+    }
+
+    fn expect_done(&mut self, request: &SandboxRequest, timeout: Duration) -> Result<(), SandboxFailure> {
+        match self.request(request, timeout)? {
+            SandboxReply::Done => Ok(()),
+            reply => Err(unexpected_reply(reply)),
+        }
+    }
+
+    /// Run interleaved audio through the plugin one block at a time
+    fn process(&mut self, input: &[f32], output: &mut [f32], timeout: Duration) -> Result<(), SandboxFailure> {
+        let block = self.buffer.block_samples;
+        for (index, output) in output.chunks_mut(block).enumerate() {
+            let start = (index * block).min(input.len());
+            let end = (start + output.len()).min(input.len());
+            self.buffer.write(false, &input[start..end]);
+            self.expect_done(&SandboxRequest::Process { frames: output.len() / self.channels }, timeout)?;
+            self.buffer.read(true, output);
+        }
+        Ok(())
+    }
+
+    /// How the child ended, once its reply pipe has closed
+    fn exit_reason(&mut self) -> String {
+        for _ in 0..20 {
+            if let Ok(Some(status)) = self.child.try_wait() {
+                return format!("sandbox exited ({})", status);
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        self.stop();
+        "sandbox closed its pipes".to_string()
+    }
+
+    fn stop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for SandboxProcess {
+    fn drop(&mut self) {
+        // Closing stdin ends the request loop, letting the plugin shut down cleanly
+        self.stdin = None;
+        for _ in 0..40 {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        self.stop();
+    }
+}
+
+/// A sandboxed instance and what it takes to bring it back after a crash
+struct SandboxedInstance {
+    /// Plugin name for events, or the instance id when the descriptor has none
+    label: String,
+    descriptor: PluginDescriptor,
+    config: SandboxConfig,
+    setup: PluginSetup,
+    process: Option<SandboxProcess>,
+    restart: Option<Receiver<Result<SandboxProcess, VjError>>>,
+    restarts: u32,
+    parameters: Vec<PluginParameter>,
+    latency: usize,
+    /// Last state saved or restored, and parameter values set since; replayed on restart
+    state: Option<Value>,
+    parameter_values: BTreeMap<u32, f32>,
+    events: Vec<AudioEventType>,
+}
+
+impl SandboxedInstance {
+    fn start(instance_id: &str, descriptor: &PluginDescriptor, config: &SandboxConfig, setup: PluginSetup) -> Result<Self, VjError> {
+        let process = SandboxProcess::start(config, descriptor, setup, None, &BTreeMap::new())?;
+        let label = if descriptor.name.is_empty() { instance_id.to_string() } else { descriptor.name.clone() };
+        Ok(Self {
+            label,
+            descriptor: descriptor.clone(),
+            config: config.clone(),
+            setup,
+            parameters: process.parameters.clone(),
+            latency: process.latency,
+            process: Some(process),
+            restart: None,
+            restarts: 0,
+            state: None,
+            parameter_values: BTreeMap::new(),
+            events: Vec::new(),
+        })
+    }
+
+    /// The running sandbox, if any, after taking over one a restart finished
+    fn running(&mut self) -> Option<&mut SandboxProcess> {
+        if let Some(restart) = &self.restart {
+            let result = match restart.try_recv() {
+                Ok(result) => Some(result),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => Some(Err(VjError::AudioError("Restart thread stopped".to_string()))),
+            };
+            match result {
+                Some(Ok(process)) => {
+                    info!("🔌 Restarted plugin '{}' ({} of {})", self.label, self.restarts, self.config.max_restarts);
+                    self.restart = None;
+                    self.parameters = process.parameters.clone();
+                    self.latency = process.latency;
+                    self.process = Some(process);
+                    self.events.push(AudioEventType::PluginRestarted { plugin: self.label.clone(), restarts: self.restarts });
+                }
+                Some(Err(e)) => {
+                    self.restart = None;
+                    self.schedule_restart(e.to_string());
+                }
+                None => {}
+            }
+        }
+        self.process.as_mut()
+    }
+
+    fn crashed(&mut self, reason: String) {
+        warn!("💥 Plugin '{}' crashed: {}; bypassing it", self.label, reason);
+        self.process = None;
+        self.events.push(AudioEventType::PluginCrashed { plugin: self.label.clone(), reason });
+        self.schedule_restart(String::new());
+    }
+
+    /// Start a new sandbox in the background; `failure` is why the last restart failed
+    fn schedule_restart(&mut self, failure: String) {
+        let gave_up = self.restarts >= self.config.max_restarts;
+        if !failure.is_empty() || gave_up {
+            let reason = if failure.is_empty() { format!("{} restarts used", self.restarts) } else { failure };
+            warn!("💥 Restarting plugin '{}' failed: {}", self.label, reason);
+            self.events.push(AudioEventType::PluginRestartFailed { plugin: self.label.clone(), reason, gave_up });
+        }
+        if gave_up {
+            return;
+        }
+
+        self.restarts += 1;
+        let (sender, receiver) = mpsc::channel();
+        let (config, descriptor, setup) = (self.config.clone(), self.descriptor.clone(), self.setup);
+        let (state, parameter_values) = (self.state.clone(), self.parameter_values.clone());
+        thread::spawn(move || {
+            thread::sleep(config.restart_delay);
+            let _ = sender.send(SandboxProcess::start(&config, &descriptor, setup, state.as_ref(), &parameter_values));
+        });
+        self.restart = Some(receiver);
+    }
+
+    /// Send a request to the running sandbox; `None` while bypassed
+    fn request(&mut self, request: &SandboxRequest, timeout: Duration) -> Result<Option<SandboxReply>, VjError> {
+        let Some(process) = self.running() else {
+            return Ok(None);
+        };
+        match process.request(request, timeout) {
+            Ok(reply) => Ok(Some(reply)),
+            Err(SandboxFailure::Rejected(message)) => Err(VjError::AudioError(message)),
+            Err(SandboxFailure::Crashed(reason)) => {
+                self.crashed(reason);
+                Ok(None)
+            }
+        }
+    }
+
+    fn process(&mut self, input: &[f32], output: &mut [f32]) -> Result<(), VjError> {
+        let timeout = self.config.response_timeout;
+        if let Some(process) = self.running() {
+            match process.process(input, output, timeout) {
+                Ok(()) => return Ok(()),
+                Err(SandboxFailure::Rejected(message)) => return Err(VjError::AudioError(message)),
+                Err(SandboxFailure::Crashed(reason)) => self.crashed(reason),
+            }
+        }
+        // Bypassed: pass the input through
+        for (index, sample) in output.iter_mut().enumerate() {
+            *sample = input.get(index).copied().unwrap_or(0.0);
+        }
+        Ok(())
+    }
+
+    fn default_value(&self, param_id: u32) -> Option<f32> {
+        self.parameters.iter().find(|parameter| parameter.id == param_id).map(|parameter| parameter.default_value as f32)
+    }
+}
+
+/// Hosts plugins of one format, each instance in its own sandbox process. While an
+/// instance is being restarted it passes audio through, keeps parameter values for the
+/// new sandbox and ignores notes.
+pub struct SandboxedPluginHost {
+    format: PluginFormat,
+    config: SandboxConfig,
+    setup: PluginSetup,
+    instances: HashMap<String, Mutex<SandboxedInstance>>,
+}
+
+impl SandboxedPluginHost {
+    pub fn new(format: PluginFormat, config: SandboxConfig) -> Self {
+        Self { format, config, setup: PluginSetup::default(), instances: HashMap::new() }
+    }
+
+    pub fn config(&self) -> &SandboxConfig {
+        &self.config
+    }
+
+    /// Descriptor an instance was loaded from
+    pub fn descriptor(&self, instance_id: &str) -> Option<PluginDescriptor> {
+        self.instances.get(instance_id).map(|instance| lock(instance).descriptor.clone())
+    }
+
+    /// Whether an instance is running rather than bypassed
+    pub fn is_running(&mut self, instance_id: &str) -> bool {
+        self.instances.get_mut(instance_id)
+            .is_some_and(|instance| lock_mut(instance).running().is_some())
+    }
+
+    fn require_mut(&mut self, instance_id: &str) -> Result<&mut SandboxedInstance, VjError> {
+        let format = self.format;
+        self.instances.get_mut(instance_id)
+            .map(lock_mut)
+            .ok_or_else(|| unknown_instance(format, instance_id))
+    }
+}
+
+fn lock(instance: &Mutex<SandboxedInstance>) -> std::sync::MutexGuard<'_, SandboxedInstance> {
+    instance.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn lock_mut(instance: &mut Mutex<SandboxedInstance>) -> &mut SandboxedInstance {
+    instance.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl PluginHost for SandboxedPluginHost {
+    fn format(&self) -> PluginFormat {
+        self.format
+    }
+
+    fn set_setup(&mut self, setup: PluginSetup) {
+        self.setup = setup;
+    }
+
+    fn load(&mut self, instance_id: &str, descriptor: &PluginDescriptor) -> Result<(), VjError> {
+        let instance = SandboxedInstance::start(instance_id, descriptor, &self.config, self.setup)?;
+        info!("🔌 Loaded {} plugin '{}' in a sandbox", self.format, instance.label);
+        self.instances.insert(instance_id.to_string(), Mutex::new(instance));
+        Ok(())
+    }
+
+    fn unload(&mut self, instance_id: &str) -> bool {
+        self.instances.remove(instance_id).is_some()
+    }
+
+    fn instance_ids(&self) -> Vec<String> {
+        self.instances.keys().cloned().collect()
+    }
+
+    fn parameters(&self, instance_id: &str) -> Result<Vec<PluginParameter>, VjError> {
+        let instance = self.instances.get(instance_id).ok_or_else(|| unknown_instance(self.format, instance_id))?;
+        Ok(lock(instance).parameters.clone())
+    }
+
+    fn set_parameter(&mut self, instance_id: &str, param_id: u32, value: f32) -> Result<(), VjError> {
+        let instance = self.require_mut(instance_id)?;
+        let timeout = instance.config.response_timeout;
+        instance.request(&SandboxRequest::SetParameter { id: param_id, value }, timeout)?;
+        instance.parameter_values.insert(param_id, value);
+        Ok(())
+    }
+
+    fn get_parameter(&self, instance_id: &str, param_id: u32) -> Result<f32, VjError> {
+        let instance = self.instances.get(instance_id).ok_or_else(|| unknown_instance(self.format, instance_id))?;
+        let mut instance = lock(instance);
+        let timeout = instance.config.response_timeout;
+        match instance.request(&SandboxRequest::GetParameter { id: param_id }, timeout)? {
+            Some(SandboxReply::Value(value)) => Ok(value),
+            Some(reply) => Err(unexpected_reply(reply).into()),
+            None => instance.parameter_values.get(&param_id).copied()
+                .or_else(|| instance.default_value(param_id))
+                .ok_or_else(|| VjError::AudioError(format!("Unknown parameter {} of plugin '{}'", param_id, instance.label))),
+        }
+    }
+
+    fn send_midi(&mut self, instance_id: &str, message: &[u8]) -> Result<bool, VjError> {
+        let instance = self.require_mut(instance_id)?;
+        let timeout = instance.config.response_timeout;
+        match instance.request(&SandboxRequest::SendMidi { message: message.to_vec() }, timeout)? {
+            Some(SandboxReply::Accepted(accepted)) => Ok(accepted),
+            Some(reply) => Err(unexpected_reply(reply).into()),
+            None => Ok(false),
+        }
+    }
+
+    fn process(&mut self, instance_id: &str, input: &[f32], output: &mut [f32]) -> Result<(), VjError> {
+        self.require_mut(instance_id)?.process(input, output)
+    }
+
+    /// Latency last reported; kept while bypassed so compensation doesn't jump around
+    fn latency(&self, instance_id: &str) -> Result<usize, VjError> {
+        let instance = self.instances.get(instance_id).ok_or_else(|| unknown_instance(self.format, instance_id))?;
+        Ok(lock(instance).latency)
+    }
+
+    fn instance_state(&mut self, instance_id: &str) -> Result<Value, VjError> {
+        let instance = self.require_mut(instance_id)?;
+        let timeout = instance.config.load_timeout;
+        match instance.request(&SandboxRequest::State, timeout)? {
+            Some(SandboxReply::State(state)) => {
+                instance.state = Some(state.clone());
+                instance.parameter_values.clear();
+                Ok(state)
+            }
+            Some(reply) => Err(unexpected_reply(reply).into()),
+            None => instance.state.clone()
+                .ok_or_else(|| VjError::AudioError(format!("Plugin '{}' is restarting and has no saved state", instance.label))),
+        }
+    }
+
+    fn restore_instance_state(&mut self, instance_id: &str, state: &Value) -> Result<(), VjError> {
+        let instance = self.require_mut(instance_id)?;
+        let timeout = instance.config.load_timeout;
+        instance.request(&SandboxRequest::RestoreState { state: state.clone() }, timeout)?;
+        instance.state = Some(state.clone());
+        instance.parameter_values.clear();
+        Ok(())
+    }
+
+    fn drain_events(&mut self) -> Vec<AudioEventType> {
+        let mut events = Vec::new();
+        for instance in self.instances.values_mut() {
+            let instance = lock_mut(instance);
+            // Pick up finished restarts even when nothing is being processed
+            instance.running();
+            events.append(&mut instance.events);
+        }
+        events
+    }
+}
+
+/// Plugin and shared buffer a sandbox works on once loaded
+struct SandboxChild {
+    host: Box<dyn PluginHost>,
+    buffer: SharedBuffer,
+    channels: usize,
+    input: Vec<f32>,
+    output: Vec<f32>,
+}
+
+impl SandboxChild {
+    fn handle(child: &mut Option<SandboxChild>, request: SandboxRequest) -> Result<SandboxReply, VjError> {
+        if let SandboxRequest::Load { descriptor, setup, shared_buffer } = request {
+            let mut host = descriptor.format.host();
+            host.set_setup(setup);
+            host.load(SANDBOX_INSTANCE, &descriptor)?;
+            let buffer = SharedBuffer::open(shared_buffer, setup.block_size * setup.num_channels)?;
+            *child = Some(SandboxChild {
+                host,
+                channels: setup.num_channels,
+                input: vec![0.0; buffer.block_samples],
+                output: vec![0.0; buffer.block_samples],
+                buffer,
+            });
+            return Ok(SandboxReply::Done);
+        }
+
+        let child = child.as_mut().ok_or_else(|| VjError::AudioError("No plugin loaded in the sandbox".to_string()))?;
+        let host = child.host.as_mut();
+        Ok(match request {
+            SandboxRequest::Load { .. } => unreachable!("handled above"),
+            SandboxRequest::Parameters => SandboxReply::Parameters(host.parameters(SANDBOX_INSTANCE)?),
+            SandboxRequest::SetParameter { id, value } => {
+                host.set_parameter(SANDBOX_INSTANCE, id, value)?;
+                SandboxReply::Done
+            }
+            SandboxRequest::GetParameter { id } => SandboxReply::Value(host.get_parameter(SANDBOX_INSTANCE, id)?),
+            SandboxRequest::SendMidi { message } => SandboxReply::Accepted(host.send_midi(SANDBOX_INSTANCE, &message)?),
+            SandboxRequest::Process { frames } => {
+                let samples = (frames * child.channels).min(child.buffer.block_samples);
+                child.buffer.read(false, &mut child.input[..samples]);
+                host.process(SANDBOX_INSTANCE, &child.input[..samples], &mut child.output[..samples])?;
+                child.buffer.write(true, &child.output[..samples]);
+                SandboxReply::Done
+            }
+            SandboxRequest::Latency => SandboxReply::Latency(host.latency(SANDBOX_INSTANCE)?),
+            SandboxRequest::State => SandboxReply::State(host.instance_state(SANDBOX_INSTANCE)?),
+            SandboxRequest::RestoreState { state } => {
+                host.restore_instance_state(SANDBOX_INSTANCE, &state)?;
+                SandboxReply::Done
+            }
+        })
+    }
+}
+
+/// Serve a `SandboxedPluginHost` over stdin and stdout until stdin closes. Executables
+/// call this when started with `PLUGIN_SANDBOX_ARG`.
+pub fn run_plugin_sandbox() -> Result<(), VjError> {
+    let mut child = None;
+    let mut stdout = std::io::stdout();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.map_err(|e| VjError::AudioError(format!("Failed to read sandbox request: {}", e)))?;
+        let reply = match serde_json::from_str(&line) {
+            Ok(request) => SandboxChild::handle(&mut child, request)
+                .unwrap_or_else(|e| SandboxReply::Error(e.to_string())),
+            Err(e) => SandboxReply::Error(format!("Invalid sandbox request: {}", e)),
+        };
+        let reply = serde_json::to_string(&reply).map_err(|e| VjError::AudioError(e.to_string()))?;
+        writeln!(stdout, "{}{}", REPLY_PREFIX, reply)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| VjError::AudioError(format!("Failed to write sandbox reply: {}", e)))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,857 @@
+#[cfg(test)]
+mod tests {
+    use crate::audio::{AudioGraph, AudioProcessor, AudioSink, AudioSource, ProcessContext, AUDIO_BLOCK_SIZE};
+    use crate::core::{AudioBufferData, Node};
+    use crate::nodes::*;
+    use std::collections::HashMap;
+    use std::f32::consts::TAU;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn sine(frequency: f32, frames: usize) -> Vec<f32> {
+        (0..frames).map(|i| (TAU * frequency * i as f32 / SAMPLE_RATE).sin()).collect()
+    }
+
+    /// Deterministic white noise in -1..1
+    fn noise(samples: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed.max(1);
+        (0..samples)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as f32 / u32::MAX as f32 * 2.0 - 1.0
+            })
+            .collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+    }
+
+    /// Steady-state gain of a mono effect for a sine at `frequency`
+    fn measured_gain(effect: &mut dyn AudioEffect, frequency: f32) -> f32 {
+        effect.prepare(SAMPLE_RATE, 1);
+        effect.reset();
+        let mut buffer = sine(frequency, SAMPLE_RATE as usize / 2);
+        effect.process(&mut buffer, 1);
+        peak(&buffer[buffer.len() / 2..])
+    }
+
+    #[test]
+    fn test_biquad_frequency_response() {
+        let mut lowpass = Biquad::new(BiquadMode::LowPass, 1000.0, std::f32::consts::FRAC_1_SQRT_2);
+        lowpass.prepare(SAMPLE_RATE, 1);
+        assert!((lowpass.magnitude_at(1000.0) - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.01);
+        assert!((lowpass.magnitude_at(20.0) - 1.0).abs() < 0.01);
+        // Second order: about 40 dB per decade above the cutoff
+        assert!(lowpass.magnitude_at(10000.0) < 0.015);
+        for frequency in [100.0, 1000.0, 3000.0] {
+            let expected = lowpass.magnitude_at(frequency);
+            assert!((measured_gain(&mut lowpass, frequency) - expected).abs() < 0.02, "{} Hz", frequency);
+        }
+
+        let mut highpass = Biquad::new(BiquadMode::HighPass, 1000.0, std::f32::consts::FRAC_1_SQRT_2);
+        assert!(measured_gain(&mut highpass, 100.0) < 0.015);
+        assert!((measured_gain(&mut highpass, 10000.0) - 1.0).abs() < 0.02);
+
+        let mut bandpass = Biquad::new(BiquadMode::BandPass, 1000.0, 4.0);
+        assert!((measured_gain(&mut bandpass, 1000.0) - 1.0).abs() < 0.02);
+        assert!(measured_gain(&mut bandpass, 100.0) < 0.05);
+
+        let mut notch = Biquad::new(BiquadMode::Notch, 1000.0, 2.0);
+        assert!(measured_gain(&mut notch, 1000.0) < 0.01);
+        assert!(measured_gain(&mut notch, 100.0) > 0.98);
+
+        let mut low_shelf = Biquad::with_gain(BiquadMode::LowShelf, 200.0, std::f32::consts::FRAC_1_SQRT_2, 6.0);
+        low_shelf.prepare(SAMPLE_RATE, 1);
+        assert!((low_shelf.magnitude_at(10.0) - 1.995).abs() < 0.02);
+        assert!((low_shelf.magnitude_at(10000.0) - 1.0).abs() < 0.01);
+
+        let mut high_shelf = Biquad::with_gain(BiquadMode::HighShelf, 5000.0, std::f32::consts::FRAC_1_SQRT_2, -12.0);
+        high_shelf.prepare(SAMPLE_RATE, 1);
+        assert!((high_shelf.magnitude_at(20000.0) - 0.251).abs() < 0.02);
+        assert!((high_shelf.magnitude_at(50.0) - 1.0).abs() < 0.01);
+
+        let mut peak_filter = Biquad::with_gain(BiquadMode::Peak, 2000.0, 1.0, 12.0);
+        assert!((measured_gain(&mut peak_filter, 2000.0) - 3.98).abs() < 0.05);
+
+        // Modes can change after construction
+        peak_filter.set_mode(BiquadMode::LowPass);
+        assert_eq!(peak_filter.mode(), BiquadMode::LowPass);
+        assert!(measured_gain(&mut peak_filter, 15000.0) < 0.1);
+    }
+
+    #[test]
+    fn test_state_variable_filter_response_and_modulation() {
+        let mut lowpass = StateVariableFilter::new(SvfMode::LowPass, 1000.0, 0.0);
+        assert!((measured_gain(&mut lowpass, 100.0) - 1.0).abs() < 0.02);
+        assert!(measured_gain(&mut lowpass, 10000.0) < 0.015);
+
+        let mut highpass = StateVariableFilter::new(SvfMode::HighPass, 1000.0, 0.0);
+        assert!(measured_gain(&mut highpass, 100.0) < 0.015);
+
+        let mut notch = StateVariableFilter::new(SvfMode::Notch, 1000.0, 0.0);
+        assert!(measured_gain(&mut notch, 1000.0) < 0.01);
+
+        // Resonance raises the response at the cutoff
+        let mut resonant = StateVariableFilter::new(SvfMode::LowPass, 1000.0, 0.9);
+        assert!(measured_gain(&mut resonant, 1000.0) > 2.0);
+
+        // Sweeping the cutoff every few samples at full resonance stays bounded
+        let mut filter = StateVariableFilter::new(SvfMode::BandPass, 1000.0, 1.0);
+        filter.prepare(SAMPLE_RATE, 2);
+        let mut buffer = noise(SAMPLE_RATE as usize * 4, 7);
+        let sweep = noise(buffer.len() / 32, 3);
+        for (block, value) in buffer.chunks_mut(32).zip(sweep) {
+            filter.set_parameter(0, 20.0 * 1000f32.powf(value * 0.5 + 0.5));
+            filter.process(block, 2);
+        }
+        assert!(buffer.iter().all(|sample| sample.is_finite()));
+        assert!(peak(&buffer) < 50.0);
+    }
+
+    #[test]
+    fn test_delay_timing_feedback_and_tempo_sync() {
+        let mut delay = Delay::new(10.0, 0.5, 1.0);
+        set_effect_parameter(&mut delay, "damping", 0.0);
+        delay.prepare(SAMPLE_RATE, 1);
+
+        let mut buffer = vec![0.0; 2000];
+        buffer[0] = 1.0;
+        delay.process(&mut buffer, 1);
+        assert!((buffer[480] - 1.0).abs() < 1e-6);
+        assert!((buffer[960] - 0.5).abs() < 1e-6);
+        assert!((buffer[1440] - 0.25).abs() < 1e-6);
+        assert!(buffer[..480].iter().all(|&sample| sample == 0.0));
+
+        let synced = Delay::synced(0.5, 120.0, 0.0, 1.0);
+        assert!((synced.delay_secs() - 0.25).abs() < 1e-6);
+        let mut synced = synced;
+        assert!(set_effect_parameter(&mut synced, "bpm", 60.0));
+        assert!((synced.delay_secs() - 0.5).abs() < 1e-6);
+        assert!(!set_effect_parameter(&mut synced, "tempo", 60.0));
+
+        // Out-of-range values are clamped
+        synced.set_parameter(4, 5.0);
+        assert_eq!(synced.parameter(4), 0.95);
+    }
+
+    #[test]
+    fn test_reverb_tail_decays() {
+        let mut reverb = Reverb::new(0.5, 0.5, 1.0);
+        reverb.prepare(SAMPLE_RATE, 2);
+        let mut buffer = vec![0.0; SAMPLE_RATE as usize * 2 * 6];
+        buffer[0] = 1.0;
+        buffer[1] = 1.0;
+        reverb.process(&mut buffer, 2);
+
+        let energy = |seconds: std::ops::Range<f32>| -> f32 {
+            let start = (seconds.start * SAMPLE_RATE) as usize * 2;
+            let end = (seconds.end * SAMPLE_RATE) as usize * 2;
+            buffer[start..end].iter().map(|sample| sample * sample).sum()
+        };
+        let early = energy(0.0..0.5);
+        let late = energy(5.5..6.0);
+        assert!(early > 1e-4);
+        assert!(late < early * 1e-3);
+        // Different tap lengths decorrelate the two sides
+        assert!(buffer.chunks(2).any(|frame| (frame[0] - frame[1]).abs() > 1e-4));
+    }
+
+    #[test]
+    fn test_chorus_flanger_bitcrusher_and_distortion() {
+        let mut chorus = Chorus::new();
+        chorus.prepare(SAMPLE_RATE, 2);
+        let input: Vec<f32> = sine(440.0, 48000).into_iter().flat_map(|sample| [sample, sample]).collect();
+        let mut buffer = input.clone();
+        chorus.process(&mut buffer, 2);
+        assert!(buffer.iter().zip(&input).any(|(a, b)| (a - b).abs() > 0.01));
+        // The LFOs are offset, so the sides differ
+        assert!(buffer.chunks(2).any(|frame| (frame[0] - frame[1]).abs() > 0.01));
+
+        let flanger = Chorus::flanger();
+        assert_eq!(flanger.name(), "Flanger");
+        assert!(flanger.parameter(3) > 0.5);
+
+        let mut crusher = Bitcrusher::new(2.0, 4.0);
+        let mut buffer = sine(100.0, 4800);
+        crusher.process(&mut buffer, 1);
+        assert!(buffer.iter().all(|sample| [-1.0, -0.5, 0.0, 0.5, 1.0].contains(sample)));
+        assert!(buffer.chunks(4).all(|held| held.iter().all(|&sample| sample == held[0])));
+
+        for mode in [DistortionMode::SoftClip, DistortionMode::HardClip, DistortionMode::Foldback] {
+            let mut distortion = Distortion::new(mode, 36.0);
+            set_effect_parameter(&mut distortion, "output_db", 0.0);
+            set_effect_parameter(&mut distortion, "tone", 20000.0);
+            distortion.prepare(SAMPLE_RATE, 1);
+            let mut buffer = sine(200.0, 4800);
+            distortion.process(&mut buffer, 1);
+            assert!(peak(&buffer) <= 1.0 + 1e-6, "{:?}", mode);
+            assert!(peak(&buffer) > 0.5, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_compressor_ratio_and_limiter() {
+        let mut compressor = Compressor::new(-20.0, 4.0);
+        set_effect_parameter(&mut compressor, "knee_db", 0.0);
+        assert!((compressor.output_level_db(-10.0) + 17.5).abs() < 1e-4);
+        assert!((compressor.output_level_db(-30.0) + 30.0).abs() < 1e-4);
+
+        // A soft knee bends the curve around the threshold
+        set_effect_parameter(&mut compressor, "knee_db", 10.0);
+        let at_threshold = compressor.output_level_db(-20.0);
+        assert!(at_threshold < -20.0 && at_threshold > -22.0);
+
+        set_effect_parameter(&mut compressor, "knee_db", 0.0);
+        set_effect_parameter(&mut compressor, "attack_ms", 1.0);
+        set_effect_parameter(&mut compressor, "release_ms", 500.0);
+        compressor.prepare(SAMPLE_RATE, 1);
+        let mut buffer = sine(1000.0, 48000);
+        compressor.process(&mut buffer, 1);
+        let level_db = 20.0 * peak(&buffer[24000..]).log10();
+        assert!((level_db + 15.0).abs() < 1.5, "{} dB", level_db);
+        assert!(compressor.gain_reduction_db() > 10.0);
+
+        let mut limiter = Compressor::limiter(-6.0);
+        assert_eq!(limiter.name(), "Limiter");
+        limiter.prepare(SAMPLE_RATE, 2);
+        let mut buffer: Vec<f32> = sine(500.0, 48000).into_iter().flat_map(|sample| [sample, sample * 0.5]).collect();
+        limiter.process(&mut buffer, 2);
+        assert!(peak(&buffer[48000..]) < 10f32.powf(-5.0 / 20.0));
+    }
+
+    #[test]
+    fn test_effects_stay_finite_at_parameter_extremes() {
+        let effects: Vec<Box<dyn Fn() -> Box<dyn AudioEffect>>> = vec![
+            Box::new(|| Box::new(Biquad::new(BiquadMode::Peak, 1000.0, 1.0))),
+            Box::new(|| Box::new(StateVariableFilter::new(SvfMode::LowPass, 1000.0, 0.5))),
+            Box::new(|| Box::new(Delay::new(100.0, 0.5, 0.5))),
+            Box::new(|| Box::new(Reverb::new(0.5, 0.5, 0.5))),
+            Box::new(|| Box::new(Chorus::flanger())),
+            Box::new(|| Box::new(Bitcrusher::new(8.0, 1.0))),
+            Box::new(|| Box::new(Distortion::new(DistortionMode::Foldback, 12.0))),
+            Box::new(|| Box::new(Compressor::new(-20.0, 4.0))),
+        ];
+
+        for make in effects {
+            for extreme in [0, 1] {
+                let mut effect = make();
+                for (index, parameter) in effect.parameters().iter().enumerate() {
+                    let value = if extreme == 0 { parameter.min } else { parameter.max };
+                    effect.set_parameter(index, value);
+                }
+                effect.prepare(SAMPLE_RATE, 2);
+
+                let mut buffer = noise(SAMPLE_RATE as usize * 2 * 2, 11);
+                for impulse in buffer.iter_mut().step_by(9001) {
+                    *impulse = 1.0;
+                }
+                effect.process(&mut buffer, 2);
+                assert!(buffer.iter().all(|sample| sample.is_finite()), "{} at {}", effect.name(), extreme);
+                assert!(peak(&buffer) < 50.0, "{} at {}: {}", effect.name(), extreme, peak(&buffer));
+            }
+        }
+    }
+
+    #[test]
+    fn test_effect_node_parameters_are_modulatable() {
+        let mut node = EffectNode::new(Box::new(Biquad::new(BiquadMode::LowPass, 1000.0, 0.707)));
+        assert_eq!(node.name(), "Biquad");
+        let inputs = node.inputs();
+        assert!(inputs.iter().any(|port| port.name == "cutoff" && !port.required));
+        assert!(inputs.iter().any(|port| port.name == "audio" && port.required));
+
+        let audio = AudioBufferData::new(sine(5000.0, 4800), 1, SAMPLE_RATE).to_value();
+        let mut run = |cutoff: f32| {
+            let mut inputs = HashMap::new();
+            inputs.insert("audio".to_string(), audio.clone());
+            inputs.insert("cutoff".to_string(), serde_json::json!(cutoff));
+            let outputs = node.process(inputs).unwrap();
+            let output = AudioBufferData::from_value(&outputs["audio"], 0.0).unwrap();
+            peak(&output.samples[2400..])
+        };
+        assert!(run(200.0) < 0.01);
+        assert!(run(20000.0) > 0.9);
+        assert_eq!(node.parameters()["cutoff"], 20000.0);
+
+        // The audio-rate graph sets effect parameters by name
+        let (mut graph, mut processor) = AudioGraph::new(SAMPLE_RATE, 1, 1);
+        let crusher = graph.add_node(Box::new(EffectAudioNode::new(Box::new(Bitcrusher::new(24.0, 1.0)), 1))).unwrap();
+        graph.connect(AudioSource::GraphInput(0), AudioSink::Node(crusher, 0)).unwrap();
+        graph.connect(AudioSource::Node(crusher, 0), AudioSink::GraphOutput(0)).unwrap();
+        graph.set_parameter(crusher, "bits", 2.0).unwrap();
+        assert!(graph.set_parameter(crusher, "drive", 1.0).is_err());
+
+        let frames = AUDIO_BLOCK_SIZE * 2;
+        let context = ProcessContext { sample_rate: SAMPLE_RATE, channels: 1, input_channels: 1, frames, frame_position: 0 };
+        let mut output = vec![0.0; frames];
+        processor.process(&context, &vec![0.3; frames], &mut output);
+        assert_eq!(output[frames - 1], 0.5);
+    }
+
+    #[test]
+    fn test_audio_mixer_sums_buffers_per_channel() {
+        let mut mixer = MixerNode::audio();
+        mixer.set_input_group_len(2);
+
+        let stereo = AudioBufferData::new(vec![0.1, -0.1, 0.2, -0.2, 0.3, -0.3], 2, SAMPLE_RATE);
+        let mono = AudioBufferData::new(vec![0.5, 0.5], 1, SAMPLE_RATE);
+        let mut inputs = HashMap::new();
+        inputs.insert("layer_0".to_string(), stereo.to_value());
+        inputs.insert("layer_1".to_string(), mono.to_value());
+
+        let outputs = mixer.process(inputs).unwrap();
+        let mixed = AudioBufferData::from_value(&outputs["output"], 0.0).unwrap();
+        assert_eq!(mixed.channels, 2);
+        assert_eq!(mixed.sample_rate, SAMPLE_RATE);
+        assert_eq!(mixed.frames(), 3);
+        // The mono layer feeds both channels; the shorter layer is padded with silence
+        let expected = [0.6, 0.4, 0.7, 0.3, 0.3, -0.3];
+        for (sample, expected) in mixed.samples.iter().zip(expected) {
+            assert!((sample - expected).abs() < 1e-6, "{} != {}", sample, expected);
+        }
+    }
+
+    /// Build the C fixture plugin into a `.vst3` bundle; `None` without a C compiler
+    fn build_test_plugin(name: &str) -> Option<std::path::PathBuf> {
+        let bundle = std::env::temp_dir()
+            .join(format!("nuwe_{}_{}", name, std::process::id()))
+            .join("NuweTest.vst3");
+        let binary_dir = bundle.join("Contents").join(format!("{}-linux", std::env::consts::ARCH));
+        std::fs::create_dir_all(&binary_dir).ok()?;
+        let status = std::process::Command::new("cc")
+            .args(["-shared", "-fPIC", "-O1", "-o"])
+            .arg(binary_dir.join("NuweTest.so"))
+            .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/vst3_test_plugin.c"))
+            .status()
+            .ok()?;
+        status.success().then_some(bundle)
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_vst3_plugin_loads_and_processes() {
+        let Some(bundle) = build_test_plugin("vst3") else {
+            bevy::log::warn!("Skipping: no C compiler for the VST3 fixture");
+            return;
+        };
+
+        let module = Vst3Module::load(&bundle).unwrap();
+        assert_eq!(module.classes().len(), 1);
+        assert_eq!(module.classes()[0].name, "NUWE Test Gain");
+
+        let config = Vst3PluginConfig {
+            plugin_path: bundle.to_string_lossy().into_owned(),
+            sample_rate: SAMPLE_RATE,
+            block_size: 64,
+            num_channels: 2,
+            class_name: None,
+        };
+        let mut plugin = Vst3PluginInstance::load(&config).unwrap();
+        assert!(plugin.is_instrument());
+        assert!(plugin.accepts_midi());
+        assert_eq!(plugin.latency_samples(), 64);
+        assert_eq!(plugin.tail_samples(), SAMPLE_RATE as u32);
+        assert_eq!(plugin.input_buses()[0].channels, 2);
+        assert_eq!(plugin.output_buses()[0].name, "Output");
+
+        // Parameters come from the controller, including ids that aren't indices
+        assert_eq!(plugin.parameters().len(), 2);
+        let gain = plugin.parameter_info(0).unwrap();
+        assert_eq!((gain.title.as_str(), gain.units.as_str()), ("Gain", "x"));
+        assert_eq!((gain.min, gain.max), (0.0, 2.0));
+        assert!(plugin.parameter_info(7).unwrap().is_read_only());
+        assert_eq!(plugin.parameter_display(0, 0.75).as_deref(), Some("1.50x"));
+        assert_eq!(plugin.parameter_plain(0, 0.25), Some(0.5));
+
+        // Unity gain passes audio through, across several blocks
+        let input: Vec<f32> = (0..200).flat_map(|i| [i as f32 / 200.0, -0.5]).collect();
+        let mut output = vec![9.0; input.len()];
+        plugin.process(&input, &mut output).unwrap();
+        assert_eq!(output, input);
+
+        // Parameter changes reach the processor through the input queue
+        plugin.set_parameter(0, 0.25).unwrap();
+        assert_eq!(plugin.get_parameter(0).unwrap(), 0.25);
+        assert!(plugin.set_parameter(3, 0.5).is_err());
+        plugin.process(&input, &mut output).unwrap();
+        assert_eq!(output[2..4], [0.005 * 0.5, -0.25]);
+
+        // Notes reach the event bus, and output parameter changes come back
+        assert!(plugin.send_midi(&[0x90, 60, 127]));
+        plugin.process(&vec![0.0; 128], &mut output[..128]).unwrap();
+        assert!(output[..128].iter().all(|&sample| sample == 1.0));
+        assert_eq!(plugin.get_parameter(7).unwrap(), 1.0);
+        assert!(plugin.note_off(0, 60, 0.0));
+        plugin.process(&vec![0.0; 128], &mut output[..128]).unwrap();
+        assert!(output[..128].iter().all(|&sample| sample == 0.0));
+
+        // A second instance shares the module
+        let other = Vst3PluginInstance::from_module(module.clone(), &Vst3PluginConfig { num_channels: 1, ..config.clone() }).unwrap();
+        assert_eq!(other.output_buses()[0].channels, 1);
+        drop(other);
+        drop(plugin);
+
+        let mut node = Vst3PluginNode::new("vst3".to_string(), "VST3".to_string());
+        node.load_plugin("gain", &config.plugin_path).unwrap();
+        assert!(node.send_midi("gain", &[0x90, 64, 0]).unwrap());
+        assert!(node.send_midi("missing", &[0x90, 64, 100]).is_err());
+
+        let _ = std::fs::remove_dir_all(bundle.parent().unwrap());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_vst3_state_automation_and_latency() {
+        use crate::audio::Transport;
+        use crate::core::{NodeId, SavedNodeData, VjEvent};
+
+        let Some(bundle) = build_test_plugin("vst3_state") else {
+            bevy::log::warn!("Skipping: no C compiler for the VST3 fixture");
+            return;
+        };
+        let config = Vst3PluginConfig {
+            plugin_path: bundle.to_string_lossy().into_owned(),
+            sample_rate: SAMPLE_RATE,
+            block_size: 64,
+            num_channels: 1,
+            class_name: None,
+        };
+
+        // State saved from one node restores into another, processor and controller alike
+        let mut node = Vst3PluginNode::new("vst3".to_string(), "VST3".to_string());
+        node.configure(config.clone());
+        node.load_plugin("gain", &config.plugin_path).unwrap();
+        node.set_parameter("gain", 0, 0.25).unwrap();
+        node.process_audio("gain", &[1.0; 64]).unwrap();
+        let saved = SavedNodeData {
+            id: uuid::Uuid::new_v4(),
+            node_type: "vst3".to_string(),
+            position: bevy::math::Vec2::ZERO,
+            parameters: node.saved_parameters().unwrap(),
+        };
+        let saved: SavedNodeData = serde_json::from_str(&serde_json::to_string(&saved).unwrap()).unwrap();
+
+        let mut restored = Vst3PluginNode::new("vst3".to_string(), "VST3".to_string());
+        restored.restore(&saved).unwrap();
+        let output = restored.process_audio("gain", &[1.0; 64]).unwrap();
+        assert_eq!(output["audio_output"][63], 0.5);
+        assert_eq!(restored.processor().get_parameter("gain", 0).unwrap(), 0.25);
+        let state = restored.processor_mut().plugin_mut("gain").unwrap().save_state().unwrap();
+        assert_eq!((state.component.len(), state.controller.len()), (8, 4));
+        assert_eq!(Vst3PluginState::from_value(&state.to_value()).unwrap(), state);
+
+        // Output parameters the plugin changes come back as events
+        let node_id = NodeId::new();
+        assert!(restored.parameter_events(node_id).is_empty());
+        restored.send_midi("gain", &[0x90, 60, 127]).unwrap();
+        restored.process_audio("gain", &[0.0; 64]).unwrap();
+        let events = restored.parameter_events(node_id);
+        assert!(matches!(
+            events.as_slice(),
+            [VjEvent::ParameterChanged { parameter, old_value, new_value, .. }]
+                if parameter == "gain.Note Level" && old_value == "0" && new_value == "1"
+        ));
+
+        // Automation lands on its sample: at 45000 BPM one beat is 64 frames
+        let mut plugin = Vst3PluginInstance::load(&config).unwrap();
+        plugin.set_transport(&Transport { bpm: 45000.0, ..Default::default() });
+        let automation = ParameterAutomation::new()
+            .with_point(0.0, 0.5)
+            .with_point(0.5, 0.5)
+            .with_point(33.0 / 64.0, 0.0);
+        assert_eq!(automation.value_at(0.25), Some(0.5));
+        assert!(plugin.set_automation(7, automation.clone()).is_err());
+        plugin.set_automation(0, automation).unwrap();
+        let mut output = vec![0.0; 128];
+        plugin.process(&[0.5; 128], &mut output).unwrap();
+        assert!(output[..33].iter().all(|&sample| sample == 0.5));
+        assert!(output[33..].iter().all(|&sample| sample == 0.0));
+        assert_eq!(plugin.get_parameter(0).unwrap(), 0.0);
+        plugin.clear_automation(0);
+        assert!(plugin.automation(0).is_none());
+
+        // The plugin's latency reaches the graph, which aligns the dry path to it
+        let (mut graph, _processor) = AudioGraph::new(SAMPLE_RATE, 1, 1);
+        let id = graph.add_node(Box::new(Vst3AudioNode::new(plugin))).unwrap();
+        assert_eq!(graph.node_name(id), Some("NUWE Test Gain"));
+        assert_eq!(graph.node_latency(id), Some(64));
+        graph.connect(AudioSource::GraphInput(0), AudioSink::Node(id, 0)).unwrap();
+        graph.connect(AudioSource::Node(id, 0), AudioSink::GraphOutput(0)).unwrap();
+        graph.connect(AudioSource::GraphInput(0), AudioSink::GraphOutput(0)).unwrap();
+        assert_eq!(graph.latency(), 64);
+
+        let _ = std::fs::remove_dir_all(bundle.parent().unwrap());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_vst3_node_system_follows_transport_and_publishes_changes() {
+        use bevy::ecs::message::Messages;
+        use bevy::prelude::{App, Update};
+        use crate::audio::Transport;
+        use crate::core::{NodeId, VjEvent};
+
+        let Some(bundle) = build_test_plugin("vst3_system") else {
+            bevy::log::warn!("Skipping: no C compiler for the VST3 fixture");
+            return;
+        };
+        let config = Vst3PluginConfig {
+            plugin_path: bundle.to_string_lossy().into_owned(),
+            sample_rate: SAMPLE_RATE,
+            block_size: 64,
+            num_channels: 1,
+            class_name: None,
+        };
+        let mut node = Vst3PluginNode::new("vst3".to_string(), "VST3".to_string());
+        node.configure(config.clone());
+        node.load_plugin("gain", &config.plugin_path).unwrap();
+        let automation = ParameterAutomation::new().with_point(0.5, 0.5).with_point(33.0 / 64.0, 0.0);
+        node.processor_mut().plugin_mut("gain").unwrap().set_automation(0, automation).unwrap();
+
+        let mut app = App::new();
+        app.add_message::<VjEvent>()
+            .insert_resource(Transport { bpm: 45000.0, ..Default::default() })
+            .add_systems(Update, update_vst3_nodes);
+        let node_id = NodeId::new();
+        let entity = app.world_mut().spawn(Vst3NodeInstance::new(node_id, node)).id();
+        app.update();
+
+        // At 45000 BPM the automation drops the gain 33 frames in
+        let mut instance = app.world_mut().get_mut::<Vst3NodeInstance>(entity).unwrap();
+        let node = instance.node_mut();
+        let output = node.process_audio("gain", &[0.5; 128]).unwrap();
+        assert_eq!(output["audio_output"][32], 0.5);
+        assert_eq!(output["audio_output"][33], 0.0);
+        node.send_midi("gain", &[0x90, 60, 127]).unwrap();
+        node.process_audio("gain", &[0.0; 64]).unwrap();
+        app.update();
+
+        let messages = app.world().resource::<Messages<VjEvent>>();
+        let events: Vec<_> = messages.iter_current_update_messages().collect();
+        assert!(matches!(
+            events.as_slice(),
+            [VjEvent::ParameterChanged { node_id: id, parameter, new_value, .. }]
+                if *id == node_id && parameter == "gain.Note Level" && new_value == "1"
+        ));
+
+        let _ = std::fs::remove_dir_all(bundle.parent().unwrap());
+    }
+
+    /// Compile a C fixture into `output`; false without a C compiler
+    fn compile_fixture(source: &str, output: &std::path::Path) -> bool {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/");
+        std::fs::create_dir_all(output.parent().unwrap()).is_ok()
+            && std::process::Command::new("cc")
+                .args(["-shared", "-fPIC", "-O1", "-o"])
+                .arg(output)
+                .arg(format!("{}{}", fixtures, source))
+                .status()
+                .is_ok_and(|status| status.success())
+    }
+
+    fn build_clap_plugin(directory: &std::path::Path) -> Option<std::path::PathBuf> {
+        let library = directory.join("NuweTest.clap");
+        compile_fixture("clap_test_plugin.c", &library).then_some(library)
+    }
+
+    fn build_lv2_bundle(directory: &std::path::Path) -> Option<std::path::PathBuf> {
+        let bundle = directory.join("nuwe_test_amp.lv2");
+        if !compile_fixture("lv2_test_plugin/nuwe_test_amp.c", &bundle.join("nuwe_test_amp.so")) {
+            return None;
+        }
+        let source = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/lv2_test_plugin/");
+        for file in ["manifest.ttl", "nuwe_test_amp.ttl"] {
+            std::fs::copy(format!("{}{}", source, file), bundle.join(file)).ok()?;
+        }
+        Some(bundle)
+    }
+
+    fn fixture_directory(name: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("nuwe_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    const TEST_SETUP: PluginSetup = PluginSetup { sample_rate: SAMPLE_RATE, block_size: 64, num_channels: 2 };
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_clap_plugin_runs_through_plugin_host() {
+        let directory = fixture_directory("clap");
+        let Some(library) = build_clap_plugin(&directory) else {
+            bevy::log::warn!("Skipping: no C compiler for the CLAP fixture");
+            return;
+        };
+
+        let plugins = scan_clap(&library).unwrap();
+        assert_eq!(plugins.len(), 1);
+        let descriptor = &plugins[0];
+        assert_eq!(descriptor.id, "org.nuwe.test-gain");
+        assert_eq!(descriptor.name, "NUWE Test Gain");
+        assert_eq!(descriptor.node_type(), "clap:org.nuwe.test-gain");
+        assert!(!descriptor.is_instrument);
+
+        let mut host = PluginFormat::Clap.host();
+        host.set_setup(TEST_SETUP);
+        host.load("gain", descriptor).unwrap();
+        assert_eq!(host.instance_ids(), vec!["gain".to_string()]);
+        let parameters = host.parameters("gain").unwrap();
+        assert_eq!(parameters.len(), 2);
+        assert_eq!(parameters[0].name, "Gain");
+        assert_eq!(parameters[0].default_value, 0.5);
+        assert!(parameters[1].read_only);
+        assert_eq!(host.get_parameter("gain", 0).unwrap(), 0.5);
+        assert_eq!(host.latency("gain").unwrap(), 32);
+
+        // Normalized 0.25 is plain 0.5 on the 0..2 range; blocks are split at 64 frames
+        host.set_parameter("gain", 0, 0.25).unwrap();
+        assert!(host.set_parameter("gain", 1, 0.5).is_err());
+        let mut output = vec![0.0; 200];
+        host.process("gain", &[1.0; 200], &mut output).unwrap();
+        assert!(output.iter().all(|&sample| sample == 0.5));
+
+        // Notes arrive as CLAP note events; the plugin reports the key as a parameter
+        assert!(host.send_midi("gain", &[0x90, 64, 100]).unwrap());
+        host.process("gain", &[1.0; 64], &mut output[..64]).unwrap();
+        assert_eq!(host.get_parameter("gain", 1).unwrap(), 64.0 / 127.0);
+
+        let state = host.instance_state("gain").unwrap();
+        host.set_parameter("gain", 0, 1.0).unwrap();
+        host.process("gain", &[1.0; 64], &mut output[..64]).unwrap();
+        assert_eq!(output[0], 2.0);
+        host.restore_instance_state("gain", &state).unwrap();
+        assert_eq!(host.get_parameter("gain", 0).unwrap(), 0.25);
+        host.process("gain", &[1.0; 64], &mut output[..64]).unwrap();
+        assert_eq!(output[0], 0.5);
+
+        assert!(host.unload("gain"));
+        assert!(host.process("gain", &[], &mut []).is_err());
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_lv2_bundle_metadata_and_processing() {
+        let mut graph = TurtleGraph::new();
+        graph.parse(
+            "@prefix ex: <http://example.org/> .\n\
+             <a> ex:list ( 1 2.5 \"three\" ) ; ex:flag true ;\n\
+                 ex:nested [ ex:name '''multi\nline'''@en ] .",
+            "file:///bundle/manifest.ttl",
+        ).unwrap();
+        let subject = Term::Iri("file:///bundle/a".to_string());
+        let list = graph.object(&subject, "http://example.org/list").unwrap().clone();
+        let first = graph.object(&list, "http://www.w3.org/1999/02/22-rdf-syntax-ns#first").unwrap();
+        assert_eq!(first.as_f64(), Some(1.0));
+        let nested = graph.object(&subject, "http://example.org/nested").unwrap().clone();
+        assert_eq!(graph.object(&nested, "http://example.org/name").and_then(Term::as_str), Some("multi\nline"));
+        assert_eq!(resolve_iri("file:///bundle/manifest.ttl", "../other/x.ttl"), "file:///other/x.ttl");
+        assert!(graph.parse("<a> <b> .", "file:///x").is_err());
+
+        let directory = fixture_directory("lv2");
+        let Some(bundle) = build_lv2_bundle(&directory) else {
+            bevy::log::warn!("Skipping: no C compiler for the LV2 fixture");
+            return;
+        };
+
+        let plugins = read_lv2_bundle(&bundle).unwrap();
+        assert_eq!(plugins.len(), 1);
+        let info = &plugins[0];
+        assert_eq!(info.uri, "urn:nuwe:test-amp");
+        assert_eq!(info.name, "NUWE Test Amp");
+        assert_eq!(info.vendor, "NUWE");
+        assert_eq!(info.version, "1.2");
+        assert_eq!(info.binary.file_name().unwrap(), "nuwe_test_amp.so");
+        assert_eq!(info.ports.len(), 6);
+        assert_eq!(info.ports[2].symbol, "gain");
+        assert_eq!(info.ports[2].range(), (0.0, 2.0));
+        assert!(info.ports[3].supports_midi);
+        assert!(info.ports[5].reports_latency());
+        assert!(info.unsupported_features().is_empty());
+
+        let descriptor = info.descriptor();
+        assert_eq!(descriptor.node_type(), "lv2:urn:nuwe:test-amp");
+        let mut host = PluginFormat::Lv2.host();
+        host.set_setup(TEST_SETUP);
+        host.load("amp", &descriptor).unwrap();
+
+        // The latency port isn't a parameter; the note output is read-only
+        let parameters = host.parameters("amp").unwrap();
+        assert_eq!(parameters.iter().map(|parameter| parameter.name.as_str()).collect::<Vec<_>>(), ["Gain", "Note"]);
+        assert!(parameters[1].read_only);
+        assert_eq!(host.get_parameter("amp", 2).unwrap(), 0.5);
+
+        host.set_parameter("amp", 2, 0.25).unwrap();
+        assert!(host.send_midi("amp", &[0x90, 69, 100]).unwrap());
+        let mut output = vec![0.0; 200];
+        host.process("amp", &[1.0; 200], &mut output).unwrap();
+        assert!(output.iter().all(|&sample| sample == 0.5));
+        assert_eq!(host.get_parameter("amp", 4).unwrap(), 69.0 / 127.0);
+        assert_eq!(host.latency("amp").unwrap(), 64);
+
+        let state = host.instance_state("amp").unwrap();
+        assert_eq!(state["controls"]["gain"], 0.5);
+        host.set_parameter("amp", 2, 1.0).unwrap();
+        host.restore_instance_state("amp", &state).unwrap();
+        assert_eq!(host.get_parameter("amp", 2).unwrap(), 0.25);
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_plugin_scanner_caches_and_registers_node_types() {
+        let directory = fixture_directory("scan");
+        let plugins = directory.join("plugins");
+        let (Some(_), Some(_)) = (build_clap_plugin(&plugins.join("clap")), build_lv2_bundle(&plugins.join("lv2"))) else {
+            bevy::log::warn!("Skipping: no C compiler for the plugin fixtures");
+            return;
+        };
+        std::fs::write(plugins.join("Broken.clap"), b"not a library").unwrap();
+        std::fs::write(plugins.join("readme.txt"), b"ignored").unwrap();
+
+        let cache_path = directory.join("cache").join("plugins.json");
+        let scanner = PluginScanner::new(vec![plugins.clone()], cache_path.clone());
+        assert_eq!(scanner.find_candidates().len(), 3);
+        let catalog = scanner.scan();
+        assert_eq!(catalog.plugins.len(), 2);
+        assert_eq!(catalog.failures.len(), 1);
+        assert!(catalog.failures[0].0.ends_with("Broken.clap"));
+        assert_eq!(catalog.by_format(PluginFormat::Lv2).count(), 1);
+        assert_eq!(scanner.cached().plugins, catalog.plugins);
+
+        // Unchanged bundles come from the cache rather than being scanned again
+        let mut cache = PluginCache::load(&cache_path);
+        for entry in &mut cache.entries {
+            for plugin in &mut entry.plugins {
+                plugin.name = format!("Cached {}", plugin.name);
+            }
+        }
+        cache.save(&cache_path).unwrap();
+        let catalog = scanner.scan();
+        assert!(catalog.plugins.iter().all(|plugin| plugin.name.starts_with("Cached ")));
+
+        let mut registry = crate::core::NodeRegistry::default();
+        catalog.register_node_types(&mut registry);
+        let definition = &registry.node_types["clap:org.nuwe.test-gain"];
+        assert_eq!(definition.category, PLUGIN_NODE_CATEGORY);
+        assert_eq!(definition.description, "Cached NUWE Test Gain by NUWE (CLAP)");
+        assert_eq!(definition.input_ports.len(), 2);
+        assert!(registry.node_types.contains_key("lv2:urn:nuwe:test-amp"));
+
+        // A registered type becomes a graph node; mono input feeds both channels
+        let mut node = catalog.create_node("lv2:urn:nuwe:test-amp", TEST_SETUP).unwrap();
+        node.restore(&HashMap::from([("state".to_string(), serde_json::json!({ "controls": { "gain": 2.0 } }))])).unwrap();
+        let mut inputs = HashMap::new();
+        inputs.insert("audio".to_string(), AudioBufferData::new(vec![0.25; 100], 1, SAMPLE_RATE).to_value());
+        let outputs = node.process(inputs).unwrap();
+        let audio = AudioBufferData::from_value(&outputs["audio"], SAMPLE_RATE).unwrap();
+        assert_eq!(audio.channels, 2);
+        assert_eq!(audio.frames(), 100);
+        assert!(audio.samples.iter().all(|&sample| sample == 0.5));
+        assert!(catalog.create_node("lv2:urn:missing", TEST_SETUP).is_err());
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    /// Sandboxes run the `plugin_sandbox` example, which `cargo test` builds alongside the
+    /// test binaries
+    fn sandbox_test_config() -> Option<SandboxConfig> {
+        let target = std::env::current_exe().ok()?.parent()?.parent()?.to_path_buf();
+        let executable = target.join("examples").join(format!("plugin_sandbox{}", std::env::consts::EXE_SUFFIX));
+        executable.exists().then(|| SandboxConfig {
+            executable,
+            args: vec![PLUGIN_SANDBOX_ARG.to_string()],
+            restart_delay: std::time::Duration::ZERO,
+            max_restarts: 1,
+            ..SandboxConfig::default()
+        })
+    }
+
+    /// Audio events from a node, waiting up to a few seconds for at least `count`
+    fn wait_for_events(node: &mut PluginNode, count: usize) -> Vec<crate::core::AudioEventType> {
+        let mut events = Vec::new();
+        for _ in 0..500 {
+            events.extend(node.drain_events().into_iter().filter_map(|event| match event {
+                crate::core::VjEvent::AudioEvent { event_type } => Some(event_type),
+                _ => None,
+            }));
+            if events.len() >= count {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        events
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_sandboxed_plugin_is_bypassed_and_restarted_after_crash() {
+        use crate::core::AudioEventType;
+
+        let directory = fixture_directory("sandbox");
+        let Some(config) = sandbox_test_config() else {
+            bevy::log::warn!("Skipping: the plugin_sandbox example is not built");
+            return;
+        };
+        let Some(library) = build_clap_plugin(&directory) else {
+            bevy::log::warn!("Skipping: no C compiler for the CLAP fixture");
+            return;
+        };
+        let descriptor = scan_clap(&library).unwrap().remove(0);
+        let mut node = PluginNode::sandboxed(descriptor, TEST_SETUP, config).unwrap();
+        let mut output = vec![0.0; 200];
+
+        node.with_host(|host, instance| {
+            assert_eq!(host.parameters(instance).unwrap().len(), 2);
+            assert_eq!(host.latency(instance).unwrap(), 32);
+            host.set_parameter(instance, 0, 0.25).unwrap();
+            host.process(instance, &[1.0; 200], &mut output).unwrap();
+            assert!(output.iter().all(|&sample| sample == 0.5));
+            // The plugin refusing a request leaves the sandbox running
+            assert!(host.set_parameter(instance, 1, 0.5).is_err());
+            assert!(host.send_midi(instance, &[0x90, 60, 100]).unwrap());
+            host.process(instance, &[1.0; 64], &mut output[..64]).unwrap();
+            assert_eq!(host.get_parameter(instance, 1).unwrap(), 60.0 / 127.0);
+
+            // Key 0 aborts the plugin mid-block; audio passes through while it restarts
+            assert!(host.send_midi(instance, &[0x90, 0, 100]).unwrap());
+            host.process(instance, &[1.0; 64], &mut output[..64]).unwrap();
+            assert!(output[..64].iter().all(|&sample| sample == 1.0));
+            assert_eq!(host.get_parameter(instance, 0).unwrap(), 0.25);
+            assert!(!host.send_midi(instance, &[0x90, 60, 100]).unwrap());
+        });
+
+        let events = wait_for_events(&mut node, 2);
+        assert!(matches!(&events[0], AudioEventType::PluginCrashed { plugin, reason }
+            if plugin == "NUWE Test Gain" && reason.contains("signal")));
+        assert!(matches!(&events[1], AudioEventType::PluginRestarted { restarts: 1, .. }));
+
+        // The new sandbox has the parameter values set before the crash
+        node.with_host(|host, instance| {
+            assert_eq!(host.get_parameter(instance, 0).unwrap(), 0.25);
+            host.process(instance, &[1.0; 64], &mut output[..64]).unwrap();
+            assert!(output[..64].iter().all(|&sample| sample == 0.5));
+        });
+        let saved = node.saved_parameters().unwrap();
+        node.with_host(|host, instance| host.set_parameter(instance, 0, 1.0)).unwrap();
+        node.restore(&saved).unwrap();
+        node.with_host(|host, instance| assert_eq!(host.get_parameter(instance, 0).unwrap(), 0.25));
+
+        // With the restart budget spent, a second crash leaves the plugin bypassed
+        node.with_host(|host, instance| {
+            host.send_midi(instance, &[0x90, 0, 100]).unwrap();
+            host.process(instance, &[0.5; 64], &mut output[..64]).unwrap();
+            assert!(output[..64].iter().all(|&sample| sample == 0.5));
+        });
+        let events = wait_for_events(&mut node, 2);
+        assert!(matches!(&events[0], AudioEventType::PluginCrashed { .. }));
+        assert!(matches!(&events[1], AudioEventType::PluginRestartFailed { gave_up: true, .. }));
+        assert!(node.events().is_empty());
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
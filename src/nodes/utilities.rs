@@ -1,3 +1,214 @@
 // Utility nodes: Math, Convert, Mix, etc.
+use std::collections::HashMap;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::core::{AudioBufferData, Node, NodeId, InputPort, OutputPort, DataType, PortGroup};
+
 pub struct MathNode;
-pub struct MixerNode;
\ No newline at end of file
+
+/// How a mixer combines its layers
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MixMode {
+    Sum,
+    Average,
+    Max,
+}
+
+/// Mixer with one `layer_*` input per connected cable.
+///
+/// Float layers are combined into a single value; audio layers are combined frame by
+/// frame per channel, padding shorter buffers with silence. Mono layers feed every
+/// channel of the widest layer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MixerNode {
+    pub id: NodeId,
+    pub mode: MixMode,
+    pub data_type: DataType,
+    pub layers: usize,
+}
+
+impl MixerNode {
+    pub fn new(mode: MixMode) -> Self {
+        Self {
+            id: NodeId::new(),
+            mode,
+            data_type: DataType::Float,
+            layers: 0,
+        }
+    }
+
+    /// Mixer summing audio buffers
+    pub fn audio() -> Self {
+        Self {
+            data_type: DataType::AudioBuffer,
+            ..Self::new(MixMode::Sum)
+        }
+    }
+
+    fn group(&self) -> PortGroup {
+        PortGroup::new("layer_", self.data_type.clone(), 0)
+    }
+
+    fn combine(&self, values: &[f32]) -> f32 {
+        match self.mode {
+            MixMode::Sum => values.iter().sum(),
+            MixMode::Average if values.is_empty() => 0.0,
+            MixMode::Average => values.iter().sum::<f32>() / values.len() as f32,
+            MixMode::Max => values.iter().copied().reduce(f32::max).unwrap_or(0.0),
+        }
+    }
+}
+
+impl Node for MixerNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "Mixer"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        self.group().ports(self.layers)
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![OutputPort::new("output", self.data_type.clone())]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        let group = self.group();
+        let layers: Vec<&Value> = (0..self.layers)
+            .filter_map(|slot| inputs.get(&group.port_name(slot)))
+            .collect();
+
+        let output = if self.data_type == DataType::AudioBuffer {
+            let buffers: Vec<AudioBufferData> = layers.iter()
+                .filter_map(|layer| AudioBufferData::from_value(layer, 44100.0))
+                .collect();
+            let channels = buffers.iter().map(|buffer| buffer.channels.max(1)).max().unwrap_or(1);
+            let sample_rate = buffers.first().map_or(44100.0, |buffer| buffer.sample_rate);
+            let frames = buffers.iter().map(AudioBufferData::frames).max().unwrap_or(0);
+
+            let mut samples = Vec::with_capacity(buffers.len());
+            let mut mixed = Vec::with_capacity(frames * channels);
+            for frame in 0..frames {
+                for channel in 0..channels {
+                    samples.clear();
+                    samples.extend(buffers.iter().map(|buffer| {
+                        let source_channels = buffer.channels.max(1);
+                        buffer.samples.get(frame * source_channels + channel % source_channels)
+                            .copied()
+                            .unwrap_or(0.0)
+                    }));
+                    mixed.push(self.combine(&samples));
+                }
+            }
+            AudioBufferData::new(mixed, channels, sample_rate).to_value()
+        } else {
+            let values: Vec<f32> = layers.iter()
+                .filter_map(|layer| layer.as_f64())
+                .map(|v| v as f32)
+                .collect();
+            serde_json::json!(self.combine(&values))
+        };
+
+        let mut outputs = HashMap::new();
+        outputs.insert("output".to_string(), output);
+        Ok(outputs)
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        let mut parameters = HashMap::new();
+        parameters.insert("mode".to_string(), serde_json::to_value(self.mode).unwrap_or(Value::Null));
+        parameters
+    }
+
+    fn input_group(&self) -> Option<PortGroup> {
+        Some(self.group())
+    }
+
+    fn set_input_group_len(&mut self, len: usize) {
+        self.layers = len;
+    }
+}
+
+/// How boolean inputs are merged
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LogicMode {
+    Any,
+    All,
+    Xor,
+}
+
+/// Boolean merge with one `input_*` port per connected cable
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogicMergeNode {
+    pub id: NodeId,
+    pub mode: LogicMode,
+    pub inputs: usize,
+}
+
+impl LogicMergeNode {
+    pub fn new(mode: LogicMode) -> Self {
+        Self {
+            id: NodeId::new(),
+            mode,
+            inputs: 0,
+        }
+    }
+
+    fn group(&self) -> PortGroup {
+        PortGroup::new("input_", DataType::Boolean, 0)
+    }
+}
+
+impl Node for LogicMergeNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        "LogicMerge"
+    }
+
+    fn inputs(&self) -> Vec<InputPort> {
+        self.group().ports(self.inputs)
+    }
+
+    fn outputs(&self) -> Vec<OutputPort> {
+        vec![OutputPort::new("output", DataType::Boolean)]
+    }
+
+    fn process(&mut self, inputs: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+        let group = self.group();
+        let values: Vec<bool> = (0..self.inputs)
+            .map(|slot| inputs.get(&group.port_name(slot)).and_then(|v| v.as_bool()).unwrap_or(false))
+            .collect();
+
+        let result = match self.mode {
+            LogicMode::Any => values.iter().any(|&v| v),
+            LogicMode::All => !values.is_empty() && values.iter().all(|&v| v),
+            LogicMode::Xor => values.iter().filter(|&&v| v).count() % 2 == 1,
+        };
+
+        let mut outputs = HashMap::new();
+        outputs.insert("output".to_string(), Value::Bool(result));
+        Ok(outputs)
+    }
+
+    fn parameters(&self) -> HashMap<String, Value> {
+        let mut parameters = HashMap::new();
+        parameters.insert("mode".to_string(), serde_json::to_value(self.mode).unwrap_or(Value::Null));
+        parameters
+    }
+
+    fn input_group(&self) -> Option<PortGroup> {
+        Some(self.group())
+    }
+
+    fn set_input_group_len(&mut self, len: usize) {
+        self.inputs = len;
+    }
+}
@@ -1,11 +1,41 @@
 //! VST3 Plugin Integration for NUWE
 //!
 //! This module provides VST3 plugin hosting capabilities for the NUWE node-based system,
-//! enabling integration with professional audio plugins.
+//! enabling integration with professional audio plugins. Modules are loaded from `.vst3`
+//! bundles (or a bare shared library), and each instance runs its component and edit
+//! controller at the configured sample rate and block size. Plugin state round-trips
+//! through saved node parameters, parameters follow beat-based automation from the
+//! transport, and instances report their latency so parallel paths can be aligned.
+//! `Vst3PluginProcessor` implements `PluginHost` alongside the CLAP and LV2 backends.
+//! `Vst3PluginNode::enable_sandbox` moves a node's plugins into sandbox processes.
+//! Nodes spawned as `Vst3NodeInstance` components are kept on the transport, and their
+//! parameter changes and sandbox crashes are published as `VjEvent`s every frame.
 
+mod automation;
+mod com;
+mod host;
+
+pub use automation::*;
+
+use base64::Engine;
+use rtrb::{Consumer, Producer, RingBuffer};
 use std::collections::HashMap;
+use std::ffi::{c_char, c_void};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use bevy::prelude::{Component, MessageWriter, Query, Res};
+use libloading::Library;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use crate::audio::{AudioNode, AudioProcessor, BlockContext, ProcessContext as AudioContext, Transport, AUDIO_BLOCK_SIZE};
+use crate::core::{NodeId, SavedNodeData, VjError, VjEvent};
+use super::plugin_host::{unknown_instance, PluginDescriptor, PluginFormat, PluginHost, PluginParameter, PluginSetup};
+use super::plugin_sandbox::{SandboxConfig, SandboxedPluginHost};
+use com::*;
+use host::{ComponentHandler, EventList, HostApplication, MemoryStream, ParameterChanges};
+
+/// Parameter changes buffered between an instance and its listener
+const PARAMETER_CHANGE_CAPACITY: usize = 1024;
 
 /// VST3 plugin configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,31 +44,1274 @@ pub struct Vst3PluginConfig {
     pub sample_rate: f32,
     pub block_size: usize,
     pub num_channels: usize,
+    /// Class to instantiate from modules holding several; `None` takes the first audio class
+    #[serde(default)]
+    pub class_name: Option<String>,
+}
+
+impl Default for Vst3PluginConfig {
+    fn default() -> Self {
+        Self {
+            plugin_path: String::new(),
+            sample_rate: 44100.0,
+            block_size: 512,
+            num_channels: 2,
+            class_name: None,
+        }
+    }
+}
+
+impl Vst3PluginConfig {
+    pub fn setup(&self) -> PluginSetup {
+        PluginSetup { sample_rate: self.sample_rate, block_size: self.block_size, num_channels: self.num_channels }
+    }
+}
+
+/// A plugin class offered by a module's factory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Vst3ClassInfo {
+    #[serde(skip)]
+    cid: Tuid,
+    pub name: String,
+    pub category: String,
+    /// `|`-separated, e.g. "Instrument|Synth" or "Fx|Delay"
+    pub sub_categories: String,
+    pub vendor: String,
+    pub version: String,
+}
+
+impl Vst3ClassInfo {
+    pub fn is_instrument(&self) -> bool {
+        self.sub_categories.split('|').any(|category| category == "Instrument")
+    }
+
+    /// Class id as 32 hex digits
+    pub fn cid_string(&self) -> String {
+        self.cid.iter().map(|byte| format!("{:02X}", byte)).collect()
+    }
+}
+
+/// A parameter exposed by a plugin's edit controller. Values are normalized 0..1.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Vst3ParameterInfo {
+    pub id: u32,
+    pub title: String,
+    pub short_title: String,
+    pub units: String,
+    /// 0 for continuous parameters, otherwise the number of steps
+    pub step_count: i32,
+    pub default_value: f64,
+    /// Plain values at normalized 0 and 1
+    pub min: f64,
+    pub max: f64,
+    pub flags: i32,
+}
+
+impl Vst3ParameterInfo {
+    pub fn can_automate(&self) -> bool {
+        self.flags & 1 != 0
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.flags & (1 << 1) != 0
+    }
+
+    pub fn is_bypass(&self) -> bool {
+        self.flags & (1 << 16) != 0
+    }
+}
+
+/// An audio bus after arrangement negotiation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Vst3BusInfo {
+    pub name: String,
+    pub channels: usize,
+    /// Main buses carry the plugin's primary signal; aux buses e.g. sidechains
+    pub is_main: bool,
+}
+
+/// Saved plugin state: the component's chunk and the edit controller's chunk
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Vst3PluginState {
+    pub component: Vec<u8>,
+    pub controller: Vec<u8>,
+}
+
+impl Vst3PluginState {
+    /// JSON form with base64 chunks, as stored in saved node parameters
+    pub fn to_value(&self) -> Value {
+        let engine = base64::engine::general_purpose::STANDARD;
+        serde_json::json!({
+            "component": engine.encode(&self.component),
+            "controller": engine.encode(&self.controller),
+        })
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, VjError> {
+        let chunk = |key: &str| -> Result<Vec<u8>, VjError> {
+            match value.get(key).and_then(Value::as_str) {
+                Some(text) => base64::engine::general_purpose::STANDARD.decode(text)
+                    .map_err(|e| VjError::ConfigError(format!("Invalid VST3 {} state: {}", key, e))),
+                None => Ok(Vec::new()),
+            }
+        };
+        Ok(Self { component: chunk("component")?, controller: chunk("controller")? })
+    }
+}
+
+/// A parameter value the plugin changed on its own, from its editor, its controller
+/// logic, or as an output of processing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vst3ParameterChange {
+    pub param_id: u32,
+    pub old_value: f64,
+    pub new_value: f64,
+}
+
+/// Receives an instance's parameter changes, also while it runs on the audio thread
+pub struct Vst3ParameterListener(Consumer<Vst3ParameterChange>);
+
+impl Vst3ParameterListener {
+    pub fn drain(&mut self) -> impl Iterator<Item = Vst3ParameterChange> + '_ {
+        std::iter::from_fn(|| self.0.pop().ok())
+    }
+}
+
+/// A loaded VST3 module, kept alive while instances of its classes exist
+pub struct Vst3Module {
+    path: PathBuf,
+    factory: Option<ComPtr>,
+    classes: Vec<Vst3ClassInfo>,
+    exit: Option<unsafe extern "system" fn() -> bool>,
+    _library: Library,
+}
+
+// SAFETY: the factory is only used to create instances, which VST3 allows from any thread
+unsafe impl Send for Vst3Module {}
+unsafe impl Sync for Vst3Module {}
+
+/// Shared library inside a `.vst3` bundle, or the path itself for a bare library
+pub fn vst3_binary_path(path: &Path) -> Result<PathBuf, VjError> {
+    if !path.is_dir() {
+        return if path.exists() {
+            Ok(path.to_path_buf())
+        } else {
+            Err(VjError::FileError(format!("VST3 plugin not found: {}", path.display())))
+        };
+    }
+
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+    let architecture = match std::env::consts::ARCH {
+        "x86" => "i386",
+        "arm" => "armv7l",
+        architecture => architecture,
+    };
+    let binary = if cfg!(target_os = "windows") {
+        path.join(format!("Contents/{}-win/{}.vst3", std::env::consts::ARCH, stem))
+    } else if cfg!(target_os = "macos") {
+        path.join("Contents/MacOS").join(stem)
+    } else {
+        path.join(format!("Contents/{}-linux/{}.so", architecture, stem))
+    };
+
+    if binary.exists() {
+        Ok(binary)
+    } else {
+        Err(VjError::FileError(format!("VST3 bundle {} has no binary for this platform ({})", path.display(), binary.display())))
+    }
+}
+
+/// Open a library, passing its handle to `ModuleEntry` as the SDK's Linux entry expects
+#[cfg(unix)]
+unsafe fn open_module(binary: &Path) -> Result<(Library, Option<unsafe extern "system" fn() -> bool>), libloading::Error> {
+    let library = libloading::os::unix::Library::new(binary)?;
+    let handle = library.into_raw();
+    let library = Library::from(libloading::os::unix::Library::from_raw(handle));
+
+    if let Ok(entry) = library.get::<unsafe extern "system" fn(*mut c_void) -> bool>(b"ModuleEntry\0") {
+        if !entry(handle) {
+            return Err(libloading::Error::DlOpenUnknown);
+        }
+    }
+    let exit = library.get::<unsafe extern "system" fn() -> bool>(b"ModuleExit\0").ok().map(|exit| *exit);
+    Ok((library, exit))
+}
+
+#[cfg(windows)]
+unsafe fn open_module(binary: &Path) -> Result<(Library, Option<unsafe extern "system" fn() -> bool>), libloading::Error> {
+    let library = Library::new(binary)?;
+    if let Ok(entry) = library.get::<unsafe extern "system" fn() -> bool>(b"InitDll\0") {
+        entry();
+    }
+    let exit = library.get::<unsafe extern "system" fn() -> bool>(b"ExitDll\0").ok().map(|exit| *exit);
+    Ok((library, exit))
+}
+
+impl Vst3Module {
+    pub fn load(path: &Path) -> Result<Arc<Self>, VjError> {
+        let binary = vst3_binary_path(path)?;
+        // SAFETY: loading a plugin runs its initializers; that's the point of hosting it
+        let (library, exit) = unsafe { open_module(&binary) }
+            .map_err(|e| VjError::AudioError(format!("Failed to load {}: {}", binary.display(), e)))?;
+
+        // SAFETY: GetPluginFactory has this signature in every VST3 module
+        let factory = unsafe {
+            let get_factory = library.get::<unsafe extern "system" fn() -> *mut c_void>(b"GetPluginFactory\0")
+                .map_err(|_| VjError::AudioError(format!("{} is not a VST3 module", binary.display())))?;
+            ComPtr::from_raw(get_factory())
+        };
+        let Some(factory) = factory else {
+            if let Some(exit) = exit {
+                // SAFETY: balances the module entry call
+                unsafe { exit() };
+            }
+            return Err(VjError::AudioError(format!("{} returned no plugin factory", binary.display())));
+        };
+
+        let classes = read_classes(&factory);
+        Ok(Arc::new(Self { path: path.to_path_buf(), factory: Some(factory), classes, exit, _library: library }))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn classes(&self) -> &[Vst3ClassInfo] {
+        &self.classes
+    }
+
+    /// Audio processor classes; other classes are controllers and the like
+    pub fn audio_classes(&self) -> impl Iterator<Item = &Vst3ClassInfo> {
+        self.classes.iter().filter(|class| class.category == AUDIO_MODULE_CLASS)
+    }
+
+    /// Create an object of class `cid` as interface `iid`
+    fn create(&self, cid: &Tuid, iid: &Tuid) -> Option<ComPtr> {
+        let factory = self.factory.as_ref()?;
+        let mut object = std::ptr::null_mut();
+        // SAFETY: the factory is live and `object` receives one owned reference
+        unsafe {
+            let vtbl: &IPluginFactoryVtbl = factory.vtable();
+            let result = (vtbl.create_instance)(factory.as_ptr(), cid.as_ptr() as *const c_char, iid.as_ptr() as *const c_char, &mut object);
+            if result != K_RESULT_OK {
+                return None;
+            }
+            ComPtr::from_raw(object)
+        }
+    }
+}
+
+/// Describe the audio classes of a `.vst3` bundle
+pub fn scan_vst3(path: &Path) -> Result<Vec<PluginDescriptor>, VjError> {
+    let module = Vst3Module::load(path)?;
+    Ok(module.audio_classes()
+        .map(|class| PluginDescriptor {
+            format: PluginFormat::Vst3,
+            id: class.cid_string(),
+            name: class.name.clone(),
+            vendor: class.vendor.clone(),
+            version: class.version.clone(),
+            path: path.to_path_buf(),
+            is_instrument: class.is_instrument(),
+        })
+        .collect())
+}
+
+impl Drop for Vst3Module {
+    fn drop(&mut self) {
+        self.factory = None;
+        if let Some(exit) = self.exit {
+            // SAFETY: every object from this module has been released
+            unsafe { exit() };
+        }
+    }
+}
+
+fn read_classes(factory: &ComPtr) -> Vec<Vst3ClassInfo> {
+    let factory2 = factory.query(&IPLUGIN_FACTORY2_IID);
+    let mut classes = Vec::new();
+    // SAFETY: the info structs are plain data the factory fills in
+    unsafe {
+        let vtbl: &IPluginFactoryVtbl = factory.vtable();
+        for index in 0..(vtbl.count_classes)(factory.as_ptr()) {
+            let mut info: PClassInfo = std::mem::zeroed();
+            if (vtbl.get_class_info)(factory.as_ptr(), index, &mut info) != K_RESULT_OK {
+                continue;
+            }
+            let mut class = Vst3ClassInfo {
+                cid: info.cid,
+                name: c_string(&info.name),
+                category: c_string(&info.category),
+                sub_categories: String::new(),
+                vendor: String::new(),
+                version: String::new(),
+            };
+            if let Some(factory2) = &factory2 {
+                let vtbl2: &IPluginFactory2Vtbl = factory2.vtable();
+                let mut info2: PClassInfo2 = std::mem::zeroed();
+                if (vtbl2.get_class_info2)(factory2.as_ptr(), index, &mut info2) == K_RESULT_OK {
+                    class.sub_categories = c_string(&info2.sub_categories);
+                    class.vendor = c_string(&info2.vendor);
+                    class.version = c_string(&info2.version);
+                }
+            }
+            classes.push(class);
+        }
+    }
+    classes
+}
+
+/// Channel buffers of one bus and the pointer array handed to the plugin
+struct BusBuffers {
+    channels: Vec<Vec<f32>>,
+    pointers: Vec<*mut f32>,
+}
+
+impl BusBuffers {
+    fn new(channels: usize, block_size: usize) -> Self {
+        let mut channels: Vec<Vec<f32>> = (0..channels).map(|_| vec![0.0; block_size]).collect();
+        let pointers = channels.iter_mut().map(|channel| channel.as_mut_ptr()).collect();
+        Self { channels, pointers }
+    }
+}
+
+fn bus_buffers(buses: &mut [BusBuffers]) -> Vec<AudioBusBuffers> {
+    buses.iter_mut()
+        .map(|bus| AudioBusBuffers {
+            num_channels: bus.channels.len() as i32,
+            silence_flags: 0,
+            channel_buffers: bus.pointers.as_mut_ptr(),
+        })
+        .collect()
+}
+
+/// VST3 plugin instance wrapper
+pub struct Vst3PluginInstance {
+    plugin_path: String,
+    config: Vst3PluginConfig,
+    class: Vst3ClassInfo,
+    // Plugin objects are released in this order, before the host objects and the module
+    processor: ComPtr,
+    controller: Option<ComPtr>,
+    connections: Option<(ComPtr, ComPtr)>,
+    /// Separate controllers are initialized and terminated on their own
+    separate_controller: bool,
+    component: ComPtr,
+    /// Context the plugin was initialized with
+    _host: Box<HostApplication>,
+    handler: Box<ComponentHandler>,
+    input_changes: Box<ParameterChanges>,
+    output_changes: Box<ParameterChanges>,
+    input_events: Box<EventList>,
+    output_events: Box<EventList>,
+    context: Box<com::ProcessContext>,
+    input_buses: Vec<Vst3BusInfo>,
+    output_buses: Vec<Vst3BusInfo>,
+    input_buffers: Vec<BusBuffers>,
+    output_buffers: Vec<BusBuffers>,
+    input_bus_buffers: Vec<AudioBusBuffers>,
+    output_bus_buffers: Vec<AudioBusBuffers>,
+    has_event_input: bool,
+    parameters: Vec<Vst3ParameterInfo>,
+    parameter_index: HashMap<u32, usize>,
+    parameter_values: Vec<f64>,
+    changes: Producer<Vst3ParameterChange>,
+    listener: Option<Vst3ParameterListener>,
+    automation: Vec<(u32, ParameterAutomation)>,
+    transport: Transport,
+    /// Engine frame of the next block
+    frame_position: u64,
+    _module: Arc<Vst3Module>,
+}
+
+// SAFETY: the instance is used from one thread at a time through `&mut self`. Without an
+// editor open, VST3 objects have no thread affinity.
+unsafe impl Send for Vst3PluginInstance {}
+
+impl Vst3PluginInstance {
+    /// Load a plugin at 44.1 kHz stereo with 512-frame blocks
+    pub fn new(plugin_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let config = Vst3PluginConfig { plugin_path: plugin_path.to_string(), ..Default::default() };
+        Ok(Self::load(&config)?)
+    }
+
+    /// Load, initialize and activate a plugin
+    pub fn load(config: &Vst3PluginConfig) -> Result<Self, VjError> {
+        let module = Vst3Module::load(Path::new(&config.plugin_path))?;
+        Self::from_module(module, config)
+    }
+
+    /// Instantiate a class of an already loaded module
+    pub fn from_module(module: Arc<Vst3Module>, config: &Vst3PluginConfig) -> Result<Self, VjError> {
+        if config.block_size == 0 || config.sample_rate <= 0.0 {
+            return Err(VjError::ConfigError(format!(
+                "Invalid VST3 processing setup: {} Hz, {} frames", config.sample_rate, config.block_size
+            )));
+        }
+
+        let class = match &config.class_name {
+            Some(name) => module.audio_classes().find(|class| &class.name == name),
+            None => module.audio_classes().next(),
+        }
+        .cloned()
+        .ok_or_else(|| VjError::AudioError(format!("No audio plugin class in {}", config.plugin_path)))?;
+
+        let component = module.create(&class.cid, &ICOMPONENT_IID)
+            .ok_or_else(|| VjError::AudioError(format!("Failed to create VST3 component '{}'", class.name)))?;
+        let mut host = HostApplication::new();
+        // SAFETY: interface pointers below come from the plugin and stay alive while held
+        unsafe {
+            let vtbl: &IComponentVtbl = component.vtable();
+            if (vtbl.initialize)(component.as_ptr(), host.as_ptr()) != K_RESULT_OK {
+                return Err(VjError::AudioError(format!("VST3 component '{}' failed to initialize", class.name)));
+            }
+        }
+
+        let mut instance = Self::initialize(module, config, class, component, host)?;
+        instance.activate()?;
+        Ok(instance)
+    }
+
+    fn initialize(module: Arc<Vst3Module>, config: &Vst3PluginConfig, class: Vst3ClassInfo, component: ComPtr, mut host: Box<HostApplication>) -> Result<Self, VjError> {
+        let processor = match component.query(&IAUDIO_PROCESSOR_IID) {
+            Some(processor) => processor,
+            None => {
+                // SAFETY: balances the initialize call
+                unsafe { (component.vtable::<IComponentVtbl>().terminate)(component.as_ptr()) };
+                return Err(VjError::AudioError(format!("VST3 plugin '{}' has no audio processor", class.name)));
+            }
+        };
+
+        // Single-component plugins implement the controller on the component itself
+        let (controller, separate_controller) = match component.query(&IEDIT_CONTROLLER_IID) {
+            Some(controller) => (Some(controller), false),
+            None => (create_controller(&module, &component, &mut host), true),
+        };
+
+        let connections = match &controller {
+            Some(controller) if separate_controller => connect(&component, controller),
+            _ => None,
+        };
+
+        let block_size = config.block_size;
+        let (changes, listener) = RingBuffer::new(PARAMETER_CHANGE_CAPACITY);
+        let mut instance = Self {
+            plugin_path: config.plugin_path.clone(),
+            config: config.clone(),
+            class,
+            processor,
+            controller,
+            connections,
+            separate_controller,
+            component,
+            _host: host,
+            handler: ComponentHandler::new(),
+            input_changes: ParameterChanges::new(),
+            output_changes: ParameterChanges::new(),
+            input_events: EventList::new(),
+            output_events: EventList::new(),
+            context: Box::default(),
+            input_buses: Vec::new(),
+            output_buses: Vec::new(),
+            input_buffers: Vec::new(),
+            output_buffers: Vec::new(),
+            input_bus_buffers: Vec::new(),
+            output_bus_buffers: Vec::new(),
+            has_event_input: false,
+            parameters: Vec::new(),
+            parameter_index: HashMap::new(),
+            parameter_values: Vec::new(),
+            changes,
+            listener: Some(Vst3ParameterListener(listener)),
+            automation: Vec::new(),
+            transport: Transport::default(),
+            frame_position: 0,
+            _module: module,
+        };
+
+        if let Some(controller) = &instance.controller {
+            // SAFETY: the handler lives as long as the instance
+            unsafe {
+                let vtbl: &IEditControllerVtbl = controller.vtable();
+                (vtbl.set_component_handler)(controller.as_ptr(), instance.handler.as_ptr());
+            }
+        }
+
+        instance.setup_processing()?;
+        instance.negotiate_buses();
+        instance.input_buffers = instance.input_buses.iter().map(|bus| BusBuffers::new(bus.channels, block_size)).collect();
+        instance.output_buffers = instance.output_buses.iter().map(|bus| BusBuffers::new(bus.channels, block_size)).collect();
+        instance.input_bus_buffers = bus_buffers(&mut instance.input_buffers);
+        instance.output_bus_buffers = bus_buffers(&mut instance.output_buffers);
+        instance.read_parameters();
+        Ok(instance)
+    }
+
+    fn setup_processing(&mut self) -> Result<(), VjError> {
+        // SAFETY: the processor is live; the setup struct is read during the call
+        unsafe {
+            let vtbl: &IAudioProcessorVtbl = self.processor.vtable();
+            if (vtbl.can_process_sample_size)(self.processor.as_ptr(), K_SAMPLE_32) != K_RESULT_OK {
+                return Err(VjError::AudioError(format!("VST3 plugin '{}' can't process 32-bit samples", self.class.name)));
+            }
+            let mut setup = ProcessSetup {
+                process_mode: K_REALTIME,
+                symbolic_sample_size: K_SAMPLE_32,
+                max_samples_per_block: self.config.block_size as i32,
+                sample_rate: self.config.sample_rate as f64,
+            };
+            if (vtbl.setup_processing)(self.processor.as_ptr(), &mut setup) != K_RESULT_OK {
+                return Err(VjError::AudioError(format!(
+                    "VST3 plugin '{}' rejected {} Hz with {}-frame blocks", self.class.name, self.config.sample_rate, self.config.block_size
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask for `num_channels` on the main buses, keep aux buses as they are, and use
+    /// whatever the plugin settles on
+    fn negotiate_buses(&mut self) {
+        // SAFETY: bus info and arrangements are plain data filled in by the plugin
+        unsafe {
+            let component: &IComponentVtbl = self.component.vtable();
+            let processor: &IAudioProcessorVtbl = self.processor.vtable();
+            let bus_infos = |direction: i32| -> Vec<BusInfo> {
+                (0..(component.get_bus_count)(self.component.as_ptr(), K_AUDIO, direction))
+                    .map(|index| {
+                        let mut info: BusInfo = std::mem::zeroed();
+                        (component.get_bus_info)(self.component.as_ptr(), K_AUDIO, direction, index, &mut info);
+                        info
+                    })
+                    .collect()
+            };
+            let (inputs, outputs) = (bus_infos(K_INPUT), bus_infos(K_OUTPUT));
+
+            let current = |direction: i32, index: usize, info: &BusInfo| -> SpeakerArrangement {
+                let mut arrangement = 0;
+                if (processor.get_bus_arrangement)(self.processor.as_ptr(), direction, index as i32, &mut arrangement) == K_RESULT_OK {
+                    arrangement
+                } else {
+                    arrangement_for_channels(info.channel_count.max(0) as usize)
+                }
+            };
+            let requested = |direction: i32, infos: &[BusInfo]| -> Vec<SpeakerArrangement> {
+                infos.iter().enumerate()
+                    .map(|(index, info)| {
+                        if index == 0 && info.bus_type == K_MAIN_BUS {
+                            arrangement_for_channels(self.config.num_channels)
+                        } else {
+                            current(direction, index, info)
+                        }
+                    })
+                    .collect()
+            };
+            let mut input_arrangements = requested(K_INPUT, &inputs);
+            let mut output_arrangements = requested(K_OUTPUT, &outputs);
+            let accepted = (processor.set_bus_arrangements)(
+                self.processor.as_ptr(),
+                input_arrangements.as_mut_ptr(), input_arrangements.len() as i32,
+                output_arrangements.as_mut_ptr(), output_arrangements.len() as i32,
+            ) == K_RESULT_OK;
+            if !accepted {
+                bevy::log::warn!("⚠️ VST3 plugin '{}' rejected {} channels, using its own layout", self.class.name, self.config.num_channels);
+            }
+
+            let describe = |direction: i32, infos: &[BusInfo]| -> Vec<Vst3BusInfo> {
+                infos.iter().enumerate()
+                    .map(|(index, info)| Vst3BusInfo {
+                        name: utf16_string(&info.name),
+                        channels: current(direction, index, info).count_ones() as usize,
+                        is_main: info.bus_type == K_MAIN_BUS,
+                    })
+                    .collect()
+            };
+            self.input_buses = describe(K_INPUT, &inputs);
+            self.output_buses = describe(K_OUTPUT, &outputs);
+
+            for (direction, buses) in [(K_INPUT, &self.input_buses), (K_OUTPUT, &self.output_buses)] {
+                if buses.first().is_some_and(|bus| bus.is_main) {
+                    (component.activate_bus)(self.component.as_ptr(), K_AUDIO, direction, 0, 1);
+                }
+            }
+            self.has_event_input = (component.get_bus_count)(self.component.as_ptr(), K_EVENT, K_INPUT) > 0;
+            if self.has_event_input {
+                (component.activate_bus)(self.component.as_ptr(), K_EVENT, K_INPUT, 0, 1);
+            }
+        }
+    }
+
+    fn read_parameters(&mut self) {
+        let Some(controller) = &self.controller else {
+            return;
+        };
+        // SAFETY: parameter info is plain data filled in by the controller
+        unsafe {
+            let vtbl: &IEditControllerVtbl = controller.vtable();
+            for index in 0..(vtbl.get_parameter_count)(controller.as_ptr()) {
+                let mut info: ParameterInfo = std::mem::zeroed();
+                if (vtbl.get_parameter_info)(controller.as_ptr(), index, &mut info) != K_RESULT_OK {
+                    continue;
+                }
+                self.parameter_index.insert(info.id, self.parameters.len());
+                self.parameter_values.push((vtbl.get_param_normalized)(controller.as_ptr(), info.id));
+                self.parameters.push(Vst3ParameterInfo {
+                    id: info.id,
+                    title: utf16_string(&info.title),
+                    short_title: utf16_string(&info.short_title),
+                    units: utf16_string(&info.units),
+                    step_count: info.step_count,
+                    default_value: info.default_normalized_value,
+                    min: (vtbl.normalized_param_to_plain)(controller.as_ptr(), info.id, 0.0),
+                    max: (vtbl.normalized_param_to_plain)(controller.as_ptr(), info.id, 1.0),
+                    flags: info.flags,
+                });
+            }
+        }
+    }
+
+    fn activate(&mut self) -> Result<(), VjError> {
+        // SAFETY: the component and processor are initialized and set up
+        unsafe {
+            let component: &IComponentVtbl = self.component.vtable();
+            if (component.set_active)(self.component.as_ptr(), 1) != K_RESULT_OK {
+                return Err(VjError::AudioError(format!("VST3 plugin '{}' failed to activate", self.class.name)));
+            }
+            // Plugins that don't need the call return kNotImplemented
+            let processor: &IAudioProcessorVtbl = self.processor.vtable();
+            (processor.set_processing)(self.processor.as_ptr(), 1);
+        }
+        Ok(())
+    }
+
+    pub fn plugin_path(&self) -> &str {
+        &self.plugin_path
+    }
+
+    pub fn config(&self) -> &Vst3PluginConfig {
+        &self.config
+    }
+
+    pub fn class_info(&self) -> &Vst3ClassInfo {
+        &self.class
+    }
+
+    pub fn input_buses(&self) -> &[Vst3BusInfo] {
+        &self.input_buses
+    }
+
+    pub fn output_buses(&self) -> &[Vst3BusInfo] {
+        &self.output_buses
+    }
+
+    /// Whether the plugin takes note input
+    pub fn is_instrument(&self) -> bool {
+        self.class.is_instrument() || (self.has_event_input && self.input_buses.is_empty())
+    }
+
+    pub fn accepts_midi(&self) -> bool {
+        self.has_event_input
+    }
+
+    pub fn latency_samples(&self) -> u32 {
+        // SAFETY: the processor is live
+        unsafe { (self.processor.vtable::<IAudioProcessorVtbl>().get_latency_samples)(self.processor.as_ptr()) }
+    }
+
+    pub fn tail_samples(&self) -> u32 {
+        // SAFETY: the processor is live
+        unsafe { (self.processor.vtable::<IAudioProcessorVtbl>().get_tail_samples)(self.processor.as_ptr()) }
+    }
+
+    pub fn parameters(&self) -> &[Vst3ParameterInfo] {
+        &self.parameters
+    }
+
+    pub fn parameter_info(&self, param_id: u32) -> Option<&Vst3ParameterInfo> {
+        self.parameter_index.get(&param_id).map(|&index| &self.parameters[index])
+    }
+
+    /// Set a normalized (0..1) parameter on the controller and the processor
+    pub fn set_parameter(&mut self, param_id: u32, value: f32) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(self.set_parameter_normalized(param_id, value as f64)?)
+    }
+
+    fn set_parameter_normalized(&mut self, param_id: u32, value: f64) -> Result<(), VjError> {
+        let index = *self.parameter_index.get(&param_id)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown VST3 parameter {}", param_id)))?;
+        let value = value.clamp(0.0, 1.0);
+        if let Some(controller) = &self.controller {
+            // SAFETY: the controller is live
+            unsafe { (controller.vtable::<IEditControllerVtbl>().set_param_normalized)(controller.as_ptr(), param_id, value) };
+        }
+        if !self.input_changes.add(param_id, 0, value) {
+            return Err(VjError::AudioError("Too many VST3 parameter changes in one block".to_string()));
+        }
+        self.parameter_values[index] = value;
+        Ok(())
+    }
+
+    /// Normalized (0..1) value of a parameter
+    pub fn get_parameter(&self, param_id: u32) -> Result<f32, Box<dyn std::error::Error>> {
+        self.parameter_index.get(&param_id)
+            .map(|&index| self.parameter_values[index] as f32)
+            .ok_or_else(|| format!("Parameter {} not found", param_id).into())
+    }
+
+    /// Plain value for a normalized one, in the parameter's units
+    pub fn parameter_plain(&self, param_id: u32, normalized: f64) -> Option<f64> {
+        let controller = self.controller.as_ref().filter(|_| self.parameter_index.contains_key(&param_id))?;
+        // SAFETY: the controller is live
+        Some(unsafe { (controller.vtable::<IEditControllerVtbl>().normalized_param_to_plain)(controller.as_ptr(), param_id, normalized) })
+    }
+
+    /// The plugin's text for a normalized value, e.g. "-6.0 dB"
+    pub fn parameter_display(&self, param_id: u32, normalized: f64) -> Option<String> {
+        let controller = self.controller.as_ref().filter(|_| self.parameter_index.contains_key(&param_id))?;
+        let mut text: String128 = [0; 128];
+        // SAFETY: the controller writes at most 128 UTF-16 units
+        let result = unsafe {
+            (controller.vtable::<IEditControllerVtbl>().get_param_string_by_value)(controller.as_ptr(), param_id, normalized, &mut text)
+        };
+        (result == K_RESULT_OK).then(|| utf16_string(&text))
+    }
+
+    /// Forward edits the controller made on its own, e.g. one parameter driving another,
+    /// and pick up values it reloaded. True when the plugin reported a new latency.
+    pub fn sync_controller_edits(&mut self) -> bool {
+        for (param_id, value) in self.handler.take_edits() {
+            let old_value = self.parameter_index.get(&param_id).map(|&index| self.parameter_values[index]);
+            if self.set_parameter_normalized(param_id, value).is_ok() {
+                self.notify(param_id, old_value.unwrap_or(value), value);
+            }
+        }
+
+        let flags = self.handler.take_restart_flags();
+        if flags & K_PARAM_VALUES_CHANGED != 0 {
+            for (param_id, old_value, new_value) in self.reload_parameter_values() {
+                self.notify(param_id, old_value, new_value);
+            }
+        }
+        flags & K_LATENCY_CHANGED != 0
+    }
+
+    /// Take the receiver of this instance's parameter changes; there is only one
+    pub fn parameter_listener(&mut self) -> Option<Vst3ParameterListener> {
+        self.listener.take()
+    }
+
+    fn notify(&mut self, param_id: u32, old_value: f64, new_value: f64) {
+        if old_value != new_value {
+            // Changes are dropped while nobody drains the listener
+            let _ = self.changes.push(Vst3ParameterChange { param_id, old_value, new_value });
+        }
+    }
+
+    /// Re-read every value from the controller; returns the ones that changed
+    fn reload_parameter_values(&mut self) -> Vec<(u32, f64, f64)> {
+        let Some(controller) = &self.controller else {
+            return Vec::new();
+        };
+        let mut changed = Vec::new();
+        for (parameter, value) in self.parameters.iter().zip(self.parameter_values.iter_mut()) {
+            // SAFETY: the controller is live
+            let new_value = unsafe { (controller.vtable::<IEditControllerVtbl>().get_param_normalized)(controller.as_ptr(), parameter.id) };
+            if new_value != *value {
+                changed.push((parameter.id, *value, new_value));
+                *value = new_value;
+            }
+        }
+        changed
+    }
+
+    /// Save the component's and the controller's state
+    pub fn save_state(&mut self) -> Result<Vst3PluginState, VjError> {
+        let mut stream = MemoryStream::new(Vec::new());
+        // SAFETY: the stream outlives the call
+        let result = unsafe { (self.component.vtable::<IComponentVtbl>().get_state)(self.component.as_ptr(), stream.as_ptr()) };
+        if result != K_RESULT_OK && result != K_NOT_IMPLEMENTED {
+            return Err(VjError::AudioError(format!("VST3 plugin '{}' failed to save its state (result {})", self.class.name, result)));
+        }
+        let component = stream.into_data();
+
+        let controller = match &self.controller {
+            Some(controller) => {
+                let mut stream = MemoryStream::new(Vec::new());
+                // SAFETY: the stream outlives the call; controllers without state leave it empty
+                unsafe { (controller.vtable::<IEditControllerVtbl>().get_state)(controller.as_ptr(), stream.as_ptr()) };
+                stream.into_data()
+            }
+            None => Vec::new(),
+        };
+        Ok(Vst3PluginState { component, controller })
+    }
+
+    /// Restore state saved with `save_state`; the controller is brought in line with
+    /// the component as the SDK describes
+    pub fn load_state(&mut self, state: &Vst3PluginState) -> Result<(), VjError> {
+        if !state.component.is_empty() {
+            let mut stream = MemoryStream::new(state.component.clone());
+            // SAFETY: the stream outlives the calls
+            unsafe {
+                let result = (self.component.vtable::<IComponentVtbl>().set_state)(self.component.as_ptr(), stream.as_ptr());
+                if result != K_RESULT_OK {
+                    return Err(VjError::AudioError(format!("VST3 plugin '{}' rejected its saved state (result {})", self.class.name, result)));
+                }
+                if let Some(controller) = &self.controller {
+                    stream.rewind();
+                    (controller.vtable::<IEditControllerVtbl>().set_component_state)(controller.as_ptr(), stream.as_ptr());
+                }
+            }
+        }
+        if let (Some(controller), false) = (&self.controller, state.controller.is_empty()) {
+            let mut stream = MemoryStream::new(state.controller.clone());
+            // SAFETY: the stream outlives the call
+            unsafe { (controller.vtable::<IEditControllerVtbl>().set_state)(controller.as_ptr(), stream.as_ptr()) };
+        }
+        self.reload_parameter_values();
+        Ok(())
+    }
+
+    /// Follow `automation` from the next block on; it replaces earlier automation
+    pub fn set_automation(&mut self, param_id: u32, automation: ParameterAutomation) -> Result<(), VjError> {
+        let parameter = self.parameter_info(param_id)
+            .ok_or_else(|| VjError::AudioError(format!("Unknown VST3 parameter {}", param_id)))?;
+        if parameter.is_read_only() {
+            return Err(VjError::AudioError(format!("VST3 parameter '{}' is read-only", parameter.title)));
+        }
+        self.clear_automation(param_id);
+        if !automation.is_empty() {
+            self.automation.push((param_id, automation));
+        }
+        Ok(())
+    }
+
+    pub fn clear_automation(&mut self, param_id: u32) {
+        self.automation.retain(|(automated, _)| *automated != param_id);
+    }
+
+    pub fn automation(&self, param_id: u32) -> Option<&ParameterAutomation> {
+        self.automation.iter().find(|(automated, _)| *automated == param_id).map(|(_, automation)| automation)
+    }
+
+    /// Tempo grid automation and the plugin's process context follow
+    pub fn set_transport(&mut self, transport: &Transport) {
+        self.transport = transport.clone();
+    }
+
+    /// Engine frame the next `process` call starts at. Running as an engine processor
+    /// takes the position from the engine instead.
+    pub fn set_frame_position(&mut self, frame: u64) {
+        self.frame_position = frame;
+    }
+
+    /// Queue this block's automation points at their sample offsets; the plugin ramps
+    /// linearly between them
+    fn apply_automation(&mut self, frames: usize) {
+        let sample_rate = self.config.sample_rate;
+        let frames_per_beat = self.transport.frames_per_beat(sample_rate);
+        let start = self.transport.beat_at(self.frame_position, sample_rate);
+        let last = frames.saturating_sub(1);
+        let end = start + last as f64 / frames_per_beat;
+        for (param_id, automation) in &self.automation {
+            let (Some(first), Some(value)) = (automation.value_at(start), automation.value_at(end)) else {
+                continue;
+            };
+            self.input_changes.add(*param_id, 0, first);
+            for point in automation.points_between(start, end) {
+                let offset = ((point.beat - start) * frames_per_beat).round() as usize;
+                self.input_changes.add(*param_id, offset.min(last) as i32, point.value);
+            }
+            self.input_changes.add(*param_id, last as i32, value);
+            if let Some(&index) = self.parameter_index.get(param_id) {
+                self.parameter_values[index] = value;
+            }
+        }
+    }
+
+    fn push_event(&mut self, event_type: u16, payload: EventPayload) -> bool {
+        self.has_event_input && self.input_events.push(Event {
+            bus_index: 0,
+            sample_offset: 0,
+            ppq_position: 0.0,
+            flags: 0,
+            event_type,
+            payload,
+        })
+    }
+
+    /// Queue a note for the next block; velocity is 0..1. False if the plugin takes no notes.
+    pub fn note_on(&mut self, channel: u8, pitch: u8, velocity: f32) -> bool {
+        let note_on = NoteOnEvent {
+            channel: channel as i16,
+            pitch: pitch as i16,
+            tuning: 0.0,
+            velocity: velocity.clamp(0.0, 1.0),
+            length: 0,
+            note_id: -1,
+        };
+        self.push_event(K_NOTE_ON_EVENT, EventPayload { note_on })
+    }
+
+    pub fn note_off(&mut self, channel: u8, pitch: u8, velocity: f32) -> bool {
+        let note_off = NoteOffEvent {
+            channel: channel as i16,
+            pitch: pitch as i16,
+            velocity: velocity.clamp(0.0, 1.0),
+            note_id: -1,
+            tuning: 0.0,
+        };
+        self.push_event(K_NOTE_OFF_EVENT, EventPayload { note_off })
+    }
+
+    /// Queue a raw MIDI message; notes and poly pressure are forwarded
+    pub fn send_midi(&mut self, message: &[u8]) -> bool {
+        let [status, pitch, value, ..] = *message else {
+            return false;
+        };
+        let channel = status & 0x0F;
+        let value = value as f32 / 127.0;
+        match status & 0xF0 {
+            0x90 if value > 0.0 => self.note_on(channel, pitch, value),
+            0x80 | 0x90 => self.note_off(channel, pitch, value),
+            0xA0 => {
+                let poly_pressure = PolyPressureEvent { channel: channel as i16, pitch: pitch as i16, pressure: value, note_id: -1 };
+                self.push_event(K_POLY_PRESSURE_EVENT, EventPayload { poly_pressure })
+            }
+            _ => false,
+        }
+    }
+
+    /// Run one block already loaded into the input buffers
+    fn process_block(&mut self, frames: usize) -> Result<(), VjError> {
+        self.apply_automation(frames);
+
+        let sample_rate = self.config.sample_rate;
+        let beat = self.transport.beat_at(self.frame_position, sample_rate);
+        let beats_per_bar = self.transport.beats_per_bar.max(1) as f64;
+        self.context.state = K_PLAYING | K_CONT_TIME_VALID | K_TEMPO_VALID | K_TIME_SIG_VALID
+            | K_PROJECT_TIME_MUSIC_VALID | K_BAR_POSITION_VALID;
+        self.context.sample_rate = sample_rate as f64;
+        self.context.project_time_samples = self.frame_position as i64 - self.transport.origin_frame;
+        self.context.continous_time_samples = self.frame_position as i64;
+        self.context.project_time_music = beat;
+        self.context.bar_position_music = (beat / beats_per_bar).floor() * beats_per_bar;
+        self.context.tempo = self.transport.bpm as f64;
+        self.context.time_sig_numerator = beats_per_bar as i32;
+        self.context.time_sig_denominator = 4;
+
+        let mut data = ProcessData {
+            process_mode: K_REALTIME,
+            symbolic_sample_size: K_SAMPLE_32,
+            num_samples: frames as i32,
+            num_inputs: self.input_bus_buffers.len() as i32,
+            num_outputs: self.output_bus_buffers.len() as i32,
+            inputs: self.input_bus_buffers.as_mut_ptr(),
+            outputs: self.output_bus_buffers.as_mut_ptr(),
+            input_parameter_changes: self.input_changes.as_ptr(),
+            output_parameter_changes: self.output_changes.as_ptr(),
+            input_events: if self.has_event_input { self.input_events.as_ptr() } else { std::ptr::null_mut() },
+            output_events: self.output_events.as_ptr(),
+            process_context: &mut *self.context,
+        };
+        // SAFETY: every buffer holds at least `block_size` frames and frames <= block_size
+        let result = unsafe { (self.processor.vtable::<IAudioProcessorVtbl>().process)(self.processor.as_ptr(), &mut data) };
+        self.frame_position += frames as u64;
+
+        for queue in self.output_changes.queues() {
+            if let (Some(&index), Some(&(_, value))) = (self.parameter_index.get(&queue.id()), queue.points().last()) {
+                let old_value = std::mem::replace(&mut self.parameter_values[index], value);
+                if old_value != value {
+                    let _ = self.changes.push(Vst3ParameterChange { param_id: queue.id(), old_value, new_value: value });
+                }
+            }
+        }
+        self.input_changes.clear();
+        self.output_changes.clear();
+        self.input_events.clear();
+        self.output_events.clear();
+
+        if result != K_RESULT_OK {
+            return Err(VjError::AudioError(format!("VST3 plugin '{}' failed to process (result {})", self.class.name, result)));
+        }
+        Ok(())
+    }
+
+    /// Process interleaved audio with `num_channels` channels. Missing input is silence;
+    /// output replaces what was in `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> Result<(), Box<dyn std::error::Error>> {
+        let channels = self.config.num_channels.max(1);
+        Ok(self.render(input, channels, output, channels, false)?)
+    }
+
+    fn render(&mut self, input: &[f32], input_channels: usize, output: &mut [f32], output_channels: usize, add: bool) -> Result<(), VjError> {
+        let frames = output.len() / output_channels.max(1);
+        let mut start = 0;
+        while start < frames {
+            let count = (frames - start).min(self.config.block_size);
+
+            for (index, bus) in self.input_buffers.iter_mut().enumerate() {
+                let bus_channels = bus.channels.len();
+                for (bus_channel, samples) in bus.channels.iter_mut().enumerate() {
+                    for (frame, sample) in samples[..count].iter_mut().enumerate() {
+                        let position = (start + frame) * input_channels + bus_channel % input_channels.max(1);
+                        *sample = match input.get(position) {
+                            Some(&value) if index == 0 && input_channels > 0 && bus_channels > 0 => value,
+                            _ => 0.0,
+                        };
+                    }
+                }
+            }
+            for bus in &mut self.output_buffers {
+                for samples in &mut bus.channels {
+                    samples[..count].fill(0.0);
+                }
+            }
+
+            self.process_block(count)?;
+
+            let main = self.output_buffers.first().filter(|bus| !bus.channels.is_empty());
+            for frame in 0..count {
+                for channel in 0..output_channels {
+                    let sample = main.map(|bus| bus.channels[channel % bus.channels.len()][frame]).unwrap_or(0.0);
+                    let target = &mut output[(start + frame) * output_channels + channel];
+                    if add {
+                        *target += sample;
+                    } else {
+                        *target = sample;
+                    }
+                }
+            }
+            start += count;
+        }
+        if !add {
+            output[frames * output_channels..].fill(0.0);
+        }
+        Ok(())
+    }
+}
+
+impl AudioProcessor for Vst3PluginInstance {
+    fn process(&mut self, context: &AudioContext, input: &[f32], output: &mut [f32]) {
+        self.frame_position = context.frame_position;
+        let samples = (context.frames * context.channels).min(output.len());
+        if let Err(e) = self.render(input, context.input_channels, &mut output[..samples], context.channels, true) {
+            bevy::log::error!("❌ {}", e);
+        }
+    }
+
+    fn latency(&self) -> usize {
+        self.latency_samples() as usize
+    }
+}
+
+/// Runs a plugin inside the audio-rate graph with one mono port per channel. The
+/// plugin's latency is reported to the graph, which delays parallel paths to match.
+pub struct Vst3AudioNode {
+    plugin: Vst3PluginInstance,
+    channels: usize,
+    input: Vec<f32>,
+    output: Vec<f32>,
+}
+
+impl Vst3AudioNode {
+    pub fn new(plugin: Vst3PluginInstance) -> Self {
+        let channels = plugin.config.num_channels.clamp(1, crate::audio::MAX_NODE_PORTS);
+        Self {
+            plugin,
+            channels,
+            input: vec![0.0; AUDIO_BLOCK_SIZE * channels],
+            output: vec![0.0; AUDIO_BLOCK_SIZE * channels],
+        }
+    }
+}
+
+impl AudioNode for Vst3AudioNode {
+    fn name(&self) -> &str {
+        &self.plugin.class.name
+    }
+
+    fn input_count(&self) -> usize {
+        if self.plugin.input_buses.is_empty() { 0 } else { self.channels }
+    }
+
+    fn output_count(&self) -> usize {
+        self.channels
+    }
+
+    fn latency(&self) -> usize {
+        self.plugin.latency_samples() as usize
+    }
+
+    fn process(&mut self, context: &BlockContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let channels = self.channels;
+        for (channel, input) in inputs.iter().enumerate() {
+            for (frame, &sample) in input.iter().enumerate() {
+                self.input[frame * channels + channel] = sample;
+            }
+        }
+        self.plugin.frame_position = context.frame_position;
+        if let Err(e) = self.plugin.render(&self.input, channels, &mut self.output, channels, false) {
+            bevy::log::error!("❌ {}", e);
+        }
+        for (channel, output) in outputs.iter_mut().enumerate() {
+            for (frame, sample) in output.iter_mut().enumerate() {
+                *sample = self.output[frame * channels + channel];
+            }
+        }
+    }
+}
+
+impl Drop for Vst3PluginInstance {
+    fn drop(&mut self) {
+        // SAFETY: shutting down in the order the SDK documents; objects are still live
+        unsafe {
+            (self.processor.vtable::<IAudioProcessorVtbl>().set_processing)(self.processor.as_ptr(), 0);
+            let component: &IComponentVtbl = self.component.vtable();
+            (component.set_active)(self.component.as_ptr(), 0);
+
+            if let Some((component_point, controller_point)) = &self.connections {
+                (component_point.vtable::<IConnectionPointVtbl>().disconnect)(component_point.as_ptr(), controller_point.as_ptr());
+                (controller_point.vtable::<IConnectionPointVtbl>().disconnect)(controller_point.as_ptr(), component_point.as_ptr());
+            }
+            if let Some(controller) = &self.controller {
+                let vtbl: &IEditControllerVtbl = controller.vtable();
+                (vtbl.set_component_handler)(controller.as_ptr(), std::ptr::null_mut());
+                if self.separate_controller {
+                    (vtbl.terminate)(controller.as_ptr());
+                }
+            }
+            (component.terminate)(self.component.as_ptr());
+        }
+    }
+}
+
+/// Create and initialize the controller class a component names
+fn create_controller(module: &Vst3Module, component: &ComPtr, host: &mut HostApplication) -> Option<ComPtr> {
+    let mut cid: Tuid = [0; 16];
+    // SAFETY: the component writes the 16-byte class id
+    unsafe {
+        let vtbl: &IComponentVtbl = component.vtable();
+        if (vtbl.get_controller_class_id)(component.as_ptr(), &mut cid) != K_RESULT_OK || cid == [0; 16] {
+            return None;
+        }
+    }
+    let controller = module.create(&cid, &IEDIT_CONTROLLER_IID)?;
+    // SAFETY: the host context outlives the controller
+    unsafe {
+        let vtbl: &IEditControllerVtbl = controller.vtable();
+        if (vtbl.initialize)(controller.as_ptr(), host.as_ptr()) != K_RESULT_OK {
+            return None;
+        }
+    }
+    Some(controller)
+}
+
+/// Connect a separate component and controller so they can message each other
+fn connect(component: &ComPtr, controller: &ComPtr) -> Option<(ComPtr, ComPtr)> {
+    let component_point = component.query(&ICONNECTION_POINT_IID)?;
+    let controller_point = controller.query(&ICONNECTION_POINT_IID)?;
+    // SAFETY: both connection points are live
+    unsafe {
+        (component_point.vtable::<IConnectionPointVtbl>().connect)(component_point.as_ptr(), controller_point.as_ptr());
+        (controller_point.vtable::<IConnectionPointVtbl>().connect)(controller_point.as_ptr(), component_point.as_ptr());
+    }
+    Some((component_point, controller_point))
 }
 
 /// VST3 plugin processor
 pub struct Vst3PluginProcessor {
     config: Vst3PluginConfig,
     loaded_plugins: HashMap<String, Vst3PluginInstance>,
+    listeners: HashMap<String, Vst3ParameterListener>,
 }
 
 impl Vst3PluginProcessor {
     pub fn new() -> Self {
         Self {
-            config: Vst3PluginConfig {
-                plugin_path: String::new(),
-                sample_rate: 44100.0,
-                block_size: 512,
-                num_channels: 2,
-            },
+            config: Vst3PluginConfig::default(),
             loaded_plugins: HashMap::new(),
+            listeners: HashMap::new(),
         }
     }
 
-    /// Load VST3 plugin
+    /// Load VST3 plugin at the configured sample rate, block size and channel count
     pub fn load_plugin(&mut self, id: &str, plugin_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let instance = Vst3PluginInstance::new(plugin_path)?;
+        let config = Vst3PluginConfig { plugin_path: plugin_path.to_string(), ..self.config.clone() };
+        self.insert_plugin(id, Vst3PluginInstance::load(&config)?);
+        Ok(())
+    }
+
+    fn insert_plugin(&mut self, id: &str, mut instance: Vst3PluginInstance) {
+        if let Some(listener) = instance.parameter_listener() {
+            self.listeners.insert(id.to_string(), listener);
+        }
         self.loaded_plugins.insert(id.to_string(), instance);
+    }
+
+    /// Parameter changes the plugins made since the last call, by plugin id
+    pub fn poll_parameter_changes(&mut self) -> Vec<(String, Vst3ParameterChange)> {
+        for plugin in self.loaded_plugins.values_mut() {
+            plugin.sync_controller_edits();
+        }
+        let mut changes = Vec::new();
+        for (id, listener) in self.listeners.iter_mut() {
+            changes.extend(listener.drain().map(|change| (id.clone(), change)));
+        }
+        changes
+    }
+
+    /// Every plugin's configuration, state and automation, for saved node parameters
+    pub fn save_state(&mut self) -> Result<HashMap<String, Value>, VjError> {
+        let mut plugins = serde_json::Map::new();
+        for (id, plugin) in self.loaded_plugins.iter_mut() {
+            let state = plugin.save_state()?.to_value();
+            let automation: HashMap<String, &ParameterAutomation> = plugin.automation.iter()
+                .map(|(param_id, automation)| (param_id.to_string(), automation))
+                .collect();
+            plugins.insert(id.clone(), serde_json::json!({
+                "config": plugin.config,
+                "state": state,
+                "automation": automation,
+            }));
+        }
+
+        let mut parameters = HashMap::new();
+        parameters.insert("config".to_string(), serde_json::to_value(&self.config)
+            .map_err(|e| VjError::ConfigError(e.to_string()))?);
+        parameters.insert("plugins".to_string(), Value::Object(plugins));
+        Ok(parameters)
+    }
+
+    /// Reload the plugins described by `save_state`, replacing any loaded now
+    pub fn restore_state(&mut self, parameters: &HashMap<String, Value>) -> Result<(), VjError> {
+        if let Some(config) = parameters.get("config") {
+            self.config = serde_json::from_value(config.clone())
+                .map_err(|e| VjError::ConfigError(format!("Invalid VST3 configuration: {}", e)))?;
+        }
+        self.loaded_plugins.clear();
+        self.listeners.clear();
+
+        let plugins = parameters.get("plugins").and_then(Value::as_object).into_iter().flatten();
+        for (id, saved) in plugins {
+            let config: Vst3PluginConfig = serde_json::from_value(saved.get("config").cloned().unwrap_or_default())
+                .map_err(|e| VjError::ConfigError(format!("Invalid configuration for VST3 plugin '{}': {}", id, e)))?;
+            let mut instance = Vst3PluginInstance::load(&config)?;
+            if let Some(state) = saved.get("state") {
+                instance.load_state(&Vst3PluginState::from_value(state)?)?;
+            }
+            let automation: HashMap<String, ParameterAutomation> = saved.get("automation")
+                .map(|automation| serde_json::from_value(automation.clone()))
+                .transpose()
+                .map_err(|e| VjError::ConfigError(format!("Invalid automation for VST3 plugin '{}': {}", id, e)))?
+                .unwrap_or_default();
+            for (param_id, automation) in automation {
+                if let Ok(param_id) = param_id.parse() {
+                    instance.set_automation(param_id, automation)?;
+                }
+            }
+            self.insert_plugin(id, instance);
+        }
         Ok(())
     }
 
@@ -71,41 +1344,115 @@ impl Vst3PluginProcessor {
         }
     }
 
-    /// Configure processor
+    pub fn plugin(&self, plugin_id: &str) -> Option<&Vst3PluginInstance> {
+        self.loaded_plugins.get(plugin_id)
+    }
+
+    pub fn plugin_mut(&mut self, plugin_id: &str) -> Option<&mut Vst3PluginInstance> {
+        self.loaded_plugins.get_mut(plugin_id)
+    }
+
+    /// Configure processor; applies to plugins loaded afterwards
     pub fn configure(&mut self, config: Vst3PluginConfig) {
         self.config = config;
     }
+
+    /// Share the transport with every loaded plugin
+    pub fn set_transport(&mut self, transport: &Transport) {
+        for plugin in self.loaded_plugins.values_mut() {
+            plugin.set_transport(transport);
+        }
+    }
 }
 
-/// VST3 plugin instance wrapper
-pub struct Vst3PluginInstance {
-    plugin_path: String,
-    parameters: HashMap<u32, f32>,
+impl Default for Vst3PluginProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl Vst3PluginInstance {
-    pub fn new(plugin_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        // Placeholder - would load actual VST3 plugin
-        Ok(Self {
-            plugin_path: plugin_path.to_string(),
-            parameters: HashMap::new(),
-        })
+impl PluginHost for Vst3PluginProcessor {
+    fn format(&self) -> PluginFormat {
+        PluginFormat::Vst3
     }
 
-    pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> Result<(), Box<dyn std::error::Error>> {
-        // Placeholder - would process through actual VST3 plugin
-        // For now, just copy input to output
-        output.copy_from_slice(input);
-        Ok(())
+    fn set_setup(&mut self, setup: PluginSetup) {
+        self.config.sample_rate = setup.sample_rate;
+        self.config.block_size = setup.block_size;
+        self.config.num_channels = setup.num_channels;
     }
 
-    pub fn set_parameter(&mut self, param_id: u32, value: f32) -> Result<(), Box<dyn std::error::Error>> {
-        self.parameters.insert(param_id, value);
+    fn load(&mut self, instance_id: &str, descriptor: &PluginDescriptor) -> Result<(), VjError> {
+        let config = Vst3PluginConfig {
+            plugin_path: descriptor.path.to_string_lossy().into_owned(),
+            // Without a name, the module's first audio class
+            class_name: (!descriptor.name.is_empty()).then(|| descriptor.name.clone()),
+            ..self.config.clone()
+        };
+        let instance = Vst3PluginInstance::load(&config)?;
+        self.insert_plugin(instance_id, instance);
         Ok(())
     }
 
-    pub fn get_parameter(&self, param_id: u32) -> Result<f32, Box<dyn std::error::Error>> {
-        self.parameters.get(&param_id).copied().ok_or_else(|| "Parameter not found".into())
+    fn unload(&mut self, instance_id: &str) -> bool {
+        self.listeners.remove(instance_id);
+        self.loaded_plugins.remove(instance_id).is_some()
+    }
+
+    fn instance_ids(&self) -> Vec<String> {
+        self.loaded_plugins.keys().cloned().collect()
+    }
+
+    fn parameters(&self, instance_id: &str) -> Result<Vec<PluginParameter>, VjError> {
+        let plugin = self.plugin(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Vst3, instance_id))?;
+        Ok(plugin.parameters().iter()
+            .map(|parameter| PluginParameter {
+                id: parameter.id,
+                name: parameter.title.clone(),
+                units: parameter.units.clone(),
+                default_value: parameter.default_value,
+                min: parameter.min,
+                max: parameter.max,
+                step_count: parameter.step_count.max(0) as u32,
+                read_only: parameter.is_read_only(),
+            })
+            .collect())
+    }
+
+    fn set_parameter(&mut self, instance_id: &str, param_id: u32, value: f32) -> Result<(), VjError> {
+        let plugin = self.plugin_mut(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Vst3, instance_id))?;
+        plugin.set_parameter_normalized(param_id, value as f64)
+    }
+
+    fn get_parameter(&self, instance_id: &str, param_id: u32) -> Result<f32, VjError> {
+        let plugin = self.plugin(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Vst3, instance_id))?;
+        plugin.get_parameter(param_id).map_err(|e| VjError::AudioError(e.to_string()))
+    }
+
+    fn send_midi(&mut self, instance_id: &str, message: &[u8]) -> Result<bool, VjError> {
+        let plugin = self.plugin_mut(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Vst3, instance_id))?;
+        Ok(plugin.send_midi(message))
+    }
+
+    fn process(&mut self, instance_id: &str, input: &[f32], output: &mut [f32]) -> Result<(), VjError> {
+        let plugin = self.plugin_mut(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Vst3, instance_id))?;
+        let channels = plugin.config.num_channels.max(1);
+        plugin.render(input, channels, output, channels, false)
+    }
+
+    fn latency(&self, instance_id: &str) -> Result<usize, VjError> {
+        let plugin = self.plugin(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Vst3, instance_id))?;
+        Ok(plugin.latency_samples() as usize)
+    }
+
+    fn instance_state(&mut self, instance_id: &str) -> Result<Value, VjError> {
+        let plugin = self.plugin_mut(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Vst3, instance_id))?;
+        Ok(plugin.save_state()?.to_value())
+    }
+
+    fn restore_instance_state(&mut self, instance_id: &str, state: &Value) -> Result<(), VjError> {
+        let plugin = self.plugin_mut(instance_id).ok_or_else(|| unknown_instance(PluginFormat::Vst3, instance_id))?;
+        plugin.load_state(&Vst3PluginState::from_value(state)?)
     }
 }
 
@@ -114,6 +1461,8 @@ pub struct Vst3PluginNode {
     pub id: String,
     pub name: String,
     processor: Vst3PluginProcessor,
+    /// Runs the node's plugins out of process once enabled
+    sandbox: Option<SandboxedPluginHost>,
 }
 
 impl Vst3PluginNode {
@@ -122,17 +1471,38 @@ impl Vst3PluginNode {
             id,
             name,
             processor: Vst3PluginProcessor::new(),
+            sandbox: None,
         }
     }
 
+    /// Load plugins from now on into sandbox processes, so a crashing plugin is bypassed
+    /// and restarted instead of taking the show down. Sandboxed plugins don't follow
+    /// transport automation.
+    pub fn enable_sandbox(&mut self, config: SandboxConfig) {
+        let mut sandbox = SandboxedPluginHost::new(PluginFormat::Vst3, config);
+        sandbox.set_setup(self.processor.config.setup());
+        self.sandbox = Some(sandbox);
+    }
+
+    pub fn sandbox(&self) -> Option<&SandboxedPluginHost> {
+        self.sandbox.as_ref()
+    }
+
+    pub fn sandbox_mut(&mut self) -> Option<&mut SandboxedPluginHost> {
+        self.sandbox.as_mut()
+    }
+
     /// Load and process VST3 plugin
     pub fn process_audio(&mut self, plugin_id: &str, input: &[f32]) -> Result<HashMap<String, Value>, Box<dyn std::error::Error>> {
         let mut output = vec![0.0f32; input.len()];
-        self.processor.process_audio(plugin_id, input, &mut output)?;
+        match &mut self.sandbox {
+            Some(sandbox) => sandbox.process(plugin_id, input, &mut output)?,
+            None => self.processor.process_audio(plugin_id, input, &mut output)?,
+        }
 
         let mut result = HashMap::new();
         result.insert("audio_output".to_string(), Value::Array(
-            output.iter().map(|&s| Value::Number(serde_json::Number::from_f64(s as f64).unwrap())).collect()
+            output.iter().map(|&s| serde_json::json!(s)).collect()
         ));
         result.insert("plugin_id".to_string(), Value::String(plugin_id.to_string()));
 
@@ -141,16 +1511,179 @@ impl Vst3PluginNode {
 
     /// Load VST3 plugin
     pub fn load_plugin(&mut self, id: &str, plugin_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        self.processor.load_plugin(id, plugin_path)
+        match &mut self.sandbox {
+            Some(sandbox) => {
+                let config = Vst3PluginConfig { plugin_path: plugin_path.to_string(), ..self.processor.config.clone() };
+                Ok(sandbox.load(id, &sandbox_descriptor(id, &config))?)
+            }
+            None => self.processor.load_plugin(id, plugin_path),
+        }
     }
 
     /// Set plugin parameter
     pub fn set_parameter(&mut self, plugin_id: &str, param_id: u32, value: f32) -> Result<(), Box<dyn std::error::Error>> {
-        self.processor.set_parameter(plugin_id, param_id, value)
+        match &mut self.sandbox {
+            Some(sandbox) => Ok(sandbox.set_parameter(plugin_id, param_id, value)?),
+            None => self.processor.set_parameter(plugin_id, param_id, value),
+        }
+    }
+
+    /// Queue a raw MIDI message for a plugin's next block
+    pub fn send_midi(&mut self, plugin_id: &str, message: &[u8]) -> Result<bool, Box<dyn std::error::Error>> {
+        if let Some(sandbox) = &mut self.sandbox {
+            return Ok(sandbox.send_midi(plugin_id, message)?);
+        }
+        let plugin = self.processor.plugin_mut(plugin_id)
+            .ok_or_else(|| format!("Plugin '{}' not found", plugin_id))?;
+        Ok(plugin.send_midi(message))
     }
 
     /// Configure node
     pub fn configure(&mut self, config: Vst3PluginConfig) {
+        if let Some(sandbox) = &mut self.sandbox {
+            sandbox.set_setup(config.setup());
+        }
         self.processor.configure(config);
     }
-}
\ No newline at end of file
+
+    pub fn processor(&self) -> &Vst3PluginProcessor {
+        &self.processor
+    }
+
+    pub fn processor_mut(&mut self) -> &mut Vst3PluginProcessor {
+        &mut self.processor
+    }
+
+    /// Parameters to store in `SavedNodeData`, including each plugin's state
+    pub fn saved_parameters(&mut self) -> Result<HashMap<String, Value>, VjError> {
+        let Some(sandbox) = &mut self.sandbox else {
+            return self.processor.save_state();
+        };
+
+        // Same layout as in-process plugins, flagged so they're sandboxed again on restore
+        let mut parameters = self.processor.save_state()?;
+        let mut plugins = serde_json::Map::new();
+        for id in sandbox.instance_ids() {
+            let Some(descriptor) = sandbox.descriptor(&id) else { continue };
+            let config = Vst3PluginConfig {
+                plugin_path: descriptor.path.to_string_lossy().into_owned(),
+                class_name: (!descriptor.name.is_empty()).then(|| descriptor.name.clone()),
+                ..self.processor.config.clone()
+            };
+            let state = sandbox.instance_state(&id)?;
+            plugins.insert(id, serde_json::json!({ "config": config, "state": state }));
+        }
+        parameters.insert("plugins".to_string(), Value::Object(plugins));
+        parameters.insert("sandboxed".to_string(), Value::Bool(true));
+        Ok(parameters)
+    }
+
+    /// Reload plugins and their state from a saved scene
+    pub fn restore(&mut self, saved: &SavedNodeData) -> Result<(), VjError> {
+        if saved.parameters.get("sandboxed").and_then(Value::as_bool).unwrap_or(false) {
+            return self.restore_sandboxed(&saved.parameters);
+        }
+        self.sandbox = None;
+        self.processor.restore_state(&saved.parameters)?;
+        bevy::log::info!("🎛️ Restored VST3 node '{}' with {} plugin(s)", self.name, self.processor.loaded_plugins.len());
+        Ok(())
+    }
+
+    fn restore_sandboxed(&mut self, parameters: &HashMap<String, Value>) -> Result<(), VjError> {
+        let mut in_process = parameters.clone();
+        let plugins = in_process.insert("plugins".to_string(), Value::Object(Default::default()));
+        self.processor.restore_state(&in_process)?;
+        let config = self.sandbox.as_ref().map(|sandbox| sandbox.config().clone()).unwrap_or_default();
+        self.enable_sandbox(config);
+        let sandbox = self.sandbox.as_mut().expect("sandbox was just enabled");
+
+        let plugins = plugins.as_ref().and_then(Value::as_object).into_iter().flatten();
+        for (id, saved) in plugins {
+            let config: Vst3PluginConfig = serde_json::from_value(saved.get("config").cloned().unwrap_or_default())
+                .map_err(|e| VjError::ConfigError(format!("Invalid configuration for VST3 plugin '{}': {}", id, e)))?;
+            sandbox.set_setup(config.setup());
+            sandbox.load(id, &sandbox_descriptor(id, &config))?;
+            if let Some(state) = saved.get("state") {
+                sandbox.restore_instance_state(id, state)?;
+            }
+        }
+        sandbox.set_setup(self.processor.config.setup());
+        bevy::log::info!("🎛️ Restored VST3 node '{}' with {} sandboxed plugin(s)", self.name, sandbox.instance_ids().len());
+        Ok(())
+    }
+
+    /// Crashes and restarts of sandboxed plugins since the last call
+    pub fn sandbox_events(&mut self) -> Vec<VjEvent> {
+        self.sandbox.as_mut().map(|sandbox| sandbox.drain_events()).unwrap_or_default().into_iter()
+            .map(|event_type| VjEvent::AudioEvent { event_type })
+            .collect()
+    }
+
+    /// Changes the plugins made since the last call, as `ParameterChanged` events named
+    /// `<plugin id>.<parameter title>` with normalized values
+    pub fn parameter_events(&mut self, node_id: NodeId) -> Vec<VjEvent> {
+        self.processor.poll_parameter_changes().into_iter()
+            .map(|(plugin_id, change)| {
+                let title = self.processor.plugin(&plugin_id)
+                    .and_then(|plugin| plugin.parameter_info(change.param_id))
+                    .map(|parameter| parameter.title.clone())
+                    .unwrap_or_else(|| change.param_id.to_string());
+                VjEvent::ParameterChanged {
+                    node_id,
+                    parameter: format!("{}.{}", plugin_id, title),
+                    old_value: change.old_value.to_string(),
+                    new_value: change.new_value.to_string(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Hosts a graph node's VST3 plugins on an entity, where `update_vst3_nodes` keeps
+/// them on the transport and publishes what they and their sandboxes report
+#[derive(Component)]
+pub struct Vst3NodeInstance {
+    pub node_id: NodeId,
+    node: Mutex<Vst3PluginNode>,
+}
+
+impl Vst3NodeInstance {
+    pub fn new(node_id: NodeId, node: Vst3PluginNode) -> Self {
+        Self { node_id, node: Mutex::new(node) }
+    }
+
+    pub fn node_mut(&mut self) -> &mut Vst3PluginNode {
+        self.node.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Share the transport with every VST3 node and publish their parameter changes and
+/// sandbox crashes and restarts
+pub(crate) fn update_vst3_nodes(
+    transport: Option<Res<Transport>>,
+    mut nodes: Query<&mut Vst3NodeInstance>,
+    mut vj_events: MessageWriter<VjEvent>,
+) {
+    for mut instance in nodes.iter_mut() {
+        let node_id = instance.node_id;
+        let node = instance.node_mut();
+        if let Some(transport) = &transport {
+            node.processor_mut().set_transport(transport);
+        }
+        vj_events.write_batch(node.parameter_events(node_id));
+        vj_events.write_batch(node.sandbox_events());
+    }
+}
+
+/// Descriptor for loading a configured plugin into a sandbox
+fn sandbox_descriptor(id: &str, config: &Vst3PluginConfig) -> PluginDescriptor {
+    PluginDescriptor {
+        format: PluginFormat::Vst3,
+        id: id.to_string(),
+        name: config.class_name.clone().unwrap_or_default(),
+        vendor: String::new(),
+        version: String::new(),
+        path: PathBuf::from(&config.plugin_path),
+        is_instrument: false,
+    }
+}
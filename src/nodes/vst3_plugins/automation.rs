@@ -0,0 +1,65 @@
+//! Beat-based parameter automation for hosted plugins
+
+use serde::{Deserialize, Serialize};
+
+/// Normalized value at a transport beat
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AutomationPoint {
+    pub beat: f64,
+    pub value: f64,
+}
+
+/// Normalized parameter values over transport beats, linear between points and held
+/// before the first and after the last
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParameterAutomation {
+    points: Vec<AutomationPoint>,
+}
+
+impl ParameterAutomation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_point(mut self, beat: f64, value: f64) -> Self {
+        self.add_point(beat, value);
+        self
+    }
+
+    /// Insert a point in beat order, replacing one at the same beat
+    pub fn add_point(&mut self, beat: f64, value: f64) {
+        let point = AutomationPoint { beat, value: value.clamp(0.0, 1.0) };
+        let position = self.points.partition_point(|existing| existing.beat < beat);
+        match self.points.get_mut(position) {
+            Some(existing) if existing.beat == beat => *existing = point,
+            _ => self.points.insert(position, point),
+        }
+    }
+
+    pub fn points(&self) -> &[AutomationPoint] {
+        &self.points
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn value_at(&self, beat: f64) -> Option<f64> {
+        let position = self.points.partition_point(|point| point.beat <= beat);
+        match (position.checked_sub(1).map(|index| self.points[index]), self.points.get(position).copied()) {
+            (Some(before), Some(after)) => {
+                let amount = (beat - before.beat) / (after.beat - before.beat);
+                Some(before.value + (after.value - before.value) * amount)
+            }
+            (Some(point), None) | (None, Some(point)) => Some(point.value),
+            (None, None) => None,
+        }
+    }
+
+    /// Points strictly between two beats
+    pub fn points_between(&self, start: f64, end: f64) -> &[AutomationPoint] {
+        let first = self.points.partition_point(|point| point.beat <= start);
+        let last = self.points.partition_point(|point| point.beat < end);
+        &self.points[first..last.max(first)]
+    }
+}
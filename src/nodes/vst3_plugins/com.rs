@@ -0,0 +1,492 @@
+//! VST3 binary interface
+//!
+//! The subset of the VST3 COM-style interfaces the host uses, laid out as in the SDK
+//! headers. Interfaces are structs holding a vtable pointer; every vtable starts with
+//! the `FUnknown` methods.
+
+#![allow(non_snake_case, dead_code)]
+
+use std::ffi::{c_char, c_void};
+
+pub type TResult = i32;
+pub type Tuid = [u8; 16];
+pub type ParamId = u32;
+pub type ParamValue = f64;
+pub type SpeakerArrangement = u64;
+pub type String128 = [u16; 128];
+
+pub const K_RESULT_OK: TResult = 0;
+pub const K_RESULT_FALSE: TResult = 1;
+pub const K_INVALID_ARGUMENT: TResult = 2;
+pub const K_NOT_IMPLEMENTED: TResult = 3;
+pub const K_NO_INTERFACE: TResult = -1;
+
+pub const K_AUDIO: i32 = 0;
+pub const K_EVENT: i32 = 1;
+pub const K_INPUT: i32 = 0;
+pub const K_OUTPUT: i32 = 1;
+pub const K_MAIN_BUS: i32 = 0;
+
+pub const K_REALTIME: i32 = 0;
+pub const K_SAMPLE_32: i32 = 0;
+
+pub const K_NOTE_ON_EVENT: u16 = 0;
+pub const K_NOTE_OFF_EVENT: u16 = 1;
+pub const K_POLY_PRESSURE_EVENT: u16 = 3;
+
+pub const K_SPEAKER_MONO: SpeakerArrangement = 1 << 19;
+
+pub const K_PLAYING: u32 = 1 << 1;
+pub const K_PROJECT_TIME_MUSIC_VALID: u32 = 1 << 9;
+pub const K_TEMPO_VALID: u32 = 1 << 10;
+pub const K_BAR_POSITION_VALID: u32 = 1 << 11;
+pub const K_TIME_SIG_VALID: u32 = 1 << 13;
+pub const K_CONT_TIME_VALID: u32 = 1 << 17;
+
+pub const K_PARAM_VALUES_CHANGED: i32 = 1 << 2;
+pub const K_LATENCY_CHANGED: i32 = 1 << 3;
+
+pub const K_IB_SEEK_SET: i32 = 0;
+pub const K_IB_SEEK_CUR: i32 = 1;
+pub const K_IB_SEEK_END: i32 = 2;
+
+/// Class category of audio processors in a factory
+pub const AUDIO_MODULE_CLASS: &str = "Audio Module Class";
+
+/// Byte order of `INLINE_UID` on platforms without COM compatibility
+const fn uid(a: u32, b: u32, c: u32, d: u32) -> Tuid {
+    let (a, b, c, d) = (a.to_be_bytes(), b.to_be_bytes(), c.to_be_bytes(), d.to_be_bytes());
+    [
+        a[0], a[1], a[2], a[3], b[0], b[1], b[2], b[3],
+        c[0], c[1], c[2], c[3], d[0], d[1], d[2], d[3],
+    ]
+}
+
+/// Windows builds of the SDK lay the first three fields out as a COM GUID
+#[cfg(windows)]
+const fn iid(a: u32, b: u32, c: u32, d: u32) -> Tuid {
+    let mut id = uid(a, b, c, d);
+    let swapped = [id[3], id[2], id[1], id[0], id[5], id[4], id[7], id[6]];
+    let mut index = 0;
+    while index < 8 {
+        id[index] = swapped[index];
+        index += 1;
+    }
+    id
+}
+
+#[cfg(not(windows))]
+const fn iid(a: u32, b: u32, c: u32, d: u32) -> Tuid {
+    uid(a, b, c, d)
+}
+
+pub const FUNKNOWN_IID: Tuid = iid(0x0000_0000, 0x0000_0000, 0xC000_0000, 0x0000_0046);
+pub const IPLUGIN_FACTORY_IID: Tuid = iid(0x7A4D_811C, 0x5211_4A1F, 0xAED9_D2EE, 0x0B43_BF9F);
+pub const IPLUGIN_FACTORY2_IID: Tuid = iid(0x0007_B650, 0xF24B_4C0B, 0xA464_EDB9, 0xF00B_2ABB);
+pub const ICOMPONENT_IID: Tuid = iid(0xE831_FF31, 0xF2D5_4301, 0x928E_BBEE, 0x2569_7802);
+pub const IAUDIO_PROCESSOR_IID: Tuid = iid(0x4204_3F99, 0xB7DA_453C, 0xA569_E79D, 0x9AAE_C33D);
+pub const IEDIT_CONTROLLER_IID: Tuid = iid(0xDCD7_BBE3, 0x7742_448D, 0xA874_AACC, 0x979C_759E);
+pub const ICONNECTION_POINT_IID: Tuid = iid(0x70A4_156F, 0x6E6E_4026, 0x9891_48BF, 0xAA60_D8D1);
+pub const ICOMPONENT_HANDLER_IID: Tuid = iid(0x93A0_BEA3, 0x0BD0_45DB, 0x8E89_0B0C, 0xC1E4_6AC6);
+pub const IHOST_APPLICATION_IID: Tuid = iid(0x58E5_95CC, 0xDB2D_4969, 0x8B6A_F8DC, 0x29E2_4EF0);
+pub const IEVENT_LIST_IID: Tuid = iid(0x3A2C_4214, 0x3463_49FE, 0xB2C4_F397, 0xB969_5A44);
+pub const IPARAMETER_CHANGES_IID: Tuid = iid(0xA477_9663, 0x0BB6_4A56, 0xB443_84A8, 0x466F_EB9D);
+pub const IPARAM_VALUE_QUEUE_IID: Tuid = iid(0x0126_3A18, 0xED07_4F6F, 0x98C9_D356, 0x4686_F9BA);
+pub const IBSTREAM_IID: Tuid = iid(0xC3BF_6EA2, 0x3099_4752, 0x9B6B_F990, 0x1EE3_3E9B);
+
+pub type QueryInterface = unsafe extern "system" fn(this: *mut c_void, iid: *const Tuid, object: *mut *mut c_void) -> TResult;
+pub type AddRef = unsafe extern "system" fn(this: *mut c_void) -> u32;
+pub type Release = unsafe extern "system" fn(this: *mut c_void) -> u32;
+
+#[repr(C)]
+pub struct FUnknownVtbl {
+    pub query_interface: QueryInterface,
+    pub add_ref: AddRef,
+    pub release: Release,
+}
+
+#[repr(C)]
+pub struct PFactoryInfo {
+    pub vendor: [c_char; 64],
+    pub url: [c_char; 256],
+    pub email: [c_char; 128],
+    pub flags: i32,
+}
+
+#[repr(C)]
+pub struct PClassInfo {
+    pub cid: Tuid,
+    pub cardinality: i32,
+    pub category: [c_char; 32],
+    pub name: [c_char; 64],
+}
+
+#[repr(C)]
+pub struct PClassInfo2 {
+    pub cid: Tuid,
+    pub cardinality: i32,
+    pub category: [c_char; 32],
+    pub name: [c_char; 64],
+    pub class_flags: u32,
+    pub sub_categories: [c_char; 128],
+    pub vendor: [c_char; 64],
+    pub version: [c_char; 64],
+    pub sdk_version: [c_char; 64],
+}
+
+#[repr(C)]
+pub struct IPluginFactoryVtbl {
+    pub unknown: FUnknownVtbl,
+    pub get_factory_info: unsafe extern "system" fn(this: *mut c_void, info: *mut PFactoryInfo) -> TResult,
+    pub count_classes: unsafe extern "system" fn(this: *mut c_void) -> i32,
+    pub get_class_info: unsafe extern "system" fn(this: *mut c_void, index: i32, info: *mut PClassInfo) -> TResult,
+    pub create_instance: unsafe extern "system" fn(this: *mut c_void, cid: *const c_char, iid: *const c_char, object: *mut *mut c_void) -> TResult,
+}
+
+#[repr(C)]
+pub struct IPluginFactory2Vtbl {
+    pub factory: IPluginFactoryVtbl,
+    pub get_class_info2: unsafe extern "system" fn(this: *mut c_void, index: i32, info: *mut PClassInfo2) -> TResult,
+}
+
+#[repr(C)]
+pub struct BusInfo {
+    pub media_type: i32,
+    pub direction: i32,
+    pub channel_count: i32,
+    pub name: String128,
+    pub bus_type: i32,
+    pub flags: u32,
+}
+
+#[repr(C)]
+pub struct RoutingInfo {
+    pub media_type: i32,
+    pub bus_index: i32,
+    pub channel: i32,
+}
+
+#[repr(C)]
+pub struct IComponentVtbl {
+    pub unknown: FUnknownVtbl,
+    pub initialize: unsafe extern "system" fn(this: *mut c_void, context: *mut c_void) -> TResult,
+    pub terminate: unsafe extern "system" fn(this: *mut c_void) -> TResult,
+    pub get_controller_class_id: unsafe extern "system" fn(this: *mut c_void, class_id: *mut Tuid) -> TResult,
+    pub set_io_mode: unsafe extern "system" fn(this: *mut c_void, mode: i32) -> TResult,
+    pub get_bus_count: unsafe extern "system" fn(this: *mut c_void, media_type: i32, direction: i32) -> i32,
+    pub get_bus_info: unsafe extern "system" fn(this: *mut c_void, media_type: i32, direction: i32, index: i32, bus: *mut BusInfo) -> TResult,
+    pub get_routing_info: unsafe extern "system" fn(this: *mut c_void, input: *mut RoutingInfo, output: *mut RoutingInfo) -> TResult,
+    pub activate_bus: unsafe extern "system" fn(this: *mut c_void, media_type: i32, direction: i32, index: i32, state: u8) -> TResult,
+    pub set_active: unsafe extern "system" fn(this: *mut c_void, state: u8) -> TResult,
+    pub set_state: unsafe extern "system" fn(this: *mut c_void, state: *mut c_void) -> TResult,
+    pub get_state: unsafe extern "system" fn(this: *mut c_void, state: *mut c_void) -> TResult,
+}
+
+#[repr(C)]
+pub struct ProcessSetup {
+    pub process_mode: i32,
+    pub symbolic_sample_size: i32,
+    pub max_samples_per_block: i32,
+    pub sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct AudioBusBuffers {
+    pub num_channels: i32,
+    pub silence_flags: u64,
+    /// `Sample32**`; the 64-bit variant of the union is never used
+    pub channel_buffers: *mut *mut f32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct Chord {
+    pub key_note: u8,
+    pub root_note: u8,
+    pub chord_mask: i16,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct FrameRate {
+    pub frames_per_second: u32,
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct ProcessContext {
+    pub state: u32,
+    pub sample_rate: f64,
+    pub project_time_samples: i64,
+    pub system_time: i64,
+    pub continous_time_samples: i64,
+    pub project_time_music: f64,
+    pub bar_position_music: f64,
+    pub cycle_start_music: f64,
+    pub cycle_end_music: f64,
+    pub tempo: f64,
+    pub time_sig_numerator: i32,
+    pub time_sig_denominator: i32,
+    pub chord: Chord,
+    pub smpte_offset_subframes: i32,
+    pub frame_rate: FrameRate,
+    pub samples_to_next_clock: i32,
+}
+
+#[repr(C)]
+pub struct ProcessData {
+    pub process_mode: i32,
+    pub symbolic_sample_size: i32,
+    pub num_samples: i32,
+    pub num_inputs: i32,
+    pub num_outputs: i32,
+    pub inputs: *mut AudioBusBuffers,
+    pub outputs: *mut AudioBusBuffers,
+    pub input_parameter_changes: *mut c_void,
+    pub output_parameter_changes: *mut c_void,
+    pub input_events: *mut c_void,
+    pub output_events: *mut c_void,
+    pub process_context: *mut ProcessContext,
+}
+
+#[repr(C)]
+pub struct IAudioProcessorVtbl {
+    pub unknown: FUnknownVtbl,
+    pub set_bus_arrangements: unsafe extern "system" fn(this: *mut c_void, inputs: *mut SpeakerArrangement, num_inputs: i32, outputs: *mut SpeakerArrangement, num_outputs: i32) -> TResult,
+    pub get_bus_arrangement: unsafe extern "system" fn(this: *mut c_void, direction: i32, index: i32, arrangement: *mut SpeakerArrangement) -> TResult,
+    pub can_process_sample_size: unsafe extern "system" fn(this: *mut c_void, symbolic_sample_size: i32) -> TResult,
+    pub get_latency_samples: unsafe extern "system" fn(this: *mut c_void) -> u32,
+    pub setup_processing: unsafe extern "system" fn(this: *mut c_void, setup: *mut ProcessSetup) -> TResult,
+    pub set_processing: unsafe extern "system" fn(this: *mut c_void, state: u8) -> TResult,
+    pub process: unsafe extern "system" fn(this: *mut c_void, data: *mut ProcessData) -> TResult,
+    pub get_tail_samples: unsafe extern "system" fn(this: *mut c_void) -> u32,
+}
+
+#[repr(C)]
+pub struct ParameterInfo {
+    pub id: ParamId,
+    pub title: String128,
+    pub short_title: String128,
+    pub units: String128,
+    pub step_count: i32,
+    pub default_normalized_value: ParamValue,
+    pub unit_id: i32,
+    pub flags: i32,
+}
+
+#[repr(C)]
+pub struct IEditControllerVtbl {
+    pub unknown: FUnknownVtbl,
+    pub initialize: unsafe extern "system" fn(this: *mut c_void, context: *mut c_void) -> TResult,
+    pub terminate: unsafe extern "system" fn(this: *mut c_void) -> TResult,
+    pub set_component_state: unsafe extern "system" fn(this: *mut c_void, state: *mut c_void) -> TResult,
+    pub set_state: unsafe extern "system" fn(this: *mut c_void, state: *mut c_void) -> TResult,
+    pub get_state: unsafe extern "system" fn(this: *mut c_void, state: *mut c_void) -> TResult,
+    pub get_parameter_count: unsafe extern "system" fn(this: *mut c_void) -> i32,
+    pub get_parameter_info: unsafe extern "system" fn(this: *mut c_void, index: i32, info: *mut ParameterInfo) -> TResult,
+    pub get_param_string_by_value: unsafe extern "system" fn(this: *mut c_void, id: ParamId, value: ParamValue, string: *mut String128) -> TResult,
+    pub get_param_value_by_string: unsafe extern "system" fn(this: *mut c_void, id: ParamId, string: *const u16, value: *mut ParamValue) -> TResult,
+    pub normalized_param_to_plain: unsafe extern "system" fn(this: *mut c_void, id: ParamId, value: ParamValue) -> ParamValue,
+    pub plain_param_to_normalized: unsafe extern "system" fn(this: *mut c_void, id: ParamId, value: ParamValue) -> ParamValue,
+    pub get_param_normalized: unsafe extern "system" fn(this: *mut c_void, id: ParamId) -> ParamValue,
+    pub set_param_normalized: unsafe extern "system" fn(this: *mut c_void, id: ParamId, value: ParamValue) -> TResult,
+    pub set_component_handler: unsafe extern "system" fn(this: *mut c_void, handler: *mut c_void) -> TResult,
+    pub create_view: unsafe extern "system" fn(this: *mut c_void, name: *const c_char) -> *mut c_void,
+}
+
+#[repr(C)]
+pub struct IConnectionPointVtbl {
+    pub unknown: FUnknownVtbl,
+    pub connect: unsafe extern "system" fn(this: *mut c_void, other: *mut c_void) -> TResult,
+    pub disconnect: unsafe extern "system" fn(this: *mut c_void, other: *mut c_void) -> TResult,
+    pub notify: unsafe extern "system" fn(this: *mut c_void, message: *mut c_void) -> TResult,
+}
+
+#[repr(C)]
+pub struct IHostApplicationVtbl {
+    pub unknown: FUnknownVtbl,
+    pub get_name: unsafe extern "system" fn(this: *mut c_void, name: *mut String128) -> TResult,
+    pub create_instance: unsafe extern "system" fn(this: *mut c_void, cid: *const Tuid, iid: *const Tuid, object: *mut *mut c_void) -> TResult,
+}
+
+#[repr(C)]
+pub struct IBStreamVtbl {
+    pub unknown: FUnknownVtbl,
+    pub read: unsafe extern "system" fn(this: *mut c_void, buffer: *mut c_void, num_bytes: i32, num_bytes_read: *mut i32) -> TResult,
+    pub write: unsafe extern "system" fn(this: *mut c_void, buffer: *mut c_void, num_bytes: i32, num_bytes_written: *mut i32) -> TResult,
+    pub seek: unsafe extern "system" fn(this: *mut c_void, pos: i64, mode: i32, result: *mut i64) -> TResult,
+    pub tell: unsafe extern "system" fn(this: *mut c_void, pos: *mut i64) -> TResult,
+}
+
+#[repr(C)]
+pub struct IComponentHandlerVtbl {
+    pub unknown: FUnknownVtbl,
+    pub begin_edit: unsafe extern "system" fn(this: *mut c_void, id: ParamId) -> TResult,
+    pub perform_edit: unsafe extern "system" fn(this: *mut c_void, id: ParamId, value: ParamValue) -> TResult,
+    pub end_edit: unsafe extern "system" fn(this: *mut c_void, id: ParamId) -> TResult,
+    pub restart_component: unsafe extern "system" fn(this: *mut c_void, flags: i32) -> TResult,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct NoteOnEvent {
+    pub channel: i16,
+    pub pitch: i16,
+    pub tuning: f32,
+    pub velocity: f32,
+    pub length: i32,
+    pub note_id: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct NoteOffEvent {
+    pub channel: i16,
+    pub pitch: i16,
+    pub velocity: f32,
+    pub note_id: i32,
+    pub tuning: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PolyPressureEvent {
+    pub channel: i16,
+    pub pitch: i16,
+    pub pressure: f32,
+    pub note_id: i32,
+}
+
+/// Sized and aligned like the SDK's event union, whose largest member is 24 bytes
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union EventPayload {
+    pub note_on: NoteOnEvent,
+    pub note_off: NoteOffEvent,
+    pub poly_pressure: PolyPressureEvent,
+    pub raw: [u64; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Event {
+    pub bus_index: i32,
+    pub sample_offset: i32,
+    pub ppq_position: f64,
+    pub flags: u16,
+    pub event_type: u16,
+    pub payload: EventPayload,
+}
+
+#[repr(C)]
+pub struct IEventListVtbl {
+    pub unknown: FUnknownVtbl,
+    pub get_event_count: unsafe extern "system" fn(this: *mut c_void) -> i32,
+    pub get_event: unsafe extern "system" fn(this: *mut c_void, index: i32, event: *mut Event) -> TResult,
+    pub add_event: unsafe extern "system" fn(this: *mut c_void, event: *mut Event) -> TResult,
+}
+
+#[repr(C)]
+pub struct IParamValueQueueVtbl {
+    pub unknown: FUnknownVtbl,
+    pub get_parameter_id: unsafe extern "system" fn(this: *mut c_void) -> ParamId,
+    pub get_point_count: unsafe extern "system" fn(this: *mut c_void) -> i32,
+    pub get_point: unsafe extern "system" fn(this: *mut c_void, index: i32, sample_offset: *mut i32, value: *mut ParamValue) -> TResult,
+    pub add_point: unsafe extern "system" fn(this: *mut c_void, sample_offset: i32, value: ParamValue, index: *mut i32) -> TResult,
+}
+
+#[repr(C)]
+pub struct IParameterChangesVtbl {
+    pub unknown: FUnknownVtbl,
+    pub get_parameter_count: unsafe extern "system" fn(this: *mut c_void) -> i32,
+    pub get_parameter_data: unsafe extern "system" fn(this: *mut c_void, index: i32) -> *mut c_void,
+    pub add_parameter_data: unsafe extern "system" fn(this: *mut c_void, id: *const ParamId, index: *mut i32) -> *mut c_void,
+}
+
+/// Vtable of the object behind an interface pointer
+///
+/// # Safety
+/// `object` must be a live interface pointer whose vtable starts with `V`.
+pub unsafe fn vtable<'a, V>(object: *mut c_void) -> &'a V {
+    &**(object as *mut *const V)
+}
+
+/// Owned reference to a plugin-side object, released on drop
+pub struct ComPtr(*mut c_void);
+
+impl ComPtr {
+    /// Take ownership of a reference returned by the plugin
+    ///
+    /// # Safety
+    /// `object` must be null or a live interface pointer the caller owns a reference to.
+    pub unsafe fn from_raw(object: *mut c_void) -> Option<Self> {
+        (!object.is_null()).then_some(Self(object))
+    }
+
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.0
+    }
+
+    /// Vtable of the interface this pointer was obtained as
+    ///
+    /// # Safety
+    /// `V` must match the interface the pointer refers to.
+    pub unsafe fn vtable<V>(&self) -> &V {
+        vtable(self.0)
+    }
+
+    /// Query another interface of the same object
+    pub fn query(&self, iid: &Tuid) -> Option<ComPtr> {
+        let mut object = std::ptr::null_mut();
+        // SAFETY: every interface starts with the FUnknown methods
+        unsafe {
+            let unknown: &FUnknownVtbl = vtable(self.0);
+            if (unknown.query_interface)(self.0, iid, &mut object) != K_RESULT_OK {
+                return None;
+            }
+            Self::from_raw(object)
+        }
+    }
+}
+
+impl Drop for ComPtr {
+    fn drop(&mut self) {
+        // SAFETY: we own one reference to a live object
+        unsafe {
+            let unknown: &FUnknownVtbl = vtable(self.0);
+            (unknown.release)(self.0);
+        }
+    }
+}
+
+/// Read a NUL-terminated C string field
+pub fn c_string(chars: &[c_char]) -> String {
+    let bytes: Vec<u8> = chars.iter().take_while(|&&c| c != 0).map(|&c| c as u8).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Read a NUL-terminated UTF-16 field
+pub fn utf16_string(chars: &[u16]) -> String {
+    let length = chars.iter().position(|&c| c == 0).unwrap_or(chars.len());
+    String::from_utf16_lossy(&chars[..length])
+}
+
+/// Write a string into a NUL-terminated UTF-16 field
+pub fn write_utf16(target: &mut String128, text: &str) {
+    let mut length = 0;
+    for (slot, unit) in target.iter_mut().take(127).zip(text.encode_utf16()) {
+        *slot = unit;
+        length += 1;
+    }
+    target[length] = 0;
+}
+
+/// Speaker arrangement for a plain channel count
+pub fn arrangement_for_channels(channels: usize) -> SpeakerArrangement {
+    match channels {
+        0 => 0,
+        1 => K_SPEAKER_MONO,
+        count => (1u64 << count.min(63)) - 1,
+    }
+}
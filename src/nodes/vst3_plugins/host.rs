@@ -0,0 +1,508 @@
+//! Host-side VST3 objects
+//!
+//! Objects the plugin calls back into: the host context, the component handler, the
+//! event and parameter-change lists passed to `process`, and the memory stream used
+//! for plugin state. Lists are allocated up front so processing never allocates.
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Mutex;
+use super::com::*;
+
+/// Most events delivered to a plugin per block
+pub const MAX_BLOCK_EVENTS: usize = 512;
+
+/// Most parameters changed per block
+pub const MAX_BLOCK_PARAMETER_CHANGES: usize = 256;
+
+/// Most points per parameter per block
+const MAX_QUEUE_POINTS: usize = 16;
+
+/// Hand out `this` for the given interfaces
+unsafe fn query_self(this: *mut c_void, iid: *const Tuid, object: *mut *mut c_void, accepted: &[&Tuid]) -> TResult {
+    if object.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    if !iid.is_null() && accepted.iter().any(|accepted| **accepted == *iid) {
+        *object = this;
+        K_RESULT_OK
+    } else {
+        *object = std::ptr::null_mut();
+        K_NO_INTERFACE
+    }
+}
+
+/// Host objects live as long as the plugin instance, so reference counts are nominal
+unsafe extern "system" fn add_ref(_this: *mut c_void) -> u32 {
+    1
+}
+
+unsafe extern "system" fn release(_this: *mut c_void) -> u32 {
+    1
+}
+
+/// Context passed to `initialize`
+#[repr(C)]
+pub struct HostApplication {
+    vtbl: *const IHostApplicationVtbl,
+}
+
+static HOST_APPLICATION_VTBL: IHostApplicationVtbl = IHostApplicationVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: host_query_interface,
+        add_ref,
+        release,
+    },
+    get_name: host_get_name,
+    create_instance: host_create_instance,
+};
+
+unsafe extern "system" fn host_query_interface(this: *mut c_void, iid: *const Tuid, object: *mut *mut c_void) -> TResult {
+    query_self(this, iid, object, &[&FUNKNOWN_IID, &IHOST_APPLICATION_IID])
+}
+
+unsafe extern "system" fn host_get_name(_this: *mut c_void, name: *mut String128) -> TResult {
+    if name.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    write_utf16(&mut *name, "NUWE");
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn host_create_instance(_this: *mut c_void, _cid: *const Tuid, _iid: *const Tuid, object: *mut *mut c_void) -> TResult {
+    if !object.is_null() {
+        *object = std::ptr::null_mut();
+    }
+    K_NOT_IMPLEMENTED
+}
+
+impl HostApplication {
+    pub fn new() -> Box<Self> {
+        Box::new(Self { vtbl: &HOST_APPLICATION_VTBL })
+    }
+
+    pub fn as_ptr(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+}
+
+/// Receives parameter edits and restart requests from the edit controller
+#[repr(C)]
+pub struct ComponentHandler {
+    vtbl: *const IComponentHandlerVtbl,
+    edits: Mutex<Vec<(ParamId, ParamValue)>>,
+    restart_flags: AtomicI32,
+}
+
+static COMPONENT_HANDLER_VTBL: IComponentHandlerVtbl = IComponentHandlerVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: handler_query_interface,
+        add_ref,
+        release,
+    },
+    begin_edit: handler_begin_edit,
+    perform_edit: handler_perform_edit,
+    end_edit: handler_end_edit,
+    restart_component: handler_restart_component,
+};
+
+unsafe extern "system" fn handler_query_interface(this: *mut c_void, iid: *const Tuid, object: *mut *mut c_void) -> TResult {
+    query_self(this, iid, object, &[&FUNKNOWN_IID, &ICOMPONENT_HANDLER_IID])
+}
+
+unsafe extern "system" fn handler_begin_edit(_this: *mut c_void, _id: ParamId) -> TResult {
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn handler_perform_edit(this: *mut c_void, id: ParamId, value: ParamValue) -> TResult {
+    let handler = &*(this as *const ComponentHandler);
+    if let Ok(mut edits) = handler.edits.lock() {
+        edits.push((id, value));
+    }
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn handler_end_edit(_this: *mut c_void, _id: ParamId) -> TResult {
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn handler_restart_component(this: *mut c_void, flags: i32) -> TResult {
+    let handler = &*(this as *const ComponentHandler);
+    handler.restart_flags.fetch_or(flags, Ordering::Relaxed);
+    K_RESULT_OK
+}
+
+impl ComponentHandler {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {
+            vtbl: &COMPONENT_HANDLER_VTBL,
+            edits: Mutex::new(Vec::new()),
+            restart_flags: AtomicI32::new(0),
+        })
+    }
+
+    pub fn as_ptr(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+
+    /// Edits the controller made since the last call
+    pub fn take_edits(&self) -> Vec<(ParamId, ParamValue)> {
+        self.edits.lock().map(|mut edits| std::mem::take(&mut *edits)).unwrap_or_default()
+    }
+
+    /// `restartComponent` flags raised since the last call
+    pub fn take_restart_flags(&self) -> i32 {
+        self.restart_flags.swap(0, Ordering::Relaxed)
+    }
+}
+
+/// Growable in-memory `IBStream` for plugin state
+#[repr(C)]
+pub struct MemoryStream {
+    vtbl: *const IBStreamVtbl,
+    data: Vec<u8>,
+    position: usize,
+}
+
+static MEMORY_STREAM_VTBL: IBStreamVtbl = IBStreamVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: stream_query_interface,
+        add_ref,
+        release,
+    },
+    read: stream_read,
+    write: stream_write,
+    seek: stream_seek,
+    tell: stream_tell,
+};
+
+unsafe extern "system" fn stream_query_interface(this: *mut c_void, iid: *const Tuid, object: *mut *mut c_void) -> TResult {
+    query_self(this, iid, object, &[&FUNKNOWN_IID, &IBSTREAM_IID])
+}
+
+unsafe extern "system" fn stream_read(this: *mut c_void, buffer: *mut c_void, num_bytes: i32, num_bytes_read: *mut i32) -> TResult {
+    let stream = &mut *(this as *mut MemoryStream);
+    if buffer.is_null() || num_bytes < 0 {
+        return K_INVALID_ARGUMENT;
+    }
+    let available = stream.data.len().saturating_sub(stream.position);
+    let count = (num_bytes as usize).min(available);
+    if count > 0 {
+        std::ptr::copy_nonoverlapping(stream.data.as_ptr().add(stream.position), buffer as *mut u8, count);
+        stream.position += count;
+    }
+    if !num_bytes_read.is_null() {
+        *num_bytes_read = count as i32;
+    }
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn stream_write(this: *mut c_void, buffer: *mut c_void, num_bytes: i32, num_bytes_written: *mut i32) -> TResult {
+    let stream = &mut *(this as *mut MemoryStream);
+    if buffer.is_null() || num_bytes < 0 {
+        return K_INVALID_ARGUMENT;
+    }
+    let bytes = std::slice::from_raw_parts(buffer as *const u8, num_bytes as usize);
+    let end = stream.position + bytes.len();
+    if stream.data.len() < end {
+        stream.data.resize(end, 0);
+    }
+    stream.data[stream.position..end].copy_from_slice(bytes);
+    stream.position = end;
+    if !num_bytes_written.is_null() {
+        *num_bytes_written = num_bytes;
+    }
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn stream_seek(this: *mut c_void, pos: i64, mode: i32, result: *mut i64) -> TResult {
+    let stream = &mut *(this as *mut MemoryStream);
+    let base = match mode {
+        K_IB_SEEK_SET => 0,
+        K_IB_SEEK_CUR => stream.position as i64,
+        K_IB_SEEK_END => stream.data.len() as i64,
+        _ => return K_INVALID_ARGUMENT,
+    };
+    let Some(position) = base.checked_add(pos).filter(|&position| position >= 0) else {
+        return K_INVALID_ARGUMENT;
+    };
+    // Seeking past the end is allowed; a later write fills the gap with zeros
+    stream.position = position as usize;
+    if !result.is_null() {
+        *result = position;
+    }
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn stream_tell(this: *mut c_void, pos: *mut i64) -> TResult {
+    if pos.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    *pos = (*(this as *const MemoryStream)).position as i64;
+    K_RESULT_OK
+}
+
+impl MemoryStream {
+    /// Stream positioned at the start of `data`
+    pub fn new(data: Vec<u8>) -> Box<Self> {
+        Box::new(Self { vtbl: &MEMORY_STREAM_VTBL, data, position: 0 })
+    }
+
+    pub fn as_ptr(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+
+    pub fn rewind(&mut self) {
+        self.position = 0;
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Fixed-capacity event list
+#[repr(C)]
+pub struct EventList {
+    vtbl: *const IEventListVtbl,
+    events: Vec<Event>,
+}
+
+static EVENT_LIST_VTBL: IEventListVtbl = IEventListVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: events_query_interface,
+        add_ref,
+        release,
+    },
+    get_event_count: events_get_event_count,
+    get_event: events_get_event,
+    add_event: events_add_event,
+};
+
+unsafe extern "system" fn events_query_interface(this: *mut c_void, iid: *const Tuid, object: *mut *mut c_void) -> TResult {
+    query_self(this, iid, object, &[&FUNKNOWN_IID, &IEVENT_LIST_IID])
+}
+
+unsafe extern "system" fn events_get_event_count(this: *mut c_void) -> i32 {
+    (*(this as *const EventList)).events.len() as i32
+}
+
+unsafe extern "system" fn events_get_event(this: *mut c_void, index: i32, event: *mut Event) -> TResult {
+    let list = &*(this as *const EventList);
+    match usize::try_from(index).ok().and_then(|index| list.events.get(index)) {
+        Some(found) if !event.is_null() => {
+            *event = *found;
+            K_RESULT_OK
+        }
+        _ => K_INVALID_ARGUMENT,
+    }
+}
+
+unsafe extern "system" fn events_add_event(this: *mut c_void, event: *mut Event) -> TResult {
+    let list = &mut *(this as *mut EventList);
+    if event.is_null() || !list.push(*event) {
+        return K_RESULT_FALSE;
+    }
+    K_RESULT_OK
+}
+
+impl EventList {
+    pub fn new() -> Box<Self> {
+        Box::new(Self { vtbl: &EVENT_LIST_VTBL, events: Vec::with_capacity(MAX_BLOCK_EVENTS) })
+    }
+
+    pub fn as_ptr(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+
+    /// Add an event unless the list is full
+    pub fn push(&mut self, event: Event) -> bool {
+        if self.events.len() >= MAX_BLOCK_EVENTS {
+            return false;
+        }
+        self.events.push(event);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+/// Changes of one parameter within a block
+#[repr(C)]
+pub struct ParamValueQueue {
+    vtbl: *const IParamValueQueueVtbl,
+    id: ParamId,
+    points: Vec<(i32, ParamValue)>,
+}
+
+static PARAM_VALUE_QUEUE_VTBL: IParamValueQueueVtbl = IParamValueQueueVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: queue_query_interface,
+        add_ref,
+        release,
+    },
+    get_parameter_id: queue_get_parameter_id,
+    get_point_count: queue_get_point_count,
+    get_point: queue_get_point,
+    add_point: queue_add_point,
+};
+
+unsafe extern "system" fn queue_query_interface(this: *mut c_void, iid: *const Tuid, object: *mut *mut c_void) -> TResult {
+    query_self(this, iid, object, &[&FUNKNOWN_IID, &IPARAM_VALUE_QUEUE_IID])
+}
+
+unsafe extern "system" fn queue_get_parameter_id(this: *mut c_void) -> ParamId {
+    (*(this as *const ParamValueQueue)).id
+}
+
+unsafe extern "system" fn queue_get_point_count(this: *mut c_void) -> i32 {
+    (*(this as *const ParamValueQueue)).points.len() as i32
+}
+
+unsafe extern "system" fn queue_get_point(this: *mut c_void, index: i32, sample_offset: *mut i32, value: *mut ParamValue) -> TResult {
+    let queue = &*(this as *const ParamValueQueue);
+    match usize::try_from(index).ok().and_then(|index| queue.points.get(index)) {
+        Some(&(offset, point)) if !sample_offset.is_null() && !value.is_null() => {
+            *sample_offset = offset;
+            *value = point;
+            K_RESULT_OK
+        }
+        _ => K_INVALID_ARGUMENT,
+    }
+}
+
+unsafe extern "system" fn queue_add_point(this: *mut c_void, sample_offset: i32, value: ParamValue, index: *mut i32) -> TResult {
+    let queue = &mut *(this as *mut ParamValueQueue);
+    match queue.add_point(sample_offset, value) {
+        Some(position) => {
+            if !index.is_null() {
+                *index = position as i32;
+            }
+            K_RESULT_OK
+        }
+        None => K_RESULT_FALSE,
+    }
+}
+
+impl ParamValueQueue {
+    fn new() -> Self {
+        Self { vtbl: &PARAM_VALUE_QUEUE_VTBL, id: 0, points: Vec::with_capacity(MAX_QUEUE_POINTS) }
+    }
+
+    /// Insert a point in offset order, replacing one at the same offset
+    fn add_point(&mut self, sample_offset: i32, value: ParamValue) -> Option<usize> {
+        let position = self.points.partition_point(|&(offset, _)| offset < sample_offset);
+        if let Some(point) = self.points.get_mut(position).filter(|(offset, _)| *offset == sample_offset) {
+            point.1 = value;
+            return Some(position);
+        }
+        if self.points.len() >= MAX_QUEUE_POINTS {
+            return None;
+        }
+        self.points.insert(position, (sample_offset, value));
+        Some(position)
+    }
+
+    pub fn id(&self) -> ParamId {
+        self.id
+    }
+
+    pub fn points(&self) -> &[(i32, ParamValue)] {
+        &self.points
+    }
+}
+
+/// Fixed-capacity parameter change list
+#[repr(C)]
+pub struct ParameterChanges {
+    vtbl: *const IParameterChangesVtbl,
+    queues: Vec<ParamValueQueue>,
+    used: usize,
+}
+
+static PARAMETER_CHANGES_VTBL: IParameterChangesVtbl = IParameterChangesVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: changes_query_interface,
+        add_ref,
+        release,
+    },
+    get_parameter_count: changes_get_parameter_count,
+    get_parameter_data: changes_get_parameter_data,
+    add_parameter_data: changes_add_parameter_data,
+};
+
+unsafe extern "system" fn changes_query_interface(this: *mut c_void, iid: *const Tuid, object: *mut *mut c_void) -> TResult {
+    query_self(this, iid, object, &[&FUNKNOWN_IID, &IPARAMETER_CHANGES_IID])
+}
+
+unsafe extern "system" fn changes_get_parameter_count(this: *mut c_void) -> i32 {
+    (*(this as *const ParameterChanges)).used as i32
+}
+
+unsafe extern "system" fn changes_get_parameter_data(this: *mut c_void, index: i32) -> *mut c_void {
+    let changes = &mut *(this as *mut ParameterChanges);
+    match usize::try_from(index) {
+        Ok(index) if index < changes.used => &mut changes.queues[index] as *mut ParamValueQueue as *mut c_void,
+        _ => std::ptr::null_mut(),
+    }
+}
+
+unsafe extern "system" fn changes_add_parameter_data(this: *mut c_void, id: *const ParamId, index: *mut i32) -> *mut c_void {
+    let changes = &mut *(this as *mut ParameterChanges);
+    if id.is_null() {
+        return std::ptr::null_mut();
+    }
+    match changes.queue_index(*id) {
+        Some(position) => {
+            if !index.is_null() {
+                *index = position as i32;
+            }
+            &mut changes.queues[position] as *mut ParamValueQueue as *mut c_void
+        }
+        None => std::ptr::null_mut(),
+    }
+}
+
+impl ParameterChanges {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {
+            vtbl: &PARAMETER_CHANGES_VTBL,
+            queues: (0..MAX_BLOCK_PARAMETER_CHANGES).map(|_| ParamValueQueue::new()).collect(),
+            used: 0,
+        })
+    }
+
+    pub fn as_ptr(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+
+    /// Queue for `id`, claiming a free one if needed
+    fn queue_index(&mut self, id: ParamId) -> Option<usize> {
+        if let Some(position) = self.queues[..self.used].iter().position(|queue| queue.id == id) {
+            return Some(position);
+        }
+        let queue = self.queues.get_mut(self.used)?;
+        queue.id = id;
+        queue.points.clear();
+        self.used += 1;
+        Some(self.used - 1)
+    }
+
+    /// Add a change; false if the list or the parameter's queue is full
+    pub fn add(&mut self, id: ParamId, sample_offset: i32, value: ParamValue) -> bool {
+        match self.queue_index(id) {
+            Some(position) => self.queues[position].add_point(sample_offset, value).is_some(),
+            None => false,
+        }
+    }
+
+    pub fn queues(&self) -> &[ParamValueQueue] {
+        &self.queues[..self.used]
+    }
+
+    pub fn clear(&mut self) {
+        self.used = 0;
+    }
+}
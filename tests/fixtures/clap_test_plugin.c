@@ -0,0 +1,306 @@
+/* Minimal CLAP plugin for tests: stereo gain with a read-only "Note" parameter that
+ * follows the last note on. A note on for key 0 aborts, to exercise crash recovery.
+ * Declares just the parts of the CLAP ABI it uses so it builds without the CLAP headers:
+ *
+ *     cc -shared -fPIC -o NuweTest.clap clap_test_plugin.c
+ */
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct { uint32_t major, minor, revision; } clap_version_t;
+
+typedef struct {
+    clap_version_t clap_version;
+    const char *id, *name, *vendor, *url, *manual_url, *support_url, *version, *description;
+    const char *const *features;
+} clap_plugin_descriptor_t;
+
+typedef struct clap_host {
+    clap_version_t clap_version;
+    void *host_data;
+    const char *name, *vendor, *url, *version;
+    const void *(*get_extension)(const struct clap_host *, const char *);
+    void (*request_restart)(const struct clap_host *);
+    void (*request_process)(const struct clap_host *);
+    void (*request_callback)(const struct clap_host *);
+} clap_host_t;
+
+typedef struct { uint32_t size, time; uint16_t space_id, type; uint32_t flags; } clap_event_header_t;
+typedef struct { clap_event_header_t header; int32_t note_id; int16_t port_index, channel, key; double velocity; } clap_event_note_t;
+typedef struct { clap_event_header_t header; uint32_t param_id; void *cookie; int32_t note_id; int16_t port_index, channel, key; double value; } clap_event_param_value_t;
+typedef struct { clap_event_header_t header; uint16_t port_index; uint8_t data[3]; } clap_event_midi_t;
+
+typedef struct clap_input_events {
+    void *ctx;
+    uint32_t (*size)(const struct clap_input_events *);
+    const clap_event_header_t *(*get)(const struct clap_input_events *, uint32_t);
+} clap_input_events_t;
+
+typedef struct clap_output_events {
+    void *ctx;
+    bool (*try_push)(const struct clap_output_events *, const clap_event_header_t *);
+} clap_output_events_t;
+
+typedef struct { float **data32; double **data64; uint32_t channel_count, latency; uint64_t constant_mask; } clap_audio_buffer_t;
+
+typedef struct {
+    int64_t steady_time;
+    uint32_t frames_count;
+    const void *transport;
+    const clap_audio_buffer_t *audio_inputs;
+    clap_audio_buffer_t *audio_outputs;
+    uint32_t audio_inputs_count, audio_outputs_count;
+    const clap_input_events_t *in_events;
+    const clap_output_events_t *out_events;
+} clap_process_t;
+
+typedef struct clap_plugin {
+    const clap_plugin_descriptor_t *desc;
+    void *plugin_data;
+    bool (*init)(const struct clap_plugin *);
+    void (*destroy)(const struct clap_plugin *);
+    bool (*activate)(const struct clap_plugin *, double, uint32_t, uint32_t);
+    void (*deactivate)(const struct clap_plugin *);
+    bool (*start_processing)(const struct clap_plugin *);
+    void (*stop_processing)(const struct clap_plugin *);
+    void (*reset)(const struct clap_plugin *);
+    int32_t (*process)(const struct clap_plugin *, const clap_process_t *);
+    const void *(*get_extension)(const struct clap_plugin *, const char *);
+    void (*on_main_thread)(const struct clap_plugin *);
+} clap_plugin_t;
+
+typedef struct clap_plugin_factory {
+    uint32_t (*get_plugin_count)(const struct clap_plugin_factory *);
+    const clap_plugin_descriptor_t *(*get_plugin_descriptor)(const struct clap_plugin_factory *, uint32_t);
+    const clap_plugin_t *(*create_plugin)(const struct clap_plugin_factory *, const clap_host_t *, const char *);
+} clap_plugin_factory_t;
+
+typedef struct {
+    clap_version_t clap_version;
+    bool (*init)(const char *);
+    void (*deinit)(void);
+    const void *(*get_factory)(const char *);
+} clap_plugin_entry_t;
+
+typedef struct {
+    uint32_t id, flags;
+    void *cookie;
+    char name[256];
+    char module[1024];
+    double min_value, max_value, default_value;
+} clap_param_info_t;
+
+typedef struct {
+    uint32_t (*count)(const clap_plugin_t *);
+    bool (*get_info)(const clap_plugin_t *, uint32_t, clap_param_info_t *);
+    bool (*get_value)(const clap_plugin_t *, uint32_t, double *);
+    bool (*value_to_text)(const clap_plugin_t *, uint32_t, double, char *, uint32_t);
+    bool (*text_to_value)(const clap_plugin_t *, uint32_t, const char *, double *);
+    void (*flush)(const clap_plugin_t *, const clap_input_events_t *, const clap_output_events_t *);
+} clap_plugin_params_t;
+
+typedef struct { uint32_t id; char name[256]; uint32_t flags, channel_count; const char *port_type; uint32_t in_place_pair; } clap_audio_port_info_t;
+typedef struct {
+    uint32_t (*count)(const clap_plugin_t *, bool);
+    bool (*get)(const clap_plugin_t *, uint32_t, bool, clap_audio_port_info_t *);
+} clap_plugin_audio_ports_t;
+
+typedef struct { uint32_t id, supported_dialects, preferred_dialect; char name[256]; } clap_note_port_info_t;
+typedef struct {
+    uint32_t (*count)(const clap_plugin_t *, bool);
+    bool (*get)(const clap_plugin_t *, uint32_t, bool, clap_note_port_info_t *);
+} clap_plugin_note_ports_t;
+
+typedef struct { uint32_t (*get)(const clap_plugin_t *); } clap_plugin_latency_t;
+
+typedef struct clap_istream { void *ctx; int64_t (*read)(const struct clap_istream *, void *, uint64_t); } clap_istream_t;
+typedef struct clap_ostream { void *ctx; int64_t (*write)(const struct clap_ostream *, const void *, uint64_t); } clap_ostream_t;
+typedef struct {
+    bool (*save)(const clap_plugin_t *, const clap_ostream_t *);
+    bool (*load)(const clap_plugin_t *, const clap_istream_t *);
+} clap_plugin_state_t;
+
+#define EVENT_NOTE_ON 0
+#define EVENT_PARAM_VALUE 5
+#define EVENT_MIDI 10
+#define PARAM_GAIN 0
+#define PARAM_NOTE 1
+#define LATENCY 32
+
+typedef struct {
+    clap_plugin_t plugin;
+    double gain;
+    double note;
+    int active;
+} test_plugin_t;
+
+static const char *const features[] = { "audio-effect", "utility", NULL };
+
+static const clap_plugin_descriptor_t descriptor = {
+    { 1, 2, 0 }, "org.nuwe.test-gain", "NUWE Test Gain", "NUWE", "", "", "", "1.0.0", "Gain for tests", features,
+};
+
+static test_plugin_t *self_of(const clap_plugin_t *plugin) { return (test_plugin_t *)plugin->plugin_data; }
+
+static bool plugin_init(const clap_plugin_t *plugin) { (void)plugin; return true; }
+static void plugin_destroy(const clap_plugin_t *plugin) { free(self_of(plugin)); }
+static bool plugin_activate(const clap_plugin_t *plugin, double rate, uint32_t min, uint32_t max) {
+    (void)rate; (void)min; (void)max;
+    self_of(plugin)->active = 1;
+    return true;
+}
+static void plugin_deactivate(const clap_plugin_t *plugin) { self_of(plugin)->active = 0; }
+static bool plugin_start(const clap_plugin_t *plugin) { return self_of(plugin)->active; }
+static void plugin_stop(const clap_plugin_t *plugin) { (void)plugin; }
+static void plugin_reset(const clap_plugin_t *plugin) { (void)plugin; }
+static void plugin_on_main_thread(const clap_plugin_t *plugin) { (void)plugin; }
+
+static void set_note(test_plugin_t *self, int key, const clap_output_events_t *out) {
+    if (key == 0) abort();
+    self->note = key;
+    clap_event_param_value_t event = {
+        { sizeof(clap_event_param_value_t), 0, 0, EVENT_PARAM_VALUE, 0 }, PARAM_NOTE, NULL, -1, -1, -1, -1, (double)key,
+    };
+    out->try_push(out, &event.header);
+}
+
+static int32_t plugin_process(const clap_plugin_t *plugin, const clap_process_t *process) {
+    test_plugin_t *self = self_of(plugin);
+    uint32_t count = process->in_events->size(process->in_events);
+    for (uint32_t i = 0; i < count; i++) {
+        const clap_event_header_t *header = process->in_events->get(process->in_events, i);
+        if (header->space_id != 0) continue;
+        if (header->type == EVENT_PARAM_VALUE) {
+            const clap_event_param_value_t *event = (const clap_event_param_value_t *)header;
+            if (event->param_id == PARAM_GAIN) self->gain = event->value;
+        } else if (header->type == EVENT_NOTE_ON) {
+            set_note(self, ((const clap_event_note_t *)header)->key, process->out_events);
+        } else if (header->type == EVENT_MIDI) {
+            const clap_event_midi_t *event = (const clap_event_midi_t *)header;
+            if ((event->data[0] & 0xF0) == 0x90 && event->data[2] > 0) set_note(self, event->data[1], process->out_events);
+        }
+    }
+
+    const clap_audio_buffer_t *in = &process->audio_inputs[0];
+    clap_audio_buffer_t *out = &process->audio_outputs[0];
+    for (uint32_t channel = 0; channel < out->channel_count; channel++) {
+        for (uint32_t frame = 0; frame < process->frames_count; frame++) {
+            out->data32[channel][frame] = (float)(in->data32[channel][frame] * self->gain);
+        }
+    }
+    return 1;
+}
+
+static uint32_t params_count(const clap_plugin_t *plugin) { (void)plugin; return 2; }
+static bool params_get_info(const clap_plugin_t *plugin, uint32_t index, clap_param_info_t *info) {
+    (void)plugin;
+    memset(info, 0, sizeof(*info));
+    if (index == 0) {
+        info->id = PARAM_GAIN;
+        info->flags = 1 << 5;
+        strcpy(info->name, "Gain");
+        info->min_value = 0.0;
+        info->max_value = 2.0;
+        info->default_value = 1.0;
+    } else if (index == 1) {
+        info->id = PARAM_NOTE;
+        info->flags = (1 << 0) | (1 << 3);
+        strcpy(info->name, "Note");
+        info->max_value = 127.0;
+    } else {
+        return false;
+    }
+    return true;
+}
+static bool params_get_value(const clap_plugin_t *plugin, uint32_t id, double *value) {
+    test_plugin_t *self = self_of(plugin);
+    if (id == PARAM_GAIN) *value = self->gain;
+    else if (id == PARAM_NOTE) *value = self->note;
+    else return false;
+    return true;
+}
+static bool params_value_to_text(const clap_plugin_t *p, uint32_t id, double v, char *b, uint32_t c) { (void)p; (void)id; (void)v; (void)b; (void)c; return false; }
+static bool params_text_to_value(const clap_plugin_t *p, uint32_t id, const char *t, double *v) { (void)p; (void)id; (void)t; (void)v; return false; }
+static void params_flush(const clap_plugin_t *p, const clap_input_events_t *in, const clap_output_events_t *out) { (void)p; (void)in; (void)out; }
+static const clap_plugin_params_t params = {
+    params_count, params_get_info, params_get_value, params_value_to_text, params_text_to_value, params_flush,
+};
+
+static uint32_t audio_ports_count(const clap_plugin_t *plugin, bool is_input) { (void)plugin; (void)is_input; return 1; }
+static bool audio_ports_get(const clap_plugin_t *plugin, uint32_t index, bool is_input, clap_audio_port_info_t *info) {
+    (void)plugin;
+    if (index != 0) return false;
+    memset(info, 0, sizeof(*info));
+    info->id = 0;
+    strcpy(info->name, is_input ? "In" : "Out");
+    info->flags = 1;
+    info->channel_count = 2;
+    info->port_type = "stereo";
+    info->in_place_pair = 0;
+    return true;
+}
+static const clap_plugin_audio_ports_t audio_ports = { audio_ports_count, audio_ports_get };
+
+static uint32_t note_ports_count(const clap_plugin_t *plugin, bool is_input) { (void)plugin; return is_input ? 1 : 0; }
+static bool note_ports_get(const clap_plugin_t *plugin, uint32_t index, bool is_input, clap_note_port_info_t *info) {
+    (void)plugin;
+    if (index != 0 || !is_input) return false;
+    memset(info, 0, sizeof(*info));
+    info->supported_dialects = 1 | 2;
+    info->preferred_dialect = 1;
+    strcpy(info->name, "Notes");
+    return true;
+}
+static const clap_plugin_note_ports_t note_ports = { note_ports_count, note_ports_get };
+
+static uint32_t latency_get(const clap_plugin_t *plugin) { (void)plugin; return LATENCY; }
+static const clap_plugin_latency_t latency = { latency_get };
+
+static bool state_save(const clap_plugin_t *plugin, const clap_ostream_t *stream) {
+    double gain = self_of(plugin)->gain;
+    return stream->write(stream, &gain, sizeof(gain)) == sizeof(gain);
+}
+static bool state_load(const clap_plugin_t *plugin, const clap_istream_t *stream) {
+    double gain;
+    if (stream->read(stream, &gain, sizeof(gain)) != sizeof(gain)) return false;
+    self_of(plugin)->gain = gain;
+    return true;
+}
+static const clap_plugin_state_t state = { state_save, state_load };
+
+static const void *plugin_get_extension(const clap_plugin_t *plugin, const char *id) {
+    (void)plugin;
+    if (!strcmp(id, "clap.params")) return &params;
+    if (!strcmp(id, "clap.audio-ports")) return &audio_ports;
+    if (!strcmp(id, "clap.note-ports")) return &note_ports;
+    if (!strcmp(id, "clap.latency")) return &latency;
+    if (!strcmp(id, "clap.state")) return &state;
+    return NULL;
+}
+
+static uint32_t factory_count(const clap_plugin_factory_t *factory) { (void)factory; return 1; }
+static const clap_plugin_descriptor_t *factory_descriptor(const clap_plugin_factory_t *factory, uint32_t index) {
+    (void)factory;
+    return index == 0 ? &descriptor : NULL;
+}
+static const clap_plugin_t *factory_create(const clap_plugin_factory_t *factory, const clap_host_t *host, const char *id) {
+    (void)factory; (void)host;
+    if (strcmp(id, descriptor.id)) return NULL;
+    test_plugin_t *self = calloc(1, sizeof(test_plugin_t));
+    self->gain = 1.0;
+    self->plugin = (clap_plugin_t){
+        &descriptor, self, plugin_init, plugin_destroy, plugin_activate, plugin_deactivate, plugin_start,
+        plugin_stop, plugin_reset, plugin_process, plugin_get_extension, plugin_on_main_thread,
+    };
+    return &self->plugin;
+}
+static const clap_plugin_factory_t factory = { factory_count, factory_descriptor, factory_create };
+
+static bool entry_init(const char *path) { (void)path; return true; }
+static void entry_deinit(void) {}
+static const void *entry_get_factory(const char *id) { return strcmp(id, "clap.plugin-factory") ? NULL : &factory; }
+
+__attribute__((visibility("default")))
+const clap_plugin_entry_t clap_entry = { { 1, 2, 0 }, entry_init, entry_deinit, entry_get_factory };
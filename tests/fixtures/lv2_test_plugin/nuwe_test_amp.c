@@ -0,0 +1,90 @@
+/* Minimal LV2 plugin for tests, see nuwe_test_amp.ttl. Declares just the parts of the
+ * LV2 ABI it uses so it builds without the LV2 headers:
+ *
+ *     cc -shared -fPIC -o nuwe_test_amp.so nuwe_test_amp.c
+ */
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct { const char *URI; void *data; } LV2_Feature;
+typedef struct { void *handle; uint32_t (*map)(void *, const char *); } LV2_URID_Map;
+typedef struct { uint32_t size, type; } LV2_Atom;
+typedef struct { int64_t frames; LV2_Atom body; } LV2_Atom_Event;
+
+typedef struct LV2_Descriptor {
+    const char *URI;
+    void *(*instantiate)(const struct LV2_Descriptor *, double, const char *, const LV2_Feature *const *);
+    void (*connect_port)(void *, uint32_t, void *);
+    void (*activate)(void *);
+    void (*run)(void *, uint32_t);
+    void (*deactivate)(void *);
+    void (*cleanup)(void *);
+    const void *(*extension_data)(const char *);
+} LV2_Descriptor;
+
+#define LATENCY 64
+
+typedef struct {
+    const float *in;
+    float *out;
+    const float *gain;
+    const LV2_Atom *events;
+    float *note;
+    float *latency;
+    uint32_t midi_event;
+} Amp;
+
+static void *instantiate(const LV2_Descriptor *descriptor, double rate, const char *bundle, const LV2_Feature *const *features) {
+    (void)descriptor; (void)rate; (void)bundle;
+    const LV2_URID_Map *map = NULL;
+    for (int i = 0; features && features[i]; i++) {
+        if (!strcmp(features[i]->URI, "http://lv2plug.in/ns/ext/urid#map")) map = features[i]->data;
+    }
+    if (!map) return NULL;
+    Amp *amp = calloc(1, sizeof(Amp));
+    amp->midi_event = map->map(map->handle, "http://lv2plug.in/ns/ext/midi#MidiEvent");
+    return amp;
+}
+
+static void connect_port(void *instance, uint32_t port, void *data) {
+    Amp *amp = instance;
+    switch (port) {
+    case 0: amp->in = data; break;
+    case 1: amp->out = data; break;
+    case 2: amp->gain = data; break;
+    case 3: amp->events = data; break;
+    case 4: amp->note = data; break;
+    case 5: amp->latency = data; break;
+    }
+}
+
+static void activate(void *instance) { (void)instance; }
+
+static void run(void *instance, uint32_t frames) {
+    Amp *amp = instance;
+    /* Sequence: atom header, 8-byte body header, then events padded to 8 bytes */
+    const uint8_t *event = (const uint8_t *)(amp->events + 1) + 8;
+    const uint8_t *end = (const uint8_t *)(amp->events + 1) + amp->events->size;
+    while (event < end) {
+        const LV2_Atom_Event *ev = (const LV2_Atom_Event *)event;
+        const uint8_t *msg = (const uint8_t *)(ev + 1);
+        if (ev->body.type == amp->midi_event && ev->body.size >= 3 && (msg[0] & 0xF0) == 0x90 && msg[2] > 0) {
+            *amp->note = msg[1];
+        }
+        event += sizeof(LV2_Atom_Event) + ((ev->body.size + 7) & ~7u);
+    }
+    for (uint32_t i = 0; i < frames; i++) amp->out[i] = amp->in[i] * *amp->gain;
+    *amp->latency = LATENCY;
+}
+
+static void deactivate(void *instance) { (void)instance; }
+static void cleanup(void *instance) { free(instance); }
+static const void *extension_data(const char *uri) { (void)uri; return NULL; }
+
+static const LV2_Descriptor descriptor = {
+    "urn:nuwe:test-amp", instantiate, connect_port, activate, run, deactivate, cleanup, extension_data,
+};
+
+__attribute__((visibility("default")))
+const LV2_Descriptor *lv2_descriptor(uint32_t index) { return index == 0 ? &descriptor : NULL; }
@@ -0,0 +1,45 @@
+# Reference mel energies and MFCCs for test_mfcc_matches_reference in src/audio/tests.rs.
+#
+# Written from the published definitions rather than from src/audio/features.rs, and
+# evaluated at 40 digits with mpmath and a direct DFT:
+#   - HTK mel scale, 2595 log10(1 + f / 700)
+#   - triangular filters with unit peaks (librosa.filters.mel with htk=True, norm=None)
+#   - periodic Hann window (scipy.signal.get_window("hann")), no centring, |X|^2 power
+#   - natural log, orthonormal DCT-II (scipy.fft.dct(type=2, norm="ortho"))
+#   - Kaldi's sinusoidal lifter, 1 + (L / 2) sin(pi n / L) with n from 0
+#
+# Run with: python3 mfcc_reference.py  (needs mpmath)
+import mpmath as mp
+mp.mp.dps = 40
+sr, n_fft, hop, n_mels, n_mfcc, L = 8000, 256, 128, 20, 13, 22
+seed = 12345
+x = []
+for i in range(512):
+    seed = (seed * 1664525 + 1013904223) % 2**32
+    noise = mp.mpf(seed >> 8) / 2**24 * 2 - 1
+    x.append(mp.mpf('0.5') * mp.sin(2 * mp.pi * 440 * i / sr) + mp.mpf('0.1') * noise)
+mel = lambda f: 2595 * mp.log10(1 + f / 700)
+imel = lambda m: 700 * (mp.power(10, m / 2595) - 1)
+pts = [imel(mel(0) + (mel(sr / 2) - mel(0)) * k / (n_mels + 1)) for k in range(n_mels + 2)]
+freqs = [mp.mpf(b) * sr / n_fft for b in range(n_fft // 2 + 1)]
+fb = [[max(0, min((f - pts[m]) / (pts[m + 1] - pts[m]), (pts[m + 2] - f) / (pts[m + 2] - pts[m + 1]))) for f in freqs] for m in range(n_mels)]
+win = [mp.mpf('0.5') - mp.mpf('0.5') * mp.cos(2 * mp.pi * n / n_fft) for n in range(n_fft)]
+frames = 1 + (len(x) - n_fft) // hop
+out_mel, out_mfcc = [], []
+for t in range(frames):
+    seg = [x[t * hop + n] * win[n] for n in range(n_fft)]
+    power = []
+    for k in range(n_fft // 2 + 1):
+        re = mp.fsum(seg[n] * mp.cos(2 * mp.pi * k * n / n_fft) for n in range(n_fft))
+        im = mp.fsum(seg[n] * mp.sin(2 * mp.pi * k * n / n_fft) for n in range(n_fft))
+        power.append(re * re + im * im)
+    m = [mp.fsum(w * p for w, p in zip(f, power)) for f in fb]
+    logm = [mp.log(v) for v in m]
+    c = []
+    for k in range(n_mfcc):
+        s = mp.fsum(logm[i] * mp.cos(mp.pi * k * (2 * i + 1) / (2 * n_mels)) for i in range(n_mels))
+        c.append(s * mp.sqrt(mp.mpf(1 if k == 0 else 2) / n_mels) * (1 + mp.mpf(L) / 2 * mp.sin(mp.pi * k / L)))
+    out_mel.append(m); out_mfcc.append(c)
+print("mel0", [mp.nstr(v, 8) for v in out_mel[0]])
+for t in (0, 2):
+    print("mfcc", t, [mp.nstr(v, 7) for v in out_mfcc[t]])
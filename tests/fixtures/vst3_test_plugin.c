@@ -0,0 +1,659 @@
+/*
+ * Minimal VST3 plugin used by the hosting tests.
+ *
+ * Written against the plain C layout of the VST3 interfaces (no SDK needed) so the
+ * tests can build it with the system C compiler:
+ *
+ *     cc -shared -fPIC -o NuweTest.so vst3_test_plugin.c
+ *
+ * One single-component class, "NUWE Test Gain", with:
+ *   - one main audio input and output bus of any width up to 8 channels, and an
+ *     event input bus
+ *   - parameter 0 "Gain" (normalized 0.5 = unity, plain value = 2 x normalized),
+ *     ramped sample by sample between the points of its queue
+ *   - parameter 7 "Note Level", read-only, reported through output parameter changes
+ *   - output = input * gain + velocity of the held note
+ *   - latency = max block size and tail = sample rate, so tests can see the
+ *     processing setup the host passed in
+ *   - component state = the gain as a double; controller state = a 32-bit UI scale
+ */
+
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+#include <stdio.h>
+#include <stddef.h>
+
+typedef int32_t tresult;
+typedef uint8_t TUID[16];
+typedef uint32_t ParamID;
+typedef double ParamValue;
+typedef uint64_t SpeakerArrangement;
+typedef uint16_t String128[128];
+
+#define kResultOk 0
+#define kResultFalse 1
+#define kInvalidArgument 2
+#define kNotImplemented 3
+#define kNoInterface -1
+
+#define UID(a, b, c, d) { \
+    (uint8_t)((a) >> 24), (uint8_t)((a) >> 16), (uint8_t)((a) >> 8), (uint8_t)(a), \
+    (uint8_t)((b) >> 24), (uint8_t)((b) >> 16), (uint8_t)((b) >> 8), (uint8_t)(b), \
+    (uint8_t)((c) >> 24), (uint8_t)((c) >> 16), (uint8_t)((c) >> 8), (uint8_t)(c), \
+    (uint8_t)((d) >> 24), (uint8_t)((d) >> 16), (uint8_t)((d) >> 8), (uint8_t)(d) }
+
+static const TUID FUnknown_iid = UID(0x00000000, 0x00000000, 0xC0000000, 0x00000046);
+static const TUID IPluginBase_iid = UID(0x22888DDB, 0x156E45AE, 0x8358B348, 0x08190625);
+static const TUID IPluginFactory_iid = UID(0x7A4D811C, 0x52114A1F, 0xAED9D2EE, 0x0B43BF9F);
+static const TUID IPluginFactory2_iid = UID(0x0007B650, 0xF24B4C0B, 0xA464EDB9, 0xF00B2ABB);
+static const TUID IComponent_iid = UID(0xE831FF31, 0xF2D54301, 0x928EBBEE, 0x25697802);
+static const TUID IAudioProcessor_iid = UID(0x42043F99, 0xB7DA453C, 0xA569E79D, 0x9AAEC33D);
+static const TUID IEditController_iid = UID(0xDCD7BBE3, 0x7742448D, 0xA874AACC, 0x979C759E);
+static const TUID plugin_cid = UID(0x4E555745, 0x54455354, 0x4741494E, 0x00000001);
+
+static int same_uid(const void* a, const void* b) {
+    return a && b && memcmp(a, b, 16) == 0;
+}
+
+/* ---- data structures ---- */
+
+typedef struct {
+    char vendor[64];
+    char url[256];
+    char email[128];
+    int32_t flags;
+} PFactoryInfo;
+
+typedef struct {
+    TUID cid;
+    int32_t cardinality;
+    char category[32];
+    char name[64];
+} PClassInfo;
+
+typedef struct {
+    TUID cid;
+    int32_t cardinality;
+    char category[32];
+    char name[64];
+    uint32_t classFlags;
+    char subCategories[128];
+    char vendor[64];
+    char version[64];
+    char sdkVersion[64];
+} PClassInfo2;
+
+typedef struct {
+    int32_t mediaType;
+    int32_t direction;
+    int32_t channelCount;
+    String128 name;
+    int32_t busType;
+    uint32_t flags;
+} BusInfo;
+
+typedef struct {
+    int32_t processMode;
+    int32_t symbolicSampleSize;
+    int32_t maxSamplesPerBlock;
+    double sampleRate;
+} ProcessSetup;
+
+typedef struct {
+    int32_t numChannels;
+    uint64_t silenceFlags;
+    float** channelBuffers32;
+} AudioBusBuffers;
+
+typedef struct {
+    int16_t channel;
+    int16_t pitch;
+    float tuning;
+    float velocity;
+    int32_t length;
+    int32_t noteId;
+} NoteOnEvent;
+
+typedef struct {
+    int16_t channel;
+    int16_t pitch;
+    float velocity;
+    int32_t noteId;
+    float tuning;
+} NoteOffEvent;
+
+typedef struct {
+    int32_t busIndex;
+    int32_t sampleOffset;
+    double ppqPosition;
+    uint16_t flags;
+    uint16_t type;
+    union {
+        NoteOnEvent noteOn;
+        NoteOffEvent noteOff;
+        struct { uint32_t typeId; int32_t noteId; uint32_t textLen; const uint16_t* text; } noteExpressionText;
+    };
+} Event;
+
+typedef struct {
+    ParamID id;
+    String128 title;
+    String128 shortTitle;
+    String128 units;
+    int32_t stepCount;
+    ParamValue defaultNormalizedValue;
+    int32_t unitId;
+    int32_t flags;
+} ParameterInfo;
+
+/* Host interfaces the plugin calls */
+
+typedef struct IBStream IBStream;
+typedef struct {
+    tresult (*queryInterface)(void*, const TUID, void**);
+    uint32_t (*addRef)(void*);
+    uint32_t (*release)(void*);
+    tresult (*read)(IBStream*, void*, int32_t, int32_t*);
+    tresult (*write)(IBStream*, void*, int32_t, int32_t*);
+    tresult (*seek)(IBStream*, int64_t, int32_t, int64_t*);
+    tresult (*tell)(IBStream*, int64_t*);
+} IBStreamVtbl;
+struct IBStream { const IBStreamVtbl* vtbl; };
+
+static tresult read_exact(void* stream, void* data, int32_t size) {
+    IBStream* s = stream;
+    int32_t read = 0;
+    if (!s || s->vtbl->read(s, data, size, &read) != kResultOk || read != size) return kResultFalse;
+    return kResultOk;
+}
+
+static tresult write_exact(void* stream, void* data, int32_t size) {
+    IBStream* s = stream;
+    int32_t written = 0;
+    if (!s || s->vtbl->write(s, data, size, &written) != kResultOk || written != size) return kResultFalse;
+    return kResultOk;
+}
+
+typedef struct IEventList IEventList;
+typedef struct {
+    tresult (*queryInterface)(void*, const TUID, void**);
+    uint32_t (*addRef)(void*);
+    uint32_t (*release)(void*);
+    int32_t (*getEventCount)(IEventList*);
+    tresult (*getEvent)(IEventList*, int32_t, Event*);
+    tresult (*addEvent)(IEventList*, Event*);
+} IEventListVtbl;
+struct IEventList { const IEventListVtbl* vtbl; };
+
+typedef struct IParamValueQueue IParamValueQueue;
+typedef struct {
+    tresult (*queryInterface)(void*, const TUID, void**);
+    uint32_t (*addRef)(void*);
+    uint32_t (*release)(void*);
+    ParamID (*getParameterId)(IParamValueQueue*);
+    int32_t (*getPointCount)(IParamValueQueue*);
+    tresult (*getPoint)(IParamValueQueue*, int32_t, int32_t*, ParamValue*);
+    tresult (*addPoint)(IParamValueQueue*, int32_t, ParamValue, int32_t*);
+} IParamValueQueueVtbl;
+struct IParamValueQueue { const IParamValueQueueVtbl* vtbl; };
+
+typedef struct IParameterChanges IParameterChanges;
+typedef struct {
+    tresult (*queryInterface)(void*, const TUID, void**);
+    uint32_t (*addRef)(void*);
+    uint32_t (*release)(void*);
+    int32_t (*getParameterCount)(IParameterChanges*);
+    IParamValueQueue* (*getParameterData)(IParameterChanges*, int32_t);
+    IParamValueQueue* (*addParameterData)(IParameterChanges*, const ParamID*, int32_t*);
+} IParameterChangesVtbl;
+struct IParameterChanges { const IParameterChangesVtbl* vtbl; };
+
+typedef struct {
+    int32_t processMode;
+    int32_t symbolicSampleSize;
+    int32_t numSamples;
+    int32_t numInputs;
+    int32_t numOutputs;
+    AudioBusBuffers* inputs;
+    AudioBusBuffers* outputs;
+    IParameterChanges* inputParameterChanges;
+    IParameterChanges* outputParameterChanges;
+    IEventList* inputEvents;
+    IEventList* outputEvents;
+    void* processContext;
+} ProcessData;
+
+/* ---- plugin object: one allocation, one interface pointer per implemented interface ---- */
+
+typedef struct Plugin Plugin;
+
+typedef struct {
+    tresult (*queryInterface)(void*, const TUID, void**);
+    uint32_t (*addRef)(void*);
+    uint32_t (*release)(void*);
+    tresult (*initialize)(void*, void*);
+    tresult (*terminate)(void*);
+    tresult (*getControllerClassId)(void*, TUID);
+    tresult (*setIoMode)(void*, int32_t);
+    int32_t (*getBusCount)(void*, int32_t, int32_t);
+    tresult (*getBusInfo)(void*, int32_t, int32_t, int32_t, BusInfo*);
+    tresult (*getRoutingInfo)(void*, void*, void*);
+    tresult (*activateBus)(void*, int32_t, int32_t, int32_t, uint8_t);
+    tresult (*setActive)(void*, uint8_t);
+    tresult (*setState)(void*, void*);
+    tresult (*getState)(void*, void*);
+} ComponentVtbl;
+
+typedef struct {
+    tresult (*queryInterface)(void*, const TUID, void**);
+    uint32_t (*addRef)(void*);
+    uint32_t (*release)(void*);
+    tresult (*setBusArrangements)(void*, SpeakerArrangement*, int32_t, SpeakerArrangement*, int32_t);
+    tresult (*getBusArrangement)(void*, int32_t, int32_t, SpeakerArrangement*);
+    tresult (*canProcessSampleSize)(void*, int32_t);
+    uint32_t (*getLatencySamples)(void*);
+    tresult (*setupProcessing)(void*, ProcessSetup*);
+    tresult (*setProcessing)(void*, uint8_t);
+    tresult (*process)(void*, ProcessData*);
+    uint32_t (*getTailSamples)(void*);
+} ProcessorVtbl;
+
+typedef struct {
+    tresult (*queryInterface)(void*, const TUID, void**);
+    uint32_t (*addRef)(void*);
+    uint32_t (*release)(void*);
+    tresult (*initialize)(void*, void*);
+    tresult (*terminate)(void*);
+    tresult (*setComponentState)(void*, void*);
+    tresult (*setState)(void*, void*);
+    tresult (*getState)(void*, void*);
+    int32_t (*getParameterCount)(void*);
+    tresult (*getParameterInfo)(void*, int32_t, ParameterInfo*);
+    tresult (*getParamStringByValue)(void*, ParamID, ParamValue, String128);
+    tresult (*getParamValueByString)(void*, ParamID, const uint16_t*, ParamValue*);
+    ParamValue (*normalizedParamToPlain)(void*, ParamID, ParamValue);
+    ParamValue (*plainParamToNormalized)(void*, ParamID, ParamValue);
+    ParamValue (*getParamNormalized)(void*, ParamID);
+    tresult (*setParamNormalized)(void*, ParamID, ParamValue);
+    tresult (*setComponentHandler)(void*, void*);
+    void* (*createView)(void*, const char*);
+} ControllerVtbl;
+
+struct Plugin {
+    const ComponentVtbl* component;
+    const ProcessorVtbl* processor;
+    const ControllerVtbl* controller;
+    uint32_t refs;
+    int32_t channels;
+    int32_t max_block;
+    double sample_rate;
+    /* The processor and controller keep separate copies, as in real plugins */
+    double processor_gain;
+    double controller_gain;
+    float note_level;
+    int32_t ui_scale;
+};
+
+#define FROM(field, this) ((Plugin*)((char*)(this) - offsetof(Plugin, field)))
+
+static void write_string(String128 target, const char* text) {
+    size_t i = 0;
+    for (; text[i] && i < 127; i++) target[i] = (uint16_t)text[i];
+    target[i] = 0;
+}
+
+static SpeakerArrangement arrangement(int32_t channels) {
+    return channels == 1 ? ((SpeakerArrangement)1 << 19) : (((SpeakerArrangement)1 << channels) - 1);
+}
+
+static uint32_t plugin_add_ref(Plugin* plugin) {
+    return ++plugin->refs;
+}
+
+static uint32_t plugin_release(Plugin* plugin) {
+    uint32_t refs = --plugin->refs;
+    if (refs == 0) free(plugin);
+    return refs;
+}
+
+static tresult plugin_query(Plugin* plugin, const TUID iid, void** obj) {
+    if (same_uid(iid, FUnknown_iid) || same_uid(iid, IPluginBase_iid) || same_uid(iid, IComponent_iid)) {
+        *obj = &plugin->component;
+    } else if (same_uid(iid, IAudioProcessor_iid)) {
+        *obj = &plugin->processor;
+    } else if (same_uid(iid, IEditController_iid)) {
+        *obj = &plugin->controller;
+    } else {
+        *obj = NULL;
+        return kNoInterface;
+    }
+    plugin_add_ref(plugin);
+    return kResultOk;
+}
+
+/* IComponent */
+
+static tresult c_query(void* this, const TUID iid, void** obj) { return plugin_query(FROM(component, this), iid, obj); }
+static uint32_t c_add_ref(void* this) { return plugin_add_ref(FROM(component, this)); }
+static uint32_t c_release(void* this) { return plugin_release(FROM(component, this)); }
+static tresult c_initialize(void* this, void* context) { (void)this; return context ? kResultOk : kInvalidArgument; }
+static tresult c_terminate(void* this) { (void)this; return kResultOk; }
+static tresult c_get_controller_class_id(void* this, TUID cid) { (void)this; memset(cid, 0, 16); return kResultFalse; }
+static tresult c_set_io_mode(void* this, int32_t mode) { (void)this; (void)mode; return kResultOk; }
+
+static int32_t c_get_bus_count(void* this, int32_t type, int32_t direction) {
+    (void)this;
+    if (type == 0) return 1;
+    return direction == 0 ? 1 : 0;
+}
+
+static tresult c_get_bus_info(void* this, int32_t type, int32_t direction, int32_t index, BusInfo* bus) {
+    Plugin* plugin = FROM(component, this);
+    if (index != 0 || (type == 1 && direction != 0)) return kInvalidArgument;
+    bus->mediaType = type;
+    bus->direction = direction;
+    bus->channelCount = type == 0 ? plugin->channels : 16;
+    write_string(bus->name, type == 1 ? "Notes" : direction == 0 ? "Input" : "Output");
+    bus->busType = 0;
+    bus->flags = 1;
+    return kResultOk;
+}
+
+static tresult c_get_routing_info(void* this, void* in, void* out) { (void)this; (void)in; (void)out; return kNotImplemented; }
+static tresult c_activate_bus(void* this, int32_t t, int32_t d, int32_t i, uint8_t s) { (void)this; (void)t; (void)d; (void)i; (void)s; return kResultOk; }
+static tresult c_set_active(void* this, uint8_t state) { (void)this; (void)state; return kResultOk; }
+static tresult c_set_state(void* this, void* state) { return read_exact(state, &FROM(component, this)->processor_gain, 8); }
+static tresult c_get_state(void* this, void* state) { return write_exact(state, &FROM(component, this)->processor_gain, 8); }
+
+static const ComponentVtbl component_vtbl = {
+    c_query, c_add_ref, c_release, c_initialize, c_terminate, c_get_controller_class_id,
+    c_set_io_mode, c_get_bus_count, c_get_bus_info, c_get_routing_info, c_activate_bus,
+    c_set_active, c_set_state, c_get_state,
+};
+
+/* IAudioProcessor */
+
+static tresult p_query(void* this, const TUID iid, void** obj) { return plugin_query(FROM(processor, this), iid, obj); }
+static uint32_t p_add_ref(void* this) { return plugin_add_ref(FROM(processor, this)); }
+static uint32_t p_release(void* this) { return plugin_release(FROM(processor, this)); }
+
+static int32_t count_bits(SpeakerArrangement value) {
+    int32_t count = 0;
+    for (; value; value &= value - 1) count++;
+    return count;
+}
+
+static tresult p_set_bus_arrangements(void* this, SpeakerArrangement* inputs, int32_t ins, SpeakerArrangement* outputs, int32_t outs) {
+    Plugin* plugin = FROM(processor, this);
+    if (ins != 1 || outs != 1 || inputs[0] != outputs[0]) return kResultFalse;
+    int32_t channels = count_bits(inputs[0]);
+    if (channels < 1 || channels > 8) return kResultFalse;
+    plugin->channels = channels;
+    return kResultOk;
+}
+
+static tresult p_get_bus_arrangement(void* this, int32_t direction, int32_t index, SpeakerArrangement* value) {
+    (void)direction;
+    if (index != 0) return kInvalidArgument;
+    *value = arrangement(FROM(processor, this)->channels);
+    return kResultOk;
+}
+
+static tresult p_can_process_sample_size(void* this, int32_t size) { (void)this; return size == 0 ? kResultOk : kResultFalse; }
+static uint32_t p_get_latency_samples(void* this) { return (uint32_t)FROM(processor, this)->max_block; }
+
+static tresult p_setup_processing(void* this, ProcessSetup* setup) {
+    Plugin* plugin = FROM(processor, this);
+    plugin->max_block = setup->maxSamplesPerBlock;
+    plugin->sample_rate = setup->sampleRate;
+    return kResultOk;
+}
+
+static tresult p_set_processing(void* this, uint8_t state) { (void)this; (void)state; return kResultOk; }
+
+static tresult p_process(void* this, ProcessData* data) {
+    Plugin* plugin = FROM(processor, this);
+    if (data->numSamples > plugin->max_block) return kInvalidArgument;
+
+    IParamValueQueue* gain_queue = NULL;
+    IParameterChanges* changes = data->inputParameterChanges;
+    if (changes) {
+        for (int32_t i = 0; i < changes->vtbl->getParameterCount(changes); i++) {
+            IParamValueQueue* queue = changes->vtbl->getParameterData(changes, i);
+            if (queue->vtbl->getParameterId(queue) == 0 && queue->vtbl->getPointCount(queue) > 0) gain_queue = queue;
+        }
+    }
+
+    IEventList* events = data->inputEvents;
+    if (events) {
+        for (int32_t i = 0; i < events->vtbl->getEventCount(events); i++) {
+            Event event;
+            if (events->vtbl->getEvent(events, i, &event) != kResultOk) continue;
+            if (event.type == 0) plugin->note_level = event.noteOn.velocity;
+            if (event.type == 1) plugin->note_level = 0.0f;
+        }
+    }
+
+    /* Ramp linearly from the previous value through each point, then hold */
+    int32_t point = 0;
+    int32_t point_offset = -1;
+    double point_value = plugin->processor_gain;
+    int32_t next_offset = 0;
+    double next_value = plugin->processor_gain;
+    int32_t points = gain_queue ? gain_queue->vtbl->getPointCount(gain_queue) : 0;
+    if (points > 0) gain_queue->vtbl->getPoint(gain_queue, 0, &next_offset, &next_value);
+    for (int32_t s = 0; s < data->numSamples; s++) {
+        while (point < points && next_offset <= s) {
+            point_offset = next_offset;
+            point_value = next_value;
+            point++;
+            if (point < points) gain_queue->vtbl->getPoint(gain_queue, point, &next_offset, &next_value);
+        }
+        double value = point_value;
+        if (point < points) value += (next_value - point_value) * (s - point_offset) / (double)(next_offset - point_offset);
+        float gain = (float)(value * 2.0);
+        for (int32_t bus = 0; bus < data->numOutputs; bus++) {
+            AudioBusBuffers* out = &data->outputs[bus];
+            AudioBusBuffers* in = bus < data->numInputs ? &data->inputs[bus] : NULL;
+            for (int32_t c = 0; c < out->numChannels; c++) {
+                float* input = in && c < in->numChannels ? in->channelBuffers32[c] : NULL;
+                out->channelBuffers32[c][s] = (input ? input[s] * gain : 0.0f) + plugin->note_level;
+            }
+        }
+        plugin->processor_gain = value;
+    }
+
+    if (data->outputParameterChanges) {
+        ParamID id = 7;
+        int32_t index;
+        IParamValueQueue* queue = data->outputParameterChanges->vtbl->addParameterData(data->outputParameterChanges, &id, &index);
+        if (queue) queue->vtbl->addPoint(queue, 0, plugin->note_level, &index);
+    }
+    return kResultOk;
+}
+
+static uint32_t p_get_tail_samples(void* this) { return (uint32_t)FROM(processor, this)->sample_rate; }
+
+static const ProcessorVtbl processor_vtbl = {
+    p_query, p_add_ref, p_release, p_set_bus_arrangements, p_get_bus_arrangement,
+    p_can_process_sample_size, p_get_latency_samples, p_setup_processing, p_set_processing,
+    p_process, p_get_tail_samples,
+};
+
+/* IEditController */
+
+static tresult e_query(void* this, const TUID iid, void** obj) { return plugin_query(FROM(controller, this), iid, obj); }
+static uint32_t e_add_ref(void* this) { return plugin_add_ref(FROM(controller, this)); }
+static uint32_t e_release(void* this) { return plugin_release(FROM(controller, this)); }
+static tresult e_initialize(void* this, void* context) { (void)this; (void)context; return kResultOk; }
+static tresult e_terminate(void* this) { (void)this; return kResultOk; }
+static tresult e_set_component_state(void* this, void* state) { return read_exact(state, &FROM(controller, this)->controller_gain, 8); }
+static tresult e_set_state(void* this, void* state) { return read_exact(state, &FROM(controller, this)->ui_scale, 4); }
+static tresult e_get_state(void* this, void* state) { return write_exact(state, &FROM(controller, this)->ui_scale, 4); }
+static int32_t e_get_parameter_count(void* this) { (void)this; return 2; }
+
+static tresult e_get_parameter_info(void* this, int32_t index, ParameterInfo* info) {
+    (void)this;
+    memset(info, 0, sizeof(*info));
+    if (index == 0) {
+        info->id = 0;
+        write_string(info->title, "Gain");
+        write_string(info->shortTitle, "Gain");
+        write_string(info->units, "x");
+        info->defaultNormalizedValue = 0.5;
+        info->flags = 1;
+    } else if (index == 1) {
+        info->id = 7;
+        write_string(info->title, "Note Level");
+        write_string(info->shortTitle, "Note");
+        info->stepCount = 0;
+        info->flags = 1 << 1;
+    } else {
+        return kInvalidArgument;
+    }
+    return kResultOk;
+}
+
+static tresult e_get_param_string_by_value(void* this, ParamID id, ParamValue value, String128 text) {
+    (void)this;
+    char buffer[64];
+    if (id != 0) return kInvalidArgument;
+    snprintf(buffer, sizeof(buffer), "%.2fx", value * 2.0);
+    write_string(text, buffer);
+    return kResultOk;
+}
+
+static tresult e_get_param_value_by_string(void* this, ParamID id, const uint16_t* text, ParamValue* value) {
+    (void)this; (void)id; (void)text; (void)value;
+    return kNotImplemented;
+}
+
+static ParamValue e_normalized_to_plain(void* this, ParamID id, ParamValue value) { (void)this; return id == 0 ? value * 2.0 : value; }
+static ParamValue e_plain_to_normalized(void* this, ParamID id, ParamValue value) { (void)this; return id == 0 ? value / 2.0 : value; }
+
+static ParamValue e_get_param_normalized(void* this, ParamID id) {
+    Plugin* plugin = FROM(controller, this);
+    return id == 0 ? plugin->controller_gain : plugin->note_level;
+}
+
+static tresult e_set_param_normalized(void* this, ParamID id, ParamValue value) {
+    if (id != 0) return kInvalidArgument;
+    FROM(controller, this)->controller_gain = value;
+    return kResultOk;
+}
+
+static tresult e_set_component_handler(void* this, void* handler) { (void)this; (void)handler; return kResultOk; }
+static void* e_create_view(void* this, const char* name) { (void)this; (void)name; return NULL; }
+
+static const ControllerVtbl controller_vtbl = {
+    e_query, e_add_ref, e_release, e_initialize, e_terminate, e_set_component_state,
+    e_set_state, e_get_state, e_get_parameter_count, e_get_parameter_info,
+    e_get_param_string_by_value, e_get_param_value_by_string, e_normalized_to_plain,
+    e_plain_to_normalized, e_get_param_normalized, e_set_param_normalized,
+    e_set_component_handler, e_create_view,
+};
+
+/* IPluginFactory2 */
+
+typedef struct {
+    tresult (*queryInterface)(void*, const TUID, void**);
+    uint32_t (*addRef)(void*);
+    uint32_t (*release)(void*);
+    tresult (*getFactoryInfo)(void*, PFactoryInfo*);
+    int32_t (*countClasses)(void*);
+    tresult (*getClassInfo)(void*, int32_t, PClassInfo*);
+    tresult (*createInstance)(void*, const char*, const char*, void**);
+    tresult (*getClassInfo2)(void*, int32_t, PClassInfo2*);
+} FactoryVtbl;
+
+typedef struct { const FactoryVtbl* vtbl; } Factory;
+
+static tresult f_query(void* this, const TUID iid, void** obj) {
+    if (same_uid(iid, FUnknown_iid) || same_uid(iid, IPluginFactory_iid) || same_uid(iid, IPluginFactory2_iid)) {
+        *obj = this;
+        return kResultOk;
+    }
+    *obj = NULL;
+    return kNoInterface;
+}
+
+static uint32_t f_add_ref(void* this) { (void)this; return 1; }
+static uint32_t f_release(void* this) { (void)this; return 1; }
+
+static tresult f_get_factory_info(void* this, PFactoryInfo* info) {
+    (void)this;
+    memset(info, 0, sizeof(*info));
+    strcpy(info->vendor, "NUWE");
+    return kResultOk;
+}
+
+static int32_t f_count_classes(void* this) { (void)this; return 1; }
+
+static tresult f_get_class_info(void* this, int32_t index, PClassInfo* info) {
+    (void)this;
+    if (index != 0) return kInvalidArgument;
+    memset(info, 0, sizeof(*info));
+    memcpy(info->cid, plugin_cid, 16);
+    info->cardinality = 0x7FFFFFFF;
+    strcpy(info->category, "Audio Module Class");
+    strcpy(info->name, "NUWE Test Gain");
+    return kResultOk;
+}
+
+static tresult f_get_class_info2(void* this, int32_t index, PClassInfo2* info) {
+    (void)this;
+    if (index != 0) return kInvalidArgument;
+    memset(info, 0, sizeof(*info));
+    memcpy(info->cid, plugin_cid, 16);
+    info->cardinality = 0x7FFFFFFF;
+    strcpy(info->category, "Audio Module Class");
+    strcpy(info->name, "NUWE Test Gain");
+    strcpy(info->subCategories, "Instrument|Fx");
+    strcpy(info->vendor, "NUWE");
+    strcpy(info->version, "1.0.0");
+    strcpy(info->sdkVersion, "VST 3.7");
+    return kResultOk;
+}
+
+static tresult f_create_instance(void* this, const char* cid, const char* iid, void** obj) {
+    (void)this;
+    *obj = NULL;
+    if (!same_uid(cid, plugin_cid)) return kInvalidArgument;
+    Plugin* plugin = calloc(1, sizeof(Plugin));
+    plugin->component = &component_vtbl;
+    plugin->processor = &processor_vtbl;
+    plugin->controller = &controller_vtbl;
+    plugin->refs = 1;
+    plugin->channels = 2;
+    plugin->max_block = 1024;
+    plugin->sample_rate = 44100.0;
+    plugin->processor_gain = 0.5;
+    plugin->controller_gain = 0.5;
+    plugin->ui_scale = 100;
+    tresult result = plugin_query(plugin, (const uint8_t*)iid, obj);
+    plugin_release(plugin);
+    return result;
+}
+
+static const FactoryVtbl factory_vtbl = {
+    f_query, f_add_ref, f_release, f_get_factory_info, f_count_classes, f_get_class_info,
+    f_create_instance, f_get_class_info2,
+};
+
+static Factory factory = { &factory_vtbl };
+static int module_entered = 0;
+
+__attribute__((visibility("default"))) int ModuleEntry(void* handle) {
+    module_entered = handle != NULL;
+    return module_entered;
+}
+
+__attribute__((visibility("default"))) int ModuleExit(void) {
+    module_entered = 0;
+    return 1;
+}
+
+__attribute__((visibility("default"))) void* GetPluginFactory(void) {
+    return module_entered ? &factory : NULL;
+}